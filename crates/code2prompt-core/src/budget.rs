@@ -0,0 +1,291 @@
+//! This module fits the files of a prompt into a token budget, omitting or truncating files
+//! according to a [`BudgetStrategy`].
+
+use anyhow::{anyhow, Result};
+use globset::GlobSet;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::str::FromStr;
+
+/// How files are cut when the prompt exceeds its token budget.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BudgetStrategy {
+    /// The largest files are omitted first.
+    #[default]
+    DropLargestFirst,
+    /// The files are omitted from the end of the file list.
+    DropLast,
+    /// Every file is cut at its end, in proportion to its size.
+    TruncateTail,
+    /// Every file that still fits is kept, in the order of the file list with the files
+    /// matching the priority patterns first.
+    Priority,
+}
+
+impl FromStr for BudgetStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "drop-largest-first" => Ok(BudgetStrategy::DropLargestFirst),
+            "drop-last" => Ok(BudgetStrategy::DropLast),
+            "truncate-tail" => Ok(BudgetStrategy::TruncateTail),
+            "priority" => Ok(BudgetStrategy::Priority),
+            _ => Err(anyhow!(
+                "Invalid budget strategy: {}. Allowed values: drop-largest-first, drop-last, truncate-tail, priority",
+                s
+            )),
+        }
+    }
+}
+
+/// A file omitted, or cut, to fit the token budget.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OmittedFile {
+    pub path: String,
+    /// The number of tokens removed from the prompt.
+    pub token_count: usize,
+    /// True if the end of the file was cut, false if the whole file was omitted.
+    pub truncated: bool,
+    /// True if the file matches the priority patterns.
+    pub priority: bool,
+}
+
+/// The outcome of fitting a prompt into its token budget.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct BudgetReport {
+    /// The maximum number of tokens of the prompt.
+    pub budget: usize,
+    /// The token count of the prompt before any file was cut.
+    pub tokens_before: usize,
+    /// The token count of the prompt after the files were cut.
+    pub tokens_after: usize,
+    /// The token count of the prompt without any file (source tree, git data and template).
+    pub overhead: usize,
+    /// The number of files matching the priority patterns.
+    pub priority_files: usize,
+    /// The files omitted or cut, in the order of the file list.
+    pub omitted: Vec<OmittedFile>,
+}
+
+impl BudgetReport {
+    /// Returns true if the prompt without any file already exceeds the budget.
+    pub fn overhead_exceeds_budget(&self) -> bool {
+        self.overhead > self.budget
+    }
+
+    /// Returns the number of priority files omitted or cut, which only happens when they
+    /// alone exceed the budget.
+    pub fn priority_cut(&self) -> usize {
+        self.omitted.iter().filter(|file| file.priority).count()
+    }
+}
+
+/// Omits or cuts files until their token counts add up to at most `available` tokens.
+///
+/// The token count of each file is read from its `token_count` field, and files without one
+/// count as empty. Cut files get an updated `token_count` and a `truncated` flag. Whatever the
+/// strategy, the files matching the priority patterns are the last to be omitted or cut.
+///
+/// # Arguments
+///
+/// * `files` - The JSON file representations, in the order of the prompt
+/// * `available` - The number of tokens left to the files
+/// * `strategy` - How the files are cut
+/// * `priority` - The patterns of the files kept first
+/// * `count` - Counts the tokens of a text
+///
+/// # Returns
+///
+/// * `(Vec<Value>, Vec<OmittedFile>)` - The files kept and the files omitted or cut
+pub fn fit_files<F>(
+    files: &[Value],
+    available: usize,
+    strategy: BudgetStrategy,
+    priority: &GlobSet,
+    count: F,
+) -> (Vec<Value>, Vec<OmittedFile>)
+where
+    F: Fn(&str) -> usize,
+{
+    let token_counts: Vec<usize> = files.iter().map(file_token_count).collect();
+    let total: usize = token_counts.iter().sum();
+    if total <= available {
+        return (files.to_vec(), Vec::new());
+    }
+    let is_priority: Vec<bool> = files
+        .iter()
+        .map(|file| priority.is_match(file_path(file)))
+        .collect();
+
+    let mut order: Vec<usize> = (0..files.len()).collect();
+    let mut dropped = vec![false; files.len()];
+    match strategy {
+        BudgetStrategy::DropLargestFirst | BudgetStrategy::DropLast => {
+            if strategy == BudgetStrategy::DropLargestFirst {
+                order.sort_by(|&a, &b| token_counts[b].cmp(&token_counts[a]).then(b.cmp(&a)));
+            } else {
+                order.reverse();
+            }
+            // The priority files are dropped last
+            order.sort_by_key(|&index| is_priority[index]);
+            let mut remaining = total;
+            for index in order {
+                if remaining <= available {
+                    break;
+                }
+                dropped[index] = true;
+                remaining -= token_counts[index];
+            }
+        }
+        BudgetStrategy::Priority => {
+            // Every file that still fits is kept, the priority files first
+            order.sort_by_key(|&index| !is_priority[index]);
+            let mut remaining = available;
+            for index in order {
+                match remaining.checked_sub(token_counts[index]) {
+                    Some(left) => remaining = left,
+                    None => dropped[index] = true,
+                }
+            }
+        }
+        BudgetStrategy::TruncateTail => {
+            return truncate_files(files, &token_counts, &is_priority, available, count)
+        }
+    }
+
+    let mut kept = Vec::new();
+    let mut omitted = Vec::new();
+    for (index, file) in files.iter().enumerate() {
+        if dropped[index] {
+            omitted.push(OmittedFile {
+                path: file_path(file).to_string(),
+                token_count: token_counts[index],
+                truncated: false,
+                priority: is_priority[index],
+            });
+        } else {
+            kept.push(file.clone());
+        }
+    }
+    (kept, omitted)
+}
+
+/// Cuts the end of every file so that each one keeps the same share of its tokens.
+///
+/// The other files are cut first, down to nothing if need be, and the priority files only
+/// when they alone exceed `available`.
+fn truncate_files<F>(
+    files: &[Value],
+    token_counts: &[usize],
+    is_priority: &[bool],
+    available: usize,
+    count: F,
+) -> (Vec<Value>, Vec<OmittedFile>)
+where
+    F: Fn(&str) -> usize,
+{
+    let group_total = |priority: bool| -> usize {
+        token_counts
+            .iter()
+            .zip(is_priority)
+            .filter(|(_, &is_priority)| is_priority == priority)
+            .map(|(&token_count, _)| token_count)
+            .sum()
+    };
+    let (priority_total, other_total) = (group_total(true), group_total(false));
+    let priority_available = available.min(priority_total);
+    let other_available = available - priority_available;
+
+    let mut kept = Vec::new();
+    let mut omitted = Vec::new();
+    for ((file, &token_count), &priority) in files.iter().zip(token_counts).zip(is_priority) {
+        let (group_available, group_total) = if priority {
+            (priority_available, priority_total)
+        } else {
+            (other_available, other_total)
+        };
+        if token_count == 0 || group_total <= group_available {
+            kept.push(file.clone());
+            continue;
+        }
+        let keep_tokens = token_count * group_available / group_total;
+        match file
+            .get("code")
+            .and_then(|code| code.as_str())
+            .and_then(|code| truncate_code(code, token_count, keep_tokens))
+        {
+            Some(code) => {
+                let new_count = count(&code);
+                let mut file = file.clone();
+                file["code"] = json!(code);
+                file["token_count"] = json!(new_count);
+                file["truncated"] = json!(true);
+                omitted.push(OmittedFile {
+                    path: file_path(&file).to_string(),
+                    token_count: token_count.saturating_sub(new_count),
+                    truncated: true,
+                    priority,
+                });
+                kept.push(file);
+            }
+            None => omitted.push(OmittedFile {
+                path: file_path(file).to_string(),
+                token_count,
+                truncated: false,
+                priority,
+            }),
+        }
+    }
+    (kept, omitted)
+}
+
+/// Keeps the leading lines of a code block holding about `keep_tokens` of its `token_count`
+/// tokens, closing a cut code fence again. Returns `None` if nothing is kept.
+fn truncate_code(code: &str, token_count: usize, keep_tokens: usize) -> Option<String> {
+    // A fence is three backticks or more, longer for a code holding backticks
+    let (opening, rest) = code.split_once('\n').unwrap_or((code, ""));
+    let fence = &opening[..opening.len() - opening.trim_start_matches('`').len()];
+    let (opening, body) = if fence.len() >= 3 && rest.ends_with(fence) {
+        (Some(opening), &rest[..rest.len() - fence.len()])
+    } else {
+        (None, code)
+    };
+
+    let mut cut = (body.len() * keep_tokens / token_count).min(body.len());
+    while !body.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    // Cut at the end of the last whole line, if any
+    let kept = &body[..body[..cut].rfind('\n').unwrap_or(cut)];
+    if kept.trim().is_empty() {
+        return None;
+    }
+
+    let mut truncated = String::new();
+    if let Some(opening) = opening {
+        truncated.push_str(opening);
+        truncated.push('\n');
+    }
+    truncated.push_str(kept);
+    truncated.push_str("\n... (truncated to fit the token budget)");
+    if opening.is_some() {
+        truncated.push('\n');
+        truncated.push_str(fence);
+    }
+    Some(truncated)
+}
+
+/// Returns the token count recorded on a file, 0 if it has none.
+fn file_token_count(file: &Value) -> usize {
+    file.get("token_count")
+        .and_then(|token_count| token_count.as_u64())
+        .map_or(0, |token_count| token_count as usize)
+}
+
+/// Returns the path of a file, empty if it has none.
+fn file_path(file: &Value) -> &str {
+    file.get("path")
+        .and_then(|path| path.as_str())
+        .unwrap_or("")
+}
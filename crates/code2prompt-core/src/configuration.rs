@@ -2,8 +2,13 @@
 //! of code2prompt in a stateless manner. It includes all parameters needed for file traversal,
 //! code filtering, token counting, and more.
 
-use crate::template::OutputFormat;
-use crate::tokenizer::TokenizerType;
+use crate::budget::BudgetStrategy;
+use crate::engine::TemplateEngine;
+use crate::filter::FileMatcher;
+use crate::git::{BlameMode, DiffConfig, GitLogOptions};
+use crate::path::{BinaryPolicy, PathStyle};
+use crate::template::{FormatOptions, IncludeOptions, OutputFormat};
+use crate::tokenizer::{count_tokens, estimate_tokens, TokenCountMode, TokenizerType};
 use crate::{sort::FileSortMethod, tokenizer::TokenFormat};
 use derive_builder::Builder;
 use std::collections::HashMap;
@@ -34,9 +39,25 @@ pub struct Code2PromptConfig {
     #[builder(default)]
     pub line_numbers: bool,
 
-    /// If true, paths in the output will be absolute instead of relative.
+    /// If set, code lines will be annotated with their git blame information.
     #[builder(default)]
-    pub absolute_path: bool,
+    pub blame: Option<BlameMode>,
+
+    /// If set, files with more lines than this are emitted without blame annotations.
+    #[builder(default)]
+    pub blame_max_lines: Option<usize>,
+
+    /// If true, each file is annotated with the last commit that touched it.
+    #[builder(default)]
+    pub file_git_info: bool,
+
+    /// How the paths are written in the output: relative, absolute or without a prefix.
+    #[builder(default)]
+    pub path_style: PathStyle,
+
+    /// What is written for the binary files: nothing, a placeholder, or their content in base64.
+    #[builder(default)]
+    pub binary_policy: BinaryPolicy,
 
     /// If true, code2prompt will generate a full directory tree, ignoring include/exclude rules.
     #[builder(default)]
@@ -46,6 +67,15 @@ pub struct Code2PromptConfig {
     #[builder(default)]
     pub no_codeblock: bool,
 
+    /// If true, the source tree is left out of the template context.
+    #[builder(default)]
+    pub no_tree: bool,
+
+    /// If true, the contents of the files are left out of the template context, so that the
+    /// default templates render none of the files.
+    #[builder(default)]
+    pub no_contents: bool,
+
     /// If true, symbolic links will be followed during traversal.
     #[builder(default)]
     pub follow_symlinks: bool,
@@ -54,10 +84,25 @@ pub struct Code2PromptConfig {
     #[builder(default)]
     pub hidden: bool,
 
-    /// If true, .gitignore rules will be ignored.
+    /// If true, initialized git submodules are traversed, uninitialized ones are shown as
+    /// placeholders in the tree, and diffs include the changes inside submodules.
+    #[builder(default)]
+    pub include_submodules: bool,
+
+    /// If true, the rules of the .gitignore files and of .git/info/exclude will be ignored.
     #[builder(default)]
     pub no_ignore: bool,
 
+    /// If true, only the files tracked in the git index are listed, instead of walking the
+    /// filesystem. Falls back to walking it outside of a git repository.
+    #[builder(default)]
+    pub tracked_only: bool,
+
+    /// If true, files marked `linguist-generated` or `export-ignore` in `.gitattributes` are
+    /// listed in the source tree but their content is omitted.
+    #[builder(default)]
+    pub respect_gitattributes: bool,
+
     /// Defines the sorting method for files.
     #[builder(default)]
     pub sort_method: Option<FileSortMethod>,
@@ -66,6 +111,10 @@ pub struct Code2PromptConfig {
     #[builder(default)]
     pub output_format: OutputFormat,
 
+    /// If true, the JSON output is written on a single line rather than indented.
+    #[builder(default)]
+    pub json_compact: bool,
+
     /// An optional custom Handlebars template string.
     #[builder(default)]
     pub custom_template: Option<String>,
@@ -78,18 +127,160 @@ pub struct Code2PromptConfig {
     #[builder(default)]
     pub token_format: TokenFormat,
 
+    /// Whether the tokens are counted exactly, estimated or not counted at all.
+    #[builder(default)]
+    pub token_count_mode: TokenCountMode,
+
+    /// The models for which the input cost of the prompt is estimated.
+    #[builder(default)]
+    pub cost_models: Vec<String>,
+
+    /// A TOML file whose prices replace or extend the embedded pricing table.
+    #[builder(default)]
+    pub pricing_file: Option<PathBuf>,
+
+    /// The number of threads reading and tokenizing the files, one per CPU if unset.
+    #[builder(default)]
+    pub jobs: Option<usize>,
+
+    /// If true, a file that can't be read or isn't valid UTF-8 fails the run instead of being
+    /// skipped or converted with a warning.
+    #[builder(default)]
+    pub strict: bool,
+
+    /// If true, the default templates start with a summary of the files of each directory.
+    #[builder(default)]
+    pub dir_summary: bool,
+
+    /// If true, the Markdown prompt has a table of contents after the tree, linking to a
+    /// heading above each file. The other output formats ignore it.
+    #[builder(default)]
+    pub toc: bool,
+
+    /// The line above each file of the Markdown prompt instead of its path and tokens, with
+    /// the `{path}`, `{language}`, `{tokens}` and `{index}` tokens, e.g. `===== {path} =====`.
+    #[builder(default)]
+    pub file_header_format: Option<String>,
+
+    /// The line below each file of the Markdown prompt, with the tokens of
+    /// `file_header_format`, e.g. `<<<END {path}>>>`.
+    #[builder(default)]
+    pub file_footer_format: Option<String>,
+
+    /// The number of directory levels of the directory summary, 1 (the top-level
+    /// directories) if unset.
+    #[builder(default)]
+    pub dir_summary_depth: Option<usize>,
+
+    /// If true, the token counts of the files are read from and saved to an on-disk cache,
+    /// keyed by the hash of their rendered code and the encoding.
+    #[builder(default)]
+    pub token_cache: bool,
+
+    /// The directory of the token cache, the `code2prompt` directory of the platform cache
+    /// directory if unset.
+    #[builder(default)]
+    pub cache_dir: Option<PathBuf>,
+
+    /// If set, the files over this many tokens are replaced with a stub showing their first
+    /// and last lines. They are still listed in the source tree.
+    #[builder(default)]
+    pub max_tokens_per_file: Option<usize>,
+
+    /// If set, the files over this many lines are replaced with a stub, before they are
+    /// tokenized.
+    #[builder(default)]
+    pub max_lines_per_file: Option<usize>,
+
+    /// If set, files are omitted or cut so that the rendered prompt fits in this many tokens.
+    #[builder(default)]
+    pub token_budget: Option<usize>,
+
+    /// How files are omitted or cut to fit `token_budget`.
+    #[builder(default)]
+    pub budget_strategy: BudgetStrategy,
+
+    /// Glob patterns of the files placed first in the prompt, and omitted or cut last to fit
+    /// `token_budget`.
+    #[builder(default)]
+    pub priority_patterns: Vec<String>,
+
     /// If true, the git diff between HEAD and index will be included.
     #[builder(default)]
     pub diff_enabled: bool,
 
-    /// If set, contains two branch names for which code2prompt will generate a git diff.
+    /// Options used when generating git diffs (context lines, whitespace handling, ...).
+    #[builder(default)]
+    pub diff_config: DiffConfig,
+
+    /// If set, contains two references (branches, tags or commits) for which code2prompt will generate a git diff.
     #[builder(default)]
     pub diff_branches: Option<(String, String)>,
 
+    /// If true, a `git diff --stat` style summary replaces the full patch between `diff_branches`
+    /// and of `show_commit`.
+    #[builder(default)]
+    pub diff_stat: bool,
+
+    /// If true, the diff and log between the two most recent tags are included, replacing
+    /// `diff_branches` and `log_branches`.
+    #[builder(default)]
+    pub diff_latest_tags: bool,
+
+    /// If set, the index of the stash entry (`stash@{n}`) whose diff code2prompt will retrieve.
+    #[builder(default)]
+    pub diff_stash: Option<usize>,
+
+    /// If set, the reference against which code2prompt will diff the working tree.
+    #[builder(default)]
+    pub diff_against: Option<String>,
+
+    /// If set, the commit whose message and diff code2prompt will show, like `git show`.
+    #[builder(default)]
+    pub show_commit: Option<String>,
+
+    /// If set, each diff exceeding this many tokens is truncated hunk by hunk.
+    #[builder(default)]
+    pub diff_token_limit: Option<usize>,
+
     /// If set, contains two branch names for which code2prompt will retrieve the git log.
     #[builder(default)]
     pub log_branches: Option<(String, String)>,
 
+    /// If set, a revision range (e.g. `HEAD~10..HEAD`) for which code2prompt will retrieve the git log.
+    #[builder(default)]
+    pub log_range: Option<String>,
+
+    /// If set, only the files changed since this revspec or number of days (e.g. "7d") are included.
+    #[builder(default)]
+    pub modified_since: Option<String>,
+
+    /// If true, only the files changed between the `diff_branches` references are included,
+    /// and their diff stats are loaded as the `git_diff_stat`.
+    #[builder(default)]
+    pub changed_files_only: bool,
+
+    /// If set, contains the two references between which a conventional changelog is generated.
+    #[builder(default)]
+    pub changelog_range: Option<(String, String)>,
+
+    /// If true, a summary of the main contributors is included, walking `log_range` if set.
+    #[builder(default)]
+    pub contributors_enabled: bool,
+
+    /// The maximum number of contributors listed, [`crate::git::DEFAULT_CONTRIBUTORS_COUNT`] if unset.
+    #[builder(default)]
+    pub contributors_max: Option<usize>,
+
+    /// The maximum number of commits walked to find the contributors,
+    /// [`crate::git::DEFAULT_CONTRIBUTORS_COMMIT_LIMIT`] if unset.
+    #[builder(default)]
+    pub contributors_commit_limit: Option<usize>,
+
+    /// Options controlling which commits are retrieved and how the git log is formatted.
+    #[builder(default)]
+    pub log_options: GitLogOptions,
+
     /// The name of the template used.
     #[builder(default)]
     pub template_name: String,
@@ -98,17 +289,75 @@ pub struct Code2PromptConfig {
     #[builder(default)]
     pub template_str: String,
 
-    /// Extra template data
+    /// The engine the template is written for, Handlebars by default. The default templates
+    /// are rendered with Handlebars whatever the engine.
+    #[builder(default)]
+    pub template_engine: TemplateEngine,
+
+    /// The partials registered before rendering, by name, with their content. They replace
+    /// the built-in partials of the same name, like `file_block`.
+    #[builder(default)]
+    pub template_partials: HashMap<String, String>,
+
+    /// The file template, rendered once per file with the file as context where the template
+    /// renders its file blocks. It replaces the `file_block` partial, even one of
+    /// `template_partials`.
+    #[builder(default)]
+    pub file_template: Option<String>,
+
+    /// The directory of the template and the other directories its `include` helper can read
+    /// files from.
+    #[builder(default)]
+    pub include_options: IncludeOptions,
+
+    /// If true, the values rendered by the template are escaped as HTML, e.g. `<` as `&lt;`.
+    #[builder(default)]
+    pub escape_html: bool,
+
+    /// The default date format and the locale of the `format_date` and `format_num` helpers.
+    #[builder(default)]
+    pub format_options: FormatOptions,
+
+    /// The languages of the code fences by file name or extension, over the built-in ones, e.g.
+    /// `pyx = "cython"` for a proprietary extension, see [`crate::language::detect_language`].
+    #[builder(default)]
+    pub languages: HashMap<String, String>,
+
+    /// User-defined variables, available to the template as `user.<key>` and, for the
+    /// templates written before, as `<key>`.
     #[builder(default)]
     pub user_variables: HashMap<String, String>,
 
-    /// If true, token counting will be performed for each file (for token map display)
+    /// If true, the CLI displays a token map built from the token count of each file
     #[builder(default)]
     pub token_map_enabled: bool,
+
+    /// The number of directory levels of the token map, every level if unset.
+    #[builder(default)]
+    pub token_map_depth: Option<usize>,
 }
 
 impl Code2PromptConfig {
     pub fn builder() -> Code2PromptConfigBuilder {
         Code2PromptConfigBuilder::default()
     }
+
+    /// Counts the tokens of a text with the configured encoding, according to the
+    /// `token_count_mode`: 0 when the tokens aren't counted.
+    pub fn count_tokens(&self, text: &str) -> usize {
+        match self.token_count_mode {
+            TokenCountMode::Exact => count_tokens(text, &self.encoding),
+            TokenCountMode::Estimate => estimate_tokens(text, &self.encoding),
+            TokenCountMode::Off => 0,
+        }
+    }
+
+    /// Compiles the include and exclude patterns into the matcher selecting files.
+    pub fn file_matcher(&self) -> FileMatcher {
+        FileMatcher::new(
+            &self.include_patterns,
+            &self.exclude_patterns,
+            self.include_priority,
+        )
+    }
 }
@@ -0,0 +1,241 @@
+//! This module abstracts the rendering of the prompts behind the [`TemplateRenderer`] trait, so
+//! that a session renders its template the same way whatever engine it is written for.
+//!
+//! Handlebars is the default engine. With the `jinja` feature, templates can be written for
+//! MiniJinja instead, e.g. `{% for file in files %}{{ file.path }}{% endfor %}`. Both engines
+//! render the same data, and the partials are included with `{% include "name" %}` in a Jinja
+//! template. The default templates and the built-in helpers are Handlebars only, except
+//! `codeblock`, a function for Jinja: `{{ codeblock("source_tree.txt", source_tree) }}`.
+#[cfg(feature = "jinja")]
+use crate::template::codeblock;
+use crate::template::{
+    check_partials, handlebars_setup, register_format_helpers, register_include_helper,
+    register_partials, render_template, FormatOptions, IncludeOptions,
+};
+use anyhow::{bail, Result};
+use handlebars::{html_escape, Handlebars};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The extensions of the template files written for MiniJinja.
+pub const JINJA_EXTENSIONS: &[&str] = &["j2", "jinja"];
+
+/// The engine a template is written for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TemplateEngine {
+    #[default]
+    Handlebars,
+    /// MiniJinja, available with the `jinja` feature.
+    Jinja,
+}
+
+impl TemplateEngine {
+    /// Returns the engine of a template file from its extension: Jinja for the `.j2` and
+    /// `.jinja` files, none for the other files.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        JINJA_EXTENSIONS
+            .contains(&extension.as_str())
+            .then_some(TemplateEngine::Jinja)
+    }
+}
+
+/// Parses the name of an engine, `handlebars` (or `hbs`) or `jinja`.
+impl FromStr for TemplateEngine {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "handlebars" | "hbs" => Ok(TemplateEngine::Handlebars),
+            "jinja" | "minijinja" => Ok(TemplateEngine::Jinja),
+            _ => Err(format!(
+                "Invalid template engine: {}. Choose handlebars or jinja",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for TemplateEngine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateEngine::Handlebars => write!(f, "handlebars"),
+            TemplateEngine::Jinja => write!(f, "jinja"),
+        }
+    }
+}
+
+/// The options of the rendering that don't depend on the engine.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// Where the `include` helper of a Handlebars template reads its files from.
+    pub includes: IncludeOptions,
+    /// If true, the `<`, `>`, `&` and quotes of the values are escaped as HTML entities, for
+    /// the prompts rendered into HTML. Off by default, so that code renders unchanged.
+    pub escape_html: bool,
+    /// The default date format and the locale of the `format_date` and `format_num` helpers.
+    pub formats: FormatOptions,
+}
+
+/// A compiled template with its partials, ready to render prompts.
+pub trait TemplateRenderer {
+    /// Renders the template with the prompt data, without the leading and trailing whitespace.
+    fn render(&self, data: &serde_json::Value) -> Result<String>;
+}
+
+/// Compiles a template and its partials for an engine.
+///
+/// # Arguments
+///
+/// * `engine` - The engine the template is written for.
+/// * `template_str` - The template, without its front-matter.
+/// * `template_name` - The name of the template, which a partial can't take.
+/// * `partials` - The partials, by name, with their content.
+/// * `options` - The included files, the escaping of the values and the default formats.
+///
+/// # Returns
+///
+/// * `Result<Box<dyn TemplateRenderer>>` - The renderer, or an error if the template or a
+///   partial can't be compiled, or if the engine isn't available in this build.
+pub fn renderer(
+    engine: TemplateEngine,
+    template_str: &str,
+    template_name: &str,
+    partials: &HashMap<String, String>,
+    options: &RenderOptions,
+) -> Result<Box<dyn TemplateRenderer>> {
+    match engine {
+        TemplateEngine::Handlebars => Ok(Box::new(HandlebarsRenderer::new(
+            template_str,
+            template_name,
+            partials,
+            options,
+        )?)),
+        #[cfg(feature = "jinja")]
+        TemplateEngine::Jinja => Ok(Box::new(JinjaRenderer::new(
+            template_str,
+            template_name,
+            partials,
+            options,
+        )?)),
+        #[cfg(not(feature = "jinja"))]
+        TemplateEngine::Jinja => bail!(
+            "The template {} is a Jinja template, but code2prompt was built without the jinja \
+             feature",
+            template_name
+        ),
+    }
+}
+
+/// Renders the templates with Handlebars and the built-in helpers.
+pub struct HandlebarsRenderer {
+    handlebars: Handlebars<'static>,
+    template_name: String,
+}
+
+impl HandlebarsRenderer {
+    /// Registers a template and its partials, checking that the partials it includes exist.
+    pub fn new(
+        template_str: &str,
+        template_name: &str,
+        partials: &HashMap<String, String>,
+        options: &RenderOptions,
+    ) -> Result<Self> {
+        let mut handlebars = handlebars_setup(template_str, template_name)?;
+        register_include_helper(&mut handlebars, &options.includes);
+        register_format_helpers(&mut handlebars, &options.formats)?;
+        if options.escape_html {
+            handlebars.register_escape_fn(html_escape);
+        }
+        register_partials(&mut handlebars, template_name, partials)?;
+        check_partials(
+            &handlebars,
+            template_name,
+            std::iter::once(template_str).chain(partials.values().map(String::as_str)),
+        )?;
+        Ok(Self {
+            handlebars,
+            template_name: template_name.to_string(),
+        })
+    }
+}
+
+impl TemplateRenderer for HandlebarsRenderer {
+    fn render(&self, data: &serde_json::Value) -> Result<String> {
+        render_template(&self.handlebars, &self.template_name, data)
+    }
+}
+
+/// Renders the templates with MiniJinja, without escaping unless asked to, with the blocks trimmed so that a
+/// line holding only a `{% ... %}` tag renders nothing, like a Handlebars block on its own line.
+#[cfg(feature = "jinja")]
+pub struct JinjaRenderer {
+    environment: minijinja::Environment<'static>,
+    template_name: String,
+}
+
+#[cfg(feature = "jinja")]
+impl JinjaRenderer {
+    /// Compiles a template and its partials, which it can include by name.
+    pub fn new(
+        template_str: &str,
+        template_name: &str,
+        partials: &HashMap<String, String>,
+        options: &RenderOptions,
+    ) -> Result<Self> {
+        let mut environment = minijinja::Environment::new();
+        let auto_escape = if options.escape_html {
+            minijinja::AutoEscape::Html
+        } else {
+            minijinja::AutoEscape::None
+        };
+        environment.set_auto_escape_callback(move |_| auto_escape);
+        environment.set_trim_blocks(true);
+        environment.set_lstrip_blocks(true);
+        environment.add_function("codeblock", |path: &str, content: Option<&str>| {
+            codeblock(path, content.unwrap_or_default())
+        });
+        for (name, content) in partials {
+            if name == template_name {
+                bail!("The partial {} has the name of the main template", name);
+            }
+            environment
+                .add_template_owned(name.clone(), content.clone())
+                .map_err(|e| jinja_error(e, "Failed to register the partial"))?;
+        }
+        environment
+            .add_template_owned(template_name.to_string(), template_str.to_string())
+            .map_err(|e| jinja_error(e, "Failed to register template"))?;
+        Ok(Self {
+            environment,
+            template_name: template_name.to_string(),
+        })
+    }
+}
+
+#[cfg(feature = "jinja")]
+impl TemplateRenderer for JinjaRenderer {
+    fn render(&self, data: &serde_json::Value) -> Result<String> {
+        let rendered = self
+            .environment
+            .get_template(&self.template_name)
+            .and_then(|template| template.render(data))
+            .map_err(|e| jinja_error(e, "Failed to render template"))?;
+        Ok(rendered.trim().to_string())
+    }
+}
+
+/// Formats a MiniJinja error with the template and the line it happened at.
+#[cfg(feature = "jinja")]
+fn jinja_error(error: minijinja::Error, context: &str) -> anyhow::Error {
+    let detail = error
+        .detail()
+        .map_or_else(|| error.kind().to_string(), str::to_string);
+    match (error.name(), error.line()) {
+        (Some(name), Some(line)) => {
+            anyhow::anyhow!("{} {} at line {}: {}", context, name, line, detail)
+        }
+        _ => anyhow::anyhow!("{}: {}", context, detail),
+    }
+}
@@ -59,10 +59,10 @@ pub fn build_globset(patterns: &[String]) -> GlobSet {
 ///
 /// * `path` - A relative path to the file that will be checked against the patterns.
 /// * `include_patterns` - A slice of glob pattern strings specifying which files to include.
-///                        If empty, all files are considered included unless excluded.
+///   If empty, all files are considered included unless excluded.
 /// * `exclude_patterns` - A slice of glob pattern strings specifying which files to exclude.
 /// * `include_priority` - A boolean flag that, when set to `true`, gives include patterns
-///                        precedence over exclude patterns in cases where both match.
+///   precedence over exclude patterns in cases where both match.
 ///
 /// # Returns
 ///
@@ -97,6 +97,60 @@ pub fn should_include_file(
     result
 }
 
+/// The compiled include and exclude patterns of a session, shared by the file selector
+/// and the git diffs so both select the same paths.
+#[derive(Debug, Clone)]
+pub struct FileMatcher {
+    include_patterns: Vec<String>,
+    exclude_patterns: Vec<String>,
+    include_globset: GlobSet,
+    exclude_globset: GlobSet,
+    include_priority: bool,
+}
+
+impl FileMatcher {
+    /// Compiles the include and exclude patterns. Invalid patterns are ignored, as in
+    /// [`build_globset`].
+    pub fn new(
+        include_patterns: &[String],
+        exclude_patterns: &[String],
+        include_priority: bool,
+    ) -> Self {
+        Self {
+            include_patterns: include_patterns.to_vec(),
+            exclude_patterns: exclude_patterns.to_vec(),
+            include_globset: build_globset(include_patterns),
+            exclude_globset: build_globset(exclude_patterns),
+            include_priority,
+        }
+    }
+
+    /// Returns true if no pattern was given, so every path is included.
+    pub fn is_empty(&self) -> bool {
+        self.include_patterns.is_empty() && self.exclude_patterns.is_empty()
+    }
+
+    /// Determines whether a relative path is included, see [`should_include_file`].
+    pub fn is_match(&self, path: &Path) -> bool {
+        should_include_file(
+            path,
+            &self.include_globset,
+            &self.exclude_globset,
+            self.include_priority,
+        )
+    }
+}
+
+impl PartialEq for FileMatcher {
+    fn eq(&self, other: &Self) -> bool {
+        self.include_patterns == other.include_patterns
+            && self.exclude_patterns == other.exclude_patterns
+            && self.include_priority == other.include_priority
+    }
+}
+
+impl Eq for FileMatcher {}
+
 /// Expands glob patterns containing `{}` into multiple separate patterns.
 ///
 /// This function detects patterns with brace expansion (e.g., `"src/{foo,bar}/**"`),
@@ -114,8 +168,8 @@ fn expand_brace_patterns(patterns: &[String]) -> Vec<String> {
     let brace_start_index = joined_patterns.find("/{").unwrap();
     let common_prefix = &joined_patterns[..brace_start_index];
 
-    return joined_patterns[brace_start_index + 2..]
+    joined_patterns[brace_start_index + 2..]
         .split(',')
         .map(|expanded_pattern| format!("{}/{}", common_prefix, expanded_pattern))
-        .collect::<Vec<String>>();
+        .collect::<Vec<String>>()
 }
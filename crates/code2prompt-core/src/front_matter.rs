@@ -0,0 +1,116 @@
+//! This module parses the front-matter of a template: a TOML block between `+++` lines at the
+//! top of the template that sets the default options of the prompts rendered with it.
+//!
+//! ```text
+//! +++
+//! diff = true
+//! exclude = ["*.lock"]
+//! +++
+//! Review the changes:
+//! {{ git_diff }}
+//! ```
+//!
+//! The front-matter is stripped before the template is handed to Handlebars.
+
+use crate::template::OutputFormat;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Deserializer};
+use std::str::FromStr;
+
+/// The line that opens and closes the front-matter.
+pub const FRONT_MATTER_DELIMITER: &str = "+++";
+
+/// The default options declared by a template.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FrontMatter {
+    /// Include the git diff of the index.
+    pub diff: bool,
+    pub line_numbers: bool,
+    pub absolute_paths: bool,
+    pub full_directory_tree: bool,
+    pub no_codeblock: bool,
+    /// Include the hidden files and directories.
+    pub hidden: bool,
+    /// The patterns to include, used when none is passed.
+    pub include: Vec<String>,
+    /// The patterns to exclude, used when none is passed.
+    pub exclude: Vec<String>,
+    #[serde(deserialize_with = "deserialize_output_format")]
+    pub output_format: Option<OutputFormat>,
+    /// Include only the files changed between the `--git-diff-branch` references.
+    pub changed_files_only: bool,
+}
+
+/// Splits a template into its front-matter and its body.
+///
+/// A template without front-matter is returned unchanged, with the default front-matter.
+///
+/// # Arguments
+///
+/// * `template` - The template, whose first line is `+++` if it has a front-matter
+///
+/// # Returns
+///
+/// * `Result<(FrontMatter, &str)>` - The front-matter and the body of the template, or an error
+///   naming the offending line if the front-matter isn't closed or isn't valid
+pub fn split_front_matter(template: &str) -> Result<(FrontMatter, &str)> {
+    let Some(first_line) = template.lines().next() else {
+        return Ok((FrontMatter::default(), template));
+    };
+    if first_line.trim_end() != FRONT_MATTER_DELIMITER {
+        return Ok((FrontMatter::default(), template));
+    }
+
+    let start = first_line.len() + line_ending_len(&template[first_line.len()..]);
+    let mut offset = start;
+    for line in template[start..].lines() {
+        let end = offset + line.len();
+        if line.trim_end() == FRONT_MATTER_DELIMITER {
+            let front_matter = parse_front_matter(&template[start..offset])?;
+            let body = &template[end + line_ending_len(&template[end..])..];
+            return Ok((front_matter, body));
+        }
+        offset = end + line_ending_len(&template[end..]);
+    }
+    bail!(
+        "The front-matter opened at line 1 isn't closed. Close it with a {} line",
+        FRONT_MATTER_DELIMITER
+    )
+}
+
+/// Parses the TOML between the delimiters, which starts at line 2 of the template.
+fn parse_front_matter(toml_str: &str) -> Result<FrontMatter> {
+    toml::from_str(toml_str).or_else(|e| {
+        let start = e.span().map_or(0, |span| span.start);
+        let line_index = toml_str[..start.min(toml_str.len())].matches('\n').count();
+        let line = toml_str.lines().nth(line_index).unwrap_or_default();
+        bail!(
+            "Invalid front-matter at line {}: {}\n  {}",
+            line_index + 2,
+            e.message().trim_end(),
+            line.trim()
+        )
+    })
+}
+
+/// Returns the length of the line ending at the start of the text, 0 at the end of the text.
+fn line_ending_len(text: &str) -> usize {
+    if text.starts_with("\r\n") {
+        2
+    } else if text.starts_with('\n') {
+        1
+    } else {
+        0
+    }
+}
+
+fn deserialize_output_format<'de, D>(deserializer: D) -> Result<Option<OutputFormat>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let format = String::deserialize(deserializer)?;
+    OutputFormat::from_str(&format)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
@@ -1,75 +1,368 @@
 //! This module handles git operations.
 
-use anyhow::{Context, Result};
-use git2::{DiffOptions, Repository};
-use log::info;
-use std::path::Path;
+use crate::filter::FileMatcher;
+use crate::path::line_number_width;
+use crate::util::format_size;
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use git2::{
+    AttrCheckFlags, AttrValue, Commit, Delta, Diff, DiffDelta, DiffFindOptions, DiffOptions,
+    ErrorCode, FileMode, ObjectType, Patch, Repository, RepositoryOpenFlags, Signature, Time,
+    TreeWalkMode, TreeWalkResult,
+};
+use log::{debug, info, warn};
+use num_format::{Locale, ToFormattedString};
+use regex::Regex;
+use serde::Serialize;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// Options controlling how git diffs are generated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffConfig {
+    /// Number of unchanged lines shown around each change.
+    pub context_lines: u32,
+    /// Maximum number of unchanged lines between two changes before they are split into separate hunks.
+    pub interhunk_lines: u32,
+    /// Which whitespace differences are ignored. Defaults to [`WhitespaceMode::All`] for
+    /// compatibility, which hides indentation-only changes (see [`WhitespaceMode`]).
+    pub whitespace: WhitespaceMode,
+    /// If true, untracked (and not ignored) files are shown as additions in working-tree diffs.
+    pub include_untracked: bool,
+    /// If true along with `include_untracked`, ignored files (e.g. `target/`) are shown as
+    /// additions too. Only meant for the rare case they are really wanted.
+    pub include_ignored: bool,
+    /// Minimum similarity (0-100) for a deleted/added pair to be reported as a rename or copy.
+    pub rename_threshold: u16,
+    /// Pathspecs restricting the diff to matching paths. Patterns prefixed with `!`, `:!`,
+    /// `:^` or `:(exclude)` exclude paths instead. An empty list means the whole repository.
+    pub pathspecs: Vec<String>,
+    /// If true, diffs between two references start from their merge base (`git diff A...B`)
+    /// instead of the first reference (`git diff A..B`).
+    pub merge_base: bool,
+    /// How changed lines are rendered.
+    pub render_style: DiffRenderStyle,
+    /// If true, binary files are left out of diffs instead of being summarized on one line.
+    pub skip_binary: bool,
+    /// If true, submodule pointer changes are followed by the submodule's own diff when its
+    /// commits are available locally.
+    pub include_submodules: bool,
+    /// The include/exclude patterns of the file selector. Files they reject are left out of
+    /// the diff and counted in a summary line.
+    pub path_filter: Option<FileMatcher>,
+}
+
+impl Default for DiffConfig {
+    fn default() -> Self {
+        Self {
+            context_lines: 3,
+            interhunk_lines: 0,
+            whitespace: WhitespaceMode::default(),
+            include_untracked: false,
+            include_ignored: false,
+            rename_threshold: 50,
+            pathspecs: Vec::new(),
+            merge_base: false,
+            render_style: DiffRenderStyle::default(),
+            skip_binary: false,
+            include_submodules: false,
+            path_filter: None,
+        }
+    }
+}
+
+/// Which whitespace differences are ignored in diffs.
+///
+/// The default, [`WhitespaceMode::All`], matches the historical behavior but hides changes
+/// that only touch indentation, which matter in languages such as Python or YAML. A diff whose
+/// only change is re-indenting a block is then reported as having no changes at all; use
+/// [`WhitespaceMode::None`] or [`WhitespaceMode::Eol`] for such projects.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WhitespaceMode {
+    /// Every whitespace change is shown (`git diff`).
+    None,
+    /// All whitespace is ignored (`git diff -w`).
+    #[default]
+    All,
+    /// Changes in the amount of whitespace are ignored (`git diff -b`).
+    Change,
+    /// Whitespace at the end of lines is ignored (`git diff --ignore-space-at-eol`).
+    Eol,
+}
+
+impl WhitespaceMode {
+    /// Sets the corresponding whitespace flags on the diff options.
+    fn apply(self, options: &mut DiffOptions) {
+        options
+            .ignore_whitespace(self == WhitespaceMode::All)
+            .ignore_whitespace_change(self == WhitespaceMode::Change)
+            .ignore_whitespace_eol(self == WhitespaceMode::Eol);
+    }
+}
+
+impl FromStr for WhitespaceMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(WhitespaceMode::None),
+            "all" => Ok(WhitespaceMode::All),
+            "change" => Ok(WhitespaceMode::Change),
+            "eol" => Ok(WhitespaceMode::Eol),
+            _ => Err(anyhow!(
+                "Invalid whitespace mode: {}. Allowed values: none, all, change, eol",
+                s
+            )),
+        }
+    }
+}
+
+/// How the changed lines of a diff are rendered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DiffRenderStyle {
+    /// Whole lines are shown, like `git diff`.
+    #[default]
+    Patch,
+    /// Changes are marked inline with `[-old-]{+new+}`, like `git diff --word-diff`.
+    /// This is much shorter when long lines only change slightly.
+    Word,
+}
+
+impl FromStr for DiffRenderStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "patch" => Ok(DiffRenderStyle::Patch),
+            "word" => Ok(DiffRenderStyle::Word),
+            _ => Err(anyhow!(
+                "Invalid diff style: {}. Allowed values: patch, word",
+                s
+            )),
+        }
+    }
+}
+
+/// A `git diff --stat` style summary of the changes between two references.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DiffStats {
+    /// Per-file statistics, in diff order.
+    pub files: Vec<FileDiffStat>,
+    /// Number of files changed.
+    pub files_changed: usize,
+    /// Total number of inserted lines (binary files excluded).
+    pub insertions: usize,
+    /// Total number of deleted lines (binary files excluded).
+    pub deletions: usize,
+}
+
+/// How two branches relate in the commit graph.
+#[derive(Debug, Clone, Serialize)]
+pub struct BranchRelationship {
+    /// The branch the comparison starts from.
+    pub from: String,
+    /// The branch compared against `from`.
+    pub to: String,
+    /// The last common commit of both branches, `None` if their histories are unrelated.
+    pub merge_base: Option<MergeBase>,
+    /// Number of commits on `to` that are not on `from`.
+    pub ahead: usize,
+    /// Number of commits on `from` that are not on `to`.
+    pub behind: usize,
+}
+
+/// The commit at which two branches diverged.
+#[derive(Debug, Clone, Serialize)]
+pub struct MergeBase {
+    /// The full commit hash.
+    pub sha: String,
+    /// The abbreviated commit hash.
+    pub short_sha: String,
+    /// The author date of the commit, which is when the branches diverged.
+    pub diverged_at: String,
+}
+
+/// The statistics of a single changed file.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileDiffStat {
+    /// Path of the file after the change.
+    pub path: String,
+    /// Path of the file before the change, set only when the file was renamed or copied.
+    pub old_path: Option<String>,
+    /// Number of inserted lines.
+    pub insertions: usize,
+    /// Number of deleted lines.
+    pub deletions: usize,
+    /// If true, the file is binary and line counts are not meaningful.
+    pub is_binary: bool,
+}
+
+impl fmt::Display for DiffStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for file in &self.files {
+            let path = match &file.old_path {
+                Some(old_path) => format!("{} => {}", old_path, file.path),
+                None => file.path.clone(),
+            };
+            if file.is_binary {
+                writeln!(f, " {} | binary", path)?;
+            } else {
+                writeln!(
+                    f,
+                    " {} | {} {}{}",
+                    path,
+                    file.insertions + file.deletions,
+                    "+".repeat(file.insertions.min(40)),
+                    "-".repeat(file.deletions.min(40))
+                )?;
+            }
+        }
+        write!(
+            f,
+            " {} files changed, {} insertions(+), {} deletions(-)",
+            self.files_changed, self.insertions, self.deletions
+        )
+    }
+}
+
+/// A machine-readable representation of a diff, for consumers that post-process the output.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StructuredDiff {
+    /// The changed files, in diff order.
+    pub files: Vec<StructuredDiffFile>,
+}
+
+/// A changed file of a [`StructuredDiff`].
+#[derive(Debug, Clone, Serialize)]
+pub struct StructuredDiffFile {
+    /// Path of the file before the change, unset for added files.
+    pub old_path: Option<String>,
+    /// Path of the file after the change, unset for deleted files.
+    pub new_path: Option<String>,
+    /// The kind of change, e.g. "added", "modified" or "renamed".
+    pub status: String,
+    /// If true, the file is binary and has no hunks.
+    pub is_binary: bool,
+    /// The hunks of the file, in order.
+    pub hunks: Vec<StructuredDiffHunk>,
+}
+
+/// A hunk of a [`StructuredDiffFile`].
+#[derive(Debug, Clone, Serialize)]
+pub struct StructuredDiffHunk {
+    /// First line of the hunk in the old file.
+    pub old_start: u32,
+    /// Number of lines of the hunk in the old file.
+    pub old_lines: u32,
+    /// First line of the hunk in the new file.
+    pub new_start: u32,
+    /// Number of lines of the hunk in the new file.
+    pub new_lines: u32,
+    /// The hunk header, e.g. "@@ -1,3 +1,4 @@ fn main() {".
+    pub header: String,
+    /// The lines of the hunk, in order.
+    pub lines: Vec<StructuredDiffLine>,
+}
+
+/// A line of a [`StructuredDiffHunk`].
+#[derive(Debug, Clone, Serialize)]
+pub struct StructuredDiffLine {
+    /// The line origin as printed by git: '+' for additions, '-' for deletions and ' ' for
+    /// context. Missing end-of-file newlines are reported with '=', '>' or '<'.
+    pub origin: char,
+    /// The content of the line, including its line terminator.
+    pub content: String,
+}
+
+/// The changes a [`StructuredDiff`] is computed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffTarget<'a> {
+    /// The staged changes, plus untracked files when `include_untracked` is set.
+    Staged,
+    /// The changes between two references.
+    Refs(&'a str, &'a str),
+    /// The changes recorded in a stash entry, `0` being the most recent.
+    Stash(usize),
+    /// The changes of a single commit against its first parent.
+    Commit(&'a str),
+    /// The changes of the working tree, staged or not, against a reference.
+    WorkdirToRef(&'a str),
+}
 
 /// Generates a git diff for the repository at the provided path.
 ///
 /// This function compares the repository's HEAD tree with the index to produce a diff of staged changes.
 /// It also checks for unstaged changes (differences between the index and the working directory) and,
-/// if found, appends a notification to the output.
+/// if found, appends a notification to the output. When `include_untracked` is enabled, files that are
+/// neither tracked nor ignored are appended to the diff as full additions.
+///
+/// In a repository without commits, the staged files are shown as additions. In both that case and
+/// a detached HEAD (e.g. during a rebase), the diff is preceded by a line describing the state of HEAD.
 ///
-/// If there are no staged changes, the function returns a message in the format:
+/// If there are no staged changes (and no untracked files to show), the function returns a message in the format:
 /// `"no diff between HEAD and index"`.
 ///
 /// # Arguments
 ///
 /// * `repo_path` - A reference to the path of the git repository.
+/// * `diff_config` - The options used to generate the diff.
 ///
 /// # Returns
 ///
 /// * `Result<String>` - On success, returns either the diff (with an appended note if unstaged changes exist)
 ///   or a message indicating that there is no diff between the compared git objects.
 ///   In case of error, returns an appropriate error.
-pub fn get_git_diff(repo_path: &Path) -> Result<String> {
+pub fn get_git_diff(repo_path: &Path, diff_config: &DiffConfig) -> Result<String> {
     info!("Opening repository at path: {:?}", repo_path);
-    let repo = Repository::open(repo_path).context("Failed to open repository")?;
+    let repo = open_repository(repo_path)?;
+    let (staged_diff, head_note) = staged_diff(&repo, repo_path, diff_config)?;
 
-    let head = repo.head().context("Failed to get repository head")?;
-    let head_tree = head.peel_to_tree().context("Failed to peel to tree")?;
+    let mut output =
+        diff_to_string(&staged_diff, diff_config).context("Failed to print staged diff")?;
+    if diff_config.include_submodules {
+        output.push_str(&submodule_diffs(&repo, &staged_diff, diff_config)?);
+    }
 
-    // Generate diff for staged changes (HEAD vs. index)
-    let staged_diff = repo
-        .diff_tree_to_index(
-            Some(&head_tree),
-            None,
-            Some(DiffOptions::new().ignore_whitespace(true)),
-        )
-        .context("Failed to generate diff for staged changes")?;
+    // Generate diff for unstaged changes (index vs. working directory)
+    let unstaged_diff = unstaged_diff(&repo, diff_config)?;
 
-    let mut staged_diff_text = Vec::new();
-    staged_diff
-        .print(git2::DiffFormat::Patch, |_delta, _hunk, line| {
-            staged_diff_text.extend_from_slice(line.content());
-            true
+    if diff_config.include_untracked {
+        let untracked_output = print_diff(&unstaged_diff, diff_config, |delta| {
+            delta.status() == Delta::Untracked
         })
-        .context("Failed to print staged diff")?;
-
-    let staged_diff_output = String::from_utf8_lossy(&staged_diff_text).into_owned();
-
-    // If there is no staged diff, return a message indicating so.
-    if staged_diff_output.trim().is_empty() {
-        return Ok("no diff between HEAD and index".to_string());
+        .context("Failed to print untracked files")?;
+        output.push_str(&untracked_output);
     }
 
-    // Generate diff for unstaged changes (index vs. working directory)
-    let unstaged_diff = repo
-        .diff_index_to_workdir(None, Some(DiffOptions::new().ignore_whitespace(true)))
-        .context("Failed to generate diff for unstaged changes")?;
+    let mut excluded = count_filtered_deltas(&staged_diff, diff_config, |_| true);
+    if diff_config.include_untracked {
+        excluded += count_filtered_deltas(&unstaged_diff, diff_config, |delta| {
+            delta.status() == Delta::Untracked
+        });
+    }
 
-    let mut unstaged_diff_text = Vec::new();
-    unstaged_diff
-        .print(git2::DiffFormat::Patch, |_delta, _hunk, line| {
-            unstaged_diff_text.extend_from_slice(line.content());
-            true
-        })
-        .context("Failed to print unstaged diff")?;
+    // If there is nothing to show, return a message indicating so.
+    if output.trim().is_empty() {
+        return Ok(format!(
+            "no diff between HEAD and index{}",
+            excluded_files_note(excluded)
+        ));
+    }
+    if let Some(head_note) = head_note {
+        output.insert_str(0, &head_note);
+    }
 
-    let unstaged_diff_output = String::from_utf8_lossy(&unstaged_diff_text).into_owned();
+    let unstaged_diff_output = print_diff(&unstaged_diff, diff_config, |delta| {
+        delta.status() != Delta::Untracked
+    })
+    .context("Failed to print unstaged diff")?;
 
-    let mut output = staged_diff_output;
+    output.push_str(&excluded_files_note(excluded));
     if !unstaged_diff_output.trim().is_empty() {
         output.push_str("\nNote: Some changes are not staged.");
     }
@@ -78,120 +371,3403 @@ pub fn get_git_diff(repo_path: &Path) -> Result<String> {
     Ok(output)
 }
 
+/// The files changed since a reference or a number of days, see [`files_changed_since`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChangedFiles {
+    /// Files added or modified, relative to the repository root.
+    pub changed: HashSet<PathBuf>,
+    /// Files deleted, relative to the repository root, in diff order.
+    pub removed: Vec<PathBuf>,
+}
+
+/// Collects the files changed since a reference or a number of days
+///
+/// `since` is either a revspec (e.g. "HEAD~5", "v1.2.0") or a number of days such as
+/// "7d", in which case the changes are counted from the last commit of HEAD older than
+/// that. The changes go up to the working tree, including staged, unstaged and untracked
+/// files; in a bare repository they go up to HEAD.
+///
+/// # Arguments
+///
+/// * `repo_path` - A reference to the path of the git repository
+/// * `since` - A revspec or a number of days followed by `d`
+///
+/// # Returns
+///
+/// * `Result<ChangedFiles>` - The changed and removed files or an error
+pub fn files_changed_since(repo_path: &Path, since: &str) -> Result<ChangedFiles> {
+    info!("Opening repository at path: {:?}", repo_path);
+    let repo = open_repository(repo_path)?;
+
+    let base_tree = match parse_days(since) {
+        Some(days) => {
+            let cutoff = Utc::now().timestamp() - days * 24 * 60 * 60;
+            let mut revwalk = repo.revwalk().context("Failed to create revwalk")?;
+            revwalk
+                .push_head()
+                .context("Failed to push HEAD to revwalk")?;
+            revwalk.set_sorting(git2::Sort::TIME)?;
+            let mut base = None;
+            for oid in revwalk {
+                let commit = repo.find_commit(oid?).context("Failed to find commit")?;
+                if commit.time().seconds() < cutoff {
+                    base = Some(commit);
+                    break;
+                }
+            }
+            // Without an older commit, every file of the history is recent
+            base.map(|commit| commit.tree()).transpose()?
+        }
+        None => Some(resolve_commit(&repo, since)?.tree()?),
+    };
+
+    let mut opts = DiffOptions::new();
+    let diff = if repo.is_bare() {
+        let head_tree = repo
+            .head()
+            .and_then(|head| head.peel_to_tree())
+            .context("Failed to get HEAD tree")?;
+        repo.diff_tree_to_tree(base_tree.as_ref(), Some(&head_tree), Some(&mut opts))
+    } else {
+        opts.include_untracked(true).recurse_untracked_dirs(true);
+        repo.diff_tree_to_workdir_with_index(base_tree.as_ref(), Some(&mut opts))
+    }
+    .with_context(|| format!("Failed to diff the changes since {}", since))?;
+
+    let mut changed_files = ChangedFiles::default();
+    for delta in diff.deltas() {
+        match delta.status() {
+            Delta::Deleted => {
+                if let Some(path) = delta.old_file().path() {
+                    changed_files.removed.push(path.to_path_buf());
+                }
+            }
+            _ => {
+                if let Some(path) = delta.new_file().path() {
+                    changed_files.changed.insert(path.to_path_buf());
+                }
+            }
+        }
+    }
+
+    info!(
+        "Found {} changed and {} removed files since {}",
+        changed_files.changed.len(),
+        changed_files.removed.len(),
+        since
+    );
+    Ok(changed_files)
+}
+
+/// An entry of the HEAD tree of a repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeadTreeEntry {
+    /// The path of the entry, relative to the repository root.
+    pub path: PathBuf,
+    /// Whether the entry is a directory.
+    pub is_dir: bool,
+    /// Whether the entry is a symbolic link.
+    pub is_symlink: bool,
+    /// The content of the file, only loaded for regular files accepted by the filter.
+    pub content: Option<Vec<u8>>,
+}
+
+/// Returns true if the provided path is a bare git repository.
+///
+/// # Arguments
+///
+/// * `repo_path` - The path to check
+///
+/// # Returns
+///
+/// * `bool` - `true` if the path is the root of a bare repository
+pub fn is_bare_repository(repo_path: &Path) -> bool {
+    open_repository(repo_path).is_ok_and(|repo| repo.is_bare())
+}
+
+/// Lists the HEAD tree of a repository without needing a working tree
+///
+/// This is what allows prompts to be generated from bare repositories such as mirror clones.
+/// Blobs are only read for regular files whose path is accepted by `keep`.
+///
+/// # Arguments
+///
+/// * `repo_path` - A reference to the path of the git repository
+/// * `keep` - Returns whether the content of a file should be loaded
+///
+/// # Returns
+///
+/// * `Result<Vec<HeadTreeEntry>>` - The directories and files of the HEAD tree, or an error
+pub fn read_head_tree<F>(repo_path: &Path, mut keep: F) -> Result<Vec<HeadTreeEntry>>
+where
+    F: FnMut(&Path) -> bool,
+{
+    let repo = open_repository(repo_path)?;
+    let tree = repo
+        .head()
+        .and_then(|head| head.peel_to_tree())
+        .context("Failed to read the HEAD tree")?;
+
+    let mut entries = Vec::new();
+    let mut error = None;
+    tree.walk(TreeWalkMode::PreOrder, |parent, entry| {
+        let Some(name) = entry.name() else {
+            return TreeWalkResult::Skip;
+        };
+        let path = Path::new(parent).join(name);
+        let mode = entry.filemode();
+        let is_dir = entry.kind() == Some(ObjectType::Tree);
+        let is_symlink = mode == i32::from(FileMode::Link);
+        // Submodules only have a commit pointer, there is nothing to read
+        if entry.kind() == Some(ObjectType::Commit) {
+            return TreeWalkResult::Ok;
+        }
+
+        let content = if !is_dir && !is_symlink && keep(&path) {
+            match repo.find_blob(entry.id()) {
+                Ok(blob) => Some(blob.content().to_vec()),
+                Err(e) => {
+                    error = Some(e);
+                    return TreeWalkResult::Abort;
+                }
+            }
+        } else {
+            None
+        };
+        entries.push(HeadTreeEntry {
+            path,
+            is_dir,
+            is_symlink,
+            content,
+        });
+        TreeWalkResult::Ok
+    })
+    .map_err(|e| error.take().unwrap_or(e))
+    .context("Failed to walk the HEAD tree")?;
+
+    Ok(entries)
+}
+
+/// A submodule of a repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubmoduleInfo {
+    /// The path of the submodule, relative to the directory given to [`list_submodules`].
+    pub path: PathBuf,
+    /// Whether the submodule is checked out (`git submodule update --init`).
+    pub initialized: bool,
+}
+
+/// Lists the submodules of the repository containing the provided path
+///
+/// Only submodules located under `repo_path` are returned, so the function can be called
+/// with any directory of a repository.
+///
+/// # Arguments
+///
+/// * `repo_path` - A reference to a path inside the git repository
+///
+/// # Returns
+///
+/// * `Result<Vec<SubmoduleInfo>>` - The submodules under `repo_path` or an error
+pub fn list_submodules(repo_path: &Path) -> Result<Vec<SubmoduleInfo>> {
+    let repo = Repository::discover(repo_path).context("Failed to open repository")?;
+    let Some(workdir) = repo.workdir() else {
+        return Ok(Vec::new());
+    };
+    let workdir = workdir.canonicalize()?;
+    let root = repo_path.canonicalize()?;
+
+    let submodules = repo
+        .submodules()
+        .context("Failed to list submodules")?
+        .iter()
+        .filter_map(|submodule| {
+            let path = workdir.join(submodule.path());
+            let relative_path = path.strip_prefix(&root).ok()?.to_path_buf();
+            Some(SubmoduleInfo {
+                path: relative_path,
+                initialized: submodule.open().is_ok(),
+            })
+        })
+        .collect();
+    Ok(submodules)
+}
+
+/// Lists the files tracked in the index of the repository containing the provided path
+///
+/// Like [`list_submodules`], only files located under `repo_path` are returned, relative to
+/// it. Submodules are skipped, and entries of a conflicted file are reported once.
+///
+/// # Arguments
+///
+/// * `repo_path` - A reference to a path inside the git repository
+///
+/// # Returns
+///
+/// * `Result<Vec<PathBuf>>` - The tracked files under `repo_path`, in index order, or an error
+pub fn list_tracked_files(repo_path: &Path) -> Result<Vec<PathBuf>> {
+    let repo = Repository::discover(repo_path).context("Failed to open repository")?;
+    let Some(workdir) = repo.workdir() else {
+        return Err(anyhow!(
+            "{} is a bare repository without an index",
+            repo_path.display()
+        ));
+    };
+    let workdir = workdir.canonicalize()?;
+    let root = repo_path.canonicalize()?;
+    let index = repo.index().context("Failed to read the index")?;
+
+    let mut files: Vec<PathBuf> = Vec::new();
+    for entry in index.iter() {
+        // Submodules are stored as gitlinks
+        if entry.mode == 0o160000 {
+            continue;
+        }
+        let path = workdir.join(String::from_utf8_lossy(&entry.path).as_ref());
+        let Ok(relative_path) = path.strip_prefix(&root) else {
+            continue;
+        };
+        // Conflicted files have one entry per stage, next to each other
+        if files.last().map(PathBuf::as_path) != Some(relative_path) {
+            files.push(relative_path.to_path_buf());
+        }
+    }
+    Ok(files)
+}
+
+/// The git attributes marking files that are left out of prompts.
+pub const OMITTING_ATTRIBUTES: [&str; 2] = ["linguist-generated", "export-ignore"];
+
+/// Finds the files marked as generated or not exported in `.gitattributes`
+///
+/// A file is omitted if it has one of the [`OMITTING_ATTRIBUTES`] set, e.g. with
+/// `dist/** linguist-generated=true` or `vendor/** export-ignore`. The repository is opened
+/// once for all paths and libgit2 caches the parsed attribute files.
+///
+/// # Arguments
+///
+/// * `repo_path` - A reference to a path inside the git repository
+/// * `paths` - The files to check, relative to `repo_path`
+///
+/// # Returns
+///
+/// * `Result<HashSet<PathBuf>>` - The paths carrying an omitting attribute or an error
+pub fn files_omitted_by_gitattributes(
+    repo_path: &Path,
+    paths: &[PathBuf],
+) -> Result<HashSet<PathBuf>> {
+    let repo = Repository::discover(repo_path).context("Failed to open repository")?;
+    let Some(workdir) = repo.workdir() else {
+        return Ok(HashSet::new());
+    };
+    let workdir = workdir.canonicalize()?;
+    let root = repo_path.canonicalize()?;
+    let prefix = root.strip_prefix(&workdir).unwrap_or(Path::new(""));
+
+    let mut omitted = HashSet::new();
+    for path in paths {
+        let repo_relative_path = prefix.join(path);
+        for attribute in OMITTING_ATTRIBUTES {
+            let value = repo
+                .get_attr_bytes(&repo_relative_path, attribute, AttrCheckFlags::default())
+                .with_context(|| format!("Failed to read the attributes of {}", path.display()))?;
+            let is_set = match AttrValue::from_bytes(value) {
+                AttrValue::True => true,
+                AttrValue::String(value) => value.eq_ignore_ascii_case("true"),
+                _ => false,
+            };
+            if is_set {
+                omitted.insert(path.clone());
+                break;
+            }
+        }
+    }
+    Ok(omitted)
+}
+
 /// Generates a git diff between two branches for the repository at the provided path
 ///
+/// This is a thin wrapper around [`get_git_diff_between_refs`], kept for compatibility.
+///
 /// # Arguments
 ///
 /// * `repo_path` - A reference to the path of the git repository
 /// * `branch1` - The name of the first branch
 /// * `branch2` - The name of the second branch
+/// * `diff_config` - The options used to generate the diff
 ///
 /// # Returns
 ///
-/// * `Result<String, git2::Error>` - The generated git diff as a string or an error
+/// * `Result<String>` - The generated git diff as a string or an error
 pub fn get_git_diff_between_branches(
     repo_path: &Path,
     branch1: &str,
     branch2: &str,
+    diff_config: &DiffConfig,
+) -> Result<String> {
+    get_git_diff_between_refs(repo_path, branch1, branch2, diff_config)
+}
+
+/// Generates a git diff between two references for the repository at the provided path
+///
+/// Both references can be any revspec git understands: branch names, tags, full or
+/// abbreviated commit hashes, or expressions such as `HEAD~2`.
+///
+/// If the references have no difference (within the configured pathspecs), the function
+/// returns a message in the format: `"no diff between <from_ref> and <to_ref>"`.
+///
+/// # Arguments
+///
+/// * `repo_path` - A reference to the path of the git repository
+/// * `from_ref` - The reference to diff from (e.g., "v1.2.0")
+/// * `to_ref` - The reference to diff to (e.g., "HEAD")
+/// * `diff_config` - The options used to generate the diff
+///
+/// # Returns
+///
+/// * `Result<String>` - The generated git diff as a string or an error
+pub fn get_git_diff_between_refs(
+    repo_path: &Path,
+    from_ref: &str,
+    to_ref: &str,
+    diff_config: &DiffConfig,
 ) -> Result<String> {
+    let mut output = Vec::new();
+    write_git_diff_between_refs(repo_path, from_ref, to_ref, diff_config, &mut output)?;
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+/// Writes the git diff between two references to a sink as it is generated
+///
+/// This is the streaming form of [`get_git_diff_between_refs`]: the patch is written line
+/// by line instead of being collected in memory, so the diff between distant references of
+/// a large repository can go straight to a file. If the sink fails, for example because a
+/// consumer stops once its token budget is spent, generation stops right away and the
+/// error is returned.
+///
+/// This is for library users: the session and the CLI still load the diff as a whole with
+/// [`get_git_diff_between_refs`], since the template renders it from a string.
+///
+/// # Arguments
+///
+/// * `repo_path` - A reference to the path of the git repository
+/// * `from_ref` - The reference to diff from (e.g., "v1.2.0")
+/// * `to_ref` - The reference to diff to (e.g., "HEAD")
+/// * `diff_config` - The options used to generate the diff
+/// * `out` - The sink the diff is written to
+///
+/// # Returns
+///
+/// * `Result<()>` - An error if the diff couldn't be generated or written
+pub fn write_git_diff_between_refs<W: Write>(
+    repo_path: &Path,
+    from_ref: &str,
+    to_ref: &str,
+    diff_config: &DiffConfig,
+    out: &mut W,
+) -> Result<()> {
     info!("Opening repository at path: {:?}", repo_path);
-    let repo = Repository::open(repo_path).context("Failed to open repository")?;
+    let repo = open_repository(repo_path)?;
 
-    for branch in [branch1, branch2].iter() {
-        if !branch_exists(&repo, branch) {
-            return Err(anyhow::anyhow!("Branch {} doesn't exist!", branch));
-        }
+    let diff = diff_between_refs(&repo, from_ref, to_ref, diff_config)?;
+    let mut out = ContentWriter::new(out);
+    write_diff(&diff, diff_config, |_| true, &mut out).context("Failed to print diff")?;
+    if diff_config.include_submodules {
+        out.write_all(submodule_diffs(&repo, &diff, diff_config)?.as_bytes())?;
     }
 
-    let branch1_commit = repo.revparse_single(branch1)?.peel_to_commit()?;
-    let branch2_commit = repo.revparse_single(branch2)?.peel_to_commit()?;
+    if !out.has_content {
+        write!(out, "no diff between {} and {}", from_ref, to_ref)?;
+    }
+    let excluded_note = excluded_files_note(count_filtered_deltas(&diff, diff_config, |_| true));
+    out.write_all(excluded_note.as_bytes())?;
 
-    let branch1_tree = branch1_commit.tree()?;
-    let branch2_tree = branch2_commit.tree()?;
+    info!("Generated git diff between references successfully");
+    Ok(())
+}
 
-    let diff = repo
-        .diff_tree_to_tree(
-            Some(&branch1_tree),
-            Some(&branch2_tree),
-            Some(DiffOptions::new().ignore_whitespace(true)),
-        )
-        .context("Failed to generate diff between branches")?;
+/// Generates the diff of a stash entry for the repository at the provided path
+///
+/// The stash is diffed against the commit it was created on, like `git stash show -p`.
+/// If the stash also recorded untracked files (`git stash -u`), they are appended as
+/// new files.
+///
+/// If the stash has no changes (within the configured pathspecs), the function returns
+/// a message in the format: `"no changes in stash@{<n>}"`.
+///
+/// # Arguments
+///
+/// * `repo_path` - A reference to the path of the git repository
+/// * `stash_index` - The index of the stash entry, `0` being the most recent
+/// * `diff_config` - The options used to generate the diff
+///
+/// # Returns
+///
+/// * `Result<String>` - The generated git diff as a string or an error
+pub fn get_git_stash_diff(
+    repo_path: &Path,
+    stash_index: usize,
+    diff_config: &DiffConfig,
+) -> Result<String> {
+    info!("Opening repository at path: {:?}", repo_path);
+    let mut repo = open_repository(repo_path)?;
 
-    let mut diff_text = Vec::new();
-    diff.print(git2::DiffFormat::Patch, |_delta, _hunk, line| {
-        diff_text.extend_from_slice(line.content());
-        true
-    })
-    .context("Failed to print diff")?;
+    let stash_oid = find_stash(&mut repo, stash_index)?;
 
-    info!("Generated git diff between branches successfully");
-    Ok(String::from_utf8_lossy(&diff_text).into_owned())
+    let mut diff_text = String::new();
+    let mut excluded = 0;
+    for diff in stash_diffs(&repo, stash_oid, diff_config)? {
+        diff_text.push_str(&diff_to_string(&diff, diff_config).context("Failed to print diff")?);
+        excluded += count_filtered_deltas(&diff, diff_config, |_| true);
+    }
+
+    if diff_text.trim().is_empty() {
+        return Ok(format!(
+            "no changes in stash@{{{}}}{}",
+            stash_index,
+            excluded_files_note(excluded)
+        ));
+    }
+    diff_text.push_str(&excluded_files_note(excluded));
+
+    info!("Generated git stash diff successfully");
+    Ok(diff_text)
 }
 
-/// Retrieves the git log between two branches for the repository at the provided path
+/// Generates a git diff between a reference and the working tree
+///
+/// Staged and unstaged changes are both compared against the reference, like
+/// `git diff <ref>`. The diff is preceded by a note telling how many commits the
+/// reference is ahead of and behind HEAD, so the direction of the changes is clear.
+///
+/// If the working tree matches the reference (within the configured pathspecs), the
+/// function returns a message in the format: `"no diff between <ref> and working tree"`.
 ///
 /// # Arguments
 ///
 /// * `repo_path` - A reference to the path of the git repository
-/// * `branch1` - The name of the first branch (e.g., "master")
-/// * `branch2` - The name of the second branch (e.g., "migrate-manifest-v3")
+/// * `reference` - The reference to diff from (e.g., "origin/main")
+/// * `diff_config` - The options used to generate the diff
 ///
 /// # Returns
 ///
-/// * `Result<String, git2::Error>` - The git log as a string or an error
-pub fn get_git_log(repo_path: &Path, branch1: &str, branch2: &str) -> Result<String> {
+/// * `Result<String>` - The generated git diff as a string or an error
+pub fn get_git_diff_workdir_to_ref(
+    repo_path: &Path,
+    reference: &str,
+    diff_config: &DiffConfig,
+) -> Result<String> {
     info!("Opening repository at path: {:?}", repo_path);
-    let repo = Repository::open(repo_path).context("Failed to open repository")?;
+    let repo = open_repository(repo_path)?;
 
-    for branch in [branch1, branch2].iter() {
-        if !branch_exists(&repo, branch) {
-            return Err(anyhow::anyhow!("Branch {} doesn't exist!", branch));
-        }
+    let (commit, diff) = workdir_to_ref_diff(&repo, reference, diff_config)?;
+    let mut diff_text = diff_to_string(&diff, diff_config).context("Failed to print diff")?;
+    if diff_config.include_submodules {
+        diff_text.push_str(&submodule_diffs(&repo, &diff, diff_config)?);
     }
 
-    let branch1_commit = repo.revparse_single(branch1)?.peel_to_commit()?;
-    let branch2_commit = repo.revparse_single(branch2)?.peel_to_commit()?;
+    let excluded_note = excluded_files_note(count_filtered_deltas(&diff, diff_config, |_| true));
+    if diff_text.trim().is_empty() {
+        return Ok(format!(
+            "no diff between {} and working tree{}",
+            reference, excluded_note
+        ));
+    }
+    if let Some(note) = ahead_behind_note(&repo, &commit, reference)? {
+        diff_text.insert_str(0, &note);
+    }
+    diff_text.push_str(&excluded_note);
 
-    let mut revwalk = repo.revwalk().context("Failed to create revwalk")?;
-    revwalk
-        .push(branch2_commit.id())
-        .context("Failed to push branch2 commit to revwalk")?;
-    revwalk
-        .hide(branch1_commit.id())
-        .context("Failed to hide branch1 commit from revwalk")?;
-    revwalk.set_sorting(git2::Sort::REVERSE)?;
+    info!("Generated git diff against reference successfully");
+    Ok(diff_text)
+}
 
-    let mut log_text = String::new();
-    for oid in revwalk {
-        let oid = oid.context("Failed to get OID from revwalk")?;
-        let commit = repo.find_commit(oid).context("Failed to find commit")?;
-        log_text.push_str(&format!(
-            "{} - {}\n",
-            &commit.id().to_string()[..7],
-            commit.summary().unwrap_or("No commit message")
-        ));
+/// Generates a structured git diff for the repository at the provided path
+///
+/// The diff is computed with the same options as the text functions ([`get_git_diff`],
+/// [`get_git_diff_between_refs`] and [`get_git_stash_diff`]), but is returned as files,
+/// hunks and lines instead of a patch. Binary files have no hunks and are left out
+/// entirely if `skip_binary` is set.
+///
+/// # Arguments
+///
+/// * `repo_path` - A reference to the path of the git repository
+/// * `target` - The changes to diff
+/// * `diff_config` - The options used to generate the diff
+///
+/// # Returns
+///
+/// * `Result<StructuredDiff>` - The structured diff or an error
+pub fn get_git_diff_structured(
+    repo_path: &Path,
+    target: DiffTarget,
+    diff_config: &DiffConfig,
+) -> Result<StructuredDiff> {
+    info!("Opening repository at path: {:?}", repo_path);
+    let mut repo = open_repository(repo_path)?;
+
+    let mut structured = StructuredDiff::default();
+    match target {
+        DiffTarget::Staged => {
+            let (staged_diff, _) = staged_diff(&repo, repo_path, diff_config)?;
+            structure_diff(&staged_diff, diff_config, |_| true, &mut structured)?;
+            if diff_config.include_untracked {
+                let unstaged_diff = unstaged_diff(&repo, diff_config)?;
+                structure_diff(
+                    &unstaged_diff,
+                    diff_config,
+                    |delta| delta.status() == Delta::Untracked,
+                    &mut structured,
+                )?;
+            }
+        }
+        DiffTarget::Refs(from_ref, to_ref) => {
+            let diff = diff_between_refs(&repo, from_ref, to_ref, diff_config)?;
+            structure_diff(&diff, diff_config, |_| true, &mut structured)?;
+        }
+        DiffTarget::Stash(stash_index) => {
+            let stash_oid = find_stash(&mut repo, stash_index)?;
+            for diff in stash_diffs(&repo, stash_oid, diff_config)? {
+                structure_diff(&diff, diff_config, |_| true, &mut structured)?;
+            }
+        }
+        DiffTarget::Commit(rev) => {
+            let (_, diff, _) = commit_diff(&repo, rev, diff_config)?;
+            structure_diff(&diff, diff_config, |_| true, &mut structured)?;
+        }
+        DiffTarget::WorkdirToRef(reference) => {
+            let (_, diff) = workdir_to_ref_diff(&repo, reference, diff_config)?;
+            structure_diff(&diff, diff_config, |_| true, &mut structured)?;
+        }
     }
 
-    info!("Retrieved git log successfully");
-    Ok(log_text)
+    info!("Generated structured git diff successfully");
+    Ok(structured)
 }
 
-/// Checks if a git reference exists in the given repository
+/// Generates the diff of a single commit, like `git show`
 ///
-/// This function can validate any git reference including:
-/// - Local and remote branch names
-/// - Commit hashes (full or abbreviated)
-/// - Tags
-/// - Any reference that git rev-parse can resolve
+/// The commit is diffed against its first parent, or the empty tree for a root commit, and
+/// preceded by its hash, author, date and full message. Merge commits are also diffed
+/// against their first parent, with a note that no combined diff is produced.
 ///
 /// # Arguments
 ///
-/// * `repo` - A reference to the `Repository` where the reference should be checked
-/// * `branch_name` - A string slice that holds the name of the reference to check
+/// * `repo_path` - A reference to the path of the git repository
+/// * `rev` - The commit to show (e.g., "HEAD", "v1.2.0", an abbreviated hash)
+/// * `diff_config` - The options used to generate the diff
+///
+/// # Returns
+///
+/// * `Result<String>` - The commit message and diff or an error
+pub fn get_commit_diff(repo_path: &Path, rev: &str, diff_config: &DiffConfig) -> Result<String> {
+    let mut output = Vec::new();
+    write_commit_diff(repo_path, rev, diff_config, &mut output)?;
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+/// Writes the message and diff of a single commit to a sink as they are generated
+///
+/// This is the streaming form of [`get_commit_diff`], see [`write_git_diff_between_refs`].
+/// Like it, this is for library users: the session loads the commit with [`get_commit_diff`].
+///
+/// # Arguments
+///
+/// * `repo_path` - A reference to the path of the git repository
+/// * `rev` - The commit to show (e.g., "HEAD", "v1.2.0", an abbreviated hash)
+/// * `diff_config` - The options used to generate the diff
+/// * `out` - The sink the commit is written to
 ///
 /// # Returns
 ///
-/// * `bool` - `true` if the reference exists, `false` otherwise
-fn branch_exists(repo: &Repository, branch_name: &str) -> bool {
-    repo.revparse_single(branch_name).is_ok()
+/// * `Result<()>` - An error if the diff couldn't be generated or written
+pub fn write_commit_diff<W: Write>(
+    repo_path: &Path,
+    rev: &str,
+    diff_config: &DiffConfig,
+    out: &mut W,
+) -> Result<()> {
+    info!("Opening repository at path: {:?}", repo_path);
+    let repo = open_repository(repo_path)?;
+
+    let (commit, diff, merge_note) = commit_diff(&repo, rev, diff_config)?;
+    out.write_all(commit_header(&commit).as_bytes())?;
+    out.write_all(merge_note.as_bytes())?;
+
+    let mut out = ContentWriter::new(out);
+    write_diff(&diff, diff_config, |_| true, &mut out).context("Failed to print diff")?;
+    if !out.has_content {
+        write!(out, "no changes in {}", rev)?;
+    }
+    let excluded_note = excluded_files_note(count_filtered_deltas(&diff, diff_config, |_| true));
+    out.write_all(excluded_note.as_bytes())?;
+
+    info!("Generated commit diff successfully");
+    Ok(())
+}
+
+/// Generates the `git show --stat` equivalent of [`get_commit_diff`]
+///
+/// # Arguments
+///
+/// * `repo_path` - A reference to the path of the git repository
+/// * `rev` - The commit to show (e.g., "HEAD", "v1.2.0", an abbreviated hash)
+/// * `diff_config` - The options used to generate the diff
+///
+/// # Returns
+///
+/// * `Result<String>` - The commit message and diff statistics or an error
+pub fn get_commit_diff_stat(
+    repo_path: &Path,
+    rev: &str,
+    diff_config: &DiffConfig,
+) -> Result<String> {
+    info!("Opening repository at path: {:?}", repo_path);
+    let repo = open_repository(repo_path)?;
+
+    let (commit, diff, merge_note) = commit_diff(&repo, rev, diff_config)?;
+    let mut output = commit_header(&commit);
+    output.push_str(&merge_note);
+    output.push_str(&diff_stats(&diff, diff_config)?.to_string());
+
+    info!("Computed commit diff stats successfully");
+    Ok(output)
+}
+
+/// Describes how two branches relate: their merge base and the commits unique to each side
+///
+/// # Arguments
+///
+/// * `repo_path` - A reference to the path of the git repository
+/// * `from` - The branch the comparison starts from (e.g., "main")
+/// * `to` - The branch compared against `from` (e.g., "feature")
+///
+/// # Returns
+///
+/// * `Result<BranchRelationship>` - The relationship of the branches or an error
+pub fn get_branch_relationship(
+    repo_path: &Path,
+    from: &str,
+    to: &str,
+) -> Result<BranchRelationship> {
+    info!("Opening repository at path: {:?}", repo_path);
+    let repo = open_repository(repo_path)?;
+
+    let from_commit = resolve_commit(&repo, from)?;
+    let to_commit = resolve_commit(&repo, to)?;
+
+    let merge_base = match repo.merge_base(from_commit.id(), to_commit.id()) {
+        Ok(oid) => {
+            let commit = repo
+                .find_commit(oid)
+                .context("Failed to find merge base commit")?;
+            let info = commit_info(&commit, &GitLogOptions::default().date_format);
+            Some(MergeBase {
+                sha: info.sha,
+                short_sha: info.short_sha,
+                diverged_at: info.date,
+            })
+        }
+        Err(e) if e.code() == ErrorCode::NotFound => None,
+        Err(e) => return Err(e).context("Failed to find merge base"),
+    };
+    let (ahead, behind) = repo
+        .graph_ahead_behind(to_commit.id(), from_commit.id())
+        .with_context(|| format!("Failed to compare {} with {}", to, from))?;
+
+    info!("Computed branch relationship successfully");
+    Ok(BranchRelationship {
+        from: from.to_string(),
+        to: to.to_string(),
+        merge_base,
+        ahead,
+        behind,
+    })
+}
+
+/// Returns the name of the branch checked out in a repository
+///
+/// # Arguments
+///
+/// * `repo_path` - A reference to the path of the git repository
+///
+/// # Returns
+///
+/// * `Result<String>` - The short name of the branch (e.g., "main"), or an error if HEAD is
+///   detached
+pub fn get_current_branch(repo_path: &Path) -> Result<String> {
+    info!("Opening repository at path: {:?}", repo_path);
+    let repo = open_repository(repo_path)?;
+
+    // HEAD names the branch even before its first commit
+    let head = repo.find_reference("HEAD").context("Failed to read HEAD")?;
+    match head.symbolic_target() {
+        Some(target) => Ok(target
+            .strip_prefix("refs/heads/")
+            .unwrap_or(target)
+            .to_string()),
+        None => bail!(
+            "HEAD is detached at {}, not on a branch",
+            head.target()
+                .map(|oid| oid.to_string()[..7].to_string())
+                .unwrap_or_default()
+        ),
+    }
+}
+
+/// Lists the most recent tags, newest first
+///
+/// Tags are ordered by the date of the commit they point to, whether they are annotated or
+/// lightweight, and tags pointing to the same commit by name. Tags that don't point to a
+/// commit are skipped.
+///
+/// # Arguments
+///
+/// * `repo_path` - A reference to the path of the git repository
+/// * `count` - The maximum number of tags to return
+///
+/// # Returns
+///
+/// * `Result<Vec<String>>` - The tag names or an error
+pub fn get_latest_tags(repo_path: &Path, count: usize) -> Result<Vec<String>> {
+    info!("Opening repository at path: {:?}", repo_path);
+    let repo = open_repository(repo_path)?;
+
+    let mut tags = Vec::new();
+    for name in repo.tag_names(None).context("Failed to list tags")?.iter() {
+        let Some(name) = name else { continue };
+        let commit = repo
+            .revparse_single(&format!("refs/tags/{}", name))
+            .and_then(|object| object.peel_to_commit());
+        match commit {
+            Ok(commit) => tags.push((commit.time().seconds(), name.to_string())),
+            Err(e) => debug!("Skipping tag {}: {}", name, e.message()),
+        }
+    }
+    tags.sort_by(|a, b| b.cmp(a));
+
+    info!("Found {} tags", tags.len());
+    Ok(tags.into_iter().take(count).map(|(_, name)| name).collect())
+}
+
+/// The references between which release notes are generated.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LatestTagsRange {
+    /// The older reference, the previous tag.
+    pub from: String,
+    /// The newer reference, the latest tag or `HEAD` if only one tag exists.
+    pub to: String,
+    /// Explains the fallback when fewer than two tags exist.
+    pub note: Option<String>,
+}
+
+/// Finds the range between the two most recent version tags
+///
+/// Tags that look like versions (`v1.2.0`, `2.0`, `pkg-v1.0.0`) are preferred over other
+/// tags when there are any. With a single tag, the range goes from it to `HEAD`.
+///
+/// # Arguments
+///
+/// * `repo_path` - A reference to the path of the git repository
+///
+/// # Returns
+///
+/// * `Result<LatestTagsRange>` - The range or an error if the repository has no tags
+pub fn get_latest_tags_range(repo_path: &Path) -> Result<LatestTagsRange> {
+    let tags = get_latest_tags(repo_path, usize::MAX)?;
+    let version_tags: Vec<&String> = tags.iter().filter(|tag| is_version_tag(tag)).collect();
+    let latest: Vec<&String> = if version_tags.is_empty() {
+        tags.iter().take(2).collect()
+    } else {
+        version_tags.into_iter().take(2).collect()
+    };
+
+    match latest.as_slice() {
+        [to, from, ..] => Ok(LatestTagsRange {
+            from: from.to_string(),
+            to: to.to_string(),
+            note: None,
+        }),
+        [tag] => Ok(LatestTagsRange {
+            from: tag.to_string(),
+            to: "HEAD".to_string(),
+            note: Some(format!(
+                "Note: {} is the only tag; showing the changes from it to HEAD\n\n",
+                tag
+            )),
+        }),
+        [] => Err(anyhow!(
+            "No tags found in the repository. Create one with `git tag <name>`, fetch them with `git fetch --tags`, or pass the references to --git-diff-branch instead"
+        )),
+    }
+}
+
+/// Checks if a tag name looks like a version, optionally prefixed (e.g. `v1.2`, `pkg-v1.0.0`).
+fn is_version_tag(name: &str) -> bool {
+    name.split(['/', '-', '_', '@']).any(|part| {
+        part.trim_start_matches(['v', 'V'])
+            .starts_with(|c: char| c.is_ascii_digit())
+    })
+}
+
+/// Computes `git diff --stat` style statistics between two references
+///
+/// Renamed files are reported once with both their old and new path, and binary
+/// files are flagged instead of being given line counts.
+///
+/// # Arguments
+///
+/// * `repo_path` - A reference to the path of the git repository
+/// * `from_ref` - The reference to diff from (e.g., "v1.2.0")
+/// * `to_ref` - The reference to diff to (e.g., "HEAD")
+/// * `diff_config` - The options used to generate the diff
+///
+/// # Returns
+///
+/// * `Result<DiffStats>` - The per-file and total statistics or an error
+pub fn get_git_diff_stats(
+    repo_path: &Path,
+    from_ref: &str,
+    to_ref: &str,
+    diff_config: &DiffConfig,
+) -> Result<DiffStats> {
+    info!("Opening repository at path: {:?}", repo_path);
+    let repo = open_repository(repo_path)?;
+
+    let diff = diff_between_refs(&repo, from_ref, to_ref, diff_config)?;
+    let stats = diff_stats(&diff, diff_config)?;
+
+    info!("Computed git diff stats successfully");
+    Ok(stats)
+}
+
+/// Trims a patch diff to a token budget, keeping whole hunks
+///
+/// If the diff exceeds `token_limit`, hunks are kept from the smallest to the largest while
+/// they fit, so the largest hunks are the first to go. Kept hunks stay in their original
+/// order with their file header, and a line telling how many hunks and tokens were omitted
+/// is appended. Text outside of files (e.g. notes about HEAD) is always kept, and a diff
+/// within the budget is returned unchanged.
+///
+/// # Arguments
+///
+/// * `diff` - The diff text, as returned by the diff functions
+/// * `token_limit` - The maximum number of tokens of the diff
+/// * `count_tokens` - Counts the tokens of a piece of text with the configured tokenizer
+///
+/// # Returns
+///
+/// * `String` - The diff, truncated if needed
+pub fn truncate_diff<F>(diff: &str, token_limit: usize, count_tokens: F) -> String
+where
+    F: Fn(&str) -> usize,
+{
+    if count_tokens(diff) <= token_limit {
+        return diff.to_string();
+    }
+
+    let patch = ParsedPatch::parse(diff);
+    let mut used = count_tokens(&patch.preamble) + count_tokens(&patch.trailer);
+
+    // Files without hunks (binary files, pure renames) are kept like a hunk of their own
+    let header_tokens: Vec<usize> = patch
+        .files
+        .iter()
+        .map(|file| count_tokens(&file.header))
+        .collect();
+    let mut items: Vec<(usize, Option<usize>, usize)> = Vec::new();
+    for (file_index, file) in patch.files.iter().enumerate() {
+        if file.hunks.is_empty() {
+            items.push((file_index, None, 0));
+        }
+        for (hunk_index, hunk) in file.hunks.iter().enumerate() {
+            items.push((file_index, Some(hunk_index), count_tokens(hunk)));
+        }
+    }
+    items.sort_by_key(|&(_, _, tokens)| tokens);
+
+    let mut kept_files = vec![false; patch.files.len()];
+    let mut kept_hunks: Vec<Vec<bool>> = patch
+        .files
+        .iter()
+        .map(|file| vec![false; file.hunks.len()])
+        .collect();
+    let (mut omitted_hunks, mut omitted_tokens) = (0, 0);
+    for (file_index, hunk_index, tokens) in items {
+        let header_cost = if kept_files[file_index] {
+            0
+        } else {
+            header_tokens[file_index]
+        };
+        if used + tokens + header_cost <= token_limit {
+            used += tokens + header_cost;
+            kept_files[file_index] = true;
+            if let Some(hunk_index) = hunk_index {
+                kept_hunks[file_index][hunk_index] = true;
+            }
+        } else {
+            omitted_tokens += tokens;
+            if hunk_index.is_some() {
+                omitted_hunks += 1;
+            }
+        }
+    }
+
+    let mut output = patch.preamble.clone();
+    for (file_index, file) in patch.files.iter().enumerate() {
+        if !kept_files[file_index] {
+            omitted_tokens += header_tokens[file_index];
+            continue;
+        }
+        output.push_str(&file.header);
+        for (hunk, kept) in file.hunks.iter().zip(&kept_hunks[file_index]) {
+            if *kept {
+                output.push_str(hunk);
+            }
+        }
+    }
+    if !output.is_empty() && !output.ends_with('\n') {
+        output.push('\n');
+    }
+    output.push_str(&format!(
+        "... diff truncated: {} {} / {} tokens omitted\n",
+        omitted_hunks,
+        if omitted_hunks == 1 { "hunk" } else { "hunks" },
+        omitted_tokens.to_formatted_string(&Locale::en)
+    ));
+    output.push_str(patch.trailer.trim_start_matches('\n'));
+    output
+}
+
+/// Options controlling which commits are retrieved and how the git log is formatted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitLogOptions {
+    /// If true, the body of each commit message is included below its summary.
+    pub include_body: bool,
+    /// If true, the author name and email of each commit are included.
+    pub include_author: bool,
+    /// If true, the author date of each commit is included.
+    pub include_date: bool,
+    /// The `strftime`-like format used to render commit dates.
+    pub date_format: String,
+    /// If set, only the most recent `max_commits` commits are retrieved.
+    pub max_commits: Option<usize>,
+    /// If true, the files changed by each commit relative to its first parent are listed.
+    pub include_files: bool,
+    /// If set, at most this many changed files are listed per commit.
+    pub max_files: Option<usize>,
+    /// If set, only commits whose author name or email contains this string (case-insensitive) are kept.
+    pub author: Option<String>,
+    /// If set, only commits authored at or after this Unix timestamp are kept.
+    pub since: Option<i64>,
+    /// If set, only commits authored at or before this Unix timestamp are kept.
+    pub until: Option<i64>,
+    /// If true, merge commits (with more than one parent) are left out.
+    pub skip_merges: bool,
+    /// If true, only the first parent of merge commits is followed, like `git log --first-parent`.
+    pub first_parent: bool,
+}
+
+impl GitLogOptions {
+    /// Returns true if any author or date filter is set.
+    pub fn has_filters(&self) -> bool {
+        self.author.is_some() || self.since.is_some() || self.until.is_some()
+    }
+
+    /// Returns true if the commit passes the author and date filters.
+    fn matches(&self, commit: &CommitInfo) -> bool {
+        let author_matches = self.author.as_ref().is_none_or(|author| {
+            let author = author.to_lowercase();
+            commit.author_name.to_lowercase().contains(&author)
+                || commit.author_email.to_lowercase().contains(&author)
+        });
+        let since_matches = self.since.is_none_or(|since| commit.timestamp >= since);
+        let until_matches = self.until.is_none_or(|until| commit.timestamp <= until);
+        author_matches && since_matches && until_matches
+    }
+}
+
+impl Default for GitLogOptions {
+    fn default() -> Self {
+        Self {
+            include_body: false,
+            include_author: false,
+            include_date: false,
+            date_format: "%Y-%m-%d %H:%M:%S %z".to_string(),
+            max_commits: None,
+            include_files: false,
+            max_files: None,
+            author: None,
+            since: None,
+            until: None,
+            skip_merges: false,
+            first_parent: false,
+        }
+    }
+}
+
+/// A single commit of the git log, as exposed to templates.
+#[derive(Debug, Clone, Serialize)]
+pub struct CommitInfo {
+    /// The full commit hash.
+    pub sha: String,
+    /// The abbreviated (7 characters) commit hash.
+    pub short_sha: String,
+    /// The first line of the commit message.
+    pub summary: String,
+    /// The commit message without its summary, empty if there is none.
+    pub body: String,
+    /// The author, as `Name <email>`.
+    pub author: String,
+    /// The author name.
+    pub author_name: String,
+    /// The author email.
+    pub author_email: String,
+    /// The author date, in seconds since the Unix epoch, for the `date` template helper.
+    pub timestamp: i64,
+    /// The author date rendered with [`GitLogOptions::date_format`].
+    pub date: String,
+    /// The files changed relative to the first parent, set only with
+    /// [`GitLogOptions::include_files`].
+    pub files: Vec<CommitFileChange>,
+    /// Number of changed files left out of `files` by [`GitLogOptions::max_files`].
+    pub more_files: usize,
+}
+
+/// A file changed by a commit.
+#[derive(Debug, Clone, Serialize)]
+pub struct CommitFileChange {
+    /// The kind of change, as in `git log --name-status`: `A`, `M`, `D`, `R`, `C` or `T`.
+    pub status: char,
+    /// Path of the file after the change.
+    pub path: String,
+    /// Path of the file before the change, set only when the file was renamed or copied.
+    pub old_path: Option<String>,
+}
+
+/// The commits retrieved from a walk of the git history.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CommitLog {
+    /// The commits, oldest first.
+    pub commits: Vec<CommitInfo>,
+    /// If true, the walk reached the boundary of a shallow clone, so older commits of the
+    /// range may be missing.
+    pub truncated: bool,
+}
+
+impl CommitLog {
+    /// Returns the note telling that the history is truncated, if it is, e.g.
+    /// `"(history truncated: shallow clone, 12 commits shown)"`.
+    pub fn truncation_note(&self) -> Option<String> {
+        self.truncated.then(|| {
+            format!(
+                "(history truncated: shallow clone, {} commits shown)",
+                self.commits.len()
+            )
+        })
+    }
+}
+
+/// Retrieves the git log between two branches for the repository at the provided path
+///
+/// # Arguments
+///
+/// * `repo_path` - A reference to the path of the git repository
+/// * `branch1` - The name of the first branch (e.g., "master")
+/// * `branch2` - The name of the second branch (e.g., "migrate-manifest-v3")
+/// * `log_options` - The options controlling which commits are listed and how
+///
+/// # Returns
+///
+/// * `Result<String>` - The git log as a string or an error
+pub fn get_git_log(
+    repo_path: &Path,
+    branch1: &str,
+    branch2: &str,
+    log_options: &GitLogOptions,
+) -> Result<String> {
+    let log = get_git_log_commits(repo_path, branch1, branch2, log_options)?;
+    Ok(format_git_log(&log, log_options))
+}
+
+/// Retrieves the commits between two branches, oldest first
+///
+/// Commit messages and signatures that are not valid UTF-8 are converted lossily. In a
+/// shallow clone, the walk stops at the shallow boundary and the log is marked as truncated.
+///
+/// # Arguments
+///
+/// * `repo_path` - A reference to the path of the git repository
+/// * `branch1` - The name of the first branch (e.g., "master")
+/// * `branch2` - The name of the second branch (e.g., "migrate-manifest-v3")
+/// * `log_options` - The options controlling which commits are retrieved
+///
+/// # Returns
+///
+/// * `Result<CommitLog>` - The commits reachable from `branch2` but not from `branch1`
+pub fn get_git_log_commits(
+    repo_path: &Path,
+    branch1: &str,
+    branch2: &str,
+    log_options: &GitLogOptions,
+) -> Result<CommitLog> {
+    info!("Opening repository at path: {:?}", repo_path);
+    let repo = open_repository(repo_path)?;
+
+    let branch1_commit = resolve_revspec(&repo, "Branch", branch1)?.peel_to_commit()?;
+    let branch2_commit = resolve_revspec(&repo, "Branch", branch2)?.peel_to_commit()?;
+
+    let log = walk_log(
+        &repo,
+        &[branch2_commit.id()],
+        &[branch1_commit.id()],
+        log_options,
+        log_options.max_commits,
+    )?;
+
+    info!("Retrieved git log successfully");
+    Ok(log)
+}
+
+/// Number of commits listed when a log range names a single reference and no limit is set.
+pub const DEFAULT_LOG_RANGE_COUNT: usize = 20;
+
+/// Retrieves the git log for a revision range for the repository at the provided path
+///
+/// The range accepts the same forms as `git log`:
+///
+/// * `A..B` - commits reachable from `B` but not from `A` (e.g. `v2.0.0..HEAD`)
+/// * `A...B` - commits reachable from either side but not from their merge base
+/// * `REF` - the most recent commits reachable from `REF`
+/// * `-n 20` or `-20` - the last 20 commits reachable from `HEAD`
+///
+/// An omitted side of `..` or `...` defaults to `HEAD`. For single references the number of
+/// commits is limited by [`GitLogOptions::max_commits`], or [`DEFAULT_LOG_RANGE_COUNT`] if unset.
+///
+/// # Arguments
+///
+/// * `repo_path` - A reference to the path of the git repository
+/// * `range` - The revision range (e.g., "HEAD~10..HEAD")
+/// * `log_options` - The options controlling which commits are listed and how
+///
+/// # Returns
+///
+/// * `Result<String>` - The git log as a string or an error
+pub fn get_git_log_range(
+    repo_path: &Path,
+    range: &str,
+    log_options: &GitLogOptions,
+) -> Result<String> {
+    let log = get_git_log_range_commits(repo_path, range, log_options)?;
+    Ok(format_git_log(&log, log_options))
+}
+
+/// Retrieves the commits of a revision range, oldest first.
+///
+/// See [`get_git_log_range`] for the accepted range forms.
+///
+/// # Arguments
+///
+/// * `repo_path` - A reference to the path of the git repository
+/// * `range` - The revision range (e.g., "HEAD~10..HEAD")
+/// * `log_options` - The options controlling which commits are listed
+///
+/// # Returns
+///
+/// * `Result<CommitLog>` - The commits in the range or an error
+pub fn get_git_log_range_commits(
+    repo_path: &Path,
+    range: &str,
+    log_options: &GitLogOptions,
+) -> Result<CommitLog> {
+    info!("Opening repository at path: {:?}", repo_path);
+    let repo = open_repository(repo_path)?;
+
+    let log = match parse_log_range(range)? {
+        LogRange::Count(count) => {
+            let head = resolve_commit(&repo, "HEAD")?;
+            let limit = log_options.max_commits.map_or(count, |max| max.min(count));
+            walk_log(&repo, &[head.id()], &[], log_options, Some(limit))?
+        }
+        LogRange::Single(reference) => {
+            let tip = resolve_commit(&repo, reference)?;
+            let limit = log_options.max_commits.unwrap_or(DEFAULT_LOG_RANGE_COUNT);
+            walk_log(&repo, &[tip.id()], &[], log_options, Some(limit))?
+        }
+        LogRange::TwoDot(from, to) => {
+            let from = resolve_commit(&repo, from)?;
+            let to = resolve_commit(&repo, to)?;
+            walk_log(
+                &repo,
+                &[to.id()],
+                &[from.id()],
+                log_options,
+                log_options.max_commits,
+            )?
+        }
+        LogRange::ThreeDot(left, right) => {
+            let left = resolve_commit(&repo, left)?;
+            let right = resolve_commit(&repo, right)?;
+            let merge_base = match repo.merge_base(left.id(), right.id()) {
+                Ok(merge_base) => Some(merge_base),
+                // The merge base may lie beyond the boundary of a shallow clone
+                Err(_) if repo.is_shallow() => None,
+                Err(e) => {
+                    return Err(e).with_context(|| {
+                        format!(
+                            "No merge base found between {} and {}",
+                            left.id(),
+                            right.id()
+                        )
+                    })
+                }
+            };
+            let mut log = walk_log(
+                &repo,
+                &[left.id(), right.id()],
+                merge_base.as_slice(),
+                log_options,
+                log_options.max_commits,
+            )?;
+            log.truncated |= merge_base.is_none();
+            log
+        }
+    };
+
+    info!("Retrieved git log successfully");
+    Ok(log)
+}
+
+/// A parsed git log revision range.
+#[derive(Debug, PartialEq, Eq)]
+enum LogRange<'a> {
+    /// The last N commits from HEAD.
+    Count(usize),
+    /// The most recent commits reachable from a reference.
+    Single(&'a str),
+    /// Commits reachable from the second reference but not from the first.
+    TwoDot(&'a str, &'a str),
+    /// Commits reachable from either reference but not from their merge base.
+    ThreeDot(&'a str, &'a str),
+}
+
+/// Splits a revision range into its references, defaulting omitted sides to `HEAD`.
+fn parse_log_range(range: &str) -> Result<LogRange<'_>> {
+    let invalid = || {
+        anyhow!(
+            "Invalid log range '{}': expected A..B, A...B, a single reference or -n <count>",
+            range
+        )
+    };
+    let range = range.trim();
+
+    if let Some(count) = range.strip_prefix("-n") {
+        return count
+            .trim()
+            .parse()
+            .map(LogRange::Count)
+            .map_err(|_| invalid());
+    }
+    if let Some(count) = range.strip_prefix('-') {
+        return count.parse().map(LogRange::Count).map_err(|_| invalid());
+    }
+
+    let (separator, three_dot) = if range.contains("...") {
+        ("...", true)
+    } else if range.contains("..") {
+        ("..", false)
+    } else if range.is_empty() {
+        return Err(invalid());
+    } else {
+        return Ok(LogRange::Single(range));
+    };
+
+    let (from, to) = range.split_once(separator).ok_or_else(invalid)?;
+    if (from.is_empty() && to.is_empty()) || to.contains("..") || to.starts_with('.') {
+        return Err(invalid());
+    }
+    let from = if from.is_empty() { "HEAD" } else { from };
+    let to = if to.is_empty() { "HEAD" } else { to };
+    Ok(if three_dot {
+        LogRange::ThreeDot(from, to)
+    } else {
+        LogRange::TwoDot(from, to)
+    })
+}
+
+/// Walks the history from `push` excluding commits reachable from `hide`.
+///
+/// Returns up to `limit` commits matching the log filters, oldest first. In a shallow
+/// clone, reaching a grafted commit or a missing parent ends the walk and marks the log
+/// as truncated.
+fn walk_log(
+    repo: &Repository,
+    push: &[git2::Oid],
+    hide: &[git2::Oid],
+    log_options: &GitLogOptions,
+    limit: Option<usize>,
+) -> Result<CommitLog> {
+    let mut revwalk = repo.revwalk().context("Failed to create revwalk")?;
+    for oid in push {
+        revwalk
+            .push(*oid)
+            .context("Failed to push commit to revwalk")?;
+    }
+    for oid in hide {
+        revwalk
+            .hide(*oid)
+            .context("Failed to hide commit from revwalk")?;
+    }
+    // Walk newest first so the limit keeps the most recent commits
+    revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME)?;
+    if log_options.first_parent {
+        revwalk
+            .simplify_first_parent()
+            .context("Failed to simplify the revwalk to first parents")?;
+    }
+
+    // Filtered out commits are skipped rather than hidden so the ancestry walk stays intact
+    let limit = limit.unwrap_or(usize::MAX);
+    let shallow_boundary = shallow_boundary(repo);
+    let mut log = CommitLog::default();
+    for oid in revwalk {
+        if log.commits.len() >= limit {
+            break;
+        }
+        let oid = match oid {
+            Ok(oid) => oid,
+            Err(e) if repo.is_shallow() => {
+                warn!(
+                    "Stopping the git log at a missing commit of the shallow clone: {}",
+                    e
+                );
+                log.truncated = true;
+                break;
+            }
+            Err(e) => return Err(e).context("Failed to get OID from revwalk"),
+        };
+        let commit = repo.find_commit(oid).context("Failed to find commit")?;
+        log.truncated |= shallow_boundary.contains(&oid);
+        if log_options.skip_merges && commit.parent_count() > 1 {
+            continue;
+        }
+        let mut info = commit_info(&commit, &log_options.date_format);
+        if log_options.matches(&info) {
+            if log_options.include_files {
+                let mut files = commit_file_changes(repo, &commit)?;
+                if let Some(max_files) = log_options.max_files {
+                    info.more_files = files.len().saturating_sub(max_files);
+                    files.truncate(max_files);
+                }
+                info.files = files;
+            }
+            log.commits.push(info);
+        }
+    }
+    log.commits.reverse();
+    Ok(log)
+}
+
+/// Returns the commits at the boundary of a shallow clone, whose parents are missing.
+fn shallow_boundary(repo: &Repository) -> HashSet<git2::Oid> {
+    if !repo.is_shallow() {
+        return HashSet::new();
+    }
+    std::fs::read_to_string(repo.path().join("shallow"))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| git2::Oid::from_str(line.trim()).ok())
+        .collect()
+}
+
+/// Formats commits as a git log according to the provided options.
+///
+/// With the default options each commit is rendered on a single `<short-sha> - <summary>` line.
+/// If filters are set and no commit matched them, a message saying so is returned instead.
+/// A truncated history is followed by its [`CommitLog::truncation_note`].
+///
+/// # Arguments
+///
+/// * `log` - The commits to format
+/// * `log_options` - The options controlling which details are rendered
+///
+/// # Returns
+///
+/// * `String` - The formatted git log
+pub fn format_git_log(log: &CommitLog, log_options: &GitLogOptions) -> String {
+    let note = log.truncation_note();
+    if log.commits.is_empty() && log_options.has_filters() {
+        let message = "no commits match the author/date filters".to_string();
+        return match note {
+            Some(note) => format!("{}\n{}", message, note),
+            None => message,
+        };
+    }
+
+    let mut log_text = format_commits(&log.commits, log_options);
+    if let Some(note) = note {
+        log_text.push_str(&note);
+        log_text.push('\n');
+    }
+    log_text
+}
+
+/// Renders each commit of a git log.
+fn format_commits(commits: &[CommitInfo], log_options: &GitLogOptions) -> String {
+    let mut log_text = String::new();
+    for commit in commits {
+        log_text.push_str(&format!("{} - {}\n", commit.short_sha, commit.summary));
+        if log_options.include_author {
+            log_text.push_str(&format!(
+                "    Author: {} <{}>\n",
+                commit.author_name, commit.author_email
+            ));
+        }
+        if log_options.include_date {
+            log_text.push_str(&format!("    Date:   {}\n", commit.date));
+        }
+        if log_options.include_files {
+            for file in &commit.files {
+                match &file.old_path {
+                    Some(old_path) => log_text.push_str(&format!(
+                        "    {} {} -> {}\n",
+                        file.status, old_path, file.path
+                    )),
+                    None => log_text.push_str(&format!("    {} {}\n", file.status, file.path)),
+                }
+            }
+            if commit.more_files > 0 {
+                log_text.push_str(&format!("    (+{} more)\n", commit.more_files));
+            }
+        }
+        if log_options.include_body && !commit.body.is_empty() {
+            log_text.push('\n');
+            for line in commit.body.lines() {
+                if !line.is_empty() {
+                    log_text.push_str("    ");
+                }
+                log_text.push_str(line);
+                log_text.push('\n');
+            }
+            log_text.push('\n');
+        }
+    }
+    log_text
+}
+
+/// The commits of a range grouped by their conventional-commit type.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Changelog {
+    /// Commits by type (e.g. "feat", "fix"), oldest first. Commits that do not follow the
+    /// conventional-commit format are grouped under [`CHANGELOG_OTHER`].
+    pub groups: BTreeMap<String, Vec<ChangelogEntry>>,
+    /// Number of commits that introduce a breaking change.
+    pub breaking_changes: usize,
+}
+
+/// The group of commits that do not follow the conventional-commit format.
+pub const CHANGELOG_OTHER: &str = "other";
+
+/// A commit of a [`Changelog`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChangelogEntry {
+    /// The abbreviated (7 characters) commit hash.
+    pub short_sha: String,
+    /// The scope given in parentheses after the type, if any.
+    pub scope: Option<String>,
+    /// The summary without its type and scope prefix.
+    pub summary: String,
+    /// If true, the commit is marked as a breaking change, with a `!` after its type or a
+    /// `BREAKING CHANGE:` footer.
+    pub breaking: bool,
+}
+
+/// Generates a changelog from the conventional commits between two references
+///
+/// The range is the same as [`get_git_log`]: commits reachable from `to` but not from
+/// `from`. Summaries such as `feat(parser)!: drop the legacy syntax` are split into their
+/// type, scope and summary; commits that do not follow the format are kept under
+/// [`CHANGELOG_OTHER`] with their full summary.
+///
+/// # Arguments
+///
+/// * `repo_path` - A reference to the path of the git repository
+/// * `from` - The reference the changelog starts after (e.g., "v1.2.0")
+/// * `to` - The reference the changelog ends at (e.g., "HEAD")
+///
+/// # Returns
+///
+/// * `Result<Changelog>` - The commits grouped by type or an error
+pub fn get_conventional_changelog(repo_path: &Path, from: &str, to: &str) -> Result<Changelog> {
+    info!("Opening repository at path: {:?}", repo_path);
+    let repo = open_repository(repo_path)?;
+
+    let from_commit = resolve_commit(&repo, from)?;
+    let to_commit = resolve_commit(&repo, to)?;
+    let log = walk_log(
+        &repo,
+        &[to_commit.id()],
+        &[from_commit.id()],
+        &GitLogOptions::default(),
+        None,
+    )?;
+    if let Some(note) = log.truncation_note() {
+        warn!(
+            "Changelog between {} and {} may be incomplete {}",
+            from, to, note
+        );
+    }
+
+    let mut changelog = Changelog::default();
+    for commit in log.commits {
+        let (kind, entry) = parse_conventional_commit(&commit);
+        if entry.breaking {
+            changelog.breaking_changes += 1;
+        }
+        changelog.groups.entry(kind).or_default().push(entry);
+    }
+
+    info!("Generated conventional changelog successfully");
+    Ok(changelog)
+}
+
+/// Parses a date given on the command line into a Unix timestamp.
+///
+/// Accepts RFC 3339 timestamps (`2024-05-01T12:00:00+02:00`), ISO dates and date-times
+/// interpreted as UTC (`2024-05-01`, `2024-05-01 12:00`), `now`, `today`, `yesterday`,
+/// and relative forms such as `2 weeks ago` or `3 days ago`.
+///
+/// # Arguments
+///
+/// * `input` - The date to parse
+///
+/// # Returns
+///
+/// * `Result<i64>` - The corresponding Unix timestamp, or an error describing the accepted formats
+pub fn parse_git_date(input: &str) -> Result<i64> {
+    let input = input.trim();
+    let now = Utc::now();
+
+    if let Ok(date) = DateTime::parse_from_rfc3339(input) {
+        return Ok(date.timestamp());
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(date) = NaiveDateTime::parse_from_str(input, format) {
+            return Ok(date.and_utc().timestamp());
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        return Ok(date.and_time(NaiveTime::MIN).and_utc().timestamp());
+    }
+
+    let today = now.date_naive().and_time(NaiveTime::MIN).and_utc();
+    match input.to_lowercase().as_str() {
+        "now" => return Ok(now.timestamp()),
+        "today" => return Ok(today.timestamp()),
+        "yesterday" => return Ok((today - Duration::days(1)).timestamp()),
+        _ => {}
+    }
+
+    let words: Vec<String> = input
+        .to_lowercase()
+        .split_whitespace()
+        .map(str::to_string)
+        .collect();
+    if let [amount, unit, ago] = words.as_slice() {
+        if let (Ok(amount), "ago") = (amount.parse::<i64>(), ago.as_str()) {
+            let seconds_per_unit = match unit.trim_end_matches('s') {
+                "second" | "sec" => Some(1),
+                "minute" | "min" => Some(60),
+                "hour" => Some(60 * 60),
+                "day" => Some(24 * 60 * 60),
+                "week" => Some(7 * 24 * 60 * 60),
+                "month" => Some(30 * 24 * 60 * 60),
+                "year" => Some(365 * 24 * 60 * 60),
+                _ => None,
+            };
+            if let Some(seconds) = seconds_per_unit {
+                return Ok(now.timestamp() - amount * seconds);
+            }
+        }
+    }
+
+    Err(anyhow!(
+        "Invalid date '{}'. Use an ISO date (2024-05-01), an RFC 3339 timestamp or a relative date (2 weeks ago)",
+        input
+    ))
+}
+
+/// How blame annotations are rendered in the prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum BlameMode {
+    /// Every line is prefixed with its commit, author and age.
+    Lines,
+    /// A single header line is emitted for each run of lines from the same commit.
+    Hunks,
+}
+
+/// The blame information of a single line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BlameLine {
+    /// The 1-based line number in the working tree file.
+    pub line_number: usize,
+    /// The abbreviated hash of the commit that last changed the line.
+    pub short_sha: String,
+    /// The author of that commit.
+    pub author: String,
+    /// The author date, in seconds since the Unix epoch.
+    pub timestamp: i64,
+    /// The age of the change relative to now (e.g. "3 days ago").
+    pub age: String,
+}
+
+/// Short hash shown for lines that are not committed yet.
+const UNCOMMITTED_SHA: &str = "0000000";
+
+/// Retrieves the blame of a file for the repository at the provided path
+///
+/// The blame is computed against the working tree content, so uncommitted lines are
+/// reported with a zero hash and the author "Not Committed Yet".
+///
+/// # Arguments
+///
+/// * `repo_path` - A reference to the path of the git repository
+/// * `file_path` - The path of the file, relative to `repo_path`
+///
+/// # Returns
+///
+/// * `Result<Vec<BlameLine>>` - One entry per line of the file, or an error if the file is not tracked
+pub fn get_git_blame(repo_path: &Path, file_path: &Path) -> Result<Vec<BlameLine>> {
+    let repo = Repository::discover(repo_path).context("Failed to open repository")?;
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| anyhow!("Cannot blame files in a bare repository"))?;
+
+    let absolute_path = repo_path
+        .join(file_path)
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {}", file_path.display()))?;
+    let relative_path = absolute_path
+        .strip_prefix(workdir.canonicalize()?)
+        .with_context(|| format!("{} is outside the repository", file_path.display()))?
+        .to_path_buf();
+
+    let blame = repo
+        .blame_file(&relative_path, None)
+        .with_context(|| format!("{} is not tracked by git", file_path.display()))?;
+    let content = std::fs::read(&absolute_path)
+        .with_context(|| format!("Failed to read {}", file_path.display()))?;
+    let blame = blame
+        .blame_buffer(&content)
+        .context("Failed to blame working tree content")?;
+
+    let now = Utc::now().timestamp();
+    // Commit authors are cached since a file usually has few distinct commits
+    let mut authors: HashMap<git2::Oid, (String, i64)> = HashMap::new();
+    let mut lines = Vec::new();
+    let line_count = String::from_utf8_lossy(&content).lines().count();
+    for line_number in 1..=line_count {
+        let Some(hunk) = blame.get_line(line_number) else {
+            continue;
+        };
+        let oid = hunk.final_commit_id();
+        // Uncommitted hunks have no signature, so only committed ones are looked up
+        let line = if oid.is_zero() {
+            BlameLine {
+                line_number,
+                short_sha: UNCOMMITTED_SHA.to_string(),
+                author: "Not Committed Yet".to_string(),
+                timestamp: now,
+                age: "uncommitted".to_string(),
+            }
+        } else {
+            let (author, timestamp) = match authors.entry(oid) {
+                Entry::Occupied(entry) => entry.get().clone(),
+                Entry::Vacant(entry) => {
+                    let commit = repo.find_commit(oid).context("Failed to find commit")?;
+                    let author = commit.author();
+                    let name = String::from_utf8_lossy(author.name_bytes()).into_owned();
+                    entry.insert((name, author.when().seconds())).clone()
+                }
+            };
+            BlameLine {
+                line_number,
+                short_sha: oid.to_string()[..7].to_string(),
+                author,
+                timestamp,
+                age: format_age(now - timestamp),
+            }
+        };
+        lines.push(line);
+    }
+
+    Ok(lines)
+}
+
+/// The most recent commit that touched a file.
+#[derive(Debug, Clone, Serialize)]
+pub struct LastCommit {
+    /// The full commit hash.
+    pub sha: String,
+    /// The abbreviated commit hash.
+    pub short_sha: String,
+    /// The author name.
+    pub author: String,
+    /// The author date, formatted as `YYYY-MM-DD`.
+    pub date: String,
+    /// The first line of the commit message.
+    pub summary: String,
+}
+
+/// Finds the most recent commit touching each of the given paths
+///
+/// The history is walked once from HEAD, newest first, and the walk stops as soon as every
+/// path has been found. A merge commit is only credited with the files it changed relative
+/// to all of its parents, so that a change is attributed to the commit that made it, like
+/// `git log -1 -- <path>`. Paths outside the repository or never committed are left out.
+///
+/// # Arguments
+///
+/// * `repo_path` - A reference to the path of the git repository
+/// * `paths` - The paths of the files, relative to `repo_path`
+///
+/// # Returns
+///
+/// * `Result<HashMap<PathBuf, LastCommit>>` - The last commit of each path that has one
+pub fn last_commits_for_paths(
+    repo_path: &Path,
+    paths: &[PathBuf],
+) -> Result<HashMap<PathBuf, LastCommit>> {
+    let repo = Repository::discover(repo_path).context("Failed to open repository")?;
+    let prefix = match repo.workdir() {
+        Some(workdir) => repo_path
+            .canonicalize()?
+            .strip_prefix(workdir.canonicalize()?)
+            .map(Path::to_path_buf)
+            .unwrap_or_default(),
+        None => PathBuf::new(),
+    };
+
+    // Paths relative to the repository root, mapped back to the requested paths
+    let mut pending: HashMap<PathBuf, &PathBuf> =
+        paths.iter().map(|path| (prefix.join(path), path)).collect();
+    let mut last_commits = HashMap::new();
+    if pending.is_empty() || repo.head().is_err() {
+        return Ok(last_commits);
+    }
+
+    let mut revwalk = repo.revwalk().context("Failed to create revwalk")?;
+    revwalk
+        .push_head()
+        .context("Failed to push HEAD to revwalk")?;
+    revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME)?;
+
+    for oid in revwalk {
+        let commit = repo
+            .find_commit(oid.context("Failed to get OID from revwalk")?)
+            .context("Failed to find commit")?;
+        let tree = commit.tree()?;
+
+        let mut changed: Option<HashSet<PathBuf>> = None;
+        let parents: Vec<Option<Commit>> = if commit.parent_count() == 0 {
+            vec![None]
+        } else {
+            commit.parents().map(Some).collect()
+        };
+        for parent in parents {
+            let parent_tree = parent.map(|parent| parent.tree()).transpose()?;
+            let diff = repo
+                .diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)
+                .context("Failed to diff commit")?;
+            let paths: HashSet<PathBuf> = diff
+                .deltas()
+                .filter_map(|delta| delta.new_file().path().map(Path::to_path_buf))
+                .filter(|path| pending.contains_key(path))
+                .collect();
+            changed = Some(match changed {
+                Some(changed) => changed.intersection(&paths).cloned().collect(),
+                None => paths,
+            });
+        }
+
+        let changed = changed.unwrap_or_default();
+        if changed.is_empty() {
+            continue;
+        }
+        let info = commit_info(&commit, "%Y-%m-%d");
+        let last_commit = LastCommit {
+            sha: info.sha,
+            short_sha: info.short_sha,
+            author: info.author_name,
+            date: info.date,
+            summary: info.summary,
+        };
+        for path in changed {
+            if let Some(requested) = pending.remove(&path) {
+                last_commits.insert(requested.clone(), last_commit.clone());
+            }
+        }
+        if pending.is_empty() {
+            break;
+        }
+    }
+
+    Ok(last_commits)
+}
+
+/// The maximum number of contributors listed by default.
+pub const DEFAULT_CONTRIBUTORS_COUNT: usize = 10;
+
+/// The maximum number of commits walked by default to find the contributors.
+pub const DEFAULT_CONTRIBUTORS_COMMIT_LIMIT: usize = 10_000;
+
+/// An author of the repository and their activity.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Contributor {
+    /// The author name, as written in their most recent commit.
+    pub name: String,
+    /// The author email, which identifies the contributor.
+    pub email: String,
+    /// The number of commits authored.
+    pub commits: usize,
+    /// The date of the most recent commit, formatted as `YYYY-MM-DD`.
+    pub last_commit: String,
+}
+
+/// Aggregates the authors of the history, most active first
+///
+/// Identities are resolved through the repository's `.mailmap` and merged by email, case
+/// insensitively, so alternate names and emails of the same person collapse into one entry.
+/// Ties are broken by the most recent activity.
+///
+/// # Arguments
+///
+/// * `repo_path` - A reference to the path of the git repository
+/// * `max` - The maximum number of contributors to return
+/// * `range` - The revision range to walk (e.g. "v1.0.0..HEAD"), all of HEAD's history if unset
+/// * `commit_limit` - The maximum number of commits walked, the most recent ones
+///
+/// # Returns
+///
+/// * `Result<Vec<Contributor>>` - The contributors or an error
+pub fn get_contributors(
+    repo_path: &Path,
+    max: usize,
+    range: Option<&str>,
+    commit_limit: usize,
+) -> Result<Vec<Contributor>> {
+    let log_options = GitLogOptions {
+        date_format: "%Y-%m-%d".to_string(),
+        max_commits: Some(commit_limit),
+        ..GitLogOptions::default()
+    };
+    let log = get_git_log_range_commits(repo_path, range.unwrap_or("HEAD"), &log_options)?;
+    if let Some(note) = log.truncation_note() {
+        warn!("Contributors may be incomplete {}", note);
+    }
+
+    let repo = open_repository(repo_path)?;
+    let mailmap = repo.mailmap().context("Failed to read the mailmap")?;
+
+    // Keyed by lowercase email, the latest commit of each contributor coming first
+    let mut contributors: HashMap<String, (Contributor, i64)> = HashMap::new();
+    for commit in log.commits.iter().rev() {
+        let (name, email) =
+            match Signature::new(&commit.author_name, &commit.author_email, &Time::new(0, 0))
+                .and_then(|signature| mailmap.resolve_signature(&signature))
+            {
+                Ok(signature) => (
+                    String::from_utf8_lossy(signature.name_bytes()).into_owned(),
+                    String::from_utf8_lossy(signature.email_bytes()).into_owned(),
+                ),
+                Err(_) => (commit.author_name.clone(), commit.author_email.clone()),
+            };
+        let (contributor, latest) = contributors.entry(email.to_lowercase()).or_insert_with(|| {
+            (
+                Contributor {
+                    name,
+                    email,
+                    commits: 0,
+                    last_commit: commit.date.clone(),
+                },
+                commit.timestamp,
+            )
+        });
+        contributor.commits += 1;
+        if commit.timestamp > *latest {
+            contributor.last_commit = commit.date.clone();
+            *latest = commit.timestamp;
+        }
+    }
+
+    let mut contributors: Vec<(Contributor, i64)> = contributors.into_values().collect();
+    contributors.sort_by(|(a, a_latest), (b, b_latest)| {
+        b.commits
+            .cmp(&a.commits)
+            .then(b_latest.cmp(a_latest))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+
+    info!("Found {} contributors", contributors.len());
+    Ok(contributors
+        .into_iter()
+        .take(max)
+        .map(|(contributor, _)| contributor)
+        .collect())
+}
+
+/// Annotates source code with its blame information.
+///
+/// Lines without blame information are emitted unannotated.
+///
+/// # Arguments
+///
+/// * `code` - The source code of the file
+/// * `blame` - The blame lines returned by [`get_git_blame`]
+/// * `mode` - Whether to annotate every line or each hunk
+/// * `line_numbers` - Whether to add line numbers to the code
+///
+/// # Returns
+///
+/// * `String` - The annotated source code
+pub fn annotate_with_blame(
+    code: &str,
+    blame: &[BlameLine],
+    mode: BlameMode,
+    line_numbers: bool,
+) -> String {
+    let by_line: HashMap<usize, &BlameLine> =
+        blame.iter().map(|line| (line.line_number, line)).collect();
+    let author_width = blame
+        .iter()
+        .map(|line| line.author.chars().count())
+        .max()
+        .unwrap_or(0);
+    let age_width = blame.iter().map(|line| line.age.len()).max().unwrap_or(0);
+    let number_width = line_number_width(code.lines().count());
+
+    let mut annotated = String::new();
+    let mut previous_sha: Option<&str> = None;
+    for (index, source_line) in code.lines().enumerate() {
+        let line_number = index + 1;
+        let line_blame = by_line.get(&line_number);
+        let numbered = if line_numbers {
+            format!("{:>number_width$} | {}", line_number, source_line)
+        } else {
+            source_line.to_string()
+        };
+
+        match (mode, line_blame) {
+            (BlameMode::Lines, Some(b)) => annotated.push_str(&format!(
+                "{} {:author_width$} {:>age_width$} | {}\n",
+                b.short_sha, b.author, b.age, numbered
+            )),
+            (BlameMode::Hunks, Some(b)) => {
+                if previous_sha != Some(b.short_sha.as_str()) {
+                    let mut end = line_number;
+                    while by_line
+                        .get(&(end + 1))
+                        .is_some_and(|next| next.short_sha == b.short_sha)
+                    {
+                        end += 1;
+                    }
+                    annotated.push_str(&format!(
+                        "[{} {}, {} (lines {}-{})]\n",
+                        b.short_sha, b.author, b.age, line_number, end
+                    ));
+                }
+                annotated.push_str(&numbered);
+                annotated.push('\n');
+            }
+            (_, None) => {
+                annotated.push_str(&numbered);
+                annotated.push('\n');
+            }
+        }
+        previous_sha = line_blame.map(|b| b.short_sha.as_str());
+    }
+    annotated
+}
+
+/// Renders an age in seconds the way `git log --date=relative` does.
+fn format_age(seconds: i64) -> String {
+    const MINUTE: i64 = 60;
+    const HOUR: i64 = 60 * MINUTE;
+    const DAY: i64 = 24 * HOUR;
+    const MONTH: i64 = 30 * DAY;
+    const YEAR: i64 = 365 * DAY;
+
+    let (amount, unit) = match seconds.max(0) {
+        s if s < MINUTE => return "just now".to_string(),
+        s if s < HOUR => (s / MINUTE, "minute"),
+        s if s < DAY => (s / HOUR, "hour"),
+        s if s < MONTH => (s / DAY, "day"),
+        s if s < YEAR => (s / MONTH, "month"),
+        s => (s / YEAR, "year"),
+    };
+    let plural = if amount == 1 { "" } else { "s" };
+    format!("{} {}{} ago", amount, unit, plural)
+}
+
+/// Splits a commit into its conventional-commit type and changelog entry.
+fn parse_conventional_commit(commit: &CommitInfo) -> (String, ChangelogEntry) {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    let pattern = PATTERN.get_or_init(|| {
+        Regex::new(
+            r"^(?P<type>[A-Za-z]+)(?:\((?P<scope>[^()]*)\))?(?P<bang>!)?:\s+(?P<summary>\S.*)$",
+        )
+        .unwrap()
+    });
+    let breaking_footer = commit
+        .body
+        .lines()
+        .any(|line| line.starts_with("BREAKING CHANGE:") || line.starts_with("BREAKING-CHANGE:"));
+
+    match pattern.captures(commit.summary.trim()) {
+        Some(captures) => (
+            captures["type"].to_lowercase(),
+            ChangelogEntry {
+                short_sha: commit.short_sha.clone(),
+                scope: captures
+                    .name("scope")
+                    .map(|scope| scope.as_str().trim().to_string())
+                    .filter(|scope| !scope.is_empty()),
+                summary: captures["summary"].trim().to_string(),
+                breaking: captures.name("bang").is_some() || breaking_footer,
+            },
+        ),
+        None => (
+            CHANGELOG_OTHER.to_string(),
+            ChangelogEntry {
+                short_sha: commit.short_sha.clone(),
+                scope: None,
+                summary: commit.summary.clone(),
+                breaking: breaking_footer,
+            },
+        ),
+    }
+}
+
+/// Extracts the details of a commit, converting non UTF-8 data lossily.
+fn commit_info(commit: &Commit, date_format: &str) -> CommitInfo {
+    let sha = commit.id().to_string();
+    let summary = commit
+        .summary_bytes()
+        .map(|summary| String::from_utf8_lossy(summary).into_owned())
+        .unwrap_or_else(|| "No commit message".to_string());
+    let body = commit
+        .body_bytes()
+        .map(|body| String::from_utf8_lossy(body).trim().to_string())
+        .unwrap_or_default();
+    let author = commit.author();
+    let time = author.when();
+
+    let author_name = String::from_utf8_lossy(author.name_bytes()).into_owned();
+    let author_email = String::from_utf8_lossy(author.email_bytes()).into_owned();
+
+    CommitInfo {
+        short_sha: sha[..7].to_string(),
+        sha,
+        summary,
+        body,
+        author: format!("{} <{}>", author_name, author_email),
+        author_name,
+        author_email,
+        timestamp: time.seconds(),
+        date: format_git_time(time, date_format),
+        files: Vec::new(),
+        more_files: 0,
+    }
+}
+
+/// Lists the files a commit changed relative to its first parent, renames included.
+fn commit_file_changes(repo: &Repository, commit: &Commit) -> Result<Vec<CommitFileChange>> {
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree()?),
+        Err(_) => None,
+    };
+    let mut diff = repo
+        .diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)
+        .context("Failed to diff commit")?;
+    diff.find_similar(None)
+        .context("Failed to detect renames")?;
+
+    let path_of = |file: git2::DiffFile| file.path().map(|p| p.to_string_lossy().into_owned());
+    Ok(diff
+        .deltas()
+        .map(|delta| {
+            let status = match delta.status() {
+                Delta::Added => 'A',
+                Delta::Deleted => 'D',
+                Delta::Renamed => 'R',
+                Delta::Copied => 'C',
+                Delta::Typechange => 'T',
+                _ => 'M',
+            };
+            let old_path = path_of(delta.old_file());
+            CommitFileChange {
+                status,
+                path: path_of(delta.new_file())
+                    .or_else(|| old_path.clone())
+                    .unwrap_or_default(),
+                old_path: old_path.filter(|_| matches!(status, 'R' | 'C')),
+            }
+        })
+        .collect())
+}
+
+/// Formats a git timestamp in its original timezone.
+fn format_git_time(time: git2::Time, date_format: &str) -> String {
+    let offset = FixedOffset::east_opt(time.offset_minutes() * 60)
+        .unwrap_or_else(|| FixedOffset::east_opt(0).unwrap());
+    match DateTime::from_timestamp(time.seconds(), 0) {
+        Some(date) => date.with_timezone(&offset).format(date_format).to_string(),
+        None => time.seconds().to_string(),
+    }
+}
+
+/// Generates the tree-to-tree diff between two references.
+///
+/// With `merge_base` enabled, the diff starts from the merge base of both references
+/// so that changes made only on `from_ref` since they diverged are left out. In a shallow
+/// clone where the merge base is out of reach, the references are diffed directly.
+fn diff_between_refs<'repo>(
+    repo: &'repo Repository,
+    from_ref: &str,
+    to_ref: &str,
+    diff_config: &DiffConfig,
+) -> Result<Diff<'repo>> {
+    let from_commit = resolve_commit(repo, from_ref)?;
+    let to_commit = resolve_commit(repo, to_ref)?;
+
+    let from_commit = if diff_config.merge_base {
+        match repo.merge_base(from_commit.id(), to_commit.id()) {
+            Ok(base_oid) => repo
+                .find_commit(base_oid)
+                .context("Failed to find merge base commit")?,
+            // The merge base may lie beyond the boundary of a shallow clone
+            Err(_) if repo.is_shallow() => {
+                warn!(
+                    "No merge base between {} and {} within the shallow clone, diffing them directly",
+                    from_ref, to_ref
+                );
+                from_commit
+            }
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!("No merge base found between {} and {}", from_ref, to_ref)
+                })
+            }
+        }
+    } else {
+        from_commit
+    };
+
+    let from_tree = from_commit.tree()?;
+    let to_tree = to_commit.tree()?;
+
+    let mut diff = repo
+        .diff_tree_to_tree(
+            Some(&from_tree),
+            Some(&to_tree),
+            Some(&mut diff_options(diff_config)?),
+        )
+        .context("Failed to generate diff between references")?;
+    detect_renames(&mut diff, diff_config)?;
+    Ok(diff)
+}
+
+/// Resolves a reference and diffs its tree against the working tree, through the index.
+fn workdir_to_ref_diff<'repo>(
+    repo: &'repo Repository,
+    reference: &str,
+    diff_config: &DiffConfig,
+) -> Result<(Commit<'repo>, Diff<'repo>)> {
+    if repo.is_bare() {
+        return Err(anyhow!(
+            "Cannot diff {} against the working tree of a bare repository",
+            reference
+        ));
+    }
+    let commit = resolve_commit(repo, reference)?;
+
+    let mut diff = repo
+        .diff_tree_to_workdir_with_index(
+            Some(&commit.tree()?),
+            Some(&mut workdir_diff_options(repo, diff_config)?),
+        )
+        .with_context(|| format!("Failed to generate diff against {}", reference))?;
+    detect_renames(&mut diff, diff_config)?;
+    Ok((commit, diff))
+}
+
+/// Describes where a commit stands relative to HEAD, e.g. "Note: origin/main is 2 commit(s)
+/// ahead of and 1 commit(s) behind HEAD". Returns `None` when HEAD is unborn.
+fn ahead_behind_note(
+    repo: &Repository,
+    commit: &Commit,
+    reference: &str,
+) -> Result<Option<String>> {
+    let head = match repo.head().ok().and_then(|head| head.target()) {
+        Some(head) => head,
+        None => return Ok(None),
+    };
+    // Commits reachable from the reference but not from HEAD, and the other way around
+    let (ahead, behind) = repo
+        .graph_ahead_behind(commit.id(), head)
+        .context("Failed to compare the reference with HEAD")?;
+
+    let position = match (ahead, behind) {
+        (0, 0) => "the same commit as HEAD".to_string(),
+        (ahead, 0) => format!("{} commit(s) ahead of HEAD", ahead),
+        (0, behind) => format!("{} commit(s) behind HEAD", behind),
+        (ahead, behind) => format!(
+            "{} commit(s) ahead of and {} commit(s) behind HEAD",
+            ahead, behind
+        ),
+    };
+    Ok(Some(format!(
+        "Note: {} is {}; the diff goes from {} to the working tree\n\n",
+        reference, position, reference
+    )))
+}
+
+/// Generates the diff of the staged changes (HEAD vs. index).
+///
+/// An unborn HEAD (no commits yet) is diffed against the empty tree. The returned note
+/// describes the state of HEAD when it is unborn or detached.
+fn staged_diff<'repo>(
+    repo: &'repo Repository,
+    repo_path: &Path,
+    diff_config: &DiffConfig,
+) -> Result<(Diff<'repo>, Option<String>)> {
+    if repo.is_bare() {
+        return Err(anyhow!(
+            "Cannot diff staged changes in {}: it is a bare repository without a working tree. Use a diff between references instead",
+            repo_path.display()
+        ));
+    }
+
+    let (head_tree, head_note) = match repo.head() {
+        Ok(head) => {
+            let commit = head
+                .peel_to_commit()
+                .context("Failed to peel HEAD to a commit")?;
+            let note = repo.head_detached().unwrap_or(false).then(|| {
+                format!(
+                    "HEAD is detached at {}; showing changes against the checked-out commit\n",
+                    &commit.id().to_string()[..7]
+                )
+            });
+            (commit.tree().context("Failed to get HEAD tree")?, note)
+        }
+        Err(e) if e.code() == ErrorCode::UnbornBranch => {
+            let empty_tree_id = repo
+                .treebuilder(None)
+                .and_then(|builder| builder.write())
+                .context("Failed to create empty tree")?;
+            let note = "repository has no commits yet; showing all staged files as additions\n";
+            (repo.find_tree(empty_tree_id)?, Some(note.to_string()))
+        }
+        Err(e) => return Err(e).context("Failed to get repository head"),
+    };
+
+    let mut diff = repo
+        .diff_tree_to_index(
+            Some(&head_tree),
+            None,
+            Some(&mut diff_options(diff_config)?),
+        )
+        .context("Failed to generate diff for staged changes")?;
+    detect_renames(&mut diff, diff_config)?;
+    Ok((diff, head_note))
+}
+
+/// Generates the diff of the unstaged changes (index vs. working directory).
+fn unstaged_diff<'repo>(repo: &'repo Repository, diff_config: &DiffConfig) -> Result<Diff<'repo>> {
+    let mut diff = repo
+        .diff_index_to_workdir(None, Some(&mut workdir_diff_options(repo, diff_config)?))
+        .context("Failed to generate diff for unstaged changes")?;
+    detect_renames(&mut diff, diff_config)?;
+    Ok(diff)
+}
+
+/// Finds the commit of a stash entry, `0` being the most recent.
+fn find_stash(repo: &mut Repository, stash_index: usize) -> Result<git2::Oid> {
+    let mut stashes = Vec::new();
+    repo.stash_foreach(|_, _, oid| {
+        stashes.push(*oid);
+        true
+    })
+    .context("Failed to list stashes")?;
+
+    match stashes.get(stash_index) {
+        Some(oid) => Ok(*oid),
+        None if stashes.is_empty() => Err(anyhow!(
+            "Stash stash@{{{}}} doesn't exist! The repository has no stashes",
+            stash_index
+        )),
+        None => Err(anyhow!(
+            "Stash stash@{{{}}} doesn't exist! The repository has {} stash(es): stash@{{0}} to stash@{{{}}}",
+            stash_index,
+            stashes.len(),
+            stashes.len() - 1
+        )),
+    }
+}
+
+/// Generates the diffs of a stash entry against the commit it was created on.
+///
+/// `git stash -u` stores untracked files in a third parent with no history; when present,
+/// they are returned as a second diff of new files.
+fn stash_diffs<'repo>(
+    repo: &'repo Repository,
+    stash_oid: git2::Oid,
+    diff_config: &DiffConfig,
+) -> Result<Vec<Diff<'repo>>> {
+    let stash = repo
+        .find_commit(stash_oid)
+        .context("Failed to find stash commit")?;
+    let base = stash.parent(0).context("Stash has no base commit")?;
+
+    let mut opts = diff_options(diff_config)?;
+    let mut diff = repo
+        .diff_tree_to_tree(Some(&base.tree()?), Some(&stash.tree()?), Some(&mut opts))
+        .context("Failed to generate stash diff")?;
+    detect_renames(&mut diff, diff_config)?;
+    let mut diffs = vec![diff];
+
+    if let Ok(untracked) = stash.parent(2) {
+        let mut opts = diff_options(diff_config)?;
+        let untracked_diff = repo
+            .diff_tree_to_tree(None, Some(&untracked.tree()?), Some(&mut opts))
+            .context("Failed to generate diff of stashed untracked files")?;
+        diffs.push(untracked_diff);
+    }
+    Ok(diffs)
+}
+
+/// Appends the deltas of a diff accepted by `keep` to a structured diff.
+fn structure_diff<F>(
+    diff: &Diff,
+    diff_config: &DiffConfig,
+    keep: F,
+    structured: &mut StructuredDiff,
+) -> Result<()>
+where
+    F: Fn(&DiffDelta) -> bool,
+{
+    let path_string = |path: Option<&Path>| path.map(|p| p.to_string_lossy().to_string());
+    for idx in 0..diff.deltas().len() {
+        let delta = diff.get_delta(idx).context("Failed to get diff delta")?;
+        if !keep(&delta) || !delta_matches_filter(&delta, diff_config) {
+            continue;
+        }
+        let patch = Patch::from_diff(diff, idx).context("Failed to compute file patch")?;
+        let is_binary = delta.flags().is_binary() || patch.is_none();
+        if is_binary && diff_config.skip_binary {
+            continue;
+        }
+
+        let mut hunks = Vec::new();
+        if let (Some(patch), false) = (&patch, is_binary) {
+            for hunk_idx in 0..patch.num_hunks() {
+                let (hunk, line_count) = patch.hunk(hunk_idx)?;
+                let mut lines = Vec::with_capacity(line_count);
+                for line_idx in 0..line_count {
+                    let line = patch.line_in_hunk(hunk_idx, line_idx)?;
+                    lines.push(StructuredDiffLine {
+                        origin: line.origin(),
+                        content: String::from_utf8_lossy(line.content()).into_owned(),
+                    });
+                }
+                hunks.push(StructuredDiffHunk {
+                    old_start: hunk.old_start(),
+                    old_lines: hunk.old_lines(),
+                    new_start: hunk.new_start(),
+                    new_lines: hunk.new_lines(),
+                    header: String::from_utf8_lossy(hunk.header())
+                        .trim_end()
+                        .to_string(),
+                    lines,
+                });
+            }
+        }
+
+        let (old_path, new_path) = match delta.status() {
+            Delta::Added | Delta::Untracked => (None, path_string(delta.new_file().path())),
+            Delta::Deleted => (path_string(delta.old_file().path()), None),
+            _ => (
+                path_string(delta.old_file().path()),
+                path_string(delta.new_file().path()),
+            ),
+        };
+        structured.files.push(StructuredDiffFile {
+            old_path,
+            new_path,
+            status: delta_status_name(delta.status()).to_string(),
+            is_binary,
+            hunks,
+        });
+    }
+    Ok(())
+}
+
+/// Returns the lowercase name of a delta status, e.g. "renamed".
+fn delta_status_name(status: Delta) -> &'static str {
+    match status {
+        Delta::Unmodified => "unmodified",
+        Delta::Added => "added",
+        Delta::Deleted => "deleted",
+        Delta::Modified => "modified",
+        Delta::Renamed => "renamed",
+        Delta::Copied => "copied",
+        Delta::Ignored => "ignored",
+        Delta::Untracked => "untracked",
+        Delta::Typechange => "typechange",
+        Delta::Unreadable => "unreadable",
+        Delta::Conflicted => "conflicted",
+    }
+}
+
+/// Computes the per-file and total statistics of a diff.
+fn diff_stats(diff: &Diff, diff_config: &DiffConfig) -> Result<DiffStats> {
+    let mut stats = DiffStats::default();
+    for idx in 0..diff.deltas().len() {
+        let patch = Patch::from_diff(diff, idx).context("Failed to compute file patch")?;
+        let delta = match &patch {
+            Some(patch) => patch.delta(),
+            None => diff.get_delta(idx).context("Failed to get diff delta")?,
+        };
+        if !delta_matches_filter(&delta, diff_config) {
+            continue;
+        }
+
+        let new_path = delta
+            .new_file()
+            .path()
+            .map(|p| p.to_string_lossy().to_string());
+        let old_path = delta
+            .old_file()
+            .path()
+            .map(|p| p.to_string_lossy().to_string());
+        let path = new_path
+            .clone()
+            .or_else(|| old_path.clone())
+            .unwrap_or_default();
+        let old_path = match delta.status() {
+            Delta::Renamed | Delta::Copied => old_path,
+            _ => None,
+        };
+
+        let is_binary = delta.flags().is_binary() || patch.is_none();
+        let (insertions, deletions) = match (&patch, is_binary) {
+            (Some(patch), false) => {
+                let (_, insertions, deletions) = patch.line_stats()?;
+                (insertions, deletions)
+            }
+            _ => (0, 0),
+        };
+
+        stats.insertions += insertions;
+        stats.deletions += deletions;
+        stats.files.push(FileDiffStat {
+            path,
+            old_path,
+            insertions,
+            deletions,
+            is_binary,
+        });
+    }
+    stats.files_changed = stats.files.len();
+
+    Ok(stats)
+}
+
+/// Resolves a commit and diffs it against its first parent, or the empty tree for a root
+/// commit. The returned note is set for merge commits.
+fn commit_diff<'repo>(
+    repo: &'repo Repository,
+    rev: &str,
+    diff_config: &DiffConfig,
+) -> Result<(Commit<'repo>, Diff<'repo>, String)> {
+    let commit = resolve_commit(repo, rev)?;
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree()?),
+        Err(_) => None,
+    };
+    let merge_note = if commit.parent_count() > 1 {
+        format!(
+            "Note: {} is a merge commit; showing the diff against its first parent, not a combined diff\n\n",
+            &commit.id().to_string()[..7]
+        )
+    } else {
+        String::new()
+    };
+
+    let mut diff = repo
+        .diff_tree_to_tree(
+            parent_tree.as_ref(),
+            Some(&commit.tree()?),
+            Some(&mut diff_options(diff_config)?),
+        )
+        .context("Failed to generate commit diff")?;
+    detect_renames(&mut diff, diff_config)?;
+    Ok((commit, diff, merge_note))
+}
+
+/// Formats the hash, author, date and message of a commit like `git show`.
+fn commit_header(commit: &Commit) -> String {
+    let info = commit_info(commit, &GitLogOptions::default().date_format);
+    let mut header = format!(
+        "commit {}\nAuthor: {} <{}>\nDate:   {}\n\n",
+        info.sha, info.author_name, info.author_email, info.date
+    );
+    for line in String::from_utf8_lossy(commit.message_bytes())
+        .trim_end()
+        .lines()
+    {
+        if line.is_empty() {
+            header.push('\n');
+        } else {
+            header.push_str(&format!("    {}\n", line));
+        }
+    }
+    header.push('\n');
+    header
+}
+
+/// Pairs up deleted and added files so renames and copies are rendered as such
+/// instead of a full deletion followed by a full addition.
+fn detect_renames(diff: &mut Diff, diff_config: &DiffConfig) -> Result<()> {
+    let mut find_options = DiffFindOptions::new();
+    find_options
+        .renames(true)
+        .copies(true)
+        .rename_threshold(diff_config.rename_threshold)
+        .copy_threshold(diff_config.rename_threshold)
+        .ignore_whitespace(diff_config.whitespace != WhitespaceMode::None);
+    diff.find_similar(Some(&mut find_options))
+        .context("Failed to detect renamed files")?;
+    Ok(())
+}
+
+/// Builds the `DiffOptions` corresponding to a [`DiffConfig`].
+fn diff_options(diff_config: &DiffConfig) -> Result<DiffOptions> {
+    let mut options = DiffOptions::new();
+    for pathspec in normalize_pathspecs(&diff_config.pathspecs)? {
+        options.pathspec(pathspec);
+    }
+    options
+        .context_lines(diff_config.context_lines)
+        .interhunk_lines(diff_config.interhunk_lines);
+    diff_config.whitespace.apply(&mut options);
+
+    if diff_config.include_untracked {
+        // Ignored files stay excluded, see `workdir_diff_options` for `include_ignored`
+        options
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .show_untracked_content(true);
+    }
+    Ok(options)
+}
+
+/// Builds the options of a diff against the working tree.
+///
+/// libgit2 never loads the content of ignored files, so with `include_ignored` the ignore
+/// rules of this repository handle are overridden instead, turning ignored files into
+/// untracked ones.
+fn workdir_diff_options(repo: &Repository, diff_config: &DiffConfig) -> Result<DiffOptions> {
+    if diff_config.include_untracked && diff_config.include_ignored {
+        repo.add_ignore_rule("!*")
+            .context("Failed to override the ignore rules")?;
+    }
+    diff_options(diff_config)
+}
+
+/// Translates user pathspecs into the form understood by libgit2.
+///
+/// libgit2 stops at the first matching pattern and only understands the `!` prefix for
+/// negation, so exclusions are moved first and, when no positive pattern remains, a
+/// catch-all is appended so that everything else is still included.
+fn normalize_pathspecs(pathspecs: &[String]) -> Result<Vec<String>> {
+    let mut excludes = Vec::new();
+    let mut includes = Vec::new();
+
+    for pathspec in pathspecs {
+        let negated = [":(exclude)", ":!", ":^", "!"]
+            .iter()
+            .find_map(|prefix| pathspec.strip_prefix(prefix));
+
+        match negated {
+            Some("") => {
+                return Err(anyhow!(
+                    "Invalid pathspec '{}': nothing to exclude",
+                    pathspec
+                ));
+            }
+            Some(pattern) => excludes.push(format!("!{}", pattern)),
+            None if pathspec.starts_with(':') => {
+                return Err(anyhow!(
+                    "Unsupported pathspec '{}': only the exclude magic (':!' or ':(exclude)') is supported",
+                    pathspec
+                ));
+            }
+            None => includes.push(pathspec.clone()),
+        }
+    }
+
+    if !excludes.is_empty() && includes.is_empty() {
+        includes.push("*".to_string());
+    }
+    excludes.extend(includes);
+    Ok(excludes)
+}
+
+/// Returns true if a delta is accepted by the `path_filter` of the config.
+///
+/// Renamed and copied files are matched against both their old and new path, and kept if
+/// either matches so that files moving into or out of the selection stay visible. Submodule
+/// pointers are kept when submodules are included, as the filter applies to their own diff.
+fn delta_matches_filter(delta: &DiffDelta, diff_config: &DiffConfig) -> bool {
+    let Some(filter) = &diff_config.path_filter else {
+        return true;
+    };
+    let (old_file, new_file) = (delta.old_file(), delta.new_file());
+    if diff_config.include_submodules
+        && (old_file.mode() == FileMode::Commit || new_file.mode() == FileMode::Commit)
+    {
+        return true;
+    }
+    [old_file.path(), new_file.path()]
+        .into_iter()
+        .flatten()
+        .any(|path| filter.is_match(path))
+}
+
+/// Counts the deltas accepted by `keep` that the `path_filter` of the config leaves out.
+fn count_filtered_deltas<F>(diff: &Diff, diff_config: &DiffConfig, keep: F) -> usize
+where
+    F: Fn(&DiffDelta) -> bool,
+{
+    diff.deltas()
+        .filter(|delta| keep(delta) && !delta_matches_filter(delta, diff_config))
+        .count()
+}
+
+/// Formats the line telling how many files were left out of a diff by the patterns.
+/// Returns true if a diff is the message of a diff without changes, e.g.
+/// `"no diff between HEAD and index"` or `"no changes in stash@{0}"`.
+pub fn is_empty_diff(diff: &str) -> bool {
+    let diff = diff.trim_start();
+    diff.starts_with("no diff between ") || diff.starts_with("no changes in ")
+}
+
+fn excluded_files_note(excluded: usize) -> String {
+    match excluded {
+        0 => String::new(),
+        1 => "\n(+1 file excluded from diff by patterns)".to_string(),
+        _ => format!("\n(+{} files excluded from diff by patterns)", excluded),
+    }
+}
+
+/// Prints a diff in patch format and returns it as a string.
+fn diff_to_string(diff: &Diff, diff_config: &DiffConfig) -> Result<String> {
+    print_diff(diff, diff_config, |_delta| true)
+}
+
+/// Describes the submodule pointer changes of a diff.
+///
+/// Each changed submodule gets a `Submodule <path> <old>..<new>` line, followed by the diff
+/// between both commits inside the submodule when it is checked out and has them. The inner
+/// diff paths are prefixed with the submodule path so pathspecs apply across the boundary.
+fn submodule_diffs(repo: &Repository, diff: &Diff, diff_config: &DiffConfig) -> Result<String> {
+    let pathspecs = normalize_pathspecs(&diff_config.pathspecs)?;
+    let pathspec = if pathspecs.is_empty() {
+        None
+    } else {
+        Some(git2::Pathspec::new(pathspecs.iter())?)
+    };
+
+    let mut output = String::new();
+    for delta in diff.deltas() {
+        let (old_file, new_file) = (delta.old_file(), delta.new_file());
+        if old_file.mode() != FileMode::Commit && new_file.mode() != FileMode::Commit {
+            continue;
+        }
+        let Some(path) = new_file.path().or(old_file.path()) else {
+            continue;
+        };
+        let (old_id, new_id) = (old_file.id(), new_file.id());
+        let short = |oid: git2::Oid| oid.to_string()[..7].to_string();
+        output.push_str(&format!(
+            "Submodule {} {}..{}",
+            path.display(),
+            short(old_id),
+            short(new_id)
+        ));
+
+        if old_id.is_zero() || new_id.is_zero() {
+            output.push_str(if old_id.is_zero() {
+                " (new submodule)\n"
+            } else {
+                " (deleted submodule)\n"
+            });
+            continue;
+        }
+
+        let inner = repo
+            .find_submodule(&path.to_string_lossy())
+            .and_then(|submodule| submodule.open())
+            .ok()
+            .and_then(|sub_repo| {
+                let old_tree = sub_repo.find_commit(old_id).ok()?.tree().ok()?;
+                let new_tree = sub_repo.find_commit(new_id).ok()?.tree().ok()?;
+                let mut opts = DiffOptions::new();
+                diff_config.whitespace.apply(&mut opts);
+                opts.context_lines(diff_config.context_lines)
+                    .interhunk_lines(diff_config.interhunk_lines)
+                    .old_prefix(format!("a/{}/", path.display()))
+                    .new_prefix(format!("b/{}/", path.display()));
+                let inner_diff = sub_repo
+                    .diff_tree_to_tree(Some(&old_tree), Some(&new_tree), Some(&mut opts))
+                    .ok()?;
+                let inner_config = DiffConfig {
+                    path_filter: None,
+                    ..diff_config.clone()
+                };
+                print_diff(&inner_diff, &inner_config, |inner_delta| {
+                    let Some(inner_path) = inner_delta.new_file().path() else {
+                        return true;
+                    };
+                    let full_path = path.join(inner_path);
+                    pathspec.as_ref().is_none_or(|pathspec| {
+                        pathspec.matches_path(&full_path, git2::PathspecFlags::DEFAULT)
+                    }) && diff_config
+                        .path_filter
+                        .as_ref()
+                        .is_none_or(|filter| filter.is_match(&full_path))
+                })
+                .ok()
+            });
+        match inner {
+            Some(inner_diff) => {
+                output.push_str(":\n");
+                output.push_str(&inner_diff);
+            }
+            None => output.push_str(" (commits not available locally)\n"),
+        }
+    }
+    Ok(output)
+}
+
+/// Prints the deltas of a diff accepted by `keep` in patch format and returns them as a string.
+fn print_diff<F>(diff: &Diff, diff_config: &DiffConfig, keep: F) -> Result<String>
+where
+    F: Fn(&DiffDelta) -> bool,
+{
+    let mut diff_text = Vec::new();
+    write_diff(diff, diff_config, keep, &mut diff_text)?;
+    Ok(String::from_utf8_lossy(&diff_text).into_owned())
+}
+
+/// Writes the deltas of a diff accepted by `keep` in patch format to a sink, line by line.
+///
+/// Binary deltas are replaced with a single descriptive line, or skipped entirely if
+/// `skip_binary` is set, so raw bytes never end up in the prompt. With the word render
+/// style, changed lines are merged into inline `[-old-]{+new+}` markers. A write error
+/// stops the diff at once.
+fn write_diff<F, W>(diff: &Diff, diff_config: &DiffConfig, keep: F, out: &mut W) -> Result<()>
+where
+    F: Fn(&DiffDelta) -> bool,
+    W: Write,
+{
+    let mut write_error: Option<io::Error> = None;
+    let mut described_binary: Option<PathBuf> = None;
+    let mut word_diff = WordDiffBuffer::default();
+    let printed = diff.print(git2::DiffFormat::Patch, |delta, _hunk, line| {
+        if !keep(&delta) || !delta_matches_filter(&delta, diff_config) {
+            return true;
+        }
+        let written = if delta.flags().is_binary() {
+            let path = delta
+                .new_file()
+                .path()
+                .or(delta.old_file().path())
+                .map(Path::to_path_buf);
+            if diff_config.skip_binary || described_binary == path {
+                return true;
+            }
+            described_binary = path;
+            out.write_all(describe_binary_delta(&delta).as_bytes())
+        } else {
+            match (diff_config.render_style, line.origin()) {
+                (DiffRenderStyle::Word, '-') => {
+                    word_diff.removed.push(line.content().to_vec());
+                    Ok(())
+                }
+                (DiffRenderStyle::Word, '+') => {
+                    word_diff.added.push(line.content().to_vec());
+                    Ok(())
+                }
+                _ => word_diff
+                    .flush(out)
+                    .and_then(|_| out.write_all(line.content())),
+            }
+        };
+        // Returning false makes libgit2 abort the diff
+        match written {
+            Ok(()) => true,
+            Err(e) => {
+                write_error = Some(e);
+                false
+            }
+        }
+    });
+    if let Some(e) = write_error {
+        return Err(e).context("Failed to write diff");
+    }
+    printed?;
+    word_diff.flush(out).context("Failed to write diff")?;
+    Ok(())
+}
+
+/// Forwards writes to a sink, remembering whether anything but whitespace went through.
+struct ContentWriter<'a, W> {
+    inner: &'a mut W,
+    has_content: bool,
+}
+
+impl<'a, W: Write> ContentWriter<'a, W> {
+    fn new(inner: &'a mut W) -> Self {
+        Self {
+            inner,
+            has_content: false,
+        }
+    }
+}
+
+impl<W: Write> Write for ContentWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.has_content |= buf[..written]
+            .iter()
+            .any(|byte| !byte.is_ascii_whitespace());
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Lines above this many tokens are not diffed word by word, to bound the quadratic cost.
+const WORD_DIFF_MAX_TOKENS: usize = 500;
+
+/// Buffers a run of removed and added lines to print them as a word diff.
+#[derive(Default)]
+struct WordDiffBuffer {
+    removed: Vec<Vec<u8>>,
+    added: Vec<Vec<u8>>,
+}
+
+impl WordDiffBuffer {
+    /// Prints the buffered lines like `git diff --word-diff`.
+    ///
+    /// Removed and added lines are paired in order and their differences marked inline with
+    /// `[-old-]` and `{+new+}`. Lines without a counterpart are marked as a whole.
+    fn flush<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        let removed = std::mem::take(&mut self.removed);
+        let added = std::mem::take(&mut self.added);
+        let pairs = removed.len().max(added.len());
+        for index in 0..pairs {
+            let old_line = removed.get(index).map(|line| String::from_utf8_lossy(line));
+            let new_line = added.get(index).map(|line| String::from_utf8_lossy(line));
+            let line = match (old_line, new_line) {
+                (Some(old_line), Some(new_line)) => word_diff_line(
+                    old_line.trim_end_matches('\n'),
+                    new_line.trim_end_matches('\n'),
+                ),
+                (Some(old_line), None) => format!("[-{}-]", old_line.trim_end_matches('\n')),
+                (None, Some(new_line)) => format!("{{+{}+}}", new_line.trim_end_matches('\n')),
+                (None, None) => continue,
+            };
+            out.write_all(line.as_bytes())?;
+            out.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// Merges two versions of a line, marking removed tokens with `[-...-]` and added ones with `{+...+}`.
+fn word_diff_line(old_line: &str, new_line: &str) -> String {
+    let old_tokens = tokenize_words(old_line);
+    let new_tokens = tokenize_words(new_line);
+    if old_tokens.len() > WORD_DIFF_MAX_TOKENS || new_tokens.len() > WORD_DIFF_MAX_TOKENS {
+        return format!("[-{}-]{{+{}+}}", old_line, new_line);
+    }
+
+    // Longest common subsequence of tokens, filled from the end
+    let (n, m) = (old_tokens.len(), new_tokens.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old_tokens[i] == new_tokens[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut merged = String::new();
+    let (mut removed, mut added) = (String::new(), String::new());
+    let flush = |merged: &mut String, removed: &mut String, added: &mut String| {
+        if !removed.is_empty() {
+            merged.push_str(&format!("[-{}-]", removed));
+            removed.clear();
+        }
+        if !added.is_empty() {
+            merged.push_str(&format!("{{+{}+}}", added));
+            added.clear();
+        }
+    };
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old_tokens[i] == new_tokens[j] {
+            flush(&mut merged, &mut removed, &mut added);
+            merged.push_str(old_tokens[i]);
+            i += 1;
+            j += 1;
+        } else if j == m || (i < n && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            removed.push_str(old_tokens[i]);
+            i += 1;
+        } else {
+            added.push_str(new_tokens[j]);
+            j += 1;
+        }
+    }
+    flush(&mut merged, &mut removed, &mut added);
+    merged
+}
+
+/// Splits a line into words, runs of whitespace and single punctuation characters.
+fn tokenize_words(line: &str) -> Vec<&str> {
+    #[derive(PartialEq)]
+    enum Class {
+        Word,
+        Space,
+        Punct,
+    }
+    let class = |c: char| {
+        if c.is_alphanumeric() || c == '_' {
+            Class::Word
+        } else if c.is_whitespace() {
+            Class::Space
+        } else {
+            Class::Punct
+        }
+    };
+
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut previous: Option<Class> = None;
+    for (index, c) in line.char_indices() {
+        let current = class(c);
+        let split = match &previous {
+            Some(Class::Punct) => true,
+            Some(previous) => *previous != current,
+            None => false,
+        };
+        if split {
+            tokens.push(&line[start..index]);
+            start = index;
+        }
+        previous = Some(current);
+    }
+    if start < line.len() {
+        tokens.push(&line[start..]);
+    }
+    tokens
+}
+
+/// Describes a binary delta on a single line, e.g.
+/// `Binary file assets/logo.png changed (12.3 KB -> 14.1 KB)`.
+fn describe_binary_delta(delta: &DiffDelta) -> String {
+    let (old_file, new_file) = (delta.old_file(), delta.new_file());
+    let path = new_file
+        .path()
+        .or(old_file.path())
+        .map(|path| path.display().to_string())
+        .unwrap_or_default();
+    match delta.status() {
+        Delta::Added | Delta::Untracked => format!(
+            "Binary file {} added ({})\n",
+            path,
+            format_size(new_file.size())
+        ),
+        Delta::Deleted => format!(
+            "Binary file {} deleted ({})\n",
+            path,
+            format_size(old_file.size())
+        ),
+        Delta::Renamed | Delta::Copied => format!(
+            "Binary file {} {} from {} ({} -> {})\n",
+            path,
+            if delta.status() == Delta::Renamed {
+                "renamed"
+            } else {
+                "copied"
+            },
+            old_file
+                .path()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default(),
+            format_size(old_file.size()),
+            format_size(new_file.size())
+        ),
+        _ => format!(
+            "Binary file {} changed ({} -> {})\n",
+            path,
+            format_size(old_file.size()),
+            format_size(new_file.size())
+        ),
+    }
+}
+
+/// A patch diff split into its files and hunks, for truncation.
+#[derive(Default)]
+struct ParsedPatch {
+    /// Text before the first file, e.g. a note about the state of HEAD.
+    preamble: String,
+    files: Vec<ParsedPatchFile>,
+    /// Summary lines appended after the last file.
+    trailer: String,
+}
+
+/// A file of a [`ParsedPatch`].
+#[derive(Default)]
+struct ParsedPatchFile {
+    /// The `diff --git` line and everything up to the first hunk.
+    header: String,
+    /// Each hunk, starting with its `@@` header line.
+    hunks: Vec<String>,
+}
+
+impl ParsedPatch {
+    fn parse(diff: &str) -> Self {
+        let mut patch = ParsedPatch::default();
+        let mut lines: Vec<&str> = diff.split_inclusive('\n').collect();
+
+        // The notes appended by the diff functions are kept apart from the last hunk
+        let trailer_start = lines
+            .iter()
+            .rposition(|line| !is_diff_note(line))
+            .map_or(0, |index| index + 1);
+        patch.trailer = lines.split_off(trailer_start).concat();
+
+        for line in lines {
+            if line.starts_with("diff --git ") {
+                patch.files.push(ParsedPatchFile::default());
+            }
+            match patch.files.last_mut() {
+                None => patch.preamble.push_str(line),
+                Some(file) if is_hunk_header(line) => file.hunks.push(line.to_string()),
+                Some(file) => match file.hunks.last_mut() {
+                    Some(hunk) => hunk.push_str(line),
+                    None => file.header.push_str(line),
+                },
+            }
+        }
+        patch
+    }
+}
+
+/// Returns true for a hunk header line such as `@@ -1,3 +1,4 @@ fn main() {`.
+fn is_hunk_header(line: &str) -> bool {
+    line.starts_with("@@ -") && line[4..].contains(" @@")
+}
+
+/// Returns true for the summary lines appended by the diff functions, and blank lines.
+fn is_diff_note(line: &str) -> bool {
+    let line = line.trim_end();
+    line.is_empty()
+        || line == "Note: Some changes are not staged."
+        || (line.starts_with("(+") && line.ends_with("excluded from diff by patterns)"))
+}
+
+/// Parses a number of days such as "7d", returning `None` for anything else.
+fn parse_days(since: &str) -> Option<i64> {
+    since
+        .strip_suffix('d')
+        .filter(|days| !days.is_empty() && days.bytes().all(|b| b.is_ascii_digit()))
+        .and_then(|days| days.parse().ok())
+}
+
+/// Opens the repository at the provided path, which may be a bare repository.
+///
+/// In a linked worktree (`git worktree add`), the `.git` file is followed to the worktree's
+/// own gitdir, so its HEAD and index are used rather than those of the main checkout.
+fn open_repository(repo_path: &Path) -> Result<Repository> {
+    Repository::open_ext(
+        repo_path,
+        RepositoryOpenFlags::NO_SEARCH,
+        std::iter::empty::<&std::ffi::OsStr>(),
+    )
+    .with_context(|| format!("Failed to open repository at {}", repo_path.display()))
+}
+
+/// The kind of a named reference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RefKind {
+    LocalBranch,
+    RemoteBranch,
+    Tag,
+}
+
+/// Lists the short names of the local branches, remote-tracking branches and tags.
+fn list_ref_names(repo: &Repository) -> Vec<(String, RefKind)> {
+    let references = match repo.references() {
+        Ok(references) => references,
+        Err(_) => return Vec::new(),
+    };
+    references
+        .flatten()
+        .filter_map(|reference| {
+            let kind = if reference.is_branch() {
+                RefKind::LocalBranch
+            } else if reference.is_remote() {
+                RefKind::RemoteBranch
+            } else if reference.is_tag() {
+                RefKind::Tag
+            } else {
+                return None;
+            };
+            let name = reference.shorthand()?.to_string();
+            // Skip symbolic refs such as origin/HEAD
+            (!name.ends_with("/HEAD")).then_some((name, kind))
+        })
+        .collect()
+}
+
+/// Builds the error for a reference that doesn't exist, suggesting the closest names.
+///
+/// A name that only exists as a remote-tracking branch is reported as such, with its
+/// qualified name. Otherwise up to 3 branches or tags within a small edit distance are
+/// suggested, closest first.
+fn missing_ref_error(repo: &Repository, what: &str, name: &str) -> anyhow::Error {
+    let ref_names = list_ref_names(repo);
+
+    let remote_names: Vec<&str> = ref_names
+        .iter()
+        .filter(|(ref_name, kind)| {
+            *kind == RefKind::RemoteBranch
+                && ref_name
+                    .split_once('/')
+                    .is_some_and(|(_, branch)| branch == name)
+        })
+        .map(|(ref_name, _)| ref_name.as_str())
+        .collect();
+    if !remote_names.is_empty() {
+        return anyhow!(
+            "{} {} doesn't exist locally, but exists as the remote-tracking branch {}. Use the qualified name instead",
+            what,
+            name,
+            quote_alternatives(&remote_names)
+        );
+    }
+
+    let max_distance = (name.chars().count() / 3).max(2);
+    let mut candidates: Vec<(usize, &str)> = ref_names
+        .iter()
+        .map(|(ref_name, kind)| {
+            let mut distance = edit_distance(name, ref_name);
+            if *kind == RefKind::RemoteBranch {
+                if let Some((_, branch)) = ref_name.split_once('/') {
+                    distance = distance.min(edit_distance(name, branch));
+                }
+            }
+            (distance, ref_name.as_str())
+        })
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    candidates.sort();
+    candidates.truncate(3);
+
+    if candidates.is_empty() {
+        anyhow!("{} {} doesn't exist!", what, name)
+    } else {
+        let suggestions: Vec<&str> = candidates.iter().map(|(_, ref_name)| *ref_name).collect();
+        anyhow!(
+            "{} {} doesn't exist! Did you mean {}?",
+            what,
+            name,
+            quote_alternatives(&suggestions)
+        )
+    }
+}
+
+/// Formats names as `'a'`, `'a' or 'b'` or `'a', 'b' or 'c'`.
+fn quote_alternatives(names: &[&str]) -> String {
+    let quoted: Vec<String> = names.iter().map(|name| format!("'{}'", name)).collect();
+    match quoted.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+        None => String::new(),
+    }
+}
+
+/// Computes the Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(a_char != *b_char);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Resolves a revspec to the commit it points to
+///
+/// See [`resolve_revspec`] for how failures are reported.
+///
+/// # Arguments
+///
+/// * `repo` - A reference to the `Repository` in which to resolve the reference
+/// * `reference` - Any revspec git rev-parse can resolve
+///
+/// # Returns
+///
+/// * `Result<Commit>` - The resolved commit or an error
+fn resolve_commit<'repo>(repo: &'repo Repository, reference: &str) -> Result<Commit<'repo>> {
+    resolve_revspec(repo, "Reference", reference)?
+        .peel_to_commit()
+        .with_context(|| format!("Reference {} does not point to a commit", reference))
+}
+
+/// Resolves a revspec to an object, classifying failures
+///
+/// A missing reference gets suggestions of similar names, an abbreviated hash matching
+/// several objects lists them, and a malformed revspec says so. When a name matches several
+/// references (e.g. a tag and a branch), it resolves like git does and a warning tells which
+/// one was chosen.
+///
+/// # Arguments
+///
+/// * `repo` - A reference to the `Repository` in which to resolve the reference
+/// * `what` - What the reference is, used in error messages (e.g. "Branch")
+/// * `reference` - Any revspec git rev-parse can resolve
+///
+/// # Returns
+///
+/// * `Result<Object>` - The resolved object or an error
+fn resolve_revspec<'repo>(
+    repo: &'repo Repository,
+    what: &str,
+    reference: &str,
+) -> Result<git2::Object<'repo>> {
+    let object = repo.revparse_single(reference).map_err(|e| match e.code() {
+        ErrorCode::Ambiguous => ambiguous_hash_error(repo, what, reference),
+        ErrorCode::InvalidSpec => anyhow!(
+            "{} {} is not a valid revision. Expected a branch, tag, commit hash or an expression such as HEAD~2",
+            what,
+            reference
+        ),
+        _ => missing_ref_error(repo, what, reference),
+    })?;
+
+    let name = revspec_base_name(reference);
+    let matches = matching_refs(repo, name);
+    if let [chosen, others @ ..] = matches.as_slice() {
+        if !others.is_empty() {
+            let names: Vec<&str> = matches.iter().map(String::as_str).collect();
+            warn!(
+                "{} {} is ambiguous, it matches {}; using {}",
+                what,
+                name,
+                quote_alternatives(&names),
+                chosen
+            );
+        }
+    }
+    Ok(object)
+}
+
+/// Returns the reference name a revspec starts from, e.g. `main` for `main~2^{tree}`.
+fn revspec_base_name(reference: &str) -> &str {
+    let name = reference.split(['~', '^', ':']).next().unwrap_or_default();
+    name.split_once("@{").map_or(name, |(name, _)| name)
+}
+
+/// Lists the full names of the references a short name can designate, in the order git
+/// tries them, so the first one is the one it resolves to.
+fn matching_refs(repo: &Repository, name: &str) -> Vec<String> {
+    if name.is_empty() || name.starts_with("refs/") {
+        return Vec::new();
+    }
+    [
+        "refs/tags/{}",
+        "refs/heads/{}",
+        "refs/remotes/{}",
+        "refs/remotes/{}/HEAD",
+    ]
+    .iter()
+    .map(|pattern| pattern.replace("{}", name))
+    .filter(|full_name| repo.find_reference(full_name).is_ok())
+    .collect()
+}
+
+/// Describes an abbreviated hash matching several objects, listing them.
+fn ambiguous_hash_error(repo: &Repository, what: &str, reference: &str) -> anyhow::Error {
+    const MAX_CANDIDATES: usize = 5;
+
+    let prefix = revspec_base_name(reference).to_lowercase();
+    let mut candidates = Vec::new();
+    if let Ok(odb) = repo.odb() {
+        let _ = odb.foreach(|oid| {
+            let sha = oid.to_string();
+            if sha.starts_with(&prefix) {
+                let description = match repo.find_commit(*oid) {
+                    Ok(commit) => format!(
+                        "commit {} ({})",
+                        &sha[..12],
+                        commit.summary().unwrap_or_default()
+                    ),
+                    Err(_) => match repo.find_object(*oid, None) {
+                        Ok(object) => format!(
+                            "{} {}",
+                            object.kind().map_or("object", |kind| kind.str()),
+                            &sha[..12]
+                        ),
+                        Err(_) => format!("object {}", &sha[..12]),
+                    },
+                };
+                candidates.push(description);
+            }
+            candidates.len() < MAX_CANDIDATES
+        });
+    }
+    candidates.sort();
+
+    if candidates.is_empty() {
+        anyhow!(
+            "{} {} is ambiguous, use a longer hash or a full reference name",
+            what,
+            reference
+        )
+    } else {
+        anyhow!(
+            "{} {} is ambiguous, it matches {}. Use a longer hash or a full reference name",
+            what,
+            reference,
+            candidates.join(", ")
+        )
+    }
 }
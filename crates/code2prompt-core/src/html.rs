@@ -0,0 +1,266 @@
+//! This module writes the prompt as a self-contained HTML page, to share it with people: a
+//! collapsible source tree, the highlighted code of the files, the git diff and the stats.
+//!
+//! The page has no external assets and no script: the CSS is inlined, the code is highlighted
+//! with inline styles, and the tree and the files collapse with `<details>` elements.
+
+use crate::path::TreeNode;
+use crate::session::{FileStats, OutputFile};
+use crate::template::heading_anchors;
+use anyhow::Result;
+use handlebars::html_escape;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::highlighted_html_for_string;
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+
+/// The syntaxes of the highlighted code, loaded on the first page.
+static SYNTAXES: Lazy<SyntaxSet> = Lazy::new(SyntaxSet::load_defaults_newlines);
+
+/// The theme of the highlighted code.
+static THEME: Lazy<Theme> = Lazy::new(|| {
+    ThemeSet::load_defaults()
+        .themes
+        .remove("InspiredGitHub")
+        .unwrap_or_default()
+});
+
+/// The CSS of the page.
+const STYLE: &str = r#"
+body { margin: 0; display: flex; font-family: -apple-system, "Segoe UI", Helvetica, Arial, sans-serif; color: #1f2328; }
+nav { position: sticky; top: 0; height: 100vh; overflow: auto; box-sizing: border-box; width: 18rem; flex-shrink: 0; padding: 1rem; background: #f6f8fa; border-right: 1px solid #d0d7de; font-size: 0.875rem; }
+nav ul { list-style: none; margin: 0; padding-left: 1rem; }
+nav > ul { padding-left: 0; }
+nav a { color: #0969da; text-decoration: none; }
+main { flex-grow: 1; min-width: 0; padding: 1rem 2rem; }
+summary { cursor: pointer; }
+section.file { margin: 1rem 0; border: 1px solid #d0d7de; border-radius: 6px; }
+section.file > details > summary { padding: 0.5rem 1rem; background: #f6f8fa; border-radius: 6px; }
+.tokens { float: right; color: #59636e; }
+pre { margin: 0; padding: 1rem; overflow: auto; font-size: 0.8125rem; }
+table { border-collapse: collapse; margin-bottom: 1rem; }
+th, td { padding: 0.25rem 0.75rem; border: 1px solid #d0d7de; text-align: left; }
+td.number { text-align: right; }
+pre.diff { border: 1px solid #d0d7de; border-radius: 6px; }
+.added { display: block; background: #dafbe1; }
+.removed { display: block; background: #ffebe9; }
+.hunk { display: block; color: #0550ae; background: #ddf4ff; }
+.meta { display: block; font-weight: bold; }
+"#;
+
+/// The content of an HTML page.
+#[derive(Debug, Clone)]
+pub struct HtmlPage {
+    /// The title of the page, the name of the directory.
+    pub title: String,
+    /// The time of the output, in RFC 3339.
+    pub generated_at: String,
+    /// None if the prompt has no source tree.
+    pub tree: Option<TreeNode>,
+    /// The files of the prompt, a section each.
+    pub files: Vec<OutputFile>,
+    /// The unified git diff, none if no diff was loaded.
+    pub diff: Option<String>,
+    pub stats: FileStats,
+    /// The tokens of the rendered prompt, none if the tokens aren't counted.
+    pub tokens: Option<usize>,
+}
+
+/// Renders an HTML page: the source tree in a sidebar linking to the files, the stats table,
+/// a section per file with its highlighted code, and the diff with the added and removed lines
+/// colored.
+pub fn render_html(page: &HtmlPage) -> Result<String> {
+    let anchors: Vec<String> = heading_anchors(page.files.iter().map(|file| file.path.as_str()))
+        .into_iter()
+        .map(|anchor| format!("file-{}", anchor))
+        .collect();
+    let file_anchors: HashMap<&str, &str> = page
+        .files
+        .iter()
+        .zip(&anchors)
+        .map(|(file, anchor)| (file.path.as_str(), anchor.as_str()))
+        .collect();
+    let title = html_escape(&page.title);
+
+    let mut html = String::new();
+    writeln!(html, "<!DOCTYPE html>")?;
+    writeln!(html, "<html lang=\"en\">")?;
+    writeln!(html, "<head>")?;
+    writeln!(html, "<meta charset=\"utf-8\">")?;
+    writeln!(
+        html,
+        "<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">"
+    )?;
+    writeln!(html, "<title>{}</title>", title)?;
+    writeln!(html, "<style>{}</style>", STYLE)?;
+    writeln!(html, "</head>")?;
+    writeln!(html, "<body>")?;
+
+    // ~~~ Source Tree ~~~
+    if let Some(tree) = &page.tree {
+        writeln!(html, "<nav>")?;
+        writeln!(html, "<ul>")?;
+        push_tree_node(&mut html, tree, &file_anchors)?;
+        writeln!(html, "</ul>")?;
+        writeln!(html, "</nav>")?;
+    }
+
+    writeln!(html, "<main>")?;
+    writeln!(html, "<h1>{}</h1>", title)?;
+    writeln!(
+        html,
+        "<p>Generated at {}</p>",
+        html_escape(&page.generated_at)
+    )?;
+
+    // ~~~ Stats ~~~
+    push_stats(&mut html, page, &anchors)?;
+
+    // ~~~ Files ~~~
+    for (file, anchor) in page.files.iter().zip(&anchors) {
+        writeln!(html, "<section class=\"file\" id=\"{}\">", anchor)?;
+        writeln!(html, "<details open>")?;
+        write!(html, "<summary><code>{}</code>", html_escape(&file.path))?;
+        if let Some(tokens) = file.tokens {
+            write!(html, "<span class=\"tokens\">{} tokens</span>", tokens)?;
+        }
+        writeln!(html, "</summary>")?;
+        html.push_str(&highlighted_html_for_string(
+            &file.content,
+            &SYNTAXES,
+            file_syntax(file),
+            &THEME,
+        )?);
+        writeln!(html, "</details>")?;
+        writeln!(html, "</section>")?;
+    }
+
+    // ~~~ Git Diff ~~~
+    if let Some(diff) = &page.diff {
+        writeln!(html, "<section class=\"git-diff\">")?;
+        writeln!(html, "<h2>Git Diff</h2>")?;
+        push_diff(&mut html, diff);
+        writeln!(html, "</section>")?;
+    }
+
+    writeln!(html, "</main>")?;
+    writeln!(html, "</body>")?;
+    writeln!(html, "</html>")?;
+    Ok(html)
+}
+
+/// Returns the syntax of a file from its extension, else from its language, plain text if
+/// neither is known.
+fn file_syntax(file: &OutputFile) -> &'static SyntaxReference {
+    let extension = Path::new(&file.path)
+        .extension()
+        .and_then(|extension| extension.to_str());
+    extension
+        .and_then(|extension| SYNTAXES.find_syntax_by_extension(extension))
+        .or_else(|| SYNTAXES.find_syntax_by_token(&file.language))
+        .unwrap_or_else(|| SYNTAXES.find_syntax_plain_text())
+}
+
+/// Writes a node of the tree as a list item: a collapsible list for a directory, a link to
+/// its section for a file of the prompt.
+fn push_tree_node(
+    html: &mut String,
+    node: &TreeNode,
+    file_anchors: &HashMap<&str, &str>,
+) -> Result<()> {
+    let name = html_escape(&node.name);
+    if node.is_dir {
+        writeln!(html, "<li><details open><summary>{}</summary><ul>", name)?;
+        for child in &node.children {
+            push_tree_node(html, child, file_anchors)?;
+        }
+        writeln!(html, "</ul></details></li>")?;
+    } else if let Some(anchor) = file_anchors.get(node.path.as_str()) {
+        writeln!(html, "<li><a href=\"#{}\">{}</a></li>", anchor, name)?;
+    } else {
+        writeln!(html, "<li>{}</li>", name)?;
+    }
+    Ok(())
+}
+
+/// Writes the totals of the prompt, then a row per file.
+fn push_stats(html: &mut String, page: &HtmlPage, anchors: &[String]) -> Result<()> {
+    let stats = &page.stats;
+    let tokens = |tokens: Option<usize>| tokens.map(|tokens| tokens.to_string());
+    writeln!(html, "<section class=\"stats\">")?;
+    writeln!(html, "<h2>Stats</h2>")?;
+    writeln!(html, "<table>")?;
+    writeln!(
+        html,
+        "<tr><th>Files</th><th>Lines</th><th>Words</th><th>Bytes</th><th>Tokens</th></tr>"
+    )?;
+    writeln!(
+        html,
+        "<tr><td class=\"number\">{}</td><td class=\"number\">{}</td><td class=\"number\">{}</td><td class=\"number\">{}</td><td class=\"number\">{}</td></tr>",
+        stats.files,
+        stats.lines,
+        stats.words,
+        stats.bytes,
+        tokens(page.tokens).unwrap_or_default()
+    )?;
+    writeln!(html, "</table>")?;
+    if !page.files.is_empty() {
+        writeln!(html, "<table>")?;
+        writeln!(
+            html,
+            "<tr><th>Path</th><th>Language</th><th>Bytes</th><th>Tokens</th></tr>"
+        )?;
+        for (file, anchor) in page.files.iter().zip(anchors) {
+            writeln!(
+                html,
+                "<tr><td><a href=\"#{}\">{}</a></td><td>{}</td><td class=\"number\">{}</td><td class=\"number\">{}</td></tr>",
+                anchor,
+                html_escape(&file.path),
+                html_escape(&file.language),
+                file.size,
+                tokens(file.tokens).unwrap_or_default()
+            )?;
+        }
+        writeln!(html, "</table>")?;
+    }
+    writeln!(html, "</section>")?;
+    Ok(())
+}
+
+/// Writes a unified diff with a class per line: `added`, `removed`, `hunk` for the hunk
+/// headers and `meta` for the file headers. The code fences of the prompt are left out.
+fn push_diff(html: &mut String, diff: &str) {
+    html.push_str("<pre class=\"diff\">");
+    for line in diff.lines().filter(|line| !line.starts_with("```")) {
+        let class = if line.starts_with("+++")
+            || line.starts_with("---")
+            || line.starts_with("diff ")
+            || line.starts_with("index ")
+        {
+            Some("meta")
+        } else if line.starts_with('+') {
+            Some("added")
+        } else if line.starts_with('-') {
+            Some("removed")
+        } else if line.starts_with("@@") {
+            Some("hunk")
+        } else {
+            None
+        };
+        match class {
+            Some(class) => html.push_str(&format!(
+                "<span class=\"{}\">{}</span>",
+                class,
+                html_escape(line)
+            )),
+            None => {
+                html.push_str(&html_escape(line));
+                html.push('\n');
+            }
+        }
+    }
+    html.push_str("</pre>\n");
+}
@@ -0,0 +1,182 @@
+//! This module maps the files to the languages of their code fences, e.g. `rust` for a `.rs`
+//! file, the names Markdown renderers highlight, and picks fences the code can't close.
+//!
+//! A file is matched by its name, e.g. `Dockerfile`, then by its extension, then by the
+//! interpreter of its `#!` line for a script without extension. A file matching none of them
+//! has an empty language, a bare fence, rather than a guess.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Returns the language of a file extension, matched case-insensitively, or an empty one if it
+/// has no known language.
+pub fn language_for_extension(extension: &str) -> &'static str {
+    match extension.to_ascii_lowercase().as_str() {
+        "rs" => "rust",
+        "py" | "pyi" | "pyw" => "python",
+        "js" | "mjs" | "cjs" => "javascript",
+        "ts" | "mts" | "cts" => "typescript",
+        "jsx" => "jsx",
+        "tsx" => "tsx",
+        "go" => "go",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "scala" | "sc" => "scala",
+        "groovy" | "gradle" => "groovy",
+        "swift" => "swift",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => "cpp",
+        "cs" => "csharp",
+        "fs" | "fsx" => "fsharp",
+        "rb" => "ruby",
+        "php" => "php",
+        "pl" | "pm" => "perl",
+        "lua" => "lua",
+        "r" => "r",
+        "jl" => "julia",
+        "dart" => "dart",
+        "zig" => "zig",
+        "hs" => "haskell",
+        "ex" | "exs" => "elixir",
+        "erl" => "erlang",
+        "clj" | "cljs" => "clojure",
+        "ml" | "mli" => "ocaml",
+        "sh" | "bash" => "bash",
+        "zsh" => "zsh",
+        "fish" => "fish",
+        "ps1" => "powershell",
+        "bat" | "cmd" => "batch",
+        "sql" => "sql",
+        "graphql" | "gql" => "graphql",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "scss" => "scss",
+        "less" => "less",
+        "vue" => "vue",
+        "svelte" => "svelte",
+        "xml" => "xml",
+        "json" => "json",
+        "yml" | "yaml" => "yaml",
+        "toml" => "toml",
+        "ini" => "ini",
+        "md" | "markdown" => "markdown",
+        "txt" => "txt",
+        "tex" => "latex",
+        "tf" | "hcl" => "hcl",
+        "proto" => "protobuf",
+        "nix" => "nix",
+        "cmake" => "cmake",
+        "mk" => "makefile",
+        "dockerfile" => "dockerfile",
+        "diff" | "patch" => "diff",
+        _ => "",
+    }
+}
+
+/// Returns the language of a file from its name alone, for the files like `Dockerfile` and
+/// `Makefile` whose extension doesn't tell it.
+fn language_for_file_name(name: &str) -> Option<&'static str> {
+    let language = match name {
+        "Dockerfile" | "Containerfile" => "dockerfile",
+        "Makefile" | "GNUmakefile" | "makefile" => "makefile",
+        "CMakeLists.txt" => "cmake",
+        "Jenkinsfile" => "groovy",
+        "Gemfile" | "Rakefile" | "Vagrantfile" => "ruby",
+        _ if name.starts_with("Dockerfile.") => "dockerfile",
+        _ => return None,
+    };
+    Some(language)
+}
+
+/// Returns the language of a file from its name, e.g. `Dockerfile`, then from its extension.
+/// Empty if the file has no known language.
+pub fn language_for_path(path: &Path) -> &'static str {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("");
+    if let Some(language) = language_for_file_name(name) {
+        return language;
+    }
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map_or("", language_for_extension)
+}
+
+/// Returns the language of a script from the interpreter of its `#!` line, e.g. `python` for
+/// `#!/usr/bin/env python3`, or none if the code has no `#!` line or an unknown interpreter.
+pub fn language_for_shebang(code: &str) -> Option<&'static str> {
+    let line = code.lines().next()?.strip_prefix("#!")?;
+    let mut words = line.split_whitespace();
+    let mut interpreter = words.next()?.rsplit('/').next()?;
+    // `env` runs the first word that isn't an option, e.g. with `env -S python3 -u`
+    if interpreter == "env" {
+        interpreter = words.find(|word| !word.starts_with('-'))?;
+    }
+    // The version of the interpreter doesn't change its language, e.g. `python3.12`
+    let interpreter = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    let language = match interpreter {
+        "python" | "pypy" => "python",
+        "node" | "nodejs" | "bun" => "javascript",
+        "deno" | "ts-node" => "typescript",
+        "sh" | "bash" | "dash" | "ksh" => "bash",
+        "zsh" => "zsh",
+        "fish" => "fish",
+        "ruby" => "ruby",
+        "perl" => "perl",
+        "php" => "php",
+        "lua" => "lua",
+        "Rscript" => "r",
+        "pwsh" => "powershell",
+        _ => return None,
+    };
+    Some(language)
+}
+
+/// Returns the language of a file of the prompt: the one `languages` sets for its name or
+/// extension, e.g. from the `[languages]` of the configuration file, then the built-in one of
+/// [`language_for_path`], then the one of its `#!` line for a script without extension.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file
+/// * `code` - The contents of the file
+/// * `languages` - The languages by file name or by extension, with or without the dot, over the
+///   built-in ones
+pub fn detect_language(path: &Path, code: &str, languages: &HashMap<String, String>) -> String {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("");
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    let configured = languages.get(name).or_else(|| {
+        let extension = extension?.to_ascii_lowercase();
+        languages
+            .iter()
+            .find(|(key, _)| key.trim_start_matches('.').to_ascii_lowercase() == extension)
+            .map(|(_, language)| language)
+    });
+    if let Some(language) = configured {
+        return language.clone();
+    }
+    match language_for_path(path) {
+        "" if extension.is_none() => language_for_shebang(code).unwrap_or("").to_string(),
+        language => language.to_string(),
+    }
+}
+
+/// The shortest code fence, three backticks.
+const MIN_FENCE_LEN: usize = 3;
+
+/// Returns the backtick fence of a code block holding a text: longer than any run of backticks
+/// in the text, so that the text can't close the block. Three backticks for most texts, four
+/// for a Markdown file holding a
+/// triple-backtick block.
+pub fn code_fence(text: &str) -> String {
+    let longest_run = text
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    "`".repeat(MIN_FENCE_LEN.max(longest_run + 1))
+}
@@ -1,10 +1,26 @@
 //! Core library for code2prompt.
+pub mod budget;
 pub mod configuration;
+pub mod engine;
 pub mod filter;
+pub mod front_matter;
 pub mod git;
+#[cfg(feature = "html")]
+pub mod html;
+pub mod language;
+pub mod manifest;
+pub mod output;
 pub mod path;
+pub mod pricing;
+pub mod project_config;
+pub mod remote;
+pub mod run_history;
 pub mod session;
 pub mod sort;
+pub mod split;
 pub mod template;
+pub mod template_library;
+pub mod token_cache;
+pub mod token_map;
 pub mod tokenizer;
 pub mod util;
@@ -0,0 +1,95 @@
+//! This module writes the manifest of a prompt: a CSV file with a row per included file and,
+//! optionally, per skipped file.
+
+use std::path::Path;
+
+/// The columns of the manifest, in order. Every manifest has all of them, whatever the options.
+pub const MANIFEST_COLUMNS: [&str; 9] = [
+    "path",
+    "extension",
+    "language",
+    "bytes",
+    "lines",
+    "tokens",
+    "truncated",
+    "last_commit_sha",
+    "skip_reason",
+];
+
+/// The skip reason of the files omitted to fit the token budget.
+pub const BUDGET_SKIP_REASON: &str = "omitted to fit the token budget";
+
+/// A file of the manifest, included in the prompt or skipped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ManifestRow {
+    pub path: String,
+    pub extension: String,
+    pub language: String,
+    /// None if the size isn't known, as for the files omitted to fit the token budget.
+    pub bytes: Option<u64>,
+    pub lines: Option<usize>,
+    /// None if the tokens weren't counted.
+    pub tokens: Option<usize>,
+    pub truncated: bool,
+    /// None unless the git info of the files is loaded and the file was committed.
+    pub last_commit_sha: Option<String>,
+    /// None for the files of the prompt.
+    pub skip_reason: Option<String>,
+}
+
+impl ManifestRow {
+    /// Returns the row of a skipped file, with its extension taken from its path.
+    pub fn skipped(path: &str, reason: &str) -> Self {
+        ManifestRow {
+            path: path.to_string(),
+            extension: Path::new(path)
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .unwrap_or_default()
+                .to_string(),
+            skip_reason: Some(reason.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the fields of the row, in the order of [`MANIFEST_COLUMNS`].
+    fn fields(&self) -> [String; 9] {
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        [
+            self.path.clone(),
+            self.extension.clone(),
+            self.language.clone(),
+            optional(self.bytes.map(|bytes| bytes.to_string())),
+            optional(self.lines.map(|lines| lines.to_string())),
+            optional(self.tokens.map(|tokens| tokens.to_string())),
+            self.truncated.to_string(),
+            optional(self.last_commit_sha.clone()),
+            optional(self.skip_reason.clone()),
+        ]
+    }
+}
+
+/// Quotes a CSV field per RFC 4180 if it contains a comma, a double quote or a line break,
+/// doubling its double quotes.
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Returns the manifest CSV: the header row then a row per file, each ended with CRLF.
+pub fn manifest_csv(rows: &[ManifestRow]) -> String {
+    let mut csv = String::new();
+    let mut push_record = |fields: &[String]| {
+        let record: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&record.join(","));
+        csv.push_str("\r\n");
+    };
+    push_record(&MANIFEST_COLUMNS.map(str::to_string));
+    for row in rows {
+        push_record(&row.fields());
+    }
+    csv
+}
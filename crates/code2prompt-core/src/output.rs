@@ -0,0 +1,265 @@
+//! This module writes the rendered prompt to its output file or stdout: overwritten, appended
+//! or compressed, at a path whose `{timestamp}`, `{dirname}` and `{branch}` tokens are
+//! replaced first, and numbered for the parts of a split prompt.
+
+use crate::git::get_current_branch;
+use crate::path::label;
+use crate::template::{format_tokens, replace_tokens};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::Utc;
+use colored::*;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Writes the rendered template to a specified output file or stdout.
+///
+/// # Arguments
+///
+/// * `output_path` - The path to the output file, or "-" for stdout.
+/// * `rendered` - The rendered template string.
+/// * `quiet` - If true, suppress success messages.
+///
+/// # Returns
+///
+/// * `Result<()>` - An empty result indicating success or an error.
+pub fn write_to_file(output_path: &str, rendered: &str, quiet: bool) -> Result<()> {
+    if output_path == "-" {
+        // Write to stdout
+        print!("{}", rendered);
+        std::io::stdout().flush()?;
+    } else {
+        // Write to file
+        let file = std::fs::File::create(output_path)?;
+        let mut writer = std::io::BufWriter::new(file);
+        write!(writer, "{}", rendered)?;
+        if !quiet {
+            println!(
+                "{}{}{} {}",
+                "[".bold().white(),
+                "✓".bold().green(),
+                "]".bold().white(),
+                format!("Prompt written to file: {}", output_path).green()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The separator line written between the prompts appended to an output file.
+pub const APPEND_SEPARATOR: &str = "\n---\n\n";
+
+/// Appends a rendered prompt to a file, after a [`APPEND_SEPARATOR`] line if the file already
+/// has some contents. The file is created if missing, and `-` writes to stdout.
+pub fn append_to_file(output_path: &str, rendered: &str, quiet: bool) -> Result<()> {
+    if output_path == "-" {
+        return write_to_file(output_path, rendered, quiet);
+    }
+    let has_contents = fs::metadata(output_path).is_ok_and(|metadata| metadata.len() > 0);
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(output_path)?;
+    let mut writer = std::io::BufWriter::new(file);
+    if has_contents {
+        write!(writer, "{}", APPEND_SEPARATOR)?;
+    }
+    write!(writer, "{}", rendered)?;
+    if !quiet {
+        println!(
+            "{}{}{} {}",
+            "[".bold().white(),
+            "✓".bold().green(),
+            "]".bold().white(),
+            format!("Prompt appended to file: {}", output_path).green()
+        );
+    }
+    Ok(())
+}
+
+/// The compression of a written prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    /// Only with the `zstd` feature.
+    Zstd,
+}
+
+impl FromStr for Compression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "gzip" | "gz" => Ok(Compression::Gzip),
+            "zstd" | "zst" => Ok(Compression::Zstd),
+            _ => Err(anyhow!(
+                "Invalid compression: {}. Allowed values: gzip, zstd",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Compression::Gzip => write!(f, "gzip"),
+            Compression::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+impl Compression {
+    /// Returns the compression of an output path from its extension, `.gz` or `.zst`.
+    pub fn from_path(output_path: &str) -> Option<Self> {
+        match Path::new(output_path).extension()?.to_str()? {
+            "gz" => Some(Compression::Gzip),
+            "zst" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// The extension of a file compressed with it, without the dot.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+
+    /// Fails for zstd unless code2prompt was built with the `zstd` feature.
+    pub fn ensure_supported(&self) -> Result<()> {
+        if *self == Compression::Zstd && !cfg!(feature = "zstd") {
+            bail!("zstd compression needs code2prompt built with the zstd feature");
+        }
+        Ok(())
+    }
+
+    /// Streams the texts through the encoder into the writer, and returns the writer once
+    /// the compressed stream is finished.
+    fn encode<W: Write>(&self, writer: W, texts: &[&str]) -> Result<W> {
+        match self {
+            Compression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+                for text in texts {
+                    encoder.write_all(text.as_bytes())?;
+                }
+                Ok(encoder.finish()?)
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => {
+                let mut encoder = zstd::Encoder::new(writer, 0)?;
+                for text in texts {
+                    encoder.write_all(text.as_bytes())?;
+                }
+                Ok(encoder.finish()?)
+            }
+            #[cfg(not(feature = "zstd"))]
+            Compression::Zstd => {
+                self.ensure_supported()?;
+                Ok(writer)
+            }
+        }
+    }
+}
+
+/// Writes the rendered prompt compressed to a file, or to stdout for `-`.
+///
+/// With `append`, the prompt is added to the file as a new gzip member or zstd frame, after a
+/// [`APPEND_SEPARATOR`] line if the file already has some contents, so that the file
+/// decompresses to the prompts one after the other.
+pub fn write_compressed_to_file(
+    output_path: &str,
+    rendered: &str,
+    compression: Compression,
+    append: bool,
+    quiet: bool,
+) -> Result<()> {
+    compression.ensure_supported()?;
+    if output_path == "-" {
+        let stdout = std::io::stdout().lock();
+        compression.encode(stdout, &[rendered])?.flush()?;
+        return Ok(());
+    }
+    let has_contents = append && fs::metadata(output_path).is_ok_and(|metadata| metadata.len() > 0);
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(output_path)?;
+    let separator = if has_contents { APPEND_SEPARATOR } else { "" };
+    let writer = std::io::BufWriter::new(file);
+    compression
+        .encode(writer, &[separator, rendered])?
+        .flush()?;
+    if !quiet {
+        let action = if append { "appended to" } else { "written to" };
+        println!(
+            "{}{}{} {}",
+            "[".bold().white(),
+            "✓".bold().green(),
+            "]".bold().white(),
+            format!("Prompt {} file ({}): {}", action, compression, output_path).green()
+        );
+    }
+    Ok(())
+}
+
+/// The tokens of an output path, e.g. `prompts/{dirname}-{timestamp}.md`.
+pub const OUTPUT_PATH_TOKENS: [&str; 3] = ["timestamp", "dirname", "branch"];
+
+/// Returns the tokens of an output path in their order, so that an unknown one fails before
+/// anything is generated.
+pub fn output_path_tokens(output_path: &str) -> Result<Vec<String>> {
+    format_tokens(
+        output_path,
+        &OUTPUT_PATH_TOKENS,
+        &format!("the output path {}", output_path),
+    )
+}
+
+/// Replaces the tokens of an output path with their values for the codebase at `path`: the
+/// `{timestamp}` of now in UTC, e.g. `20240305-102030`, the `{dirname}` of the codebase and
+/// its current git `{branch}`. The path separators of a value, e.g. of a `feature/login`
+/// branch, are replaced by `-` so that it stays a single file name.
+pub fn resolve_output_path(output_path: &str, path: &Path) -> Result<String> {
+    let tokens = output_path_tokens(output_path)?;
+    let branch = if tokens.iter().any(|token| token == "branch") {
+        Some(
+            get_current_branch(path)
+                .with_context(|| "The {branch} of the output path needs a git branch")?,
+        )
+    } else {
+        None
+    };
+    let timestamp = Utc::now().format("%Y%m%d-%H%M%S").to_string();
+    Ok(replace_tokens(output_path, |token| {
+        let value = match token {
+            "timestamp" => timestamp.clone(),
+            "dirname" => label(path),
+            "branch" => branch.clone()?,
+            _ => return None,
+        };
+        Some(value.replace(['/', '\\'], "-"))
+    }))
+}
+
+/// Returns the path of a part of a split prompt, with the number of the part inserted before
+/// the extension of the output path (e.g. `prompt.part2.md` for `prompt.md`).
+pub fn part_file_path(output_path: &str, index: usize) -> String {
+    let path = Path::new(output_path);
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{}.part{}.{}", stem, index, extension.to_string_lossy()),
+        None => format!("{}.part{}", stem, index),
+    };
+    path.with_file_name(file_name)
+        .to_string_lossy()
+        .into_owned()
+}
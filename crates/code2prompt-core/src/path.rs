@@ -1,18 +1,269 @@
 //! This module contains the functions for traversing the directory and processing the files.
 use crate::configuration::Code2PromptConfig;
-use crate::filter::{build_globset, should_include_file};
-use crate::sort::{sort_files, sort_tree, FileSortMethod};
-use crate::tokenizer::count_tokens;
-use crate::util::strip_utf8_bom;
-use anyhow::Result;
+use crate::filter::build_globset;
+use crate::git::{
+    annotate_with_blame, files_changed_since, files_omitted_by_gitattributes, get_git_blame,
+    is_bare_repository, last_commits_for_paths, list_submodules, list_tracked_files,
+    read_head_tree, SubmoduleInfo,
+};
+use crate::language::{code_fence, detect_language};
+use crate::sort::{prioritize_files, sort_files, sort_tree, FileSortMethod};
+use crate::token_cache::TokenCache;
+use crate::tokenizer::{count_tokens, TokenCountMode};
+use crate::util::{decode_text, format_size, sniff_mime_type};
+use anyhow::{anyhow, bail, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use chrono::{DateTime, SecondsFormat, Utc};
+use globset::GlobSet;
 use ignore::WalkBuilder;
-use log::debug;
+use log::{debug, warn};
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use termtree::Tree;
 
+/// Number of lines kept at the start and at the end of the stub of an oversized file.
+const STUB_CONTEXT_LINES: usize = 5;
+
+/// Number of characters kept of each line of the stub of an oversized file.
+const STUB_LINE_CHARS: usize = 200;
+
+/// How the paths of the files are written in the prompt, the tree and the JSON output.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum PathStyle {
+    /// Relative to the root, e.g. `src/main.rs`.
+    #[default]
+    Relative,
+    /// Absolute, e.g. `/home/ada/project/src/main.rs`.
+    Absolute,
+    /// Relative to the root without a leading directory, e.g. `main.rs` for `strip:src`, so
+    /// that the subdirectory of a monorepo reads as the root. The other paths are relative.
+    Strip(String),
+}
+
+/// Parses a path style, `relative`, `absolute` or `strip:<prefix>`.
+impl FromStr for PathStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            Some(("strip", prefix)) if !prefix.trim_matches(['/', '\\']).is_empty() => {
+                Ok(PathStyle::Strip(prefix.to_string()))
+            }
+            None if s == "relative" => Ok(PathStyle::Relative),
+            None if s == "absolute" => Ok(PathStyle::Absolute),
+            _ => Err(anyhow!(
+                "Invalid path style: {}. Allowed values: relative, absolute, strip:<prefix>",
+                s
+            )),
+        }
+    }
+}
+
+/// What is written for the binary files, which have no text to render. The tree lists them
+/// whatever the policy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BinaryPolicy {
+    /// Left out of the files, with a warning.
+    #[default]
+    Skip,
+    /// A line with the path, the size and the MIME type sniffed from the content.
+    Placeholder,
+    /// The content as a base64 data URI for the images and fonts of at most `max_bytes`, a
+    /// placeholder for the other binary files.
+    Base64 { max_bytes: u64 },
+}
+
+/// Parses a binary policy, `skip`, `placeholder` or `base64:<max-bytes>`.
+impl FromStr for BinaryPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            Some(("base64", max_bytes)) => max_bytes
+                .parse()
+                .map(|max_bytes| BinaryPolicy::Base64 { max_bytes })
+                .map_err(|_| {
+                    anyhow!(
+                        "Invalid base64 size: {}. Expected a number of bytes, e.g. base64:4096",
+                        max_bytes
+                    )
+                }),
+            None if s == "skip" => Ok(BinaryPolicy::Skip),
+            None if s == "placeholder" => Ok(BinaryPolicy::Placeholder),
+            _ => Err(anyhow!(
+                "Invalid binary policy: {}. Allowed values: skip, placeholder, base64:<max-bytes>",
+                s
+            )),
+        }
+    }
+}
+
+/// How the content of a file is written in the prompt, the `encoding` of its entry.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileEncoding {
+    /// The text of the file.
+    #[default]
+    Text,
+    /// The placeholder line of a binary file, see [`BinaryPolicy::Placeholder`].
+    Placeholder,
+    /// The content of a binary file as a base64 data URI.
+    Base64,
+}
+
+/// Returns the path of a file as the prompt writes it, with `/` separators on every platform.
+///
+/// This is the only place the paths are labeled, so that the files, the tree, the JSON output
+/// and the diff statistics agree.
+///
+/// # Arguments
+///
+/// * `style` - How the path is written
+/// * `root` - The canonical root path, which an absolute path starts with
+/// * `relative_path` - The path of the file relative to the root
+///
+/// # Returns
+///
+/// * `String` - The path, e.g. `src/main.rs`
+pub fn display_path(style: &PathStyle, root: &Path, relative_path: &Path) -> String {
+    let relative = relative_path.to_string_lossy().replace('\\', "/");
+    match style {
+        PathStyle::Relative => relative,
+        PathStyle::Absolute => root
+            .join(relative_path)
+            .to_string_lossy()
+            .replace('\\', "/"),
+        PathStyle::Strip(prefix) => {
+            let prefix = prefix.replace('\\', "/");
+            let prefix = prefix.trim_start_matches("./").trim_matches('/');
+            match relative
+                .strip_prefix(prefix)
+                .and_then(|rest| rest.strip_prefix('/'))
+            {
+                Some(rest) if !rest.is_empty() => rest.to_string(),
+                _ => relative,
+            }
+        }
+    }
+}
+
+/// A file that was skipped, or whose content was converted, because it isn't plain UTF-8 text
+/// or couldn't be read.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileWarning {
+    pub path: String,
+    pub message: String,
+}
+
+/// The directory tree, the files and the file warnings of a traversal.
+pub(crate) type Traversal = (Tree<String>, Vec<serde_json::Value>, Vec<FileWarning>);
+
+/// The text of a file and how it was encoded, none if it is skipped, and its warning.
+type DecodedFile = (Option<(String, FileEncoding)>, Option<FileWarning>);
+
+/// A file or directory of the source tree, for the templates that render the tree their own
+/// way, with the counts of the files of the prompt below it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TreeNode {
+    pub name: String,
+    /// The `/`-separated path of the entry, empty for the root.
+    pub path: String,
+    pub is_dir: bool,
+    /// The depth of the entry, 0 for the root.
+    pub depth: usize,
+    /// The token count of the file, or the sum of the ones of the files below the directory.
+    pub tokens: usize,
+    /// The number of files of the prompt below the directory, 1 for a file of the prompt and
+    /// 0 for a file only shown in the tree.
+    pub files: usize,
+    /// The entries of a directory, in the order of the source tree.
+    pub children: Vec<TreeNode>,
+}
+
+impl TreeNode {
+    /// Builds the nodes of a directory tree, without counts, with the paths written in a path
+    /// style like the ones of the files.
+    pub(crate) fn from_tree(tree: &Tree<String>, style: &PathStyle, root: &Path) -> Self {
+        let mut node = Self::from_subtree(tree, String::new(), 0, &|relative| {
+            display_path(style, root, Path::new(relative))
+        });
+        node.is_dir = true;
+        node
+    }
+
+    fn from_subtree(
+        tree: &Tree<String>,
+        relative: String,
+        depth: usize,
+        label: &dyn Fn(&str) -> String,
+    ) -> Self {
+        let children = tree
+            .leaves
+            .iter()
+            .map(|leaf| {
+                let child_path = if relative.is_empty() {
+                    leaf.root.clone()
+                } else {
+                    format!("{}/{}", relative, leaf.root)
+                };
+                Self::from_subtree(leaf, child_path, depth + 1, label)
+            })
+            .collect::<Vec<_>>();
+        Self {
+            name: tree.root.clone(),
+            path: if relative.is_empty() {
+                relative
+            } else {
+                label(&relative)
+            },
+            is_dir: !children.is_empty(),
+            depth,
+            tokens: 0,
+            files: 0,
+            children,
+        }
+    }
+
+    /// Sets the counts of the nodes from the token counts of the files of the prompt, by path.
+    pub(crate) fn count_files(&mut self, token_counts: &HashMap<String, usize>) {
+        if self.is_dir {
+            for child in &mut self.children {
+                child.count_files(token_counts);
+            }
+            self.tokens = self.children.iter().map(|child| child.tokens).sum();
+            self.files = self.children.iter().map(|child| child.files).sum();
+        } else if let Some(&tokens) = token_counts.get(&self.path) {
+            self.tokens = tokens;
+            self.files = 1;
+        }
+    }
+
+    /// Renders the nodes as a source tree, in the format of the one read from the codebase.
+    pub(crate) fn to_source_tree(&self) -> String {
+        fn to_tree(node: &TreeNode) -> Tree<String> {
+            Tree::new(node.name.clone()).with_leaves(node.children.iter().map(to_tree))
+        }
+        to_tree(self).to_string()
+    }
+
+    /// Adds the paths of the files below the node, or of the node itself for a file.
+    pub(crate) fn collect_file_paths(&self, paths: &mut HashSet<String>) {
+        if self.is_dir {
+            for child in &self.children {
+                child.collect_file_paths(paths);
+            }
+        } else {
+            paths.insert(self.path.clone());
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct EntryMetadata {
     pub is_dir: bool,
@@ -42,134 +293,862 @@ impl From<&std::fs::Metadata> for EntryMetadata {
 /// * `Result<(String, Vec<serde_json::Value>)>` - A tuple containing the string representation of the directory
 ///   tree and a vector of JSON representations of the files
 pub fn traverse_directory(config: &Code2PromptConfig) -> Result<(String, Vec<serde_json::Value>)> {
+    let selection = match &config.modified_since {
+        Some(since) => Some(files_changed_since(&config.path, since)?.changed),
+        None => None,
+    };
+    let priority = build_globset(&config.priority_patterns);
+    let (tree, files, _) = traverse_selected_files(config, selection.as_ref(), &priority)?;
+    Ok((tree.to_string(), files))
+}
+
+/// Traverses the directory like [`traverse_directory`], keeping only the files of `selection`
+/// (relative to the root) when it is set, and placing the files matching `priority` first.
+///
+/// The files are read and tokenized on `config.jobs` threads, every CPU if unset. The files
+/// that were skipped or converted are returned with their warning.
+pub(crate) fn traverse_selected_files(
+    config: &Code2PromptConfig,
+    selection: Option<&HashSet<PathBuf>>,
+    priority: &GlobSet,
+) -> Result<Traversal> {
+    match config.jobs {
+        Some(jobs) => ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build()?
+            .install(|| walk_selected_files(config, selection, priority)),
+        None => walk_selected_files(config, selection, priority),
+    }
+}
+
+/// Walks the directory for [`traverse_selected_files`], on the current rayon thread pool.
+fn walk_selected_files(
+    config: &Code2PromptConfig,
+    selection: Option<&HashSet<PathBuf>>,
+    priority: &GlobSet,
+) -> Result<Traversal> {
     // ~~~ Initialization ~~~
     let mut files = Vec::new();
+    let mut warnings = Vec::new();
+    let mut blame_candidates = Vec::new();
+    let mut committed_files = Vec::new();
     let canonical_root_path = config.path.canonicalize()?;
     let parent_directory = label(&canonical_root_path);
 
-    let include_globset = build_globset(&config.include_patterns);
-    let exclude_globset = build_globset(&config.exclude_patterns);
+    let file_matcher = config.file_matcher();
+
+    // ~~~ Bare Repositories ~~~
+    // Without a working tree the files are read from the HEAD tree instead
+    if is_bare_repository(&canonical_root_path) {
+        return traverse_bare_repository(config, &canonical_root_path, selection, priority);
+    }
+
+    // ~~~ Submodules ~~~
+    // Outside of a git repository there are simply no submodules
+    let submodules: Vec<SubmoduleInfo> = list_submodules(&canonical_root_path).unwrap_or_default();
+    let opaque_submodules: Vec<PathBuf> = if config.include_submodules {
+        Vec::new()
+    } else {
+        submodules
+            .iter()
+            .map(|submodule| canonical_root_path.join(&submodule.path))
+            .collect()
+    };
+
+    // ~~~ Tracked Files ~~~
+    // Outside of a git repository, the filesystem is walked as usual
+    let tracked_files = if config.tracked_only {
+        match list_tracked_files(&canonical_root_path) {
+            Ok(tracked_files) => Some(tracked_files),
+            Err(e) => {
+                warn!(
+                    "Listing all files instead of the tracked ones: {} is not inside a git repository ({})",
+                    canonical_root_path.display(),
+                    e
+                );
+                None
+            }
+        }
+    } else {
+        None
+    };
 
     // ~~~ Build the Walker ~~~
+    // The .gitignore files of every directory apply like in git, the deeper ones first, and
+    // outside of a git repository too
     let walker = WalkBuilder::new(&canonical_root_path)
         .hidden(!config.hidden)
         .git_ignore(!config.no_ignore)
+        .git_exclude(!config.no_ignore)
+        .require_git(false)
         .follow_links(config.follow_symlinks)
+        .filter_entry(move |entry| {
+            // Submodules are listed in the tree but their content is only walked on request
+            !opaque_submodules
+                .iter()
+                .any(|submodule| entry.path() != submodule && entry.path().starts_with(submodule))
+        })
         .build()
         .filter_map(|entry| entry.ok());
 
+    // ~~~ Collect Entries ~~~
+    let mut entries: Vec<PathBuf> = match tracked_files {
+        Some(tracked_files) => tracked_files
+            .into_iter()
+            .filter(|relative_path| config.hidden || !is_hidden(relative_path))
+            .map(|relative_path| canonical_root_path.join(relative_path))
+            // Deleted files may still be in the index
+            .filter(|path| path.exists())
+            .collect(),
+        None => walker.map(|entry| entry.into_path()).collect(),
+    };
+    // The walker yields the entries in the order of the filesystem, which differs between
+    // platforms, so they are sorted by path, component by component and byte-wise
+    entries.sort();
+
+    // ~~~ Git Attributes ~~~
+    // Generated and export-ignored files are listed in the tree without their content
+    let omitted_files: HashSet<PathBuf> = if config.respect_gitattributes {
+        let relative_files: Vec<PathBuf> = entries
+            .iter()
+            .filter(|path| path.is_file())
+            .filter_map(|path| path.strip_prefix(&canonical_root_path).ok())
+            .map(Path::to_path_buf)
+            .collect();
+        files_omitted_by_gitattributes(&canonical_root_path, &relative_files).unwrap_or_else(|e| {
+            debug!("Git attributes not applied: {}", e);
+            HashSet::new()
+        })
+    } else {
+        HashSet::new()
+    };
+
     // ~~~ Build the Tree ~~~
     let mut tree = Tree::new(parent_directory.to_owned());
+    let mut candidates: Vec<(&Path, &Path)> = Vec::new();
 
-    for entry in walker {
-        let path = entry.path();
+    for path in &entries {
         if let Ok(relative_path) = path.strip_prefix(&canonical_root_path) {
-            let file_match = should_include_file(
-                relative_path,
-                &include_globset,
-                &exclude_globset,
-                config.include_priority,
-            );
+            let file_match = file_matcher.is_match(relative_path)
+                && selection.is_none_or(|selection| selection.contains(relative_path));
+            let omitted = omitted_files.contains(relative_path);
 
             // ~~~ Directory Tree ~~~
             let include_in_tree = config.full_directory_tree || file_match;
 
             if include_in_tree {
-                let mut current_tree = &mut tree;
-                for component in relative_path.components() {
-                    let component_str = component.as_os_str().to_string_lossy().to_string();
-                    current_tree = if let Some(pos) = current_tree
-                        .leaves
-                        .iter_mut()
-                        .position(|child| child.root == component_str)
-                    {
-                        &mut current_tree.leaves[pos]
-                    } else {
-                        let new_tree = Tree::new(component_str.clone());
-                        current_tree.leaves.push(new_tree);
-                        current_tree.leaves.last_mut().unwrap()
-                    };
+                if omitted {
+                    add_labeled_to_tree(&mut tree, relative_path, "generated, omitted");
+                } else {
+                    add_to_tree(&mut tree, relative_path);
                 }
             }
 
             // ~~~ Processing File ~~~
-            if path.is_file() && file_match {
-                if let Ok(metadata) = entry.metadata() {
-                    if let Ok(code_bytes) = fs::read(path) {
-                        let clean_bytes = strip_utf8_bom(&code_bytes);
-                        let code = String::from_utf8_lossy(&clean_bytes);
-
-                        let code_block = wrap_code_block(
-                            &code,
-                            path.extension().and_then(|ext| ext.to_str()).unwrap_or(""),
-                            config.line_numbers,
-                            config.no_codeblock,
-                        );
-
-                        if !code.trim().is_empty() && !code.contains(char::REPLACEMENT_CHARACTER) {
-                            // ~~~ Filepath ~~~
-                            let file_path = if config.absolute_path {
-                                path.to_string_lossy().to_string()
-                            } else {
-                                relative_path.to_string_lossy().to_string()
-                            };
-
-                            // ~~~ File JSON Representation ~~~
-                            let mut file_entry = serde_json::Map::new();
-                            file_entry.insert("path".to_string(), json!(file_path));
-                            file_entry.insert(
-                                "extension".to_string(),
-                                json!(path.extension().and_then(|ext| ext.to_str()).unwrap_or("")),
-                            );
-                            file_entry.insert("code".to_string(), json!(code_block));
-
-                            // Store metadata
-                            let entry_meta = EntryMetadata::from(&metadata);
-                            file_entry
-                                .insert("metadata".to_string(), serde_json::to_value(entry_meta)?);
-
-                            // Add token count for the file only if token map is enabled
-                            if config.token_map_enabled {
-                                let token_count = count_tokens(&code, &config.encoding);
-                                file_entry.insert("token_count".to_string(), json!(token_count));
-                            }
-
-                            // If date sorting is requested, record the file modification time.
-                            if let Some(method) = config.sort_method {
-                                if method == FileSortMethod::DateAsc
-                                    || method == FileSortMethod::DateDesc
-                                {
-                                    let mod_time = metadata
-                                        .modified()
-                                        .ok()
-                                        .and_then(|mtime| {
-                                            mtime
-                                                .duration_since(std::time::SystemTime::UNIX_EPOCH)
-                                                .ok()
-                                        })
-                                        .map(|d| d.as_secs())
-                                        .unwrap_or(0);
-                                    file_entry.insert("mod_time".to_string(), json!(mod_time));
-                                }
-                            }
-                            files.push(serde_json::Value::Object(file_entry));
-                            debug!(target: "included_files", "Included file: {}", file_path);
-                        } else {
-                            debug!("Excluded file (empty or invalid UTF-8): {}", path.display());
-                        }
-                    } else {
-                        debug!("Failed to read file: {}", path.display());
-                    }
-                }
+            if path.is_file() && file_match && !omitted {
+                candidates.push((path, relative_path));
             }
         }
     }
 
+    // ~~~ Read the Files ~~~
+    // The files are read in parallel, in the order of the entries
+    let read_files: Vec<(Option<serde_json::Value>, Option<FileWarning>)> = candidates
+        .par_iter()
+        .map(|(path, relative_path)| {
+            read_file_entry(config, &canonical_root_path, path, relative_path)
+        })
+        .collect::<Result<_>>()?;
+    for ((_, relative_path), (file, warning)) in candidates.iter().zip(read_files) {
+        warnings.extend(warning);
+        let Some(file) = file else {
+            continue;
+        };
+        if config.blame.is_some() {
+            blame_candidates.push((files.len(), relative_path.to_path_buf()));
+        }
+        if config.file_git_info {
+            committed_files.push(relative_path.to_path_buf());
+        }
+        files.push(file);
+    }
+
+    // ~~~ Uninitialized Submodules ~~~
+    if config.include_submodules {
+        for submodule in submodules.iter().filter(|submodule| !submodule.initialized) {
+            let include_in_tree =
+                config.full_directory_tree || file_matcher.is_match(&submodule.path);
+            if include_in_tree {
+                add_labeled_to_tree(&mut tree, &submodule.path, "uninitialized submodule");
+            }
+        }
+    }
+
+    // ~~~ Blame ~~~
+    if config.blame.is_some() {
+        annotate_files_with_blame(config, &canonical_root_path, &mut files, blame_candidates);
+    }
+
+    // ~~~ Last Commits ~~~
+    if config.file_git_info {
+        annotate_files_with_last_commit(&canonical_root_path, &mut files, &committed_files);
+    }
+
+    // ~~~ Token Counts ~~~
+    stub_files_over_line_limit(config, &mut files);
+    annotate_files_with_token_counts(config, &mut files);
+    stub_files_over_token_limit(config, &mut files);
+
     // ~~~ Sorting ~~~
     sort_tree(&mut tree, config.sort_method);
     sort_files(&mut files, config.sort_method);
+    prioritize_files(&mut files, priority);
 
-    Ok((tree.to_string(), files))
+    Ok((tree, files, warnings))
+}
+
+/// Reads a file of the walk into its JSON representation.
+///
+/// # Arguments
+///
+/// * `config` - Configuration object containing the path and rendering settings
+/// * `root` - The canonical root path
+/// * `path` - The absolute path of the file
+/// * `relative_path` - The path of the file relative to the root
+///
+/// # Returns
+///
+/// * `Result<(Option<serde_json::Value>, Option<FileWarning>)>` - The file, or `None` if it is
+///   empty, binary or can't be read, and the warning of a skipped or converted file. With
+///   `config.strict`, a file that can't be read or decoded is an error.
+fn read_file_entry(
+    config: &Code2PromptConfig,
+    root: &Path,
+    path: &Path,
+    relative_path: &Path,
+) -> Result<(Option<serde_json::Value>, Option<FileWarning>)> {
+    // ~~~ Filepath ~~~
+    let file_path = display_path(&config.path_style, root, relative_path);
+
+    let read = entry_metadata(path, config.follow_symlinks)
+        .and_then(|metadata| Ok((metadata, fs::read(path)?)));
+    let (metadata, code_bytes) = match read {
+        Ok(read) => read,
+        Err(e) if config.strict => bail!("Failed to read {}: {}", file_path, e),
+        Err(e) => {
+            let warning = file_warning(&file_path, format!("failed to read, skipped: {}", e));
+            return Ok((None, Some(warning)));
+        }
+    };
+    let (code, warning) = decode_file(config, &file_path, &code_bytes)?;
+    let Some((code, encoding)) = code else {
+        return Ok((None, warning));
+    };
+
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+
+    // ~~~ File JSON Representation ~~~
+    let mut file_entry = serde_json::Map::new();
+    file_entry.insert("path".to_string(), json!(file_path));
+    file_entry.insert("extension".to_string(), json!(extension));
+    insert_code(config, &mut file_entry, path, &code, encoding, &code_bytes);
+    file_entry.insert("truncated".to_string(), json!(false));
+
+    // Store metadata
+    let entry_meta = EntryMetadata::from(&metadata);
+    file_entry.insert("metadata".to_string(), serde_json::to_value(entry_meta)?);
+
+    // Record the file size in bytes, for the directory summary and sorting by size, and the
+    // modification time, both from the metadata read with the file.
+    file_entry.insert("size".to_string(), json!(metadata.len()));
+    file_entry.insert("size_bytes".to_string(), json!(metadata.len()));
+    let mtime = metadata
+        .modified()
+        .ok()
+        .map(|mtime| DateTime::<Utc>::from(mtime).to_rfc3339_opts(SecondsFormat::Secs, true));
+    file_entry.insert("mtime".to_string(), json!(mtime));
+    insert_text_stats(&mut file_entry, &code);
+
+    // If date sorting is requested, record the file modification time.
+    if let Some(method) = config.sort_method {
+        if method == FileSortMethod::DateAsc || method == FileSortMethod::DateDesc {
+            let mod_time = metadata
+                .modified()
+                .ok()
+                .and_then(|mtime| mtime.duration_since(std::time::SystemTime::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            file_entry.insert("mod_time".to_string(), json!(mod_time));
+        }
+    }
+    if let Some(warning) = &warning {
+        file_entry.insert("warning".to_string(), json!(warning.message));
+    }
+    debug!(target: "included_files", "Included file: {}", file_path);
+    Ok((Some(serde_json::Value::Object(file_entry)), warning))
+}
+
+/// Decodes the content of a file for the prompt.
+///
+/// Binary content is written as `config.binary_policy` asks, see [`binary_file`], and content
+/// that isn't plain UTF-8 is converted with a warning; with `config.strict`, a conversion is an
+/// error instead. Empty files are skipped without a warning.
+///
+/// # Returns
+///
+/// * `Result<DecodedFile>` - The text of the file and how it was encoded, `None` if it is
+///   skipped, and the warning, if any
+fn decode_file(config: &Code2PromptConfig, file_path: &str, content: &[u8]) -> Result<DecodedFile> {
+    let Some(decoded) = decode_text(content) else {
+        return Ok(binary_file(config.binary_policy, file_path, content));
+    };
+    if decoded.text.trim().is_empty() {
+        debug!("Excluded empty file: {}", file_path);
+        return Ok((None, None));
+    }
+    let warning = match decoded.warning {
+        Some(message) if config.strict => bail!("Failed to decode {}: {}", file_path, message),
+        Some(message) => Some(file_warning(file_path, message)),
+        None => None,
+    };
+    Ok((Some((decoded.text, FileEncoding::Text)), warning))
+}
+
+/// Returns the text of a binary file for its policy: its base64 data URI if it is an image
+/// or a font small enough, else its placeholder line, or nothing and a warning if binary
+/// files are skipped.
+fn binary_file(policy: BinaryPolicy, file_path: &str, content: &[u8]) -> DecodedFile {
+    let mime_type = sniff_mime_type(content);
+    let inlined = |max_bytes: u64| {
+        content.len() as u64 <= max_bytes
+            && (mime_type.starts_with("image/") || mime_type.starts_with("font/"))
+    };
+    match policy {
+        BinaryPolicy::Skip => {
+            let warning = file_warning(file_path, "binary content, skipped".to_string());
+            (None, Some(warning))
+        }
+        BinaryPolicy::Base64 { max_bytes } if inlined(max_bytes) => {
+            debug!("Inlined binary file in base64: {}", file_path);
+            let data_uri = format!(
+                "data:{};base64,{}",
+                mime_type,
+                BASE64_STANDARD.encode(content)
+            );
+            (Some((data_uri, FileEncoding::Base64)), None)
+        }
+        BinaryPolicy::Placeholder | BinaryPolicy::Base64 { .. } => {
+            let placeholder = format!(
+                "[binary file: {}, {}, {}]",
+                file_path,
+                format_size(content.len() as u64),
+                mime_type
+            );
+            (Some((placeholder, FileEncoding::Placeholder)), None)
+        }
+    }
+}
+
+/// Records the language, the code block, the line numbers setting and the `encoding` of a
+/// file. A binary file has no language, its MIME type as `mime_type`, and its line is fenced
+/// without line numbers.
+fn insert_code(
+    config: &Code2PromptConfig,
+    file_entry: &mut serde_json::Map<String, serde_json::Value>,
+    path: &Path,
+    code: &str,
+    encoding: FileEncoding,
+    content: &[u8],
+) {
+    let (language, line_numbers) = match encoding {
+        FileEncoding::Text => (
+            detect_language(path, code, &config.languages),
+            config.line_numbers,
+        ),
+        FileEncoding::Placeholder | FileEncoding::Base64 => {
+            file_entry.insert("mime_type".to_string(), json!(sniff_mime_type(content)));
+            (String::new(), false)
+        }
+    };
+    let code_block = wrap_code_block(code, &language, line_numbers, config.no_codeblock);
+    file_entry.insert("language".to_string(), json!(language));
+    file_entry.insert("code".to_string(), json!(code_block));
+    file_entry.insert("line_numbers".to_string(), json!(line_numbers));
+    file_entry.insert("encoding".to_string(), json!(encoding));
+}
+
+/// Logs the warning of a file and returns it for the report.
+fn file_warning(file_path: &str, message: String) -> FileWarning {
+    warn!("{}: {}", file_path, message);
+    FileWarning {
+        path: file_path.to_string(),
+        message,
+    }
+}
+
+/// Records the line and word counts of a file content, which don't need a tokenizer.
+fn insert_text_stats(file_entry: &mut serde_json::Map<String, serde_json::Value>, code: &str) {
+    file_entry.insert("lines".to_string(), json!(code.lines().count()));
+    file_entry.insert("words".to_string(), json!(code.split_whitespace().count()));
+}
+
+/// Returns the metadata of an entry, describing the symlink itself unless links are followed.
+fn entry_metadata(path: &Path, follow_symlinks: bool) -> std::io::Result<fs::Metadata> {
+    if follow_symlinks {
+        fs::metadata(path)
+    } else {
+        fs::symlink_metadata(path)
+    }
+}
+
+/// Returns true if a component of the relative path starts with a dot.
+fn is_hidden(path: &Path) -> bool {
+    path.components()
+        .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+}
+
+/// Traverses the HEAD tree of a bare repository.
+///
+/// This mirrors [`traverse_directory`] for repositories without a working tree: gitignore rules
+/// do not apply since every file is tracked, but include/exclude patterns and hidden files do.
+///
+/// # Arguments
+///
+/// * `config` - Configuration object containing include/exclude patterns, and other settings
+/// * `repo_path` - The canonical path of the bare repository
+/// * `selection` - If set, the only files to keep
+/// * `priority` - The patterns of the files placed first
+///
+/// # Returns
+///
+/// * `Result<(String, Vec<serde_json::Value>)>` - A tuple containing the string representation of the directory
+///   tree and a vector of JSON representations of the files
+fn traverse_bare_repository(
+    config: &Code2PromptConfig,
+    repo_path: &Path,
+    selection: Option<&HashSet<PathBuf>>,
+    priority: &GlobSet,
+) -> Result<Traversal> {
+    let mut files = Vec::new();
+    let mut warnings = Vec::new();
+    let mut committed_files = Vec::new();
+    let file_matcher = config.file_matcher();
+    let is_visible = |path: &Path| config.hidden || !is_hidden(path);
+    let file_match = |path: &Path| {
+        file_matcher.is_match(path) && selection.is_none_or(|selection| selection.contains(path))
+    };
+
+    let mut entries = read_head_tree(repo_path, |path| is_visible(path) && file_match(path))?;
+    // Re-sort the entries by path to match the order of the working-tree traversal
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    let mut tree = Tree::new(label(repo_path));
+
+    for entry in entries {
+        let relative_path = entry.path.as_path();
+        if !is_visible(relative_path) {
+            continue;
+        }
+        if config.full_directory_tree || file_match(relative_path) {
+            add_to_tree(&mut tree, relative_path);
+        }
+
+        let Some(code_bytes) = entry.content else {
+            continue;
+        };
+        let file_path = display_path(&config.path_style, repo_path, relative_path);
+        let (code, warning) = decode_file(config, &file_path, &code_bytes)?;
+        let Some((code, encoding)) = code else {
+            warnings.extend(warning);
+            continue;
+        };
+        let extension = relative_path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("");
+
+        let mut file_entry = serde_json::Map::new();
+        file_entry.insert("path".to_string(), json!(file_path));
+        file_entry.insert("extension".to_string(), json!(extension));
+        insert_code(
+            config,
+            &mut file_entry,
+            relative_path,
+            &code,
+            encoding,
+            &code_bytes,
+        );
+        file_entry.insert("truncated".to_string(), json!(false));
+        let entry_meta = EntryMetadata {
+            is_dir: false,
+            is_symlink: entry.is_symlink,
+        };
+        file_entry.insert("metadata".to_string(), serde_json::to_value(entry_meta)?);
+        // Blobs have no modification time, so date sorting keeps the tree order
+        if matches!(
+            config.sort_method,
+            Some(FileSortMethod::DateAsc | FileSortMethod::DateDesc)
+        ) {
+            file_entry.insert("mod_time".to_string(), json!(0));
+        }
+        file_entry.insert("size".to_string(), json!(code_bytes.len()));
+        file_entry.insert("size_bytes".to_string(), json!(code_bytes.len()));
+        file_entry.insert("mtime".to_string(), json!(null));
+        insert_text_stats(&mut file_entry, &code);
+        if let Some(warning) = warning {
+            file_entry.insert("warning".to_string(), json!(warning.message));
+            warnings.push(warning);
+        }
+        files.push(serde_json::Value::Object(file_entry));
+        committed_files.push(relative_path.to_path_buf());
+        debug!(target: "included_files", "Included file: {}", file_path);
+    }
+
+    if config.file_git_info {
+        annotate_files_with_last_commit(repo_path, &mut files, &committed_files);
+    }
+    stub_files_over_line_limit(config, &mut files);
+    annotate_files_with_token_counts(config, &mut files);
+    stub_files_over_token_limit(config, &mut files);
+
+    sort_tree(&mut tree, config.sort_method);
+    sort_files(&mut files, config.sort_method);
+    prioritize_files(&mut files, priority);
+
+    Ok((tree, files, warnings))
+}
+
+/// Adds a path to the tree, creating the intermediate nodes as needed.
+///
+/// # Arguments
+///
+/// * `tree` - The directory tree to update
+/// * `relative_path` - The path to add, relative to the tree root
+fn add_to_tree(tree: &mut Tree<String>, relative_path: &Path) {
+    let mut current_tree = tree;
+    for component in relative_path.components() {
+        let component_str = component.as_os_str().to_string_lossy().to_string();
+        current_tree = if let Some(pos) = current_tree
+            .leaves
+            .iter_mut()
+            .position(|child| child.root == component_str)
+        {
+            &mut current_tree.leaves[pos]
+        } else {
+            let new_tree = Tree::new(component_str.clone());
+            current_tree.leaves.push(new_tree);
+            current_tree.leaves.last_mut().unwrap()
+        };
+    }
+}
+
+/// Adds an entry to the tree with a parenthesized note, e.g. `name (uninitialized submodule)`.
+///
+/// An existing node for the entry, such as the empty directory of an uninitialized submodule,
+/// is relabeled instead of being duplicated.
+///
+/// # Arguments
+///
+/// * `tree` - The directory tree to update
+/// * `relative_path` - The path of the entry, relative to the tree root
+/// * `note` - The note appended to the entry name
+fn add_labeled_to_tree(tree: &mut Tree<String>, relative_path: &Path, note: &str) {
+    let components: Vec<String> = relative_path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().to_string())
+        .collect();
+    let Some((name, parents)) = components.split_last() else {
+        return;
+    };
+
+    let mut current_tree = tree;
+    for component_str in parents {
+        current_tree = if let Some(pos) = current_tree
+            .leaves
+            .iter()
+            .position(|child| &child.root == component_str)
+        {
+            &mut current_tree.leaves[pos]
+        } else {
+            current_tree.leaves.push(Tree::new(component_str.clone()));
+            current_tree.leaves.last_mut().unwrap()
+        };
+    }
+
+    let placeholder = format!("{} ({})", name, note);
+    match current_tree
+        .leaves
+        .iter_mut()
+        .find(|child| &child.root == name)
+    {
+        Some(child) => child.root = placeholder,
+        None => current_tree.leaves.push(Tree::new(placeholder)),
+    }
+}
+
+/// Replaces the code of the given files with blame-annotated code.
+///
+/// Blame is computed in parallel. Files that are not tracked by git, or that have more lines
+/// than `blame_max_lines`, keep their unannotated code.
+///
+/// # Arguments
+///
+/// * `config` - Configuration object containing the blame mode and limits
+/// * `root_path` - The canonical root path the file paths are relative to
+/// * `files` - The JSON file representations to update
+/// * `candidates` - The indices in `files` and relative paths of the files to annotate
+fn annotate_files_with_blame(
+    config: &Code2PromptConfig,
+    root_path: &Path,
+    files: &mut [serde_json::Value],
+    candidates: Vec<(usize, PathBuf)>,
+) {
+    let Some(mode) = config.blame else {
+        return;
+    };
+
+    let annotated: Vec<(usize, String)> = candidates
+        .into_par_iter()
+        .filter_map(|(index, relative_path)| {
+            let code_bytes = fs::read(root_path.join(&relative_path)).ok()?;
+            let code = decode_text(&code_bytes)?.text;
+            if config
+                .blame_max_lines
+                .is_some_and(|max_lines| code.lines().count() > max_lines)
+            {
+                debug!("Skipped blame for large file: {}", relative_path.display());
+                return None;
+            }
+
+            let blame = match get_git_blame(root_path, &relative_path) {
+                Ok(blame) => blame,
+                Err(e) => {
+                    debug!("No blame for {}: {}", relative_path.display(), e);
+                    return None;
+                }
+            };
+            let annotated = annotate_with_blame(&code, &blame, mode, config.line_numbers);
+            let language = detect_language(&relative_path, &code, &config.languages);
+            Some((
+                index,
+                wrap_code_block(&annotated, &language, false, config.no_codeblock),
+            ))
+        })
+        .collect();
+
+    for (index, code_block) in annotated {
+        files[index]["code"] = json!(code_block);
+    }
+}
+
+/// Adds the last commit that touched each file as its `last_commit`.
+///
+/// The history is walked once for all files. Files outside the repository or never
+/// committed get a `null` last commit.
+///
+/// # Arguments
+///
+/// * `root_path` - The canonical root path the file paths are relative to
+/// * `files` - The JSON file representations to update
+/// * `relative_paths` - The relative path of each file, in the same order as `files`
+fn annotate_files_with_last_commit(
+    root_path: &Path,
+    files: &mut [serde_json::Value],
+    relative_paths: &[PathBuf],
+) {
+    let last_commits = last_commits_for_paths(root_path, relative_paths).unwrap_or_else(|e| {
+        debug!("No last commits: {}", e);
+        Default::default()
+    });
+    for (file, relative_path) in files.iter_mut().zip(relative_paths) {
+        file["last_commit"] = json!(last_commits.get(relative_path));
+    }
+}
+
+/// Adds the number of tokens of each file's rendered code as its `token_count`.
+///
+/// Files are counted in parallel with the configured tokenizer. A file without code to
+/// count gets a `null` token count rather than 0. With the token cache enabled, the cached
+/// counts are reused and the new ones are saved to the cache. Estimated counts aren't cached,
+/// and no file is counted with the token count mode off.
+///
+/// # Arguments
+///
+/// * `config` - Configuration object containing the tokenizer encoding and the cache settings
+/// * `files` - The JSON file representations to update
+fn annotate_files_with_token_counts(config: &Code2PromptConfig, files: &mut [serde_json::Value]) {
+    match config.token_count_mode {
+        TokenCountMode::Exact => {}
+        TokenCountMode::Estimate => {
+            files.par_iter_mut().for_each(|file| {
+                let token_count = file
+                    .get("code")
+                    .and_then(|code| code.as_str())
+                    .map(|code| config.count_tokens(code));
+                file["token_count"] = json!(token_count);
+            });
+            return;
+        }
+        TokenCountMode::Off => {
+            for file in files.iter_mut() {
+                file["token_count"] = json!(null);
+            }
+            return;
+        }
+    }
+
+    let mut cache = if config.token_cache {
+        config
+            .cache_dir
+            .clone()
+            .or_else(TokenCache::default_dir)
+            .map(|dir| TokenCache::open(&dir))
+    } else {
+        None
+    };
+    let encoding = config.encoding.to_string();
+
+    let counted: Vec<(String, usize)> = files
+        .par_iter_mut()
+        .filter_map(|file| {
+            let Some(code) = file.get("code").and_then(|code| code.as_str()) else {
+                file["token_count"] = json!(null);
+                return None;
+            };
+            let Some(cache) = &cache else {
+                file["token_count"] = json!(count_tokens(code, &config.encoding));
+                return None;
+            };
+            let key = TokenCache::key(code, &encoding);
+            let token_count = cache
+                .get(&key)
+                .unwrap_or_else(|| count_tokens(code, &config.encoding));
+            file["token_count"] = json!(token_count);
+            Some((key, token_count))
+        })
+        .collect();
+
+    if let Some(cache) = &mut cache {
+        debug!(
+            "Token cache: {} hits, {} misses",
+            cache.hits(),
+            cache.misses()
+        );
+        for (key, token_count) in counted {
+            cache.insert(key, token_count);
+        }
+        if let Err(e) = cache.save() {
+            warn!("Failed to save the token cache: {:#}", e);
+        }
+    }
+}
+
+/// Replaces the code of the files longer than `max_lines_per_file` lines with a stub.
+///
+/// This runs before the files are tokenized, so the long files are never counted.
+///
+/// # Arguments
+///
+/// * `config` - Configuration object containing the line limit
+/// * `files` - The JSON file representations to update
+fn stub_files_over_line_limit(config: &Code2PromptConfig, files: &mut [serde_json::Value]) {
+    let Some(max_lines) = config.max_lines_per_file else {
+        return;
+    };
+    for file in files.iter_mut() {
+        let Some(code) = file.get("code").and_then(|code| code.as_str()) else {
+            continue;
+        };
+        let lines = code_lines(code, config.no_codeblock).1.len();
+        if lines > max_lines {
+            let note = format!(
+                "{} has {} lines, over the limit of {} lines per file",
+                file["path"].as_str().unwrap_or(""),
+                lines,
+                max_lines
+            );
+            file["code"] = json!(stub_code(code, &note, config.no_codeblock));
+            file["stub"] = json!({ "lines": lines, "token_count": null });
+            file["truncated"] = json!(true);
+        }
+    }
+}
+
+/// Replaces the code of the files over `max_tokens_per_file` tokens with a stub, whose
+/// tokens are counted instead.
+///
+/// # Arguments
+///
+/// * `config` - Configuration object containing the token limit and the tokenizer encoding
+/// * `files` - The JSON file representations to update, annotated with their token count
+fn stub_files_over_token_limit(config: &Code2PromptConfig, files: &mut [serde_json::Value]) {
+    let Some(max_tokens) = config.max_tokens_per_file else {
+        return;
+    };
+    files.par_iter_mut().for_each(|file| {
+        let token_count = match file.get("token_count").and_then(|t| t.as_u64()) {
+            Some(token_count)
+                if token_count as usize > max_tokens && file.get("stub").is_none() =>
+            {
+                token_count
+            }
+            _ => return,
+        };
+        let Some(code) = file.get("code").and_then(|code| code.as_str()) else {
+            return;
+        };
+        let lines = code_lines(code, config.no_codeblock).1.len();
+        let note = format!(
+            "{} has {} tokens, over the limit of {} tokens per file",
+            file["path"].as_str().unwrap_or(""),
+            token_count,
+            max_tokens
+        );
+        let stub = stub_code(code, &note, config.no_codeblock);
+        file["token_count"] = json!(config.count_tokens(&stub));
+        file["code"] = json!(stub);
+        file["stub"] = json!({ "lines": lines, "token_count": token_count });
+        file["truncated"] = json!(true);
+    });
+}
+
+/// Splits a code block into its fences, if any, and its lines.
+pub(crate) fn code_lines(
+    code: &str,
+    no_codeblock: bool,
+) -> ((Option<&str>, Option<&str>), Vec<&str>) {
+    let mut lines: Vec<&str> = code.lines().collect();
+    if no_codeblock || lines.len() < 2 {
+        return ((None, None), lines);
+    }
+    let opening = lines.remove(0);
+    let closing = lines.pop();
+    // The code ends with a newline before the closing fence
+    if lines.last() == Some(&"") {
+        lines.pop();
+    }
+    ((Some(opening), closing), lines)
+}
+
+/// Builds the stub of a code block: its first and last lines around a note.
+fn stub_code(code: &str, note: &str, no_codeblock: bool) -> String {
+    let ((opening, closing), lines) = code_lines(code, no_codeblock);
+    let head = lines.len().min(STUB_CONTEXT_LINES);
+    let tail = lines.len().saturating_sub(head).min(STUB_CONTEXT_LINES);
+    let shorten = |line: &&str| match line.char_indices().nth(STUB_LINE_CHARS) {
+        Some((end, _)) => format!("{}…", &line[..end]),
+        None => line.to_string(),
+    };
+
+    let mut stub: Vec<String> = opening.map(str::to_string).into_iter().collect();
+    stub.extend(lines[..head].iter().map(shorten));
+    stub.push(format!(
+        "... ({} lines omitted: {})",
+        lines.len() - head - tail,
+        note
+    ));
+    stub.extend(lines[lines.len() - tail..].iter().map(shorten));
+    stub.extend(closing.map(str::to_string));
+    stub.join("\n")
 }
 
 /// Returns the file name or the string representation of the path.
@@ -198,25 +1177,36 @@ pub fn label<P: AsRef<Path>>(p: P) -> String {
     }
 }
 
-/// Wraps the code block with a delimiter and adds line numbers if required.
+/// Returns the width of the line numbers of a text of `line_count` lines, the digits of the
+/// last one, e.g. 3 for a file of 120 lines.
+pub(crate) fn line_number_width(line_count: usize) -> usize {
+    line_count.max(1).to_string().len()
+}
+
+/// Wraps the code block with a fence longer than its backtick runs, see [`code_fence`], and
+/// adds line numbers if required, right-aligned to the width of the last one: `  84 | `.
+///
+/// The numbers are added when the file is read, before a stub or the token budget cuts it,
+/// so that the lines kept show their position in the file and the cut lines are replaced with
+/// a `... (N lines omitted...)` line.
 ///
 /// # Arguments
 ///
 /// * `code` - The code block to wrap.
-/// * `extension` - The file extension of the code block.
+/// * `language` - The language of the code fence, see [`detect_language`].
 /// * `line_numbers` - Whether to add line numbers to the code.
 /// * `no_codeblock` - Whether to not wrap the code block with a delimiter.
 ///
 /// # Returns
 ///
 /// * `String` - The wrapped code block.
-fn wrap_code_block(code: &str, extension: &str, line_numbers: bool, no_codeblock: bool) -> String {
-    let delimiter = "`".repeat(3);
+fn wrap_code_block(code: &str, language: &str, line_numbers: bool, no_codeblock: bool) -> String {
     let mut code_with_line_numbers = String::new();
 
     if line_numbers {
+        let width = line_number_width(code.lines().count());
         for (line_number, line) in code.lines().enumerate() {
-            code_with_line_numbers.push_str(&format!("{:4} | {}\n", line_number + 1, line));
+            code_with_line_numbers.push_str(&format!("{:>width$} | {}\n", line_number + 1, line));
         }
     } else {
         code_with_line_numbers = code.to_string();
@@ -225,9 +1215,10 @@ fn wrap_code_block(code: &str, extension: &str, line_numbers: bool, no_codeblock
     if no_codeblock {
         code_with_line_numbers
     } else {
+        let delimiter = code_fence(&code_with_line_numbers);
         format!(
             "{}{}\n{}\n{}",
-            delimiter, extension, code_with_line_numbers, delimiter
+            delimiter, language, code_with_line_numbers, delimiter
         )
     }
 }
@@ -0,0 +1,132 @@
+//! This module estimates the input cost of a prompt for popular models, from a pricing table
+//! embedded in the binary that can be overridden with a TOML file.
+
+use crate::tokenizer::{count_tokens, estimate_tokens, TokenCountMode, TokenizerType};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// The pricing table embedded in the binary.
+const DEFAULT_PRICING: &str = include_str!("./default_pricing.toml");
+
+/// The price of a model.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelPrice {
+    /// The cost in US dollars of 1M input tokens.
+    pub input_per_million: f64,
+    /// The encoding (e.g. `o200k_base`) used to count the tokens, the one of the model name
+    /// if unset.
+    #[serde(default)]
+    pub encoding: Option<String>,
+    /// The size of the context window of the model, in tokens.
+    #[serde(default)]
+    pub context_window: Option<usize>,
+}
+
+/// The prices of the models, by name.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct PricingTable {
+    pub models: BTreeMap<String, ModelPrice>,
+}
+
+/// The estimated input cost of a prompt for a model.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CostEstimate {
+    pub model: String,
+    /// The encoding the tokens were counted with.
+    pub encoding: &'static str,
+    pub token_count: usize,
+    /// The estimated cost in US dollars.
+    pub cost_usd: f64,
+}
+
+impl PricingTable {
+    /// Parses a pricing table in TOML, with a table per model.
+    pub fn parse(toml_str: &str) -> Result<Self> {
+        toml::from_str(toml_str).context("Invalid pricing table")
+    }
+
+    /// Loads the embedded pricing table, with the models of the override file, if any,
+    /// replacing or extending it.
+    ///
+    /// # Arguments
+    ///
+    /// * `override_path` - The path of a TOML file in the format of the embedded table
+    ///
+    /// # Returns
+    ///
+    /// * `Result<PricingTable>` - The merged table, or an error if the file can't be read or parsed
+    pub fn load(override_path: Option<&Path>) -> Result<Self> {
+        let mut table = Self::parse(DEFAULT_PRICING)?;
+        if let Some(path) = override_path {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read the pricing file {}", path.display()))?;
+            let overrides = Self::parse(&content)
+                .with_context(|| format!("Failed to parse the pricing file {}", path.display()))?;
+            table.models.extend(overrides.models);
+        }
+        Ok(table)
+    }
+
+    /// Returns the price and tokenizer of a model, whose name is matched case-insensitively.
+    pub fn price(&self, model: &str) -> Result<(&ModelPrice, TokenizerType)> {
+        let price = self
+            .models
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(model))
+            .map(|(_, price)| price)
+            .ok_or_else(|| {
+                let models: Vec<&str> = self.models.keys().map(String::as_str).collect();
+                anyhow!(
+                    "No price for the model {}. Known models: {}. Add its price to a pricing file",
+                    model,
+                    models.join(", ")
+                )
+            })?;
+        let tokenizer_type = match &price.encoding {
+            Some(encoding) => encoding
+                .parse()
+                .map_err(|e: String| anyhow!("Invalid encoding for the model {}: {}", model, e))?,
+            None => TokenizerType::from_model(model).unwrap_or_default(),
+        };
+        Ok((price, tokenizer_type))
+    }
+
+    /// Returns the context window of a model, whose name is matched case-insensitively.
+    pub fn context_window(&self, model: &str) -> Result<usize> {
+        let (price, _) = self.price(model)?;
+        price.context_window.ok_or_else(|| {
+            anyhow!(
+                "No context window for the model {}. Add it to a pricing file",
+                model
+            )
+        })
+    }
+
+    /// Estimates the input cost of a prompt for a model, counting its tokens with the
+    /// tokenizer of the model. With [`TokenCountMode::Estimate`], the tokens are estimated
+    /// from the length of the prompt instead; they are still counted with the mode off, since
+    /// the cost was asked for.
+    pub fn estimate(
+        &self,
+        prompt: &str,
+        model: &str,
+        mode: TokenCountMode,
+    ) -> Result<CostEstimate> {
+        let (price, tokenizer_type) = self.price(model)?;
+        let token_count = match mode {
+            TokenCountMode::Estimate => estimate_tokens(prompt, &tokenizer_type),
+            TokenCountMode::Exact | TokenCountMode::Off => count_tokens(prompt, &tokenizer_type),
+        };
+        Ok(CostEstimate {
+            model: model.to_string(),
+            encoding: tokenizer_type.encoding_name(),
+            token_count,
+            cost_usd: token_count as f64 * price.input_per_million / 1_000_000.0,
+        })
+    }
+}
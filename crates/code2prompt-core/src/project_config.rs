@@ -0,0 +1,163 @@
+//! This module reads the `.code2prompt.toml` file of a repository, the defaults shared by
+//! everyone generating prompts from it, and resolves the template of a prompt.
+//!
+//! The file is looked up like the templates directories: in the target path, then in its
+//! parents up to the root of the git repository. For instance:
+//!
+//! ```toml
+//! # A path relative to this file, or the name of a repository or built-in template
+//! template = "review"
+//!
+//! # The languages of the code fences by extension or file name, over the built-in ones
+//! [languages]
+//! pyx = "cython"
+//! Tiltfile = "starlark"
+//! ```
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The name of the configuration file of a repository.
+pub const CONFIG_FILE: &str = ".code2prompt.toml";
+
+/// The environment variable setting the default template, over the configuration file.
+pub const TEMPLATE_ENV_VAR: &str = "CODE2PROMPT_TEMPLATE";
+
+/// The template name that always stands for the default template, even when the
+/// configuration file or the environment sets another one.
+pub const DEFAULT_TEMPLATE_NAME: &str = "default";
+
+/// The settings of a `.code2prompt.toml` file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectConfig {
+    /// The template of the prompts when none is passed: a path relative to the file, or the
+    /// name of a repository or built-in template.
+    #[serde(default)]
+    pub template: Option<String>,
+    /// The languages of the code fences by file extension or file name, over the built-in ones.
+    #[serde(default)]
+    pub languages: HashMap<String, String>,
+}
+
+impl ProjectConfig {
+    /// Parses a configuration file in TOML.
+    pub fn parse(toml_str: &str) -> Result<Self> {
+        toml::from_str(toml_str).context("Invalid configuration file")
+    }
+}
+
+/// Returns the configuration file of a path, the nearest one up to the root of the git
+/// repository, or in the directory of the path only outside a git repository.
+pub fn find_project_config(path: &Path) -> Option<PathBuf> {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let start = if path.is_file() {
+        path.parent().map(Path::to_path_buf).unwrap_or(path)
+    } else {
+        path
+    };
+    let in_repository = start.ancestors().any(|dir| dir.join(".git").exists());
+    for dir in start.ancestors() {
+        let file = dir.join(CONFIG_FILE);
+        if file.is_file() {
+            return Some(file);
+        }
+        if !in_repository || dir.join(".git").exists() {
+            break;
+        }
+    }
+    None
+}
+
+/// Loads the configuration file of a path, if it has one.
+///
+/// # Arguments
+///
+/// * `path` - The target path, whose configuration file is looked up
+///
+/// # Returns
+///
+/// * `Result<Option<(PathBuf, ProjectConfig)>>` - The file with its settings, none if there is
+///   no configuration file, or an error if it can't be read or parsed
+pub fn load_project_config(path: &Path) -> Result<Option<(PathBuf, ProjectConfig)>> {
+    let Some(file) = find_project_config(path) else {
+        return Ok(None);
+    };
+    let content = fs::read_to_string(&file)
+        .with_context(|| format!("Failed to read the configuration file {}", file.display()))?;
+    let config = ProjectConfig::parse(&content)
+        .with_context(|| format!("Failed to parse the configuration file {}", file.display()))?;
+    Ok(Some((file, config)))
+}
+
+/// Where the template of a prompt was chosen, from the highest precedence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateOrigin {
+    /// The `--template` option.
+    Flag,
+    /// The [`TEMPLATE_ENV_VAR`] environment variable.
+    Environment,
+    /// The `template` of a configuration file.
+    ConfigFile(PathBuf),
+    /// None of them, so the default template.
+    Default,
+}
+
+/// The template of a prompt and where it was chosen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateChoice {
+    /// The path or name of the template, none for the default template.
+    pub template: Option<PathBuf>,
+    pub origin: TemplateOrigin,
+}
+
+/// Resolves the template of a prompt: the `--template` option, then the environment variable,
+/// then the configuration file, then the default template. [`DEFAULT_TEMPLATE_NAME`] picks the
+/// default template wherever it's set, and an empty value is ignored.
+///
+/// # Arguments
+///
+/// * `flag` - The value of the `--template` option
+/// * `env` - The value of the [`TEMPLATE_ENV_VAR`] environment variable
+/// * `config` - The configuration file, with its settings
+///
+/// # Returns
+///
+/// * `TemplateChoice` - The template, whose path is relative to the directory of the
+///   configuration file when it comes from it
+pub fn resolve_template(
+    flag: Option<&Path>,
+    env: Option<&str>,
+    config: Option<(&Path, &ProjectConfig)>,
+) -> TemplateChoice {
+    let flag = flag.filter(|flag| !flag.as_os_str().is_empty());
+    let env = env.filter(|env| !env.is_empty());
+    let config_template = config
+        .and_then(|(file, config)| Some((file, config.template.as_deref()?)))
+        .filter(|(_, template)| !template.is_empty());
+    let (template, origin) = if let Some(flag) = flag {
+        (flag.to_path_buf(), TemplateOrigin::Flag)
+    } else if let Some(env) = env {
+        (PathBuf::from(env), TemplateOrigin::Environment)
+    } else if let Some((file, template)) = config_template {
+        // A path is relative to the configuration file, a name is looked up as is
+        let dir = file.parent().unwrap_or(Path::new(""));
+        let template = match dir.join(template) {
+            path if path.is_file() || Path::new(template).components().count() > 1 => path,
+            _ => PathBuf::from(template),
+        };
+        (template, TemplateOrigin::ConfigFile(file.to_path_buf()))
+    } else {
+        return TemplateChoice {
+            template: None,
+            origin: TemplateOrigin::Default,
+        };
+    };
+    TemplateChoice {
+        template: (template != Path::new(DEFAULT_TEMPLATE_NAME)).then_some(template),
+        origin,
+    }
+}
@@ -0,0 +1,223 @@
+//! This module clones remote git repositories into temporary directories so they can be
+//! processed like local codebases.
+
+use anyhow::{anyhow, Context, Result};
+use git2::build::RepoBuilder;
+use git2::{Cred, CredentialType, ErrorCode, FetchOptions, RemoteCallbacks};
+use log::{debug, info};
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// Environment variables checked, in order, for a token used to authenticate HTTPS clones.
+pub const TOKEN_ENV_VARS: [&str; 2] = ["GIT_TOKEN", "GITHUB_TOKEN"];
+
+/// Maximum number of times credentials are offered before the clone is aborted.
+const MAX_AUTH_ATTEMPTS: usize = 3;
+
+/// Options controlling how a remote repository is cloned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloneOptions {
+    /// The branch to check out. The remote's default branch is used if unset.
+    pub branch: Option<String>,
+    /// The number of commits to fetch. `None` fetches the full history.
+    pub depth: Option<u32>,
+}
+
+impl Default for CloneOptions {
+    fn default() -> Self {
+        Self {
+            branch: None,
+            depth: Some(1),
+        }
+    }
+}
+
+/// A remote repository cloned into a temporary directory.
+///
+/// The clone is deleted when this value is dropped, unless [`ClonedRepository::keep`] is called.
+#[derive(Debug)]
+pub struct ClonedRepository {
+    path: PathBuf,
+    temp_dir: Option<TempDir>,
+}
+
+impl ClonedRepository {
+    /// Returns the root of the working tree, whose directory is named after the repository.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Keeps the clone on disk after this value is dropped and returns its path.
+    pub fn keep(mut self) -> PathBuf {
+        if let Some(temp_dir) = self.temp_dir.take() {
+            let _ = temp_dir.keep();
+        }
+        self.path.clone()
+    }
+}
+
+/// Returns true if the argument looks like a git URL rather than a local path.
+///
+/// HTTPS, HTTP, SSH and git URLs are recognized, as well as the scp-like `user@host:path` syntax.
+///
+/// # Arguments
+///
+/// * `url` - The command line argument to check
+///
+/// # Returns
+///
+/// * `bool` - `true` if the argument is a remote URL
+pub fn is_remote_url(url: &str) -> bool {
+    const SCHEMES: [&str; 4] = ["https://", "http://", "ssh://", "git://"];
+    if SCHEMES.iter().any(|scheme| url.starts_with(scheme)) {
+        return true;
+    }
+
+    // scp-like syntax, e.g. git@github.com:user/repo.git
+    match url.split_once(':') {
+        Some((host, path)) => {
+            host.contains('@')
+                && !host.contains('/')
+                && !path.is_empty()
+                && !Path::new(url).exists()
+        }
+        None => false,
+    }
+}
+
+/// Extracts the repository name from a git URL.
+///
+/// # Arguments
+///
+/// * `url` - The git URL (e.g., "https://github.com/user/repo.git")
+///
+/// # Returns
+///
+/// * `String` - The repository name (e.g., "repo"), or "repository" if none can be found
+pub fn repository_name(url: &str) -> String {
+    let name = url
+        .trim_end_matches('/')
+        .rsplit(['/', ':'])
+        .next()
+        .unwrap_or_default();
+    let name = name.strip_suffix(".git").unwrap_or(name);
+    if name.is_empty() {
+        "repository".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Clones a remote repository into a temporary directory
+///
+/// SSH URLs authenticate through the running ssh-agent. HTTPS URLs use the token found in the
+/// first set variable of [`TOKEN_ENV_VARS`], falling back to the git credential helpers.
+///
+/// # Arguments
+///
+/// * `url` - The HTTPS or SSH URL of the repository
+/// * `options` - The branch and depth of the clone
+///
+/// # Returns
+///
+/// * `Result<ClonedRepository>` - The cloned repository or an error explaining how to fix it
+pub fn clone_repository(url: &str, options: &CloneOptions) -> Result<ClonedRepository> {
+    let temp_dir = TempDir::with_prefix("code2prompt-").context("Failed to create temp dir")?;
+    // The clone directory is named after the repository so emitted paths are rooted at it
+    let path = temp_dir.path().join(repository_name(url));
+    info!("Cloning {} into {:?}", url, path);
+
+    let mut auth_attempts = 0;
+    let mut last_progress = 0;
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(|url, username, allowed_types| {
+        auth_attempts += 1;
+        if auth_attempts > MAX_AUTH_ATTEMPTS {
+            return Err(git2::Error::new(
+                ErrorCode::Auth,
+                git2::ErrorClass::Net,
+                "no valid credentials",
+            ));
+        }
+        credentials(url, username, allowed_types)
+    });
+    callbacks.transfer_progress(|progress| {
+        let total = progress.total_objects();
+        if let Some(percent) = (progress.received_objects() * 100).checked_div(total) {
+            if percent >= last_progress + 10 || percent == 100 && last_progress < 100 {
+                debug!(
+                    "Received {}/{} objects ({} KiB)",
+                    progress.received_objects(),
+                    total,
+                    progress.received_bytes() / 1024
+                );
+                last_progress = percent;
+            }
+        }
+        true
+    });
+
+    let mut fetch_options = FetchOptions::new();
+    fetch_options.remote_callbacks(callbacks);
+    if let Some(depth) = options.depth {
+        fetch_options.depth(depth.try_into().unwrap_or(i32::MAX));
+    }
+
+    let mut builder = RepoBuilder::new();
+    builder.fetch_options(fetch_options);
+    if let Some(branch) = &options.branch {
+        builder.branch(branch);
+    }
+
+    builder
+        .clone(url, &path)
+        .map_err(|e| clone_error(url, options, e))?;
+
+    info!("Cloned {} successfully", url);
+    Ok(ClonedRepository {
+        path,
+        temp_dir: Some(temp_dir),
+    })
+}
+
+/// Picks the credentials to offer for the authentication methods the server accepts.
+fn credentials(
+    url: &str,
+    username: Option<&str>,
+    allowed_types: CredentialType,
+) -> std::result::Result<Cred, git2::Error> {
+    if allowed_types.contains(CredentialType::SSH_KEY) {
+        return Cred::ssh_key_from_agent(username.unwrap_or("git"));
+    }
+    if allowed_types.contains(CredentialType::USER_PASS_PLAINTEXT) {
+        if let Some(token) = TOKEN_ENV_VARS
+            .iter()
+            .find_map(|var| std::env::var(var).ok().filter(|token| !token.is_empty()))
+        {
+            return Cred::userpass_plaintext(username.unwrap_or("x-access-token"), &token);
+        }
+        debug!("No token found, trying the git credential helpers");
+        let config = git2::Config::open_default()?;
+        return Cred::credential_helper(&config, url, username);
+    }
+    Cred::default()
+}
+
+/// Turns a clone failure into an actionable error message.
+fn clone_error(url: &str, options: &CloneOptions, error: git2::Error) -> anyhow::Error {
+    match (error.code(), &options.branch) {
+        (ErrorCode::Auth, _) => anyhow!(
+            "Authentication failed for {}. For HTTPS, set {} to an access token; for SSH, make sure your key is loaded in ssh-agent ({})",
+            url,
+            TOKEN_ENV_VARS.join(" or "),
+            error.message()
+        ),
+        (ErrorCode::NotFound, Some(branch)) => anyhow!(
+            "Branch {} doesn't exist in {} ({})",
+            branch,
+            url,
+            error.message()
+        ),
+        _ => anyhow!("Failed to clone {}: {}", url, error.message()),
+    }
+}
@@ -0,0 +1,213 @@
+//! This module remembers the token counts of the last run on a codebase, so that the next run
+//! can report how they changed.
+//!
+//! The runs are keyed by the resolved root path and a hash of the settings the counts depend
+//! on, but not of the filters: changing the include and exclude patterns is what the
+//! comparison is for. Like the token cache, the history is best effort: a missing or corrupted
+//! history file silently disables the comparison.
+
+use crate::configuration::Code2PromptConfig;
+use anyhow::{Context, Result};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The name of the history file in the cache directory.
+pub const HISTORY_FILE_NAME: &str = "last_runs.json";
+
+/// The version of the history file format, the histories of other versions are discarded.
+const HISTORY_VERSION: u32 = 1;
+
+/// Number of codebases remembered, the least recently run are forgotten first.
+const MAX_RUNS: usize = 100;
+
+/// The token counts of a run.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunSummary {
+    /// The token count of the rendered prompt.
+    pub total_tokens: usize,
+    /// The token count of each file, by path.
+    pub files: BTreeMap<String, usize>,
+}
+
+/// A run stored in the history file.
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredRun {
+    /// The time of the run in seconds since the Unix epoch.
+    time: u64,
+    #[serde(flatten)]
+    summary: RunSummary,
+}
+
+/// The content of the history file.
+#[derive(Serialize, Deserialize)]
+struct HistoryFile {
+    version: u32,
+    runs: BTreeMap<String, StoredRun>,
+}
+
+/// The last run of each codebase, loaded from the history file of a directory.
+#[derive(Debug)]
+pub struct RunHistory {
+    path: PathBuf,
+    runs: BTreeMap<String, StoredRun>,
+}
+
+impl RunHistory {
+    /// Opens the history of a directory, empty if the history file is missing or can't be read.
+    pub fn open(dir: &Path) -> Self {
+        let path = dir.join(HISTORY_FILE_NAME);
+        let runs = fs::read(&path)
+            .ok()
+            .and_then(|content| serde_json::from_slice::<HistoryFile>(&content).ok())
+            .filter(|history| history.version == HISTORY_VERSION)
+            .map(|history| history.runs)
+            .unwrap_or_else(|| {
+                debug!("No previous run in {}", path.display());
+                BTreeMap::new()
+            });
+        Self { path, runs }
+    }
+
+    /// Returns the key of the runs of a configuration: its resolved root path and a hash of
+    /// the encoding, the token count mode, the output format and the template.
+    pub fn key(config: &Code2PromptConfig) -> String {
+        let root = config
+            .path
+            .canonicalize()
+            .unwrap_or_else(|_| config.path.clone());
+        let settings = format!(
+            "{}\n{:?}\n{:?}\n{}\n{}",
+            config.encoding,
+            config.token_count_mode,
+            config.output_format,
+            config.template_name,
+            config.template_str
+        );
+        let hash = format!("{:x}", Sha256::digest(settings.as_bytes()));
+        format!("{}#{}", root.display(), &hash[..16])
+    }
+
+    /// Returns the last run recorded for a key.
+    pub fn previous(&self, key: &str) -> Option<&RunSummary> {
+        self.runs.get(key).map(|run| &run.summary)
+    }
+
+    /// Records a run, replacing the previous run of the key.
+    pub fn record(&mut self, key: String, summary: RunSummary) {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        self.runs.insert(key, StoredRun { time, summary });
+        while self.runs.len() > MAX_RUNS {
+            let oldest = self
+                .runs
+                .iter()
+                .min_by_key(|(_, run)| run.time)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.runs.remove(&oldest);
+            }
+        }
+    }
+
+    /// Saves the history, replacing the history file atomically.
+    pub fn save(&mut self) -> Result<()> {
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create the cache directory {}", dir.display()))?;
+        let mut file = tempfile::NamedTempFile::new_in(dir)?;
+        let mut writer = BufWriter::new(file.as_file_mut());
+        serde_json::to_writer(
+            &mut writer,
+            &HistoryFile {
+                version: HISTORY_VERSION,
+                runs: std::mem::take(&mut self.runs),
+            },
+        )?;
+        writer.flush()?;
+        drop(writer);
+        file.persist(&self.path)
+            .with_context(|| format!("Failed to save the run history {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+/// The change of the token count of a file since the previous run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileDelta {
+    pub path: String,
+    /// The token count of the previous run, `None` for a new file.
+    pub before: Option<usize>,
+    /// The token count of this run, `None` for a removed file.
+    pub after: Option<usize>,
+}
+
+impl FileDelta {
+    /// Returns the change of the token count, negative when it went down.
+    pub fn change(&self) -> i64 {
+        self.after.unwrap_or(0) as i64 - self.before.unwrap_or(0) as i64
+    }
+}
+
+/// The changes of the token counts since the previous run.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TokenDelta {
+    pub total_before: usize,
+    pub total_after: usize,
+    /// The biggest changes of the files, added and removed files included.
+    pub changes: Vec<FileDelta>,
+}
+
+impl TokenDelta {
+    /// Returns the change of the total token count, negative when it went down.
+    pub fn total_change(&self) -> i64 {
+        self.total_after as i64 - self.total_before as i64
+    }
+}
+
+/// Compares a run with the previous one.
+///
+/// # Arguments
+///
+/// * `previous` - The previous run
+/// * `current` - This run
+/// * `max_changes` - The number of file changes kept, the biggest first
+///
+/// # Returns
+///
+/// * `TokenDelta` - The change of the total and the biggest changes of the files, by
+///   decreasing size of the change then by path
+pub fn compare_runs(previous: &RunSummary, current: &RunSummary, max_changes: usize) -> TokenDelta {
+    let mut paths: Vec<&String> = previous.files.keys().chain(current.files.keys()).collect();
+    paths.sort();
+    paths.dedup();
+
+    let mut changes: Vec<FileDelta> = paths
+        .into_iter()
+        .map(|path| FileDelta {
+            path: path.clone(),
+            before: previous.files.get(path).copied(),
+            after: current.files.get(path).copied(),
+        })
+        .filter(|delta| delta.before != delta.after)
+        .collect();
+    changes.sort_by(|a, b| {
+        b.change()
+            .abs()
+            .cmp(&a.change().abs())
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    changes.truncate(max_changes);
+
+    TokenDelta {
+        total_before: previous.total_tokens,
+        total_after: current.total_tokens,
+        changes,
+    }
+}
@@ -1,14 +1,42 @@
 //! This module defines a Code2promptSession struct that provide a stateful interface to code2prompt-core.
 //! It allows you to load codebase data, Git info, and render prompts using a template.
 
-use anyhow::{Context, Result};
-use std::path::PathBuf;
+use anyhow::{bail, Context, Result};
+use chrono::{SecondsFormat, Utc};
+use globset::GlobSet;
+use log::debug;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
+use crate::budget::{fit_files, BudgetReport};
 use crate::configuration::Code2PromptConfig;
-use crate::git::{get_git_diff, get_git_diff_between_branches, get_git_log};
-use crate::path::{label, traverse_directory};
-use crate::template::{handlebars_setup, render_template, OutputFormat};
-use crate::tokenizer::{count_tokens, TokenizerType};
+use crate::engine::{renderer, RenderOptions, TemplateEngine};
+use crate::filter::build_globset;
+use crate::front_matter::split_front_matter;
+use crate::git::{
+    files_changed_since, format_git_log, get_branch_relationship, get_commit_diff,
+    get_commit_diff_stat, get_contributors, get_conventional_changelog, get_git_diff,
+    get_git_diff_between_refs, get_git_diff_stats, get_git_diff_structured,
+    get_git_diff_workdir_to_ref, get_git_log_commits, get_git_log_range_commits,
+    get_git_stash_diff, get_latest_tags_range, is_empty_diff, truncate_diff, BranchRelationship,
+    Changelog, CommitInfo, Contributor, DiffConfig, DiffStats, DiffTarget, StructuredDiff,
+    DEFAULT_CONTRIBUTORS_COMMIT_LIMIT, DEFAULT_CONTRIBUTORS_COUNT,
+};
+use crate::manifest::{ManifestRow, BUDGET_SKIP_REASON};
+use crate::path::{
+    code_lines, display_path, label, traverse_selected_files, FileEncoding, FileWarning, PathStyle,
+    TreeNode,
+};
+use crate::pricing::{CostEstimate, PricingTable};
+use crate::run_history::RunSummary;
+use crate::split::{
+    chunk_files, split_files, ChunkSize, DirectoryPrompt, PromptPart, ROOT_DIRECTORY_NAME,
+};
+use crate::template::{heading_anchors, resolve_file_format, OutputFormat};
+use crate::token_map::{build_token_map, summarize_directories, DirectorySummary, TokenMapNode};
+use crate::tokenizer::{TokenCountMode, TokenizerType};
 
 /// Represents a live session that holds stateful data about the user's codebase,
 /// including which files have been added or removed, or other data that evolves over time.
@@ -17,6 +45,9 @@ pub struct Code2PromptSession {
     pub config: Code2PromptConfig,
     pub selected_files: Vec<PathBuf>,
     pub data: SessionData,
+    /// The matcher of the `priority_patterns` the files were loaded with, reused to fit the
+    /// token budget.
+    priority: Option<GlobSet>,
 }
 
 /// Represents the collected data about the code (tree + files) and optional Git info.
@@ -24,11 +55,224 @@ pub struct Code2PromptSession {
 #[derive(Debug, Default, Clone)]
 pub struct SessionData {
     pub source_tree: Option<String>,
+    /// The source tree as nodes, without their counts, see [`Code2PromptSession::tree`].
+    pub tree: Option<TreeNode>,
     pub files: Option<serde_json::Value>,
     pub stats: Option<serde_json::Value>,
+    pub removed_files: Option<Vec<String>>,
+    /// The files skipped or converted while reading the codebase.
+    pub file_warnings: Vec<FileWarning>,
     pub git_diff: Option<String>,
     pub git_diff_branch: Option<String>,
+    pub git_relationship: Option<BranchRelationship>,
+    pub git_diff_stat: Option<DiffStats>,
+    pub git_diff_stash: Option<String>,
+    pub git_diff_against: Option<String>,
+    pub git_show: Option<String>,
+    /// The structured form of the first loaded diff, embedded in the JSON output.
+    pub git_diff_structured: Option<StructuredDiff>,
     pub git_log_branch: Option<String>,
+    pub git_log_commits: Option<Vec<CommitInfo>>,
+    pub git_changelog: Option<Changelog>,
+    pub contributors: Option<Vec<Contributor>>,
+    /// The files omitted to fit the token budget, set by [`Code2PromptSession::enforce_token_budget`].
+    pub token_budget: Option<BudgetReport>,
+}
+
+impl SessionData {
+    /// Removes the source tree and the git sections, which a chunk after the first one of a
+    /// prompt leaves to the first one.
+    fn clear_context(&mut self) {
+        self.source_tree = None;
+        self.tree = None;
+        self.git_diff = None;
+        self.git_diff_branch = None;
+        self.git_relationship = None;
+        self.git_diff_stat = None;
+        self.git_diff_stash = None;
+        self.git_diff_against = None;
+        self.git_show = None;
+        self.git_diff_structured = None;
+        self.git_log_branch = None;
+        self.git_log_commits = None;
+        self.git_changelog = None;
+        self.contributors = None;
+    }
+}
+
+/// The token counts of the sections of a prompt
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SectionTokenCounts {
+    /// Tokens of the file contents.
+    pub files: usize,
+    /// Tokens of the source tree.
+    pub tree: usize,
+    /// Tokens of the git diffs (staged, between references, stash, against a reference and
+    /// shown commit) and diff stats.
+    pub diff: usize,
+    /// Tokens of the git log.
+    pub log: usize,
+    /// Tokens of the rest of the rendered prompt: the template text, file headers and code
+    /// fences. Only set on the counts of a rendered prompt.
+    pub template: usize,
+}
+
+impl SectionTokenCounts {
+    /// Sets the template tokens to what the sections leave of the prompt's token count.
+    fn set_total(&mut self, total_tokens: usize) {
+        self.template = total_tokens.saturating_sub(self.files + self.tree + self.diff + self.log);
+    }
+}
+
+/// The tokens of a rendered prompt, split between the file contents and the rest
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TokenBreakdown {
+    /// Tokens of the concatenated file contents, without their code fences.
+    pub file_tokens: usize,
+    /// Tokens of the rendered prompt.
+    pub total_tokens: usize,
+    /// Tokens of the template text, headers, source tree, diffs and code fences, the
+    /// difference between the two counts above.
+    pub overhead_tokens: usize,
+}
+
+impl TokenBreakdown {
+    fn new(file_tokens: usize, total_tokens: usize) -> Self {
+        Self {
+            file_tokens,
+            total_tokens,
+            overhead_tokens: total_tokens.saturating_sub(file_tokens),
+        }
+    }
+}
+
+/// The totals of the loaded files, counted without a tokenizer
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct FileStats {
+    pub files: usize,
+    pub lines: usize,
+    pub words: usize,
+    /// The size of the files in bytes.
+    pub bytes: u64,
+}
+
+/// The metadata of a loaded file, the fields of its template entry besides its code
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileMetadata {
+    pub path: String,
+    pub size_bytes: u64,
+    /// The modification time in RFC 3339, none for the files read from a git revision.
+    pub mtime: Option<String>,
+    pub extension: String,
+    /// The language of the code fence, see [`crate::language::detect_language`].
+    pub language: String,
+    /// None if the tokens weren't counted.
+    pub token_count: Option<usize>,
+    /// True if the code was cut to fit the token budget or replaced with a stub.
+    pub truncated: bool,
+}
+
+/// A file of the JSON output.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OutputFile {
+    pub path: String,
+    /// The language of the code fence, see [`crate::language::detect_language`].
+    pub language: String,
+    /// The size of the file in bytes.
+    pub size: u64,
+    /// None if the tokens weren't counted.
+    pub tokens: Option<usize>,
+    /// The code as the prompt renders it, without its code fence.
+    pub content: String,
+    /// How the content is written: the text, or the placeholder or base64 of a binary file.
+    pub encoding: FileEncoding,
+}
+
+/// The totals of the JSON output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct OutputStats {
+    #[serde(flatten)]
+    pub files: FileStats,
+    /// The tokens of the rendered prompt, none if the tokens aren't counted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<usize>,
+}
+
+/// A line of the NDJSON output, tagged with its `type`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NdjsonRecord {
+    /// The first record.
+    Meta {
+        directory: String,
+        /// The time of the output, in RFC 3339.
+        generated_at: String,
+        config: NdjsonConfig,
+        tree: Option<TreeNode>,
+    },
+    File(OutputFile),
+    /// Only if a diff, a log or diff stats were loaded.
+    Git {
+        #[serde(flatten)]
+        git: OutputGit,
+        /// The structured form of the diff, as `git_diff` of the JSON output.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        structured_diff: Option<StructuredDiff>,
+    },
+    /// The last record.
+    Stats(OutputStats),
+}
+
+/// The options of the `meta` record of the NDJSON output.
+#[derive(Debug, Clone, Serialize)]
+pub struct NdjsonConfig {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub encoding: String,
+    pub token_count_mode: TokenCountMode,
+    pub line_numbers: bool,
+    pub no_codeblock: bool,
+    pub full_directory_tree: bool,
+}
+
+/// The git sections of the JSON output, each left out unless it was loaded.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct OutputGit {
+    /// The first diff loaded, of the index, between references, of a stash, against a
+    /// reference or of a commit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<DiffStats>,
+}
+
+/// The effective configuration and results of a session, exposed to the templates as
+/// `flags` to render sections conditionally
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TemplateFlags {
+    /// A source tree was generated, isn't empty and isn't left out with `no_tree`.
+    pub has_tree: bool,
+    /// At least one file was loaded.
+    pub has_files: bool,
+    /// At least one file was loaded and the contents aren't left out with `no_contents`.
+    pub has_contents: bool,
+    /// A git diff with changes was loaded: of the index, between references, of a stash,
+    /// against a reference or of a shown commit, or a diff stat with files.
+    pub has_diff: bool,
+    /// At least one git diff was requested and none has changes, e.g. the diff is
+    /// `"no diff between HEAD and index"`.
+    pub diff_empty: bool,
+    /// A git log with at least one commit was loaded.
+    pub has_log: bool,
+    pub line_numbers: bool,
+    pub absolute_paths: bool,
+    pub full_directory_tree: bool,
+    /// The file contents are wrapped in code blocks.
+    pub code_blocks: bool,
+    /// The token counts are estimated from the length of the text.
+    pub token_count_estimated: bool,
 }
 
 /// Encapsulates the final rendered prompt and some metadata
@@ -39,6 +283,14 @@ pub struct RenderedPrompt {
     pub token_count: usize,
     pub model_info: &'static str,
     pub files: Vec<String>,
+    /// The estimated input cost of the prompt for each of the configured `cost_models`.
+    pub cost_estimates: Vec<CostEstimate>,
+    pub token_breakdown: TokenBreakdown,
+    /// The tokens of each section of the prompt.
+    pub section_tokens: SectionTokenCounts,
+    /// The tokens the prompt would have taken with the tree and the file contents, when
+    /// `no_tree` or `no_contents` left one out and the tokens are counted.
+    pub full_token_count: Option<usize>,
 }
 
 impl Code2PromptSession {
@@ -48,6 +300,7 @@ impl Code2PromptSession {
             config,
             selected_files: Vec::new(),
             data: SessionData::default(),
+            priority: None,
         }
     }
 
@@ -84,28 +337,192 @@ impl Code2PromptSession {
     }
 
     /// Loads the codebase data (source tree and file list) into the session.
+    ///
+    /// With `modified_since`, only the changed files are loaded and the files removed since
+    /// then are listed in `removed_files`.
     pub fn load_codebase(&mut self) -> Result<()> {
-        let (tree, files_json) =
-            traverse_directory(&self.config).with_context(|| "Failed to traverse directory")?;
+        self.config.encoding.load()?;
+        let mut selection: Option<HashSet<PathBuf>> = None;
+        if let Some(since) = &self.config.modified_since {
+            let changed_files = files_changed_since(&self.config.path, since)?;
+            let file_matcher = self.config.file_matcher();
+            self.data.removed_files = Some(
+                changed_files
+                    .removed
+                    .iter()
+                    .filter(|path| file_matcher.is_match(path))
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .collect(),
+            );
+            selection = Some(changed_files.changed);
+        }
+        if self.config.changed_files_only {
+            let Some((from, to)) = &self.config.diff_branches else {
+                bail!("Only including the changed files needs the two references of a branch diff");
+            };
+            let stats = get_git_diff_stats(&self.config.path, from, to, &self.diff_config())?;
+            let changed: HashSet<PathBuf> = stats
+                .files
+                .iter()
+                .map(|file| PathBuf::from(&file.path))
+                .collect();
+            selection = Some(match selection {
+                Some(selection) => selection.intersection(&changed).cloned().collect(),
+                None => changed,
+            });
+            self.data.git_diff_stat = Some(self.label_diff_stats(stats));
+        }
+        let priority = build_globset(&self.config.priority_patterns);
+        let (tree, files_json, file_warnings) =
+            traverse_selected_files(&self.config, selection.as_ref(), &priority)
+                .with_context(|| "Failed to traverse directory")?;
 
-        self.data.source_tree = Some(tree);
+        self.data.source_tree = Some(tree.to_string());
+        let root = self
+            .config
+            .path
+            .canonicalize()
+            .unwrap_or_else(|_| self.config.path.clone());
+        self.data.tree = Some(TreeNode::from_tree(&tree, &self.config.path_style, &root));
         self.data.files = Some(serde_json::Value::Array(files_json));
+        self.data.file_warnings = file_warnings;
+        self.priority = Some(priority);
 
         Ok(())
     }
 
+    /// Writes the paths of the diff statistics without the prefix of a `strip:` path style.
+    /// With the other styles, they stay relative to the repository like the paths of the
+    /// diffs, which are kept as git writes them so that the diffs still apply.
+    fn label_diff_stats(&self, mut stats: DiffStats) -> DiffStats {
+        if let PathStyle::Strip(_) = self.config.path_style {
+            let label =
+                |path: &str| display_path(&self.config.path_style, Path::new(""), Path::new(path));
+            for file in &mut stats.files {
+                file.path = label(&file.path);
+                file.old_path = file.old_path.as_deref().map(label);
+            }
+        }
+        stats
+    }
+
     /// Loads the Git diff into the session data.
     pub fn load_git_diff(&mut self) -> Result<()> {
-        let diff = get_git_diff(&self.config.path)?;
-        self.data.git_diff = Some(diff);
-        Ok(())
+        let diff = get_git_diff(&self.config.path, &self.diff_config())?;
+        self.data.git_diff = Some(self.limit_diff_tokens(diff));
+        self.load_git_diff_structured(DiffTarget::Staged)
     }
 
-    /// Loads the Git diff between two branches into the session data.
+    /// Loads the Git diff between two references (branches, tags or commits) into the session data.
+    ///
+    /// When `diff_stat` is enabled, only the per-file statistics are loaded. The relationship
+    /// of both references (merge base, ahead/behind counts) is loaded either way.
     pub fn load_git_diff_between_branches(&mut self) -> Result<()> {
         if let Some((b1, b2)) = &self.config.diff_branches {
-            let diff = get_git_diff_between_branches(&self.config.path, b1, b2)?;
-            self.data.git_diff_branch = Some(diff);
+            self.data.git_relationship = Some(get_branch_relationship(&self.config.path, b1, b2)?);
+            if self.config.diff_stat {
+                let stats = get_git_diff_stats(&self.config.path, b1, b2, &self.diff_config())?;
+                self.data.git_diff_stat = Some(self.label_diff_stats(stats));
+            } else {
+                let diff =
+                    get_git_diff_between_refs(&self.config.path, b1, b2, &self.diff_config())?;
+                self.data.git_diff_branch = Some(self.limit_diff_tokens(diff));
+            }
+            let (b1, b2) = (b1.clone(), b2.clone());
+            self.load_git_diff_structured(DiffTarget::Refs(&b1, &b2))?;
+        }
+        Ok(())
+    }
+
+    /// Loads the diff and the log between the two most recent tags into the session data.
+    ///
+    /// With a single tag, the range goes from it to `HEAD` and the diff starts with a note
+    /// saying so.
+    pub fn load_git_diff_latest_tags(&mut self) -> Result<()> {
+        if self.config.diff_latest_tags {
+            let range = get_latest_tags_range(&self.config.path)?;
+            let (from, to) = (range.from.as_str(), range.to.as_str());
+            self.data.git_relationship =
+                Some(get_branch_relationship(&self.config.path, from, to)?);
+            let diff = get_git_diff_between_refs(&self.config.path, from, to, &self.diff_config())?;
+            self.data.git_diff_branch = Some(format!(
+                "{}{}",
+                range.note.as_deref().unwrap_or_default(),
+                self.limit_diff_tokens(diff)
+            ));
+            self.load_git_diff_structured(DiffTarget::Refs(from, to))?;
+
+            let log = get_git_log_commits(&self.config.path, from, to, &self.config.log_options)?;
+            self.data.git_log_branch = Some(format_git_log(&log, &self.config.log_options));
+            self.data.git_log_commits = Some(log.commits);
+        }
+        Ok(())
+    }
+
+    /// Loads the diff of a stash entry into the session data.
+    pub fn load_git_diff_stash(&mut self) -> Result<()> {
+        if let Some(stash_index) = self.config.diff_stash {
+            let diff = get_git_stash_diff(&self.config.path, stash_index, &self.diff_config())?;
+            self.data.git_diff_stash = Some(self.limit_diff_tokens(diff));
+            self.load_git_diff_structured(DiffTarget::Stash(stash_index))?;
+        }
+        Ok(())
+    }
+
+    /// Loads the diff between a reference and the working tree into the session data.
+    pub fn load_git_diff_against(&mut self) -> Result<()> {
+        if let Some(reference) = self.config.diff_against.clone() {
+            let diff =
+                get_git_diff_workdir_to_ref(&self.config.path, &reference, &self.diff_config())?;
+            self.data.git_diff_against = Some(self.limit_diff_tokens(diff));
+            self.load_git_diff_structured(DiffTarget::WorkdirToRef(&reference))?;
+        }
+        Ok(())
+    }
+
+    /// Loads the message and diff of a single commit into the session data.
+    ///
+    /// When `diff_stat` is enabled, the diff is replaced by its per-file statistics.
+    pub fn load_git_show(&mut self) -> Result<()> {
+        if let Some(rev) = self.config.show_commit.clone() {
+            let diff = if self.config.diff_stat {
+                get_commit_diff_stat(&self.config.path, &rev, &self.diff_config())?
+            } else {
+                self.limit_diff_tokens(get_commit_diff(
+                    &self.config.path,
+                    &rev,
+                    &self.diff_config(),
+                )?)
+            };
+            self.data.git_show = Some(diff);
+            self.load_git_diff_structured(DiffTarget::Commit(&rev))?;
+        }
+        Ok(())
+    }
+
+    /// Truncates a diff to the configured `diff_token_limit`, if any.
+    fn limit_diff_tokens(&self, diff: String) -> String {
+        match self.config.diff_token_limit {
+            Some(token_limit) => {
+                truncate_diff(&diff, token_limit, |text| self.config.count_tokens(text))
+            }
+            None => diff,
+        }
+    }
+
+    /// Loads the structured form of a diff when the output format is JSON, YAML or NDJSON.
+    ///
+    /// Only the first diff loaded is kept, so the staged diff takes precedence over the
+    /// diff between references, the stash diff, the diff against a reference and the shown
+    /// commit, in that order.
+    fn load_git_diff_structured(&mut self, target: DiffTarget) -> Result<()> {
+        if matches!(
+            self.config.output_format,
+            OutputFormat::Json | OutputFormat::Yaml | OutputFormat::Ndjson
+        ) && self.data.git_diff_structured.is_none()
+        {
+            let diff = get_git_diff_structured(&self.config.path, target, &self.diff_config())?;
+            self.data.git_diff_structured = Some(diff);
         }
         Ok(())
     }
@@ -113,25 +530,934 @@ impl Code2PromptSession {
     /// Loads the Git log between two branches into the session data.
     pub fn load_git_log_between_branches(&mut self) -> Result<()> {
         if let Some((b1, b2)) = &self.config.log_branches {
-            let log_output = get_git_log(&self.config.path, b1, b2)?;
-            self.data.git_log_branch = Some(log_output);
+            let log = get_git_log_commits(&self.config.path, b1, b2, &self.config.log_options)?;
+            self.data.git_log_branch = Some(format_git_log(&log, &self.config.log_options));
+            self.data.git_log_commits = Some(log.commits);
+        }
+        Ok(())
+    }
+
+    /// Loads the Git log for a revision range into the session data.
+    pub fn load_git_log_range(&mut self) -> Result<()> {
+        if let Some(range) = &self.config.log_range {
+            let log =
+                get_git_log_range_commits(&self.config.path, range, &self.config.log_options)?;
+            self.data.git_log_branch = Some(format_git_log(&log, &self.config.log_options));
+            self.data.git_log_commits = Some(log.commits);
+        }
+        Ok(())
+    }
+
+    /// Loads the conventional changelog between two references into the session data.
+    pub fn load_git_changelog(&mut self) -> Result<()> {
+        if let Some((from, to)) = &self.config.changelog_range {
+            let changelog = get_conventional_changelog(&self.config.path, from, to)?;
+            self.data.git_changelog = Some(changelog);
+        }
+        Ok(())
+    }
+
+    /// Loads the most active contributors into the session data.
+    ///
+    /// The walk covers `log_range` if set, and HEAD's history otherwise.
+    pub fn load_contributors(&mut self) -> Result<()> {
+        if self.config.contributors_enabled {
+            let contributors = get_contributors(
+                &self.config.path,
+                self.config
+                    .contributors_max
+                    .unwrap_or(DEFAULT_CONTRIBUTORS_COUNT),
+                self.config.log_range.as_deref(),
+                self.config
+                    .contributors_commit_limit
+                    .unwrap_or(DEFAULT_CONTRIBUTORS_COMMIT_LIMIT),
+            )?;
+            self.data.contributors = Some(contributors);
+        }
+        Ok(())
+    }
+
+    /// Returns the diff configuration to use for this session.
+    ///
+    /// Unless explicit pathspecs are configured, diffs are filtered through the same
+    /// include/exclude matcher as the files of the prompt.
+    fn diff_config(&self) -> DiffConfig {
+        let mut diff_config = self.config.diff_config.clone();
+        diff_config.include_submodules |= self.config.include_submodules;
+        if diff_config.pathspecs.is_empty() {
+            let file_matcher = self.config.file_matcher();
+            if !file_matcher.is_empty() {
+                diff_config.path_filter = Some(file_matcher);
+            }
+        }
+        diff_config
+    }
+
+    /// Omits or cuts files so that the rendered prompt fits in the configured `token_budget`.
+    ///
+    /// Call it once everything else is loaded, since the source tree, git data and template
+    /// take their share of the budget first. The files are cut with the configured
+    /// `budget_strategy`, and the omitted files are listed in `token_budget` along with the
+    /// token counts before and after. If the prompt without any file already exceeds the
+    /// budget, every file is omitted.
+    pub fn enforce_token_budget(&mut self) -> Result<()> {
+        let Some(budget) = self.config.token_budget else {
+            return Ok(());
+        };
+        if self.config.token_count_mode == TokenCountMode::Off {
+            anyhow::bail!("The token budget needs the tokens to be counted or estimated");
+        }
+        let Some(files) = self
+            .data
+            .files
+            .as_ref()
+            .and_then(|files_json| files_json.as_array())
+            .cloned()
+        else {
+            return Ok(());
+        };
+
+        let tokens_before = self.render_prompt(&self.build_template_data())?.token_count;
+        let priority = self
+            .priority
+            .clone()
+            .unwrap_or_else(|| build_globset(&self.config.priority_patterns));
+        let mut report = BudgetReport {
+            budget,
+            tokens_before,
+            tokens_after: tokens_before,
+            priority_files: files
+                .iter()
+                .filter_map(|file| file.get("path").and_then(|path| path.as_str()))
+                .filter(|path| priority.is_match(path))
+                .count(),
+            ..Default::default()
+        };
+        if tokens_before <= budget {
+            self.data.token_budget = Some(report);
+            return Ok(());
+        }
+
+        self.data.files = Some(serde_json::json!([]));
+        report.overhead = self.render_prompt(&self.build_template_data())?.token_count;
+        if report.overhead_exceeds_budget() {
+            log::warn!(
+                "The prompt takes {} tokens without any file, over the budget of {}; every file is omitted",
+                report.overhead,
+                budget
+            );
+        }
+
+        let count = |text: &str| self.config.count_tokens(text);
+        let mut available = budget.saturating_sub(report.overhead);
+        loop {
+            let (kept, omitted) = fit_files(
+                &files,
+                available,
+                self.config.budget_strategy,
+                &priority,
+                count,
+            );
+            let kept_tokens: usize = kept
+                .iter()
+                .filter_map(|file| file.get("token_count").and_then(|t| t.as_u64()))
+                .map(|t| t as usize)
+                .sum();
+            self.data.files = Some(serde_json::Value::Array(kept));
+            report.omitted = omitted;
+            self.data.token_budget = Some(report.clone());
+
+            // File headers and the list of omitted files take tokens too, so cut further
+            // until the rendered prompt fits
+            report.tokens_after = self.render_prompt(&self.build_template_data())?.token_count;
+            if report.tokens_after <= budget || kept_tokens == 0 {
+                break;
+            }
+            available = kept_tokens
+                .min(available)
+                .saturating_sub(report.tokens_after - budget);
         }
+        self.data.token_budget = Some(report);
         Ok(())
     }
 
+    /// Splits the prompt into parts that each fit in a context window of `context_window`
+    /// tokens, cutting only between files.
+    ///
+    /// Every part renders the whole template with its share of the files and a `part` marker
+    /// (`part.index` of `part.count`), so the source tree and the other sections are repeated
+    /// in each part. A file that alone exceeds the context window gets a part of its own,
+    /// flagged as `oversized`.
+    pub fn split_prompt(&self, context_window: usize) -> Result<Vec<PromptPart>> {
+        let files = self
+            .data
+            .files
+            .as_ref()
+            .and_then(|files_json| files_json.as_array())
+            .cloned()
+            .unwrap_or_default();
+        let render_part = |part_files: Vec<serde_json::Value>, index: usize, count: usize| {
+            let mut session = self.clone();
+            session.data.files = Some(serde_json::Value::Array(part_files));
+            let mut template_data = session.build_template_data();
+            template_data["part"] = serde_json::json!({ "index": index, "count": count });
+            session.render_prompt(&template_data)
+        };
+
+        let max_parts = files.len().max(1);
+        let overhead = render_part(Vec::new(), max_parts, max_parts)?.token_count;
+        let mut available = context_window.saturating_sub(overhead);
+        loop {
+            let groups = split_files(&files, available);
+            let count = groups.len();
+            let mut parts = Vec::with_capacity(count);
+            for (index, group) in groups.into_iter().enumerate() {
+                let single_file = group.len() == 1;
+                let rendered = render_part(group, index + 1, count)?;
+                parts.push(PromptPart {
+                    index: index + 1,
+                    count,
+                    oversized: single_file && rendered.token_count > context_window,
+                    prompt: rendered.prompt,
+                    token_count: rendered.token_count,
+                    files: rendered.files,
+                });
+            }
+
+            // File headers take tokens too, so split further until every part fits
+            let excess = parts
+                .iter()
+                .filter(|part| part.files.len() > 1 && part.token_count > context_window)
+                .map(|part| part.token_count - context_window)
+                .max();
+            match excess {
+                Some(excess) if available > 0 => available = available.saturating_sub(excess),
+                _ => {
+                    for part in parts.iter().filter(|part| part.oversized) {
+                        log::warn!(
+                            "{} alone takes {} tokens, over the context window of {}; it is part {} of {}",
+                            part.files[0],
+                            part.token_count,
+                            context_window,
+                            part.index,
+                            part.count
+                        );
+                    }
+                    return Ok(parts);
+                }
+            }
+        }
+    }
+
+    /// Splits the prompt into chunks of at most `size` tokens or characters, cutting only
+    /// between files, e.g. to paste a prompt into a chat in several messages.
+    ///
+    /// Every chunk renders the template with its share of the files and a `part` marker. The
+    /// source tree and the git sections are only in the first chunk, unless `repeat_context`
+    /// is set: the next ones have a `part.context_omitted` flag instead, which the default
+    /// templates render as `Part 2 of 5, see part 1 for the tree`.
+    /// A file that doesn't fit in a chunk alone is cut into chunks of its own, with a
+    /// continuation marker, see [`chunk_files`].
+    pub fn chunk_prompt(&self, size: ChunkSize, repeat_context: bool) -> Result<Vec<PromptPart>> {
+        let files = self
+            .data
+            .files
+            .as_ref()
+            .and_then(|files_json| files_json.as_array())
+            .cloned()
+            .unwrap_or_default();
+        let measure = |text: &str| match size {
+            ChunkSize::Tokens(_) => self.config.count_tokens(text),
+            ChunkSize::Chars(_) => text.chars().count(),
+        };
+        let file_size = |file: &serde_json::Value| {
+            let code = file
+                .get("code")
+                .and_then(|code| code.as_str())
+                .unwrap_or("");
+            match (size, file.get("token_count").and_then(|t| t.as_u64())) {
+                (ChunkSize::Tokens(_), Some(token_count)) => token_count as usize,
+                _ => measure(code),
+            }
+        };
+        let render_chunk = |chunk_files: Vec<serde_json::Value>, index: usize, count: usize| {
+            let mut session = self.clone();
+            if index > 1 && !repeat_context {
+                session.data.clear_context();
+            }
+            session.data.files = Some(serde_json::Value::Array(chunk_files));
+            let mut template_data = session.build_template_data();
+            template_data["part"] = serde_json::json!({
+                "index": index,
+                "count": count,
+                "context_omitted": index > 1 && !repeat_context,
+            });
+            session.render_prompt(&template_data)
+        };
+
+        let max_parts = files.len().max(2);
+        let overhead = |index: usize| -> Result<usize> {
+            Ok(measure(&render_chunk(Vec::new(), index, max_parts)?.prompt))
+        };
+        let (first_overhead, rest_overhead) = (overhead(1)?, overhead(2)?);
+        let mut margin = 0;
+        loop {
+            let first = size.limit().saturating_sub(first_overhead + margin);
+            let rest = size.limit().saturating_sub(rest_overhead + margin);
+            let chunks = chunk_files(
+                &files,
+                first,
+                rest,
+                self.config.no_codeblock,
+                file_size,
+                measure,
+            );
+            let count = chunks.len();
+            let mut parts = Vec::with_capacity(count);
+            // The files too large for a chunk that can't be cut between their lines
+            let mut uncut = Vec::with_capacity(count);
+            for (index, mut chunk) in chunks.into_iter().enumerate() {
+                // The pieces of a file are counted again
+                for file in chunk
+                    .iter_mut()
+                    .filter(|file| file.get("continued").is_some())
+                {
+                    let code = file["code"].as_str().unwrap_or("").to_string();
+                    if file.get("token_count").is_some_and(|t| !t.is_null()) {
+                        file["token_count"] = serde_json::json!(self.config.count_tokens(&code));
+                    }
+                }
+                let piece = chunk.len() == 1 && chunk[0].get("continued").is_some();
+                let available = if index == 0 { first } else { rest };
+                uncut.push(chunk.len() == 1 && !piece && file_size(&chunk[0]) > available);
+                let oversized = piece || uncut[index];
+                let rendered = render_chunk(chunk, index + 1, count)?;
+                parts.push(PromptPart {
+                    index: index + 1,
+                    count,
+                    oversized,
+                    token_count: rendered.token_count,
+                    files: rendered.files,
+                    prompt: rendered.prompt,
+                });
+            }
+
+            // The file headers take room too, so cut further until every chunk fits
+            let excess = parts
+                .iter()
+                .zip(&uncut)
+                .filter(|(_, uncut)| !**uncut)
+                .map(|(part, _)| measure(&part.prompt).saturating_sub(size.limit()))
+                .max()
+                .filter(|excess| *excess > 0);
+            match excess {
+                Some(excess) if first.min(rest) > excess => margin += excess,
+                _ => return Ok(parts),
+            }
+        }
+    }
+
+    /// Renders one prompt per top-level directory of the codebase, e.g. for the services of a
+    /// monorepo, each with the tree and the files of its directory only. The files at the root
+    /// are in a [`ROOT_DIRECTORY_NAME`] prompt, and a directory without any file of the prompt,
+    /// e.g. once filtered by the include patterns, has none.
+    ///
+    /// The files are grouped from the loaded ones, so that the codebase is only traversed once.
+    /// The git sections are in every prompt.
+    pub fn split_by_directory(&self) -> Result<Vec<DirectoryPrompt>> {
+        let Some(tree) = &self.data.tree else {
+            bail!("Splitting the prompt by directory needs the codebase to be loaded");
+        };
+        let files = self
+            .data
+            .files
+            .as_ref()
+            .and_then(|files_json| files_json.as_array())
+            .cloned()
+            .unwrap_or_default();
+        // The root with the given entries only
+        let root_with = |children: Vec<TreeNode>| TreeNode {
+            name: tree.name.clone(),
+            path: tree.path.clone(),
+            is_dir: true,
+            depth: 0,
+            tokens: 0,
+            files: 0,
+            children,
+        };
+
+        let mut groups = vec![(
+            ROOT_DIRECTORY_NAME.to_string(),
+            root_with(
+                tree.children
+                    .iter()
+                    .filter(|c| !c.is_dir)
+                    .cloned()
+                    .collect(),
+            ),
+            HashSet::new(),
+        )];
+        let mut in_directories = HashSet::new();
+        for directory in tree.children.iter().filter(|child| child.is_dir) {
+            let mut paths = HashSet::new();
+            directory.collect_file_paths(&mut paths);
+            in_directories.extend(paths.iter().cloned());
+            groups.push((
+                directory.name.replace(['/', '\\'], "_"),
+                root_with(vec![directory.clone()]),
+                paths,
+            ));
+        }
+        // The files missing from the tree are left at the root
+        groups[0].2 = files
+            .iter()
+            .filter_map(|file| file.get("path")?.as_str())
+            .filter(|path| !in_directories.contains(*path))
+            .map(str::to_string)
+            .collect();
+
+        let mut prompts = Vec::new();
+        for (name, node, paths) in groups {
+            let group_files: Vec<serde_json::Value> = files
+                .iter()
+                .filter(|file| {
+                    file.get("path")
+                        .and_then(|path| path.as_str())
+                        .is_some_and(|path| paths.contains(path))
+                })
+                .cloned()
+                .collect();
+            if group_files.is_empty() {
+                continue;
+            }
+            let mut session = self.clone();
+            session.data.source_tree = Some(node.to_source_tree());
+            session.data.tree = Some(node);
+            session.data.files = Some(serde_json::Value::Array(group_files));
+            let rendered = session.render_prompt(&session.build_template_data())?;
+            prompts.push(DirectoryPrompt {
+                name,
+                prompt: rendered.prompt,
+                token_count: rendered.token_count,
+                files: rendered.files,
+            });
+        }
+        Ok(prompts)
+    }
+
+    /// Counts the tokens of each loaded section with the configured tokenizer.
+    ///
+    /// The counts cover the raw section contents, not the surrounding template, so their
+    /// sum is usually a bit lower than the token count of the rendered prompt; the rendered
+    /// prompt has the rest in its `section_tokens.template`.
+    pub fn section_token_counts(&self) -> SectionTokenCounts {
+        let count = |text: &str| self.config.count_tokens(text);
+        let files = self
+            .data
+            .files
+            .as_ref()
+            .and_then(|files_json| files_json.as_array())
+            .map(|files| {
+                files
+                    .iter()
+                    .map(
+                        |file| match file.get("token_count").and_then(|t| t.as_u64()) {
+                            Some(token_count) => token_count as usize,
+                            None => file
+                                .get("code")
+                                .and_then(|code| code.as_str())
+                                .map_or(0, count),
+                        },
+                    )
+                    .sum()
+            })
+            .unwrap_or_default();
+        let diff_stat = self
+            .data
+            .git_diff_stat
+            .as_ref()
+            .map(|stats| stats.to_string());
+        let diff = [
+            self.data.git_diff.as_deref(),
+            self.data.git_diff_branch.as_deref(),
+            self.data.git_diff_stash.as_deref(),
+            self.data.git_diff_against.as_deref(),
+            self.data.git_show.as_deref(),
+            diff_stat.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        .map(count)
+        .sum();
+
+        SectionTokenCounts {
+            files,
+            tree: self.data.source_tree.as_deref().map_or(0, count),
+            diff,
+            log: self.data.git_log_branch.as_deref().map_or(0, count),
+            template: 0,
+        }
+    }
+
+    /// Returns the file contents without their code fences, one after the other.
+    fn raw_file_contents(&self) -> String {
+        let mut contents = String::new();
+        let files = self
+            .data
+            .files
+            .as_ref()
+            .and_then(|files_json| files_json.as_array());
+        for code in files
+            .into_iter()
+            .flatten()
+            .filter_map(|file| file.get("code").and_then(|code| code.as_str()))
+        {
+            for line in code_lines(code, self.config.no_codeblock).1 {
+                contents.push_str(line);
+                contents.push('\n');
+            }
+        }
+        contents
+    }
+
+    /// Returns the token count of each file by path, `None` for files that weren't counted.
+    pub fn file_token_counts(&self) -> BTreeMap<String, Option<usize>> {
+        self.data
+            .files
+            .as_ref()
+            .and_then(|files_json| files_json.as_array())
+            .map(|files| {
+                files
+                    .iter()
+                    .filter_map(|file| {
+                        let path = file.get("path")?.as_str()?.to_string();
+                        let token_count = file
+                            .get("token_count")
+                            .and_then(|t| t.as_u64())
+                            .map(|t| t as usize);
+                        Some((path, token_count))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the metadata of the loaded files, in the order of the prompt.
+    pub fn file_metadata(&self) -> Vec<FileMetadata> {
+        let files = self
+            .data
+            .files
+            .as_ref()
+            .and_then(|files_json| files_json.as_array());
+        files
+            .into_iter()
+            .flatten()
+            .filter_map(|file| {
+                let text = |key: &str| file.get(key).and_then(|value| value.as_str());
+                Some(FileMetadata {
+                    path: text("path")?.to_string(),
+                    size_bytes: file.get("size_bytes").and_then(|size| size.as_u64())?,
+                    mtime: text("mtime").map(str::to_string),
+                    extension: text("extension").unwrap_or_default().to_string(),
+                    language: text("language").unwrap_or_default().to_string(),
+                    token_count: file
+                        .get("token_count")
+                        .and_then(|t| t.as_u64())
+                        .map(|t| t as usize),
+                    truncated: file
+                        .get("truncated")
+                        .and_then(|truncated| truncated.as_bool())
+                        .unwrap_or(false),
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the loaded files as the JSON output lists them, in the order of the prompt.
+    pub fn output_files(&self) -> Vec<OutputFile> {
+        let files = self
+            .data
+            .files
+            .as_ref()
+            .and_then(|files_json| files_json.as_array());
+        files
+            .into_iter()
+            .flatten()
+            .filter_map(|file| self.output_file(file))
+            .collect()
+    }
+
+    /// Returns a loaded file as the JSON output lists it, none without a path or code.
+    fn output_file(&self, file: &serde_json::Value) -> Option<OutputFile> {
+        let text = |key: &str| file.get(key).and_then(|value| value.as_str());
+        let mut content = String::new();
+        for line in code_lines(text("code")?, self.config.no_codeblock).1 {
+            content.push_str(line);
+            content.push('\n');
+        }
+        Some(OutputFile {
+            path: text("path")?.to_string(),
+            language: text("language").unwrap_or_default().to_string(),
+            size: file
+                .get("size_bytes")
+                .and_then(|size| size.as_u64())
+                .unwrap_or_default(),
+            tokens: file
+                .get("token_count")
+                .and_then(|t| t.as_u64())
+                .map(|t| t as usize),
+            content,
+            encoding: file
+                .get("encoding")
+                .and_then(|encoding| serde_json::from_value(encoding.clone()).ok())
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Renders the HTML output, see [`crate::html::render_html`]. The tree and the files are
+    /// left out like in the template with `no_tree` and `no_contents`.
+    #[cfg(feature = "html")]
+    fn render_html(&self, generated_at: &str, token_count: Option<usize>) -> Result<String> {
+        crate::html::render_html(&crate::html::HtmlPage {
+            title: label(&self.config.path),
+            generated_at: generated_at.to_string(),
+            tree: if self.config.no_tree {
+                None
+            } else {
+                self.tree()
+            },
+            files: if self.config.no_contents {
+                Vec::new()
+            } else {
+                self.output_files()
+            },
+            diff: self.output_git().and_then(|git| git.diff),
+            stats: self.file_stats(),
+            tokens: token_count,
+        })
+    }
+
+    /// Fails: the HTML output needs the html feature.
+    #[cfg(not(feature = "html"))]
+    fn render_html(&self, _generated_at: &str, _token_count: Option<usize>) -> Result<String> {
+        bail!("the html output format needs code2prompt built with the html feature")
+    }
+
+    /// Writes the NDJSON output, serializing each record straight into the writer on a line
+    /// of its own rather than building the whole document: a `meta` record with the
+    /// directory, the configuration and the tree, a `file` record per file in the order of
+    /// the prompt, a `git` record if a diff, a log or diff stats were loaded, and the `stats`
+    /// record last. Every record has a `type` field naming it.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - Where the records are written
+    /// * `token_count` - The tokens of the prompt for the `stats` record, none if they aren't
+    ///   counted
+    pub fn write_ndjson<W: Write>(&self, mut writer: W, token_count: Option<usize>) -> Result<()> {
+        let mut write_record = |record: &NdjsonRecord| -> Result<()> {
+            serde_json::to_writer(&mut writer, record)?;
+            writer.write_all(b"\n")?;
+            Ok(())
+        };
+        write_record(&NdjsonRecord::Meta {
+            directory: label(&self.config.path),
+            generated_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            config: NdjsonConfig {
+                include: self.config.include_patterns.clone(),
+                exclude: self.config.exclude_patterns.clone(),
+                encoding: self.config.encoding.to_string(),
+                token_count_mode: self.config.token_count_mode,
+                line_numbers: self.config.line_numbers,
+                no_codeblock: self.config.no_codeblock,
+                full_directory_tree: self.config.full_directory_tree,
+            },
+            tree: self.tree(),
+        })?;
+        let files = self
+            .data
+            .files
+            .as_ref()
+            .and_then(|files_json| files_json.as_array());
+        for file in files.into_iter().flatten() {
+            if let Some(file) = self.output_file(file) {
+                write_record(&NdjsonRecord::File(file))?;
+            }
+        }
+        let structured_diff = self.data.git_diff_structured.clone();
+        if let Some(git) = self.output_git() {
+            write_record(&NdjsonRecord::Git {
+                git,
+                structured_diff,
+            })?;
+        }
+        write_record(&NdjsonRecord::Stats(OutputStats {
+            files: self.file_stats(),
+            tokens: token_count,
+        }))?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Returns the rows of the manifest of the prompt, a row per loaded file in the order of
+    /// the prompt then, with `include_skipped`, a row per file skipped while reading the
+    /// codebase or omitted to fit the token budget, with its skip reason.
+    pub fn manifest_rows(&self, include_skipped: bool) -> Vec<ManifestRow> {
+        let files = self
+            .data
+            .files
+            .as_ref()
+            .and_then(|files_json| files_json.as_array());
+        let mut rows: Vec<ManifestRow> = files
+            .into_iter()
+            .flatten()
+            .filter_map(|file| {
+                let text = |key: &str| file.get(key).and_then(|value| value.as_str());
+                let count = |key: &str| file.get(key).and_then(|value| value.as_u64());
+                Some(ManifestRow {
+                    path: text("path")?.to_string(),
+                    extension: text("extension").unwrap_or_default().to_string(),
+                    language: text("language").unwrap_or_default().to_string(),
+                    bytes: count("size_bytes"),
+                    lines: count("lines").map(|lines| lines as usize),
+                    tokens: count("token_count").map(|tokens| tokens as usize),
+                    truncated: file
+                        .get("truncated")
+                        .and_then(|truncated| truncated.as_bool())
+                        .unwrap_or(false),
+                    last_commit_sha: file
+                        .pointer("/last_commit/sha")
+                        .and_then(|sha| sha.as_str())
+                        .map(str::to_string),
+                    skip_reason: None,
+                })
+            })
+            .collect();
+        if include_skipped {
+            let included: HashSet<String> = rows.iter().map(|row| row.path.clone()).collect();
+            // The files converted to UTF-8 have a warning too, but they are in the prompt
+            for warning in &self.data.file_warnings {
+                if !included.contains(&warning.path) {
+                    rows.push(ManifestRow::skipped(&warning.path, &warning.message));
+                }
+            }
+            let omitted = self
+                .data
+                .token_budget
+                .iter()
+                .flat_map(|report| &report.omitted);
+            for file in omitted.filter(|file| !file.truncated) {
+                rows.push(ManifestRow {
+                    tokens: Some(file.token_count),
+                    ..ManifestRow::skipped(&file.path, BUDGET_SKIP_REASON)
+                });
+            }
+        }
+        rows
+    }
+
+    /// Returns the git sections of the JSON output, none if no diff, log or diff stats
+    /// were loaded.
+    pub fn output_git(&self) -> Option<OutputGit> {
+        let data = &self.data;
+        let git = OutputGit {
+            diff: [
+                &data.git_diff,
+                &data.git_diff_branch,
+                &data.git_diff_stash,
+                &data.git_diff_against,
+                &data.git_show,
+            ]
+            .into_iter()
+            .flatten()
+            .find(|diff| !diff.is_empty())
+            .cloned(),
+            log: data.git_log_branch.clone(),
+            stats: data.git_diff_stat.clone(),
+        };
+        (git.diff.is_some() || git.log.is_some() || git.stats.is_some()).then_some(git)
+    }
+
+    /// Returns the number of files, lines, words and bytes of the loaded files.
+    pub fn file_stats(&self) -> FileStats {
+        let files = self
+            .data
+            .files
+            .as_ref()
+            .and_then(|files_json| files_json.as_array());
+        let mut stats = FileStats::default();
+        for file in files.into_iter().flatten() {
+            let count = |key: &str| file.get(key).and_then(|value| value.as_u64()).unwrap_or(0);
+            stats.files += 1;
+            stats.lines += count("lines") as usize;
+            stats.words += count("words") as usize;
+            stats.bytes += count("size");
+        }
+        stats
+    }
+
+    /// Returns the token counts of the loaded files and of the rendered prompt, for the run
+    /// history. The files that weren't counted are left out.
+    pub fn run_summary(&self, total_tokens: usize) -> RunSummary {
+        RunSummary {
+            total_tokens,
+            files: self
+                .file_token_counts()
+                .into_iter()
+                .filter_map(|(path, token_count)| Some((path, token_count?)))
+                .collect(),
+        }
+    }
+
+    /// Builds the token map of the loaded files, limited to the configured `token_map_depth`.
+    pub fn token_map(&self) -> TokenMapNode {
+        let files = self
+            .data
+            .files
+            .as_ref()
+            .and_then(|files_json| files_json.as_array())
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let mut token_map = build_token_map(files, self.config.token_map_depth);
+        token_map.name = label(&self.config.path);
+        token_map
+    }
+
+    /// Summarizes the loaded files per directory, down to the configured `dir_summary_depth`.
+    pub fn directory_summary(&self) -> Vec<DirectorySummary> {
+        let files = self
+            .data
+            .files
+            .as_ref()
+            .and_then(|files_json| files_json.as_array())
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        summarize_directories(files, self.config.dir_summary_depth.unwrap_or(1))
+    }
+
+    /// Returns the flags of the loaded data and of the configuration, for the templates.
+    pub fn template_flags(&self) -> TemplateFlags {
+        self.section_flags(self.config.no_tree, self.config.no_contents)
+    }
+
+    /// Returns the template flags with the tree and the file contents left out or not.
+    fn section_flags(&self, no_tree: bool, no_contents: bool) -> TemplateFlags {
+        let diffs: Vec<&String> = [
+            &self.data.git_diff,
+            &self.data.git_diff_branch,
+            &self.data.git_diff_stash,
+            &self.data.git_diff_against,
+            &self.data.git_show,
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        let has_diff_stat = self
+            .data
+            .git_diff_stat
+            .as_ref()
+            .is_some_and(|stats| !stats.files.is_empty());
+        let has_diff = has_diff_stat || diffs.iter().any(|diff| !is_empty_diff(diff));
+        let has_files = self.file_stats().files > 0;
+        TemplateFlags {
+            has_tree: !no_tree
+                && self
+                    .data
+                    .source_tree
+                    .as_ref()
+                    .is_some_and(|tree| !tree.trim().is_empty()),
+            has_files,
+            has_contents: has_files && !no_contents,
+            has_diff,
+            diff_empty: !has_diff && (!diffs.is_empty() || self.data.git_diff_stat.is_some()),
+            has_log: self
+                .data
+                .git_log_commits
+                .as_ref()
+                .is_some_and(|commits| !commits.is_empty()),
+            line_numbers: self.config.line_numbers,
+            absolute_paths: self.config.path_style == PathStyle::Absolute,
+            full_directory_tree: self.config.full_directory_tree,
+            code_blocks: !self.config.no_codeblock,
+            token_count_estimated: self.config.token_count_mode == TokenCountMode::Estimate,
+        }
+    }
+
+    /// Returns the source tree as nodes, with the token counts of the files currently in the
+    /// prompt, so that the files cut or omitted to fit the token budget count as such.
+    pub fn tree(&self) -> Option<TreeNode> {
+        let mut tree = self.data.tree.clone()?;
+        // The nodes have the paths of the files, in the same path style
+        let token_counts: HashMap<String, usize> = self
+            .data
+            .files
+            .as_ref()
+            .and_then(|files| files.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|file| {
+                let path = file.get("path")?.as_str()?.to_string();
+                let tokens = file.get("token_count").and_then(|count| count.as_u64());
+                Some((path, tokens.unwrap_or_default() as usize))
+            })
+            .collect();
+        tree.count_files(&token_counts);
+        Some(tree)
+    }
+
     /// Constructs a JSON object that merges the session data and your config’s path label.
     pub fn build_template_data(&self) -> serde_json::Value {
         let mut data = serde_json::json!({
             "absolute_code_path": label(&self.config.path),
+            "generated_at": Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
             "source_tree": self.data.source_tree,
+            "tree": self.tree(),
             "files": self.data.files,
+            "removed_files": self.data.removed_files,
+            "file_warnings": self.data.file_warnings,
             "git_diff": self.data.git_diff,
             "git_diff_branch": self.data.git_diff_branch,
-            "git_log_branch": self.data.git_log_branch
+            "git_relationship": self.data.git_relationship,
+            "git_diff_stat": self.data.git_diff_stat,
+            "git_diff_stash": self.data.git_diff_stash,
+            "git_diff_against": self.data.git_diff_against,
+            "git_show": self.data.git_show,
+            "git_log_branch": self.data.git_log_branch,
+            "git_changelog": self.data.git_changelog,
+            "git_log_commits": self.data.git_log_commits,
+            "commits": self.data.git_log_commits,
+            "contributors": self.data.contributors,
+            "token_budget": self.data.token_budget,
+            "directories": self.directory_summary(),
+            "stats": self.file_stats(),
+            "dir_summary": self.config.dir_summary,
+            "token_count_estimated": self.config.token_count_mode == TokenCountMode::Estimate,
+            "flags": self.template_flags()
         });
 
-        // Add user-defined variables to the template data
-        if self.config.user_variables.len() > 0 {
+        // The sections left out are empty, and their flags are false
+        if self.config.no_tree {
+            data["source_tree"] = serde_json::Value::Null;
+            data["tree"] = serde_json::Value::Null;
+        }
+        if self.config.no_contents {
+            let files = data.get_mut("files").and_then(|files| files.as_array_mut());
+            for file in files.into_iter().flatten() {
+                if let Some(file) = file.as_object_mut() {
+                    file.remove("code");
+                }
+            }
+        }
+        if self.config.toc {
+            self.insert_toc(&mut data);
+        }
+        if self.config.file_header_format.is_some() || self.config.file_footer_format.is_some() {
+            self.insert_file_formats(&mut data);
+        }
+
+        // Add user-defined variables to the template data, under `user` and at the top level
+        data["user"] = serde_json::json!(self.config.user_variables);
+        if !self.config.user_variables.is_empty() {
             if let Some(obj) = data.as_object_mut() {
                 for (key, value) in &self.config.user_variables {
                     obj.insert(key.clone(), serde_json::Value::String(value.clone()));
@@ -142,6 +1468,65 @@ impl Code2PromptSession {
         data
     }
 
+    /// Adds the `toc` of the files of the prompt, with their path and `anchor`, and the
+    /// `anchor` of the heading of each file, to the template data of a Markdown prompt.
+    fn insert_toc(&self, data: &mut serde_json::Value) {
+        if self.config.output_format != OutputFormat::Markdown {
+            debug!(
+                "No table of contents for the {:?} output format",
+                self.config.output_format
+            );
+            return;
+        }
+        let files = data.get_mut("files").and_then(|files| files.as_array_mut());
+        let mut toc_files: Vec<&mut serde_json::Value> = files
+            .into_iter()
+            .flatten()
+            .filter(|file| file.get("code").is_some() && file.get("path").is_some())
+            .collect();
+        let paths: Vec<String> = toc_files
+            .iter()
+            .filter_map(|file| file["path"].as_str().map(str::to_string))
+            .collect();
+        let anchors = heading_anchors(paths.iter().map(String::as_str));
+        for (file, anchor) in toc_files.iter_mut().zip(&anchors) {
+            file["anchor"] = serde_json::json!(anchor);
+        }
+        data["toc"] = paths
+            .iter()
+            .zip(&anchors)
+            .map(|(path, anchor)| serde_json::json!({ "path": path, "anchor": anchor }))
+            .collect();
+    }
+
+    /// Adds the `header` and `footer` of each file of the prompt, from the file header and
+    /// footer formats, to the template data of a Markdown prompt.
+    fn insert_file_formats(&self, data: &mut serde_json::Value) {
+        if self.config.output_format != OutputFormat::Markdown {
+            debug!(
+                "No file header or footer for the {:?} output format",
+                self.config.output_format
+            );
+            return;
+        }
+        let files = data.get_mut("files").and_then(|files| files.as_array_mut());
+        let prompt_files = files
+            .into_iter()
+            .flatten()
+            .filter(|file| file.get("code").is_some());
+        for (index, file) in prompt_files.enumerate() {
+            let formats = [
+                ("header", &self.config.file_header_format),
+                ("footer", &self.config.file_footer_format),
+            ];
+            for (key, format) in formats {
+                if let Some(format) = format {
+                    file[key] = serde_json::json!(resolve_file_format(format, file, index + 1));
+                }
+            }
+        }
+    }
+
     /// Renders the final prompt given a template-data JSON object. Returns both
     /// the rendered prompt and the token count information. The session
     /// does not do any printing or user prompting — that’s up to the caller.
@@ -149,27 +1534,112 @@ impl Code2PromptSession {
         // ~~~ Template selection ~~~
         let mut template_str = self.config.template_str.clone();
         let mut template_name = self.config.template_name.clone();
+        let mut engine = self.config.template_engine;
         if self.config.template_str.is_empty() {
+            engine = TemplateEngine::Handlebars;
             template_str = match self.config.output_format {
-                OutputFormat::Markdown => include_str!("./default_template_md.hbs").to_string(),
-                OutputFormat::Xml | OutputFormat::Json => {
-                    include_str!("./default_template_xml.hbs").to_string()
+                OutputFormat::Markdown | OutputFormat::Html => {
+                    include_str!("./default_template_md.hbs").to_string()
                 }
+                OutputFormat::Xml
+                | OutputFormat::Json
+                | OutputFormat::Yaml
+                | OutputFormat::Ndjson => include_str!("./default_template_xml.hbs").to_string(),
             };
             template_name = match self.config.output_format {
-                OutputFormat::Markdown => "markdown".to_string(),
-                OutputFormat::Xml | OutputFormat::Json => "xml".to_string(),
+                OutputFormat::Markdown | OutputFormat::Html => "markdown".to_string(),
+                OutputFormat::Xml
+                | OutputFormat::Json
+                | OutputFormat::Yaml
+                | OutputFormat::Ndjson => "xml".to_string(),
             };
         }
 
+        // The options of the front-matter are applied by the caller
+        let template_str = split_front_matter(&template_str)?.1.to_string();
+
+        // ~~~ Partials ~~~
+        // The built-in partials are Handlebars ones
+        let mut partials = HashMap::new();
+        if engine == TemplateEngine::Handlebars {
+            let file_block = match self.config.output_format {
+                OutputFormat::Markdown | OutputFormat::Html => {
+                    include_str!("./default_file_block_md.hbs")
+                }
+                OutputFormat::Xml
+                | OutputFormat::Json
+                | OutputFormat::Yaml
+                | OutputFormat::Ndjson => {
+                    include_str!("./default_file_block_xml.hbs")
+                }
+            };
+            partials.insert("file_block".to_string(), file_block.to_string());
+            partials.insert(
+                "tree_node".to_string(),
+                include_str!("./default_tree_node.hbs").to_string(),
+            );
+        }
+        partials.extend(self.config.template_partials.clone());
+        if let Some(file_template) = &self.config.file_template {
+            partials.insert("file_block".to_string(), file_template.clone());
+        }
+
         // ~~~ Rendering ~~~
-        let handlebars = handlebars_setup(&template_str, &template_name)?;
-        let template_content = render_template(&handlebars, &template_name, template_data)?;
+        let renderer = renderer(
+            engine,
+            &template_str,
+            &template_name,
+            &partials,
+            &RenderOptions {
+                includes: self.config.include_options.clone(),
+                escape_html: self.config.escape_html,
+                formats: self.config.format_options.clone(),
+            },
+        )?;
+        let mut template_content = renderer.render(template_data)?;
 
         // ~~~ Informations ~~~
-        let tokenizer_type: TokenizerType = self.config.encoding;
-        let token_count = count_tokens(&template_content, &tokenizer_type);
+        let tokenizer_type: &TokenizerType = &self.config.encoding;
+        let mut token_count = self.config.count_tokens(&template_content);
+        let file_tokens = self.config.count_tokens(&self.raw_file_contents());
+        let mut token_breakdown = TokenBreakdown::new(file_tokens, token_count);
+        let mut section_tokens = self.section_token_counts();
+        section_tokens.set_total(token_count);
+        // A template showing the breakdowns sees the counts of the prompt rendered without them
+        if template_str.contains("token_breakdown") || template_str.contains("section_tokens") {
+            let mut template_data = template_data.clone();
+            template_data["token_breakdown"] = serde_json::to_value(token_breakdown)?;
+            template_data["section_tokens"] = serde_json::to_value(section_tokens)?;
+            template_content = renderer.render(&template_data)?;
+            token_count = self.config.count_tokens(&template_content);
+            token_breakdown = TokenBreakdown::new(file_tokens, token_count);
+            section_tokens.set_total(token_count);
+        }
+        // What the prompt would have taken with the sections left out, rendered the same way
+        let counted = self.config.token_count_mode != TokenCountMode::Off;
+        let full_token_count = if counted && (self.config.no_tree || self.config.no_contents) {
+            let mut full_data = template_data.clone();
+            full_data["source_tree"] = serde_json::json!(self.data.source_tree);
+            full_data["tree"] = serde_json::to_value(self.tree())?;
+            full_data["files"] = serde_json::json!(self.data.files);
+            full_data["flags"] = serde_json::to_value(self.section_flags(false, false))?;
+            Some(self.config.count_tokens(&renderer.render(&full_data)?))
+        } else {
+            None
+        };
         let model_info = tokenizer_type.description();
+        let cost_estimates = if self.config.cost_models.is_empty() {
+            Vec::new()
+        } else {
+            let pricing = PricingTable::load(self.config.pricing_file.as_deref())?;
+            self.config
+                .cost_models
+                .iter()
+                .map(|model| {
+                    pricing.estimate(&template_content, model, self.config.token_count_mode)
+                })
+                .collect::<Result<Vec<_>>>()?
+        };
         let directory_name = label(&self.config.path);
         let files: Vec<String> = self
             .data
@@ -190,25 +1660,78 @@ impl Code2PromptSession {
 
         // ~~~ Final output format ~~~
         let final_output = match self.config.output_format {
-            OutputFormat::Json => {
-                let json_data = serde_json::json!({
+            OutputFormat::Json | OutputFormat::Yaml => {
+                let mut json_data = serde_json::json!({
+                    "directory": directory_name.clone(),
+                    "generated_at": template_data.get("generated_at"),
                     "prompt": template_content,
                     "directory_name": directory_name.clone(),
                     "token_count": token_count,
+                    "encoding": tokenizer_type.to_string(),
+                    "token_count_mode": self.config.token_count_mode,
                     "model_info": model_info,
-                    "files": files.clone(),
+                    "files": self.output_files(),
+                    "tree": self.tree(),
+                    "file_token_counts": self.file_token_counts(),
+                    "file_metadata": self.file_metadata(),
+                    "token_breakdown": token_breakdown,
+                    "section_tokens": section_tokens,
+                    "stats": OutputStats {
+                        files: self.file_stats(),
+                        tokens: (self.config.token_count_mode != TokenCountMode::Off)
+                            .then_some(token_count),
+                    },
+                    "file_warnings": self.data.file_warnings,
                 });
-                serde_json::to_string_pretty(&json_data)?
+                if let Some(git) = self.output_git() {
+                    json_data["git"] = serde_json::to_value(git)?;
+                }
+                if !cost_estimates.is_empty() {
+                    json_data["cost_estimates"] = serde_json::to_value(&cost_estimates)?;
+                }
+                json_data["directories"] = serde_json::to_value(self.directory_summary())?;
+                if self.config.token_map_enabled {
+                    json_data["token_map"] = serde_json::to_value(self.token_map())?;
+                }
+                if let Some(report) = &self.data.token_budget {
+                    json_data["token_budget"] = serde_json::to_value(report)?;
+                }
+                if let Some(structured_diff) = &self.data.git_diff_structured {
+                    json_data["git_diff"] = serde_json::to_value(structured_diff)?;
+                }
+                // The multi-line strings, like the file contents, are literal blocks in YAML
+                match (&self.config.output_format, self.config.json_compact) {
+                    (OutputFormat::Yaml, _) => serde_yaml::to_string(&json_data)?,
+                    (_, true) => serde_json::to_string(&json_data)?,
+                    (_, false) => serde_json::to_string_pretty(&json_data)?,
+                }
+            }
+            OutputFormat::Ndjson => {
+                let mut records = Vec::new();
+                let tokens =
+                    (self.config.token_count_mode != TokenCountMode::Off).then_some(token_count);
+                self.write_ndjson(&mut records, tokens)?;
+                String::from_utf8(records)?
+            }
+            OutputFormat::Html => {
+                let generated_at = template_data["generated_at"].as_str().unwrap_or_default();
+                let tokens =
+                    (self.config.token_count_mode != TokenCountMode::Off).then_some(token_count);
+                self.render_html(generated_at, tokens)?
             }
             _ => template_content,
         };
 
         Ok(RenderedPrompt {
             prompt: final_output,
-            directory_name: directory_name,
+            directory_name,
             token_count,
             model_info,
-            files: files,
+            files,
+            cost_estimates,
+            token_breakdown,
+            section_tokens,
+            full_token_count,
         })
     }
 
@@ -231,6 +1754,25 @@ impl Code2PromptSession {
             }
         }
 
+        // ~~~ Load Git info between the latest tags ~~~
+        if self.config.diff_latest_tags {
+            match self.load_git_diff_latest_tags() {
+                Ok(_) => {}
+                Err(e) => log::warn!(
+                    "Git diff between the latest tags could not be loaded: {}",
+                    e
+                ),
+            }
+        }
+
+        // ~~~ Load Git stash diff ~~~
+        if self.config.diff_stash.is_some() {
+            match self.load_git_diff_stash() {
+                Ok(_) => {}
+                Err(e) => log::warn!("Git stash diff could not be loaded: {}", e),
+            }
+        }
+
         // ~~~ Load Git log between branches ~~~
         if self.config.log_branches.is_some() {
             match self.load_git_log_between_branches() {
@@ -238,6 +1780,31 @@ impl Code2PromptSession {
                 Err(e) => log::warn!("Git branch log could not be loaded: {}", e),
             }
         }
+
+        // ~~~ Load Git log for a revision range ~~~
+        if self.config.log_range.is_some() {
+            match self.load_git_log_range() {
+                Ok(_) => {}
+                Err(e) => log::warn!("Git log range could not be loaded: {}", e),
+            }
+        }
+
+        // ~~~ Load conventional changelog ~~~
+        if self.config.changelog_range.is_some() {
+            match self.load_git_changelog() {
+                Ok(_) => {}
+                Err(e) => log::warn!("Git changelog could not be loaded: {}", e),
+            }
+        }
+
+        // ~~~ Load contributors ~~~
+        if self.config.contributors_enabled {
+            match self.load_contributors() {
+                Ok(_) => {}
+                Err(e) => log::warn!("Contributors could not be loaded: {}", e),
+            }
+        }
+        self.enforce_token_budget()?;
         let template_data = self.build_template_data();
         let rendered = self.render_prompt(&template_data)?;
         Ok(rendered)
@@ -1,18 +1,23 @@
 //! This module provides sorting methods for files and directory trees.
 
+use globset::GlobSet;
 use serde_json::Value;
+use std::cmp::Ordering;
+use std::path::Path;
 use std::str::FromStr;
 use termtree::Tree;
 
-///! Sorting methods for files.
-
 // Define the available sort methods.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FileSortMethod {
-    NameAsc,  // Sort files alphabetically (A → Z)
-    NameDesc, // Sort files alphabetically in reverse (Z → A)
-    DateAsc,  // Sort files by modification date (oldest first)
-    DateDesc, // Sort files by modification date (newest first)
+    NameAsc,    // Sort files alphabetically (A → Z)
+    NameDesc,   // Sort files alphabetically in reverse (Z → A)
+    DateAsc,    // Sort files by modification date (oldest first)
+    DateDesc,   // Sort files by modification date (newest first)
+    TokensAsc,  // Sort files by token count (smallest first)
+    TokensDesc, // Sort files by token count (largest first)
+    SizeAsc,    // Sort files by size in bytes (smallest first)
+    SizeDesc,   // Sort files by size in bytes (largest first)
 }
 
 impl FromStr for FileSortMethod {
@@ -24,8 +29,12 @@ impl FromStr for FileSortMethod {
             "name_desc" => Ok(FileSortMethod::NameDesc),
             "date_asc" => Ok(FileSortMethod::DateAsc),
             "date_desc" => Ok(FileSortMethod::DateDesc),
+            "tokens_asc" => Ok(FileSortMethod::TokensAsc),
+            "tokens_desc" => Ok(FileSortMethod::TokensDesc),
+            "size_asc" => Ok(FileSortMethod::SizeAsc),
+            "size_desc" => Ok(FileSortMethod::SizeDesc),
             _ => Err(format!(
-                "Invalid sort method: {}. Supported values: name_asc, name_desc, date_asc, date_desc",
+                "Invalid sort method: {}. Supported values: name_asc, name_desc, date_asc, date_desc, tokens_asc, tokens_desc, size_asc, size_desc",
                 s
             )),
         }
@@ -34,43 +43,69 @@ impl FromStr for FileSortMethod {
 
 /// Sorts the provided `files` in place using the specified `sort_method`.
 ///
-/// If `sort_method` is `None`, no sorting will be performed.
+/// If `sort_method` is `None`, no sorting will be performed. Paths are compared with
+/// [`compare_paths`], and files with the same modification time, token count or size are sorted
+/// by path, so the order is the same on every platform.
 ///
 /// # Arguments
 ///
 /// * `files` - A mutable slice of JSON values representing files. Each file is expected
-///             to have a `"path"` key (as a string) and a `"mod_time"` key (as a u64).
+///   to have a `"path"` key (as a string) and, depending on the method, a `"mod_time"`,
+///   `"token_count"` or `"size"` key (as a u64). Files missing the key sort as 0.
 /// * `sort_method` - An optional `FileSortMethod` indicating how to sort the files.
-pub fn sort_files(files: &mut Vec<Value>, sort_method: Option<FileSortMethod>) {
+pub fn sort_files(files: &mut [Value], sort_method: Option<FileSortMethod>) {
     if let Some(method) = sort_method {
-        files.sort_by(|a, b| match method {
-            FileSortMethod::NameAsc => {
-                let a_path = a.get("path").and_then(Value::as_str).unwrap_or("");
-                let b_path = b.get("path").and_then(Value::as_str).unwrap_or("");
-                a_path.cmp(b_path)
-            }
-            FileSortMethod::NameDesc => {
-                let a_path = a.get("path").and_then(Value::as_str).unwrap_or("");
-                let b_path = b.get("path").and_then(Value::as_str).unwrap_or("");
-                b_path.cmp(a_path)
-            }
-            FileSortMethod::DateAsc => {
-                let a_time = a.get("mod_time").and_then(Value::as_u64).unwrap_or(0);
-                let b_time = b.get("mod_time").and_then(Value::as_u64).unwrap_or(0);
-                a_time.cmp(&b_time)
-            }
-            FileSortMethod::DateDesc => {
-                let a_time = a.get("mod_time").and_then(Value::as_u64).unwrap_or(0);
-                let b_time = b.get("mod_time").and_then(Value::as_u64).unwrap_or(0);
-                b_time.cmp(&a_time)
-            }
+        files.sort_by(|a, b| {
+            let (a_path, b_path) = (file_path(a), file_path(b));
+            let (key, ascending) = match method {
+                FileSortMethod::NameAsc => return compare_paths(a_path, b_path),
+                FileSortMethod::NameDesc => return compare_paths(b_path, a_path),
+                FileSortMethod::DateAsc => ("mod_time", true),
+                FileSortMethod::DateDesc => ("mod_time", false),
+                FileSortMethod::TokensAsc => ("token_count", true),
+                FileSortMethod::TokensDesc => ("token_count", false),
+                FileSortMethod::SizeAsc => ("size", true),
+                FileSortMethod::SizeDesc => ("size", false),
+            };
+            let order = file_number(a, key).cmp(&file_number(b, key));
+            let order = if ascending { order } else { order.reverse() };
+            order.then_with(|| compare_paths(a_path, b_path))
         });
     }
 }
 
+/// Compares two paths component by component, byte-wise, like the traversal orders the entries
+/// of a directory. A directory comes before its siblings whose name it prefixes, so `src/main.rs`
+/// sorts before `src-tauri/main.rs`.
+pub fn compare_paths(a: &str, b: &str) -> Ordering {
+    Path::new(a).cmp(Path::new(b))
+}
+
+/// Moves the files matching the priority patterns before the other files, keeping the order of
+/// both groups.
+///
+/// # Arguments
+///
+/// * `files` - A mutable slice of JSON values representing files, with their `"path"` key
+/// * `priority` - The patterns of the priority files
+pub fn prioritize_files(files: &mut [Value], priority: &GlobSet) {
+    files.sort_by_key(|file| !priority.is_match(file_path(file)));
+}
+
+/// Returns the path of a file, empty if it has none.
+fn file_path(file: &Value) -> &str {
+    file.get("path").and_then(Value::as_str).unwrap_or("")
+}
+
+/// Returns a numeric field of a file, 0 if it has none.
+fn file_number(file: &Value, key: &str) -> u64 {
+    file.get(key).and_then(Value::as_u64).unwrap_or(0)
+}
+
 /// Recursively sorts a directory tree (represented by `termtree::Tree<D>`) in place using the specified
 /// `FileSortMethod`. For directory nodes, since modification time is typically unavailable, this function
 /// falls back to sorting by name. In effect, DateAsc is treated as NameAsc and DateDesc as NameDesc for directories.
+/// Token and size sorts keep the tree in alphabetical order.
 ///
 /// If `sort_method` is `None`, no sorting is performed.
 ///
@@ -85,7 +120,12 @@ pub fn sort_tree<D: Ord + std::fmt::Display>(
     if let Some(method) = sort_method {
         // For directories we only have the name (the root), so date-based sorts fall back to name sorting.
         let ascending = match method {
-            FileSortMethod::NameAsc | FileSortMethod::DateAsc => true,
+            FileSortMethod::NameAsc
+            | FileSortMethod::DateAsc
+            | FileSortMethod::TokensAsc
+            | FileSortMethod::TokensDesc
+            | FileSortMethod::SizeAsc
+            | FileSortMethod::SizeDesc => true,
             FileSortMethod::NameDesc | FileSortMethod::DateDesc => false,
         };
         sort_tree_impl(tree, ascending);
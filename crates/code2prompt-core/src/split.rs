@@ -0,0 +1,264 @@
+//! This module splits the files of a prompt into parts that each fit in a model's context
+//! window, cutting only between files, and into chunks of a size that can be pasted at once.
+
+use crate::path::code_lines;
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use std::fmt;
+use std::str::FromStr;
+
+/// A part of a prompt split to fit in a context window.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptPart {
+    /// The number of the part, from 1.
+    pub index: usize,
+    /// The number of parts of the prompt.
+    pub count: usize,
+    pub prompt: String,
+    pub token_count: usize,
+    /// The paths of the files of the part.
+    pub files: Vec<String>,
+    /// True if the part is a single file that alone exceeds the context window.
+    pub oversized: bool,
+}
+
+/// The name of the prompt of the files at the root of the codebase, when it is split by
+/// directory.
+pub const ROOT_DIRECTORY_NAME: &str = "_root";
+
+/// The prompt of a top-level directory of the codebase, see
+/// [`Code2PromptSession::split_by_directory`](crate::session::Code2PromptSession::split_by_directory).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectoryPrompt {
+    /// The name of the directory, with its path separators replaced by `_`, or
+    /// [`ROOT_DIRECTORY_NAME`] for the files at the root.
+    pub name: String,
+    pub prompt: String,
+    pub token_count: usize,
+    /// The paths of the files of the directory.
+    pub files: Vec<String>,
+}
+
+/// Splits the files into consecutive groups whose token counts add up to at most `available`.
+///
+/// A file that alone takes more than `available` tokens is put in a group of its own. Files
+/// without a token count count as 0.
+///
+/// # Arguments
+///
+/// * `files` - The JSON file representations, in the order of the prompt
+/// * `available` - The number of tokens available to the files of a part
+///
+/// # Returns
+///
+/// * `Vec<Vec<Value>>` - The groups of files, in order. A single empty group without files.
+pub fn split_files(files: &[Value], available: usize) -> Vec<Vec<Value>> {
+    let mut groups: Vec<Vec<Value>> = Vec::new();
+    let mut current: Vec<Value> = Vec::new();
+    let mut used = 0;
+    for file in files {
+        let tokens = file
+            .get("token_count")
+            .and_then(|tokens| tokens.as_u64())
+            .unwrap_or(0) as usize;
+        if !current.is_empty() && used + tokens > available {
+            groups.push(std::mem::take(&mut current));
+            used = 0;
+        }
+        current.push(file.clone());
+        used += tokens;
+    }
+    if !current.is_empty() || groups.is_empty() {
+        groups.push(current);
+    }
+    groups
+}
+
+/// The size of the chunks of a prompt, in tokens or in characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkSize {
+    Tokens(usize),
+    Chars(usize),
+}
+
+impl ChunkSize {
+    /// Returns the size, in tokens or in characters.
+    pub fn limit(&self) -> usize {
+        match self {
+            ChunkSize::Tokens(limit) | ChunkSize::Chars(limit) => *limit,
+        }
+    }
+
+    /// Returns true for a size in tokens, which needs the tokens to be counted.
+    pub fn is_tokens(&self) -> bool {
+        matches!(self, ChunkSize::Tokens(_))
+    }
+}
+
+/// Parses a chunk size: a number of tokens, e.g. `30000` or `30k`, or of characters with a `c`
+/// suffix, e.g. `120000c` or `120kc`.
+impl FromStr for ChunkSize {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let value = s.trim().to_lowercase();
+        let (number, chars) = match value.strip_suffix('c') {
+            Some(number) => (number, true),
+            None => (value.strip_suffix('t').unwrap_or(&value), false),
+        };
+        let (number, multiplier) = match number.strip_suffix('k') {
+            Some(number) => (number, 1000),
+            None => (number, 1),
+        };
+        let limit = number
+            .parse::<usize>()
+            .ok()
+            .filter(|number| *number > 0)
+            .and_then(|number| number.checked_mul(multiplier));
+        match limit {
+            Some(limit) => Ok(if chars {
+                ChunkSize::Chars(limit)
+            } else {
+                ChunkSize::Tokens(limit)
+            }),
+            None => Err(anyhow!(
+                "Invalid chunk size: {}. Use a number of tokens, e.g. 30000 or 30k, or of characters with a c suffix, e.g. 120000c",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ChunkSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkSize::Tokens(limit) => write!(f, "{} tokens", limit),
+            ChunkSize::Chars(limit) => write!(f, "{} characters", limit),
+        }
+    }
+}
+
+/// Groups the files into chunks, cutting only between files, so that the files of the first
+/// chunk take at most `first` and those of the next chunks at most `rest`.
+///
+/// A file that doesn't fit in a chunk alone is cut between its lines into chunks of its own.
+/// Each piece is marked with a `... (continued in part N)` line inside its code block, and
+/// has a `continued` key with its `piece` number and the number of `pieces`.
+///
+/// # Arguments
+///
+/// * `files` - The JSON file representations, in the order of the prompt
+/// * `first` - The size available to the files of the first chunk
+/// * `rest` - The size available to the files of the next chunks
+/// * `no_codeblock` - Whether the code of the files isn't wrapped in a code fence
+/// * `file_size` - The size of a file, in the unit of the chunk size
+/// * `measure` - The size of a text, in the same unit
+///
+/// # Returns
+///
+/// * `Vec<Vec<Value>>` - The chunks of files, in order. A single empty chunk without files.
+pub fn chunk_files<S, M>(
+    files: &[Value],
+    first: usize,
+    rest: usize,
+    no_codeblock: bool,
+    file_size: S,
+    measure: M,
+) -> Vec<Vec<Value>>
+where
+    S: Fn(&Value) -> usize,
+    M: Fn(&str) -> usize,
+{
+    let available = |chunk: usize| if chunk == 0 { first } else { rest };
+    let mut chunks: Vec<Vec<Value>> = Vec::new();
+    let mut current: Vec<Value> = Vec::new();
+    let mut used = 0;
+    for file in files {
+        let size = file_size(file);
+        if !current.is_empty() && used + size > available(chunks.len()) {
+            chunks.push(std::mem::take(&mut current));
+            used = 0;
+        }
+        if current.is_empty() && size > available(chunks.len()) {
+            let pieces = split_file(file, chunks.len(), available, no_codeblock, &measure);
+            chunks.extend(pieces.into_iter().map(|piece| vec![piece]));
+            continue;
+        }
+        current.push(file.clone());
+        used += size;
+    }
+    if !current.is_empty() || chunks.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Cuts a file between its lines into pieces that each fit in a chunk, the first one in the
+/// chunk of index `start`.
+fn split_file<A, M>(
+    file: &Value,
+    start: usize,
+    available: A,
+    no_codeblock: bool,
+    measure: &M,
+) -> Vec<Value>
+where
+    A: Fn(usize) -> usize,
+    M: Fn(&str) -> usize,
+{
+    let Some(code) = file.get("code").and_then(|code| code.as_str()) else {
+        return vec![file.clone()];
+    };
+    let ((opening, closing), lines) = code_lines(code, no_codeblock);
+    // The fences and both markers are in every piece
+    let fixed = [opening, closing]
+        .iter()
+        .flatten()
+        .map(|fence| measure(fence) + 1)
+        .sum::<usize>()
+        + 2 * (measure("... (continued from part 1000)") + 1);
+
+    let mut pieces: Vec<Vec<&str>> = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    let mut used = 0;
+    for line in lines {
+        let size = measure(line) + 1;
+        let budget = available(start + pieces.len()).saturating_sub(fixed);
+        if !current.is_empty() && used + size > budget {
+            pieces.push(std::mem::take(&mut current));
+            used = 0;
+        }
+        current.push(line);
+        used += size;
+    }
+    if !current.is_empty() || pieces.is_empty() {
+        pieces.push(current);
+    }
+    if pieces.len() == 1 {
+        return vec![file.clone()];
+    }
+
+    let count = pieces.len();
+    pieces
+        .into_iter()
+        .enumerate()
+        .map(|(index, lines)| {
+            // The parts are numbered from 1
+            let part = start + index + 1;
+            let mut piece: Vec<String> = opening.map(str::to_string).into_iter().collect();
+            if index > 0 {
+                piece.push(format!("... (continued from part {})", part - 1));
+            }
+            piece.extend(lines.iter().map(|line| line.to_string()));
+            if index + 1 < count {
+                piece.push(format!("... (continued in part {})", part + 1));
+            }
+            piece.extend(closing.map(str::to_string));
+
+            let mut file = file.clone();
+            file["code"] = json!(piece.join("\n"));
+            file["continued"] = json!({ "piece": index + 1, "pieces": count });
+            file
+        })
+        .collect()
+}
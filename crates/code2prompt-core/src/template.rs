@@ -1,11 +1,175 @@
 //! This module contains the functions to set up the Handlebars template engine and render the template with the provided data.
-//! It also includes functions for handling user-defined variables, copying the rendered output to the clipboard, and writing it to a file.
-use anyhow::{anyhow, Result};
-use colored::*;
-use handlebars::{no_escape, Handlebars};
+//! It also includes functions for handling user-defined variables and copying the rendered output to the clipboard; [`crate::output`] writes it to a file.
+//!
+//! Templates can use these built-in helpers on top of the Handlebars ones:
+//!
+//! * `{{truncate_lines code 20}}` - The first 20 lines of a text
+//! * `{{strip_prefix path "src/"}}` - A text without a prefix, unchanged if it doesn't start with it
+//! * `{{basename path}}` - The file name of a path
+//! * `{{extension path}}` - The extension of a path without the dot, empty if it has none
+//! * `{{upper text}}` and `{{lower text}}` - A text in upper or lower case
+//! * `{{indent code 4}}` - A text with its non-empty lines indented by 4 spaces
+//! * `{{repeat "  " depth}}` - A text repeated a number of times, e.g. to indent by depth
+//! * `{{json value}}` - Any value serialized to JSON, indented with `pretty=true`
+//! * `{{codeblock path content}}` - A content in a code fence, tagged with the language of the
+//!   path and longer than any run of backticks in the content, so that a Markdown file holding
+//!   a triple-backtick block can't close it
+//! * `{{add @index 1}}` - The sum of two integers, e.g. to number the files from 1
+//! * `{{{cdata code}}}` - A text in a CDATA section of XML, which it can't close: a `]]>` of
+//!   the text is split across two sections
+//! * `{{{xml_escape path}}}` - A text with the `&`, `<`, `>` and quotes escaped for XML
+//! * `{{strip_line_numbers code}}` - A text without the `  84 | ` numbers of `--line-numbers`,
+//!   e.g. for the files whose `line_numbers` is true that a template shows without them
+//! * `{{date timestamp "%Y-%m-%d"}}` - A Unix timestamp formatted in UTC with a `strftime`
+//!   format, `%Y-%m-%d %H:%M` by default. Without parameters, `{{date}}` is the `date` value.
+//! * `{{format_date last_commit.date "%d %B %Y"}}` - A date formatted with a `strftime` format,
+//!   `%Y-%m-%d` by default or the one of [`FormatOptions`]. The date is a Unix timestamp, an
+//!   RFC 3339 date like `generated_at` and `mtime`, or a date formatted like the commit dates.
+//! * `{{format_num stats.tokens}}` - A number with thousands separators, e.g. `12,345`, those
+//!   of the locale of [`FormatOptions`]
+//! * `{{include "style.md"}}` - The contents of a file, relative to the directory of the
+//!   template, rendered with the same data so that it can include other files. See
+//!   [`IncludeOptions`] for the directories it can read from.
+//!
+//! Templates can include partials with `{{> name}}`. The default templates render each file
+//! with the built-in `file_block` partial, which a partial of the same name replaces. The
+//! built-in `tree_node` partial renders the `tree` as a nested list, recursively:
+//! `{{#with tree}}{{> tree_node}}{{/with}}`.
+//!
+//! [`validate_template`] compiles a template without any data, to report its syntax errors and
+//! the keys it references that the prompt data never has before the codebase is read.
+use crate::front_matter::split_front_matter;
+use crate::language::{code_fence, language_for_path};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use handlebars::{
+    handlebars_helper, no_escape, Context as HandlebarsContext, Handlebars, Helper, HelperDef,
+    HelperResult, Output, RenderContext, RenderError, ScopedJson, TemplateError,
+};
+use num_format::{Locale, ToFormattedString};
 use regex::Regex;
-use std::io::Write;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+
+/// The directory of the partials of a template file, next to it.
+pub const PARTIALS_DIR: &str = "partials";
+
+/// The keys of the data the prompts are rendered with, at the top level of a template.
+pub const TEMPLATE_KEYS: &[&str] = &[
+    "absolute_code_path",
+    "commits",
+    "contributors",
+    "dir_summary",
+    "directories",
+    "file_warnings",
+    "files",
+    "flags",
+    "generated_at",
+    "git_changelog",
+    "git_diff",
+    "git_diff_against",
+    "git_diff_branch",
+    "git_diff_stash",
+    "git_diff_stat",
+    "git_log_branch",
+    "git_log_commits",
+    "git_relationship",
+    "git_show",
+    "part",
+    "removed_files",
+    "section_tokens",
+    "source_tree",
+    "stats",
+    "token_breakdown",
+    "token_budget",
+    "token_count_estimated",
+    "toc",
+    "tree",
+    "user",
+];
+
+/// The keys of a file, at the top level of the file template and of the `file_block` partial.
+pub const FILE_KEYS: &[&str] = &[
+    "anchor",
+    "code",
+    "continued",
+    "encoding",
+    "extension",
+    "footer",
+    "header",
+    "language",
+    "last_commit",
+    "line_numbers",
+    "lines",
+    "metadata",
+    "mime_type",
+    "mod_time",
+    "mtime",
+    "path",
+    "size",
+    "size_bytes",
+    "stub",
+    "token_count",
+    "truncated",
+    "warning",
+    "words",
+];
+
+/// The helpers of Handlebars, on top of the built-in helpers of [`register_helpers`].
+const HANDLEBARS_HELPERS: &[&str] = &[
+    "if", "unless", "each", "with", "lookup", "log", "raw", "eq", "ne", "gt", "gte", "lt", "lte",
+    "and", "or", "not", "len",
+];
+
+/// The built-in helpers registered by [`register_helpers`].
+const BUILTIN_HELPERS: &[&str] = &[
+    "truncate_lines",
+    "strip_prefix",
+    "basename",
+    "extension",
+    "upper",
+    "lower",
+    "indent",
+    "repeat",
+    "json",
+    "codeblock",
+    "add",
+    "cdata",
+    "xml_escape",
+    "strip_line_numbers",
+    "date",
+    "format_date",
+    "format_num",
+    "include",
+];
+
+/// The longest chain of files the `include` helper follows, e.g. a file including a file
+/// including a file is a chain of 3.
+pub const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// Where the `include` helper looks for the files of a template.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IncludeOptions {
+    /// The directory of the template, which the paths are relative to. The current directory
+    /// if unset, e.g. for the built-in templates.
+    pub base_dir: Option<PathBuf>,
+    /// The directories the files can be read from on top of the base directory.
+    pub roots: Vec<PathBuf>,
+}
+
+/// A reference of a template to a key that the prompt data doesn't have.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownKey {
+    pub key: String,
+    /// The line of the reference in the template, from 1, counting the front-matter.
+    pub line: usize,
+    /// The column of the reference in the line, from 1.
+    pub column: usize,
+}
 
 /// Set up the Handlebars template engine with a template string and a template name.
 ///
@@ -19,7 +183,9 @@ use std::str::FromStr;
 /// * `Result<Handlebars<'static>>` - The configured Handlebars instance.
 pub fn handlebars_setup(template_str: &str, template_name: &str) -> Result<Handlebars<'static>> {
     let mut handlebars = Handlebars::new();
+    // The values are mostly code, which HTML entities would corrupt
     handlebars.register_escape_fn(no_escape);
+    register_helpers(&mut handlebars);
 
     handlebars
         .register_template_string(template_name, template_str)
@@ -28,6 +194,859 @@ pub fn handlebars_setup(template_str: &str, template_name: &str) -> Result<Handl
     Ok(handlebars)
 }
 
+handlebars_helper!(truncate_lines_helper: |text: str, lines: u64| {
+    text.lines().take(lines as usize).collect::<Vec<_>>().join("\n")
+});
+handlebars_helper!(strip_prefix_helper: |text: str, prefix: str| {
+    text.strip_prefix(prefix).unwrap_or(text).to_string()
+});
+handlebars_helper!(basename_helper: |path: str| {
+    Path::new(path).file_name().map_or(String::new(), |name| name.to_string_lossy().into_owned())
+});
+handlebars_helper!(extension_helper: |path: str| {
+    Path::new(path).extension().map_or(String::new(), |ext| ext.to_string_lossy().into_owned())
+});
+handlebars_helper!(upper_helper: |text: str| text.to_uppercase());
+handlebars_helper!(lower_helper: |text: str| text.to_lowercase());
+handlebars_helper!(indent_helper: |text: str, width: u64| {
+    let padding = " ".repeat(width as usize);
+    text.lines()
+        .map(|line| if line.is_empty() { String::new() } else { format!("{}{}", padding, line) })
+        .collect::<Vec<_>>()
+        .join("\n")
+});
+handlebars_helper!(repeat_helper: |text: str, count: u64| text.repeat(count as usize));
+// A missing content, e.g. the source tree of a prompt without it, is an empty block
+handlebars_helper!(codeblock_helper: |path: str, content: Json| {
+    codeblock(path, content.as_str().unwrap_or_default())
+});
+handlebars_helper!(json_helper: |value: Json, {pretty: bool = false}| {
+    if pretty {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
+    }
+    .unwrap_or_default()
+});
+
+handlebars_helper!(add_helper: |a: i64, b: i64| a + b);
+handlebars_helper!(cdata_helper: |text: str| cdata(text));
+handlebars_helper!(xml_escape_helper: |text: str| xml_escape(text));
+handlebars_helper!(strip_line_numbers_helper: |text: str| strip_line_numbers(text));
+
+/// Wraps a content in a Markdown code fence tagged with the language of a path, e.g. `diff`
+/// for `changes.diff`. The fence is longer than any run of backticks in the content, so that
+/// a content holding a fenced block, like a diff of a README, can't close it.
+pub fn codeblock(path: &str, content: &str) -> String {
+    let fence = code_fence(content);
+    let language = language_for_path(Path::new(path));
+    format!("{}{}\n{}\n{}", fence, language, content, fence)
+}
+
+/// Returns the anchor GitHub gives a Markdown heading: its text in lower case, without the
+/// characters other than letters, digits, `-`, `_` and spaces, and with the spaces as `-`.
+/// A `src/main.rs` heading is `srcmainrs`.
+pub fn heading_slug(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | ' '))
+        .map(|c| if c == ' ' { '-' } else { c })
+        .collect()
+}
+
+/// Returns the anchors of headings, in their order. A repeated anchor gets a `-1`, `-2`, ...
+/// suffix as on GitHub, so that `a/b.rs` and `ab.rs`, which are both `abrs`, link to their own
+/// heading.
+pub fn heading_anchors<'a>(headings: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let mut occurrences: HashMap<String, usize> = HashMap::new();
+    headings
+        .into_iter()
+        .map(|heading| {
+            let slug = heading_slug(heading);
+            let mut anchor = slug.clone();
+            while occurrences.contains_key(&anchor) {
+                let count = occurrences.entry(slug.clone()).or_default();
+                *count += 1;
+                anchor = format!("{}-{}", slug, count);
+            }
+            occurrences.insert(anchor.clone(), 0);
+            anchor
+        })
+        .collect()
+}
+
+/// Wraps a text in a CDATA section of XML. The `]]>` of the text, which would close the
+/// section, are split across two sections, so any text reads back unchanged.
+pub fn cdata(text: &str) -> String {
+    format!("<![CDATA[{}]]>", text.replace("]]>", "]]]]><![CDATA[>"))
+}
+
+/// Escapes the `&`, `<`, `>` and quotes of a text for the content or an attribute of XML.
+pub fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Removes the line numbers added by `--line-numbers`, a right-aligned number then ` | `, from
+/// the lines of a text. The other lines, like the code fences, are unchanged.
+pub fn strip_line_numbers(text: &str) -> String {
+    text.split_inclusive('\n')
+        .map(|line| {
+            let number = line.trim_start_matches(' ');
+            let digits = number.len()
+                - number
+                    .trim_start_matches(|c: char| c.is_ascii_digit())
+                    .len();
+            match number[digits..].strip_prefix(" | ") {
+                Some(rest) if digits > 0 => rest,
+                _ => line,
+            }
+        })
+        .collect()
+}
+
+/// The default format of the `date` helper.
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+/// The `date` helper, which formats a Unix timestamp. Without parameters, it falls back to
+/// the `date` value of the context, so the templates rendering a `date` field still work.
+struct DateHelper;
+
+impl HelperDef for DateHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars<'reg>,
+        ctx: &'rc HandlebarsContext,
+        rc: &mut RenderContext<'reg, 'rc>,
+    ) -> std::result::Result<ScopedJson<'reg, 'rc>, RenderError> {
+        let Some(timestamp) = h.param(0) else {
+            return rc.evaluate(ctx, "date");
+        };
+        let timestamp = timestamp
+            .value()
+            .as_i64()
+            .ok_or_else(|| RenderError::new("`date` helper: the timestamp must be an integer"))?;
+        let format = h
+            .param(1)
+            .and_then(|format| format.value().as_str())
+            .unwrap_or(DEFAULT_DATE_FORMAT);
+        let date = DateTime::from_timestamp(timestamp, 0)
+            .ok_or_else(|| RenderError::new("`date` helper: the timestamp is out of range"))?;
+        let mut formatted = String::new();
+        std::fmt::Write::write_fmt(&mut formatted, format_args!("{}", date.format(format)))
+            .map_err(|_| RenderError::new(format!("`date` helper: invalid format {}", format)))?;
+        Ok(ScopedJson::Derived(serde_json::Value::String(formatted)))
+    }
+}
+
+/// The default format of the `format_date` helper, the date of a commit.
+pub const DEFAULT_FORMAT_DATE: &str = "%Y-%m-%d";
+
+/// How the `format_date` and `format_num` helpers format the values they're not given a
+/// format for.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// The `strftime` format of the dates, [`DEFAULT_FORMAT_DATE`] if unset.
+    pub date_format: Option<String>,
+    /// The name of the locale whose separators the numbers are formatted with, e.g. `de` or
+    /// `fr`, English if unset. The names of the months and days are English whatever it is.
+    pub locale: Option<String>,
+}
+
+/// Checks a `strftime` format, e.g. `%d %B %Y`, which is invalid if it has an unknown
+/// specifier like `%Q` or a lone `%`.
+pub fn check_date_format(format: &str) -> Result<()> {
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        bail!(
+            "Invalid date format {:?}: see the strftime specifiers of chrono",
+            format
+        );
+    }
+    Ok(())
+}
+
+/// Returns the locale of a name, e.g. `de` or `en-IN`.
+pub fn parse_locale(name: &str) -> Result<Locale> {
+    Locale::from_name(name).map_err(|_| anyhow!("Unknown locale {}, e.g. en, de or fr", name))
+}
+
+/// Parses the dates of the prompt data: the Unix timestamps, the RFC 3339 dates, the dates
+/// formatted like the commits of the git log, and the days like `2024-01-02`, at midnight UTC.
+fn parse_date(value: &serde_json::Value) -> Option<DateTime<FixedOffset>> {
+    if let Some(timestamp) = value.as_i64() {
+        return DateTime::from_timestamp(timestamp, 0).map(|date| date.fixed_offset());
+    }
+    let text = value.as_str()?.trim();
+    DateTime::parse_from_rfc3339(text)
+        .or_else(|_| DateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S %z"))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()
+                .and_then(|day| day.and_hms_opt(0, 0, 0))
+                .map(|midnight| midnight.and_utc().fixed_offset())
+        })
+}
+
+/// The `format_date` helper, which formats a date with a format, or the default one.
+struct FormatDateHelper {
+    default_format: String,
+}
+
+impl HelperDef for FormatDateHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc HandlebarsContext,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> std::result::Result<ScopedJson<'reg, 'rc>, RenderError> {
+        let value = h
+            .param(0)
+            .map(|param| param.value())
+            .ok_or_else(|| RenderError::new("`format_date` helper: a date is expected"))?;
+        // A missing date renders nothing, like a missing value
+        if value.is_null() {
+            return Ok(ScopedJson::Derived(
+                serde_json::Value::String(String::new()),
+            ));
+        }
+        let date = parse_date(value).ok_or_else(|| {
+            RenderError::new(format!("`format_date` helper: {} isn't a date", value))
+        })?;
+        let format = h
+            .param(1)
+            .and_then(|format| format.value().as_str())
+            .unwrap_or(&self.default_format);
+        check_date_format(format)
+            .map_err(|e| RenderError::new(format!("`format_date` helper: {}", e)))?;
+        Ok(ScopedJson::Derived(serde_json::Value::String(
+            date.format(format).to_string(),
+        )))
+    }
+}
+
+/// The `format_num` helper, which writes a number with the thousands separators of a locale.
+struct FormatNumHelper {
+    locale: Locale,
+}
+
+impl HelperDef for FormatNumHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc HandlebarsContext,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> std::result::Result<ScopedJson<'reg, 'rc>, RenderError> {
+        let value = h
+            .param(0)
+            .map(|param| param.value())
+            .ok_or_else(|| RenderError::new("`format_num` helper: a number is expected"))?;
+        let formatted = if value.is_null() {
+            String::new()
+        } else if let Some(number) = value.as_i64() {
+            number.to_formatted_string(&self.locale)
+        } else if let Some(number) = value.as_u64() {
+            number.to_formatted_string(&self.locale)
+        } else if let Some(number) = value.as_f64() {
+            // The integer part is separated, the decimals are kept as they are
+            let text = number.to_string();
+            let (_, decimals) = text.split_once('.').unwrap_or((&text, ""));
+            let integer = (number.trunc() as i64).to_formatted_string(&self.locale);
+            let sign = if number < 0.0 && number > -1.0 {
+                "-"
+            } else {
+                ""
+            };
+            match decimals {
+                "" => format!("{}{}", sign, integer),
+                _ => format!("{}{}{}{}", sign, integer, self.locale.decimal(), decimals),
+            }
+        } else {
+            return Err(RenderError::new(format!(
+                "`format_num` helper: {} isn't a number",
+                value
+            )));
+        };
+        Ok(ScopedJson::Derived(serde_json::Value::String(formatted)))
+    }
+}
+
+/// Registers the `format_date` and `format_num` helpers with the default date format and
+/// locale of the options, replacing the ones of [`register_helpers`].
+///
+/// # Arguments
+///
+/// * `handlebars` - The Handlebars instance to register the helpers on.
+/// * `options` - The default date format and the locale of the numbers.
+///
+/// # Returns
+///
+/// * `Result<()>` - An error if the date format or the locale is invalid.
+pub fn register_format_helpers(handlebars: &mut Handlebars, options: &FormatOptions) -> Result<()> {
+    let default_format = options
+        .date_format
+        .clone()
+        .unwrap_or_else(|| DEFAULT_FORMAT_DATE.to_string());
+    check_date_format(&default_format)?;
+    let locale = options
+        .locale
+        .as_deref()
+        .map_or(Ok(Locale::en), parse_locale)?;
+    handlebars.register_helper("format_date", Box::new(FormatDateHelper { default_format }));
+    handlebars.register_helper("format_num", Box::new(FormatNumHelper { locale }));
+    Ok(())
+}
+
+/// The `include` helper, which renders a file with the data of the prompt. The files being
+/// included are stacked, so that a file including itself, directly or not, is an error
+/// rather than an endless recursion.
+struct IncludeHelper {
+    base_dir: PathBuf,
+    roots: Vec<PathBuf>,
+    stack: Mutex<Vec<PathBuf>>,
+}
+
+impl IncludeHelper {
+    fn new(options: &IncludeOptions) -> Self {
+        let base_dir = options
+            .base_dir
+            .clone()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default();
+        let roots = std::iter::once(&base_dir)
+            .chain(&options.roots)
+            .filter_map(|root| root.canonicalize().ok())
+            .collect();
+        Self {
+            base_dir,
+            roots,
+            stack: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Resolves a path relative to the file being included, or to the base directory, and
+    /// checks that it can be included.
+    fn resolve(&self, path: &str) -> std::result::Result<PathBuf, RenderError> {
+        let stack = self.stack.lock().unwrap();
+        let dir = stack
+            .last()
+            .and_then(|file| file.parent())
+            .unwrap_or(&self.base_dir);
+        let file = dir.join(path).canonicalize().map_err(|_| {
+            RenderError::new(format!(
+                "`include` helper: the file {} doesn't exist in {}",
+                path,
+                dir.display()
+            ))
+        })?;
+        if !self.roots.iter().any(|root| file.starts_with(root)) {
+            return Err(RenderError::new(format!(
+                "`include` helper: the file {} is outside the template directory and the \
+                 include roots",
+                path
+            )));
+        }
+        if stack.contains(&file) {
+            let cycle: Vec<String> = stack
+                .iter()
+                .chain(std::iter::once(&file))
+                .map(|file| file.display().to_string())
+                .collect();
+            return Err(RenderError::new(format!(
+                "`include` helper: the files include each other: {}",
+                cycle.join(" -> ")
+            )));
+        }
+        if stack.len() >= MAX_INCLUDE_DEPTH {
+            return Err(RenderError::new(format!(
+                "`include` helper: the file {} is included more than {} levels deep",
+                path, MAX_INCLUDE_DEPTH
+            )));
+        }
+        Ok(file)
+    }
+}
+
+impl HelperDef for IncludeHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        r: &'reg Handlebars<'reg>,
+        ctx: &'rc HandlebarsContext,
+        _: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let path = h
+            .param(0)
+            .and_then(|path| path.value().as_str())
+            .ok_or_else(|| RenderError::new("`include` helper: the path must be a string"))?;
+        let file = self.resolve(path)?;
+        let content = fs::read_to_string(&file).map_err(|e| {
+            RenderError::new(format!("`include` helper: failed to read {}: {}", path, e))
+        })?;
+
+        // Like the lines of a template, the file doesn't end its line
+        let content = content.strip_suffix('\n').unwrap_or(&content);
+        self.stack.lock().unwrap().push(file);
+        let rendered = r.render_template_with_context(content, ctx);
+        self.stack.lock().unwrap().pop();
+        out.write(&rendered?)?;
+        Ok(())
+    }
+}
+
+/// Registers the `include` helper, replacing the one of [`register_helpers`] that reads from
+/// the current directory only.
+///
+/// # Arguments
+///
+/// * `handlebars` - The Handlebars instance to register the helper on.
+/// * `options` - The directory of the template and the other directories it can read from.
+pub fn register_include_helper(handlebars: &mut Handlebars, options: &IncludeOptions) {
+    handlebars.register_helper("include", Box::new(IncludeHelper::new(options)));
+}
+
+/// Registers the built-in helpers listed in the module documentation.
+///
+/// # Arguments
+///
+/// * `handlebars` - The Handlebars instance to register the helpers on.
+pub fn register_helpers(handlebars: &mut Handlebars) {
+    handlebars.register_helper("truncate_lines", Box::new(truncate_lines_helper));
+    handlebars.register_helper("strip_prefix", Box::new(strip_prefix_helper));
+    handlebars.register_helper("basename", Box::new(basename_helper));
+    handlebars.register_helper("extension", Box::new(extension_helper));
+    handlebars.register_helper("upper", Box::new(upper_helper));
+    handlebars.register_helper("lower", Box::new(lower_helper));
+    handlebars.register_helper("indent", Box::new(indent_helper));
+    handlebars.register_helper("repeat", Box::new(repeat_helper));
+    handlebars.register_helper("json", Box::new(json_helper));
+    handlebars.register_helper("codeblock", Box::new(codeblock_helper));
+    handlebars.register_helper("add", Box::new(add_helper));
+    handlebars.register_helper("cdata", Box::new(cdata_helper));
+    handlebars.register_helper("xml_escape", Box::new(xml_escape_helper));
+    handlebars.register_helper("strip_line_numbers", Box::new(strip_line_numbers_helper));
+    handlebars.register_helper("date", Box::new(DateHelper));
+    handlebars.register_helper(
+        "format_date",
+        Box::new(FormatDateHelper {
+            default_format: DEFAULT_FORMAT_DATE.to_string(),
+        }),
+    );
+    handlebars.register_helper(
+        "format_num",
+        Box::new(FormatNumHelper { locale: Locale::en }),
+    );
+    register_include_helper(handlebars, &IncludeOptions::default());
+}
+
+/// Registers partials on a Handlebars instance, replacing the partials of the same name.
+///
+/// # Arguments
+///
+/// * `handlebars` - The Handlebars instance to register the partials on.
+/// * `template_name` - The name of the main template, which a partial can't take.
+/// * `partials` - The partials, by name, with their content.
+///
+/// # Returns
+///
+/// * `Result<()>` - An error naming the partial if it can't be parsed.
+pub fn register_partials(
+    handlebars: &mut Handlebars,
+    template_name: &str,
+    partials: &HashMap<String, String>,
+) -> Result<()> {
+    for (name, content) in partials {
+        if name == template_name {
+            bail!("The partial {} has the name of the main template", name);
+        }
+        handlebars
+            .register_partial(name, content)
+            .map_err(|e| anyhow!("Failed to register the partial {}: {}", name, e))?;
+    }
+    Ok(())
+}
+
+/// Checks that the partials included by a template and its partials are registered.
+///
+/// Partials included with a block, `{{#> name}}...{{/name}}`, have a fallback and aren't
+/// checked, nor are the inline partials the sources define.
+///
+/// # Arguments
+///
+/// * `handlebars` - The Handlebars instance the partials are registered on.
+/// * `template_name` - The name of the main template, for the error.
+/// * `sources` - The main template string and the content of the partials.
+///
+/// # Returns
+///
+/// * `Result<()>` - An error naming the missing partial and the main template.
+pub fn check_partials<'a>(
+    handlebars: &Handlebars,
+    template_name: &str,
+    sources: impl IntoIterator<Item = &'a str>,
+) -> Result<()> {
+    let include = Regex::new(r"\{\{~?>\s*([A-Za-z0-9_][A-Za-z0-9_./-]*)").unwrap();
+    let inline = Regex::new(r#"\{\{~?#\*inline\s+"([^"]+)""#).unwrap();
+    let sources: Vec<&str> = sources.into_iter().collect();
+    let inline_partials: Vec<&str> = sources
+        .iter()
+        .flat_map(|source| inline.captures_iter(source))
+        .filter_map(|cap| cap.get(1).map(|name| name.as_str()))
+        .collect();
+    for source in &sources {
+        for cap in include.captures_iter(source) {
+            let name = &cap[1];
+            if !handlebars.has_template(name) && !inline_partials.contains(&name) {
+                bail!(
+                    "The template {} uses the partial {}, which isn't registered",
+                    template_name,
+                    name
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads the partials of the `partials` directory next to a template file, named after their
+/// file without the `.hbs` extension.
+///
+/// # Arguments
+///
+/// * `template_path` - The path of the template file.
+///
+/// # Returns
+///
+/// * `Result<HashMap<String, String>>` - The partials, none if the directory doesn't exist, or
+///   an error if it or a partial can't be read.
+pub fn read_partials_dir(template_path: &Path) -> Result<HashMap<String, String>> {
+    let dir = template_path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join(PARTIALS_DIR);
+    let mut partials = HashMap::new();
+    if !dir.is_dir() {
+        return Ok(partials);
+    }
+    let entries = fs::read_dir(&dir)
+        .with_context(|| format!("Failed to read the partials directory {}", dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        if !path.is_file() || path.extension().is_none_or(|ext| ext != "hbs") {
+            continue;
+        }
+        let Some(name) = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+        else {
+            continue;
+        };
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read the partial {}", path.display()))?;
+        partials.insert(name, content);
+    }
+    Ok(partials)
+}
+
+/// Validates a template without rendering it: compiles it and its partials, checks that the
+/// partials it includes are registered and looks for the keys it references outside of the
+/// prompt data.
+///
+/// Only the references evaluated against the top level of the data are checked: the ones
+/// outside of the blocks that change the context, like `{{#each}}` and `{{#with}}`, and the
+/// ones starting with `@root`. A helper called with a name the data doesn't have is reported
+/// as well.
+///
+/// # Arguments
+///
+/// * `template` - The template, with its front-matter if any.
+/// * `template_name` - The name of the template, for the errors.
+/// * `partials` - The partials, by name, with their content.
+///
+/// # Returns
+///
+/// * `Result<Vec<UnknownKey>>` - The unknown keys, in the order of the template, or an error
+///   with the line and column of the syntax error.
+pub fn validate_template(
+    template: &str,
+    template_name: &str,
+    partials: &HashMap<String, String>,
+) -> Result<Vec<UnknownKey>> {
+    let (_, body) = split_front_matter(template)?;
+    let front_matter_lines = template[..template.len() - body.len()]
+        .matches('\n')
+        .count();
+
+    let scan = scan_template(body, TEMPLATE_KEYS);
+    let mut handlebars = Handlebars::new();
+    register_helpers(&mut handlebars);
+    handlebars
+        .register_template_string(template_name, body)
+        .map_err(|e| match &scan.unclosed_block {
+            Some(block) => anyhow!(
+                "Invalid template {} at line {}, column {}: the block {{{{#{}}}}} isn't closed",
+                template_name,
+                block.line + front_matter_lines,
+                block.column,
+                block.key
+            ),
+            None => syntax_error(&e, template_name, front_matter_lines),
+        })?;
+    let mut partials = partials.clone();
+    partials
+        .entry("file_block".to_string())
+        .or_insert_with(|| include_str!("./default_file_block_md.hbs").to_string());
+    partials
+        .entry("tree_node".to_string())
+        .or_insert_with(|| include_str!("./default_tree_node.hbs").to_string());
+    for (name, content) in &partials {
+        if name == template_name {
+            bail!("The partial {} has the name of the main template", name);
+        }
+        handlebars
+            .register_partial(name, content)
+            .map_err(|e| syntax_error(&e, &format!("partial {}", name), 0))?;
+    }
+    check_partials(
+        &handlebars,
+        template_name,
+        std::iter::once(body).chain(partials.values().map(String::as_str)),
+    )?;
+    check_date_formats(body, template_name, front_matter_lines)?;
+
+    Ok(scan
+        .unknown_keys
+        .into_iter()
+        .map(|mut unknown| {
+            unknown.line += front_matter_lines;
+            unknown
+        })
+        .collect())
+}
+
+/// Validates a file template, the `file_block` partial rendered once per file: compiles it,
+/// checks that the partials it includes are registered and looks for the keys it references
+/// outside of [`FILE_KEYS`], like [`validate_template`] does for a template.
+///
+/// # Arguments
+///
+/// * `template` - The file template.
+/// * `template_name` - The name of the file template, for the errors.
+/// * `partials` - The other partials, by name, with their content.
+///
+/// # Returns
+///
+/// * `Result<Vec<UnknownKey>>` - The unknown keys, in the order of the template, or an error
+///   with the line and column of the syntax error.
+pub fn validate_file_template(
+    template: &str,
+    template_name: &str,
+    partials: &HashMap<String, String>,
+) -> Result<Vec<UnknownKey>> {
+    let scan = scan_template(template, FILE_KEYS);
+    let mut handlebars = Handlebars::new();
+    register_helpers(&mut handlebars);
+    handlebars
+        .register_template_string(template_name, template)
+        .map_err(|e| match &scan.unclosed_block {
+            Some(block) => anyhow!(
+                "Invalid template {} at line {}, column {}: the block {{{{#{}}}}} isn't closed",
+                template_name,
+                block.line,
+                block.column,
+                block.key
+            ),
+            None => syntax_error(&e, template_name, 0),
+        })?;
+    register_partials(&mut handlebars, template_name, partials)?;
+    check_partials(&handlebars, template_name, [template])?;
+    check_date_formats(template, template_name, 0)?;
+    Ok(scan.unknown_keys)
+}
+
+fn syntax_error(error: &TemplateError, template_name: &str, line_offset: usize) -> anyhow::Error {
+    match (error.line_no, error.column_no) {
+        (Some(line), Some(column)) => anyhow!(
+            "Invalid template {} at line {}, column {}: {}",
+            template_name,
+            line + line_offset,
+            column,
+            error.reason()
+        ),
+        _ => anyhow!("Invalid template {}: {}", template_name, error.reason()),
+    }
+}
+
+/// Checks the formats the `date` and `format_date` helpers of a template are called with, so
+/// that an invalid format fails before the codebase is read rather than when rendering.
+fn check_date_formats(template: &str, template_name: &str, line_offset: usize) -> Result<()> {
+    let call =
+        Regex::new(r#"(?:^|[\s({~])(format_date|date)\s+(?:\([^)]*\)|[^\s"()}]+)\s+"([^"]*)""#)
+            .unwrap();
+    for cap in call.captures_iter(template) {
+        if let Err(e) = check_date_format(&cap[2]) {
+            let start = cap.get(1).unwrap().start();
+            let line_start = template[..start].rfind('\n').map_or(0, |i| i + 1);
+            bail!(
+                "Invalid template {} at line {}, column {}: {}",
+                template_name,
+                template[..start].matches('\n').count() + 1 + line_offset,
+                template[line_start..start].chars().count() + 1,
+                e
+            );
+        }
+    }
+    Ok(())
+}
+
+/// What [`scan_template`] finds in a template.
+struct TemplateScan {
+    unknown_keys: Vec<UnknownKey>,
+    /// The first block that isn't closed, with the name of its helper as key.
+    unclosed_block: Option<UnknownKey>,
+}
+
+/// A block open while scanning a template.
+struct OpenBlock {
+    helper: String,
+    /// Whether the block changes the context of the references inside it.
+    changes_context: bool,
+    offset: usize,
+}
+
+/// Scans a template for the references to keys outside of `keys` evaluated against the top
+/// level of its context, or outside of [`TEMPLATE_KEYS`] with `@root`, and for its unclosed
+/// blocks.
+fn scan_template(template: &str, keys: &[&str]) -> TemplateScan {
+    let comment = Regex::new(r"(?s)\{\{!--.*?--\}\}|\{\{![^}]*\}\}").unwrap();
+    let mustache = Regex::new(r"\{\{\{?~?([^}]*?)~?\}?\}\}").unwrap();
+    let token = Regex::new(r#""[^"]*"|'[^']*'|\(|\)|\|[^|]*\||[^\s()|]+"#).unwrap();
+    // The comments are blanked out to keep the offsets of the rest
+    let template = comment.replace_all(template, |caps: &regex::Captures| {
+        caps[0].replace(|c: char| c != '\n', " ")
+    });
+    let position = |offset: usize| {
+        let line_start = template[..offset].rfind('\n').map_or(0, |i| i + 1);
+        (
+            template[..offset].matches('\n').count() + 1,
+            template[line_start..offset].chars().count() + 1,
+        )
+    };
+    let block_key = |block: &OpenBlock| {
+        let (line, column) = position(block.offset);
+        UnknownKey {
+            key: block.helper.clone(),
+            line,
+            column,
+        }
+    };
+
+    let mut unknown_keys = Vec::new();
+    let mut unclosed_block = None;
+    let mut blocks: Vec<OpenBlock> = Vec::new();
+    for cap in mustache.captures_iter(&template) {
+        let inner = cap.get(1).unwrap();
+        let expression = inner.as_str().trim_start();
+        let start = inner.start() + inner.as_str().len() - expression.len();
+        let (expression, start, opens_block) = match expression.chars().next() {
+            Some('/') => {
+                let helper = expression[1..].trim();
+                if let Some(index) = blocks.iter().rposition(|block| block.helper == helper) {
+                    if index + 1 < blocks.len() && unclosed_block.is_none() {
+                        unclosed_block = blocks.last().map(block_key);
+                    }
+                    blocks.truncate(index);
+                }
+                continue;
+            }
+            Some('>') | Some('!') | None => continue,
+            Some('^') if expression.len() == 1 => continue,
+            Some('#') | Some('^') => {
+                let rest = expression[1..].trim_start_matches(['*', '>']).trim_start();
+                let helper = rest.split_whitespace().next().unwrap_or_default();
+                let changes_context = !["if", "unless"].contains(&helper);
+                blocks.push(OpenBlock {
+                    helper: helper.to_string(),
+                    changes_context,
+                    offset: cap.get(0).unwrap().start(),
+                });
+                if expression[1..].starts_with(['*', '>']) {
+                    // Inline partials and partial blocks are rendered in an unknown context
+                    continue;
+                }
+                (rest, start + expression.len() - rest.len(), true)
+            }
+            _ => (expression, start, false),
+        };
+        // The parameters of a block are evaluated in the context around it
+        let outer_blocks = match opens_block {
+            true => &blocks[..blocks.len() - 1],
+            false => &blocks[..],
+        };
+        let at_top_level = !outer_blocks.iter().any(|block| block.changes_context);
+
+        let mut helper_position = true;
+        for token_match in token.find_iter(expression) {
+            let text = token_match.as_str();
+            let was_helper_position = helper_position;
+            helper_position = text == "(";
+            if text == "as" || text.starts_with('|') {
+                break;
+            }
+            if was_helper_position
+                && (HANDLEBARS_HELPERS.contains(&text)
+                    || BUILTIN_HELPERS.contains(&text)
+                    || text == "else")
+            {
+                helper_position = text == "else";
+                continue;
+            }
+            let path = text.rsplit_once('=').map_or(text, |(_, value)| value);
+            let (path, checked, known_keys) = match path.strip_prefix("@root.") {
+                Some(path) => (path, true, TEMPLATE_KEYS),
+                None => (path, at_top_level, keys),
+            };
+            let path = path
+                .strip_prefix("this.")
+                .or_else(|| path.strip_prefix("./"))
+                .unwrap_or(path);
+            let key = path.split(['.', '/', '[']).next().unwrap_or_default();
+            let is_key = key
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && !["this", "true", "false", "null", "undefined"].contains(&key);
+            if checked && is_key && !known_keys.contains(&key) {
+                let (line, column) = position(start + token_match.start());
+                unknown_keys.push(UnknownKey {
+                    key: key.to_string(),
+                    line,
+                    column,
+                });
+            }
+        }
+    }
+    TemplateScan {
+        unknown_keys,
+        unclosed_block: unclosed_block.or_else(|| blocks.last().map(block_key)),
+    }
+}
+
 /// Extracts the undefined variables from the template string.
 ///
 /// # Arguments
@@ -46,6 +1065,31 @@ pub fn extract_undefined_variables(template: &str) -> Vec<String> {
         .collect()
 }
 
+/// Extracts the keys of the user-defined variables, `{{user.<key>}}`, a template uses, in the
+/// order they first appear.
+///
+/// # Arguments
+///
+/// * `template` - The Handlebars template string.
+///
+/// # Returns
+///
+/// * `Vec<String>` - The keys, without the `user.` prefix.
+pub fn extract_user_variables(template: &str) -> Vec<String> {
+    let mustache = Regex::new(r"\{\{[^}]*\}\}").unwrap();
+    let variable =
+        Regex::new(r"(?:^|[^A-Za-z0-9_.])(?:@root\.)?user\.([A-Za-z_][A-Za-z0-9_]*)").unwrap();
+    let mut keys: Vec<String> = Vec::new();
+    for expression in mustache.find_iter(template) {
+        for cap in variable.captures_iter(expression.as_str()) {
+            if !keys.iter().any(|key| key == &cap[1]) {
+                keys.push(cap[1].to_string());
+            }
+        }
+    }
+    keys
+}
+
 /// Renders the template with the provided data.
 ///
 /// # Arguments
@@ -68,46 +1112,124 @@ pub fn render_template(
     Ok(rendered.trim().to_string())
 }
 
-/// Writes the rendered template to a specified output file or stdout.
+/// The number of characters of each file content kept by [`elide_file_contents`] by default.
+pub const CONTEXT_PREVIEW_CHARS: usize = 200;
+
+/// Cuts the `code` of each file of the template data to its first characters, followed by a
+/// marker with the number of characters elided, to show the data without the whole codebase.
+/// The marker can't be mistaken for the truncation of the token budget, which the files flag
+/// with `truncated`.
 ///
 /// # Arguments
 ///
-/// * `output_path` - The path to the output file, or "-" for stdout.
-/// * `rendered` - The rendered template string.
-/// * `quiet` - If true, suppress success messages.
-///
-/// # Returns
-///
-/// * `Result<()>` - An empty result indicating success or an error.
-pub fn write_to_file(output_path: &str, rendered: &str, quiet: bool) -> Result<()> {
-    if output_path == "-" {
-        // Write to stdout
-        print!("{}", rendered);
-        std::io::stdout().flush()?;
-    } else {
-        // Write to file
-        let file = std::fs::File::create(output_path)?;
-        let mut writer = std::io::BufWriter::new(file);
-        write!(writer, "{}", rendered)?;
-        if !quiet {
-            println!(
-                "{}{}{} {}",
-                "[".bold().white(),
-                "✓".bold().green(),
-                "]".bold().white(),
-                format!("Prompt written to file: {}", output_path).green()
+/// * `data` - The template data, whose `files` are cut in place.
+/// * `max_chars` - The number of characters of each content to keep.
+pub fn elide_file_contents(data: &mut serde_json::Value, max_chars: usize) {
+    let Some(files) = data.get_mut("files").and_then(|files| files.as_array_mut()) else {
+        return;
+    };
+    for file in files {
+        let Some(code) = file.get("code").and_then(|code| code.as_str()) else {
+            continue;
+        };
+        let elided = code.chars().count().saturating_sub(max_chars);
+        if elided > 0 {
+            let kept: String = code.chars().take(max_chars).collect();
+            file["code"] = serde_json::Value::String(format!(
+                "{}[... {} more characters elided from this preview ...]",
+                kept, elided
+            ));
+        }
+    }
+}
+
+/// The tokens of the file header and footer formats, e.g. `===== {path} =====`.
+pub const FILE_FORMAT_TOKENS: [&str; 4] = ["path", "language", "tokens", "index"];
+
+/// Returns the pattern of a `{name}` token of a format string, compiled once.
+fn token_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"\{([^{}]*)\}").unwrap())
+}
+
+/// Returns the `{name}` tokens of a format string in their order, failing on a token that
+/// isn't one of `valid` with the list of the valid ones. `what` names the format string in
+/// the error, e.g. `the output path prompts/{date}.md`.
+pub(crate) fn format_tokens(format: &str, valid: &[&str], what: &str) -> Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    for captures in token_pattern().captures_iter(format) {
+        let name = &captures[1];
+        if !valid.contains(&name) {
+            bail!(
+                "Unknown token {{{}}} in {}. Valid tokens: {}",
+                name,
+                what,
+                valid
+                    .iter()
+                    .map(|token| format!("{{{}}}", token))
+                    .collect::<Vec<_>>()
+                    .join(", ")
             );
         }
+        tokens.push(name.to_string());
     }
-    Ok(())
+    Ok(tokens)
+}
+
+/// Replaces the `{name}` tokens of a format string with their values, leaving the tokens
+/// without a value as they are.
+pub fn replace_tokens(format: &str, value: impl Fn(&str) -> Option<String>) -> String {
+    token_pattern()
+        .replace_all(format, |captures: &regex::Captures| {
+            value(&captures[1]).unwrap_or_else(|| captures[0].to_string())
+        })
+        .into_owned()
+}
+
+/// Returns the tokens of a file header or footer format in their order, so that an unknown
+/// one fails before anything is generated.
+pub fn file_format_tokens(format: &str) -> Result<Vec<String>> {
+    format_tokens(
+        format,
+        &FILE_FORMAT_TOKENS,
+        &format!("the file format {}", format),
+    )
+}
+
+/// Replaces the tokens of a file header or footer format with the values of a file of the
+/// prompt: its `{path}`, its `{language}`, its `{tokens}`, empty if they weren't counted, and
+/// its 1-based `{index}` among the files of the prompt.
+pub fn resolve_file_format(format: &str, file: &serde_json::Value, index: usize) -> String {
+    let text = |key: &str| file.get(key).and_then(|value| value.as_str()).unwrap_or("");
+    replace_tokens(format, |token| match token {
+        "path" => Some(text("path").to_string()),
+        "language" => Some(text("language").to_string()),
+        "tokens" => Some(
+            file.get("token_count")
+                .and_then(|tokens| tokens.as_u64())
+                .map(|tokens| tokens.to_string())
+                .unwrap_or_default(),
+        ),
+        "index" => Some(index.to_string()),
+        _ => None,
+    })
 }
 
 /// Enum to represent the output format.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub enum OutputFormat {
+    #[default]
     Markdown,
     Json,
     Xml,
+    /// The document of the JSON output, with the same keys, in YAML.
+    Yaml,
+    /// A JSON record per line, see
+    /// [`Code2PromptSession::write_ndjson`](crate::session::Code2PromptSession::write_ndjson).
+    Ndjson,
+    /// A self-contained HTML page for people, see [`crate::html::render_html`]. Needs the
+    /// html feature.
+    Html,
 }
 
 impl FromStr for OutputFormat {
@@ -118,16 +1240,35 @@ impl FromStr for OutputFormat {
             "markdown" | "md" => Ok(OutputFormat::Markdown),
             "json" => Ok(OutputFormat::Json),
             "xml" => Ok(OutputFormat::Xml),
+            "yaml" | "yml" => Ok(OutputFormat::Yaml),
+            "ndjson" | "jsonl" => Ok(OutputFormat::Ndjson),
+            "html" | "htm" => Ok(OutputFormat::Html),
             _ => Err(anyhow!(
-                "Invalid output format: {}. Allowed values: markdown, json, xml",
+                "Invalid output format: {}. Allowed values: markdown, json, xml, yaml, ndjson, html",
                 s
             )),
         }
     }
 }
 
-impl Default for OutputFormat {
-    fn default() -> Self {
-        OutputFormat::Markdown
+impl OutputFormat {
+    /// The extension of a file written in the format, e.g. `md` for Markdown.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Markdown => "md",
+            OutputFormat::Json => "json",
+            OutputFormat::Xml => "xml",
+            OutputFormat::Yaml => "yaml",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Html => "html",
+        }
+    }
+
+    /// Fails if the format needs a feature code2prompt was built without.
+    pub fn ensure_supported(&self) -> Result<()> {
+        if *self == OutputFormat::Html && !cfg!(feature = "html") {
+            bail!("the html output format needs code2prompt built with the html feature");
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,372 @@
+//! This module finds the templates a prompt can be rendered with by name: the templates of the
+//! `.code2prompt/templates` directories of the repository and the built-in templates embedded
+//! in the binary.
+//!
+//! The templates directories are looked up in the target path, then in its parents up to the
+//! root of the git repository. A template `review` is the `review.hbs` file of the nearest
+//! directory that has one; a repository template takes precedence over a built-in template of
+//! the same name.
+
+use crate::front_matter::split_front_matter;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The directory of the repository templates, relative to a directory of the repository.
+pub const TEMPLATES_DIR: &str = ".code2prompt/templates";
+
+/// The extension of the template files.
+const TEMPLATE_EXTENSION: &str = "hbs";
+
+/// A section of the prompt that is only filled when its option is passed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplateSection {
+    /// A git diff, of the working tree, between references, of a stash or of a commit.
+    GitDiff,
+    /// A git log, between references or of a revision range.
+    GitLog,
+}
+
+impl TemplateSection {
+    /// Returns the key of the section, e.g. `git_diff`.
+    pub fn key(&self) -> &'static str {
+        match self {
+            TemplateSection::GitDiff => "git_diff",
+            TemplateSection::GitLog => "git_log",
+        }
+    }
+
+    /// Returns the name of the section in a sentence, e.g. `git diff`.
+    pub fn label(&self) -> &'static str {
+        match self {
+            TemplateSection::GitDiff => "git diff",
+            TemplateSection::GitLog => "git log",
+        }
+    }
+}
+
+/// A template embedded in the binary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuiltinTemplate {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub content: &'static str,
+    /// The optional sections the template expects to be filled.
+    pub sections: &'static [TemplateSection],
+}
+
+/// The built-in templates, by name.
+pub const BUILTIN_TEMPLATES: &[BuiltinTemplate] = &[
+    BuiltinTemplate {
+        name: "binary-exploitation-ctf-solver",
+        description: "Solve a binary exploitation CTF challenge",
+        content: include_str!("../templates/binary-exploitation-ctf-solver.hbs"),
+        sections: &[],
+    },
+    BuiltinTemplate {
+        name: "claude-xml",
+        description: "The codebase in XML tags, for Claude",
+        content: include_str!("../templates/claude-xml.hbs"),
+        sections: &[],
+    },
+    BuiltinTemplate {
+        name: "clean-up-code",
+        description: "Clean up the code and improve its quality",
+        content: include_str!("../templates/clean-up-code.hbs"),
+        sections: &[],
+    },
+    BuiltinTemplate {
+        name: "cryptography-ctf-solver",
+        description: "Solve a cryptography CTF challenge",
+        content: include_str!("../templates/cryptography-ctf-solver.hbs"),
+        sections: &[],
+    },
+    BuiltinTemplate {
+        name: "document-the-code",
+        description: "Add documentation comments to the code",
+        content: include_str!("../templates/document-the-code.hbs"),
+        sections: &[],
+    },
+    BuiltinTemplate {
+        name: "explain-codebase",
+        description: "Explain the codebase to a new contributor",
+        content: include_str!("../templates/explain-codebase.hbs"),
+        sections: &[],
+    },
+    BuiltinTemplate {
+        name: "find-security-vulnerabilities",
+        description: "Find the security vulnerabilities of the code",
+        content: include_str!("../templates/find-security-vulnerabilities.hbs"),
+        sections: &[],
+    },
+    BuiltinTemplate {
+        name: "fix-bugs",
+        description: "Track down and fix reported bugs",
+        content: include_str!("../templates/fix-bugs.hbs"),
+        sections: &[],
+    },
+    BuiltinTemplate {
+        name: "improve-performance",
+        description: "Make the code faster and more efficient",
+        content: include_str!("../templates/improve-performance.hbs"),
+        sections: &[],
+    },
+    BuiltinTemplate {
+        name: "pr",
+        description: "Write a pull request description from the changed files of a branch",
+        content: include_str!("../templates/pr.hbs"),
+        sections: &[TemplateSection::GitDiff, TemplateSection::GitLog],
+    },
+    BuiltinTemplate {
+        name: "pr-description",
+        description: "Write a pull request description for the changes",
+        content: include_str!("../templates/pr-description.hbs"),
+        sections: &[TemplateSection::GitDiff, TemplateSection::GitLog],
+    },
+    BuiltinTemplate {
+        name: "refactor",
+        description: "Refactor the code to improve its design",
+        content: include_str!("../templates/refactor.hbs"),
+        sections: &[],
+    },
+    BuiltinTemplate {
+        name: "reverse-engineering-ctf-solver",
+        description: "Solve a reverse engineering CTF challenge",
+        content: include_str!("../templates/reverse-engineering-ctf-solver.hbs"),
+        sections: &[],
+    },
+    BuiltinTemplate {
+        name: "security-review",
+        description: "Review the code for security vulnerabilities",
+        content: include_str!("../templates/security-review.hbs"),
+        sections: &[],
+    },
+    BuiltinTemplate {
+        name: "web-ctf-solver",
+        description: "Solve a web exploitation CTF challenge",
+        content: include_str!("../templates/web-ctf-solver.hbs"),
+        sections: &[],
+    },
+    BuiltinTemplate {
+        name: "write-git-commit",
+        description: "Write a commit message for the git diff",
+        content: include_str!("../templates/write-git-commit.hbs"),
+        sections: &[TemplateSection::GitDiff],
+    },
+    BuiltinTemplate {
+        name: "write-github-pull-request",
+        description: "Write a pull request description for the git diff",
+        content: include_str!("../templates/write-github-pull-request.hbs"),
+        sections: &[TemplateSection::GitDiff, TemplateSection::GitLog],
+    },
+    BuiltinTemplate {
+        name: "write-github-readme",
+        description: "Write a README for the project",
+        content: include_str!("../templates/write-github-readme.hbs"),
+        sections: &[],
+    },
+    BuiltinTemplate {
+        name: "write-tests",
+        description: "Write tests for the files",
+        content: include_str!("../templates/write-tests.hbs"),
+        sections: &[],
+    },
+];
+
+/// Where a template comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateSource {
+    /// A file of a repository templates directory.
+    Repository(PathBuf),
+    /// A template embedded in the binary.
+    Builtin,
+}
+
+/// A template that can be used by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateInfo {
+    pub name: String,
+    /// The first line of the template, or the description of a built-in template.
+    pub description: String,
+    pub source: TemplateSource,
+    /// True if the template is a repository template that takes precedence over a built-in
+    /// template of the same name.
+    pub overrides_builtin: bool,
+    /// The optional sections a built-in template expects, none for a repository template.
+    pub sections: Vec<TemplateSection>,
+}
+
+/// A template found by name, with its content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamedTemplate {
+    pub info: TemplateInfo,
+    pub content: String,
+}
+
+/// Returns the templates directories of a path, the nearest first: the one of the path, then
+/// the ones of its parents up to the root of the git repository. Outside a git repository,
+/// only the directory of the path itself is looked at.
+pub fn template_dirs(path: &Path) -> Vec<PathBuf> {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let start = if path.is_file() {
+        path.parent().map(Path::to_path_buf).unwrap_or(path)
+    } else {
+        path
+    };
+    let in_repository = start.ancestors().any(|dir| dir.join(".git").exists());
+
+    let mut dirs = Vec::new();
+    for dir in start.ancestors() {
+        let templates_dir = dir.join(TEMPLATES_DIR);
+        if templates_dir.is_dir() {
+            dirs.push(templates_dir);
+        }
+        if !in_repository || dir.join(".git").exists() {
+            break;
+        }
+    }
+    dirs
+}
+
+/// Lists the templates that can be used by name from a path, the repository templates then the
+/// built-in templates that they don't override, each by name.
+///
+/// # Arguments
+///
+/// * `path` - The target path, whose templates directories are looked up
+///
+/// # Returns
+///
+/// * `Result<Vec<TemplateInfo>>` - The templates, or an error if a templates directory or
+///   template can't be read
+pub fn list_templates(path: &Path) -> Result<Vec<TemplateInfo>> {
+    let mut templates: Vec<TemplateInfo> = Vec::new();
+    for dir in template_dirs(path) {
+        let entries = fs::read_dir(&dir)
+            .with_context(|| format!("Failed to read the templates directory {}", dir.display()))?;
+        for entry in entries {
+            let file_path = entry?.path();
+            let Some(name) = template_name(&file_path) else {
+                continue;
+            };
+            if templates.iter().any(|template| template.name == name) {
+                continue;
+            }
+            let content = read_template(&file_path)?;
+            templates.push(repository_template(name, file_path, &content));
+        }
+    }
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+
+    templates.extend(
+        BUILTIN_TEMPLATES
+            .iter()
+            .filter(|builtin| {
+                !templates
+                    .iter()
+                    .any(|template| template.name == builtin.name)
+            })
+            .map(builtin_info)
+            .collect::<Vec<_>>(),
+    );
+    Ok(templates)
+}
+
+/// Finds a template by name, in the templates directories of a path then among the built-in
+/// templates.
+///
+/// # Arguments
+///
+/// * `name` - The name of the template, with or without the `.hbs` extension
+/// * `path` - The target path, whose templates directories are looked up
+///
+/// # Returns
+///
+/// * `Result<Option<NamedTemplate>>` - The template, none if no template has this name, or an
+///   error if the repository template can't be read
+pub fn find_template(name: &str, path: &Path) -> Result<Option<NamedTemplate>> {
+    let name = name
+        .strip_suffix(&format!(".{}", TEMPLATE_EXTENSION))
+        .unwrap_or(name);
+    for dir in template_dirs(path) {
+        let file_path = dir.join(format!("{}.{}", name, TEMPLATE_EXTENSION));
+        if file_path.is_file() {
+            let content = read_template(&file_path)?;
+            return Ok(Some(NamedTemplate {
+                info: repository_template(name.to_string(), file_path, &content),
+                content,
+            }));
+        }
+    }
+    Ok(builtin_template(name).map(|builtin| NamedTemplate {
+        info: builtin_info(builtin),
+        content: builtin.content.to_string(),
+    }))
+}
+
+/// Returns the built-in template of a name, without the repository templates that could
+/// override it.
+pub fn builtin_template(name: &str) -> Option<&'static BuiltinTemplate> {
+    BUILTIN_TEMPLATES
+        .iter()
+        .find(|builtin| builtin.name == name)
+}
+
+/// Returns the description of a template, the first non-empty line after its front-matter,
+/// without the delimiters of a Handlebars comment.
+///
+/// # Examples
+///
+/// * `{{!-- Review the changes --}}` - `Review the changes`
+/// * `Project Path: {{ absolute_code_path }}` - unchanged
+pub fn template_description(content: &str) -> String {
+    let content = split_front_matter(content).map_or(content, |(_, body)| body);
+    let line = content
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+    line.strip_prefix("{{!--")
+        .and_then(|comment| comment.strip_suffix("--}}"))
+        .or_else(|| {
+            line.strip_prefix("{{!")
+                .and_then(|comment| comment.strip_suffix("}}"))
+        })
+        .unwrap_or(line)
+        .trim()
+        .to_string()
+}
+
+/// Returns the name of a template file, none if it isn't a `.hbs` file.
+fn template_name(path: &Path) -> Option<String> {
+    if !path.is_file() || path.extension().is_none_or(|ext| ext != TEMPLATE_EXTENSION) {
+        return None;
+    }
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+}
+
+fn read_template(path: &Path) -> Result<String> {
+    fs::read_to_string(path)
+        .with_context(|| format!("Failed to read the template {}", path.display()))
+}
+
+fn repository_template(name: String, path: PathBuf, content: &str) -> TemplateInfo {
+    TemplateInfo {
+        overrides_builtin: builtin_template(&name).is_some(),
+        name,
+        description: template_description(content),
+        source: TemplateSource::Repository(path),
+        sections: Vec::new(),
+    }
+}
+
+fn builtin_info(builtin: &BuiltinTemplate) -> TemplateInfo {
+    TemplateInfo {
+        name: builtin.name.to_string(),
+        description: builtin.description.to_string(),
+        source: TemplateSource::Builtin,
+        overrides_builtin: false,
+        sections: builtin.sections.to_vec(),
+    }
+}
@@ -0,0 +1,172 @@
+//! This module caches the token counts of the files on disk, so that the unchanged files
+//! aren't tokenized again on the next run.
+//!
+//! The counts are keyed by the SHA-256 hash of the rendered code of the file and the name of
+//! the encoding. The cache is best effort: a missing, unreadable or corrupted cache file is
+//! treated as empty, and a failure to save it is only logged.
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The name of the cache file in the cache directory.
+pub const CACHE_FILE_NAME: &str = "token_counts.json";
+
+/// The version of the cache file format, the caches of other versions are discarded.
+const CACHE_VERSION: u32 = 1;
+
+/// Number of entries above which only the entries used by the run are saved.
+const MAX_ENTRIES: usize = 100_000;
+
+/// The content of the cache file.
+#[derive(Deserialize)]
+struct CacheFile {
+    version: u32,
+    entries: HashMap<String, usize>,
+}
+
+/// The content of the cache file, borrowed to save it.
+#[derive(Serialize)]
+struct CacheFileRef<'a> {
+    version: u32,
+    entries: &'a HashMap<String, usize>,
+}
+
+/// The token counts of the files, loaded from the cache file of a directory.
+#[derive(Debug)]
+pub struct TokenCache {
+    path: PathBuf,
+    entries: HashMap<String, usize>,
+    /// The keys looked up or inserted during the run.
+    used: HashSet<String>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl TokenCache {
+    /// Returns the default cache directory, the `code2prompt` directory of the platform cache
+    /// directory (e.g. `~/.cache/code2prompt` on Linux).
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("code2prompt"))
+    }
+
+    /// Opens the cache of a directory, empty if the cache file is missing or can't be read.
+    pub fn open(dir: &Path) -> Self {
+        let path = dir.join(CACHE_FILE_NAME);
+        let entries = match fs::read(&path) {
+            Ok(content) => match serde_json::from_slice::<CacheFile>(&content) {
+                Ok(cache) if cache.version == CACHE_VERSION => cache.entries,
+                Ok(cache) => {
+                    debug!(
+                        "Discarding the token cache {} of version {}",
+                        path.display(),
+                        cache.version
+                    );
+                    HashMap::new()
+                }
+                Err(e) => {
+                    warn!(
+                        "The token cache {} is corrupted, the tokens are recounted: {}",
+                        path.display(),
+                        e
+                    );
+                    HashMap::new()
+                }
+            },
+            Err(e) if e.kind() == ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                warn!("Failed to read the token cache {}: {}", path.display(), e);
+                HashMap::new()
+            }
+        };
+        Self {
+            path,
+            entries,
+            used: HashSet::new(),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the cache key of the code counted with an encoding.
+    pub fn key(code: &str, encoding: &str) -> String {
+        format!("{}:{:x}", encoding, Sha256::digest(code.as_bytes()))
+    }
+
+    /// Returns the cached token count of a key, counting the hit or miss.
+    pub fn get(&self, key: &str) -> Option<usize> {
+        let token_count = self.entries.get(key).copied();
+        let counter = if token_count.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        token_count
+    }
+
+    /// Stores the token count of a key, and marks it as used by the run.
+    pub fn insert(&mut self, key: String, token_count: usize) {
+        self.entries.insert(key.clone(), token_count);
+        self.used.insert(key);
+    }
+
+    /// Returns the number of lookups that found a token count.
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of lookups that didn't find a token count.
+    pub fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Saves the cache if any token count was added, replacing the cache file atomically.
+    ///
+    /// When the cache grows over `MAX_ENTRIES` entries, only the entries used by the run are
+    /// kept.
+    pub fn save(&mut self) -> Result<()> {
+        if self.misses() == 0 {
+            return Ok(());
+        }
+        if self.entries.len() > MAX_ENTRIES {
+            let used = &self.used;
+            self.entries.retain(|key, _| used.contains(key));
+        }
+
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create the cache directory {}", dir.display()))?;
+        let mut file = tempfile::NamedTempFile::new_in(dir)?;
+        let mut writer = BufWriter::new(file.as_file_mut());
+        serde_json::to_writer(
+            &mut writer,
+            &CacheFileRef {
+                version: CACHE_VERSION,
+                entries: &self.entries,
+            },
+        )?;
+        writer.flush()?;
+        drop(writer);
+        file.persist(&self.path)
+            .with_context(|| format!("Failed to save the token cache {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+/// Deletes the cache file of a directory, if any.
+pub fn clear_cache(dir: &Path) -> Result<()> {
+    let path = dir.join(CACHE_FILE_NAME);
+    match fs::remove_file(&path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("Failed to delete the token cache {}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,184 @@
+//! This module aggregates the token counts of the files up the directory tree, to show where
+//! the tokens of a prompt go, and summarizes the files of each directory.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Component, Path};
+
+/// A file or directory of the token map.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TokenMapNode {
+    pub name: String,
+    /// The `/`-separated path of the entry, empty for the root.
+    pub path: String,
+    /// The token count of the file, or the sum of the files below the directory.
+    pub tokens: usize,
+    /// The share of the tokens of all the files, in percent.
+    pub percentage: f64,
+    pub is_dir: bool,
+    /// The entries of a directory, by descending token count then by name. Empty for the
+    /// directories below the depth limit.
+    pub children: Vec<TokenMapNode>,
+}
+
+/// The token counts gathered for a directory before the entries are sorted.
+#[derive(Default)]
+struct DirectoryTokens {
+    tokens: usize,
+    is_dir: bool,
+    children: BTreeMap<String, DirectoryTokens>,
+}
+
+/// Builds the token map of the files from the token count they were annotated with.
+///
+/// Files without a token count are left out.
+///
+/// # Arguments
+///
+/// * `files` - The JSON file representations, with their `path` and `token_count`
+/// * `max_depth` - The number of directory levels listed, every level if unset. Deeper
+///   directories are collapsed into their parent, whose token count still covers them.
+///
+/// # Returns
+///
+/// * `TokenMapNode` - The root of the token map
+pub fn build_token_map(files: &[Value], max_depth: Option<usize>) -> TokenMapNode {
+    let mut root = DirectoryTokens {
+        is_dir: true,
+        ..Default::default()
+    };
+    for file in files {
+        let (Some(path), Some(tokens)) = (
+            file.get("path").and_then(|path| path.as_str()),
+            file.get("token_count").and_then(|tokens| tokens.as_u64()),
+        ) else {
+            continue;
+        };
+        let tokens = tokens as usize;
+        let components: Vec<&str> = Path::new(path)
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => name.to_str(),
+                _ => None,
+            })
+            .collect();
+
+        root.tokens += tokens;
+        let mut node = &mut root;
+        for (index, name) in components.iter().enumerate() {
+            node = node.children.entry(name.to_string()).or_default();
+            node.tokens += tokens;
+            node.is_dir = index + 1 < components.len();
+        }
+    }
+
+    let total = root.tokens;
+    to_node(String::new(), String::new(), root, total, 0, max_depth)
+}
+
+/// Turns the gathered token counts into a sorted node, collapsing the levels below `max_depth`.
+fn to_node(
+    name: String,
+    path: String,
+    directory: DirectoryTokens,
+    total: usize,
+    depth: usize,
+    max_depth: Option<usize>,
+) -> TokenMapNode {
+    let mut children = Vec::new();
+    if max_depth.is_none_or(|max_depth| depth < max_depth) {
+        children = directory
+            .children
+            .into_iter()
+            .map(|(child_name, child)| {
+                let child_path = if path.is_empty() {
+                    child_name.clone()
+                } else {
+                    format!("{}/{}", path, child_name)
+                };
+                to_node(child_name, child_path, child, total, depth + 1, max_depth)
+            })
+            .collect();
+        children.sort_by(|a: &TokenMapNode, b| {
+            b.tokens.cmp(&a.tokens).then_with(|| a.name.cmp(&b.name))
+        });
+    }
+
+    TokenMapNode {
+        name,
+        path,
+        tokens: directory.tokens,
+        percentage: if total == 0 {
+            0.0
+        } else {
+            directory.tokens as f64 / total as f64 * 100.0
+        },
+        is_dir: directory.is_dir,
+        children,
+    }
+}
+
+/// The files of a directory, for the directory summary.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DirectorySummary {
+    /// The `/`-separated path of the directory, `.` for the files at the root.
+    pub path: String,
+    /// The number of files in the directory and below.
+    pub files: usize,
+    /// The size of the files in bytes.
+    pub size: u64,
+    /// The token count of the files, without the files that weren't counted.
+    pub tokens: usize,
+}
+
+/// Summarizes the files per directory, from the `size` and `token_count` they were annotated
+/// with.
+///
+/// # Arguments
+///
+/// * `files` - The JSON file representations, with their `path`, `size` and `token_count`
+/// * `depth` - The number of directory levels the files are grouped by: with 1, the files
+///   are grouped by top-level directory. The files above that depth are grouped under their
+///   own directory, and the files at the root under `.`.
+///
+/// # Returns
+///
+/// * `Vec<DirectorySummary>` - The directories, by path
+pub fn summarize_directories(files: &[Value], depth: usize) -> Vec<DirectorySummary> {
+    let mut directories: BTreeMap<String, DirectorySummary> = BTreeMap::new();
+    for file in files {
+        let Some(path) = file.get("path").and_then(|path| path.as_str()) else {
+            continue;
+        };
+        let components: Vec<&str> = Path::new(path)
+            .parent()
+            .into_iter()
+            .flat_map(Path::components)
+            .filter_map(|component| match component {
+                Component::Normal(name) => name.to_str(),
+                _ => None,
+            })
+            .take(depth)
+            .collect();
+        let directory = if components.is_empty() {
+            ".".to_string()
+        } else {
+            components.join("/")
+        };
+
+        let summary = directories
+            .entry(directory.clone())
+            .or_insert_with(|| DirectorySummary {
+                path: directory,
+                ..Default::default()
+            });
+        summary.files += 1;
+        summary.size += file.get("size").and_then(|size| size.as_u64()).unwrap_or(0);
+        summary.tokens += file
+            .get("token_count")
+            .and_then(|tokens| tokens.as_u64())
+            .unwrap_or(0) as usize;
+    }
+    directories.into_values().collect()
+}
@@ -1,11 +1,22 @@
 //! This module encapsulates the logic for counting the tokens in the rendered text.
+use log::debug;
+use serde::Serialize;
+use std::fmt;
 use std::str::FromStr;
 use std::sync::OnceLock;
 use tiktoken_rs::{cl100k_base, o200k_base, p50k_base, p50k_edit, r50k_base, CoreBPE};
-use log::debug;
+#[cfg(feature = "huggingface")]
+use {
+    anyhow::anyhow,
+    std::collections::HashMap,
+    std::path::{Path, PathBuf},
+    std::sync::{Arc, Mutex},
+    tokenizers::Tokenizer,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub enum TokenFormat {
+    #[default]
     Raw,
     Format,
 }
@@ -25,28 +36,119 @@ impl FromStr for TokenFormat {
     }
 }
 
-impl Default for TokenFormat {
-    fn default() -> Self {
-        TokenFormat::Raw
+/// How the tokens are counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenCountMode {
+    /// Counts the tokens with the tokenizer.
+    #[default]
+    Exact,
+    /// Estimates the counts from the length of the text, without loading the tokenizer.
+    Estimate,
+    /// Doesn't count the tokens.
+    Off,
+}
+
+/// Parses a string into a [`TokenCountMode`].
+impl FromStr for TokenCountMode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "exact" => Ok(TokenCountMode::Exact),
+            "estimate" => Ok(TokenCountMode::Estimate),
+            "off" => Ok(TokenCountMode::Off),
+            _ => Err(format!(
+                "Invalid token count mode: {}. Use 'exact', 'estimate' or 'off'.",
+                s
+            )),
+        }
     }
 }
 
-/// Tokenizer types supported by tiktoken.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Tokenizer types: the tiktoken encodings, and Hugging Face tokenizers with the
+/// `huggingface` feature.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub enum TokenizerType {
     O200kBase,
+    #[default]
     Cl100kBase,
     P50kBase,
     P50kEdit,
     R50kBase,
     Gpt2,
+    /// A Hugging Face tokenizer, loaded from a local `tokenizer.json` file.
+    #[cfg(feature = "huggingface")]
+    HuggingFace(PathBuf),
 }
 
-/// Returns a description of the tokenizer type.
+/// The encodings, by name, accepted by [`TokenizerType::from_str`].
+pub const ENCODING_NAMES: [(&str, TokenizerType); 6] = [
+    ("o200k_base", TokenizerType::O200kBase),
+    ("cl100k_base", TokenizerType::Cl100kBase),
+    ("p50k_base", TokenizerType::P50kBase),
+    ("p50k_edit", TokenizerType::P50kEdit),
+    ("r50k_base", TokenizerType::R50kBase),
+    ("gpt2", TokenizerType::Gpt2),
+];
+
+/// Model name prefixes and the encoding of the models, checked in order so that the most
+/// specific prefix wins (e.g. `gpt-4o` before `gpt-4`).
+pub const MODEL_PREFIXES: [(&str, TokenizerType); 22] = [
+    ("gpt-4o", TokenizerType::O200kBase),
+    ("chatgpt-4o", TokenizerType::O200kBase),
+    ("gpt-4.1", TokenizerType::O200kBase),
+    ("gpt-4.5", TokenizerType::O200kBase),
+    ("gpt-5", TokenizerType::O200kBase),
+    ("o1", TokenizerType::O200kBase),
+    ("o3", TokenizerType::O200kBase),
+    ("o4", TokenizerType::O200kBase),
+    ("gpt-4", TokenizerType::Cl100kBase),
+    ("gpt-3.5", TokenizerType::Cl100kBase),
+    ("gpt-35", TokenizerType::Cl100kBase),
+    ("text-embedding-ada-002", TokenizerType::Cl100kBase),
+    ("text-embedding-3", TokenizerType::Cl100kBase),
+    ("text-davinci-edit", TokenizerType::P50kEdit),
+    ("code-davinci-edit", TokenizerType::P50kEdit),
+    ("text-davinci-002", TokenizerType::P50kBase),
+    ("text-davinci-003", TokenizerType::P50kBase),
+    ("code-davinci", TokenizerType::P50kBase),
+    ("code-cushman", TokenizerType::P50kBase),
+    ("davinci", TokenizerType::R50kBase),
+    ("curie", TokenizerType::R50kBase),
+    ("babbage", TokenizerType::R50kBase),
+];
+
 impl TokenizerType {
+    /// Returns the name of the encoding, e.g. `o200k_base`, or `huggingface` for a Hugging Face
+    /// tokenizer.
+    pub fn encoding_name(&self) -> &'static str {
+        #[cfg(feature = "huggingface")]
+        if let TokenizerType::HuggingFace(_) = self {
+            return "huggingface";
+        }
+        ENCODING_NAMES
+            .iter()
+            .find(|(_, tokenizer_type)| tokenizer_type == self)
+            .map_or("", |(name, _)| name)
+    }
+
+    /// Returns the tokenizer type of a model, e.g. `gpt-4o` or `o3-mini`.
+    pub fn from_model(model: &str) -> Option<Self> {
+        let model = model.to_lowercase();
+        MODEL_PREFIXES
+            .iter()
+            .find(|(prefix, _)| {
+                model
+                    .strip_prefix(prefix)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with(['-', ':', '.']))
+            })
+            .map(|(_, tokenizer_type)| tokenizer_type.clone())
+    }
+
+    /// Returns a description of the tokenizer type.
     pub fn description(&self) -> &'static str {
         match self {
-            TokenizerType::O200kBase => "OpenAI models, ChatGPT-4o",
+            TokenizerType::O200kBase => "GPT-4o, GPT-4.1, GPT-5 and o-series models",
             TokenizerType::Cl100kBase => "ChatGPT models, text-embedding-ada-002",
             TokenizerType::P50kBase => "Code models, text-davinci-002, text-davinci-003",
             TokenizerType::P50kEdit => {
@@ -54,29 +156,86 @@ impl TokenizerType {
             }
             TokenizerType::R50kBase => "GPT-3 models like davinci",
             TokenizerType::Gpt2 => "GPT-2 tokenizer",
+            #[cfg(feature = "huggingface")]
+            TokenizerType::HuggingFace(_) => "Hugging Face tokenizer",
         }
     }
-}
 
-/// Parses a string into a [`TokenizerType`].
-impl FromStr for TokenizerType {
-    type Err = ();
+    /// Returns the average number of bytes per token of the encoding, measured on source code.
+    pub fn bytes_per_token(&self) -> f64 {
+        match self {
+            TokenizerType::O200kBase | TokenizerType::Cl100kBase => 4.0,
+            TokenizerType::P50kBase | TokenizerType::P50kEdit => 3.1,
+            TokenizerType::R50kBase | TokenizerType::Gpt2 => 2.3,
+            #[cfg(feature = "huggingface")]
+            TokenizerType::HuggingFace(_) => 3.6,
+        }
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "o200k" => Ok(TokenizerType::O200kBase),
-            "cl100k" => Ok(TokenizerType::Cl100kBase),
-            "p50k" => Ok(TokenizerType::P50kBase),
-            "p50k_edit" => Ok(TokenizerType::P50kEdit),
-            "r50k" | "gpt2" => Ok(TokenizerType::R50kBase),
-            _ => Err(()),
+    /// Returns true if the tokenizer was loaded in memory by a count.
+    pub fn is_loaded(&self) -> bool {
+        match self {
+            TokenizerType::O200kBase => O200K_BASE.get().is_some(),
+            TokenizerType::Cl100kBase => CL100K_BASE.get().is_some(),
+            TokenizerType::P50kBase => P50K_BASE.get().is_some(),
+            TokenizerType::P50kEdit => P50K_EDIT.get().is_some(),
+            TokenizerType::R50kBase | TokenizerType::Gpt2 => R50K_BASE.get().is_some(),
+            #[cfg(feature = "huggingface")]
+            TokenizerType::HuggingFace(path) => HF_TOKENIZERS.get().is_some_and(|tokenizers| {
+                tokenizers
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .contains_key(path)
+            }),
         }
     }
+
+    /// Loads the tokenizer, so that an invalid Hugging Face tokenizer file is reported before
+    /// any count. The tiktoken encodings always load.
+    pub fn load(&self) -> anyhow::Result<()> {
+        #[cfg(feature = "huggingface")]
+        if let TokenizerType::HuggingFace(path) = self {
+            hf_tokenizer(path)?;
+        }
+        Ok(())
+    }
 }
 
-impl Default for TokenizerType {
-    fn default() -> Self {
-        TokenizerType::Cl100kBase
+/// Displays the name of the encoding, or the path of the Hugging Face tokenizer file.
+impl fmt::Display for TokenizerType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "huggingface")]
+            TokenizerType::HuggingFace(path) => write!(f, "{}", path.display()),
+            _ => f.write_str(self.encoding_name()),
+        }
+    }
+}
+
+/// Parses an encoding name (e.g. `o200k_base` or `o200k`) or a model name (e.g. `gpt-4o`)
+/// into a [`TokenizerType`].
+impl FromStr for TokenizerType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_lowercase();
+        ENCODING_NAMES
+            .iter()
+            .find(|(encoding, _)| {
+                *encoding == name || encoding.strip_suffix("_base") == Some(name.as_str())
+            })
+            .map(|(_, tokenizer_type)| tokenizer_type.clone())
+            .or_else(|| TokenizerType::from_model(&name))
+            .ok_or_else(|| {
+                let encodings: Vec<&str> = ENCODING_NAMES.iter().map(|(name, _)| *name).collect();
+                let models: Vec<&str> = MODEL_PREFIXES.iter().map(|(prefix, _)| *prefix).collect();
+                format!(
+                    "Unknown encoding or model: {}. Supported encodings: {}. Supported models: {}",
+                    s,
+                    encodings.join(", "),
+                    models.join(", ")
+                )
+            })
     }
 }
 
@@ -86,6 +245,30 @@ static CL100K_BASE: OnceLock<CoreBPE> = OnceLock::new();
 static P50K_BASE: OnceLock<CoreBPE> = OnceLock::new();
 static P50K_EDIT: OnceLock<CoreBPE> = OnceLock::new();
 static R50K_BASE: OnceLock<CoreBPE> = OnceLock::new();
+#[cfg(feature = "huggingface")]
+static HF_TOKENIZERS: OnceLock<Mutex<HashMap<PathBuf, Arc<Tokenizer>>>> = OnceLock::new();
+
+/// Returns the Hugging Face tokenizer of a `tokenizer.json` file, loaded once per path.
+#[cfg(feature = "huggingface")]
+fn hf_tokenizer(path: &Path) -> anyhow::Result<Arc<Tokenizer>> {
+    let mut tokenizers = HF_TOKENIZERS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    if let Some(tokenizer) = tokenizers.get(path) {
+        return Ok(Arc::clone(tokenizer));
+    }
+    let tokenizer = Tokenizer::from_file(path).map_err(|e| {
+        anyhow!(
+            "Failed to load the Hugging Face tokenizer {}: {}",
+            path.display(),
+            e
+        )
+    })?;
+    let tokenizer = Arc::new(tokenizer);
+    tokenizers.insert(path.to_path_buf(), Arc::clone(&tokenizer));
+    Ok(tokenizer)
+}
 
 /// Counts the tokens in the provided text using the specified tokenizer type.
 ///
@@ -97,11 +280,32 @@ static R50K_BASE: OnceLock<CoreBPE> = OnceLock::new();
 /// # Returns
 ///
 /// * `usize` - The number of tokens in the text
+///
+/// # Panics
+///
+/// If the tokenizer is a Hugging Face tokenizer that fails to load or to encode the text,
+/// which [`TokenizerType::load`] checks beforehand.
 pub fn count_tokens(rendered: &str, tokenizer_type: &TokenizerType) -> usize {
     use std::time::Instant;
     let start = Instant::now();
 
     let bpe = match tokenizer_type {
+        #[cfg(feature = "huggingface")]
+        TokenizerType::HuggingFace(path) => {
+            let tokenizer = hf_tokenizer(path).unwrap_or_else(|e| panic!("{:#}", e));
+            // A text the tokenizer rejects is estimated rather than failing the run
+            return match tokenizer.encode(rendered, false) {
+                Ok(encoding) => encoding.len(),
+                Err(e) => {
+                    log::warn!(
+                        "Failed to tokenize with the Hugging Face tokenizer {}, estimating the tokens: {}",
+                        path.display(),
+                        e
+                    );
+                    estimate_tokens(rendered, tokenizer_type)
+                }
+            };
+        }
         TokenizerType::O200kBase => O200K_BASE.get_or_init(|| o200k_base().unwrap()),
         TokenizerType::Cl100kBase => CL100K_BASE.get_or_init(|| cl100k_base().unwrap()),
         TokenizerType::P50kBase => P50K_BASE.get_or_init(|| p50k_base().unwrap()),
@@ -123,3 +327,17 @@ pub fn count_tokens(rendered: &str, tokenizer_type: &TokenizerType) -> usize {
 
     token_count
 }
+
+/// Estimates the tokens in the text from its length, without loading the tokenizer.
+///
+/// # Arguments
+///
+/// * `text` - The text to estimate the tokens of
+/// * `tokenizer_type` - The tokenizer encoding whose average token length is used
+///
+/// # Returns
+///
+/// * `usize` - The estimated number of tokens in the text
+pub fn estimate_tokens(text: &str, tokenizer_type: &TokenizerType) -> usize {
+    (text.len() as f64 / tokenizer_type.bytes_per_token()).ceil() as usize
+}
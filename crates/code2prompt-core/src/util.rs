@@ -13,3 +13,107 @@ pub fn strip_utf8_bom(data: &[u8]) -> &[u8] {
         data
     }
 }
+
+/// The content of a file decoded as text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedText {
+    pub text: String,
+    /// What was converted or replaced when the content wasn't plain UTF-8.
+    pub warning: Option<String>,
+}
+
+/// Decodes the content of a file as text, or returns `None` for binary content.
+///
+/// UTF-8 (with or without a BOM) and UTF-16 with a BOM are decoded; other content containing
+/// a NUL byte is considered binary. Invalid UTF-8 sequences, e.g. in Latin-1 files, are
+/// replaced with U+FFFD and reported in the warning.
+pub fn decode_text(data: &[u8]) -> Option<DecodedText> {
+    let utf16 = match data {
+        [0xFF, 0xFE, rest @ ..] => {
+            Some(("UTF-16LE", rest, u16::from_le_bytes as fn([u8; 2]) -> u16))
+        }
+        [0xFE, 0xFF, rest @ ..] => {
+            Some(("UTF-16BE", rest, u16::from_be_bytes as fn([u8; 2]) -> u16))
+        }
+        _ => None,
+    };
+    if let Some((encoding, rest, from_bytes)) = utf16 {
+        let mut chunks = rest.chunks_exact(2);
+        let units: Vec<u16> = chunks
+            .by_ref()
+            .map(|pair| from_bytes([pair[0], pair[1]]))
+            .collect();
+        let mut text = String::from_utf16_lossy(&units);
+        if !chunks.remainder().is_empty() {
+            text.push(char::REPLACEMENT_CHARACTER);
+        }
+        return Some(DecodedText {
+            text,
+            warning: Some(format!("converted from {}", encoding)),
+        });
+    }
+
+    let data = strip_utf8_bom(data);
+    if data.contains(&0) {
+        return None;
+    }
+    match std::str::from_utf8(data) {
+        Ok(text) => Some(DecodedText {
+            text: text.to_string(),
+            warning: None,
+        }),
+        Err(_) => {
+            let text = String::from_utf8_lossy(data).into_owned();
+            let replaced = text.matches(char::REPLACEMENT_CHARACTER).count();
+            Some(DecodedText {
+                text,
+                warning: Some(format!(
+                    "invalid UTF-8, {} invalid sequence(s) replaced",
+                    replaced
+                )),
+            })
+        }
+    }
+}
+
+/// Returns the MIME type of binary content from its magic bytes, e.g. `image/png`, or
+/// `application/octet-stream` if it isn't known.
+pub fn sniff_mime_type(data: &[u8]) -> &'static str {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"BM", "image/bmp"),
+        (b"\x00\x00\x01\x00", "image/x-icon"),
+        (b"wOFF", "font/woff"),
+        (b"wOF2", "font/woff2"),
+        (b"\x00\x01\x00\x00", "font/ttf"),
+        (b"OTTO", "font/otf"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"\x00asm", "application/wasm"),
+    ];
+    if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        return "image/webp";
+    }
+    SIGNATURES
+        .iter()
+        .find(|(signature, _)| data.starts_with(signature))
+        .map_or("application/octet-stream", |(_, mime_type)| mime_type)
+}
+
+/// Formats a size in bytes for humans, e.g. `12.3 KB`.
+pub fn format_size(bytes: u64) -> String {
+    const KB: f64 = 1024.0;
+    const MB: f64 = 1024.0 * KB;
+    let bytes_f = bytes as f64;
+    if bytes_f >= MB {
+        format!("{:.1} MB", bytes_f / MB)
+    } else if bytes_f >= KB {
+        format!("{:.1} KB", bytes_f / KB)
+    } else {
+        format!("{} B", bytes)
+    }
+}
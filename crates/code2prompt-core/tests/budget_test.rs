@@ -0,0 +1,360 @@
+use code2prompt_core::budget::{fit_files, BudgetStrategy};
+use code2prompt_core::configuration::Code2PromptConfig;
+use code2prompt_core::filter::build_globset;
+use code2prompt_core::session::Code2PromptSession;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::fs;
+    use std::str::FromStr;
+    use tempfile::tempdir;
+
+    fn files() -> Vec<Value> {
+        vec![
+            json!({"path": "src/a.rs", "code": "a", "token_count": 30}),
+            json!({"path": "src/big.rs", "code": "b", "token_count": 50}),
+            json!({"path": "docs/c.md", "code": "c", "token_count": 20}),
+            json!({"path": "src/d.rs", "code": "d", "token_count": 10}),
+        ]
+    }
+
+    fn paths(files: &[Value]) -> Vec<&str> {
+        files
+            .iter()
+            .map(|file| file["path"].as_str().unwrap())
+            .collect()
+    }
+
+    fn word_count(text: &str) -> usize {
+        text.split_whitespace().count()
+    }
+
+    #[test]
+    fn test_budget_strategy_from_str() {
+        assert_eq!(
+            BudgetStrategy::from_str("drop-largest-first").unwrap(),
+            BudgetStrategy::DropLargestFirst
+        );
+        assert_eq!(
+            BudgetStrategy::from_str("Truncate-Tail").unwrap(),
+            BudgetStrategy::TruncateTail
+        );
+        assert!(BudgetStrategy::from_str("largest").is_err());
+    }
+
+    #[test]
+    fn test_fit_files_drop_strategies() {
+        let no_priority = build_globset(&[]);
+
+        // Everything fits
+        let (kept, omitted) = fit_files(
+            &files(),
+            110,
+            BudgetStrategy::DropLast,
+            &no_priority,
+            word_count,
+        );
+        assert_eq!(kept.len(), 4);
+        assert!(omitted.is_empty());
+
+        let (kept, omitted) = fit_files(
+            &files(),
+            60,
+            BudgetStrategy::DropLargestFirst,
+            &no_priority,
+            word_count,
+        );
+        assert_eq!(paths(&kept), ["src/a.rs", "docs/c.md", "src/d.rs"]);
+        assert_eq!(omitted.len(), 1);
+        assert_eq!(omitted[0].path, "src/big.rs");
+        assert_eq!(omitted[0].token_count, 50);
+        assert!(!omitted[0].truncated);
+
+        let (kept, omitted) = fit_files(
+            &files(),
+            60,
+            BudgetStrategy::DropLast,
+            &no_priority,
+            word_count,
+        );
+        assert_eq!(paths(&kept), ["src/a.rs"]);
+        let omitted: Vec<&str> = omitted.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(omitted, ["src/big.rs", "docs/c.md", "src/d.rs"]);
+
+        // Nothing fits
+        let (kept, omitted) = fit_files(
+            &files(),
+            5,
+            BudgetStrategy::DropLargestFirst,
+            &no_priority,
+            word_count,
+        );
+        assert!(kept.is_empty());
+        assert_eq!(
+            omitted.iter().map(|file| file.token_count).sum::<usize>(),
+            110
+        );
+    }
+
+    #[test]
+    fn test_fit_files_priority() {
+        let priority = build_globset(&["src/big.rs".to_string(), "*.md".to_string()]);
+        let (kept, omitted) = fit_files(
+            &files(),
+            80,
+            BudgetStrategy::Priority,
+            &priority,
+            word_count,
+        );
+        // The priority files take 70 tokens, then only src/d.rs still fits
+        assert_eq!(paths(&kept), ["src/big.rs", "docs/c.md", "src/d.rs"]);
+        assert_eq!(omitted.len(), 1);
+        assert_eq!(omitted[0].path, "src/a.rs");
+    }
+
+    #[test]
+    fn test_fit_files_truncate_tail() {
+        let body: String = (0..100).map(|line| format!("word{}\n", line)).collect();
+        let code = format!("```rs\n{}```", body);
+        let token_count = word_count(&code);
+        let files = vec![
+            json!({"path": "long.rs", "code": code, "token_count": token_count}),
+            json!({"path": "empty.rs", "code": null, "token_count": null}),
+        ];
+        let (kept, omitted) = fit_files(
+            &files,
+            50,
+            BudgetStrategy::TruncateTail,
+            &build_globset(&[]),
+            word_count,
+        );
+
+        assert_eq!(kept.len(), 2);
+        let code = kept[0]["code"].as_str().unwrap();
+        assert!(code.starts_with("```rs\nword0\n"));
+        assert!(code.ends_with("(truncated to fit the token budget)\n```"));
+        assert!(!code.contains("word99"));
+        assert_eq!(kept[0]["truncated"], true);
+        assert!(kept[0]["token_count"].as_u64().unwrap() <= 60);
+
+        assert_eq!(omitted.len(), 1);
+        assert_eq!(omitted[0].path, "long.rs");
+        assert!(omitted[0].truncated);
+        assert_eq!(
+            omitted[0].token_count as u64,
+            token_count as u64 - kept[0]["token_count"].as_u64().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_fit_files_truncate_tail_keeps_long_fence() {
+        let body: String = (0..100).map(|line| format!("word{}\n", line)).collect();
+        let code = format!("````markdown\n```sh\n{}```\n````", body);
+        let token_count = word_count(&code);
+        let files = vec![json!({"path": "README.md", "code": code, "token_count": token_count})];
+        let (kept, _) = fit_files(
+            &files,
+            50,
+            BudgetStrategy::TruncateTail,
+            &build_globset(&[]),
+            word_count,
+        );
+
+        let code = kept[0]["code"].as_str().unwrap();
+        assert!(code.starts_with("````markdown\n```sh\nword0\n"), "{}", code);
+        assert!(
+            code.ends_with("(truncated to fit the token budget)\n````"),
+            "{}",
+            code
+        );
+    }
+
+    #[test]
+    fn test_enforce_token_budget() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("small.txt"), "a small file").unwrap();
+        fs::write(
+            dir.path().join("large.txt"),
+            "many words in this file ".repeat(200),
+        )
+        .unwrap();
+
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .token_budget(Some(300))
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let rendered = session.generate_prompt().unwrap();
+
+        let report = session.data.token_budget.clone().unwrap();
+        assert!(report.tokens_before > 1000);
+        assert_eq!(report.tokens_after, rendered.token_count);
+        assert!(rendered.token_count <= 300);
+        assert!(!report.overhead_exceeds_budget());
+        assert_eq!(report.omitted.len(), 1);
+        assert_eq!(report.omitted[0].path, "large.txt");
+        assert_eq!(rendered.files, ["small.txt"]);
+        assert!(rendered
+            .prompt
+            .contains("Omitted to fit the token budget of 300 tokens:"));
+        assert!(rendered.prompt.contains("- `large.txt` ("));
+    }
+
+    #[test]
+    fn test_enforce_token_budget_overhead_exceeds_budget() {
+        let dir = tempdir().unwrap();
+        for index in 0..20 {
+            fs::write(dir.path().join(format!("file_{}.txt", index)), "content").unwrap();
+        }
+
+        // The source tree and template alone take more than 10 tokens
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .token_budget(Some(10))
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let rendered = session.generate_prompt().unwrap();
+
+        let report = session.data.token_budget.clone().unwrap();
+        assert!(report.overhead_exceeds_budget());
+        assert!(rendered.files.is_empty());
+        assert_eq!(report.omitted.len(), 20);
+        assert!(report.tokens_after > report.budget);
+        assert!(report.tokens_after < report.tokens_before);
+        // The source tree is kept since the budget only cuts files
+        assert!(rendered.prompt.contains("file_19.txt"));
+        assert!(!rendered.prompt.contains("content"));
+
+        // A budget of exactly the overhead fits the prompt without any file
+        let overhead = report.overhead;
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .token_budget(Some(overhead))
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        session.generate_prompt().unwrap();
+        let report = session.data.token_budget.clone().unwrap();
+        assert!(!report.overhead_exceeds_budget());
+    }
+
+    #[test]
+    fn test_fit_files_priority_files_are_dropped_last() {
+        let priority = build_globset(&["src/big.rs".to_string()]);
+        let (kept, omitted) = fit_files(
+            &files(),
+            60,
+            BudgetStrategy::DropLargestFirst,
+            &priority,
+            word_count,
+        );
+        // The largest files other than src/big.rs are dropped instead
+        assert_eq!(paths(&kept), ["src/big.rs", "src/d.rs"]);
+        assert!(omitted.iter().all(|file| !file.priority));
+
+        let priority = build_globset(&["src/d.rs".to_string()]);
+        let (kept, _) = fit_files(
+            &files(),
+            60,
+            BudgetStrategy::DropLast,
+            &priority,
+            word_count,
+        );
+        assert_eq!(paths(&kept), ["src/a.rs", "src/d.rs"]);
+
+        // The priority file alone exceeds the budget
+        let priority = build_globset(&["src/big.rs".to_string()]);
+        let (kept, omitted) = fit_files(
+            &files(),
+            40,
+            BudgetStrategy::DropLargestFirst,
+            &priority,
+            word_count,
+        );
+        assert!(kept.is_empty());
+        let priority_omitted: Vec<&str> = omitted
+            .iter()
+            .filter(|file| file.priority)
+            .map(|file| file.path.as_str())
+            .collect();
+        assert_eq!(priority_omitted, ["src/big.rs"]);
+    }
+
+    #[test]
+    fn test_fit_files_truncate_tail_cuts_priority_files_last() {
+        let code = |name: &str| -> String {
+            let body: String = (0..100).map(|line| format!("{}{}\n", name, line)).collect();
+            format!("```rs\n{}```", body)
+        };
+        let files: Vec<Value> = ["other", "wanted"]
+            .iter()
+            .map(|name| {
+                let code = code(name);
+                json!({"path": format!("{}.rs", name), "token_count": word_count(&code), "code": code})
+            })
+            .collect();
+        let wanted_tokens = files[1]["token_count"].as_u64().unwrap() as usize;
+        let priority = build_globset(&["wanted.rs".to_string()]);
+
+        let (kept, omitted) = fit_files(
+            &files,
+            wanted_tokens + 30,
+            BudgetStrategy::TruncateTail,
+            &priority,
+            word_count,
+        );
+        assert_eq!(paths(&kept), ["other.rs", "wanted.rs"]);
+        assert_eq!(kept[1], files[1]);
+        assert_eq!(kept[0]["truncated"], true);
+        assert_eq!(omitted.len(), 1);
+        assert!(!omitted[0].priority);
+
+        // Without room for the other file, it is omitted and the priority file is cut
+        let (kept, omitted) = fit_files(
+            &files,
+            wanted_tokens / 2,
+            BudgetStrategy::TruncateTail,
+            &priority,
+            word_count,
+        );
+        assert_eq!(paths(&kept), ["wanted.rs"]);
+        assert_eq!(kept[0]["truncated"], true);
+        assert_eq!(omitted.len(), 2);
+        assert!(omitted[1].priority && omitted[1].truncated);
+    }
+
+    #[test]
+    fn test_enforce_token_budget_keeps_priority_files() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("small.txt"), "a small file").unwrap();
+        fs::write(
+            dir.path().join("large.txt"),
+            "many words in this file ".repeat(200),
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("other.txt"),
+            "some other words ".repeat(400),
+        )
+        .unwrap();
+
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .token_budget(Some(1500))
+            .priority_patterns(vec!["large.txt".to_string()])
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let rendered = session.generate_prompt().unwrap();
+
+        let report = session.data.token_budget.clone().unwrap();
+        assert_eq!(report.priority_files, 1);
+        assert_eq!(report.priority_cut(), 0);
+        assert_eq!(rendered.files[0], "large.txt");
+        assert!(!rendered.files.contains(&"other.txt".to_string()));
+    }
+}
@@ -0,0 +1,302 @@
+use code2prompt_core::engine::{renderer, RenderOptions, TemplateEngine};
+use std::collections::HashMap;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::path::Path;
+    use std::str::FromStr;
+
+    /// The data of a prompt with every section of the default template filled.
+    #[cfg(feature = "jinja")]
+    fn fixture_context() -> serde_json::Value {
+        json!({
+            "absolute_code_path": "/repo",
+            "source_tree": "repo\n└── src\n    └── main.rs",
+            "dir_summary": true,
+            "directories": [{"path": "src", "files": 1, "size": 13, "tokens": 5}],
+            "files": [
+                {
+                    "path": "src/main.rs",
+                    "code": "```rust\nfn main() {}\n```",
+                    "token_count": 5,
+                    "last_commit": {
+                        "short_sha": "abc1234",
+                        "author": "Jane",
+                        "date": "2024-01-02",
+                        "summary": "Add main"
+                    }
+                },
+                {"path": "empty.txt", "code": ""}
+            ],
+            "token_count_estimated": true,
+            "token_budget": {"budget": 100, "omitted": [{"path": "big.rs", "token_count": 500, "truncated": true}]},
+            "removed_files": ["old.rs"],
+            "flags": {"has_tree": true, "has_diff": true},
+            "git_diff": "diff --git a/src/main.rs b/src/main.rs",
+            "git_show": "commit abc1234",
+            "git_relationship": {"from": "main", "to": "feature", "ahead": 2, "behind": 1, "merge_base": {"short_sha": "def5678", "diverged_at": "2024-01-01"}},
+            "git_diff_stat": {
+                "files": [
+                    {"path": "src/main.rs", "old_path": "main.rs", "insertions": 3, "deletions": 1, "is_binary": false},
+                    {"path": "logo.png", "is_binary": true}
+                ],
+                "files_changed": 2,
+                "insertions": 3,
+                "deletions": 1
+            },
+            "git_changelog": {"groups": {
+                "feat": [{"short_sha": "abc1234", "scope": "cli", "summary": "add main", "breaking": true}],
+                "fix": [{"short_sha": "fed4321", "summary": "fix typo", "breaking": false}]
+            }},
+            "contributors": [{"name": "Jane", "email": "jane@example.com", "commits": 2, "last_commit": "2024-01-02"}]
+        })
+    }
+
+    #[test]
+    fn test_template_engine_from_str_and_path() {
+        assert_eq!(
+            TemplateEngine::from_str("Jinja").unwrap(),
+            TemplateEngine::Jinja
+        );
+        assert_eq!(
+            TemplateEngine::from_str("hbs").unwrap(),
+            TemplateEngine::Handlebars
+        );
+        assert!(TemplateEngine::from_str("tera").is_err());
+        assert_eq!(TemplateEngine::default(), TemplateEngine::Handlebars);
+
+        assert_eq!(
+            TemplateEngine::from_path(Path::new("review.j2")),
+            Some(TemplateEngine::Jinja)
+        );
+        assert_eq!(
+            TemplateEngine::from_path(Path::new("templates/review.JINJA")),
+            Some(TemplateEngine::Jinja)
+        );
+        assert_eq!(TemplateEngine::from_path(Path::new("review.hbs")), None);
+        assert_eq!(TemplateEngine::from_path(Path::new("review")), None);
+    }
+
+    #[test]
+    fn test_handlebars_renderer() {
+        let partials = HashMap::from([("item".to_string(), "- {{path}}".to_string())]);
+        let items = renderer(
+            TemplateEngine::Handlebars,
+            "{{#each files}}\n{{> item}}\n\n{{/each}}\n",
+            "custom",
+            &partials,
+            &RenderOptions::default(),
+        )
+        .unwrap();
+        let rendered = items
+            .render(&json!({"files": [{"path": "a.rs"}, {"path": "b.rs"}]}))
+            .unwrap();
+        assert_eq!(rendered, "- a.rs\n- b.rs");
+
+        let error = renderer(
+            TemplateEngine::Handlebars,
+            "{{> missing}}",
+            "custom",
+            &HashMap::new(),
+            &RenderOptions::default(),
+        )
+        .err()
+        .unwrap();
+        assert!(error.to_string().contains("missing"), "{}", error);
+    }
+
+    #[test]
+    fn test_escape_html() {
+        let data = json!({"content": "Vec<Option<&str>>"});
+        let options = RenderOptions {
+            escape_html: true,
+            ..RenderOptions::default()
+        };
+        let rendered = renderer(
+            TemplateEngine::Handlebars,
+            "{{content}} {{{content}}}",
+            "custom",
+            &HashMap::new(),
+            &options,
+        )
+        .unwrap()
+        .render(&data)
+        .unwrap();
+        assert_eq!(
+            rendered,
+            "Vec&lt;Option&lt;&amp;str&gt;&gt; Vec<Option<&str>>"
+        );
+
+        #[cfg(feature = "jinja")]
+        for (options, expected) in [
+            (RenderOptions::default(), "Vec<Option<&str>>"),
+            (options, "Vec&lt;Option&lt;&amp;str&gt;&gt;"),
+        ] {
+            let rendered = renderer(
+                TemplateEngine::Jinja,
+                "{{ content }}",
+                "custom",
+                &HashMap::new(),
+                &options,
+            )
+            .unwrap()
+            .render(&data)
+            .unwrap();
+            assert_eq!(rendered, expected);
+        }
+    }
+
+    #[cfg(not(feature = "jinja"))]
+    #[test]
+    fn test_jinja_renderer_unavailable() {
+        let error = renderer(
+            TemplateEngine::Jinja,
+            "{{ path }}",
+            "custom",
+            &HashMap::new(),
+            &RenderOptions::default(),
+        )
+        .err()
+        .unwrap();
+        assert!(error.to_string().contains("jinja feature"), "{}", error);
+    }
+
+    #[cfg(feature = "jinja")]
+    #[test]
+    fn test_jinja_renderer() {
+        let partials = HashMap::from([("item".to_string(), "- {{ file.path }}".to_string())]);
+        let items = renderer(
+            TemplateEngine::Jinja,
+            "{% for file in files %}\n{% include \"item\" %}\n\n{% endfor %}\n<{{ missing }}>",
+            "custom",
+            &partials,
+            &RenderOptions::default(),
+        )
+        .unwrap();
+        let rendered = items
+            .render(&json!({"files": [{"path": "a.rs"}, {"path": "<b>.rs"}]}))
+            .unwrap();
+        assert_eq!(rendered, "- a.rs\n- <b>.rs\n<>");
+
+        let error = renderer(
+            TemplateEngine::Jinja,
+            "Files:\n{% for file in files %}",
+            "custom",
+            &HashMap::new(),
+            &RenderOptions::default(),
+        )
+        .err()
+        .unwrap()
+        .to_string();
+        assert!(
+            error.starts_with("Failed to register template custom at line 2"),
+            "{}",
+            error
+        );
+    }
+
+    #[cfg(feature = "jinja")]
+    #[test]
+    fn test_jinja_codeblock_function() {
+        let rendered = renderer(
+            TemplateEngine::Jinja,
+            "{{ codeblock(\"notes.md\", text) }}\n{{ codeblock(\"tree.txt\", missing) }}",
+            "custom",
+            &HashMap::new(),
+            &RenderOptions::default(),
+        )
+        .unwrap()
+        .render(&json!({"text": "```sh\nls\n```"}))
+        .unwrap();
+        assert_eq!(
+            rendered,
+            "````markdown\n```sh\nls\n```\n````\n```txt\n\n```"
+        );
+    }
+
+    #[cfg(feature = "jinja")]
+    #[test]
+    fn test_default_templates_render_the_same() {
+        let context = fixture_context();
+        let partials = HashMap::from([(
+            "file_block".to_string(),
+            include_str!("../src/default_file_block_md.hbs").to_string(),
+        )]);
+        let handlebars = renderer(
+            TemplateEngine::Handlebars,
+            include_str!("../src/default_template_md.hbs"),
+            "markdown",
+            &partials,
+            &RenderOptions::default(),
+        )
+        .unwrap()
+        .render(&context)
+        .unwrap();
+        let jinja = renderer(
+            TemplateEngine::Jinja,
+            include_str!("../src/default_template_md.j2"),
+            "markdown",
+            &HashMap::new(),
+            &RenderOptions::default(),
+        )
+        .unwrap()
+        .render(&context)
+        .unwrap();
+        assert_eq!(jinja, handlebars);
+        for expected in [
+            "`src/main.rs` (~5 tokens):",
+            " main.rs => src/main.rs | +3 -1\n logo.png | binary\n",
+            "- cli: add main [BREAKING] (abc1234)",
+            "| Jane <jane@example.com> | 2 | 2024-01-02 |",
+        ] {
+            assert!(jinja.contains(expected), "{}", jinja);
+        }
+    }
+
+    #[cfg(feature = "jinja")]
+    #[test]
+    fn test_session_renders_jinja_template() {
+        use code2prompt_core::configuration::Code2PromptConfig;
+        use code2prompt_core::session::Code2PromptSession;
+        use std::fs;
+        use tempfile::tempdir;
+
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+        fs::write(dir.path().join("lib.rs"), "pub fn run() {}\n").unwrap();
+        // With the table of contents and the file header and footer too
+        for (toc, formats) in [(false, false), (true, false), (false, true)] {
+            let header = formats.then(|| "===== {index}: {path} =====".to_string());
+            let footer = formats.then(|| "<<<END {path}>>>".to_string());
+            let mut session = Code2PromptSession::new(
+                Code2PromptConfig::builder()
+                    .path(dir.path().to_path_buf())
+                    .toc(toc)
+                    .file_header_format(header.clone())
+                    .file_footer_format(footer.clone())
+                    .build()
+                    .unwrap(),
+            );
+            let handlebars = session.generate_prompt().unwrap().prompt;
+
+            let mut session = Code2PromptSession::new(
+                Code2PromptConfig::builder()
+                    .path(dir.path().to_path_buf())
+                    .template_str(include_str!("../src/default_template_md.j2").to_string())
+                    .template_name("custom".to_string())
+                    .template_engine(TemplateEngine::Jinja)
+                    .toc(toc)
+                    .file_header_format(header)
+                    .file_footer_format(footer)
+                    .build()
+                    .unwrap(),
+            );
+            let jinja = session.generate_prompt().unwrap().prompt;
+            assert_eq!(jinja, handlebars);
+            assert_eq!(jinja.contains("Table of Contents:"), toc);
+            assert_eq!(jinja.contains("<<<END main.rs>>>"), formats);
+        }
+    }
+}
@@ -12,10 +12,12 @@ use tempfile::{tempdir, TempDir};
 fn create_temp_file(dir: &Path, name: &str, content: &str) {
     let file_path = dir.join(name);
     let parent_dir = file_path.parent().unwrap();
-    fs::create_dir_all(parent_dir).expect(&format!("Failed to create directory: {:?}", parent_dir));
-    let mut file =
-        File::create(&file_path).expect(&format!("Failed to create temp file: {:?}", file_path));
-    writeln!(file, "{}", content).expect(&format!("Failed to write to temp file: {:?}", file_path));
+    fs::create_dir_all(parent_dir)
+        .unwrap_or_else(|_| panic!("Failed to create directory: {:?}", parent_dir));
+    let mut file = File::create(&file_path)
+        .unwrap_or_else(|_| panic!("Failed to create temp file: {:?}", file_path));
+    writeln!(file, "{}", content)
+        .unwrap_or_else(|_| panic!("Failed to write to temp file: {:?}", file_path));
 }
 
 static TEST_DIR: Lazy<TempDir> = Lazy::new(|| {
@@ -69,13 +71,13 @@ mod tests {
     #[test]
     fn test_no_include_no_exclude_path() {
         let path = Path::new("src/main.rs");
-        let include_patterns = build_globset(&vec![]);
-        let exclude_patterns = build_globset(&vec![]);
+        let include_patterns = build_globset(&[]);
+        let exclude_patterns = build_globset(&[]);
         let include_priority = false;
 
         // ~~~ Must be included ~~~
         assert!(should_include_file(
-            &path,
+            path,
             &include_patterns,
             &exclude_patterns,
             include_priority
@@ -86,8 +88,8 @@ mod tests {
     fn test_no_include_no_exclude_empty() {
         let base_path = TEST_DIR.path();
 
-        let include_patterns = build_globset(&vec![]);
-        let exclude_patterns = build_globset(&vec![]);
+        let include_patterns = build_globset(&[]);
+        let exclude_patterns = build_globset(&[]);
         let include_priority = true;
 
         // ~~~ Must be included ~~~
@@ -120,11 +122,11 @@ mod tests {
     #[test]
     fn test_no_include_exclude_path() {
         let path = Path::new("src/main.rs");
-        let include_patterns = build_globset(&vec![]);
-        let exclude_patterns = build_globset(&vec!["*.rs".to_string()]);
+        let include_patterns = build_globset(&[]);
+        let exclude_patterns = build_globset(&["*.rs".to_string()]);
         let include_priority = false;
         assert!(!should_include_file(
-            &path,
+            path,
             &include_patterns,
             &exclude_patterns,
             include_priority
@@ -136,8 +138,8 @@ mod tests {
     fn test_no_include_exclude_by_filename() {
         let base_path = TEST_DIR.path();
 
-        let include_patterns = build_globset(&vec![]);
-        let exclude_patterns = build_globset(&vec!["default_template.hbs".to_string()]);
+        let include_patterns = build_globset(&[]);
+        let exclude_patterns = build_globset(&["default_template.hbs".to_string()]);
         let include_priority = false;
 
         // ~~~ Must be excluded ~~~
@@ -154,8 +156,8 @@ mod tests {
     fn test_no_include_exclude_path_patterns() {
         let base_path = TEST_DIR.path();
 
-        let include_patterns = build_globset(&vec![]);
-        let exclude_patterns = build_globset(&vec!["lowercase/{*.txt,*.py}".to_string()]);
+        let include_patterns = build_globset(&[]);
+        let exclude_patterns = build_globset(&["lowercase/{*.txt,*.py}".to_string()]);
         let include_priority = false;
 
         // ~~~ Must be excluded ~~~
@@ -171,7 +173,7 @@ mod tests {
             let relative_path = path.strip_prefix(base_path).unwrap();
 
             assert!(!should_include_file(
-                &relative_path,
+                relative_path,
                 &include_patterns,
                 &exclude_patterns,
                 include_priority
@@ -192,7 +194,7 @@ mod tests {
             let relative_path = path.strip_prefix(base_path).unwrap();
 
             assert!(should_include_file(
-                &relative_path,
+                relative_path,
                 &include_patterns,
                 &exclude_patterns,
                 include_priority
@@ -204,8 +206,8 @@ mod tests {
     fn test_no_include_exclude_patterns() {
         let base_path = TEST_DIR.path();
 
-        let include_patterns = build_globset(&vec![]);
-        let exclude_patterns = build_globset(&vec!["*.txt".to_string()]);
+        let include_patterns = build_globset(&[]);
+        let exclude_patterns = build_globset(&["*.txt".to_string()]);
         let include_priority = false;
 
         // ~~~ Must be excluded ~~~
@@ -250,9 +252,8 @@ mod tests {
     fn test_no_include_exclude_files() {
         let base_path = TEST_DIR.path();
 
-        let include_patterns = build_globset(&vec![]);
-        let exclude_patterns =
-            build_globset(&vec!["**/foo.py".to_string(), "**/bar.py".to_string()]);
+        let include_patterns = build_globset(&[]);
+        let exclude_patterns = build_globset(&["**/foo.py".to_string(), "**/bar.py".to_string()]);
         let include_priority = false;
 
         // ~~~ Must be excluded ~~~
@@ -294,8 +295,8 @@ mod tests {
     fn test_no_include_exclude_folders() {
         let base_path = TEST_DIR.path();
 
-        let include_patterns = build_globset(&vec![]);
-        let exclude_patterns = build_globset(&vec!["**/lowercase/**".to_string()]);
+        let include_patterns = build_globset(&[]);
+        let exclude_patterns = build_globset(&["**/lowercase/**".to_string()]);
 
         let include_priority = false;
 
@@ -331,8 +332,8 @@ mod tests {
     fn test_include_no_exclude_patterns() {
         let base_path = TEST_DIR.path();
 
-        let include_patterns = build_globset(&vec!["*.py".to_string()]);
-        let exclude_patterns = build_globset(&vec![]);
+        let include_patterns = build_globset(&["*.py".to_string()]);
+        let exclude_patterns = build_globset(&[]);
         let include_priority = false;
 
         // ~~~ Must be included ~~~
@@ -378,8 +379,8 @@ mod tests {
     fn test_include_no_exclude_by_filename() {
         let base_path = TEST_DIR.path();
 
-        let include_patterns = build_globset(&vec!["default_template.hbs".to_string()]);
-        let exclude_patterns = build_globset(&vec![]);
+        let include_patterns = build_globset(&["default_template.hbs".to_string()]);
+        let exclude_patterns = build_globset(&[]);
         let include_priority = false;
 
         // ~~~ Must be excluded ~~~
@@ -408,8 +409,8 @@ mod tests {
         let base_path = TEST_DIR.path();
 
         // let include_patterns = vec!["lowercase/*.txt".to_string(), "lowercase/*.py".to_string()];
-        let include_patterns = build_globset(&vec!["lowercase/{*.txt,*.py}".to_string()]);
-        let exclude_patterns = build_globset(&vec![]);
+        let include_patterns = build_globset(&["lowercase/{*.txt,*.py}".to_string()]);
+        let exclude_patterns = build_globset(&[]);
         let include_priority = false;
 
         // ~~~ Must be included ~~~
@@ -425,7 +426,7 @@ mod tests {
             let relative_path = path.strip_prefix(base_path).unwrap();
 
             assert!(should_include_file(
-                &relative_path,
+                relative_path,
                 &include_patterns,
                 &exclude_patterns,
                 include_priority
@@ -446,7 +447,7 @@ mod tests {
             let relative_path = path.strip_prefix(base_path).unwrap();
 
             assert!(!should_include_file(
-                &relative_path,
+                relative_path,
                 &include_patterns,
                 &exclude_patterns,
                 include_priority
@@ -458,8 +459,8 @@ mod tests {
     fn test_include_no_exclude_folders() {
         let base_path = TEST_DIR.path();
 
-        let include_patterns = build_globset(&vec!["**/lowercase/**".to_string()]);
-        let exclude_patterns = build_globset(&vec![]);
+        let include_patterns = build_globset(&["**/lowercase/**".to_string()]);
+        let exclude_patterns = build_globset(&[]);
         let include_priority = true;
 
         // ~~~ Must be included ~~~
@@ -493,9 +494,8 @@ mod tests {
     fn test_include_no_exclude_files() {
         let base_path = TEST_DIR.path();
 
-        let include_patterns =
-            build_globset(&vec!["**/foo.py".to_string(), "**/bar.py".to_string()]);
-        let exclude_patterns = build_globset(&vec![]);
+        let include_patterns = build_globset(&["**/foo.py".to_string(), "**/bar.py".to_string()]);
+        let exclude_patterns = build_globset(&[]);
         let include_priority = false;
 
         // ~~~ Must be included ~~~
@@ -538,12 +538,13 @@ mod tests {
     fn test_include_exclude_conflict_file() {
         let base_path = TEST_DIR.path();
 
-        let include_patterns = build_globset(&vec!["**/foo.py".to_string()]);
-        let exclude_patterns = build_globset(&vec!["**/foo.py".to_string()]);
+        let include_patterns = build_globset(&["**/foo.py".to_string()]);
+        let exclude_patterns = build_globset(&["**/foo.py".to_string()]);
         let include_priority = true;
 
         // ~~~ Must be included ~~~
-        for file in ["lowercase/foo.py"] {
+        {
+            let file = "lowercase/foo.py";
             let path = base_path.join(file);
             assert!(should_include_file(
                 &path,
@@ -582,8 +583,8 @@ mod tests {
     fn test_include_exclude_conflict_extension() {
         let base_path = TEST_DIR.path();
 
-        let include_patterns = build_globset(&vec!["*.py".to_string()]);
-        let exclude_patterns = build_globset(&vec!["*.py".to_string()]);
+        let include_patterns = build_globset(&["*.py".to_string()]);
+        let exclude_patterns = build_globset(&["*.py".to_string()]);
         let include_priority = true;
 
         // ~~~ Must be included ~~~
@@ -628,8 +629,8 @@ mod tests {
     fn test_include_exclude_conflict_folder() {
         let base_path = TEST_DIR.path();
 
-        let include_patterns = build_globset(&vec!["**/lowercase/**".to_string()]);
-        let exclude_patterns = build_globset(&vec!["**/lowercase/**".to_string()]);
+        let include_patterns = build_globset(&["**/lowercase/**".to_string()]);
+        let exclude_patterns = build_globset(&["**/lowercase/**".to_string()]);
         let include_priority = true;
 
         // ~~~ Must be included ~~~
@@ -674,8 +675,8 @@ mod tests {
     fn test_include_exclude_priority_include() {
         let base_path = TEST_DIR.path();
 
-        let include_patterns = build_globset(&vec!["**/*.py".to_string()]);
-        let exclude_patterns = build_globset(&vec!["**/uppercase/*".to_string()]);
+        let include_patterns = build_globset(&["**/*.py".to_string()]);
+        let exclude_patterns = build_globset(&["**/uppercase/*".to_string()]);
         let include_priority = true;
 
         // ~~~ Must be included ~~~ priority
@@ -710,12 +711,13 @@ mod tests {
     fn test_include_exclude_priority_exclude() {
         let base_path = TEST_DIR.path();
 
-        let include_patterns = build_globset(&vec!["**/*.py".to_string()]);
-        let exclude_patterns = build_globset(&vec!["**/uppercase/*".to_string()]);
+        let include_patterns = build_globset(&["**/*.py".to_string()]);
+        let exclude_patterns = build_globset(&["**/uppercase/*".to_string()]);
         let include_priority = false;
 
         // ~~~ Must be included ~~~
-        for file in ["lowercase/foo.py"] {
+        {
+            let file = "lowercase/foo.py";
             let path = base_path.join(file);
             assert!(should_include_file(
                 &path,
@@ -743,9 +745,9 @@ mod tests {
 
         // This pattern uses brace expansion to match foo.py, bar.py, and baz.py
         // The issue was that the first item (foo.py) wasn't being considered
-        let include_patterns = build_globset(&vec!["lowercase/{foo.py,bar.py,baz.py}".to_string()]);
+        let include_patterns = build_globset(&["lowercase/{foo.py,bar.py,baz.py}".to_string()]);
         let exclude_patterns =
-            build_globset(&vec!["lowercase/{qux.py,corge.py,grault.py}".to_string()]);
+            build_globset(&["lowercase/{qux.py,corge.py,grault.py}".to_string()]);
         let include_priority = false;
 
         // ALL files in the brace expansion should be included
@@ -755,7 +757,7 @@ mod tests {
 
             assert!(
                 should_include_file(
-                    &relative_path,
+                    relative_path,
                     &include_patterns,
                     &exclude_patterns,
                     include_priority
@@ -772,7 +774,7 @@ mod tests {
 
             assert!(
                 !should_include_file(
-                    &relative_path,
+                    relative_path,
                     &include_patterns,
                     &exclude_patterns,
                     include_priority
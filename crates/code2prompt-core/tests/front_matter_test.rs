@@ -0,0 +1,101 @@
+use code2prompt_core::configuration::Code2PromptConfig;
+use code2prompt_core::front_matter::{split_front_matter, FrontMatter};
+use code2prompt_core::session::Code2PromptSession;
+use code2prompt_core::template::OutputFormat;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_split_front_matter() {
+        let template = "+++\ndiff = true\nline_numbers = true\nexclude = [\"*.lock\"]\noutput_format = \"xml\"\n+++\nReview:\n{{ git_diff }}\n";
+        let (front_matter, body) = split_front_matter(template).unwrap();
+        assert_eq!(
+            front_matter,
+            FrontMatter {
+                diff: true,
+                line_numbers: true,
+                exclude: vec!["*.lock".to_string()],
+                output_format: Some(OutputFormat::Xml),
+                ..FrontMatter::default()
+            }
+        );
+        assert_eq!(body, "Review:\n{{ git_diff }}\n");
+    }
+
+    #[test]
+    fn test_split_front_matter_crlf_and_empty() {
+        let (front_matter, body) =
+            split_front_matter("+++\r\nhidden = true\r\nchanged_files_only = true\r\n+++\r\nBody")
+                .unwrap();
+        assert!(front_matter.hidden);
+        assert!(front_matter.changed_files_only);
+        assert_eq!(body, "Body");
+
+        let (front_matter, body) = split_front_matter("+++\n+++").unwrap();
+        assert_eq!(front_matter, FrontMatter::default());
+        assert_eq!(body, "");
+    }
+
+    #[test]
+    fn test_without_front_matter() {
+        for template in [
+            "",
+            "Project Path: {{ absolute_code_path }}",
+            "++ not a delimiter\n+++\n",
+        ] {
+            let (front_matter, body) = split_front_matter(template).unwrap();
+            assert_eq!(front_matter, FrontMatter::default());
+            assert_eq!(body, template);
+        }
+    }
+
+    #[test]
+    fn test_invalid_front_matter() {
+        let error = split_front_matter("+++\ndiff = true\nline_numbers = yes\n+++\nBody")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.starts_with("Invalid front-matter at line 3"),
+            "{}",
+            error
+        );
+        assert!(error.contains("line_numbers = yes"), "{}", error);
+
+        let error = split_front_matter("+++\ncolor = true\n+++\n")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("line 2"), "{}", error);
+        assert!(error.contains("unknown field `color`"), "{}", error);
+
+        let error = split_front_matter("+++\noutput_format = \"pdf\"\n+++\n")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("Invalid output format: pdf"), "{}", error);
+
+        let error = split_front_matter("+++\ndiff = true\nBody")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("isn't closed"), "{}", error);
+    }
+
+    #[test]
+    fn test_session_strips_front_matter() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .template_str(
+                "+++\nline_numbers = true\n+++\n{{#each files}}{{path}}{{/each}}".to_string(),
+            )
+            .template_name("front-matter".to_string())
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let rendered = session.generate_prompt().unwrap();
+        assert_eq!(rendered.prompt, "main.rs");
+    }
+}
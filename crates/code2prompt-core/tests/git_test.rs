@@ -1,12 +1,65 @@
-use code2prompt_core::git::{get_git_diff, get_git_diff_between_branches, get_git_log};
+use code2prompt_core::filter::FileMatcher;
+use code2prompt_core::git::{
+    annotate_with_blame, files_changed_since, get_branch_relationship, get_commit_diff,
+    get_commit_diff_stat, get_contributors, get_conventional_changelog, get_current_branch,
+    get_git_blame, get_git_diff, get_git_diff_between_branches, get_git_diff_between_refs,
+    get_git_diff_stats, get_git_diff_structured, get_git_diff_workdir_to_ref, get_git_log,
+    get_git_log_commits, get_git_log_range, get_git_log_range_commits, get_git_stash_diff,
+    get_latest_tags, get_latest_tags_range, last_commits_for_paths, list_submodules,
+    list_tracked_files, parse_git_date, truncate_diff, write_commit_diff,
+    write_git_diff_between_refs, BlameMode, DiffConfig, DiffRenderStyle, DiffTarget, GitLogOptions,
+    WhitespaceMode,
+};
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use git2::{Repository, RepositoryInitOptions, Signature};
+    use git2::{Oid, Repository, RepositoryInitOptions, Signature};
+    use std::collections::HashMap;
     use std::fs;
+    use std::path::Path;
     use tempfile::TempDir;
 
+    /// Writes `content` to `file_name`, stages it and commits it on HEAD.
+    fn commit_file(repo: &Repository, file_name: &str, content: &str, message: &str) -> Oid {
+        let repo_path = repo.workdir().expect("Repository has no workdir");
+        fs::write(repo_path.join(file_name), content).expect("Failed to write to test file");
+
+        let mut index = repo.index().expect("Failed to get repository index");
+        index
+            .add_path(Path::new(file_name))
+            .expect("Failed to add file to index");
+        index.write().expect("Failed to write index");
+
+        let tree_id = index.write_tree().expect("Failed to write tree");
+        let tree = repo.find_tree(tree_id).expect("Failed to find tree");
+        let signature =
+            Signature::now("Test", "test@example.com").expect("Failed to create signature");
+
+        let parents = match repo.head() {
+            Ok(head) => vec![head.peel_to_commit().expect("Failed to peel HEAD")],
+            Err(_) => vec![],
+        };
+        let parent_refs: Vec<&git2::Commit> = parents.iter().collect();
+
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parent_refs,
+        )
+        .expect("Failed to commit")
+    }
+
+    /// Initializes a repository whose default branch is `master`.
+    fn init_repo(repo_path: &Path) -> Repository {
+        let mut binding = RepositoryInitOptions::new();
+        let init_options = binding.initial_head("master");
+        Repository::init_opts(repo_path, init_options).expect("Failed to initialize repository")
+    }
+
     #[test]
     fn test_get_git_diff() {
         // Create a temporary directory
@@ -53,7 +106,7 @@ mod tests {
         index.write().expect("Failed to write index");
 
         // Get the git diff using the function from the module
-        let diff = get_git_diff(repo_path).expect("Failed to get git diff");
+        let diff = get_git_diff(repo_path, &DiffConfig::default()).expect("Failed to get git diff");
 
         // Print the diff for debugging
         println!("Generated diff:\n{}", diff);
@@ -140,8 +193,13 @@ mod tests {
         .expect("Failed to commit in new branch");
 
         // Get the git diff between branches
-        let diff = get_git_diff_between_branches(repo_path, "master", "development")
-            .expect("Failed to get git diff between branches");
+        let diff = get_git_diff_between_branches(
+            repo_path,
+            "master",
+            "development",
+            &DiffConfig::default(),
+        )
+        .expect("Failed to get git diff between branches");
 
         // Print the diff for debugging
         println!("Generated diff between branches:\n{}", diff);
@@ -254,8 +312,13 @@ mod tests {
         .expect("Failed to commit second change in new branch");
 
         // Get the git log between branches
-        let log = get_git_log(repo_path, "master", "development")
-            .expect("Failed to get git log between branches");
+        let log = get_git_log(
+            repo_path,
+            "master",
+            "development",
+            &GitLogOptions::default(),
+        )
+        .expect("Failed to get git log between branches");
 
         // Print the log for debugging
         println!("Generated git log:\n{}", log);
@@ -310,7 +373,7 @@ mod tests {
             .expect("Failed to find first commit");
         repo.tag(
             "v1.0.0",
-            &first_commit.as_object(),
+            first_commit.as_object(),
             &signature,
             "Version 1.0.0",
             false,
@@ -343,9 +406,13 @@ mod tests {
         let first_commit_hash = first_commit_id.to_string();
         let second_commit_hash = second_commit_id.to_string();
 
-        let diff_full_hash =
-            get_git_diff_between_branches(repo_path, &first_commit_hash, &second_commit_hash)
-                .expect("Failed to get git diff between full commit hashes");
+        let diff_full_hash = get_git_diff_between_branches(
+            repo_path,
+            &first_commit_hash,
+            &second_commit_hash,
+            &DiffConfig::default(),
+        )
+        .expect("Failed to get git diff between full commit hashes");
 
         assert!(diff_full_hash.contains("Initial content"));
         assert!(diff_full_hash.contains("Modified content"));
@@ -354,35 +421,2300 @@ mod tests {
         let first_commit_short = &first_commit_hash[..7];
         let second_commit_short = &second_commit_hash[..7];
 
-        let diff_short_hash =
-            get_git_diff_between_branches(repo_path, first_commit_short, second_commit_short)
-                .expect("Failed to get git diff between abbreviated commit hashes");
+        let diff_short_hash = get_git_diff_between_branches(
+            repo_path,
+            first_commit_short,
+            second_commit_short,
+            &DiffConfig::default(),
+        )
+        .expect("Failed to get git diff between abbreviated commit hashes");
 
         assert!(diff_short_hash.contains("Initial content"));
         assert!(diff_short_hash.contains("Modified content"));
 
         // Test 3: Diff between tag and commit hash
-        let diff_tag_to_hash =
-            get_git_diff_between_branches(repo_path, "v1.0.0", &second_commit_hash)
-                .expect("Failed to get git diff between tag and commit hash");
+        let diff_tag_to_hash = get_git_diff_between_branches(
+            repo_path,
+            "v1.0.0",
+            &second_commit_hash,
+            &DiffConfig::default(),
+        )
+        .expect("Failed to get git diff between tag and commit hash");
 
         assert!(diff_tag_to_hash.contains("Initial content"));
         assert!(diff_tag_to_hash.contains("Modified content"));
 
         // Test 4: Diff between tag and HEAD
-        let diff_tag_to_head = get_git_diff_between_branches(repo_path, "v1.0.0", "HEAD")
-            .expect("Failed to get git diff between tag and HEAD");
+        let diff_tag_to_head =
+            get_git_diff_between_branches(repo_path, "v1.0.0", "HEAD", &DiffConfig::default())
+                .expect("Failed to get git diff between tag and HEAD");
 
         assert!(diff_tag_to_head.contains("Initial content"));
         assert!(diff_tag_to_head.contains("Modified content"));
 
         // Test 5: Error case - invalid reference should still fail
-        let result = get_git_diff_between_branches(repo_path, "nonexistent_reference", "HEAD");
+        let result = get_git_diff_between_branches(
+            repo_path,
+            "nonexistent_reference",
+            "HEAD",
+            &DiffConfig::default(),
+        );
 
         assert!(result.is_err());
         assert!(result
             .unwrap_err()
             .to_string()
-            .contains("Branch nonexistent_reference doesn't exist!"));
+            .contains("Reference nonexistent_reference doesn't exist!"));
+    }
+
+    #[test]
+    fn test_git_diff_between_refs_with_revspec_expressions() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo = init_repo(temp_dir.path());
+
+        let first = commit_file(&repo, "test_file.txt", "First content\n", "First commit");
+        commit_file(&repo, "test_file.txt", "Second content\n", "Second commit");
+        let third = commit_file(&repo, "test_file.txt", "Third content\n", "Third commit");
+
+        let signature =
+            Signature::now("Test", "test@example.com").expect("Failed to create signature");
+        let first_object = repo.find_object(first, None).unwrap();
+        repo.tag("v1.0.0", &first_object, &signature, "Version 1.0.0", false)
+            .expect("Failed to create tag");
+
+        // HEAD~2 expression
+        let diff =
+            get_git_diff_between_refs(temp_dir.path(), "HEAD~2", "HEAD", &DiffConfig::default())
+                .expect("Failed to diff HEAD~2..HEAD");
+        assert!(diff.contains("First content"));
+        assert!(diff.contains("Third content"));
+
+        // Tag against a full hash
+        let diff = get_git_diff_between_refs(
+            temp_dir.path(),
+            "v1.0.0",
+            &third.to_string(),
+            &DiffConfig::default(),
+        )
+        .expect("Failed to diff tag against full hash");
+        assert!(diff.contains("First content"));
+        assert!(diff.contains("Third content"));
+
+        // Short hash against HEAD~1
+        let diff = get_git_diff_between_refs(
+            temp_dir.path(),
+            &first.to_string()[..7],
+            "HEAD~1",
+            &DiffConfig::default(),
+        )
+        .expect("Failed to diff short hash against HEAD~1");
+        assert!(diff.contains("First content"));
+        assert!(diff.contains("Second content"));
+
+        // Missing references mention "reference" rather than "branch"
+        let err =
+            get_git_diff_between_refs(temp_dir.path(), "HEAD~10", "HEAD", &DiffConfig::default())
+                .unwrap_err();
+        assert_eq!(err.to_string(), "Reference HEAD~10 doesn't exist!");
+    }
+
+    #[test]
+    fn test_git_diff_between_refs_ambiguous_short_hash() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo = init_repo(temp_dir.path());
+        commit_file(
+            &repo,
+            "test_file.txt",
+            "Initial content\n",
+            "Initial commit",
+        );
+
+        // Write blobs until two of them share a 4 character prefix
+        let mut seen: HashMap<String, Oid> = HashMap::new();
+        let mut ambiguous = None;
+        for i in 0..100_000 {
+            let oid = repo
+                .blob(format!("blob {}", i).as_bytes())
+                .expect("Failed to write blob");
+            let prefix = oid.to_string()[..4].to_string();
+            if let Some(other) = seen.insert(prefix.clone(), oid) {
+                ambiguous = Some((prefix, [oid, other]));
+                break;
+            }
+        }
+        let (prefix, candidates) = ambiguous.expect("Failed to produce an ambiguous prefix");
+
+        let err =
+            get_git_diff_between_refs(temp_dir.path(), &prefix, "HEAD", &DiffConfig::default())
+                .unwrap_err();
+        assert!(err.to_string().contains("is ambiguous"), "{}", err);
+        // The candidates are listed
+        for oid in candidates {
+            assert!(
+                err.to_string()
+                    .contains(&format!("blob {}", &oid.to_string()[..12])),
+                "{}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_revspec_error_classification() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+        let first = commit_file(&repo, "file.txt", "first\n", "First");
+        let second = commit_file(&repo, "file.txt", "second\n", "Second");
+        commit_file(&repo, "file.txt", "third\n", "Third");
+        let config = DiffConfig::default();
+
+        // Not found
+        let err = get_git_diff_between_refs(repo_path, "missing", "HEAD", &config).unwrap_err();
+        assert_eq!(err.to_string(), "Reference missing doesn't exist!");
+
+        // Syntactically invalid
+        for spec in ["HEAD^{bogus}", "HEAD@{", "main..feature", "feat ure"] {
+            let err = get_git_diff_between_refs(repo_path, spec, "HEAD", &config).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "Reference {} is not a valid revision. Expected a branch, tag, commit hash or an expression such as HEAD~2",
+                    spec
+                )
+            );
+        }
+        let err = get_git_log_commits(repo_path, "HEAD^{", "HEAD", &GitLogOptions::default())
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("Branch HEAD^{ is not a valid revision"),
+            "{}",
+            err
+        );
+
+        // A tag shadowing a branch wins, like in git, with only a warning
+        repo.branch("pu", &repo.find_commit(second).unwrap(), false)
+            .unwrap();
+        repo.tag_lightweight("pu", &repo.find_object(first, None).unwrap(), false)
+            .unwrap();
+        let diff = get_git_diff_between_refs(repo_path, "pu", "HEAD", &config).unwrap();
+        assert!(
+            diff.contains("first") && !diff.contains("second"),
+            "{}",
+            diff
+        );
+        let diff = get_git_diff_between_refs(repo_path, "refs/heads/pu", "HEAD", &config).unwrap();
+        assert!(
+            diff.contains("second") && !diff.contains("first"),
+            "{}",
+            diff
+        );
+        let log =
+            get_git_log_commits(repo_path, "pu~0", "HEAD", &GitLogOptions::default()).unwrap();
+        assert_eq!(log.commits.len(), 2);
+    }
+
+    #[test]
+    fn test_git_diff_context_lines() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo = init_repo(temp_dir.path());
+
+        let original: Vec<String> = (1..=30).map(|i| format!("line {}", i)).collect();
+        commit_file(
+            &repo,
+            "test_file.txt",
+            &original.join("\n"),
+            "Initial commit",
+        );
+
+        let mut modified = original.clone();
+        modified[14] = "line 15 changed".to_string();
+        commit_file(
+            &repo,
+            "test_file.txt",
+            &modified.join("\n"),
+            "Change line 15",
+        );
+
+        let diff_with_context = |context_lines| {
+            let diff_config = DiffConfig {
+                context_lines,
+                ..DiffConfig::default()
+            };
+            get_git_diff_between_refs(temp_dir.path(), "HEAD~1", "HEAD", &diff_config)
+                .expect("Failed to get git diff between refs")
+        };
+
+        let default_diff = diff_with_context(3);
+        assert!(
+            default_diff.contains("@@ -12,7 +12,7 @@"),
+            "{}",
+            default_diff
+        );
+        assert!(!default_diff.contains("line 8\n"));
+
+        let wide_diff = diff_with_context(10);
+        assert!(wide_diff.contains("@@ -5,21 +5,21 @@"), "{}", wide_diff);
+        assert!(wide_diff.contains("line 8\n"));
+
+        let zero_diff = diff_with_context(0);
+        assert!(zero_diff.contains("@@ -15 +15 @@"), "{}", zero_diff);
+        assert!(zero_diff.len() < default_diff.len());
+    }
+
+    #[test]
+    fn test_get_git_diff_include_untracked() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+
+        commit_file(&repo, ".gitignore", "build/\n", "Ignore build directory");
+        fs::create_dir_all(repo_path.join("build")).unwrap();
+        fs::write(repo_path.join("build/artifact.txt"), "Ignored artifact").unwrap();
+        fs::create_dir_all(repo_path.join("src")).unwrap();
+        fs::write(repo_path.join("src/new_file.rs"), "fn prototype() {}").unwrap();
+
+        // By default untracked files are not part of the diff
+        let diff = get_git_diff(repo_path, &DiffConfig::default()).unwrap();
+        assert_eq!(diff, "no diff between HEAD and index");
+
+        let diff_config = DiffConfig {
+            include_untracked: true,
+            ..DiffConfig::default()
+        };
+        let diff = get_git_diff(repo_path, &diff_config).unwrap();
+        println!("Generated diff with untracked files:\n{}", diff);
+
+        assert!(diff.contains("src/new_file.rs"));
+        assert!(diff.contains("fn prototype() {}"));
+        assert!(!diff.contains("artifact.txt"));
+        assert!(!diff.contains("Ignored artifact"));
+        assert!(!diff.contains("Note: Some changes are not staged."));
+    }
+
+    #[test]
+    fn test_git_diff_untracked_and_ignored_matrix() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+
+        commit_file(
+            &repo,
+            ".gitignore",
+            "target/\nnode_modules/\n",
+            "Ignore build output",
+        );
+        fs::create_dir_all(repo_path.join("target/debug")).unwrap();
+        fs::write(repo_path.join("target/debug/build.log"), "Ignored log").unwrap();
+        fs::create_dir_all(repo_path.join("node_modules/pkg")).unwrap();
+        fs::write(repo_path.join("node_modules/pkg/index.js"), "ignored()").unwrap();
+        fs::write(repo_path.join("notes.txt"), "Untracked notes").unwrap();
+
+        for (include_untracked, include_ignored) in
+            [(false, false), (false, true), (true, false), (true, true)]
+        {
+            let diff_config = DiffConfig {
+                include_untracked,
+                include_ignored,
+                ..DiffConfig::default()
+            };
+            let expect_ignored = include_untracked && include_ignored;
+            for diff in [
+                get_git_diff(repo_path, &diff_config).unwrap(),
+                get_git_diff_workdir_to_ref(repo_path, "HEAD", &diff_config).unwrap(),
+            ] {
+                let case = format!(
+                    "untracked: {}, ignored: {}\n{}",
+                    include_untracked, include_ignored, diff
+                );
+                assert_eq!(
+                    diff.contains("Untracked notes"),
+                    include_untracked,
+                    "{}",
+                    case
+                );
+                assert_eq!(diff.contains("Ignored log"), expect_ignored, "{}", case);
+                assert_eq!(
+                    diff.contains("node_modules/pkg/index.js"),
+                    expect_ignored,
+                    "{}",
+                    case
+                );
+                assert!(!diff.contains(".git/"), "{}", case);
+            }
+        }
+    }
+
+    #[test]
+    fn test_get_git_diff_stats() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+
+        let long_content: String = (1..=20).map(|i| format!("line {}\n", i)).collect();
+        commit_file(&repo, "moved.txt", &long_content, "Add file to rename");
+        commit_file(&repo, "edited.txt", "one\ntwo\nthree\n", "Add file to edit");
+
+        // Rename a file, edit another and add a binary blob
+        fs::rename(repo_path.join("moved.txt"), repo_path.join("renamed.txt")).unwrap();
+        fs::write(repo_path.join("edited.txt"), "one\n2\nthree\nfour\n").unwrap();
+        fs::write(
+            repo_path.join("logo.png"),
+            [0x89, b'P', b'N', b'G', 0x00, 0x01, 0x02],
+        )
+        .unwrap();
+
+        let mut index = repo.index().unwrap();
+        index.remove_path(Path::new("moved.txt")).unwrap();
+        for file in ["renamed.txt", "edited.txt", "logo.png"] {
+            index.add_path(Path::new(file)).unwrap();
+        }
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let parent = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "Changes",
+            &tree,
+            &[&parent],
+        )
+        .unwrap();
+
+        let stats = get_git_diff_stats(repo_path, "HEAD~1", "HEAD", &DiffConfig::default())
+            .expect("Failed to get git diff stats");
+        println!("Generated diff stats:\n{}", stats);
+
+        assert_eq!(stats.files_changed, 3);
+        assert_eq!(stats.insertions, 2);
+        assert_eq!(stats.deletions, 1);
+
+        let edited = stats.files.iter().find(|f| f.path == "edited.txt").unwrap();
+        assert_eq!((edited.insertions, edited.deletions), (2, 1));
+        assert!(!edited.is_binary);
+
+        let renamed = stats
+            .files
+            .iter()
+            .find(|f| f.path == "renamed.txt")
+            .unwrap();
+        assert_eq!(renamed.old_path.as_deref(), Some("moved.txt"));
+        assert_eq!((renamed.insertions, renamed.deletions), (0, 0));
+
+        let binary = stats.files.iter().find(|f| f.path == "logo.png").unwrap();
+        assert!(binary.is_binary);
+
+        let summary = stats.to_string();
+        assert!(summary.contains("moved.txt => renamed.txt"));
+        assert!(summary.contains("logo.png | binary"));
+        assert!(summary.contains("3 files changed, 2 insertions(+), 1 deletions(-)"));
+    }
+
+    #[test]
+    fn test_git_diff_detects_renames() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+
+        let original: Vec<String> = (1..=30).map(|i| format!("line {}", i)).collect();
+        commit_file(
+            &repo,
+            "old_name.txt",
+            &original.join("\n"),
+            "Initial commit",
+        );
+
+        // Rename the file and edit a single line
+        let mut edited = original.clone();
+        edited[24] = "line 25 edited".to_string();
+        fs::remove_file(repo_path.join("old_name.txt")).unwrap();
+        fs::write(repo_path.join("new_name.txt"), edited.join("\n")).unwrap();
+
+        let mut index = repo.index().unwrap();
+        index.remove_path(Path::new("old_name.txt")).unwrap();
+        index.add_path(Path::new("new_name.txt")).unwrap();
+        index.write().unwrap();
+
+        let diff = get_git_diff(repo_path, &DiffConfig::default()).unwrap();
+        println!("Generated diff with rename:\n{}", diff);
+
+        assert!(diff.contains("rename from old_name.txt"));
+        assert!(diff.contains("rename to new_name.txt"));
+        assert_eq!(diff.matches("@@ -").count(), 1);
+        assert!(diff.contains("line 25 edited"));
+        assert!(!diff.contains("line 2\n"));
+
+        // Same detection between references
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let parent = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "Rename",
+            &tree,
+            &[&parent],
+        )
+        .unwrap();
+
+        let diff =
+            get_git_diff_between_refs(repo_path, "HEAD~1", "HEAD", &DiffConfig::default()).unwrap();
+        assert!(diff.contains("rename from old_name.txt"));
+        assert_eq!(diff.matches("@@ -").count(), 1);
+
+        // A threshold above the actual similarity falls back to delete + add
+        let strict_config = DiffConfig {
+            rename_threshold: 100,
+            ..DiffConfig::default()
+        };
+        let diff = get_git_diff_between_refs(repo_path, "HEAD~1", "HEAD", &strict_config).unwrap();
+        assert!(!diff.contains("rename from old_name.txt"));
+        assert!(diff.contains("deleted file mode"));
+    }
+
+    #[test]
+    fn test_git_diff_restricted_to_pathspecs() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+
+        fs::create_dir_all(repo_path.join("src")).unwrap();
+        fs::create_dir_all(repo_path.join("docs")).unwrap();
+        commit_file(&repo, "src/lib.rs", "pub fn old() {}\n", "Add lib");
+        commit_file(&repo, "docs/guide.md", "Old guide\n", "Add guide");
+        commit_file(&repo, "src/lib.rs", "pub fn new() {}\n", "Change lib");
+        commit_file(&repo, "docs/guide.md", "New guide\n", "Change guide");
+
+        let diff_for = |pathspecs: &[&str]| {
+            let diff_config = DiffConfig {
+                pathspecs: pathspecs.iter().map(|p| p.to_string()).collect(),
+                ..DiffConfig::default()
+            };
+            get_git_diff_between_refs(repo_path, "HEAD~2", "HEAD", &diff_config)
+        };
+
+        let diff = diff_for(&["src/**"]).unwrap();
+        assert!(diff.contains("pub fn new() {}"));
+        assert!(!diff.contains("New guide"));
+
+        // Negated patterns, alone or with the exclude magic
+        for pathspec in ["!docs/**", ":!docs/**", ":(exclude)docs/**"] {
+            let diff = diff_for(&[pathspec]).unwrap();
+            assert!(diff.contains("pub fn new() {}"), "{}", pathspec);
+            assert!(!diff.contains("New guide"), "{}", pathspec);
+        }
+
+        // Exclusions win over inclusions regardless of their order
+        let diff = diff_for(&["*.rs", "*.md", "!src/**"]).unwrap();
+        assert!(!diff.contains("pub fn new() {}"));
+        assert!(diff.contains("New guide"));
+
+        // An empty selection keeps the "no diff" message
+        let diff = diff_for(&["tests/**"]).unwrap();
+        assert_eq!(diff, "no diff between HEAD~2 and HEAD");
+
+        // Other pathspec magic is rejected
+        let err = diff_for(&[":(icase)src"]).unwrap_err();
+        assert!(err.to_string().contains("Unsupported pathspec"), "{}", err);
+    }
+
+    #[test]
+    fn test_git_diff_filtered_by_file_matcher() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+
+        fs::create_dir_all(repo_path.join("src")).unwrap();
+        commit_file(&repo, "src/main.rs", "fn main() {}\n", "Add main");
+        commit_file(&repo, "package-lock.json", "{}\n", "Add lockfile");
+        commit_file(
+            &repo,
+            "src/main.rs",
+            "fn main() { run(); }\n",
+            "Change main",
+        );
+        commit_file(
+            &repo,
+            "package-lock.json",
+            "{ \"v\": 2 }\n",
+            "Change lockfile",
+        );
+
+        let filtered = |include: &[&str], exclude: &[&str]| DiffConfig {
+            path_filter: Some(FileMatcher::new(
+                &include.iter().map(|p| p.to_string()).collect::<Vec<_>>(),
+                &exclude.iter().map(|p| p.to_string()).collect::<Vec<_>>(),
+                false,
+            )),
+            ..DiffConfig::default()
+        };
+
+        let diff =
+            get_git_diff_between_refs(repo_path, "HEAD~2", "HEAD", &filtered(&[], &["*.json"]))
+                .unwrap();
+        assert!(diff.contains("fn main() { run(); }"));
+        assert!(!diff.contains("\"v\": 2"));
+        assert!(diff.ends_with("\n(+1 file excluded from diff by patterns)"));
+
+        // Everything filtered out keeps the "no diff" message
+        let diff =
+            get_git_diff_between_refs(repo_path, "HEAD~2", "HEAD", &filtered(&["docs/**"], &[]))
+                .unwrap();
+        assert_eq!(
+            diff,
+            "no diff between HEAD~2 and HEAD\n(+2 files excluded from diff by patterns)"
+        );
+
+        // Staged changes are filtered too
+        fs::write(repo_path.join("package-lock.json"), "{ \"v\": 3 }\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("package-lock.json")).unwrap();
+        index.write().unwrap();
+        let diff = get_git_diff(repo_path, &filtered(&[], &["*.json"])).unwrap();
+        assert!(diff.starts_with("no diff between HEAD and index"));
+        assert!(diff.contains("(+1 file excluded from diff by patterns)"));
+
+        // Renamed files are kept when either path matches
+        fs::rename(repo_path.join("src/main.rs"), repo_path.join("src/app.rs")).unwrap();
+        let mut index = repo.index().unwrap();
+        index.remove_path(Path::new("src/main.rs")).unwrap();
+        index.add_path(Path::new("src/app.rs")).unwrap();
+        index.write().unwrap();
+        let diff = get_git_diff(repo_path, &filtered(&["src/main.rs"], &[])).unwrap();
+        assert!(diff.contains("rename from src/main.rs"), "{}", diff);
+        assert!(diff.contains("rename to src/app.rs"));
+    }
+
+    #[test]
+    fn test_git_diff_merge_base() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+
+        let base = commit_file(&repo, "shared.txt", "Shared content\n", "Initial commit");
+        repo.branch("feature", &repo.find_commit(base).unwrap(), false)
+            .expect("Failed to create branch");
+
+        // master advances after the feature branch was created
+        commit_file(
+            &repo,
+            "master_only.txt",
+            "Master only change\n",
+            "Master commit",
+        );
+
+        // feature adds its own change
+        repo.set_head("refs/heads/feature").unwrap();
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+            .unwrap();
+        commit_file(&repo, "feature.txt", "Feature change\n", "Feature commit");
+
+        let plain =
+            get_git_diff_between_refs(repo_path, "master", "feature", &DiffConfig::default())
+                .expect("Failed to get plain diff");
+        assert!(plain.contains("Feature change"));
+        assert!(plain.contains("Master only change"));
+
+        let merge_base_config = DiffConfig {
+            merge_base: true,
+            ..DiffConfig::default()
+        };
+        let three_dot =
+            get_git_diff_between_refs(repo_path, "master", "feature", &merge_base_config)
+                .expect("Failed to get merge base diff");
+        assert!(three_dot.contains("Feature change"));
+        assert!(!three_dot.contains("Master only change"));
+
+        let stats = get_git_diff_stats(repo_path, "master", "feature", &merge_base_config).unwrap();
+        assert_eq!(stats.files_changed, 1);
+        assert_eq!(stats.files[0].path, "feature.txt");
+    }
+
+    #[test]
+    fn test_get_git_log_with_details() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+
+        commit_file(&repo, "test_file.txt", "v1\n", "Initial commit");
+        commit_file(
+            &repo,
+            "test_file.txt",
+            "v2\n",
+            "Add feature\n\nExplain why the feature\nis needed.",
+        );
+        commit_file(&repo, "test_file.txt", "v3\n", "Fix feature");
+
+        // Default output stays terse
+        let log = get_git_log(repo_path, "HEAD~2", "HEAD", &GitLogOptions::default()).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with(" - Add feature"));
+        assert!(lines[1].ends_with(" - Fix feature"));
+
+        let log_options = GitLogOptions {
+            include_body: true,
+            include_author: true,
+            include_date: true,
+            date_format: "%Y".to_string(),
+            ..GitLogOptions::default()
+        };
+        let log = get_git_log(repo_path, "HEAD~2", "HEAD", &log_options).unwrap();
+        println!("Generated detailed git log:\n{}", log);
+        assert!(log.contains("    Author: Test <test@example.com>"));
+        assert!(log.contains("    Explain why the feature\n    is needed."));
+        assert!(log.contains(&format!("    Date:   {}", chrono::Utc::now().format("%Y"))));
+
+        // max_commits keeps the most recent commits, oldest first
+        let log_options = GitLogOptions {
+            max_commits: Some(1),
+            ..GitLogOptions::default()
+        };
+        let commits = get_git_log_commits(repo_path, "HEAD~2", "HEAD", &log_options)
+            .unwrap()
+            .commits;
+        assert_eq!(commits.len(), 1);
+        assert_eq!(commits[0].summary, "Fix feature");
+        assert_eq!(commits[0].author_email, "test@example.com");
+        assert_eq!(commits[0].sha.len(), 40);
+    }
+
+    #[test]
+    fn test_get_git_log_non_utf8_message() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+        let parent = commit_file(&repo, "test_file.txt", "v1\n", "Initial commit");
+        let tree = repo.find_commit(parent).unwrap().tree_id();
+
+        // Write a commit whose message is Latin-1 encoded
+        let mut raw_commit = format!(
+            "tree {}\nparent {}\nauthor Test <test@example.com> 1700000000 +0000\ncommitter Test <test@example.com> 1700000000 +0000\n\n",
+            tree, parent
+        )
+        .into_bytes();
+        raw_commit.extend_from_slice(b"Caf\xe9 fix\n");
+        let oid = repo
+            .odb()
+            .unwrap()
+            .write(git2::ObjectType::Commit, &raw_commit)
+            .unwrap();
+        repo.reference("refs/heads/master", oid, true, "latin-1 commit")
+            .unwrap();
+
+        let log = get_git_log(repo_path, "HEAD~1", "HEAD", &GitLogOptions::default())
+            .expect("Non UTF-8 messages should not fail");
+        assert!(log.contains("Caf\u{FFFD} fix"), "{}", log);
+    }
+
+    #[test]
+    fn test_get_git_log_filtered_by_author_and_date() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+        let base = commit_file(&repo, "test_file.txt", "v0\n", "Initial commit");
+
+        // Commit with explicit authors and timestamps
+        let mut parent = repo.find_commit(base).unwrap();
+        for (name, email, time, message) in [
+            (
+                "Alice",
+                "alice@example.com",
+                1_704_067_200,
+                "Alice in January",
+            ),
+            ("Bob", "bob@example.com", 1_706_745_600, "Bob in February"),
+            (
+                "Alice",
+                "alice@example.com",
+                1_709_251_200,
+                "Alice in March",
+            ),
+        ] {
+            fs::write(repo_path.join("test_file.txt"), message).unwrap();
+            let mut index = repo.index().unwrap();
+            index.add_path(Path::new("test_file.txt")).unwrap();
+            index.write().unwrap();
+            let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+            let signature = Signature::new(name, email, &git2::Time::new(time, 0)).unwrap();
+            let oid = repo
+                .commit(
+                    Some("HEAD"),
+                    &signature,
+                    &signature,
+                    message,
+                    &tree,
+                    &[&parent],
+                )
+                .unwrap();
+            parent = repo.find_commit(oid).unwrap();
+        }
+
+        let summaries = |log_options: &GitLogOptions| -> Vec<String> {
+            get_git_log_commits(repo_path, "HEAD~3", "HEAD", log_options)
+                .unwrap()
+                .commits
+                .into_iter()
+                .map(|commit| commit.summary)
+                .collect()
+        };
+
+        let by_author = GitLogOptions {
+            author: Some("ALICE".to_string()),
+            ..GitLogOptions::default()
+        };
+        assert_eq!(
+            summaries(&by_author),
+            vec!["Alice in January", "Alice in March"]
+        );
+
+        let by_email = GitLogOptions {
+            author: Some("bob@".to_string()),
+            ..GitLogOptions::default()
+        };
+        assert_eq!(summaries(&by_email), vec!["Bob in February"]);
+
+        let by_date = GitLogOptions {
+            since: Some(parse_git_date("2024-02-01").unwrap()),
+            until: Some(parse_git_date("2024-02-15").unwrap()),
+            ..GitLogOptions::default()
+        };
+        assert_eq!(summaries(&by_date), vec!["Bob in February"]);
+
+        // Filters combine with max_commits
+        let combined = GitLogOptions {
+            author: Some("alice".to_string()),
+            max_commits: Some(1),
+            ..GitLogOptions::default()
+        };
+        assert_eq!(summaries(&combined), vec!["Alice in March"]);
+
+        let no_match = GitLogOptions {
+            author: Some("carol".to_string()),
+            ..GitLogOptions::default()
+        };
+        let log = get_git_log(repo_path, "HEAD~3", "HEAD", &no_match).unwrap();
+        assert_eq!(log, "no commits match the author/date filters");
+    }
+
+    #[test]
+    fn test_parse_git_date() {
+        assert_eq!(parse_git_date("2024-01-01").unwrap(), 1_704_067_200);
+        assert_eq!(parse_git_date("2024-01-01 01:00").unwrap(), 1_704_070_800);
+        assert_eq!(
+            parse_git_date("2024-01-01T02:00:00+01:00").unwrap(),
+            1_704_070_800
+        );
+
+        let now = chrono::Utc::now().timestamp();
+        let two_weeks_ago = parse_git_date("2 weeks ago").unwrap();
+        assert!((now - 14 * 24 * 60 * 60 - two_weeks_ago).abs() < 60);
+        assert!(parse_git_date("yesterday").unwrap() < parse_git_date("today").unwrap());
+
+        let err = parse_git_date("last tuesday").unwrap_err();
+        assert!(err.to_string().contains("Invalid date 'last tuesday'"));
+    }
+
+    #[test]
+    fn test_get_git_log_range() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+
+        commit_file(&repo, "test_file.txt", "v1\n", "Commit 1");
+        commit_file(&repo, "test_file.txt", "v2\n", "Commit 2");
+        let base = commit_file(&repo, "test_file.txt", "v3\n", "Commit 3");
+        repo.tag_lightweight("v1.0.0", &repo.find_object(base, None).unwrap(), false)
+            .unwrap();
+        commit_file(&repo, "test_file.txt", "v4\n", "Commit 4");
+
+        // Diverge a feature branch from the tag
+        repo.branch("feature", &repo.find_commit(base).unwrap(), false)
+            .unwrap();
+        repo.set_head("refs/heads/feature").unwrap();
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+            .unwrap();
+        commit_file(&repo, "feature.txt", "feature\n", "Feature commit");
+
+        let summaries = |range: &str| -> Vec<String> {
+            get_git_log_range_commits(repo_path, range, &GitLogOptions::default())
+                .unwrap()
+                .commits
+                .into_iter()
+                .map(|commit| commit.summary)
+                .collect()
+        };
+
+        assert_eq!(summaries("master~2..master"), vec!["Commit 3", "Commit 4"]);
+        assert_eq!(summaries("v1.0.0..master"), vec!["Commit 4"]);
+        assert_eq!(summaries("v1.0.0.."), vec!["Feature commit"]);
+        assert_eq!(summaries("-n 2"), vec!["Commit 3", "Feature commit"]);
+        assert_eq!(summaries("-1"), vec!["Feature commit"]);
+        assert_eq!(summaries("v1.0.0").len(), 3);
+
+        // Three dots list both sides since their merge base
+        let mut symmetric = summaries("master...feature");
+        symmetric.sort();
+        assert_eq!(symmetric, vec!["Commit 4", "Feature commit"]);
+
+        let log =
+            get_git_log_range(repo_path, "master~1..master", &GitLogOptions::default()).unwrap();
+        assert!(log.trim_end().ends_with(" - Commit 4"), "{}", log);
+
+        let unknown =
+            get_git_log_range(repo_path, "v9.9.9..HEAD", &GitLogOptions::default()).unwrap_err();
+        assert_eq!(unknown.to_string(), "Reference v9.9.9 doesn't exist!");
+
+        for range in ["master..feature..HEAD", "master....feature", "...", "-n x"] {
+            let invalid =
+                get_git_log_range(repo_path, range, &GitLogOptions::default()).unwrap_err();
+            assert!(
+                invalid.to_string().starts_with("Invalid log range"),
+                "{}: {}",
+                range,
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_get_git_blame() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+
+        let first = commit_file(&repo, "test_file.txt", "one\ntwo\n", "Initial commit");
+        let second = commit_file(&repo, "test_file.txt", "one\ntwo\nthree\n", "Add three");
+        fs::write(repo_path.join("test_file.txt"), "one\ntwo\nthree\nfour\n").unwrap();
+
+        let blame = get_git_blame(repo_path, Path::new("test_file.txt")).unwrap();
+        let shas: Vec<&str> = blame.iter().map(|line| line.short_sha.as_str()).collect();
+        assert_eq!(
+            shas,
+            vec![
+                &first.to_string()[..7],
+                &first.to_string()[..7],
+                &second.to_string()[..7],
+                "0000000"
+            ]
+        );
+        assert_eq!(blame[0].author, "Test");
+        assert_eq!(blame[0].age, "just now");
+        assert_eq!(blame[3].author, "Not Committed Yet");
+        assert_eq!(blame[3].line_number, 4);
+
+        let code = "one\ntwo\nthree\nfour\n";
+        let lines = annotate_with_blame(code, &blame, BlameMode::Lines, false);
+        assert!(lines.starts_with(&format!(
+            "{} Test                 just now | one\n",
+            &first.to_string()[..7]
+        )));
+        let hunks = annotate_with_blame(code, &blame, BlameMode::Hunks, true);
+        assert_eq!(hunks.lines().count(), 7);
+        assert!(hunks.contains(&format!(
+            "[{} Test, just now (lines 1-2)]\n1 | one\n2 | two\n",
+            &first.to_string()[..7]
+        )));
+
+        // Untracked files have no blame
+        fs::write(repo_path.join("untracked.txt"), "new\n").unwrap();
+        assert!(get_git_blame(repo_path, Path::new("untracked.txt")).is_err());
+    }
+
+    #[test]
+    fn test_get_git_stash_diff() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let mut repo = init_repo(repo_path);
+        commit_file(&repo, "test_file.txt", "original\n", "Initial commit");
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+
+        let err = get_git_stash_diff(repo_path, 0, &DiffConfig::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Stash stash@{0} doesn't exist! The repository has no stashes"
+        );
+
+        // Older stash with a tracked change only
+        fs::write(repo_path.join("test_file.txt"), "first experiment\n").unwrap();
+        repo.stash_save(&signature, "first", None).unwrap();
+
+        // Newer stash including an untracked file
+        fs::write(repo_path.join("test_file.txt"), "second experiment\n").unwrap();
+        fs::write(repo_path.join("scratch.txt"), "scratch notes\n").unwrap();
+        repo.stash_save(
+            &signature,
+            "second",
+            Some(git2::StashFlags::INCLUDE_UNTRACKED),
+        )
+        .unwrap();
+        assert!(!repo_path.join("scratch.txt").exists());
+
+        let diff = get_git_stash_diff(repo_path, 0, &DiffConfig::default()).unwrap();
+        println!("Generated stash diff:\n{}", diff);
+        assert!(diff.contains("second experiment"));
+        assert!(diff.contains("scratch notes"));
+        assert!(diff.contains("scratch.txt"));
+
+        let diff = get_git_stash_diff(repo_path, 1, &DiffConfig::default()).unwrap();
+        assert!(diff.contains("first experiment"));
+        assert!(!diff.contains("scratch notes"));
+
+        let err = get_git_stash_diff(repo_path, 5, &DiffConfig::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Stash stash@{5} doesn't exist! The repository has 2 stash(es): stash@{0} to stash@{1}"
+        );
+    }
+
+    #[test]
+    fn test_git_diff_with_submodules() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let inner_path = temp_dir.path().join("inner");
+        let outer_path = temp_dir.path().join("outer");
+        let inner = init_repo(&inner_path);
+        commit_file(&inner, "lib.rs", "fn v1() {}\n", "Inner v1");
+
+        let outer = init_repo(&outer_path);
+        commit_file(&outer, "main.rs", "fn main() {}\n", "Outer initial");
+        let mut submodule = outer
+            .submodule(inner_path.to_str().unwrap(), Path::new("sub"), true)
+            .unwrap();
+        submodule.clone(None).unwrap();
+        submodule.add_finalize().unwrap();
+        commit_file(
+            &outer,
+            ".gitmodules",
+            &fs::read_to_string(outer_path.join(".gitmodules")).unwrap(),
+            "Add submodule",
+        );
+
+        let submodules = list_submodules(&outer_path).unwrap();
+        assert_eq!(submodules.len(), 1);
+        assert_eq!(submodules[0].path, Path::new("sub"));
+        assert!(submodules[0].initialized);
+
+        // Move the submodule forward and stage the new pointer
+        let sub_repo = submodule.open().unwrap();
+        let old_id = sub_repo.head().unwrap().target().unwrap();
+        let new_id = commit_file(&sub_repo, "lib.rs", "fn v2() {}\n", "Inner v2");
+        let mut index = outer.index().unwrap();
+        index.add_path(Path::new("sub")).unwrap();
+        index.write().unwrap();
+
+        let diff_config = DiffConfig {
+            include_submodules: true,
+            ..DiffConfig::default()
+        };
+        let diff = get_git_diff(&outer_path, &diff_config).unwrap();
+        println!("Generated submodule diff:\n{}", diff);
+        assert!(diff.contains(&format!(
+            "Submodule sub {}..{}:",
+            &old_id.to_string()[..7],
+            &new_id.to_string()[..7]
+        )));
+        assert!(diff.contains("fn v2() {}"));
+        assert!(diff.contains("a/sub/lib.rs"));
+
+        // Pathspecs relative to the outer repository apply inside the submodule
+        let excluded = DiffConfig {
+            pathspecs: vec!["!sub/lib.rs".to_string()],
+            ..diff_config.clone()
+        };
+        let diff = get_git_diff(&outer_path, &excluded).unwrap();
+        assert!(diff.contains("Submodule sub"));
+        assert!(!diff.contains("fn v2() {}"));
+
+        // Without the option only the pointer change is shown
+        let diff = get_git_diff(&outer_path, &DiffConfig::default()).unwrap();
+        assert!(!diff.contains("fn v2() {}"));
+    }
+
+    #[test]
+    fn test_get_git_diff_unborn_head() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+
+        let diff = get_git_diff(repo_path, &DiffConfig::default()).unwrap();
+        assert_eq!(diff, "no diff between HEAD and index");
+
+        fs::write(repo_path.join("first.txt"), "first file\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("first.txt")).unwrap();
+        index.write().unwrap();
+
+        let diff = get_git_diff(repo_path, &DiffConfig::default()).unwrap();
+        println!("Generated unborn diff:\n{}", diff);
+        assert!(diff
+            .starts_with("repository has no commits yet; showing all staged files as additions\n"));
+        assert!(diff.contains("first file"));
+    }
+
+    #[test]
+    fn test_get_git_diff_detached_head() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+
+        let first = commit_file(&repo, "test_file.txt", "v1\n", "First commit");
+        commit_file(&repo, "test_file.txt", "v2\n", "Second commit");
+        repo.set_head_detached(first).unwrap();
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+            .unwrap();
+
+        fs::write(repo_path.join("test_file.txt"), "v1 edited\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("test_file.txt")).unwrap();
+        index.write().unwrap();
+
+        let diff = get_git_diff(repo_path, &DiffConfig::default()).unwrap();
+        println!("Generated detached diff:\n{}", diff);
+        assert!(diff.starts_with(&format!(
+            "HEAD is detached at {}; showing changes against the checked-out commit\n",
+            &first.to_string()[..7]
+        )));
+        // Changes are relative to the checked-out commit, not the branch tip
+        assert!(diff.contains("v1 edited"));
+        assert!(!diff.contains("v2"));
+    }
+
+    #[test]
+    fn test_git_operations_on_bare_repository() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let work_path = temp_dir.path().join("work");
+        let bare_path = temp_dir.path().join("mirror.git");
+        let repo = init_repo(&work_path);
+        commit_file(&repo, "test_file.txt", "v1\n", "First commit");
+        commit_file(&repo, "test_file.txt", "v2\n", "Second commit");
+        git2::build::RepoBuilder::new()
+            .bare(true)
+            .clone(work_path.to_str().unwrap(), &bare_path)
+            .expect("Failed to create bare clone");
+
+        let diff =
+            get_git_diff_between_branches(&bare_path, "HEAD~1", "HEAD", &DiffConfig::default())
+                .unwrap();
+        assert!(diff.contains("v2"));
+
+        let log = get_git_log(&bare_path, "HEAD~1", "HEAD", &GitLogOptions::default()).unwrap();
+        assert!(log.contains("Second commit"));
+
+        let err = get_git_diff(&bare_path, &DiffConfig::default()).unwrap_err();
+        assert!(err.to_string().contains("bare repository"), "{}", err);
+    }
+
+    #[test]
+    fn test_git_diff_binary_files() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+        commit_file(&repo, "readme.txt", "readme\n", "Initial commit");
+
+        let mut png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR".to_vec();
+        png.extend([0xffu8; 2048]);
+        fs::create_dir_all(repo_path.join("assets")).unwrap();
+        fs::write(repo_path.join("assets/logo.png"), &png).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("assets/logo.png")).unwrap();
+        index.write().unwrap();
+
+        let diff = get_git_diff(repo_path, &DiffConfig::default()).unwrap();
+        println!("Generated binary diff:\n{}", diff);
+        assert!(diff.contains("Binary file assets/logo.png added (2.0 KB)"));
+        assert!(!diff.contains("PNG"));
+        assert!(!diff.contains('\u{FFFD}'));
+
+        // Changing the blob between two commits reports both sizes
+        commit_file(&repo, "readme.txt", "readme\n", "Add logo");
+        png.extend([0xeeu8; 1024]);
+        fs::write(repo_path.join("assets/logo.png"), &png).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("assets/logo.png")).unwrap();
+        index.write().unwrap();
+        commit_file(&repo, "readme.txt", "readme v2\n", "Update logo and readme");
+
+        let diff =
+            get_git_diff_between_refs(repo_path, "HEAD~1", "HEAD", &DiffConfig::default()).unwrap();
+        assert!(diff.contains("Binary file assets/logo.png changed (2.0 KB -> 3.0 KB)"));
+        assert!(diff.contains("readme v2"));
+
+        let skip_binary = DiffConfig {
+            skip_binary: true,
+            ..DiffConfig::default()
+        };
+        let diff = get_git_diff_between_refs(repo_path, "HEAD~1", "HEAD", &skip_binary).unwrap();
+        assert!(!diff.contains("logo.png"));
+        assert!(diff.contains("readme v2"));
+    }
+
+    #[test]
+    fn test_git_diff_word_style() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+
+        let line = "let configuration = load_configuration(path, include_patterns, exclude_patterns, verbose);";
+        commit_file(
+            &repo,
+            "main.rs",
+            &format!("fn main() {{\n    {}\n}}\n", line),
+            "Initial commit",
+        );
+        let changed = line.replace("verbose", "quiet");
+        commit_file(
+            &repo,
+            "main.rs",
+            &format!("fn main() {{\n    {}\n}}\n", changed),
+            "Be quiet",
+        );
+
+        let patch =
+            get_git_diff_between_refs(repo_path, "HEAD~1", "HEAD", &DiffConfig::default()).unwrap();
+        let word_config = DiffConfig {
+            render_style: DiffRenderStyle::Word,
+            ..DiffConfig::default()
+        };
+        let word = get_git_diff_between_refs(repo_path, "HEAD~1", "HEAD", &word_config).unwrap();
+        println!("Generated word diff:\n{}", word);
+
+        assert!(word.contains(
+            "    let configuration = load_configuration(path, include_patterns, exclude_patterns, [-verbose-]{+quiet+});\n"
+        ));
+        assert!(word.contains("fn main() {\n"));
+        assert!(word.len() + line.len() / 2 < patch.len());
+        assert_eq!(
+            "word".parse::<DiffRenderStyle>().unwrap(),
+            DiffRenderStyle::Word
+        );
+        assert!("side-by-side".parse::<DiffRenderStyle>().is_err());
+    }
+
+    #[test]
+    fn test_git_diff_whitespace_modes() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+
+        commit_file(
+            &repo,
+            "script.py",
+            "if ready:\n    run()\nstop()\n",
+            "Initial commit",
+        );
+        // Indentation-only change: stop() moves into the if block
+        fs::write(
+            repo_path.join("script.py"),
+            "if ready:\n    run()\n    stop()\n",
+        )
+        .unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("script.py")).unwrap();
+        index.write().unwrap();
+
+        let whitespace_config = |whitespace| DiffConfig {
+            whitespace,
+            ..DiffConfig::default()
+        };
+
+        // The default ignores all whitespace and hides the change
+        let diff = get_git_diff(repo_path, &DiffConfig::default()).unwrap();
+        assert!(!diff.contains("    stop()"), "{}", diff);
+
+        let diff = get_git_diff(repo_path, &whitespace_config(WhitespaceMode::None)).unwrap();
+        assert!(diff.contains("    stop()"), "{}", diff);
+
+        // Adding leading whitespace is not a change in the amount of existing whitespace
+        let diff = get_git_diff(repo_path, &whitespace_config(WhitespaceMode::Change)).unwrap();
+        assert!(diff.contains("    stop()"), "{}", diff);
+
+        let diff = get_git_diff(repo_path, &whitespace_config(WhitespaceMode::Eol)).unwrap();
+        assert!(diff.contains("    stop()"), "{}", diff);
+
+        assert_eq!(
+            "EOL".parse::<WhitespaceMode>().unwrap(),
+            WhitespaceMode::Eol
+        );
+        assert!("tabs".parse::<WhitespaceMode>().is_err());
+    }
+
+    #[test]
+    fn test_get_git_diff_structured() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+        commit_file(
+            &repo,
+            "main.rs",
+            "fn main() {\n    old();\n}\n",
+            "Initial commit",
+        );
+        commit_file(
+            &repo,
+            "main.rs",
+            "fn main() {\n    new();\n}\n",
+            "Change main",
+        );
+
+        let diff = get_git_diff_structured(
+            repo_path,
+            DiffTarget::Refs("HEAD~1", "HEAD"),
+            &DiffConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(diff.files.len(), 1);
+        let file = &diff.files[0];
+        assert_eq!(file.old_path.as_deref(), Some("main.rs"));
+        assert_eq!(file.new_path.as_deref(), Some("main.rs"));
+        assert_eq!(file.status, "modified");
+        assert!(!file.is_binary);
+        assert_eq!(file.hunks.len(), 1);
+
+        let hunk = &file.hunks[0];
+        assert_eq!((hunk.old_start, hunk.old_lines), (1, 3));
+        assert_eq!((hunk.new_start, hunk.new_lines), (1, 3));
+        assert_eq!(hunk.header, "@@ -1,3 +1,3 @@");
+        let lines: Vec<(char, &str)> = hunk
+            .lines
+            .iter()
+            .map(|line| (line.origin, line.content.as_str()))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                (' ', "fn main() {\n"),
+                ('-', "    old();\n"),
+                ('+', "    new();\n"),
+                (' ', "}\n"),
+            ]
+        );
+
+        // The staged target uses the same options as the text diff
+        fs::write(repo_path.join("notes.txt"), "draft\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("notes.txt")).unwrap();
+        index.write().unwrap();
+        let diff =
+            get_git_diff_structured(repo_path, DiffTarget::Staged, &DiffConfig::default()).unwrap();
+        assert_eq!(diff.files.len(), 1);
+        assert_eq!(diff.files[0].old_path, None);
+        assert_eq!(diff.files[0].new_path.as_deref(), Some("notes.txt"));
+        assert_eq!(diff.files[0].status, "added");
+
+        let json = serde_json::to_value(&diff).unwrap();
+        assert_eq!(json["files"][0]["hunks"][0]["lines"][0]["origin"], "+");
+        assert_eq!(
+            json["files"][0]["hunks"][0]["lines"][0]["content"],
+            "draft\n"
+        );
+    }
+
+    #[test]
+    fn test_truncate_diff() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+
+        let lines: Vec<String> = (0..20).map(|i| format!("line {}", i)).collect();
+        commit_file(&repo, "small.txt", &(lines.join("\n") + "\n"), "Add small");
+        let big: Vec<String> = (0..10).map(|i| format!("old big line {}", i)).collect();
+        commit_file(&repo, "big.txt", &(big.join("\n") + "\n"), "Add big");
+
+        let mut changed = lines.clone();
+        changed[1] = "changed 1".to_string();
+        changed[18] = "changed 18".to_string();
+        commit_file(
+            &repo,
+            "small.txt",
+            &(changed.join("\n") + "\n"),
+            "Change small",
+        );
+        let big: Vec<String> = (0..10).map(|i| format!("new big line {}", i)).collect();
+        commit_file(&repo, "big.txt", &(big.join("\n") + "\n"), "Change big");
+
+        let diff_config = DiffConfig {
+            context_lines: 0,
+            ..DiffConfig::default()
+        };
+        let diff = get_git_diff_between_refs(repo_path, "HEAD~2", "HEAD", &diff_config).unwrap();
+        let count_words = |text: &str| text.split_whitespace().count();
+        let total = count_words(&diff);
+
+        // Within the budget the diff is unchanged
+        assert_eq!(truncate_diff(&diff, total, count_words), diff);
+
+        // The big hunk goes first, the small hunks are kept whole with their headers
+        let truncated = truncate_diff(&diff, total - 1, count_words);
+        println!("Truncated diff:\n{}", truncated);
+        let big_file = &diff[..diff.find("diff --git a/small.txt").unwrap()];
+        assert!(!truncated.contains("big.txt"));
+        assert!(truncated.contains("@@ -2 +2 @@"));
+        assert!(truncated.contains("changed 1\n"));
+        assert!(truncated.contains("changed 18\n"));
+        assert!(truncated.ends_with(&format!(
+            "... diff truncated: 1 hunk / {} tokens omitted\n",
+            count_words(big_file)
+        )));
+
+        // Hunk headers are never split, even with a tiny budget
+        let truncated = truncate_diff(&diff, 1, count_words);
+        assert!(!truncated.contains("@@"));
+        assert!(truncated.starts_with("... diff truncated: 3 hunks / "));
+    }
+
+    #[test]
+    fn test_get_conventional_changelog() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+
+        commit_file(&repo, "file.txt", "v0", "chore: initial commit");
+        repo.tag_lightweight(
+            "v1.0.0",
+            &repo.head().unwrap().peel(git2::ObjectType::Commit).unwrap(),
+            false,
+        )
+        .unwrap();
+        commit_file(
+            &repo,
+            "file.txt",
+            "v1",
+            "feat(parser): support nested blocks",
+        );
+        commit_file(&repo, "file.txt", "v2", "fix: handle empty input");
+        commit_file(&repo, "file.txt", "v3", "feat(cli)!: rename the --out flag");
+        commit_file(
+            &repo,
+            "file.txt",
+            "v4",
+            "refactor: split the lexer\n\nBREAKING CHANGE: Lexer::new takes a config",
+        );
+        commit_file(&repo, "file.txt", "v5", "Update README");
+
+        let changelog = get_conventional_changelog(repo_path, "v1.0.0", "HEAD").unwrap();
+        let types: Vec<&str> = changelog.groups.keys().map(String::as_str).collect();
+        assert_eq!(types, vec!["feat", "fix", "other", "refactor"]);
+        assert_eq!(changelog.breaking_changes, 2);
+
+        let feats = &changelog.groups["feat"];
+        assert_eq!(feats.len(), 2);
+        assert_eq!(feats[0].scope.as_deref(), Some("parser"));
+        assert_eq!(feats[0].summary, "support nested blocks");
+        assert!(!feats[0].breaking);
+        assert_eq!(feats[1].scope.as_deref(), Some("cli"));
+        assert!(feats[1].breaking);
+
+        assert_eq!(changelog.groups["fix"][0].scope, None);
+        assert!(changelog.groups["refactor"][0].breaking);
+
+        // Non-conforming commits are kept with their full summary
+        assert_eq!(changelog.groups["other"][0].summary, "Update README");
+        assert!(!changelog.groups.contains_key("chore"));
+
+        let err = get_conventional_changelog(repo_path, "v9.9.9", "HEAD").unwrap_err();
+        assert!(err.to_string().contains("v9.9.9"), "{}", err);
+    }
+
+    #[test]
+    fn test_files_changed_since() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+
+        commit_file(&repo, "old.txt", "old", "Add old");
+        commit_file(&repo, "gone.txt", "gone", "Add gone");
+        commit_file(&repo, "kept.txt", "kept", "Add kept");
+        commit_file(&repo, "kept.txt", "kept v2", "Change kept");
+
+        // Remove a file and leave an untracked one in the working tree
+        let mut index = repo.index().unwrap();
+        index.remove_path(Path::new("gone.txt")).unwrap();
+        index.write().unwrap();
+        fs::remove_file(repo_path.join("gone.txt")).unwrap();
+        fs::write(repo_path.join("scratch.txt"), "new").unwrap();
+
+        let changed_files = files_changed_since(repo_path, "HEAD~2").unwrap();
+        let mut changed: Vec<_> = changed_files.changed.iter().cloned().collect();
+        changed.sort();
+        assert_eq!(
+            changed,
+            vec![
+                Path::new("kept.txt").to_path_buf(),
+                Path::new("scratch.txt").to_path_buf()
+            ]
+        );
+        assert_eq!(
+            changed_files.removed,
+            vec![Path::new("gone.txt").to_path_buf()]
+        );
+
+        // All commits are recent, so every file of the history counts as changed
+        let changed_files = files_changed_since(repo_path, "30d").unwrap();
+        assert!(changed_files.changed.contains(Path::new("old.txt")));
+        assert!(changed_files.removed.is_empty());
+
+        let err = files_changed_since(repo_path, "no-such-ref").unwrap_err();
+        assert!(err.to_string().contains("no-such-ref"), "{}", err);
+    }
+
+    #[test]
+    fn test_get_commit_diff() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+        let config = DiffConfig::default();
+
+        let root = commit_file(&repo, "file.txt", "one\n", "Add file");
+        let second = commit_file(
+            &repo,
+            "file.txt",
+            "one\ntwo\n",
+            "Extend file\n\nWith a longer body.",
+        );
+
+        // The root commit is diffed against the empty tree
+        let shown = get_commit_diff(repo_path, &root.to_string()[..7], &config).unwrap();
+        assert!(
+            shown.starts_with(&format!("commit {}\n", root)),
+            "{}",
+            shown
+        );
+        assert!(
+            shown.contains("Author: Test <test@example.com>"),
+            "{}",
+            shown
+        );
+        assert!(shown.contains("    Add file\n"), "{}", shown);
+        assert!(shown.contains("one"), "{}", shown);
+
+        let shown = get_commit_diff(repo_path, "HEAD", &config).unwrap();
+        assert!(
+            shown.starts_with(&format!("commit {}\n", second)),
+            "{}",
+            shown
+        );
+        assert!(
+            shown.contains("    Extend file\n\n    With a longer body.\n"),
+            "{}",
+            shown
+        );
+        assert!(shown.contains("two"), "{}", shown);
+        assert!(!shown.contains("merge commit"), "{}", shown);
+
+        let stat = get_commit_diff_stat(repo_path, "HEAD", &config).unwrap();
+        assert!(stat.contains("    Extend file\n"), "{}", stat);
+        assert!(
+            stat.contains("1 files changed, 1 insertions(+), 0 deletions(-)"),
+            "{}",
+            stat
+        );
+
+        // Merge commits are diffed against their first parent
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let first = repo.find_commit(root).unwrap();
+        let head = repo.find_commit(second).unwrap();
+        let merge = repo
+            .commit(
+                None,
+                &signature,
+                &signature,
+                "Merge",
+                &head.tree().unwrap(),
+                &[&first, &head],
+            )
+            .unwrap();
+        let shown = get_commit_diff(repo_path, &merge.to_string(), &config).unwrap();
+        assert!(
+            shown.contains(&format!("{} is a merge commit", &merge.to_string()[..7])),
+            "{}",
+            shown
+        );
+        assert!(shown.contains("two"), "{}", shown);
+
+        let err = get_commit_diff(repo_path, "no-such-rev", &config).unwrap_err();
+        assert!(err.to_string().contains("no-such-rev"), "{}", err);
+    }
+
+    #[test]
+    fn test_get_git_diff_workdir_to_ref() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+        let config = DiffConfig::default();
+
+        commit_file(&repo, "file.txt", "one\n", "Add file");
+        repo.tag_lightweight(
+            "base",
+            &repo.head().unwrap().peel(git2::ObjectType::Commit).unwrap(),
+            false,
+        )
+        .unwrap();
+        commit_file(&repo, "file.txt", "one\ntwo\n", "Extend file");
+
+        // Staged and unstaged changes are both compared against the reference
+        fs::write(repo_path.join("file.txt"), "one\ntwo\nthree\n").unwrap();
+        commit_file(&repo, "committed.txt", "committed\n", "Add committed");
+        let mut index = repo.index().unwrap();
+        fs::write(repo_path.join("other.txt"), "other\n").unwrap();
+        index.add_path(Path::new("other.txt")).unwrap();
+        index.write().unwrap();
+
+        let diff = get_git_diff_workdir_to_ref(repo_path, "base", &config).unwrap();
+        assert!(
+            diff.starts_with("Note: base is 2 commit(s) behind HEAD"),
+            "{}",
+            diff
+        );
+        for added in ["two", "three", "committed", "other"] {
+            assert!(diff.contains(added), "{} missing from {}", added, diff);
+        }
+
+        let diff = get_git_diff_workdir_to_ref(repo_path, "HEAD", &config).unwrap();
+        assert!(
+            diff.starts_with("Note: HEAD is the same commit as HEAD"),
+            "{}",
+            diff
+        );
+        assert!(!diff.contains("committed"), "{}", diff);
+        assert!(diff.contains("other"), "{}", diff);
+
+        let err = get_git_diff_workdir_to_ref(repo_path, "origin/main", &config).unwrap_err();
+        assert!(err.to_string().contains("origin/main"), "{}", err);
+    }
+
+    #[test]
+    fn test_git_log_and_diff_in_shallow_clone() {
+        let source_dir = TempDir::new().expect("Failed to create temp dir");
+        let source = init_repo(source_dir.path());
+
+        let root = commit_file(&source, "base.txt", "base\n", "Add base");
+        source
+            .branch("side", &source.find_commit(root).unwrap(), false)
+            .unwrap();
+        commit_file(&source, "main.txt", "one\n", "Main one");
+        let boundary = commit_file(&source, "main.txt", "two\n", "Main two");
+        commit_file(&source, "main.txt", "three\n", "Main three");
+        source.set_head("refs/heads/side").unwrap();
+        source
+            .checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+            .unwrap();
+        commit_file(&source, "side.txt", "side\n", "Side change");
+
+        // Clone the repository, then cut its history at `boundary` like `git clone --depth 2`
+        let clone_dir = TempDir::new().expect("Failed to create temp dir");
+        let url = format!("file://{}", source_dir.path().display());
+        let clone = Repository::clone(&url, clone_dir.path()).unwrap();
+        fs::write(clone.path().join("shallow"), format!("{}\n", boundary)).unwrap();
+        let clone = Repository::open(clone_dir.path()).unwrap();
+        assert!(clone.is_shallow());
+        let repo_path = clone_dir.path();
+
+        let log = get_git_log_commits(
+            repo_path,
+            "origin/side",
+            "origin/master",
+            &GitLogOptions::default(),
+        )
+        .unwrap();
+        let summaries: Vec<&str> = log.commits.iter().map(|c| c.summary.as_str()).collect();
+        assert_eq!(summaries, vec!["Main two", "Main three"]);
+        assert!(log.truncated);
+        let log_text = get_git_log(
+            repo_path,
+            "origin/side",
+            "origin/master",
+            &GitLogOptions::default(),
+        )
+        .unwrap();
+        assert!(
+            log_text.ends_with("(history truncated: shallow clone, 2 commits shown)\n"),
+            "{}",
+            log_text
+        );
+
+        // Commits above the boundary are listed without a note
+        let log = get_git_log_range_commits(
+            repo_path,
+            "origin/master~1..origin/master",
+            &GitLogOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(log.commits.len(), 1);
+        assert!(!log.truncated);
+
+        // The merge base is out of reach, so the references are diffed directly
+        let config = DiffConfig {
+            merge_base: true,
+            ..DiffConfig::default()
+        };
+        let diff =
+            get_git_diff_between_refs(repo_path, "origin/side", "origin/master", &config).unwrap();
+        assert!(diff.contains("side.txt"), "{}", diff);
+        assert!(diff.contains("three"), "{}", diff);
+    }
+
+    #[test]
+    fn test_missing_ref_suggestions() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+        let config = DiffConfig::default();
+        let log_options = GitLogOptions::default();
+
+        let oid = commit_file(&repo, "file.txt", "content", "Initial commit");
+        let commit = repo.find_commit(oid).unwrap();
+        repo.branch("feature/foo", &commit, false).unwrap();
+        repo.branch("feature/bar", &commit, false).unwrap();
+        repo.tag_lightweight("v1.0.0", commit.as_object(), false)
+            .unwrap();
+        for remote_branch in ["origin/feature/foo", "origin/release"] {
+            repo.reference(
+                &format!("refs/remotes/{}", remote_branch),
+                oid,
+                false,
+                "remote-tracking branch",
+            )
+            .unwrap();
+        }
+
+        // Closest names first, remote-tracking branches matched by their branch part
+        let err = get_git_log_commits(repo_path, "master", "feture/foo", &log_options).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Branch feture/foo doesn't exist! Did you mean 'feature/foo' or 'origin/feature/foo'?"
+        );
+
+        let err = get_git_diff_between_branches(repo_path, "v1.0", "master", &config).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Reference v1.0 doesn't exist! Did you mean 'v1.0.0'?"
+        );
+
+        // A branch that only exists on the remote is reported with its qualified name
+        let err = get_git_log_commits(repo_path, "release", "master", &log_options).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("remote-tracking branch 'origin/release'"),
+            "{}",
+            err
+        );
+
+        // Nothing is suggested when no name is close enough
+        let err =
+            get_git_diff_between_branches(repo_path, "master", "unrelated", &config).unwrap_err();
+        assert_eq!(err.to_string(), "Reference unrelated doesn't exist!");
+    }
+
+    #[test]
+    fn test_get_branch_relationship() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+
+        let base = commit_file(&repo, "file.txt", "base\n", "Base");
+        repo.branch("feature", &repo.find_commit(base).unwrap(), false)
+            .unwrap();
+        commit_file(&repo, "file.txt", "main\n", "Main change");
+        repo.set_head("refs/heads/feature").unwrap();
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+            .unwrap();
+        commit_file(&repo, "feature.txt", "one\n", "Feature one");
+        commit_file(&repo, "feature.txt", "two\n", "Feature two");
+
+        let relationship = get_branch_relationship(repo_path, "master", "feature").unwrap();
+        assert_eq!(relationship.ahead, 2);
+        assert_eq!(relationship.behind, 1);
+        let merge_base = relationship.merge_base.expect("Missing merge base");
+        assert_eq!(merge_base.sha, base.to_string());
+        assert_eq!(merge_base.short_sha, base.to_string()[..7]);
+        assert!(!merge_base.diverged_at.is_empty());
+
+        // Unrelated histories have no merge base
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let tree = repo.find_commit(base).unwrap().tree().unwrap();
+        repo.commit(
+            Some("refs/heads/orphan"),
+            &signature,
+            &signature,
+            "Orphan",
+            &tree,
+            &[],
+        )
+        .unwrap();
+        let relationship = get_branch_relationship(repo_path, "master", "orphan").unwrap();
+        assert!(relationship.merge_base.is_none());
+        assert_eq!((relationship.ahead, relationship.behind), (1, 2));
+    }
+
+    #[test]
+    fn test_get_current_branch() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+        assert_eq!(get_current_branch(repo_path).unwrap(), "master");
+
+        let base = commit_file(&repo, "file.txt", "base\n", "Base");
+        repo.branch("feature/login", &repo.find_commit(base).unwrap(), false)
+            .unwrap();
+        repo.set_head("refs/heads/feature/login").unwrap();
+        assert_eq!(get_current_branch(repo_path).unwrap(), "feature/login");
+
+        repo.set_head_detached(base).unwrap();
+        let error = get_current_branch(repo_path).unwrap_err().to_string();
+        assert!(error.contains("detached"), "{}", error);
+    }
+
+    #[test]
+    fn test_git_diff_in_linked_worktree() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let main_path = temp_dir.path().join("main");
+        fs::create_dir(&main_path).unwrap();
+        let repo = init_repo(&main_path);
+        commit_file(&repo, "file.txt", "one\n", "Add file");
+
+        let worktree_path = temp_dir.path().join("linked");
+        repo.worktree("linked", &worktree_path, None).unwrap();
+        assert!(worktree_path.join(".git").is_file());
+
+        // Stage a change in the worktree only
+        let worktree = Repository::open(&worktree_path).unwrap();
+        commit_file(&worktree, "linked.txt", "committed\n", "Commit in worktree");
+        fs::write(worktree_path.join("file.txt"), "one\ntwo\n").unwrap();
+        let mut index = worktree.index().unwrap();
+        index.add_path(Path::new("file.txt")).unwrap();
+        index.write().unwrap();
+
+        let config = DiffConfig::default();
+        let diff = get_git_diff(&worktree_path, &config).unwrap();
+        assert!(diff.contains("two"), "{}", diff);
+        // The worktree's HEAD is used, so its own commit is not part of the staged diff
+        assert!(!diff.contains("linked.txt"), "{}", diff);
+
+        let diff = get_git_diff(&main_path, &config).unwrap();
+        assert_eq!(diff, "no diff between HEAD and index");
+
+        let log = get_git_log_range(&worktree_path, "-n 1", &GitLogOptions::default()).unwrap();
+        assert!(log.contains("Commit in worktree"), "{}", log);
+        let log = get_git_log_range(&main_path, "-n 1", &GitLogOptions::default()).unwrap();
+        assert!(log.contains("Add file"), "{}", log);
+
+        let tracked = list_tracked_files(&worktree_path).unwrap();
+        assert!(tracked.contains(&Path::new("linked.txt").to_path_buf()));
+        let tracked = list_tracked_files(&main_path).unwrap();
+        assert_eq!(tracked, vec![Path::new("file.txt").to_path_buf()]);
+    }
+
+    #[test]
+    fn test_last_commits_for_paths() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+
+        fs::create_dir(repo_path.join("sub")).unwrap();
+        commit_file(&repo, "sub/c.txt", "c\n", "Add c");
+        commit_file(&repo, "a.txt", "a\n", "Add a");
+        let base = commit_file(&repo, "b.txt", "b\n", "Add b");
+        repo.branch("side", &repo.find_commit(base).unwrap(), false)
+            .unwrap();
+        let main_change = commit_file(&repo, "a.txt", "a2\n", "Change a");
+
+        repo.set_head("refs/heads/side").unwrap();
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+            .unwrap();
+        let side_change = commit_file(&repo, "b.txt", "b2\n", "Change b");
+
+        // Merge side into master, which changes b.txt relative to the first parent only
+        repo.set_head("refs/heads/master").unwrap();
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+            .unwrap();
+        let ours = repo.find_commit(main_change).unwrap();
+        let theirs = repo.find_commit(side_change).unwrap();
+        let mut merged = repo.merge_commits(&ours, &theirs, None).unwrap();
+        let tree = repo
+            .find_tree(merged.write_tree_to(&repo).unwrap())
+            .unwrap();
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "Merge side",
+            &tree,
+            &[&ours, &theirs],
+        )
+        .unwrap();
+
+        let paths: Vec<_> = ["a.txt", "b.txt", "sub/c.txt", "untracked.txt"]
+            .iter()
+            .map(|path| Path::new(path).to_path_buf())
+            .collect();
+        let last_commits = last_commits_for_paths(repo_path, &paths).unwrap();
+        assert_eq!(last_commits.len(), 3);
+        assert_eq!(last_commits[&paths[0]].sha, main_change.to_string());
+        assert_eq!(last_commits[&paths[1]].sha, side_change.to_string());
+        assert_eq!(last_commits[&paths[1]].summary, "Change b");
+        assert_eq!(last_commits[&paths[2]].author, "Test");
+        assert_eq!(last_commits[&paths[2]].date.len(), "2024-03-02".len());
+
+        // Paths are relative to a subdirectory of the repository
+        let last_commits =
+            last_commits_for_paths(&repo_path.join("sub"), &[Path::new("c.txt").to_path_buf()])
+                .unwrap();
+        assert_eq!(last_commits[Path::new("c.txt")].summary, "Add c");
+    }
+
+    #[test]
+    fn test_last_commits_for_paths_performance() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+
+        // Build a long history without the working tree: only churn.txt changes after the
+        // first commit, so finding stable.txt requires walking the whole history
+        let stable = repo.blob(b"stable\n").unwrap();
+        let mut parent: Option<git2::Commit> = None;
+        for i in 0..3000 {
+            let churn = repo.blob(format!("churn {}\n", i).as_bytes()).unwrap();
+            let mut builder = repo.treebuilder(None).unwrap();
+            builder.insert("stable.txt", stable, 0o100644).unwrap();
+            builder.insert("churn.txt", churn, 0o100644).unwrap();
+            let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+            let parents: Vec<&git2::Commit> = parent.iter().collect();
+            let oid = repo
+                .commit(
+                    Some("HEAD"),
+                    &signature,
+                    &signature,
+                    &format!("Commit {}", i),
+                    &tree,
+                    &parents,
+                )
+                .unwrap();
+            parent = Some(repo.find_commit(oid).unwrap());
+        }
+
+        let paths = vec![
+            Path::new("stable.txt").to_path_buf(),
+            Path::new("churn.txt").to_path_buf(),
+        ];
+        let started = std::time::Instant::now();
+        let last_commits = last_commits_for_paths(repo_path, &paths).unwrap();
+        let elapsed = started.elapsed();
+
+        assert_eq!(last_commits[&paths[0]].summary, "Commit 0");
+        assert_eq!(last_commits[&paths[1]].summary, "Commit 2999");
+        assert!(
+            elapsed < std::time::Duration::from_secs(10),
+            "Walking 3000 commits took {:?}",
+            elapsed
+        );
+    }
+
+    #[test]
+    fn test_git_log_with_files() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+
+        commit_file(&repo, "a.txt", "a\n", "Add a");
+        commit_file(&repo, "b.txt", "b\nb\nb\n", "Add b");
+        commit_file(&repo, "a.txt", "a2\n", "Change a");
+
+        // Rename b.txt and delete a.txt in a single commit
+        fs::rename(repo_path.join("b.txt"), repo_path.join("c.txt")).unwrap();
+        fs::remove_file(repo_path.join("a.txt")).unwrap();
+        let mut index = repo.index().unwrap();
+        index.remove_path(Path::new("a.txt")).unwrap();
+        index.remove_path(Path::new("b.txt")).unwrap();
+        index.add_path(Path::new("c.txt")).unwrap();
+        index.write().unwrap();
+        commit_file(&repo, "c.txt", "b\nb\nb\n", "Rename b and drop a");
+
+        let log_options = GitLogOptions {
+            include_files: true,
+            ..GitLogOptions::default()
+        };
+        let log = get_git_log_range_commits(repo_path, "-n 4", &log_options).unwrap();
+        let statuses = |index: usize| -> Vec<(char, String, Option<String>)> {
+            log.commits[index]
+                .files
+                .iter()
+                .map(|file| (file.status, file.path.clone(), file.old_path.clone()))
+                .collect()
+        };
+        assert_eq!(statuses(0), vec![('A', "a.txt".to_string(), None)]);
+        assert_eq!(statuses(2), vec![('M', "a.txt".to_string(), None)]);
+        assert_eq!(
+            statuses(3),
+            vec![
+                ('D', "a.txt".to_string(), None),
+                ('R', "c.txt".to_string(), Some("b.txt".to_string())),
+            ]
+        );
+
+        let log_text = get_git_log_range(repo_path, "-n 1", &log_options).unwrap();
+        assert!(log_text.contains("    D a.txt\n"), "{}", log_text);
+        assert!(log_text.contains("    R b.txt -> c.txt\n"), "{}", log_text);
+
+        // The listed files are capped per commit
+        let log_options = GitLogOptions {
+            include_files: true,
+            max_files: Some(1),
+            ..GitLogOptions::default()
+        };
+        let log = get_git_log_range_commits(repo_path, "-n 1", &log_options).unwrap();
+        assert_eq!(log.commits[0].files.len(), 1);
+        assert_eq!(log.commits[0].more_files, 1);
+        let log_text = get_git_log_range(repo_path, "-n 1", &log_options).unwrap();
+        assert!(log_text.contains("    (+1 more)\n"), "{}", log_text);
+
+        // Files are only listed when asked for
+        let log = get_git_log_range_commits(repo_path, "-n 1", &GitLogOptions::default()).unwrap();
+        assert!(log.commits[0].files.is_empty());
+    }
+
+    #[test]
+    fn test_get_latest_tags() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+
+        assert!(get_latest_tags(repo_path, 2).unwrap().is_empty());
+        let error = get_latest_tags_range(repo_path).unwrap_err().to_string();
+        assert!(error.contains("No tags found"), "{}", error);
+
+        let mut parents = vec![];
+        let mut commits = vec![];
+        for (time, content) in [(1_000, "one"), (2_000, "two"), (3_000, "three")] {
+            fs::write(repo_path.join("version.txt"), content).unwrap();
+            let mut index = repo.index().unwrap();
+            index.add_path(Path::new("version.txt")).unwrap();
+            index.write().unwrap();
+            let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+            let signature =
+                Signature::new("Test", "test@example.com", &git2::Time::new(time, 0)).unwrap();
+            let parent_refs: Vec<&git2::Commit> = parents.iter().collect();
+            let oid = repo
+                .commit(
+                    Some("HEAD"),
+                    &signature,
+                    &signature,
+                    content,
+                    &tree,
+                    &parent_refs,
+                )
+                .unwrap();
+            parents = vec![repo.find_commit(oid).unwrap()];
+            commits.push(repo.find_object(oid, None).unwrap());
+        }
+
+        // Only one tag: fall back to the range from it to HEAD
+        let tagger =
+            Signature::new("Test", "test@example.com", &git2::Time::new(9_000, 0)).unwrap();
+        repo.tag("v1.9.0", &commits[0], &tagger, "Release 1.9.0", false)
+            .unwrap();
+        let range = get_latest_tags_range(repo_path).unwrap();
+        assert_eq!((range.from.as_str(), range.to.as_str()), ("v1.9.0", "HEAD"));
+        assert!(range.note.unwrap().contains("v1.9.0 is the only tag"));
+
+        // Lightweight and annotated tags are ordered by the date of their commit, not by name
+        // or by the date of the tag object
+        repo.tag_lightweight("v1.10.0", &commits[1], false).unwrap();
+        repo.tag_lightweight("nightly", &commits[2], false).unwrap();
+        assert_eq!(
+            get_latest_tags(repo_path, 2).unwrap(),
+            vec!["nightly", "v1.10.0"]
+        );
+        assert_eq!(get_latest_tags(repo_path, 10).unwrap().len(), 3);
+
+        // Version tags are preferred over other tags
+        let range = get_latest_tags_range(repo_path).unwrap();
+        assert_eq!(
+            (range.from.as_str(), range.to.as_str(), range.note),
+            ("v1.9.0", "v1.10.0", None)
+        );
+        let diff =
+            get_git_diff_between_refs(repo_path, &range.from, &range.to, &DiffConfig::default())
+                .unwrap();
+        assert!(diff.contains("one") && diff.contains("two"), "{}", diff);
+        let log = get_git_log_commits(repo_path, &range.from, &range.to, &GitLogOptions::default())
+            .unwrap();
+        assert_eq!(log.commits.len(), 1);
+        assert_eq!(log.commits[0].summary, "two");
+    }
+
+    #[test]
+    fn test_get_contributors() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+
+        fs::write(
+            repo_path.join(".mailmap"),
+            "Alice Smith <alice@example.com>\nAlice Smith <alice@example.com> <alice@old-job.com>\n",
+        )
+        .unwrap();
+        let mut parents = vec![];
+        for (index, (name, email, time)) in [
+            ("Bob", "bob@example.com", 1_704_067_200),
+            ("alice", "alice@old-job.com", 1_704_153_600),
+            ("Bob", "BOB@example.com", 1_704_240_000),
+            ("Alice Smith", "alice@example.com", 1_704_326_400),
+            ("Alice S.", "alice@example.com", 1_704_412_800),
+            ("Carol", "carol@example.com", 1_704_499_200),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            fs::write(repo_path.join("file.txt"), index.to_string()).unwrap();
+            let mut index = repo.index().unwrap();
+            index.add_path(Path::new("file.txt")).unwrap();
+            index.write().unwrap();
+            let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+            let signature = Signature::new(name, email, &git2::Time::new(time, 0)).unwrap();
+            let parent_refs: Vec<&git2::Commit> = parents.iter().collect();
+            let oid = repo
+                .commit(
+                    Some("HEAD"),
+                    &signature,
+                    &signature,
+                    "change",
+                    &tree,
+                    &parent_refs,
+                )
+                .unwrap();
+            parents = vec![repo.find_commit(oid).unwrap()];
+        }
+
+        // The mailmap and case insensitive emails merge the identities
+        let contributors = get_contributors(repo_path, 10, None, 1000).unwrap();
+        let summary: Vec<(&str, &str, usize, &str)> = contributors
+            .iter()
+            .map(|c| {
+                (
+                    c.name.as_str(),
+                    c.email.as_str(),
+                    c.commits,
+                    c.last_commit.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("Alice Smith", "alice@example.com", 3, "2024-01-05"),
+                ("Bob", "BOB@example.com", 2, "2024-01-03"),
+                ("Carol", "carol@example.com", 1, "2024-01-06"),
+            ]
+        );
+
+        assert_eq!(get_contributors(repo_path, 1, None, 1000).unwrap().len(), 1);
+
+        // The commit limit keeps the most recent commits, and the range restricts the walk
+        let recent = get_contributors(repo_path, 10, None, 2).unwrap();
+        assert_eq!(recent.len(), 2);
+        assert!(recent.iter().all(|c| c.commits == 1));
+        let ranged = get_contributors(repo_path, 10, Some("HEAD~6..HEAD~3"), 1000);
+        assert!(ranged.is_err(), "HEAD~6 doesn't exist");
+        let ranged = get_contributors(repo_path, 10, Some("HEAD~5..HEAD~3"), 1000).unwrap();
+        // Ties are broken by the most recent activity
+        let names: Vec<&str> = ranged.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["Bob", "Alice Smith"]);
+    }
+
+    #[test]
+    fn test_git_log_merges() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+
+        // master: base - main work - merge, feature: base - feature one - feature two
+        let base = commit_file(&repo, "file.txt", "base", "Base");
+        commit_file(&repo, "feature.txt", "one", "Feature one");
+        let feature = commit_file(&repo, "feature.txt", "two", "Feature two");
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let base = repo.find_commit(base).unwrap();
+        let main_work = repo
+            .commit(
+                None,
+                &signature,
+                &signature,
+                "Main work",
+                &base.tree().unwrap(),
+                &[&base],
+            )
+            .unwrap();
+        let main_work = repo.find_commit(main_work).unwrap();
+        let feature = repo.find_commit(feature).unwrap();
+        let merge = repo
+            .commit(
+                None,
+                &signature,
+                &signature,
+                "Merge branch 'feature'",
+                &feature.tree().unwrap(),
+                &[&main_work, &feature],
+            )
+            .unwrap();
+        repo.reference("refs/heads/master", merge, true, "merge")
+            .unwrap();
+
+        let summaries = |log_options: &GitLogOptions| -> Vec<String> {
+            get_git_log_range_commits(repo_path, "-n 10", log_options)
+                .unwrap()
+                .commits
+                .into_iter()
+                .map(|commit| commit.summary)
+                .collect()
+        };
+
+        let all = summaries(&GitLogOptions::default());
+        assert_eq!(all.len(), 5);
+        assert!(all.contains(&"Merge branch 'feature'".to_string()));
+
+        let no_merges = summaries(&GitLogOptions {
+            skip_merges: true,
+            ..GitLogOptions::default()
+        });
+        assert_eq!(no_merges.len(), 4);
+        assert!(!no_merges.contains(&"Merge branch 'feature'".to_string()));
+
+        let first_parent = summaries(&GitLogOptions {
+            first_parent: true,
+            ..GitLogOptions::default()
+        });
+        assert_eq!(
+            first_parent,
+            vec!["Base", "Main work", "Merge branch 'feature'"]
+        );
+
+        // Both combined leave only the commits made on the branch itself
+        let own_commits = summaries(&GitLogOptions {
+            skip_merges: true,
+            first_parent: true,
+            ..GitLogOptions::default()
+        });
+        assert_eq!(own_commits, vec!["Base", "Main work"]);
+    }
+
+    /// A sink that keeps no data, recording how much was written and the largest single write.
+    #[derive(Default)]
+    struct MeasuringSink {
+        written: usize,
+        largest_write: usize,
+        limit: Option<usize>,
+    }
+
+    impl std::io::Write for MeasuringSink {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.limit.is_some_and(|limit| self.written >= limit) {
+                return Err(std::io::Error::other("budget exhausted"));
+            }
+            self.written += buf.len();
+            self.largest_write = self.largest_write.max(buf.len());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_git_diff_streams_large_diff() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = temp_dir.path();
+        let repo = init_repo(repo_path);
+
+        // 200 files of 2,000 lines, all rewritten: a diff of several megabytes
+        let commit_all = |version: &str| {
+            let mut index = repo.index().unwrap();
+            for file in 0..200 {
+                let name = format!("file_{}.txt", file);
+                let content: String = (0..2000)
+                    .map(|line| format!("{} line {} of {}\n", version, line, name))
+                    .collect();
+                fs::write(repo_path.join(&name), content).unwrap();
+                index.add_path(Path::new(&name)).unwrap();
+            }
+            index.write().unwrap();
+            let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+            let signature = Signature::now("Test", "test@example.com").unwrap();
+            let parents: Vec<git2::Commit> = repo
+                .head()
+                .ok()
+                .map(|head| head.peel_to_commit().unwrap())
+                .into_iter()
+                .collect();
+            let parent_refs: Vec<&git2::Commit> = parents.iter().collect();
+            repo.commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                version,
+                &tree,
+                &parent_refs,
+            )
+            .unwrap();
+        };
+        commit_all("old");
+        commit_all("new");
+
+        // The diff reaches the sink line by line instead of as one buffer
+        let mut sink = MeasuringSink::default();
+        write_git_diff_between_refs(
+            repo_path,
+            "HEAD~1",
+            "HEAD",
+            &DiffConfig::default(),
+            &mut sink,
+        )
+        .unwrap();
+        assert!(sink.written > 5_000_000, "{} bytes", sink.written);
+        assert!(sink.largest_write < 1024, "{} bytes", sink.largest_write);
+
+        let mut sink = MeasuringSink::default();
+        write_commit_diff(repo_path, "HEAD", &DiffConfig::default(), &mut sink).unwrap();
+        assert!(sink.written > 5_000_000, "{} bytes", sink.written);
+        assert!(sink.largest_write < 1024, "{} bytes", sink.largest_write);
+
+        // A consumer whose budget is spent stops the generation early
+        let mut sink = MeasuringSink {
+            limit: Some(10_000),
+            ..MeasuringSink::default()
+        };
+        let error = write_git_diff_between_refs(
+            repo_path,
+            "HEAD~1",
+            "HEAD",
+            &DiffConfig::default(),
+            &mut sink,
+        )
+        .unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("budget exhausted"),
+            "{:#}",
+            error
+        );
+        assert!(sink.written < 11_000, "{} bytes", sink.written);
+
+        // The string functions wrap the streaming ones
+        let diff =
+            get_git_diff_between_refs(repo_path, "HEAD~1", "HEAD", &DiffConfig::default()).unwrap();
+        let mut streamed = Vec::new();
+        write_git_diff_between_refs(
+            repo_path,
+            "HEAD~1",
+            "HEAD",
+            &DiffConfig::default(),
+            &mut streamed,
+        )
+        .unwrap();
+        assert_eq!(diff.as_bytes(), streamed.as_slice());
     }
 }
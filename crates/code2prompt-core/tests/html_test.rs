@@ -0,0 +1,161 @@
+use code2prompt_core::configuration::Code2PromptConfig;
+#[cfg(feature = "html")]
+use code2prompt_core::html::{render_html, HtmlPage};
+#[cfg(feature = "html")]
+use code2prompt_core::path::FileEncoding;
+use code2prompt_core::session::Code2PromptSession;
+#[cfg(feature = "html")]
+use code2prompt_core::session::{FileStats, OutputFile};
+use code2prompt_core::template::OutputFormat;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[cfg(feature = "html")]
+    use scraper::{Html, Selector};
+
+    fn html_session(dir: &std::path::Path) -> Code2PromptSession {
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(
+            dir.join("src/main.rs"),
+            "fn main() {\n    println!(\"<hello>\");\n}\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("src/lib.rs"),
+            "pub fn add(a: u8, b: u8) -> u8 {\n    a + b\n}\n",
+        )
+        .unwrap();
+        fs::write(dir.join("a&b.md"), "# Notes & <tags>\n").unwrap();
+        let config = Code2PromptConfig::builder()
+            .path(dir.to_path_buf())
+            .output_format(OutputFormat::Html)
+            .build()
+            .unwrap();
+        Code2PromptSession::new(config)
+    }
+
+    #[test]
+    fn test_html_output_format() {
+        assert_eq!("html".parse::<OutputFormat>().unwrap(), OutputFormat::Html);
+        assert_eq!("HTM".parse::<OutputFormat>().unwrap(), OutputFormat::Html);
+        assert_eq!(OutputFormat::Html.extension(), "html");
+        assert!(OutputFormat::Markdown.ensure_supported().is_ok());
+        assert_eq!(
+            OutputFormat::Html.ensure_supported().is_ok(),
+            cfg!(feature = "html")
+        );
+    }
+
+    #[cfg(not(feature = "html"))]
+    #[test]
+    fn test_html_output_needs_the_feature() {
+        let dir = tempdir().unwrap();
+        let error = html_session(dir.path()).generate_prompt().unwrap_err();
+        assert!(error.to_string().contains("html feature"), "{}", error);
+    }
+
+    #[cfg(feature = "html")]
+    #[test]
+    fn test_html_output_has_a_section_per_file() {
+        let dir = tempdir().unwrap();
+        let rendered = html_session(dir.path()).generate_prompt().unwrap();
+        let document = Html::parse_document(&rendered.prompt);
+        assert!(document.errors.is_empty(), "{:?}", document.errors);
+
+        let select = |selector: &str| Selector::parse(selector).unwrap();
+        let sections: Vec<_> = document.select(&select("main > section.file")).collect();
+        assert_eq!(sections.len(), 3);
+        let paths: Vec<String> = sections
+            .iter()
+            .map(|section| {
+                section
+                    .select(&select("summary > code"))
+                    .next()
+                    .unwrap()
+                    .text()
+                    .collect()
+            })
+            .collect();
+        assert_eq!(paths, vec!["a&b.md", "src/lib.rs", "src/main.rs"]);
+
+        // The tree links to the sections, which are collapsible
+        let links: Vec<&str> = document
+            .select(&select("nav a"))
+            .map(|link| link.value().attr("href").unwrap())
+            .collect();
+        let ids: Vec<String> = sections
+            .iter()
+            .map(|section| format!("#{}", section.value().id().unwrap()))
+            .collect();
+        assert_eq!(links, ids);
+        assert_eq!(
+            document
+                .select(&select("section.file > details[open]"))
+                .count(),
+            3
+        );
+
+        // The code is highlighted with inline styles, escaped, and there is no external asset
+        let main: String = sections[2].text().collect();
+        assert!(main.contains("println!(\"<hello>\");"), "{}", main);
+        assert!(sections[2].select(&select("pre span[style]")).count() > 1);
+        assert_eq!(document.select(&select("script, link, img")).count(), 0);
+        assert_eq!(document.select(&select("head > style")).count(), 1);
+
+        // The stats table has the totals and a row per file
+        let cells: Vec<String> = document
+            .select(&select("section.stats table:first-of-type td"))
+            .map(|cell| cell.text().collect())
+            .collect();
+        assert_eq!(cells[0], "3");
+        assert_eq!(document.select(&select("section.stats tr")).count(), 2 + 4);
+    }
+
+    #[cfg(feature = "html")]
+    #[test]
+    fn test_render_html_colors_the_diff() {
+        let page = HtmlPage {
+            title: "project".to_string(),
+            generated_at: "2025-01-01T00:00:00Z".to_string(),
+            tree: None,
+            files: vec![OutputFile {
+                path: "notes.unknown".to_string(),
+                language: String::new(),
+                size: 5,
+                tokens: None,
+                content: "plain\n".to_string(),
+                encoding: FileEncoding::Text,
+            }],
+            diff: Some(
+                "```diff\ndiff --git a/a.rs b/a.rs\n--- a/a.rs\n+++ b/a.rs\n@@ -1 +1 @@\n-old()\n+new()\n context\n```\n"
+                    .to_string(),
+            ),
+            stats: FileStats::default(),
+            tokens: Some(12),
+        };
+        let html = render_html(&page).unwrap();
+        let document = Html::parse_document(&html);
+        assert!(document.errors.is_empty(), "{:?}", document.errors);
+        assert!(document
+            .select(&Selector::parse("nav").unwrap())
+            .next()
+            .is_none());
+
+        let lines = |class: &str| -> Vec<String> {
+            let selector = Selector::parse(&format!("pre.diff span.{}", class)).unwrap();
+            document
+                .select(&selector)
+                .map(|line| line.text().collect())
+                .collect()
+        };
+        assert_eq!(lines("added"), vec!["+new()"]);
+        assert_eq!(lines("removed"), vec!["-old()"]);
+        assert_eq!(lines("hunk"), vec!["@@ -1 +1 @@"]);
+        assert_eq!(lines("meta").len(), 3);
+        assert!(!html.contains("```"));
+    }
+}
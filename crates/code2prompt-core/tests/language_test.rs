@@ -0,0 +1,110 @@
+use code2prompt_core::language::{
+    code_fence, detect_language, language_for_extension, language_for_path, language_for_shebang,
+};
+use std::collections::HashMap;
+use std::path::Path;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_language_for_extension() {
+        assert_eq!(language_for_extension("rs"), "rust");
+        assert_eq!(language_for_extension("PY"), "python");
+        assert_eq!(language_for_extension("yml"), "yaml");
+        assert_eq!(language_for_extension("hpp"), "cpp");
+        assert_eq!(language_for_extension("txt"), "txt");
+        assert_eq!(language_for_extension("xyz"), "");
+        assert_eq!(language_for_extension(""), "");
+    }
+
+    #[test]
+    fn test_language_for_path() {
+        assert_eq!(language_for_path(Path::new("src/main.rs")), "rust");
+        assert_eq!(
+            language_for_path(Path::new("docker/Dockerfile")),
+            "dockerfile"
+        );
+        assert_eq!(language_for_path(Path::new("Makefile")), "makefile");
+        assert_eq!(
+            language_for_path(Path::new("Dockerfile.prod")),
+            "dockerfile"
+        );
+        assert_eq!(language_for_path(Path::new("CMakeLists.txt")), "cmake");
+        assert_eq!(language_for_path(Path::new("LICENSE")), "");
+        assert_eq!(language_for_path(Path::new("notes.xyz")), "");
+    }
+
+    #[test]
+    fn test_language_for_shebang() {
+        assert_eq!(
+            language_for_shebang("#!/usr/bin/env python3\nprint(1)"),
+            Some("python")
+        );
+        assert_eq!(language_for_shebang("#!/bin/sh\necho"), Some("bash"));
+        assert_eq!(
+            language_for_shebang("#!/usr/bin/env -S node --harmony\n"),
+            Some("javascript")
+        );
+        assert_eq!(
+            language_for_shebang("#!/usr/bin/python3.12"),
+            Some("python")
+        );
+        assert_eq!(language_for_shebang("#!/usr/bin/env unknown"), None);
+        assert_eq!(language_for_shebang("print(1)"), None);
+    }
+
+    #[test]
+    fn test_detect_language() {
+        let none = HashMap::new();
+        assert_eq!(
+            detect_language(Path::new("Dockerfile"), "FROM rust", &none),
+            "dockerfile"
+        );
+        assert_eq!(
+            detect_language(Path::new("bin/deploy"), "#!/usr/bin/env python3\n", &none),
+            "python"
+        );
+        // Only the scripts without extension are sniffed
+        assert_eq!(
+            detect_language(Path::new("notes.xyz"), "#!/bin/sh\n", &none),
+            ""
+        );
+        assert_eq!(
+            detect_language(Path::new("LICENSE"), "MIT License", &none),
+            ""
+        );
+
+        let languages = HashMap::from([
+            ("pyx".to_string(), "cython".to_string()),
+            (".RS".to_string(), "rust2".to_string()),
+            ("Tiltfile".to_string(), "starlark".to_string()),
+        ]);
+        assert_eq!(
+            detect_language(Path::new("fast.pyx"), "", &languages),
+            "cython"
+        );
+        assert_eq!(
+            detect_language(Path::new("main.rs"), "", &languages),
+            "rust2"
+        );
+        assert_eq!(
+            detect_language(Path::new("Tiltfile"), "", &languages),
+            "starlark"
+        );
+        assert_eq!(
+            detect_language(Path::new("app.py"), "", &languages),
+            "python"
+        );
+    }
+
+    #[test]
+    fn test_code_fence() {
+        assert_eq!(code_fence("fn main() {}"), "```");
+        assert_eq!(code_fence("Use `cargo test` or ``x``"), "```");
+        assert_eq!(code_fence("# Usage\n```sh\ncargo run\n```\n"), "````");
+        assert_eq!(code_fence("`````"), "``````");
+        assert_eq!(code_fence(""), "```");
+    }
+}
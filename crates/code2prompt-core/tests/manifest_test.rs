@@ -0,0 +1,100 @@
+use code2prompt_core::configuration::Code2PromptConfig;
+use code2prompt_core::manifest::{
+    csv_field, manifest_csv, ManifestRow, BUDGET_SKIP_REASON, MANIFEST_COLUMNS,
+};
+use code2prompt_core::session::Code2PromptSession;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_csv_field_quotes_per_rfc_4180() {
+        assert_eq!(csv_field("src/main.rs"), "src/main.rs");
+        assert_eq!(csv_field(""), "");
+        assert_eq!(csv_field("a,b.rs"), "\"a,b.rs\"");
+        assert_eq!(csv_field("say \"hi\".txt"), "\"say \"\"hi\"\".txt\"");
+        assert_eq!(csv_field("line\nbreak"), "\"line\nbreak\"");
+        assert_eq!(csv_field("carriage\rreturn"), "\"carriage\rreturn\"");
+    }
+
+    #[test]
+    fn test_manifest_csv() {
+        let rows = vec![
+            ManifestRow {
+                path: "src/a,b.rs".to_string(),
+                extension: "rs".to_string(),
+                language: "rust".to_string(),
+                bytes: Some(120),
+                lines: Some(4),
+                tokens: Some(30),
+                truncated: true,
+                last_commit_sha: Some("abc123".to_string()),
+                skip_reason: None,
+            },
+            ManifestRow::skipped("image.png", "binary content, skipped"),
+        ];
+        let csv = manifest_csv(&rows);
+        assert_eq!(
+            csv,
+            "path,extension,language,bytes,lines,tokens,truncated,last_commit_sha,skip_reason\r\n\
+             \"src/a,b.rs\",rs,rust,120,4,30,true,abc123,\r\n\
+             image.png,png,,,,,false,,\"binary content, skipped\"\r\n"
+        );
+
+        // Without rows, only the header
+        assert_eq!(
+            manifest_csv(&[]),
+            format!("{}\r\n", MANIFEST_COLUMNS.join(","))
+        );
+    }
+
+    #[test]
+    fn test_manifest_rows() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("small.txt"), "a small file\nof two lines\n").unwrap();
+        fs::write(
+            dir.path().join("large.txt"),
+            "many words in this file ".repeat(200),
+        )
+        .unwrap();
+        fs::write(dir.path().join("data.bin"), [0u8, 159, 146, 150, 0, 1]).unwrap();
+
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .token_budget(Some(300))
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        session.generate_prompt().unwrap();
+
+        let rows = session.manifest_rows(false);
+        assert_eq!(rows.len(), 1);
+        let small = &rows[0];
+        assert_eq!(small.path, "small.txt");
+        assert_eq!(small.extension, "txt");
+        assert_eq!(small.bytes, Some(26));
+        assert_eq!(small.lines, Some(2));
+        assert!(small.tokens.unwrap() > 0);
+        assert!(!small.truncated);
+        assert_eq!(small.last_commit_sha, None);
+        assert_eq!(small.skip_reason, None);
+
+        let rows = session.manifest_rows(true);
+        let skipped: Vec<(&str, Option<&str>)> = rows[1..]
+            .iter()
+            .map(|row| (row.path.as_str(), row.skip_reason.as_deref()))
+            .collect();
+        assert_eq!(
+            skipped,
+            vec![
+                ("data.bin", Some("binary content, skipped")),
+                ("large.txt", Some(BUDGET_SKIP_REASON)),
+            ]
+        );
+        assert!(rows[2].tokens.unwrap() > 1000);
+        assert_eq!(rows[2].bytes, None);
+    }
+}
@@ -0,0 +1,131 @@
+use code2prompt_core::output::{
+    append_to_file, output_path_tokens, part_file_path, resolve_output_path,
+    write_compressed_to_file, Compression, APPEND_SEPARATOR,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_part_file_path() {
+        assert_eq!(part_file_path("prompt.md", 2), "prompt.part2.md");
+        assert_eq!(part_file_path("out/prompt.xml", 1), "out/prompt.part1.xml");
+        assert_eq!(part_file_path("prompt", 3), "prompt.part3");
+    }
+
+    #[test]
+    fn test_output_path_tokens() {
+        assert_eq!(
+            output_path_tokens("prompts/{dirname}-{timestamp}.md").unwrap(),
+            vec!["dirname", "timestamp"]
+        );
+        assert!(output_path_tokens("prompt.md").unwrap().is_empty());
+        let error = output_path_tokens("prompts/{date}.md")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("Unknown token {date}"), "{}", error);
+        assert!(
+            error.contains("{timestamp}, {dirname}, {branch}"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_resolve_output_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let codebase = dir.path().join("my_project");
+        std::fs::create_dir(&codebase).unwrap();
+        assert_eq!(
+            resolve_output_path("prompts/{dirname}.md", &codebase).unwrap(),
+            "prompts/my_project.md"
+        );
+        let resolved = resolve_output_path("{timestamp}.md", &codebase).unwrap();
+        assert!(
+            regex::Regex::new(r"^\d{8}-\d{6}\.md$")
+                .unwrap()
+                .is_match(&resolved),
+            "{}",
+            resolved
+        );
+        assert!(resolve_output_path("{branch}.md", &codebase).is_err());
+    }
+
+    #[test]
+    fn test_append_to_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("prompts.md");
+        let path = path.to_str().unwrap();
+        append_to_file(path, "first", true).unwrap();
+        append_to_file(path, "second", true).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path).unwrap(),
+            format!("first{}second", APPEND_SEPARATOR)
+        );
+    }
+
+    #[test]
+    fn test_compression_from_path_and_name() {
+        assert_eq!(
+            Compression::from_path("prompt.md.gz"),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(
+            Compression::from_path("out/prompt.zst"),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(Compression::from_path("prompt.md"), None);
+        assert_eq!("gzip".parse::<Compression>().unwrap(), Compression::Gzip);
+        assert_eq!("ZSTD".parse::<Compression>().unwrap(), Compression::Zstd);
+        assert!("brotli".parse::<Compression>().is_err());
+        assert_eq!(
+            Compression::Zstd.ensure_supported().is_ok(),
+            cfg!(feature = "zstd")
+        );
+    }
+
+    #[test]
+    fn test_write_compressed_to_file_gzip() {
+        use std::io::Read;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("prompts.md.gz");
+        let path = path.to_str().unwrap();
+        let prompt = "# Prompt\n\n".repeat(1000);
+        write_compressed_to_file(path, &prompt, Compression::Gzip, false, true).unwrap();
+        let compressed = std::fs::read(path).unwrap();
+        assert!(compressed.len() < prompt.len());
+        let mut decompressed = String::new();
+        flate2::read::GzDecoder::new(compressed.as_slice())
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, prompt);
+
+        // An appended prompt is a gzip member of its own
+        write_compressed_to_file(path, "second", Compression::Gzip, true, true).unwrap();
+        let mut decompressed = String::new();
+        flate2::read::MultiGzDecoder::new(std::fs::File::open(path).unwrap())
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(
+            decompressed,
+            format!("{}{}second", prompt, APPEND_SEPARATOR)
+        );
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_write_compressed_to_file_zstd() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("prompts.md.zst");
+        let path = path.to_str().unwrap();
+        write_compressed_to_file(path, "first", Compression::Zstd, false, true).unwrap();
+        write_compressed_to_file(path, "second", Compression::Zstd, true, true).unwrap();
+        let decompressed = zstd::decode_all(std::fs::File::open(path).unwrap()).unwrap();
+        assert_eq!(
+            String::from_utf8(decompressed).unwrap(),
+            format!("first{}second", APPEND_SEPARATOR)
+        );
+    }
+}
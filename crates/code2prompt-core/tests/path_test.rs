@@ -1,5 +1,7 @@
 use code2prompt_core::configuration::Code2PromptConfig;
-use code2prompt_core::path::traverse_directory;
+use code2prompt_core::path::{display_path, traverse_directory, BinaryPolicy, PathStyle};
+use code2prompt_core::sort::FileSortMethod;
+use code2prompt_core::tokenizer::count_tokens;
 use colored::*;
 use log::{debug, info};
 use std::fs::{self, read_to_string, File};
@@ -25,11 +27,13 @@ fn init_logger() {
 fn create_temp_file(dir: &Path, name: &str, content: &str) {
     let file_path = dir.join(name);
     let parent_dir = file_path.parent().unwrap();
-    fs::create_dir_all(parent_dir).expect(&format!("Failed to create directory: {:?}", parent_dir));
-    let mut file =
-        File::create(&file_path).expect(&format!("Failed to create temp file: {:?}", file_path));
+    fs::create_dir_all(parent_dir)
+        .unwrap_or_else(|_| panic!("Failed to create directory: {:?}", parent_dir));
+    let mut file = File::create(&file_path)
+        .unwrap_or_else(|_| panic!("Failed to create temp file: {:?}", file_path));
     //debug!("Writing to file: {:?}", file_path);
-    writeln!(file, "{}", content).expect(&format!("Failed to write to temp file: {:?}", file_path));
+    writeln!(file, "{}", content)
+        .unwrap_or_else(|_| panic!("Failed to write to temp file: {:?}", file_path));
 }
 
 fn create_test_hierarchy(base_path: &Path) {
@@ -135,4 +139,980 @@ mod tests {
             path.map(|p| p.contains("ignored.txt")).unwrap_or(false)
         }));
     }
+
+    /// Creates a repository at `path` with a single committed `lib.rs`.
+    fn create_inner_repo(path: &Path) {
+        let repo = Repository::init(path).expect("Failed to initialize repository");
+        create_temp_file(path, "lib.rs", "Submodule file");
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("lib.rs")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("Test", "test@example.com").unwrap();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "Initial commit",
+            &tree,
+            &[],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_include_submodules() {
+        let env = TestEnv::new();
+        let inner_dir = tempdir().unwrap();
+        create_inner_repo(inner_dir.path());
+
+        let repo = Repository::open(env.dir.path()).unwrap();
+        let url = inner_dir.path().to_str().unwrap();
+        let mut submodule = repo.submodule(url, Path::new("sub"), true).unwrap();
+        submodule.clone(None).unwrap();
+        submodule.add_finalize().unwrap();
+
+        // A registered submodule that was never checked out
+        repo.submodule(url, Path::new("vendor/dep"), true).unwrap();
+        fs::remove_dir_all(env.dir.path().join("vendor")).unwrap();
+        fs::remove_dir_all(env.dir.path().join(".git/modules/vendor")).unwrap();
+
+        // By default submodules are opaque entries of the tree
+        let config = Code2PromptConfig::builder()
+            .path(env.dir.path().to_path_buf())
+            .build()
+            .expect("Failed to build config");
+        let (tree_str, files) = traverse_directory(&config).unwrap();
+        assert!(tree_str.contains("sub"));
+        assert!(!tree_str.contains("lib.rs"));
+        assert!(!tree_str.contains("uninitialized submodule"));
+        assert_eq!(files.len(), 1);
+
+        let config = Code2PromptConfig::builder()
+            .path(env.dir.path().to_path_buf())
+            .include_submodules(true)
+            .build()
+            .expect("Failed to build config");
+        let (tree_str, files) = traverse_directory(&config).unwrap();
+        debug!("Tree with submodules:\n{}", tree_str);
+        assert!(tree_str.contains("lib.rs"));
+        assert!(tree_str.contains("dep (uninitialized submodule)"));
+        assert!(files
+            .iter()
+            .any(|file| file.get("path").and_then(|p| p.as_str()) == Some("sub/lib.rs")));
+
+        // Globs apply with paths relative to the outer repository
+        let config = Code2PromptConfig::builder()
+            .path(env.dir.path().to_path_buf())
+            .include_submodules(true)
+            .exclude_patterns(vec!["sub/**".to_string()])
+            .build()
+            .expect("Failed to build config");
+        let (_, files) = traverse_directory(&config).unwrap();
+        assert!(!files
+            .iter()
+            .any(|file| file.get("path").and_then(|p| p.as_str()) == Some("sub/lib.rs")));
+    }
+
+    #[test]
+    fn test_traverse_bare_repository() {
+        let env = TestEnv::new();
+        create_temp_file(env.dir.path(), ".hidden/secret.txt", "Hidden file");
+        let repo = Repository::open(env.dir.path()).unwrap();
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("Test", "test@example.com").unwrap();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "Initial commit",
+            &tree,
+            &[],
+        )
+        .unwrap();
+
+        let bare_dir = tempdir().unwrap();
+        let bare_path = bare_dir.path().join("mirror.git");
+        git2::build::RepoBuilder::new()
+            .bare(true)
+            .clone(env.dir.path().to_str().unwrap(), &bare_path)
+            .unwrap();
+
+        let config = Code2PromptConfig::builder()
+            .path(bare_path.clone())
+            .build()
+            .expect("Failed to build config");
+        let (tree_str, files) = traverse_directory(&config).unwrap();
+        debug!("Bare repository tree:\n{}", tree_str);
+        assert!(tree_str.contains("mirror.git"));
+        assert!(tree_str.contains("included.txt"));
+        assert!(!tree_str.contains("secret.txt"));
+        // Git internals of the bare repository are not listed
+        assert!(!tree_str.contains("HEAD"));
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            files[0].get("path").and_then(|p| p.as_str()),
+            Some("test_dir/included.txt")
+        );
+        let code = files[0]
+            .get("code")
+            .and_then(|c| c.as_str())
+            .unwrap_or_default();
+        assert!(code.contains("Included file"));
+
+        let config = Code2PromptConfig::builder()
+            .path(bare_path)
+            .hidden(true)
+            .include_patterns(vec!["**/*.txt".to_string()])
+            .build()
+            .expect("Failed to build config");
+        let (tree_str, files) = traverse_directory(&config).unwrap();
+        assert!(tree_str.contains("secret.txt"));
+        assert_eq!(files.len(), 2);
+    }
+
+    #[test]
+    fn test_traverse_modified_since() {
+        let env = TestEnv::new();
+        let repo = Repository::open(env.dir.path()).unwrap();
+        let signature = git2::Signature::now("Test", "test@example.com").unwrap();
+        let commit_all = |message: &str| {
+            let mut index = repo.index().unwrap();
+            index
+                .add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)
+                .unwrap();
+            index.write().unwrap();
+            let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+            let parents: Vec<git2::Commit> = repo
+                .head()
+                .ok()
+                .and_then(|head| head.peel_to_commit().ok())
+                .into_iter()
+                .collect();
+            let parent_refs: Vec<&git2::Commit> = parents.iter().collect();
+            repo.commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                message,
+                &tree,
+                &parent_refs,
+            )
+            .unwrap();
+        };
+        commit_all("Initial commit");
+        create_temp_file(env.dir.path(), "src/changed.rs", "fn changed() {}");
+        create_temp_file(env.dir.path(), "src/changed.txt", "changed notes");
+
+        let config = Code2PromptConfig::builder()
+            .path(env.dir.path().to_path_buf())
+            .modified_since(Some("HEAD".to_string()))
+            .exclude_patterns(vec!["*.txt".to_string()])
+            .build()
+            .expect("Failed to build config");
+        let (tree_str, files) = traverse_directory(&config).unwrap();
+        debug!("Tree of changed files:\n{}", tree_str);
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            files[0].get("path").and_then(|p| p.as_str()),
+            Some("src/changed.rs")
+        );
+        assert!(tree_str.contains("changed.rs"));
+        assert!(!tree_str.contains("included.txt"));
+        assert!(!tree_str.contains("changed.txt"));
+    }
+
+    #[test]
+    fn test_traverse_tracked_only() {
+        let env = TestEnv::new();
+        create_temp_file(env.dir.path(), "test_dir/deleted.txt", "Deleted file");
+        create_temp_file(env.dir.path(), "test_dir/notes.md", "Tracked notes");
+        let repo = Repository::open(env.dir.path()).unwrap();
+        let mut index = repo.index().unwrap();
+        for tracked in [
+            "test_dir/included.txt",
+            "test_dir/deleted.txt",
+            "test_dir/notes.md",
+        ] {
+            index.add_path(Path::new(tracked)).unwrap();
+        }
+        index.write().unwrap();
+        // Still in the index but gone from the disk
+        fs::remove_file(env.dir.path().join("test_dir/deleted.txt")).unwrap();
+        create_temp_file(env.dir.path(), "test_dir/scratch.txt", "Untracked file");
+
+        let config = Code2PromptConfig::builder()
+            .path(env.dir.path().to_path_buf())
+            .tracked_only(true)
+            .exclude_patterns(vec!["*.md".to_string()])
+            .build()
+            .expect("Failed to build config");
+        let (tree_str, files) = traverse_directory(&config).unwrap();
+        debug!("Tree of tracked files:\n{}", tree_str);
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            files[0].get("path").and_then(|p| p.as_str()),
+            Some("test_dir/included.txt")
+        );
+        assert!(!tree_str.contains("scratch.txt"));
+        assert!(!tree_str.contains("deleted.txt"));
+        assert!(!tree_str.contains("notes.md"));
+
+        // Outside of a repository every file is listed
+        let plain_dir = tempdir().unwrap();
+        create_temp_file(plain_dir.path(), "loose.txt", "Loose file");
+        let config = Code2PromptConfig::builder()
+            .path(plain_dir.path().to_path_buf())
+            .tracked_only(true)
+            .build()
+            .expect("Failed to build config");
+        let (_, files) = traverse_directory(&config).unwrap();
+        assert_eq!(files.len(), 1);
+    }
+
+    #[test]
+    fn test_respect_gitattributes() {
+        let env = TestEnv::new();
+        create_temp_file(
+            env.dir.path(),
+            ".gitattributes",
+            "dist/** linguist-generated=true\nvendor/** export-ignore\nsrc/generated.rs linguist-generated\nsrc/kept.rs linguist-generated=false\n",
+        );
+        create_temp_file(env.dir.path(), "dist/bundle.js", "var bundle;");
+        create_temp_file(env.dir.path(), "vendor/lib.c", "int lib;");
+        create_temp_file(env.dir.path(), "src/generated.rs", "// @generated");
+        create_temp_file(env.dir.path(), "src/kept.rs", "fn kept() {}");
+
+        let config = Code2PromptConfig::builder()
+            .path(env.dir.path().to_path_buf())
+            .respect_gitattributes(true)
+            .build()
+            .expect("Failed to build config");
+        let (tree_str, files) = traverse_directory(&config).unwrap();
+        debug!("Tree with git attributes:\n{}", tree_str);
+        assert!(tree_str.contains("bundle.js (generated, omitted)"));
+        assert!(tree_str.contains("lib.c (generated, omitted)"));
+        assert!(tree_str.contains("generated.rs (generated, omitted)"));
+        assert!(tree_str.contains("kept.rs") && !tree_str.contains("kept.rs ("));
+        let paths: Vec<&str> = files
+            .iter()
+            .filter_map(|f| f.get("path").and_then(|p| p.as_str()))
+            .collect();
+        assert!(paths.contains(&"src/kept.rs"));
+        assert!(!paths.contains(&"dist/bundle.js"));
+        assert!(!paths.contains(&"vendor/lib.c"));
+        assert!(!paths.contains(&"src/generated.rs"));
+
+        // Without the option the attributes are not consulted
+        let config = Code2PromptConfig::builder()
+            .path(env.dir.path().to_path_buf())
+            .build()
+            .expect("Failed to build config");
+        let (tree_str, files) = traverse_directory(&config).unwrap();
+        assert!(!tree_str.contains("omitted"));
+        let paths: Vec<&str> = files
+            .iter()
+            .filter_map(|f| f.get("path").and_then(|p| p.as_str()))
+            .collect();
+        assert!(paths.contains(&"dist/bundle.js"));
+    }
+
+    #[test]
+    fn test_file_git_info() {
+        let env = TestEnv::new();
+        let repo = Repository::open(env.dir.path()).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("test_dir/included.txt")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("Jane Doe", "jane@example.com").unwrap();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "Add included file",
+            &tree,
+            &[],
+        )
+        .unwrap();
+        create_temp_file(env.dir.path(), "test_dir/scratch.txt", "Never committed");
+
+        let config = Code2PromptConfig::builder()
+            .path(env.dir.path().to_path_buf())
+            .file_git_info(true)
+            .build()
+            .expect("Failed to build config");
+        let (_, files) = traverse_directory(&config).unwrap();
+        let last_commit = |path: &str| {
+            files
+                .iter()
+                .find(|f| f.get("path").and_then(|p| p.as_str()) == Some(path))
+                .and_then(|f| f.get("last_commit"))
+                .cloned()
+                .unwrap_or_else(|| panic!("No last_commit for {}", path))
+        };
+        let included = last_commit("test_dir/included.txt");
+        assert_eq!(included["author"], "Jane Doe");
+        assert_eq!(included["summary"], "Add included file");
+        assert_eq!(included["short_sha"].as_str().map(str::len), Some(7));
+        assert!(last_commit("test_dir/scratch.txt").is_null());
+
+        // The history is only walked when asked for
+        let config = Code2PromptConfig::builder()
+            .path(env.dir.path().to_path_buf())
+            .build()
+            .expect("Failed to build config");
+        let (_, files) = traverse_directory(&config).unwrap();
+        assert!(files.iter().all(|f| f.get("last_commit").is_none()));
+    }
+
+    #[test]
+    fn test_file_token_counts() {
+        let env = TestEnv::new();
+        create_temp_file(
+            env.dir.path(),
+            "test_dir/longer.txt",
+            &"many words in this file ".repeat(50),
+        );
+        fs::write(
+            env.dir.path().join("test_dir/binary.bin"),
+            [0u8, 159, 146, 150, 255],
+        )
+        .unwrap();
+
+        let config = Code2PromptConfig::builder()
+            .path(env.dir.path().to_path_buf())
+            .build()
+            .expect("Failed to build config");
+        let (_, files) = traverse_directory(&config).unwrap();
+        let token_count = |path: &str| {
+            files
+                .iter()
+                .find(|f| f.get("path").and_then(|p| p.as_str()) == Some(path))
+                .and_then(|f| f.get("token_count"))
+                .and_then(|t| t.as_u64())
+                .unwrap_or_else(|| panic!("No token_count for {}", path))
+        };
+
+        // Every file is counted, without enabling the token map
+        let included = token_count("test_dir/included.txt");
+        let longer = token_count("test_dir/longer.txt");
+        assert!(included > 0);
+        assert!(longer > 200, "{} tokens", longer);
+        assert!(files.iter().all(|f| f["token_count"].is_u64()));
+        // Binary files are not included, so they are never counted as 0 tokens
+        assert!(!files.iter().any(|f| f["path"] == "test_dir/binary.bin"));
+    }
+
+    #[test]
+    fn test_stub_files_over_per_file_limits() {
+        let env = TestEnv::new();
+        let long: Vec<String> = (1..=100)
+            .map(|i| format!("line {} of the generated file", i))
+            .collect();
+        create_temp_file(env.dir.path(), "test_dir/generated.txt", &long.join("\n"));
+
+        let config = Code2PromptConfig::builder()
+            .path(env.dir.path().to_path_buf())
+            .max_tokens_per_file(Some(100))
+            .build()
+            .expect("Failed to build config");
+        let (tree, files) = traverse_directory(&config).unwrap();
+        let file = files
+            .iter()
+            .find(|f| f["path"] == "test_dir/generated.txt")
+            .unwrap();
+        let code = file["code"].as_str().unwrap();
+        debug!("Stub:\n{}", code);
+
+        // The stub keeps the first and last lines around a note with the path and the count
+        assert!(code.starts_with("```txt\nline 1 of the generated file\n"));
+        assert!(code.contains("line 5 of"));
+        assert!(!code.contains("line 6 of"));
+        assert!(code.contains("line 100 of"));
+        assert!(code.ends_with("\n```"));
+        let original = file["stub"]["token_count"].as_u64().unwrap();
+        assert!(original > 100);
+        assert!(code.contains(&format!(
+            "... (90 lines omitted: test_dir/generated.txt has {} tokens, over the limit of 100 tokens per file)",
+            original
+        )));
+        assert_eq!(file["stub"]["lines"], 100);
+        // The token count is the one of the stub
+        let token_count = file["token_count"].as_u64().unwrap();
+        assert!(token_count < original);
+        assert_eq!(token_count as usize, count_tokens(code, &config.encoding));
+        // The file is still listed in the source tree, and small files are untouched
+        assert!(tree.contains("generated.txt"));
+        let included = files
+            .iter()
+            .find(|f| f["path"] == "test_dir/included.txt")
+            .unwrap();
+        assert!(included.get("stub").is_none());
+
+        let config = Code2PromptConfig::builder()
+            .path(env.dir.path().to_path_buf())
+            .max_lines_per_file(Some(50))
+            .build()
+            .expect("Failed to build config");
+        let (_, files) = traverse_directory(&config).unwrap();
+        let file = files
+            .iter()
+            .find(|f| f["path"] == "test_dir/generated.txt")
+            .unwrap();
+        assert!(file["code"]
+            .as_str()
+            .unwrap()
+            .contains("... (90 lines omitted: test_dir/generated.txt has 100 lines, over the limit of 50 lines per file)"));
+        assert_eq!(file["stub"]["token_count"], serde_json::Value::Null);
+        assert!(file["token_count"].as_u64().unwrap() < original);
+    }
+
+    #[test]
+    fn test_sort_by_tokens_and_size() {
+        let env = TestEnv::new();
+        create_temp_file(
+            env.dir.path(),
+            "test_dir/longer.txt",
+            &"many words in this file ".repeat(50),
+        );
+
+        let config = Code2PromptConfig::builder()
+            .path(env.dir.path().to_path_buf())
+            .sort_method(Some(FileSortMethod::TokensDesc))
+            .build()
+            .expect("Failed to build config");
+        let (_, files) = traverse_directory(&config).unwrap();
+        assert_eq!(files[0]["path"], "test_dir/longer.txt");
+        let counts: Vec<u64> = files
+            .iter()
+            .map(|f| f["token_count"].as_u64().unwrap())
+            .collect();
+        assert!(
+            counts.windows(2).all(|pair| pair[0] >= pair[1]),
+            "{:?}",
+            counts
+        );
+
+        let config = Code2PromptConfig::builder()
+            .path(env.dir.path().to_path_buf())
+            .sort_method(Some(FileSortMethod::SizeAsc))
+            .build()
+            .expect("Failed to build config");
+        let (_, files) = traverse_directory(&config).unwrap();
+        assert_eq!(files.last().unwrap()["path"], "test_dir/longer.txt");
+        let sizes: Vec<u64> = files.iter().map(|f| f["size"].as_u64().unwrap()).collect();
+        assert!(
+            sizes.windows(2).all(|pair| pair[0] <= pair[1]),
+            "{:?}",
+            sizes
+        );
+    }
+
+    #[test]
+    fn test_parallel_traversal_is_deterministic() {
+        init_logger();
+        let dir = tempdir().unwrap();
+        let body: String = (0..40)
+            .map(|i| format!("fn function_{}() {{ println!(\"{}\"); }}\n", i, i))
+            .collect();
+        for index in 0..500 {
+            create_temp_file(
+                dir.path(),
+                &format!("module_{}/file_{}.rs", index % 25, index),
+                &body,
+            );
+        }
+
+        let traverse = |jobs: Option<usize>| {
+            let config = Code2PromptConfig::builder()
+                .path(dir.path().to_path_buf())
+                .jobs(jobs)
+                .build()
+                .expect("Failed to build config");
+            let start = std::time::Instant::now();
+            let result = traverse_directory(&config).unwrap();
+            (result, start.elapsed())
+        };
+
+        let ((serial_tree, serial_files), serial_time) = traverse(Some(1));
+        let ((parallel_tree, parallel_files), parallel_time) = traverse(None);
+        info!(
+            "Traversed 500 files in {:?} on one thread, {:?} on all of them",
+            serial_time, parallel_time
+        );
+
+        assert_eq!(serial_files.len(), 500);
+        assert_eq!(parallel_tree, serial_tree);
+        assert_eq!(parallel_files, serial_files);
+        // Guards against the files being read or counted one at a time again, with a margin
+        // for machines with a single CPU
+        assert!(
+            parallel_time <= serial_time * 2 + std::time::Duration::from_millis(500),
+            "{:?} in parallel, {:?} on one thread",
+            parallel_time,
+            serial_time
+        );
+    }
+
+    #[test]
+    fn test_file_metadata_fields() {
+        let dir = tempdir().unwrap();
+        create_temp_file(
+            dir.path(),
+            "main.rs",
+            "fn main() {}\nfn other() {}\nfn third() {}\n",
+        );
+        create_temp_file(dir.path(), "Dockerfile", "FROM rust\n");
+        create_temp_file(dir.path(), "notes.xyz", "notes\n");
+        create_temp_file(dir.path(), "deploy", "#!/usr/bin/env python3\nprint(1)\n");
+
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .max_lines_per_file(Some(2))
+            .build()
+            .expect("Failed to build config");
+        let (_, files) = traverse_directory(&config).unwrap();
+        let file = |path: &str| files.iter().find(|f| f["path"] == path).unwrap();
+
+        let main = file("main.rs");
+        assert_eq!(main["extension"], "rs");
+        assert_eq!(main["language"], "rust");
+        assert_eq!(main["size_bytes"], 42);
+        assert!(main["code"].as_str().unwrap().starts_with("```rust\n"));
+        let mtime = main["mtime"].as_str().unwrap();
+        assert!(
+            chrono::DateTime::parse_from_rfc3339(mtime).is_ok(),
+            "{}",
+            mtime
+        );
+        assert_eq!(main["truncated"], true);
+        assert!(main["token_count"].is_u64());
+
+        assert_eq!(file("Dockerfile")["language"], "dockerfile");
+        assert_eq!(file("Dockerfile")["extension"], "");
+        assert_eq!(file("Dockerfile")["truncated"], false);
+        assert_eq!(file("notes.xyz")["language"], "");
+        assert!(file("notes.xyz")["code"]
+            .as_str()
+            .unwrap()
+            .starts_with("```\n"));
+        assert_eq!(file("deploy")["language"], "python");
+        assert!(file("deploy")["code"]
+            .as_str()
+            .unwrap()
+            .starts_with("```python\n"));
+    }
+
+    #[test]
+    fn test_line_numbers_keep_the_positions_of_a_stub() {
+        let env = TestEnv::new();
+        let long: Vec<String> = (1..=100).map(|i| format!("line {}", i)).collect();
+        create_temp_file(env.dir.path(), "test_dir/generated.txt", &long.join("\n"));
+
+        let config = Code2PromptConfig::builder()
+            .path(env.dir.path().to_path_buf())
+            .line_numbers(true)
+            .max_lines_per_file(Some(50))
+            .build()
+            .expect("Failed to build config");
+        let (_, files) = traverse_directory(&config).unwrap();
+        let file = files
+            .iter()
+            .find(|f| f["path"] == "test_dir/generated.txt")
+            .unwrap();
+        let code = file["code"].as_str().unwrap();
+        debug!("Stub:\n{}", code);
+
+        // The width is the one of the last line number, and the tail keeps its positions
+        assert!(code.starts_with("```txt\n  1 | line 1\n"), "{}", code);
+        assert!(
+            code.contains("  5 | line 5\n... (90 lines omitted: "),
+            "{}",
+            code
+        );
+        assert!(code.contains(" 96 | line 96\n"), "{}", code);
+        assert!(code.ends_with("100 | line 100\n```"), "{}", code);
+        assert_eq!(file["line_numbers"], true);
+
+        let included = files
+            .iter()
+            .find(|f| f["path"] == "test_dir/included.txt")
+            .unwrap();
+        assert!(included["code"]
+            .as_str()
+            .unwrap()
+            .starts_with("```txt\n1 | "));
+
+        let config = Code2PromptConfig::builder()
+            .path(env.dir.path().to_path_buf())
+            .build()
+            .expect("Failed to build config");
+        let (_, files) = traverse_directory(&config).unwrap();
+        assert!(files.iter().all(|f| f["line_numbers"] == false));
+    }
+
+    #[test]
+    fn test_display_path_styles() {
+        let root = Path::new("/repo");
+        let path = Path::new("packages/api/src/main.rs");
+        assert_eq!(
+            display_path(&PathStyle::Relative, root, path),
+            "packages/api/src/main.rs"
+        );
+        assert_eq!(
+            display_path(&PathStyle::Absolute, root, path),
+            "/repo/packages/api/src/main.rs"
+        );
+        for prefix in [
+            "packages/api",
+            "packages/api/",
+            "./packages/api",
+            "packages\\api",
+        ] {
+            let style = PathStyle::Strip(prefix.to_string());
+            assert_eq!(
+                display_path(&style, root, path),
+                "src/main.rs",
+                "{}",
+                prefix
+            );
+        }
+        // The paths outside the prefix, or only matching a part of a name, stay relative
+        let style: PathStyle = "strip:packages/api".parse().unwrap();
+        assert_eq!(
+            display_path(&style, root, Path::new("packages/apis/a.rs")),
+            "packages/apis/a.rs"
+        );
+        assert_eq!(
+            display_path(&style, root, Path::new("README.md")),
+            "README.md"
+        );
+
+        // The Windows separators are written as slashes on every platform
+        let windows = Path::new("packages\\api\\src\\main.rs");
+        assert_eq!(
+            display_path(&PathStyle::Relative, root, windows),
+            "packages/api/src/main.rs"
+        );
+        assert_eq!(display_path(&style, root, windows), "src/main.rs");
+        assert_eq!(
+            display_path(&PathStyle::Absolute, Path::new("C:\\repo"), windows),
+            "C:/repo/packages/api/src/main.rs"
+        );
+
+        assert_eq!(
+            "absolute".parse::<PathStyle>().unwrap(),
+            PathStyle::Absolute
+        );
+        assert!("strip:".parse::<PathStyle>().is_err());
+        assert!("strip".parse::<PathStyle>().is_err());
+        assert!("full"
+            .parse::<PathStyle>()
+            .unwrap_err()
+            .to_string()
+            .contains("strip:<prefix>"));
+    }
+
+    #[test]
+    fn test_traverse_with_path_style() {
+        let env = TestEnv::new();
+        let config = Code2PromptConfig::builder()
+            .path(env.dir.path().to_path_buf())
+            .path_style(PathStyle::Strip("test_dir".to_string()))
+            .build()
+            .expect("Failed to build config");
+        let (_, files) = traverse_directory(&config).unwrap();
+        assert!(files.iter().any(|f| f["path"] == "included.txt"));
+        assert!(!files
+            .iter()
+            .any(|f| f["path"].as_str().unwrap().starts_with("test_dir/")));
+
+        let config = Code2PromptConfig::builder()
+            .path(env.dir.path().to_path_buf())
+            .path_style(PathStyle::Absolute)
+            .build()
+            .expect("Failed to build config");
+        let (_, files) = traverse_directory(&config).unwrap();
+        let root = env.dir.path().canonicalize().unwrap();
+        let expected = display_path(
+            &PathStyle::Absolute,
+            &root,
+            Path::new("test_dir/included.txt"),
+        );
+        assert!(
+            files.iter().any(|f| f["path"] == expected.as_str()),
+            "{}",
+            expected
+        );
+    }
+
+    #[test]
+    fn test_traversal_order_is_the_same_on_every_platform() {
+        let dir = tempdir().unwrap();
+        // Created out of order, with names whose order differs between a byte-wise comparison,
+        // a case-insensitive one and the order of the filesystem
+        for name in [
+            "src.rs",
+            "src-tauri/main.rs",
+            "a-b/c.txt",
+            "alpha.md",
+            "src/main.rs",
+            "9.txt",
+            "_notes.md",
+            "a/c.txt",
+            "Zeta.md",
+            "src/lib.rs",
+            "10.txt",
+        ] {
+            create_temp_file(dir.path(), name, "the same content");
+        }
+        let expected = vec![
+            "10.txt",
+            "9.txt",
+            "Zeta.md",
+            "_notes.md",
+            "a/c.txt",
+            "a-b/c.txt",
+            "alpha.md",
+            "src/lib.rs",
+            "src/main.rs",
+            "src-tauri/main.rs",
+            "src.rs",
+        ];
+
+        let traverse = |sort_method: Option<FileSortMethod>, jobs: Option<usize>| {
+            let config = Code2PromptConfig::builder()
+                .path(dir.path().to_path_buf())
+                .sort_method(sort_method)
+                .jobs(jobs)
+                .build()
+                .expect("Failed to build config");
+            let (tree, files) = traverse_directory(&config).unwrap();
+            let paths: Vec<String> = files
+                .iter()
+                .map(|f| f["path"].as_str().unwrap().to_string())
+                .collect();
+            (tree, paths)
+        };
+
+        let (tree, paths) = traverse(None, Some(1));
+        assert_eq!(paths, expected);
+        let tree_lines: Vec<&str> = tree.lines().skip(1).collect();
+        assert_eq!(
+            tree_lines,
+            vec![
+                "├── 10.txt",
+                "├── 9.txt",
+                "├── Zeta.md",
+                "├── _notes.md",
+                "├── a",
+                "│   └── c.txt",
+                "├── a-b",
+                "│   └── c.txt",
+                "├── alpha.md",
+                "├── src",
+                "│   ├── lib.rs",
+                "│   └── main.rs",
+                "├── src-tauri",
+                "│   └── main.rs",
+                "└── src.rs",
+            ]
+        );
+        assert_eq!(traverse(None, None), (tree, paths));
+
+        // Every sort mode breaks its ties by path, in the same order
+        for method in [
+            FileSortMethod::NameAsc,
+            FileSortMethod::TokensAsc,
+            FileSortMethod::SizeDesc,
+        ] {
+            assert_eq!(traverse(Some(method), None).1, expected, "{:?}", method);
+        }
+        let mut reversed = expected.clone();
+        reversed.reverse();
+        assert_eq!(traverse(Some(FileSortMethod::NameDesc), None).1, reversed);
+    }
+
+    #[test]
+    fn test_parse_binary_policy() {
+        assert_eq!("skip".parse::<BinaryPolicy>().unwrap(), BinaryPolicy::Skip);
+        assert_eq!(
+            "placeholder".parse::<BinaryPolicy>().unwrap(),
+            BinaryPolicy::Placeholder
+        );
+        assert_eq!(
+            "base64:4096".parse::<BinaryPolicy>().unwrap(),
+            BinaryPolicy::Base64 { max_bytes: 4096 }
+        );
+        assert!("base64:4k"
+            .parse::<BinaryPolicy>()
+            .unwrap_err()
+            .to_string()
+            .contains("e.g. base64:4096"));
+        assert!("base64"
+            .parse::<BinaryPolicy>()
+            .unwrap_err()
+            .to_string()
+            .contains("base64:<max-bytes>"));
+        assert!("inline".parse::<BinaryPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_binary_policy() {
+        let dir = tempdir().unwrap();
+        let mut icon = b"\x89PNG\r\n\x1a\n".to_vec();
+        icon.extend([0u8; 8]);
+        fs::write(dir.path().join("icon.png"), &icon).unwrap();
+        let mut photo = b"\x89PNG\r\n\x1a\n".to_vec();
+        photo.extend([0u8; 5000]);
+        fs::write(dir.path().join("photo.png"), &photo).unwrap();
+        fs::write(dir.path().join("blob.bin"), [0u8, 1, 2, 3]).unwrap();
+        fs::write(dir.path().join("readme.txt"), "text\n").unwrap();
+
+        let traverse = |policy: BinaryPolicy| {
+            let config = Code2PromptConfig::builder()
+                .path(dir.path().to_path_buf())
+                .binary_policy(policy)
+                .line_numbers(true)
+                .build()
+                .expect("Failed to build config");
+            traverse_directory(&config).unwrap()
+        };
+        let file = |files: &[serde_json::Value], path: &str| {
+            files
+                .iter()
+                .find(|f| f["path"] == path)
+                .cloned()
+                .unwrap_or_default()
+        };
+
+        // The tree lists the binary files whatever the policy
+        let (tree, files) = traverse(BinaryPolicy::Skip);
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0]["encoding"], "text");
+        for name in ["icon.png", "photo.png", "blob.bin"] {
+            assert!(tree.contains(name), "{}", tree);
+        }
+
+        let (placeholder_tree, files) = traverse(BinaryPolicy::Placeholder);
+        assert_eq!(placeholder_tree, tree);
+        assert_eq!(files.len(), 4);
+        let icon_entry = file(&files, "icon.png");
+        assert_eq!(
+            icon_entry["code"],
+            "```\n[binary file: icon.png, 16 B, image/png]\n```"
+        );
+        assert_eq!(icon_entry["encoding"], "placeholder");
+        assert_eq!(icon_entry["mime_type"], "image/png");
+        assert_eq!(icon_entry["line_numbers"], false);
+        assert_eq!(icon_entry["size"], 16);
+        assert_eq!(
+            file(&files, "blob.bin")["mime_type"],
+            "application/octet-stream"
+        );
+
+        // Only the images and fonts under the size cap are inlined
+        let (_, files) = traverse(BinaryPolicy::Base64 { max_bytes: 4096 });
+        let icon_entry = file(&files, "icon.png");
+        assert_eq!(
+            icon_entry["code"],
+            "```\ndata:image/png;base64,iVBORw0KGgoAAAAAAAAAAA==\n```"
+        );
+        assert_eq!(icon_entry["encoding"], "base64");
+        assert!(icon_entry["token_count"].as_u64().unwrap() > 0);
+        assert_eq!(file(&files, "photo.png")["encoding"], "placeholder");
+        assert_eq!(file(&files, "blob.bin")["encoding"], "placeholder");
+        assert_eq!(file(&files, "readme.txt")["encoding"], "text");
+    }
+
+    #[test]
+    fn test_nested_gitignore_files() {
+        for in_repository in [false, true] {
+            let dir = tempdir().unwrap();
+            let root = dir.path();
+            if in_repository {
+                Repository::init(root).unwrap();
+                create_temp_file(root, ".git/info/exclude", "secrets.txt");
+            }
+            create_temp_file(
+                root,
+                ".gitignore",
+                "*.log\n*.js\n!keep.js\nbuild/\ntmp\n!tmp\n",
+            );
+            // A deeper file overrides the shallower ones, and its later rules its earlier ones
+            create_temp_file(
+                root,
+                "frontend/.gitignore",
+                "dist/\n!important.log\n*.map\n!app.js.map\n",
+            );
+            for name in [
+                "readme.md",
+                "debug.log",
+                "keep.js",
+                "bundle.js",
+                "secrets.txt",
+                "build/out.txt",
+                "docs/build/page.txt",
+                "docs/build.txt",
+                "tmp/notes.txt",
+                "frontend/dist/app.js",
+                "frontend/src/dist.txt",
+                "frontend/important.log",
+                "frontend/other.log",
+                "frontend/app.js.map",
+                "frontend/lib.js.map",
+                "frontend/lib/keep.js",
+            ] {
+                create_temp_file(root, name, "content");
+            }
+
+            let traverse = |no_ignore: bool| {
+                let config = Code2PromptConfig::builder()
+                    .path(root.to_path_buf())
+                    .no_ignore(no_ignore)
+                    .build()
+                    .expect("Failed to build config");
+                let (tree, files) = traverse_directory(&config).unwrap();
+                let mut paths: Vec<String> = files
+                    .iter()
+                    .map(|f| f["path"].as_str().unwrap().to_string())
+                    .collect();
+                paths.sort();
+                (tree, paths)
+            };
+
+            let (tree, paths) = traverse(false);
+            let mut expected = vec![
+                "docs/build.txt",
+                "frontend/app.js.map",
+                "frontend/important.log",
+                "frontend/lib/keep.js",
+                "frontend/src/dist.txt",
+                "keep.js",
+                "readme.md",
+                "tmp/notes.txt",
+            ];
+            // Outside of a repository, git has no exclude file
+            if !in_repository {
+                expected.push("secrets.txt");
+                expected.sort();
+            }
+            assert_eq!(paths, expected, "in a repository: {}", in_repository);
+            assert!(
+                !tree.contains("dist\n") && !tree.contains("out.txt"),
+                "{}",
+                tree
+            );
+
+            // Without the ignore rules, every file is there
+            let (_, paths) = traverse(true);
+            assert_eq!(paths.len(), 16, "{:?}", paths);
+        }
+    }
 }
@@ -0,0 +1,87 @@
+use code2prompt_core::pricing::PricingTable;
+use code2prompt_core::tokenizer::{count_tokens, TokenCountMode, TokenizerType};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_default_pricing_table() {
+        let pricing = PricingTable::load(None).unwrap();
+        assert!(pricing.models.len() > 10);
+
+        let (price, tokenizer_type) = pricing.price("gpt-4o").unwrap();
+        assert_eq!(price.input_per_million, 2.50);
+        assert_eq!(tokenizer_type, TokenizerType::O200kBase);
+
+        // The models without a public tokenizer use the encoding of the table
+        let (_, tokenizer_type) = pricing.price("Claude-3-5-Sonnet").unwrap();
+        assert_eq!(tokenizer_type, TokenizerType::Cl100kBase);
+        let (_, tokenizer_type) = pricing.price("gpt-3.5-turbo").unwrap();
+        assert_eq!(tokenizer_type, TokenizerType::Cl100kBase);
+    }
+
+    #[test]
+    fn test_estimate_cost() {
+        let pricing = PricingTable::load(None).unwrap();
+        let prompt = "fn main() {\n    println!(\"Hello, world!\");\n}\n".repeat(100);
+
+        let estimate = pricing
+            .estimate(&prompt, "gpt-4o", TokenCountMode::Exact)
+            .unwrap();
+        let token_count = count_tokens(&prompt, &TokenizerType::O200kBase);
+        assert_eq!(estimate.model, "gpt-4o");
+        assert_eq!(estimate.encoding, "o200k_base");
+        assert_eq!(estimate.token_count, token_count);
+        assert!((estimate.cost_usd - token_count as f64 * 2.50 / 1_000_000.0).abs() < 1e-12);
+
+        let estimate = pricing
+            .estimate(&prompt, "claude-3-5-sonnet", TokenCountMode::Exact)
+            .unwrap();
+        assert_eq!(estimate.encoding, "cl100k_base");
+        assert_eq!(
+            estimate.token_count,
+            count_tokens(&prompt, &TokenizerType::Cl100kBase)
+        );
+    }
+
+    #[test]
+    fn test_pricing_file_overrides() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("pricing.toml");
+        fs::write(
+            &path,
+            "[\"gpt-4o\"]\ninput_per_million = 1.0\n\n[my-model]\ninput_per_million = 4.0\nencoding = \"p50k_base\"\n",
+        )
+        .unwrap();
+
+        let pricing = PricingTable::load(Some(&path)).unwrap();
+        assert_eq!(pricing.price("gpt-4o").unwrap().0.input_per_million, 1.0);
+        let (price, tokenizer_type) = pricing.price("my-model").unwrap();
+        assert_eq!(price.input_per_million, 4.0);
+        assert_eq!(tokenizer_type, TokenizerType::P50kBase);
+        // The other models are kept
+        assert!(pricing.price("o3").is_ok());
+
+        fs::write(&path, "[my-model]\ninput_cost = 4.0\n").unwrap();
+        assert!(PricingTable::load(Some(&path)).is_err());
+        fs::write(
+            &path,
+            "[my-model]\ninput_per_million = 4.0\nencoding = \"unknown\"\n",
+        )
+        .unwrap();
+        let pricing = PricingTable::load(Some(&path)).unwrap();
+        assert!(pricing.price("my-model").is_err());
+    }
+
+    #[test]
+    fn test_unknown_model() {
+        let pricing = PricingTable::load(None).unwrap();
+        let error = pricing.price("gpt-9").unwrap_err().to_string();
+        assert!(error.contains("No price for the model gpt-9"));
+        assert!(error.contains("gpt-4o"));
+        assert!(error.contains("claude-3-5-sonnet"));
+    }
+}
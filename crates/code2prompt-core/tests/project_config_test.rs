@@ -0,0 +1,115 @@
+use code2prompt_core::project_config::{
+    find_project_config, load_project_config, resolve_template, ProjectConfig, TemplateOrigin,
+    CONFIG_FILE,
+};
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn config(template: &str) -> ProjectConfig {
+        ProjectConfig {
+            template: Some(template.to_string()),
+            ..ProjectConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_project_config() {
+        assert_eq!(
+            ProjectConfig::parse("template = \"review\"\n").unwrap(),
+            config("review")
+        );
+        assert_eq!(ProjectConfig::parse("").unwrap(), ProjectConfig::default());
+        assert!(ProjectConfig::parse("templat = \"review\"").is_err());
+
+        let parsed = ProjectConfig::parse("[languages]\npyx = \"cython\"\n").unwrap();
+        assert_eq!(parsed.languages["pyx"], "cython");
+        assert_eq!(parsed.template, None);
+    }
+
+    #[test]
+    fn test_find_project_config_up_to_repository_root() {
+        let dir = tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        let sub = repo.join("sub");
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        fs::create_dir_all(&sub).unwrap();
+        fs::write(dir.path().join(CONFIG_FILE), "template = \"outside\"").unwrap();
+        assert_eq!(find_project_config(&sub), None);
+
+        fs::write(repo.join(CONFIG_FILE), "template = \"review\"").unwrap();
+        let (file, config) = load_project_config(&sub).unwrap().unwrap();
+        assert_eq!(file, repo.canonicalize().unwrap().join(CONFIG_FILE));
+        assert_eq!(config.template.as_deref(), Some("review"));
+
+        fs::write(repo.join(CONFIG_FILE), "template = 1").unwrap();
+        let error = load_project_config(&sub).unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("Failed to parse the configuration file"),
+            "{:#}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_resolve_template_config_only() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join(CONFIG_FILE);
+        let choice = resolve_template(None, None, Some((&file, &config("review"))));
+        assert_eq!(choice.template, Some(PathBuf::from("review")));
+        assert_eq!(choice.origin, TemplateOrigin::ConfigFile(file.clone()));
+
+        // A path is relative to the configuration file
+        fs::write(dir.path().join("review.hbs"), "Review").unwrap();
+        let choice = resolve_template(None, None, Some((&file, &config("review.hbs"))));
+        assert_eq!(choice.template, Some(dir.path().join("review.hbs")));
+        let choice = resolve_template(None, None, Some((&file, &config("prompts/review.hbs"))));
+        assert_eq!(choice.template, Some(dir.path().join("prompts/review.hbs")));
+    }
+
+    #[test]
+    fn test_resolve_template_env_only() {
+        let choice = resolve_template(None, Some("review"), None);
+        assert_eq!(choice.template, Some(PathBuf::from("review")));
+        assert_eq!(choice.origin, TemplateOrigin::Environment);
+
+        let choice = resolve_template(None, Some(""), None);
+        assert_eq!(choice.template, None);
+        assert_eq!(choice.origin, TemplateOrigin::Default);
+    }
+
+    #[test]
+    fn test_resolve_template_env_over_config() {
+        let file = Path::new("/repo").join(CONFIG_FILE);
+        let choice = resolve_template(None, Some("security"), Some((&file, &config("review"))));
+        assert_eq!(choice.template, Some(PathBuf::from("security")));
+        assert_eq!(choice.origin, TemplateOrigin::Environment);
+    }
+
+    #[test]
+    fn test_resolve_template_flag_over_env_and_config() {
+        let file = Path::new("/repo").join(CONFIG_FILE);
+        let choice = resolve_template(
+            Some(Path::new("fix-bugs")),
+            Some("security"),
+            Some((&file, &config("review"))),
+        );
+        assert_eq!(choice.template, Some(PathBuf::from("fix-bugs")));
+        assert_eq!(choice.origin, TemplateOrigin::Flag);
+
+        let choice = resolve_template(
+            Some(Path::new("default")),
+            Some("security"),
+            Some((&file, &config("review"))),
+        );
+        assert_eq!(choice.template, None);
+        assert_eq!(choice.origin, TemplateOrigin::Flag);
+
+        let choice = resolve_template(None, None, Some((&file, &ProjectConfig::default())));
+        assert_eq!(choice.origin, TemplateOrigin::Default);
+    }
+}
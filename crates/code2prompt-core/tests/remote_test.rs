@@ -0,0 +1,96 @@
+use code2prompt_core::remote::{clone_repository, is_remote_url, repository_name, CloneOptions};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::{Repository, RepositoryInitOptions, Signature};
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    /// Creates a repository with a `main` branch and a `feature` branch.
+    fn create_origin(path: &Path) {
+        let mut binding = RepositoryInitOptions::new();
+        let repo = Repository::init_opts(path, binding.initial_head("main"))
+            .expect("Failed to initialize repository");
+        fs::write(path.join("lib.rs"), "fn main() {}\n").unwrap();
+
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("lib.rs")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let oid = repo
+            .commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                "Initial commit",
+                &tree,
+                &[],
+            )
+            .unwrap();
+        repo.branch("feature", &repo.find_commit(oid).unwrap(), false)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_is_remote_url() {
+        assert!(is_remote_url("https://github.com/user/repo"));
+        assert!(is_remote_url("ssh://git@github.com/user/repo.git"));
+        assert!(is_remote_url("git@github.com:user/repo.git"));
+        assert!(!is_remote_url("."));
+        assert!(!is_remote_url("src/main.rs"));
+        assert!(!is_remote_url("C:\\projects\\repo"));
+    }
+
+    #[test]
+    fn test_repository_name() {
+        assert_eq!(repository_name("https://github.com/user/repo"), "repo");
+        assert_eq!(repository_name("https://github.com/user/repo.git/"), "repo");
+        assert_eq!(repository_name("git@github.com:user/repo.git"), "repo");
+        assert_eq!(repository_name("git@host:repo.git"), "repo");
+        assert_eq!(repository_name("https://"), "repository");
+    }
+
+    #[test]
+    fn test_clone_repository() {
+        let origin_dir = TempDir::new().unwrap();
+        let origin = origin_dir.path().join("origin");
+        create_origin(&origin);
+        let url = origin.to_str().unwrap();
+
+        // The local transport does not support shallow fetches
+        let options = CloneOptions {
+            branch: Some("feature".to_string()),
+            depth: None,
+        };
+        let cloned = clone_repository(url, &options).expect("Failed to clone");
+        let clone_path = cloned.path().to_path_buf();
+        assert_eq!(clone_path.file_name().unwrap(), "origin");
+        assert!(clone_path.join("lib.rs").exists());
+        let clone = Repository::open(&clone_path).unwrap();
+        assert_eq!(clone.head().unwrap().shorthand(), Some("feature"));
+        drop(clone);
+
+        // The clone is removed once dropped
+        drop(cloned);
+        assert!(!clone_path.exists());
+
+        let kept = clone_repository(url, &options).unwrap().keep();
+        assert!(kept.join("lib.rs").exists());
+        fs::remove_dir_all(kept.parent().unwrap()).unwrap();
+
+        let options = CloneOptions {
+            branch: Some("missing".to_string()),
+            depth: None,
+        };
+        let err = clone_repository(url, &options).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("Branch missing doesn't exist in"),
+            "{}",
+            err
+        );
+    }
+}
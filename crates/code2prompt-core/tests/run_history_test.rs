@@ -0,0 +1,101 @@
+use code2prompt_core::configuration::Code2PromptConfig;
+use code2prompt_core::run_history::{compare_runs, RunHistory, RunSummary, HISTORY_FILE_NAME};
+use code2prompt_core::tokenizer::TokenizerType;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn summary(total_tokens: usize, files: &[(&str, usize)]) -> RunSummary {
+        RunSummary {
+            total_tokens,
+            files: files
+                .iter()
+                .map(|(path, tokens)| (path.to_string(), *tokens))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_run_history_round_trip() {
+        let dir = tempdir().unwrap();
+        let mut history = RunHistory::open(dir.path());
+        assert_eq!(history.previous("root#key"), None);
+
+        let run = summary(100, &[("a.rs", 60), ("b.rs", 30)]);
+        history.record("root#key".to_string(), run.clone());
+        history.save().unwrap();
+
+        let history = RunHistory::open(dir.path());
+        assert_eq!(history.previous("root#key"), Some(&run));
+        assert_eq!(history.previous("root#other"), None);
+    }
+
+    #[test]
+    fn test_corrupted_run_history_is_ignored() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join(HISTORY_FILE_NAME), "{ not json").unwrap();
+        let mut history = RunHistory::open(dir.path());
+        assert_eq!(history.previous("root#key"), None);
+
+        // The corrupted file is replaced on save
+        history.record("root#key".to_string(), summary(1, &[]));
+        history.save().unwrap();
+        assert!(RunHistory::open(dir.path()).previous("root#key").is_some());
+    }
+
+    #[test]
+    fn test_run_key_ignores_filters() {
+        let dir = tempdir().unwrap();
+        let config = |include: &str, encoding: TokenizerType| {
+            Code2PromptConfig::builder()
+                .path(dir.path().to_path_buf())
+                .include_patterns(vec![include.to_string()])
+                .encoding(encoding)
+                .build()
+                .unwrap()
+        };
+        let key = RunHistory::key(&config("*.rs", TokenizerType::Cl100kBase));
+        assert!(key.starts_with(&dir.path().canonicalize().unwrap().display().to_string()));
+        assert_eq!(
+            key,
+            RunHistory::key(&config("*.md", TokenizerType::Cl100kBase))
+        );
+        assert_ne!(
+            key,
+            RunHistory::key(&config("*.rs", TokenizerType::O200kBase))
+        );
+    }
+
+    #[test]
+    fn test_compare_runs() {
+        let previous = summary(
+            1000,
+            &[("kept.rs", 100), ("grown.rs", 100), ("removed.rs", 300)],
+        );
+        let current = summary(
+            900,
+            &[("kept.rs", 100), ("grown.rs", 150), ("added.rs", 20)],
+        );
+
+        let delta = compare_runs(&previous, &current, 5);
+        assert_eq!(delta.total_change(), -100);
+        let changes: Vec<(&str, i64)> = delta
+            .changes
+            .iter()
+            .map(|file| (file.path.as_str(), file.change()))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![("removed.rs", -300), ("grown.rs", 50), ("added.rs", 20)]
+        );
+        assert_eq!(delta.changes[0].after, None);
+        assert_eq!(delta.changes[2].before, None);
+
+        let delta = compare_runs(&previous, &current, 1);
+        assert_eq!(delta.changes.len(), 1);
+        assert_eq!(delta.changes[0].path, "removed.rs");
+    }
+}
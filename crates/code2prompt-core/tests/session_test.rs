@@ -0,0 +1,1309 @@
+use code2prompt_core::configuration::Code2PromptConfig;
+use code2prompt_core::path::PathStyle;
+use code2prompt_core::session::Code2PromptSession;
+use code2prompt_core::sort::FileSortMethod;
+use code2prompt_core::template::{heading_anchors, OutputFormat};
+use code2prompt_core::tokenizer::{count_tokens, TokenCountMode, TokenizerType};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_token_breakdown() {
+        let dir = tempdir().unwrap();
+        let code = "fn main() {\n    println!(\"Hello, world!\");\n}\n";
+        fs::write(dir.path().join("main.rs"), code).unwrap();
+
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let rendered = session.generate_prompt().unwrap();
+
+        let breakdown = rendered.token_breakdown;
+        assert_eq!(
+            breakdown.file_tokens,
+            count_tokens(code, &TokenizerType::Cl100kBase)
+        );
+        assert_eq!(breakdown.total_tokens, rendered.token_count);
+        assert_eq!(
+            breakdown.overhead_tokens,
+            breakdown.total_tokens - breakdown.file_tokens
+        );
+        assert!(breakdown.overhead_tokens > 0);
+    }
+
+    #[test]
+    fn test_token_breakdown_in_template_and_json() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "some file content").unwrap();
+
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .template_str(
+                "{{#each files}}{{code}}{{/each}}\nfiles: {{token_breakdown.file_tokens}}, total: {{token_breakdown.total_tokens}}".to_string(),
+            )
+            .template_name("breakdown".to_string())
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let rendered = session.generate_prompt().unwrap();
+        let file_tokens = rendered.token_breakdown.file_tokens;
+        assert!(rendered
+            .prompt
+            .contains(&format!("files: {}, total: ", file_tokens)));
+
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .output_format(OutputFormat::Json)
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let rendered = session.generate_prompt().unwrap();
+        let json: serde_json::Value = serde_json::from_str(&rendered.prompt).unwrap();
+        assert_eq!(json["token_breakdown"]["file_tokens"], file_tokens);
+        assert_eq!(json["token_breakdown"]["total_tokens"], json["token_count"]);
+    }
+
+    #[test]
+    fn test_estimate_mode_never_loads_the_tokenizer() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("small.rs"), "fn small() {}\n").unwrap();
+        fs::write(dir.path().join("large.rs"), "fn large() {}\n".repeat(100)).unwrap();
+
+        // No other test of this file counts with p50k_edit
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .encoding(TokenizerType::P50kEdit)
+            .token_count_mode(TokenCountMode::Estimate)
+            .sort_method(Some(FileSortMethod::TokensDesc))
+            .token_budget(Some(200))
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let rendered = session.generate_prompt().unwrap();
+        assert!(!TokenizerType::P50kEdit.is_loaded());
+
+        // The estimates drive the sorting and the budget
+        let report = session.data.token_budget.clone().unwrap();
+        assert!(report.tokens_before > 200);
+        assert_eq!(report.omitted.len(), 1);
+        assert_eq!(report.omitted[0].path, "large.rs");
+        assert_eq!(rendered.files, ["small.rs"]);
+        assert!(rendered.token_count <= 200);
+        assert!(rendered.prompt.contains("`small.rs` (~"));
+    }
+
+    #[test]
+    fn test_token_count_mode_off() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "some file content").unwrap();
+
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .token_count_mode(TokenCountMode::Off)
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config.clone());
+        let rendered = session.generate_prompt().unwrap();
+        assert_eq!(rendered.token_count, 0);
+        assert_eq!(session.file_token_counts()["a.txt"], None);
+        assert!(rendered.prompt.contains("some file content"));
+
+        let mut config = config;
+        config.token_budget = Some(100);
+        let mut session = Code2PromptSession::new(config);
+        let error = session.generate_prompt().unwrap_err().to_string();
+        assert!(error.contains("The token budget needs the tokens to be counted"));
+    }
+
+    #[test]
+    fn test_file_stats() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "one two\nthree\n").unwrap();
+        fs::write(dir.path().join("b.txt"), "four five six").unwrap();
+
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .template_str("{{#each files}}{{path}}: {{lines}} lines, {{words}} words\n{{/each}}{{stats.bytes}} bytes".to_string())
+            .template_name("stats".to_string())
+            .token_count_mode(TokenCountMode::Off)
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let rendered = session.generate_prompt().unwrap();
+
+        let stats = session.file_stats();
+        assert_eq!(stats.files, 2);
+        assert_eq!(stats.lines, 3);
+        assert_eq!(stats.words, 6);
+        assert_eq!(stats.bytes, 27);
+        assert!(rendered.prompt.contains("a.txt: 2 lines, 3 words"));
+        assert!(rendered.prompt.contains("b.txt: 1 lines, 3 words"));
+        assert!(rendered.prompt.contains("27 bytes"));
+    }
+
+    fn encodings_fixture() -> std::path::PathBuf {
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/encodings")
+    }
+
+    #[test]
+    fn test_files_with_encoding_issues_are_reported() {
+        let config = Code2PromptConfig::builder()
+            .path(encodings_fixture())
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let rendered = session.generate_prompt().unwrap();
+
+        let mut warnings: Vec<(&str, &str)> = session
+            .data
+            .file_warnings
+            .iter()
+            .map(|warning| (warning.path.as_str(), warning.message.as_str()))
+            .collect();
+        warnings.sort();
+        assert_eq!(
+            warnings,
+            vec![
+                ("binary.bin", "binary content, skipped"),
+                (
+                    "latin1.txt",
+                    "invalid UTF-8, 4 invalid sequence(s) replaced"
+                ),
+                ("utf16be.txt", "converted from UTF-16BE"),
+                ("utf16le.txt", "converted from UTF-16LE"),
+            ]
+        );
+
+        let mut files = rendered.files.clone();
+        files.sort();
+        assert_eq!(
+            files,
+            vec!["latin1.txt", "utf16be.txt", "utf16le.txt", "utf8.txt"]
+        );
+        assert!(rendered.prompt.contains("Hello from UTF-16LE"));
+        assert!(rendered.prompt.contains("Hello from UTF-16BE"));
+        assert!(rendered.prompt.contains("Caf\u{FFFD} cr\u{FFFD}me"));
+        assert!(rendered.prompt.contains("Plain UTF-8 ✓"));
+        assert!(rendered.token_count > 0);
+    }
+
+    #[test]
+    fn test_strict_fails_on_encoding_issues() {
+        let config = Code2PromptConfig::builder()
+            .path(encodings_fixture())
+            .include_patterns(vec!["latin1.txt".to_string()])
+            .strict(true)
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let error = session.load_codebase().unwrap_err();
+        assert!(format!("{:#}", error).contains("Failed to decode latin1.txt: invalid UTF-8"));
+
+        // Binary files are still skipped
+        let config = Code2PromptConfig::builder()
+            .path(encodings_fixture())
+            .include_patterns(vec!["binary.bin".to_string(), "utf8.txt".to_string()])
+            .strict(true)
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        session.load_codebase().unwrap();
+        assert_eq!(session.data.file_warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_section_tokens() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let rendered = session.generate_prompt().unwrap();
+        let sections = rendered.section_tokens;
+        assert_eq!(
+            sections.files,
+            count_tokens("```rust\nfn main() {}\n\n```", &TokenizerType::Cl100kBase)
+        );
+        assert!(sections.tree > 0);
+        assert_eq!(sections.diff, 0);
+        assert_eq!(sections.log, 0);
+        assert!(sections.template > 0);
+        assert_eq!(
+            sections.files + sections.tree + sections.diff + sections.log + sections.template,
+            rendered.token_count
+        );
+
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .template_str(
+                "{{source_tree}}\ntree: {{section_tokens.tree}}, template: {{section_tokens.template}}"
+                    .to_string(),
+            )
+            .template_name("sections".to_string())
+            .output_format(OutputFormat::Json)
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let rendered = session.generate_prompt().unwrap();
+        let json: serde_json::Value = serde_json::from_str(&rendered.prompt).unwrap();
+        let tree_tokens = json["section_tokens"]["tree"].as_u64().unwrap();
+        assert_eq!(tree_tokens as usize, rendered.section_tokens.tree);
+        assert!(json["prompt"]
+            .as_str()
+            .unwrap()
+            .contains(&format!("tree: {}, template: ", tree_tokens)));
+    }
+
+    #[test]
+    fn test_file_block_partial() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+        let partials = std::collections::HashMap::from([(
+            "file_block".to_string(),
+            "FILE {{path}}\n{{code}}\n".to_string(),
+        )]);
+
+        // The default templates render each file with the built-in partial
+        for output_format in [OutputFormat::Markdown, OutputFormat::Xml] {
+            let config = Code2PromptConfig::builder()
+                .path(dir.path().to_path_buf())
+                .output_format(output_format.clone())
+                .build()
+                .unwrap();
+            let mut session = Code2PromptSession::new(config);
+            let prompt = session.generate_prompt().unwrap().prompt;
+            assert!(prompt.contains("main.rs"));
+            assert!(!prompt.contains("FILE main.rs"));
+
+            let config = Code2PromptConfig::builder()
+                .path(dir.path().to_path_buf())
+                .output_format(output_format)
+                .template_partials(partials.clone())
+                .build()
+                .unwrap();
+            let mut session = Code2PromptSession::new(config);
+            let prompt = session.generate_prompt().unwrap().prompt;
+            assert!(prompt.contains("FILE main.rs\n```rust\nfn main() {}"));
+        }
+
+        // A custom template can include the built-in partial
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .template_str("{{#each files}}{{> file_block}}{{/each}}".to_string())
+            .template_name("custom".to_string())
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let prompt = session.generate_prompt().unwrap().prompt;
+        assert!(prompt.starts_with("`main.rs`"));
+    }
+
+    #[test]
+    fn test_missing_partial() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .template_str("{{#each files}}{{> header}}{{/each}}".to_string())
+            .template_name("review".to_string())
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let error = session.generate_prompt().unwrap_err().to_string();
+        assert!(error.contains("partial header"), "{}", error);
+        assert!(error.contains("template review"), "{}", error);
+    }
+
+    #[test]
+    fn test_user_variables() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .template_str("{{user.ticket}}: {{user.note}} ({{ticket}})".to_string())
+            .template_name("variables".to_string())
+            .user_variables(std::collections::HashMap::from([
+                ("ticket".to_string(), "ABC-12".to_string()),
+                ("note".to_string(), "a=b=c".to_string()),
+            ]))
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let prompt = session.generate_prompt().unwrap().prompt;
+        assert_eq!(prompt, "ABC-12: a=b=c (ABC-12)");
+    }
+
+    #[test]
+    fn test_commits_in_template() {
+        use git2::{Repository, Signature, Time};
+
+        let dir = tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let mut parent: Option<git2::Oid> = None;
+        for (index, message) in ["First commit", "Second commit\n\nWith a body"]
+            .iter()
+            .enumerate()
+        {
+            fs::write(dir.path().join("file.txt"), message).unwrap();
+            let mut index_file = repo.index().unwrap();
+            index_file
+                .add_path(std::path::Path::new("file.txt"))
+                .unwrap();
+            index_file.write().unwrap();
+            let tree = repo.find_tree(index_file.write_tree().unwrap()).unwrap();
+            let time = Time::new(1_700_000_000 + index as i64 * 86_400, 0);
+            let signature = Signature::new("Ada", "ada@example.com", &time).unwrap();
+            let parents: Vec<git2::Commit> = parent
+                .map(|oid| repo.find_commit(oid).unwrap())
+                .into_iter()
+                .collect();
+            let parent_refs: Vec<&git2::Commit> = parents.iter().collect();
+            parent = Some(
+                repo.commit(
+                    Some("HEAD"),
+                    &signature,
+                    &signature,
+                    message,
+                    &tree,
+                    &parent_refs,
+                )
+                .unwrap(),
+            );
+        }
+
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .log_range(Some("-n 2".to_string()))
+            .template_str(
+                "{{#each commits}}- {{short_sha}} {{summary}} ({{author}}, {{date timestamp \"%Y-%m-%d\"}}){{#if body}}: {{body}}{{/if}}\n{{/each}}"
+                    .to_string(),
+            )
+            .template_name("commits".to_string())
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        session.load_git_log_range().unwrap();
+        let data = session.build_template_data();
+        assert!(data["git_log_branch"].as_str().is_some());
+        let prompt = session.render_prompt(&data).unwrap().prompt;
+
+        let short_sha = |oid: git2::Oid| oid.to_string()[..7].to_string();
+        let head = parent.unwrap();
+        let first = repo.find_commit(head).unwrap().parent_id(0).unwrap();
+        assert_eq!(
+            prompt,
+            format!(
+                "- {} First commit (Ada <ada@example.com>, 2023-11-14)\n- {} Second commit (Ada <ada@example.com>, 2023-11-15): With a body",
+                short_sha(first),
+                short_sha(head)
+            )
+        );
+    }
+
+    #[test]
+    fn test_template_flags_empty_diff() {
+        use git2::{Repository, Signature};
+
+        let dir = tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(std::path::Path::new("main.rs")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("Ada", "ada@example.com").unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "Initial", &tree, &[])
+            .unwrap();
+
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .diff_enabled(true)
+            .line_numbers(true)
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let rendered = session.generate_prompt().unwrap();
+
+        let flags = session.template_flags();
+        assert!(flags.diff_empty);
+        assert!(!flags.has_diff);
+        assert!(!flags.has_log);
+        assert!(flags.has_tree);
+        assert!(flags.has_files);
+        assert!(flags.line_numbers);
+        assert!(flags.code_blocks);
+        assert!(!rendered.prompt.contains("no diff between"));
+        assert!(!rendered.prompt.contains("Git Diff:"));
+        assert!(rendered.prompt.contains("Source Tree:"));
+
+        let data = session.build_template_data();
+        assert_eq!(data["flags"]["diff_empty"], true);
+        assert_eq!(data["flags"]["absolute_paths"], false);
+    }
+
+    #[test]
+    fn test_no_tree_and_no_contents() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+        let config = |no_tree: bool, no_contents: bool, output_format: OutputFormat| {
+            Code2PromptConfig::builder()
+                .path(dir.path().to_path_buf())
+                .no_tree(no_tree)
+                .no_contents(no_contents)
+                .output_format(output_format)
+                .build()
+                .unwrap()
+        };
+        let mut session = Code2PromptSession::new(config(false, false, OutputFormat::Markdown));
+        let full = session.generate_prompt().unwrap();
+        assert_eq!(full.full_token_count, None);
+
+        // Only the tree, and what the prompt would have taken with the contents
+        let mut session = Code2PromptSession::new(config(false, true, OutputFormat::Markdown));
+        let tree_only = session.generate_prompt().unwrap();
+        assert!(tree_only.prompt.contains("Source Tree:"));
+        assert!(tree_only.prompt.contains("main.rs"));
+        assert!(!tree_only.prompt.contains("fn main()"));
+        assert!(tree_only.token_count < full.token_count);
+        assert_eq!(tree_only.full_token_count, Some(full.token_count));
+        let flags = session.template_flags();
+        assert!(flags.has_tree && flags.has_files && !flags.has_contents);
+
+        let mut session = Code2PromptSession::new(config(true, false, OutputFormat::Markdown));
+        let contents_only = session.generate_prompt().unwrap();
+        assert!(!contents_only.prompt.contains("Source Tree:"));
+        assert!(contents_only.prompt.contains("fn main()"));
+        assert_eq!(contents_only.full_token_count, Some(full.token_count));
+        assert_eq!(
+            session.build_template_data()["source_tree"],
+            serde_json::Value::Null
+        );
+
+        // The empty sections of the XML template vanish too
+        let mut session = Code2PromptSession::new(config(true, true, OutputFormat::Xml));
+        let prompt = session.generate_prompt().unwrap().prompt;
+        assert!(!prompt.contains("<source_tree>"));
+        assert!(!prompt.contains("<documents>"));
+        assert!(prompt.contains("<directory>"));
+    }
+
+    #[test]
+    fn test_template_flags_without_diff() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .template_str(
+                "{{#if flags.has_diff}}Recent changes{{/if}}{{#if flags.diff_empty}}No changes{{/if}}"
+                    .to_string(),
+            )
+            .template_name("flags".to_string())
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let rendered = session.generate_prompt().unwrap();
+        assert_eq!(rendered.prompt, "");
+        assert!(!session.template_flags().diff_empty);
+    }
+
+    #[test]
+    fn test_file_metadata_in_template_and_json() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .template_str(
+                "{{#each files}}{{path}} {{language}} {{size_bytes}} {{truncated}}{{/each}}"
+                    .to_string(),
+            )
+            .template_name("metadata".to_string())
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let rendered = session.generate_prompt().unwrap();
+        assert_eq!(rendered.prompt, "main.rs rust 13 false");
+
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .output_format(OutputFormat::Json)
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let rendered = session.generate_prompt().unwrap();
+        let json: serde_json::Value = serde_json::from_str(&rendered.prompt).unwrap();
+        let metadata = &json["file_metadata"][0];
+        assert_eq!(metadata["path"], "main.rs");
+        assert_eq!(metadata["size_bytes"], 13);
+        assert_eq!(metadata["extension"], "rs");
+        assert_eq!(metadata["language"], "rust");
+        assert_eq!(metadata["truncated"], false);
+        assert!(metadata["token_count"].is_u64());
+        assert!(metadata["mtime"].is_string());
+        assert_eq!(
+            session.file_metadata()[0].mtime.as_deref(),
+            metadata["mtime"].as_str()
+        );
+    }
+
+    #[test]
+    fn test_markdown_file_with_code_fence() {
+        let dir = tempdir().unwrap();
+        let readme = "# Usage\n\n```sh\ncargo run\n```\n";
+        fs::write(dir.path().join("README.md"), readme).unwrap();
+
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let rendered = session.generate_prompt().unwrap();
+        assert!(
+            rendered
+                .prompt
+                .contains(&format!("````markdown\n{}\n````", readme)),
+            "{}",
+            rendered.prompt
+        );
+        assert!(rendered.prompt.contains("```txt\n"), "{}", rendered.prompt);
+    }
+
+    #[test]
+    fn test_structured_tree() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src/nested")).unwrap();
+        fs::write(dir.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(dir.path().join("src/nested/lib.rs"), "pub fn run() {}\n").unwrap();
+        fs::write(dir.path().join("README.md"), "# Readme\n").unwrap();
+
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .template_str("{{#with tree}}{{> tree_node}}{{/with}}".to_string())
+            .template_name("tree".to_string())
+            .sort_method(FileSortMethod::NameDesc)
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let rendered = session.generate_prompt().unwrap();
+        let tree = session.tree().unwrap();
+        assert!(tree.is_dir);
+        assert_eq!(tree.path, "");
+        assert_eq!(tree.files, 3);
+        let names: Vec<&str> = tree
+            .children
+            .iter()
+            .map(|node| node.name.as_str())
+            .collect();
+        assert_eq!(names, ["src", "README.md"]);
+        let src = &tree.children[0];
+        assert_eq!(src.depth, 1);
+        assert_eq!(src.files, 2);
+        assert_eq!(src.children[0].path, "src/nested");
+        assert_eq!(src.children[0].children[0].path, "src/nested/lib.rs");
+        assert!(!src.children[0].children[0].is_dir);
+        assert_eq!(
+            tree.tokens,
+            src.tokens + tree.children[1].tokens,
+            "{:?}",
+            tree
+        );
+
+        let lines: Vec<&str> = rendered.prompt.lines().collect();
+        assert_eq!(lines.len(), 6, "{}", rendered.prompt);
+        assert!(
+            lines[1].starts_with("  - src/ (2 files, "),
+            "{}",
+            rendered.prompt
+        );
+        assert!(
+            lines[2].starts_with("    - nested/ (1 files, "),
+            "{}",
+            rendered.prompt
+        );
+        assert!(
+            lines[3].starts_with("      - lib.rs ("),
+            "{}",
+            rendered.prompt
+        );
+        assert!(
+            lines[4].starts_with("    - main.rs ("),
+            "{}",
+            rendered.prompt
+        );
+        assert!(
+            lines[5].starts_with("  - README.md ("),
+            "{}",
+            rendered.prompt
+        );
+
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .template_str(
+                "{{#each tree.children}}{{name}}:{{#each children}} {{name}}{{/each}};{{/each}}"
+                    .to_string(),
+            )
+            .template_name("two-levels".to_string())
+            .sort_method(FileSortMethod::NameAsc)
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let rendered = session.generate_prompt().unwrap();
+        assert_eq!(rendered.prompt, "README.md:;src: main.rs nested;");
+    }
+
+    #[test]
+    fn test_changed_files_only() {
+        use git2::{Repository, Signature};
+
+        let dir = tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let signature = Signature::now("Ada", "ada@example.com").unwrap();
+        let commit = |files: &[(&str, &str)], message: &str| {
+            let mut index = repo.index().unwrap();
+            for (name, content) in files {
+                fs::write(dir.path().join(name), content).unwrap();
+                index.add_path(std::path::Path::new(name)).unwrap();
+            }
+            index.write().unwrap();
+            let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+            let parents: Vec<git2::Commit> = repo
+                .head()
+                .ok()
+                .map(|head| head.peel_to_commit().unwrap())
+                .into_iter()
+                .collect();
+            let parent_refs: Vec<&git2::Commit> = parents.iter().collect();
+            let oid = repo
+                .commit(
+                    Some("HEAD"),
+                    &signature,
+                    &signature,
+                    message,
+                    &tree,
+                    &parent_refs,
+                )
+                .unwrap();
+            repo.find_commit(oid).unwrap()
+        };
+        let base = commit(
+            &[
+                ("main.rs", "fn main() {}\n"),
+                ("lib.rs", "pub fn run() {}\n"),
+            ],
+            "Initial",
+        );
+        commit(
+            &[
+                ("lib.rs", "pub fn run() {}\npub fn stop() {}\n"),
+                ("new.rs", "fn new() {}\n"),
+            ],
+            "feat: add stop",
+        );
+        repo.branch("base", &base, false).unwrap();
+        let head = repo.head().unwrap().shorthand().unwrap().to_string();
+
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .diff_branches(Some(("base".to_string(), head)))
+            .changed_files_only(true)
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        session.load_codebase().unwrap();
+        let mut paths: Vec<String> = session
+            .data
+            .files
+            .as_ref()
+            .and_then(|files| files.as_array())
+            .unwrap()
+            .iter()
+            .map(|file| file["path"].as_str().unwrap().to_string())
+            .collect();
+        paths.sort();
+        assert_eq!(paths, ["lib.rs", "new.rs"]);
+        let stats = session.data.git_diff_stat.as_ref().unwrap();
+        assert_eq!(stats.files_changed, 2);
+        assert_eq!(stats.insertions, 2);
+
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .changed_files_only(true)
+            .build()
+            .unwrap();
+        let error = Code2PromptSession::new(config)
+            .load_codebase()
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("branch diff"), "{}", error);
+    }
+
+    #[test]
+    fn test_xml_documents() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join("a.md"),
+            "Close with </document_contents> or ]]>\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("b.rs"), "fn main() {}\n").unwrap();
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .output_format(OutputFormat::Xml)
+            .sort_method(FileSortMethod::NameAsc)
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let prompt = session.generate_prompt().unwrap().prompt;
+
+        assert!(prompt.contains("<source_tree>\n<![CDATA["), "{}", prompt);
+        assert!(
+            prompt.contains("<documents>\n<document index=\"1\""),
+            "{}",
+            prompt
+        );
+        assert!(prompt.contains("<source>a.md</source>"), "{}", prompt);
+        assert!(prompt.contains("<document index=\"2\""), "{}", prompt);
+        assert!(prompt.contains("<source>b.rs</source>"), "{}", prompt);
+        // The contents can't close their element nor their CDATA section
+        assert_eq!(
+            prompt.matches("</document_contents>\n</document>").count(),
+            2
+        );
+        assert!(
+            prompt.contains("Close with </document_contents> or ]]]]><![CDATA[>"),
+            "{}",
+            prompt
+        );
+        let documents = prompt
+            .split("<document_contents>\n")
+            .skip(1)
+            .map(|document| document.split("\n</document_contents>").next().unwrap());
+        for document in documents {
+            assert!(document.starts_with("<![CDATA[") && document.ends_with("]]>"));
+            // Every end of a section but the last one starts the next section
+            let ends = document.matches("]]>").count();
+            assert_eq!(ends, document.matches("]]><![CDATA[").count() + 1);
+        }
+    }
+
+    #[test]
+    fn test_code_is_not_html_escaped() {
+        let dir = tempdir().unwrap();
+        let code = "fn first(items: Vec<Option<&str>>) -> Option<&str> {\n    items[0]\n}\n";
+        fs::write(dir.path().join("lib.rs"), code).unwrap();
+
+        for escape_html in [false, true] {
+            let config = Code2PromptConfig::builder()
+                .path(dir.path().to_path_buf())
+                .template_str("{{#each files}}{{code}}{{/each}}".to_string())
+                .template_name("code".to_string())
+                .no_codeblock(true)
+                .escape_html(escape_html)
+                .build()
+                .unwrap();
+            let mut session = Code2PromptSession::new(config);
+            let prompt = session.generate_prompt().unwrap().prompt;
+            assert_eq!(
+                prompt.contains("Vec<Option<&str>>"),
+                !escape_html,
+                "{}",
+                prompt
+            );
+            assert_eq!(
+                prompt.contains("Vec&lt;Option&lt;&amp;str&gt;&gt;"),
+                escape_html,
+                "{}",
+                prompt
+            );
+        }
+    }
+
+    #[test]
+    fn test_file_template() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+        fs::write(dir.path().join("lib.rs"), "pub fn a() {}\npub fn b() {}\n").unwrap();
+
+        for output_format in [OutputFormat::Markdown, OutputFormat::Xml] {
+            let config = Code2PromptConfig::builder()
+                .path(dir.path().to_path_buf())
+                .output_format(output_format)
+                .sort_method(FileSortMethod::NameAsc)
+                .file_template(Some(
+                    "[{{path}}: {{lines}} lines, {{language}}]".to_string(),
+                ))
+                .template_partials(std::collections::HashMap::from([(
+                    "file_block".to_string(),
+                    "replaced".to_string(),
+                )]))
+                .build()
+                .unwrap();
+            let mut session = Code2PromptSession::new(config);
+            let prompt = session.generate_prompt().unwrap().prompt;
+            assert!(prompt.contains("[lib.rs: 2 lines, rust]"), "{}", prompt);
+            assert!(prompt.contains("[main.rs: 1 lines, rust]"), "{}", prompt);
+            assert!(!prompt.contains("replaced"), "{}", prompt);
+            assert!(!prompt.contains("fn main"), "{}", prompt);
+            assert!(prompt.contains("Source Tree:") || prompt.contains("<directory>"));
+        }
+    }
+
+    /// Compares a text with its snapshot in `tests/fixtures/snapshots`, which is written
+    /// instead with `UPDATE_SNAPSHOTS=1` to accept a change of the output.
+    fn assert_snapshot(name: &str, actual: &str) {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/snapshots")
+            .join(name);
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, actual).unwrap();
+            return;
+        }
+        let expected = fs::read_to_string(&path)
+            .unwrap_or_else(|_| panic!("No snapshot {}: run with UPDATE_SNAPSHOTS=1", name));
+        assert_eq!(
+            actual, expected,
+            "The output changed from the snapshot {}: run with UPDATE_SNAPSHOTS=1 to accept it",
+            name
+        );
+    }
+
+    #[test]
+    fn test_json_output_snapshot() {
+        use git2::{Repository, Signature};
+
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("project");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(root.join("README.md"), "# Project\n").unwrap();
+        let repo = Repository::init(&root).unwrap();
+        let signature = Signature::now("Ada", "ada@example.com").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(std::path::Path::new("src/main.rs")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "Initial", &tree, &[])
+            .unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {\n    run();\n}\n").unwrap();
+        index.add_path(std::path::Path::new("src/main.rs")).unwrap();
+        index.write().unwrap();
+
+        let config = |json_compact: bool| {
+            Code2PromptConfig::builder()
+                .path(root.clone())
+                .output_format(OutputFormat::Json)
+                .sort_method(FileSortMethod::NameAsc)
+                .diff_enabled(true)
+                .json_compact(json_compact)
+                .build()
+                .unwrap()
+        };
+        let mut session = Code2PromptSession::new(config(false));
+        let prompt = session.generate_prompt().unwrap().prompt;
+        let mut json: serde_json::Value = serde_json::from_str(&prompt).unwrap();
+
+        assert_eq!(json["directory"], "project");
+        assert_eq!(json["files"][1]["path"], "src/main.rs");
+        assert_eq!(json["files"][1]["content"], "fn main() {\n    run();\n}\n");
+        assert_eq!(json["stats"]["files"], 2);
+        assert_eq!(json["stats"]["tokens"], json["token_count"]);
+        let diff = json["git"]["diff"].as_str().unwrap();
+        assert!(diff.contains("@@ -1 +1,3 @@"), "{}", diff);
+        // The times change from a run to the next
+        assert!(
+            chrono::DateTime::parse_from_rfc3339(json["generated_at"].as_str().unwrap()).is_ok()
+        );
+        json["generated_at"] = "<generated_at>".into();
+        for metadata in json["file_metadata"].as_array_mut().unwrap() {
+            metadata["mtime"] = "<mtime>".into();
+        }
+        assert_snapshot(
+            "json_output.json",
+            &format!("{}\n", serde_json::to_string_pretty(&json).unwrap()),
+        );
+
+        let mut session = Code2PromptSession::new(config(true));
+        let compact = session.generate_prompt().unwrap().prompt;
+        assert_eq!(compact.lines().count(), 1);
+        let compact: serde_json::Value = serde_json::from_str(&compact).unwrap();
+        assert_eq!(compact["files"], json["files"]);
+    }
+
+    #[test]
+    fn test_yaml_output() {
+        let dir = tempdir().unwrap();
+        // Contents that would be read as YAML syntax if they weren't quoted or in a block
+        let contents = [
+            ("a.md", "- item\n# heading: x\n"),
+            ("b.py", "  indented\nnext\n"),
+            ("c.yml", "---\nkey: value\n...\n"),
+            ("d.txt", "trailing  \n\ttab\n"),
+            ("e.txt", "| not a block\n> nor this\n"),
+        ];
+        for (name, content) in contents {
+            fs::write(dir.path().join(name), content).unwrap();
+        }
+        let config = |output_format: OutputFormat| {
+            Code2PromptConfig::builder()
+                .path(dir.path().to_path_buf())
+                .output_format(output_format)
+                .sort_method(FileSortMethod::NameAsc)
+                .build()
+                .unwrap()
+        };
+        let mut session = Code2PromptSession::new(config(OutputFormat::Yaml));
+        let yaml = session.generate_prompt().unwrap().prompt;
+        assert!(
+            yaml.contains("- content: |\n    - item\n    # heading: x\n"),
+            "{}",
+            yaml
+        );
+        assert!(
+            yaml.contains("- content: |\n    ---\n    key: value\n"),
+            "{}",
+            yaml
+        );
+
+        let mut yaml: serde_json::Value = serde_yaml::from_str(&yaml).unwrap();
+        for (file, (name, content)) in yaml["files"].as_array().unwrap().iter().zip(contents) {
+            assert_eq!(file["path"], name);
+            assert_eq!(file["content"], content);
+        }
+
+        let mut session = Code2PromptSession::new(config(OutputFormat::Json));
+        let mut json: serde_json::Value =
+            serde_json::from_str(&session.generate_prompt().unwrap().prompt).unwrap();
+        for document in [&mut yaml, &mut json] {
+            document["generated_at"] = "<generated_at>".into();
+        }
+        assert_eq!(yaml, json);
+    }
+
+    #[test]
+    fn test_line_numbers_not_in_the_git_diff() {
+        use git2::{Repository, Signature};
+
+        let dir = tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(std::path::Path::new("main.rs")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("Ada", "ada@example.com").unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "Initial", &tree, &[])
+            .unwrap();
+        fs::write(dir.path().join("main.rs"), "fn main() {\n    run();\n}\n").unwrap();
+        index.add_path(std::path::Path::new("main.rs")).unwrap();
+        index.write().unwrap();
+
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .diff_enabled(true)
+            .line_numbers(true)
+            .template_str("{{#each files}}{{code}}{{/each}}\n---\n{{git_diff}}".to_string())
+            .template_name("custom".to_string())
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let prompt = session.generate_prompt().unwrap().prompt;
+        let (files, diff) = prompt.split_once("\n---\n").unwrap();
+        assert!(
+            files.contains("1 | fn main() {\n2 |     run();\n3 | }\n"),
+            "{}",
+            files
+        );
+        assert!(diff.contains("fn main() {"), "{}", diff);
+        assert!(!diff.contains(" | "), "{}", diff);
+    }
+
+    #[test]
+    fn test_path_style_in_tree_and_files() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("packages/api/src")).unwrap();
+        fs::write(
+            dir.path().join("packages/api/src/main.rs"),
+            "fn main() {}\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("README.md"), "# Readme\n").unwrap();
+
+        for (style, main) in [
+            (PathStyle::Relative, "packages/api/src/main.rs".to_string()),
+            (
+                PathStyle::Strip("packages/api".to_string()),
+                "src/main.rs".to_string(),
+            ),
+            (
+                PathStyle::Absolute,
+                format!(
+                    "{}/packages/api/src/main.rs",
+                    dir.path().canonicalize().unwrap().display()
+                ),
+            ),
+        ] {
+            let config = Code2PromptConfig::builder()
+                .path(dir.path().to_path_buf())
+                .path_style(style.clone())
+                .output_format(OutputFormat::Json)
+                .sort_method(FileSortMethod::NameAsc)
+                .build()
+                .unwrap();
+            let mut session = Code2PromptSession::new(config);
+            let json: serde_json::Value =
+                serde_json::from_str(&session.generate_prompt().unwrap().prompt).unwrap();
+            assert_eq!(json["files"][1]["path"], main.as_str(), "{:?}", style);
+            assert!(
+                json["prompt"].as_str().unwrap().contains(&main),
+                "{:?}",
+                style
+            );
+
+            // The nodes of the tree have the paths of the files, so they count their tokens
+            let tree = session.tree().unwrap();
+            let packages = tree.children.iter().find(|node| node.name == "packages");
+            let file = &packages.unwrap().children[0].children[0].children[0];
+            assert_eq!(file.path, main, "{:?}", style);
+            assert_eq!(file.files, 1, "{:?}", style);
+            assert_eq!(tree.files, 2, "{:?}", style);
+        }
+    }
+
+    #[test]
+    fn test_markdown_file_with_fences_stays_in_its_block() {
+        use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+
+        let dir = tempdir().unwrap();
+        let readme =
+            "# Usage\n\n```sh\ncargo run\n```\n\n````md\n```rust\nfn main() {}\n```\n````\n";
+        fs::write(dir.path().join("README.md"), readme).unwrap();
+        fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let rendered = session.generate_prompt().unwrap();
+
+        let mut blocks: Vec<String> = Vec::new();
+        let mut in_block = false;
+        for event in Parser::new(&rendered.prompt) {
+            match event {
+                Event::Start(Tag::CodeBlock(_)) => {
+                    blocks.push(String::new());
+                    in_block = true;
+                }
+                Event::End(TagEnd::CodeBlock) => in_block = false,
+                Event::Text(text) if in_block => blocks.last_mut().unwrap().push_str(&text),
+                _ => {}
+            }
+        }
+        // The source tree and the two files
+        assert_eq!(blocks.len(), 3, "{}", rendered.prompt);
+        assert!(
+            blocks
+                .iter()
+                .any(|block| block.trim_end() == readme.trim_end()),
+            "{}",
+            rendered.prompt
+        );
+        assert!(
+            rendered.prompt.contains("`````markdown\n# Usage"),
+            "{}",
+            rendered.prompt
+        );
+    }
+
+    #[test]
+    fn test_ndjson_output() {
+        use git2::{Repository, Signature};
+
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("project");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(root.join("README.md"), "# Project\n").unwrap();
+        let repo = Repository::init(&root).unwrap();
+        let signature = Signature::now("Ada", "ada@example.com").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(std::path::Path::new("src/main.rs")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "Initial", &tree, &[])
+            .unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {\n    run();\n}\n").unwrap();
+        index.add_path(std::path::Path::new("src/main.rs")).unwrap();
+        index.write().unwrap();
+
+        let config = Code2PromptConfig::builder()
+            .path(root.clone())
+            .output_format(OutputFormat::Ndjson)
+            .sort_method(FileSortMethod::NameAsc)
+            .diff_enabled(true)
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let rendered = session.generate_prompt().unwrap();
+
+        // Every line is a record of its own
+        let records: Vec<serde_json::Value> = rendered
+            .prompt
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let types: Vec<&str> = records
+            .iter()
+            .map(|record| record["type"].as_str().unwrap())
+            .collect();
+        assert_eq!(types, vec!["meta", "file", "file", "git", "stats"]);
+
+        let meta = &records[0];
+        assert_eq!(meta["directory"], "project");
+        assert_eq!(meta["config"]["line_numbers"], false);
+        assert_eq!(meta["tree"]["name"], "project");
+        assert_eq!(records[1]["path"], "README.md");
+        assert_eq!(records[2]["path"], "src/main.rs");
+        assert_eq!(records[2]["content"], "fn main() {\n    run();\n}\n");
+        assert!(records[3]["diff"]
+            .as_str()
+            .unwrap()
+            .contains("@@ -1 +1,3 @@"));
+        assert_eq!(
+            records[3]["structured_diff"]["files"][0]["new_path"],
+            "src/main.rs"
+        );
+        assert_eq!(records[4]["files"], 2);
+        assert_eq!(records[4]["tokens"], rendered.token_count);
+
+        // Without git data there is no git record, and the stats still come last
+        let mut records = Vec::new();
+        session.data.git_diff = None;
+        session.data.git_diff_stat = None;
+        session.write_ndjson(&mut records, None).unwrap();
+        let records = String::from_utf8(records).unwrap();
+        let last: serde_json::Value =
+            serde_json::from_str(records.lines().last().unwrap()).unwrap();
+        assert_eq!(records.lines().count(), 4);
+        assert_eq!(last["type"], "stats");
+        assert!(last.get("tokens").is_none());
+    }
+
+    #[test]
+    fn test_toc_links_to_file_headings() {
+        use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+
+        let dir = tempdir().unwrap();
+        for path in ["a/b.rs", "ab.rs", "src/My File.rs"] {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, "fn main() {}\n").unwrap();
+        }
+        let config = |output_format: OutputFormat| {
+            Code2PromptConfig::builder()
+                .path(dir.path().to_path_buf())
+                .sort_method(FileSortMethod::NameAsc)
+                .toc(true)
+                .output_format(output_format)
+                .build()
+                .unwrap()
+        };
+        let mut session = Code2PromptSession::new(config(OutputFormat::Markdown));
+        let prompt = session.generate_prompt().unwrap().prompt;
+
+        // The links of the table of contents and the anchors GitHub gives the headings
+        let mut links = Vec::new();
+        let mut headings = Vec::new();
+        let mut heading: Option<String> = None;
+        for event in Parser::new(&prompt) {
+            match event {
+                Event::Start(Tag::Link { dest_url, .. }) => links.push(dest_url.to_string()),
+                Event::Start(Tag::Heading { .. }) => heading = Some(String::new()),
+                Event::Text(text) | Event::Code(text) => {
+                    if let Some(heading) = heading.as_mut() {
+                        heading.push_str(&text);
+                    }
+                }
+                Event::End(TagEnd::Heading(_)) => headings.extend(heading.take()),
+                _ => {}
+            }
+        }
+        assert_eq!(headings, ["a/b.rs", "ab.rs", "src/My File.rs"]);
+        assert_eq!(links, ["#abrs", "#abrs-1", "#srcmy-filers"]);
+        let anchors = heading_anchors(headings.iter().map(String::as_str));
+        let targets: Vec<String> = anchors
+            .iter()
+            .map(|anchor| format!("#{}", anchor))
+            .collect();
+        assert_eq!(links, targets);
+        let toc = prompt.find("Table of Contents:").unwrap();
+        assert!(prompt.find("Source Tree:").unwrap() < toc);
+        assert!(toc < prompt.find("### `a/b.rs`").unwrap());
+
+        let mut session = Code2PromptSession::new(config(OutputFormat::Xml));
+        let prompt = session.generate_prompt().unwrap().prompt;
+        assert!(!prompt.contains("Table of Contents"));
+        assert!(session.build_template_data().get("toc").is_none());
+    }
+
+    #[test]
+    fn test_file_header_and_footer_formats() {
+        let dir = tempdir().unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(dir.path().join("notes.txt"), "notes\n").unwrap();
+        let config = |output_format: OutputFormat, footer: Option<&str>| {
+            Code2PromptConfig::builder()
+                .path(dir.path().to_path_buf())
+                .sort_method(FileSortMethod::NameAsc)
+                .file_header_format(Some("===== {index}: {path} ({language}) =====".to_string()))
+                .file_footer_format(footer.map(str::to_string))
+                .output_format(output_format)
+                .build()
+                .unwrap()
+        };
+
+        let mut session =
+            Code2PromptSession::new(config(OutputFormat::Markdown, Some("<<<END {path}>>>")));
+        let prompt = session.generate_prompt().unwrap().prompt;
+        assert!(
+            prompt.contains(
+                "===== 1: notes.txt (txt) =====\n\n```txt\nnotes\n\n```\n<<<END notes.txt>>>"
+            ),
+            "{}",
+            prompt
+        );
+        assert!(
+            prompt.contains("===== 2: src/main.rs (rust) =====\n"),
+            "{}",
+            prompt
+        );
+        assert!(prompt.contains("<<<END src/main.rs>>>"), "{}", prompt);
+        // The header replaces the path and tokens line
+        assert!(!prompt.contains("`src/main.rs`"), "{}", prompt);
+
+        // Without a footer, the file block ends with the code
+        let mut session = Code2PromptSession::new(config(OutputFormat::Markdown, None));
+        let prompt = session.generate_prompt().unwrap().prompt;
+        assert!(!prompt.contains("<<<END"), "{}", prompt);
+
+        // The XML prompt keeps its document tags
+        let mut session =
+            Code2PromptSession::new(config(OutputFormat::Xml, Some("<<<END {path}>>>")));
+        let prompt = session.generate_prompt().unwrap().prompt;
+        assert!(!prompt.contains("====="), "{}", prompt);
+        assert!(!prompt.contains("<<<END"), "{}", prompt);
+    }
+}
@@ -1,4 +1,7 @@
-use code2prompt_core::sort::{sort_files, sort_tree, FileSortMethod};
+use code2prompt_core::filter::build_globset;
+use code2prompt_core::sort::{
+    compare_paths, prioritize_files, sort_files, sort_tree, FileSortMethod,
+};
 
 #[cfg(test)]
 mod tests {
@@ -110,6 +113,81 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    fn sorted_paths(files: &[serde_json::Value]) -> Vec<&str> {
+        files
+            .iter()
+            .map(|v| v.get("path").and_then(|s| s.as_str()).unwrap_or(""))
+            .collect()
+    }
+
+    #[test]
+    fn test_sort_files_tokens() {
+        // Ties fall back to the path order, whatever the original order
+        let files = vec![
+            json!({"path": "zeta.rs", "token_count": 20}),
+            json!({"path": "beta.rs", "token_count": 5}),
+            json!({"path": "alpha.rs", "token_count": 20}),
+            json!({"path": "gamma.rs", "token_count": null}),
+        ];
+
+        let mut ascending = files.clone();
+        sort_files(&mut ascending, Some(FileSortMethod::TokensAsc));
+        assert_eq!(
+            sorted_paths(&ascending),
+            vec!["gamma.rs", "beta.rs", "alpha.rs", "zeta.rs"]
+        );
+
+        let mut descending = files.clone();
+        sort_files(&mut descending, Some(FileSortMethod::TokensDesc));
+        assert_eq!(
+            sorted_paths(&descending),
+            vec!["alpha.rs", "zeta.rs", "beta.rs", "gamma.rs"]
+        );
+
+        // Sorting a reversed list gives the same order
+        let mut reversed: Vec<_> = files.into_iter().rev().collect();
+        sort_files(&mut reversed, Some(FileSortMethod::TokensDesc));
+        assert_eq!(sorted_paths(&reversed), sorted_paths(&descending));
+    }
+
+    #[test]
+    fn test_sort_files_size() {
+        let mut files = vec![
+            json!({"path": "b.txt", "size": 300}),
+            json!({"path": "c.txt", "size": 100}),
+            json!({"path": "a.txt", "size": 300}),
+        ];
+
+        sort_files(&mut files, Some(FileSortMethod::SizeAsc));
+        assert_eq!(sorted_paths(&files), vec!["c.txt", "a.txt", "b.txt"]);
+
+        sort_files(&mut files, Some(FileSortMethod::SizeDesc));
+        assert_eq!(sorted_paths(&files), vec!["a.txt", "b.txt", "c.txt"]);
+    }
+
+    #[test]
+    fn test_sort_files_date_ties_by_path() {
+        let mut files = vec![
+            json!({"path": "b.txt", "mod_time": 100}),
+            json!({"path": "a.txt", "mod_time": 100}),
+        ];
+        sort_files(&mut files, Some(FileSortMethod::DateDesc));
+        assert_eq!(sorted_paths(&files), vec!["a.txt", "b.txt"]);
+    }
+
+    #[test]
+    fn test_sort_method_from_str() {
+        assert_eq!(
+            "tokens_desc".parse::<FileSortMethod>(),
+            Ok(FileSortMethod::TokensDesc)
+        );
+        assert_eq!(
+            "size_asc".parse::<FileSortMethod>(),
+            Ok(FileSortMethod::SizeAsc)
+        );
+        assert!("tokens".parse::<FileSortMethod>().is_err());
+    }
+
     #[test]
     fn test_sort_files_none() {
         // When sort method is None, the original order should be preserved.
@@ -125,6 +203,22 @@ mod tests {
         assert_eq!(files, original_files);
     }
 
+    #[test]
+    fn test_prioritize_files() {
+        let mut files = vec![
+            json!({"path": "src/a.rs"}),
+            json!({"path": "README.md"}),
+            json!({"path": "src/b.rs"}),
+            json!({"path": "docs/guide.md"}),
+        ];
+        prioritize_files(&mut files, &build_globset(&["*.md".to_string()]));
+        let paths: Vec<&str> = files.iter().map(|f| f["path"].as_str().unwrap()).collect();
+        assert_eq!(
+            paths,
+            ["README.md", "docs/guide.md", "src/a.rs", "src/b.rs"]
+        );
+    }
+
     #[test]
     fn test_sort_tree_name_asc() {
         // Build a simple tree with unsorted leaf nodes.
@@ -190,4 +284,28 @@ mod tests {
 
         assert_eq!(original, after);
     }
+
+    #[test]
+    fn test_compare_paths_by_component_and_byte() {
+        use std::cmp::Ordering;
+        assert_eq!(
+            compare_paths("src/main.rs", "src-tauri/main.rs"),
+            Ordering::Less
+        );
+        assert_eq!(compare_paths("src/main.rs", "src.rs"), Ordering::Less);
+        assert_eq!(compare_paths("Zeta.md", "alpha.md"), Ordering::Less);
+        assert_eq!(compare_paths("10.txt", "9.txt"), Ordering::Less);
+        assert_eq!(compare_paths("a/b.rs", "a/b.rs"), Ordering::Equal);
+
+        let mut files = vec![
+            json!({"path": "src.rs", "size": 1}),
+            json!({"path": "src-tauri/main.rs", "size": 1}),
+            json!({"path": "src/main.rs", "size": 1}),
+        ];
+        sort_files(&mut files, Some(FileSortMethod::SizeDesc));
+        assert_eq!(
+            sorted_paths(&files),
+            vec!["src/main.rs", "src-tauri/main.rs", "src.rs"]
+        );
+    }
 }
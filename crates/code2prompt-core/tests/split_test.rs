@@ -0,0 +1,262 @@
+use code2prompt_core::configuration::Code2PromptConfig;
+use code2prompt_core::session::Code2PromptSession;
+use code2prompt_core::split::{chunk_files, split_files, ChunkSize, ROOT_DIRECTORY_NAME};
+use serde_json::{json, Value};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn file(path: &str, token_count: usize) -> Value {
+        json!({ "path": path, "token_count": token_count })
+    }
+
+    fn paths(groups: &[Vec<Value>]) -> Vec<Vec<&str>> {
+        groups
+            .iter()
+            .map(|group| group.iter().map(|f| f["path"].as_str().unwrap()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_split_files_at_file_boundaries() {
+        let files = vec![file("a", 40), file("b", 50), file("c", 30), file("d", 20)];
+        let groups = split_files(&files, 100);
+        assert_eq!(paths(&groups), vec![vec!["a", "b"], vec!["c", "d"]]);
+    }
+
+    #[test]
+    fn test_split_files_puts_an_oversized_file_in_its_own_group() {
+        let files = vec![file("a", 30), file("big", 500), file("b", 30)];
+        let groups = split_files(&files, 100);
+        assert_eq!(paths(&groups), vec![vec!["a"], vec!["big"], vec!["b"]]);
+    }
+
+    #[test]
+    fn test_split_files_without_files() {
+        let groups = split_files(&[], 100);
+        assert_eq!(groups, vec![Vec::<Value>::new()]);
+    }
+
+    #[test]
+    fn test_split_prompt_parts_fit_in_the_context_window() {
+        let dir = tempdir().unwrap();
+        for name in ["a", "b", "c", "d"] {
+            fs::write(
+                dir.path().join(format!("{}.txt", name)),
+                format!("{} ", name).repeat(200),
+            )
+            .unwrap();
+        }
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        let rendered = session.generate_prompt().unwrap();
+
+        let context_window = rendered.token_count / 2;
+        let parts = session.split_prompt(context_window).unwrap();
+        assert!(parts.len() > 1);
+        let mut files: Vec<String> = Vec::new();
+        for (index, part) in parts.iter().enumerate() {
+            assert_eq!(part.index, index + 1);
+            assert_eq!(part.count, parts.len());
+            assert!(part.token_count <= context_window);
+            assert!(!part.oversized);
+            assert!(part
+                .prompt
+                .contains(&format!("Part {} of {}", part.index, part.count)));
+            assert!(part.prompt.contains("Source Tree:"));
+            files.extend(part.files.iter().cloned());
+        }
+        files.sort();
+        assert_eq!(files, vec!["a.txt", "b.txt", "c.txt", "d.txt"]);
+    }
+
+    #[test]
+    fn test_split_prompt_oversized_file() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "small").unwrap();
+        fs::write(dir.path().join("b.txt"), "big ".repeat(2000)).unwrap();
+        fs::write(dir.path().join("c.txt"), "small").unwrap();
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        session.generate_prompt().unwrap();
+
+        let parts = session.split_prompt(1000).unwrap();
+        let oversized: Vec<_> = parts.iter().filter(|part| part.oversized).collect();
+        assert_eq!(oversized.len(), 1);
+        assert_eq!(oversized[0].files, vec!["b.txt"]);
+        assert!(oversized[0].token_count > 1000);
+        for part in parts.iter().filter(|part| !part.oversized) {
+            assert!(part.token_count <= 1000);
+            assert!(!part.files.contains(&"b.txt".to_string()));
+        }
+    }
+
+    #[test]
+    fn test_parse_chunk_size() {
+        assert_eq!(
+            "30000".parse::<ChunkSize>().unwrap(),
+            ChunkSize::Tokens(30000)
+        );
+        assert_eq!(
+            "30k".parse::<ChunkSize>().unwrap(),
+            ChunkSize::Tokens(30000)
+        );
+        assert_eq!(
+            "30000t".parse::<ChunkSize>().unwrap(),
+            ChunkSize::Tokens(30000)
+        );
+        assert_eq!(
+            "120000c".parse::<ChunkSize>().unwrap(),
+            ChunkSize::Chars(120000)
+        );
+        assert_eq!(
+            "120kc".parse::<ChunkSize>().unwrap(),
+            ChunkSize::Chars(120000)
+        );
+        assert!("".parse::<ChunkSize>().is_err());
+        assert!("0".parse::<ChunkSize>().is_err());
+        assert!("30x".parse::<ChunkSize>().is_err());
+        assert!("18446744073709552k".parse::<ChunkSize>().is_err());
+        assert_eq!(ChunkSize::Chars(8000).to_string(), "8000 characters");
+    }
+
+    #[test]
+    fn test_chunk_files_cuts_an_oversized_file_between_its_lines() {
+        let code = (1..=30)
+            .map(|n| format!("line {}", n))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let files = vec![
+            json!({ "path": "a", "code": "a" }),
+            json!({ "path": "big", "code": format!("```\n{}\n```", code) }),
+            json!({ "path": "b", "code": "b" }),
+        ];
+        let size = |file: &Value| file["code"].as_str().unwrap().len();
+        let chunks = chunk_files(&files, 1, 120, false, size, |text: &str| text.len());
+        assert_eq!(chunks.first().map(Vec::len), Some(1));
+        assert_eq!(chunks[0][0]["path"], "a");
+        let pieces: Vec<&Value> = chunks[1..chunks.len() - 1].iter().map(|c| &c[0]).collect();
+        assert!(pieces.len() > 1);
+        for (index, piece) in pieces.iter().enumerate() {
+            let code = piece["code"].as_str().unwrap();
+            assert_eq!(piece["path"], "big");
+            assert_eq!(piece["continued"]["piece"], index + 1);
+            assert_eq!(piece["continued"]["pieces"], pieces.len());
+            assert!(code.starts_with("```\n") && code.ends_with("\n```"));
+            assert!(code.len() <= 120);
+            if index > 0 {
+                assert!(code.contains(&format!("... (continued from part {})", index + 1)));
+            }
+            if index + 1 < pieces.len() {
+                assert!(code.contains(&format!("... (continued in part {})", index + 3)));
+            }
+        }
+        assert!(pieces[0]["code"].as_str().unwrap().contains("line 1\n"));
+        assert!(pieces.last().unwrap()["code"]
+            .as_str()
+            .unwrap()
+            .contains("line 30\n"));
+        assert_eq!(chunks.last().unwrap()[0]["path"], "b");
+    }
+
+    #[test]
+    fn test_chunk_prompt_writes_the_tree_in_the_first_chunk_only() {
+        let dir = tempdir().unwrap();
+        for name in ["a", "b", "c", "d"] {
+            fs::write(
+                dir.path().join(format!("{}.txt", name)),
+                format!("{} ", name).repeat(200),
+            )
+            .unwrap();
+        }
+        fs::write(
+            dir.path().join("e.txt"),
+            (1..=400)
+                .map(|n| format!("line {}\n", n))
+                .collect::<String>(),
+        )
+        .unwrap();
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        session.generate_prompt().unwrap();
+
+        let parts = session.chunk_prompt(ChunkSize::Chars(1500), false).unwrap();
+        assert!(parts.len() > 4);
+        for part in &parts {
+            assert!(part.prompt.chars().count() <= 1500);
+            assert_eq!(part.prompt.contains("Source Tree:"), part.index == 1);
+            if part.index > 1 {
+                assert!(part.prompt.contains(&format!(
+                    "Part {} of {}, see part 1 for the tree",
+                    part.index, part.count
+                )));
+            }
+        }
+        let pieces: Vec<_> = parts
+            .iter()
+            .filter(|part| part.files == ["e.txt"])
+            .collect();
+        assert!(pieces.len() > 1);
+        assert!(pieces.iter().all(|part| part.oversized));
+        assert!(pieces[0].prompt.contains("... (continued in part"));
+
+        let parts = session.chunk_prompt(ChunkSize::Chars(1500), true).unwrap();
+        assert!(parts
+            .iter()
+            .all(|part| part.prompt.contains("Source Tree:")));
+        assert!(!parts[1].prompt.contains("see part 1"));
+    }
+
+    #[test]
+    fn test_split_by_directory() {
+        let dir = tempdir().unwrap();
+        for path in [
+            "services/a/main.rs",
+            "services/b/main.rs",
+            "libs/core/lib.rs",
+            "docs/notes.txt",
+        ] {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, format!("content of {}", path.display())).unwrap();
+        }
+        fs::write(dir.path().join("README.md"), "readme").unwrap();
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .include_patterns(vec!["*.rs".to_string(), "*.md".to_string()])
+            .build()
+            .unwrap();
+        let mut session = Code2PromptSession::new(config);
+        session.generate_prompt().unwrap();
+
+        let prompts = session.split_by_directory().unwrap();
+        let names: Vec<&str> = prompts.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec![ROOT_DIRECTORY_NAME, "libs", "services"]);
+        assert_eq!(prompts[0].files, vec!["README.md"]);
+        assert_eq!(prompts[1].files, vec!["libs/core/lib.rs"]);
+        let mut services_files = prompts[2].files.clone();
+        services_files.sort();
+        assert_eq!(
+            services_files,
+            vec!["services/a/main.rs", "services/b/main.rs"]
+        );
+        let services = &prompts[2].prompt;
+        assert!(services.contains("Source Tree:"));
+        assert!(services.contains("└── services"));
+        assert!(!services.contains("libs"));
+        assert!(!services.contains("README.md"));
+        assert!(prompts.iter().all(|prompt| prompt.token_count > 0));
+    }
+}
@@ -0,0 +1,250 @@
+use code2prompt_core::configuration::Code2PromptConfig;
+use code2prompt_core::session::Code2PromptSession;
+use code2prompt_core::template_library::{
+    builtin_template, find_template, list_templates, template_description, template_dirs,
+    TemplateSection, TemplateSource, BUILTIN_TEMPLATES, TEMPLATES_DIR,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+    use std::fs;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    fn write_template(dir: &Path, name: &str, content: &str) {
+        let templates_dir = dir.join(TEMPLATES_DIR);
+        fs::create_dir_all(&templates_dir).unwrap();
+        fs::write(templates_dir.join(name), content).unwrap();
+    }
+
+    #[test]
+    fn test_template_description() {
+        assert_eq!(
+            template_description("{{!-- Review the changes --}}\nBody"),
+            "Review the changes"
+        );
+        assert_eq!(
+            template_description("\n{{! Short comment }}\n"),
+            "Short comment"
+        );
+        assert_eq!(
+            template_description("Project Path: {{ absolute_code_path }}\n"),
+            "Project Path: {{ absolute_code_path }}"
+        );
+        assert_eq!(
+            template_description("+++\ndiff = true\n+++\n{{!-- Review the diff --}}\n"),
+            "Review the diff"
+        );
+        assert_eq!(template_description(""), "");
+    }
+
+    #[test]
+    fn test_template_dirs_up_to_repository_root() {
+        let dir = tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        let sub = repo.join("sub");
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        fs::create_dir_all(&sub).unwrap();
+        write_template(dir.path(), "outside.hbs", "Outside");
+        write_template(&repo, "root.hbs", "Root");
+        write_template(&sub, "sub.hbs", "Sub");
+
+        let dirs = template_dirs(&sub);
+        let expected: Vec<_> = [&sub, &repo]
+            .iter()
+            .map(|dir| dir.canonicalize().unwrap().join(TEMPLATES_DIR))
+            .collect();
+        assert_eq!(dirs, expected);
+    }
+
+    #[test]
+    fn test_template_dirs_outside_repository() {
+        let dir = tempdir().unwrap();
+        let sub = dir.path().join("sub");
+        fs::create_dir_all(&sub).unwrap();
+        write_template(dir.path(), "parent.hbs", "Parent");
+
+        assert!(template_dirs(&sub).is_empty());
+        assert_eq!(template_dirs(dir.path()).len(), 1);
+    }
+
+    #[test]
+    fn test_find_template_nearest_first() {
+        let dir = tempdir().unwrap();
+        let sub = dir.path().join("sub");
+        fs::create_dir_all(dir.path().join(".git")).unwrap();
+        fs::create_dir_all(&sub).unwrap();
+        write_template(dir.path(), "review.hbs", "Root review");
+        write_template(&sub, "review.hbs", "{{!-- Sub review --}}\nReview");
+
+        let template = find_template("review", &sub).unwrap().unwrap();
+        assert_eq!(template.info.name, "review");
+        assert_eq!(template.info.description, "Sub review");
+        assert_eq!(template.content, "{{!-- Sub review --}}\nReview");
+        assert!(!template.info.overrides_builtin);
+
+        let template = find_template("review.hbs", dir.path()).unwrap().unwrap();
+        assert_eq!(template.content, "Root review");
+        assert!(find_template("missing", &sub).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_find_template_prefers_repository_over_builtin() {
+        let dir = tempdir().unwrap();
+        let builtin = find_template("fix-bugs", dir.path()).unwrap().unwrap();
+        assert_eq!(builtin.info.source, TemplateSource::Builtin);
+        assert!(!builtin.content.is_empty());
+
+        write_template(dir.path(), "fix-bugs.hbs", "Our own bug template");
+        let template = find_template("fix-bugs", dir.path()).unwrap().unwrap();
+        assert_eq!(template.content, "Our own bug template");
+        assert!(template.info.overrides_builtin);
+        assert!(matches!(
+            template.info.source,
+            TemplateSource::Repository(_)
+        ));
+    }
+
+    #[test]
+    fn test_list_templates() {
+        let dir = tempdir().unwrap();
+        write_template(dir.path(), "review.hbs", "{{!-- Review the changes --}}");
+        write_template(dir.path(), "fix-bugs.hbs", "Our own bug template");
+        write_template(dir.path(), "notes.txt", "Not a template");
+
+        let templates = list_templates(dir.path()).unwrap();
+        let names: Vec<&str> = templates
+            .iter()
+            .map(|template| template.name.as_str())
+            .collect();
+        assert_eq!(&names[..2], ["fix-bugs", "review"]);
+        assert!(!names.contains(&"notes"));
+        assert_eq!(names.iter().filter(|name| **name == "fix-bugs").count(), 1);
+        assert!(templates[0].overrides_builtin);
+        assert_eq!(templates[1].description, "Review the changes");
+        assert!(templates[2..]
+            .iter()
+            .all(|template| template.source == TemplateSource::Builtin));
+        assert!(names.contains(&"document-the-code"));
+    }
+
+    #[test]
+    fn test_builtin_registry() {
+        let names: Vec<&str> = BUILTIN_TEMPLATES
+            .iter()
+            .map(|builtin| builtin.name)
+            .collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(names, sorted);
+        for name in [
+            "explain-codebase",
+            "pr-description",
+            "security-review",
+            "write-tests",
+        ] {
+            assert!(names.contains(&name), "{}", name);
+        }
+        assert_eq!(
+            builtin_template("pr-description").unwrap().sections,
+            [TemplateSection::GitDiff, TemplateSection::GitLog]
+        );
+        assert!(builtin_template("explain-codebase")
+            .unwrap()
+            .sections
+            .is_empty());
+        assert!(builtin_template("missing").is_none());
+
+        let dir = tempdir().unwrap();
+        let template = find_template("pr-description", dir.path())
+            .unwrap()
+            .unwrap();
+        assert_eq!(template.info.sections.len(), 2);
+    }
+
+    #[test]
+    fn test_builtin_templates_render() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+        for builtin in BUILTIN_TEMPLATES {
+            let config = Code2PromptConfig::builder()
+                .path(dir.path().to_path_buf())
+                .template_str(builtin.content.to_string())
+                .template_name(builtin.name.to_string())
+                .build()
+                .unwrap();
+            let mut session = Code2PromptSession::new(config);
+            let rendered = session.generate_prompt().unwrap();
+            assert!(!rendered.prompt.is_empty(), "{}", builtin.name);
+        }
+    }
+
+    /// Returns the texts of the code blocks of a Markdown document, and the text outside them.
+    fn markdown_code_blocks(markdown: &str) -> (Vec<String>, String) {
+        let mut blocks = Vec::new();
+        let mut prose = String::new();
+        let mut block: Option<String> = None;
+        for event in Parser::new(markdown) {
+            match event {
+                Event::Start(Tag::CodeBlock(_)) => block = Some(String::new()),
+                Event::End(TagEnd::CodeBlock) => blocks.extend(block.take()),
+                Event::Text(text) => match &mut block {
+                    Some(block) => block.push_str(&text),
+                    None => prose.push_str(&text),
+                },
+                _ => {}
+            }
+        }
+        (blocks, prose)
+    }
+
+    #[test]
+    fn test_builtin_templates_keep_embedded_fences_in_their_blocks() {
+        let dir = tempdir().unwrap();
+        let readme =
+            "# Usage\n\n```rust\nlet inner = 1;\n```\n\n````\n```\nnested = 2\n```\n````\n";
+        fs::write(dir.path().join("README.md"), readme).unwrap();
+        // The context lines of a diff start with a space, so a fence among them closes a block
+        let diff = "--- a/README.md\n+++ b/README.md\n@@ -1,3 +1,3 @@\n ```\n-diff_inner = 2\n+diff_inner = 3\n ```";
+        // The XML templates don't render Markdown blocks
+        for builtin in BUILTIN_TEMPLATES
+            .iter()
+            .filter(|builtin| !builtin.content.contains("<files>"))
+        {
+            let config = Code2PromptConfig::builder()
+                .path(dir.path().to_path_buf())
+                .template_str(builtin.content.to_string())
+                .template_name(builtin.name.to_string())
+                .build()
+                .unwrap();
+            let mut session = Code2PromptSession::new(config);
+            session.load_codebase().unwrap();
+            session.data.git_diff = Some(diff.to_string());
+            session.data.git_diff_branch = Some(diff.to_string());
+            let rendered = session
+                .render_prompt(&session.build_template_data())
+                .unwrap();
+
+            let (blocks, prose) = markdown_code_blocks(&rendered.prompt);
+            for inner in ["let inner = 1;", "nested = 2", "diff_inner = 3"] {
+                assert!(
+                    !prose.contains(inner),
+                    "{}: {}",
+                    builtin.name,
+                    rendered.prompt
+                );
+            }
+            if rendered.prompt.contains("nested = 2") {
+                assert!(
+                    blocks.iter().any(|block| block.contains(readme)),
+                    "{}: {}",
+                    builtin.name,
+                    rendered.prompt
+                );
+            }
+        }
+    }
+}
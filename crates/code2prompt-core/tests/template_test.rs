@@ -1,4 +1,13 @@
-use code2prompt_core::template::{extract_undefined_variables, handlebars_setup, render_template};
+use code2prompt_core::template::{
+    cdata, check_date_format, check_partials, elide_file_contents, extract_undefined_variables,
+    extract_user_variables, file_format_tokens, handlebars_setup, heading_anchors, heading_slug,
+    read_partials_dir, register_format_helpers, register_include_helper, register_partials,
+    render_template, replace_tokens, resolve_file_format, strip_line_numbers,
+    validate_file_template, validate_template, xml_escape, FormatOptions, IncludeOptions,
+    UnknownKey, CONTEXT_PREVIEW_CHARS, MAX_INCLUDE_DEPTH, PARTIALS_DIR,
+};
+use code2prompt_core::template_library::BUILTIN_TEMPLATES;
+use std::collections::HashMap;
 
 #[cfg(test)]
 mod tests {
@@ -36,6 +45,15 @@ mod tests {
         assert_eq!(variables, vec!["name", "language", "framework"]);
     }
 
+    #[test]
+    fn test_extract_user_variables() {
+        let template_str = "Fix {{user.ticket}}: {{ user.instruction }}\n\
+            {{#if user.ticket}}{{@root.user.reviewer}}{{/if}} {{user.ticket}} user.outside \
+            {{files.user.nested}} {{superuser.name}}";
+        let variables = extract_user_variables(template_str);
+        assert_eq!(variables, vec!["ticket", "instruction", "reviewer"]);
+    }
+
     #[test]
     fn test_render_template() {
         let template_str = "{{greeting}}, {{name}}!";
@@ -49,4 +67,643 @@ mod tests {
             Err(e) => panic!("Template rendering failed: {}", e),
         }
     }
+
+    fn render(template_str: &str, data: serde_json::Value) -> String {
+        let handlebars = handlebars_setup(template_str, "helpers").unwrap();
+        render_template(&handlebars, "helpers", &data).unwrap()
+    }
+
+    #[test]
+    fn test_truncate_lines_helper() {
+        let data = json!({ "code": "line 1\nline 2\nline 3\nline 4" });
+        assert_eq!(
+            render("{{truncate_lines code 2}}", data.clone()),
+            "line 1\nline 2"
+        );
+        assert_eq!(
+            render("{{truncate_lines code 10}}", data),
+            "line 1\nline 2\nline 3\nline 4"
+        );
+    }
+
+    #[test]
+    fn test_strip_prefix_helper() {
+        let data = json!({ "path": "src/lib.rs" });
+        assert_eq!(
+            render("{{strip_prefix path \"src/\"}}", data.clone()),
+            "lib.rs"
+        );
+        assert_eq!(
+            render("{{strip_prefix path \"tests/\"}}", data),
+            "src/lib.rs"
+        );
+    }
+
+    #[test]
+    fn test_basename_helper() {
+        let data = json!({ "path": "src/nested/lib.rs" });
+        assert_eq!(render("{{basename path}}", data), "lib.rs");
+    }
+
+    #[test]
+    fn test_extension_helper() {
+        assert_eq!(
+            render("{{extension path}}", json!({ "path": "src/lib.rs" })),
+            "rs"
+        );
+        assert_eq!(
+            render("[{{extension path}}]", json!({ "path": "Makefile" })),
+            "[]"
+        );
+    }
+
+    #[test]
+    fn test_upper_and_lower_helpers() {
+        let data = json!({ "language": "Rust" });
+        assert_eq!(
+            render("{{upper language}} {{lower language}}", data),
+            "RUST rust"
+        );
+    }
+
+    #[test]
+    fn test_indent_helper() {
+        let data = json!({ "code": "fn main() {\n\n}" });
+        assert_eq!(
+            render("[{{indent code 4}}]", data),
+            "[    fn main() {\n\n    }]"
+        );
+    }
+
+    #[test]
+    fn test_json_helper() {
+        let data = json!({ "file": { "path": "a.rs", "lines": 3 } });
+        assert_eq!(
+            render("{{json file}}", data.clone()),
+            r#"{"lines":3,"path":"a.rs"}"#
+        );
+        assert_eq!(
+            render("{{json file pretty=true}}", data),
+            "{\n  \"lines\": 3,\n  \"path\": \"a.rs\"\n}"
+        );
+    }
+
+    #[test]
+    fn test_date_helper() {
+        let template_str = "{{date timestamp \"%Y-%m-%d\"}} | {{date timestamp}} | {{date}}";
+        let handlebars = handlebars_setup(template_str, "date").unwrap();
+        let data = json!({ "timestamp": 1700000000, "date": "the date field" });
+        let rendered = render_template(&handlebars, "date", &data).unwrap();
+        assert_eq!(rendered, "2023-11-14 | 2023-11-14 22:13 | the date field");
+
+        let handlebars = handlebars_setup("{{date \"soon\"}}", "invalid").unwrap();
+        assert!(render_template(&handlebars, "invalid", &json!({})).is_err());
+    }
+
+    #[test]
+    fn test_xml_helpers() {
+        let data = json!({
+            "files": [{ "path": "a&b.rs" }, { "path": "<c>.rs" }],
+            "code": "if a]]>b { \"</document_contents>\" }"
+        });
+        let rendered = render(
+            "{{#each files}}{{add @index 1}}={{{xml_escape path}}} {{/each}}{{{cdata code}}}",
+            data,
+        );
+        assert_eq!(
+            rendered,
+            "1=a&amp;b.rs 2=&lt;c&gt;.rs \
+             <![CDATA[if a]]]]><![CDATA[>b { \"</document_contents>\" }]]>"
+        );
+        assert_eq!(cdata("]]>"), "<![CDATA[]]]]><![CDATA[>]]>");
+        assert_eq!(xml_escape("'\"'"), "&apos;&quot;&apos;");
+    }
+
+    #[test]
+    fn test_format_date_helper() {
+        let template_str = "{{format_date timestamp}} | {{format_date mtime \"%d %B %Y\"}} | \
+                            {{format_date commit \"%H:%M %z\"}} | {{format_date day}}|{{format_date missing}}";
+        let handlebars = handlebars_setup(template_str, "format_date").unwrap();
+        let data = json!({
+            "timestamp": 1700000000,
+            "mtime": "2024-03-05T10:20:30+01:00",
+            "commit": "2024-01-02 08:09:10 +0200",
+            "day": "2024-01-02"
+        });
+        let rendered = render_template(&handlebars, "format_date", &data).unwrap();
+        assert_eq!(
+            rendered,
+            "2023-11-14 | 05 March 2024 | 08:09 +0200 | 2024-01-02|"
+        );
+
+        let mut handlebars = handlebars_setup("{{format_date day}}", "default").unwrap();
+        let options = FormatOptions {
+            date_format: Some("%d/%m/%Y".to_string()),
+            locale: None,
+        };
+        register_format_helpers(&mut handlebars, &options).unwrap();
+        let rendered = render_template(&handlebars, "default", &data).unwrap();
+        assert_eq!(rendered, "02/01/2024");
+
+        let mut handlebars = handlebars_setup("{{format_date \"soon\"}}", "invalid").unwrap();
+        assert!(render_template(&handlebars, "invalid", &json!({})).is_err());
+        assert!(check_date_format("%Y-%m-%d").is_ok());
+        assert!(check_date_format("%Q").is_err());
+        let options = FormatOptions {
+            date_format: Some("%".to_string()),
+            locale: None,
+        };
+        assert!(register_format_helpers(&mut handlebars, &options).is_err());
+    }
+
+    #[test]
+    fn test_format_num_helper() {
+        let data = json!({ "tokens": 1234567, "small": 12, "ratio": 12345.5, "negative": -0.5 });
+        let template_str = "{{format_num tokens}} {{format_num small}} {{format_num ratio}} \
+                            {{format_num negative}}";
+        let rendered = render(template_str, data.clone());
+        assert_eq!(rendered, "1,234,567 12 12,345.5 -0.5");
+
+        let mut handlebars = handlebars_setup(template_str, "locale").unwrap();
+        let options = FormatOptions {
+            date_format: None,
+            locale: Some("de".to_string()),
+        };
+        register_format_helpers(&mut handlebars, &options).unwrap();
+        let rendered = render_template(&handlebars, "locale", &data).unwrap();
+        assert_eq!(rendered, "1.234.567 12 12.345,5 -0,5");
+
+        let options = FormatOptions {
+            date_format: None,
+            locale: Some("klingon".to_string()),
+        };
+        assert!(register_format_helpers(&mut handlebars, &options).is_err());
+        let handlebars = handlebars_setup("{{format_num path}}", "invalid").unwrap();
+        assert!(render_template(&handlebars, "invalid", &json!({ "path": "a.rs" })).is_err());
+    }
+
+    #[test]
+    fn test_validate_template_date_formats() {
+        let template = "+++\ndiff = true\n+++\n{{format_date generated_at \"%Y\"}}\n\
+                        {{#each files}}{{format_date mtime \"%d %Q\"}}{{/each}}";
+        let error = validate_template(template, "dates", &HashMap::new()).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("Invalid template dates at line 5, column 18: Invalid date format"),
+            "{}",
+            error
+        );
+        assert!(validate_template("{{date 0 \"%Y\"}}", "valid", &HashMap::new()).is_ok());
+        let error = validate_file_template(
+            "{{date (lookup this \"t\") \"%\"}}",
+            "file",
+            &HashMap::new(),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("line 1, column 3"), "{}", error);
+    }
+
+    #[test]
+    fn test_helpers_in_each_block() {
+        let data = json!({ "files": [{ "path": "src/a.rs" }, { "path": "src/b.py" }] });
+        let rendered = render(
+            "{{#each files}}{{basename path}}:{{upper (extension path)}} {{/each}}",
+            data,
+        );
+        assert_eq!(rendered, "a.rs:RS b.py:PY");
+    }
+
+    #[test]
+    fn test_unknown_helper_is_an_error() {
+        let handlebars = handlebars_setup("{{shout name}}", "unknown").unwrap();
+        let error = render_template(&handlebars, "unknown", &json!({ "name": "x" })).unwrap_err();
+        assert!(error.to_string().contains("shout"));
+    }
+
+    #[test]
+    fn test_register_and_check_partials() {
+        let template_str = "{{#each items}}{{> item}}{{/each}}";
+        let mut handlebars = handlebars_setup(template_str, "list").unwrap();
+        assert!(check_partials(&handlebars, "list", [template_str])
+            .unwrap_err()
+            .to_string()
+            .contains("template list uses the partial item"));
+
+        let partials =
+            std::collections::HashMap::from([("item".to_string(), "[{{this}}]".to_string())]);
+        register_partials(&mut handlebars, "list", &partials).unwrap();
+        check_partials(&handlebars, "list", [template_str]).unwrap();
+        let rendered = render_template(&handlebars, "list", &json!({"items": [1, 2]})).unwrap();
+        assert_eq!(rendered, "[1][2]");
+
+        let partials = std::collections::HashMap::from([("list".to_string(), String::new())]);
+        assert!(register_partials(&mut handlebars, "list", &partials).is_err());
+    }
+
+    #[test]
+    fn test_check_partials_skips_blocks_and_inline_partials() {
+        let template_str =
+            "{{#*inline \"row\"}}row{{/inline}}{{> row}}{{#> optional}}fallback{{/optional}}";
+        let handlebars = handlebars_setup(template_str, "main").unwrap();
+        check_partials(&handlebars, "main", [template_str]).unwrap();
+        let rendered = render_template(&handlebars, "main", &json!({})).unwrap();
+        assert_eq!(rendered, "rowfallback");
+    }
+
+    #[test]
+    fn test_read_partials_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let template_path = dir.path().join("main.hbs");
+        assert!(read_partials_dir(&template_path).unwrap().is_empty());
+
+        let partials_dir = dir.path().join(PARTIALS_DIR);
+        std::fs::create_dir(&partials_dir).unwrap();
+        std::fs::write(partials_dir.join("file_block.hbs"), "{{path}}").unwrap();
+        std::fs::write(partials_dir.join("notes.txt"), "not a partial").unwrap();
+        let partials = read_partials_dir(&template_path).unwrap();
+        assert_eq!(partials.len(), 1);
+        assert_eq!(partials["file_block"], "{{path}}");
+    }
+
+    #[test]
+    fn test_validate_template_syntax_error() {
+        let error = validate_template(
+            "Files:\n{{#each file}}\n{{path}}\n",
+            "review",
+            &HashMap::new(),
+        )
+        .unwrap_err()
+        .to_string();
+        assert_eq!(
+            error,
+            "Invalid template review at line 2, column 1: the block {{#each}} isn't closed"
+        );
+
+        // The lines of the front-matter are counted
+        let error = validate_template(
+            "+++\ndiff = true\n+++\n{{#if}}\n",
+            "review",
+            &HashMap::new(),
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains("at line 4"), "{}", error);
+
+        let partials = HashMap::from([("block".to_string(), "{{#if code}}".to_string())]);
+        let error = validate_template("{{> block}}", "review", &partials)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.starts_with("Invalid template partial block"),
+            "{}",
+            error
+        );
+
+        let error = validate_template("{{> missing}}", "review", &HashMap::new())
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("partial missing"), "{}", error);
+    }
+
+    #[test]
+    fn test_validate_template_unknown_keys() {
+        let template = "{{!-- {{ignored}} --}}\n\
+            Project: {{absolute_code_path}} {{projet}}\n\
+            {{#each file}}{{path}}{{/each}}\n\
+            {{#each files as |entry|}}{{entry.path}} {{@index}} {{../files.length}} {{@root.trees}}{{/each}}\n\
+            {{#if flags.has_diff}}{{upper git_diff}}{{else if (eq git_log \"x\")}}{{/if}}\n\
+            {{json stats pretty=true}} {{truncate_lines source_tree 20}} {{user.ticket}} {{this.git_diff}}";
+        let unknown = validate_template(template, "review", &HashMap::new()).unwrap();
+        let key = |key: &str, line, column| UnknownKey {
+            key: key.to_string(),
+            line,
+            column,
+        };
+        assert_eq!(
+            unknown,
+            [
+                key("projet", 2, 35),
+                key("file", 3, 9),
+                key("trees", 4, 75),
+                key("git_log", 5, 55),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_default_and_builtin_templates() {
+        for template in [
+            include_str!("../src/default_template_md.hbs"),
+            include_str!("../src/default_template_xml.hbs"),
+        ] {
+            assert_eq!(
+                validate_template(template, "default", &HashMap::new()).unwrap(),
+                []
+            );
+        }
+        let partials = HashMap::from([(
+            "file_block".to_string(),
+            include_str!("../src/default_file_block_md.hbs").to_string(),
+        )]);
+        for builtin in BUILTIN_TEMPLATES {
+            let unknown = validate_template(builtin.content, builtin.name, &partials).unwrap();
+            let variables = extract_undefined_variables(builtin.content);
+            assert!(
+                unknown
+                    .iter()
+                    .all(|unknown| variables.contains(&unknown.key)),
+                "{}: {:?}",
+                builtin.name,
+                unknown
+            );
+        }
+    }
+
+    #[test]
+    fn test_codeblock_helper() {
+        let data = json!({
+            "files": [
+                { "path": "src/main.rs", "content": "fn main() {}" },
+                { "path": "README.md", "content": "# Usage\n```sh\ncargo run\n```" }
+            ]
+        });
+        let rendered = render("{{#each files}}{{codeblock path content}}\n{{/each}}", data);
+        assert_eq!(
+            rendered,
+            "```rust\nfn main() {}\n```\n````markdown\n# Usage\n```sh\ncargo run\n```\n````"
+        );
+    }
+
+    #[test]
+    fn test_repeat_helper() {
+        let data = json!({ "depth": 2 });
+        assert_eq!(render("[{{repeat \"  \" depth}}]", data), "[    ]");
+        assert_eq!(render("[{{repeat \"-\" 0}}]", json!({})), "[]");
+    }
+
+    /// Renders a template with the `include` helper reading from a base directory.
+    fn render_with_includes(
+        template_str: &str,
+        options: &IncludeOptions,
+        data: serde_json::Value,
+    ) -> anyhow::Result<String> {
+        let mut handlebars = handlebars_setup(template_str, "includes").unwrap();
+        register_include_helper(&mut handlebars, options);
+        render_template(&handlebars, "includes", &data)
+    }
+
+    #[test]
+    fn test_include_helper() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("shared")).unwrap();
+        std::fs::write(dir.path().join("style.md"), "Style of {{name}}\n").unwrap();
+        std::fs::write(
+            dir.path().join("shared/rules.md"),
+            "Rules:\n{{include \"details.md\"}}\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("shared/details.md"), "- Be brief\n").unwrap();
+        let options = IncludeOptions {
+            base_dir: Some(dir.path().to_path_buf()),
+            ..IncludeOptions::default()
+        };
+
+        let rendered = render_with_includes(
+            "{{include \"style.md\"}}\n{{include \"shared/rules.md\"}}",
+            &options,
+            json!({ "name": "code2prompt" }),
+        )
+        .unwrap();
+        assert_eq!(rendered, "Style of code2prompt\nRules:\n- Be brief");
+
+        let error = render_with_includes("{{include \"missing.md\"}}", &options, json!({}))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("the file missing.md doesn't exist in"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_include_helper_roots() {
+        let dir = tempfile::tempdir().unwrap();
+        let templates = dir.path().join("templates");
+        let docs = dir.path().join("docs");
+        std::fs::create_dir_all(&templates).unwrap();
+        std::fs::create_dir_all(&docs).unwrap();
+        std::fs::write(docs.join("guide.md"), "The guide").unwrap();
+        let template_str = "{{include \"../docs/guide.md\"}}";
+
+        let mut options = IncludeOptions {
+            base_dir: Some(templates),
+            roots: Vec::new(),
+        };
+        let error = render_with_includes(template_str, &options, json!({}))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("is outside the template directory"),
+            "{}",
+            error
+        );
+
+        options.roots.push(docs);
+        let rendered = render_with_includes(template_str, &options, json!({})).unwrap();
+        assert_eq!(rendered, "The guide");
+    }
+
+    #[test]
+    fn test_include_helper_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.md"), "A {{include \"b.md\"}}").unwrap();
+        std::fs::write(dir.path().join("b.md"), "B {{include \"a.md\"}}").unwrap();
+        std::fs::write(dir.path().join("self.md"), "{{include \"self.md\"}}").unwrap();
+        let options = IncludeOptions {
+            base_dir: Some(dir.path().to_path_buf()),
+            ..IncludeOptions::default()
+        };
+
+        let error = render_with_includes("{{include \"a.md\"}}", &options, json!({}))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("the files include each other"), "{}", error);
+        assert!(error.contains("a.md -> "), "{}", error);
+        assert!(render_with_includes("{{include \"self.md\"}}", &options, json!({})).is_err());
+
+        // A file can still be included several times side by side
+        std::fs::write(dir.path().join("leaf.md"), "leaf").unwrap();
+        let rendered = render_with_includes(
+            "{{include \"leaf.md\"}} {{include \"leaf.md\"}}",
+            &options,
+            json!({}),
+        )
+        .unwrap();
+        assert_eq!(rendered, "leaf leaf");
+    }
+
+    #[test]
+    fn test_include_helper_depth() {
+        let dir = tempfile::tempdir().unwrap();
+        for level in 0..=MAX_INCLUDE_DEPTH {
+            let content = format!("{} {{{{include \"{}.md\"}}}}", level, level + 1);
+            std::fs::write(dir.path().join(format!("{}.md", level)), content).unwrap();
+        }
+        let options = IncludeOptions {
+            base_dir: Some(dir.path().to_path_buf()),
+            ..IncludeOptions::default()
+        };
+        let error = render_with_includes("{{include \"0.md\"}}", &options, json!({}))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains(&format!("more than {} levels deep", MAX_INCLUDE_DEPTH)),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_values_are_not_html_escaped() {
+        let code = "fn first(items: Vec<Option<&str>>) -> \"a\" {}";
+        assert_eq!(render("{{content}}", json!({ "content": code })), code);
+        assert_eq!(render("{{{content}}}", json!({ "content": code })), code);
+    }
+
+    #[test]
+    fn test_validate_file_template() {
+        let unknown = validate_file_template(
+            "{{path}} {{lines}} {{@root.source_tree}} {{#if last_commit}}{{last_commit.author}}{{/if}}\n{{upper nope}} {{@root.nope}}",
+            "file",
+            &HashMap::new(),
+        )
+        .unwrap();
+        let keys: Vec<(&str, usize)> = unknown
+            .iter()
+            .map(|unknown| (unknown.key.as_str(), unknown.line))
+            .collect();
+        assert_eq!(keys, [("nope", 2), ("nope", 2)]);
+
+        let example = include_str!("../templates/examples/file_block_stats.hbs");
+        assert!(validate_file_template(example, "example", &HashMap::new())
+            .unwrap()
+            .is_empty());
+
+        let error = validate_file_template("{{#if path}}", "file", &HashMap::new())
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("file at line 1, column 1: the block {{#if}} isn't closed"),
+            "{}",
+            error
+        );
+        let error = validate_file_template("{{> header}}", "file", &HashMap::new())
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("uses the partial header"), "{}", error);
+    }
+
+    #[test]
+    fn test_elide_file_contents() {
+        let mut data = json!({
+            "files": [
+                { "path": "long.rs", "code": "é".repeat(250) },
+                { "path": "short.rs", "code": "fn main() {}" },
+                { "path": "binary.bin" }
+            ],
+            "git_diff": "x".repeat(300)
+        });
+        elide_file_contents(&mut data, CONTEXT_PREVIEW_CHARS);
+        assert_eq!(
+            data["files"][0]["code"],
+            format!(
+                "{}[... 50 more characters elided from this preview ...]",
+                "é".repeat(200)
+            )
+        );
+        assert_eq!(data["files"][1]["code"], "fn main() {}");
+        assert!(data["files"][2].get("code").is_none());
+        assert_eq!(data["git_diff"].as_str().unwrap().len(), 300);
+    }
+
+    #[test]
+    fn test_strip_line_numbers_helper() {
+        let code = "```rust\n 9 | fn main() {\n10 |     let a = 1 | 2;\n... (1 lines omitted)\n```";
+        assert_eq!(
+            strip_line_numbers(code),
+            "```rust\nfn main() {\n    let a = 1 | 2;\n... (1 lines omitted)\n```"
+        );
+        // The lines that aren't numbered are unchanged
+        assert_eq!(
+            strip_line_numbers("a | b\n | c\n12| d"),
+            "a | b\n | c\n12| d"
+        );
+
+        // A template can show some files without their numbers
+        let rendered = render(
+            "{{#each files}}{{#if (eq extension \"md\")}}{{strip_line_numbers code}}\
+             {{else}}{{code}}{{/if}}\n{{/each}}",
+            json!({"files": [
+                {"extension": "rs", "line_numbers": true, "code": "1 | a\n2 | b"},
+                {"extension": "md", "line_numbers": true, "code": "1 | # B"}
+            ]}),
+        );
+        assert_eq!(rendered, "1 | a\n2 | b\n# B");
+    }
+
+    #[test]
+    fn test_file_format_tokens() {
+        assert_eq!(
+            file_format_tokens("===== {index}. {path} ({language}, {tokens}) =====").unwrap(),
+            vec!["index", "path", "language", "tokens"]
+        );
+        assert!(file_format_tokens("-----").unwrap().is_empty());
+        let error = file_format_tokens("<<<FILE {name}>>>")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("Unknown token {name} in the file format <<<FILE {name}>>>"),
+            "{}",
+            error
+        );
+        assert!(
+            error.contains("Valid tokens: {path}, {language}, {tokens}, {index}"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_resolve_file_format() {
+        let file =
+            serde_json::json!({"path": "src/main.rs", "language": "rust", "token_count": 42});
+        assert_eq!(
+            resolve_file_format("<<<FILE {index} {path} {language} {tokens}>>>", &file, 3),
+            "<<<FILE 3 src/main.rs rust 42>>>"
+        );
+        // Without token counts, {tokens} is empty
+        let file = serde_json::json!({"path": "a.txt", "language": ""});
+        assert_eq!(resolve_file_format("{path}:{tokens}", &file, 1), "a.txt:");
+
+        assert_eq!(
+            replace_tokens("{a}-{b}-{a}", |token| (token == "a")
+                .then(|| "x".to_string())),
+            "x-{b}-x"
+        );
+    }
+
+    #[test]
+    fn test_heading_slug() {
+        assert_eq!(heading_slug("src/main.rs"), "srcmainrs");
+        assert_eq!(heading_slug("My File_v2 (copy).md"), "my-file_v2-copymd");
+        assert_eq!(heading_slug("docs/Ünïcode-name.txt"), "docsünïcode-nametxt");
+    }
+
+    #[test]
+    fn test_heading_anchors_deduplicate() {
+        let anchors = heading_anchors(["a/b.rs", "ab.rs", "a/b/.rs", "abrs-1", "a.b.rs"]);
+        assert_eq!(anchors, ["abrs", "abrs-1", "abrs-2", "abrs-1-1", "abrs-3"]);
+    }
 }
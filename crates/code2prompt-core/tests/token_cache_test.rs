@@ -0,0 +1,130 @@
+use code2prompt_core::configuration::Code2PromptConfig;
+use code2prompt_core::path::traverse_directory;
+use code2prompt_core::token_cache::{clear_cache, TokenCache, CACHE_FILE_NAME};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    fn token_counts(files: &[Value]) -> Vec<(String, u64)> {
+        let mut counts: Vec<(String, u64)> = files
+            .iter()
+            .map(|file| {
+                (
+                    file["path"].as_str().unwrap().to_string(),
+                    file["token_count"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        counts.sort();
+        counts
+    }
+
+    fn config(path: &Path, cache_dir: &Path) -> Code2PromptConfig {
+        Code2PromptConfig::builder()
+            .path(path.to_path_buf())
+            .token_cache(true)
+            .cache_dir(Some(cache_dir.to_path_buf()))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_token_cache_round_trip() {
+        let cache_dir = tempdir().unwrap();
+        let key = TokenCache::key("fn main() {}", "cl100k_base");
+        assert_ne!(key, TokenCache::key("fn main() {}", "o200k_base"));
+
+        let mut cache = TokenCache::open(cache_dir.path());
+        assert_eq!(cache.get(&key), None);
+        cache.insert(key.clone(), 42);
+        cache.save().unwrap();
+
+        let cache = TokenCache::open(cache_dir.path());
+        assert_eq!(cache.get(&key), Some(42));
+        assert_eq!(cache.get("cl100k_base:unknown"), None);
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+        clear_cache(cache_dir.path()).unwrap();
+        assert!(!cache_dir.path().join(CACHE_FILE_NAME).exists());
+        assert_eq!(TokenCache::open(cache_dir.path()).get(&key), None);
+        // Clearing a missing cache is not an error
+        clear_cache(cache_dir.path()).unwrap();
+    }
+
+    #[test]
+    fn test_second_run_reuses_cached_counts() {
+        let dir = tempdir().unwrap();
+        let cache_dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.rs"), "fn a() {}\n").unwrap();
+        fs::write(dir.path().join("b.rs"), "fn b() { println!(\"b\"); }\n").unwrap();
+
+        let (_, files) = traverse_directory(&config(dir.path(), cache_dir.path())).unwrap();
+        let counted = token_counts(&files);
+        let cache_file = cache_dir.path().join(CACHE_FILE_NAME);
+        assert!(cache_file.exists());
+
+        // Tamper with the cached counts: a second run returning them didn't recount the files
+        let mut cache: Value =
+            serde_json::from_str(&fs::read_to_string(&cache_file).unwrap()).unwrap();
+        let entries = cache["entries"].as_object_mut().unwrap();
+        assert_eq!(entries.len(), 2);
+        for count in entries.values_mut() {
+            *count = Value::from(12345);
+        }
+        fs::write(&cache_file, cache.to_string()).unwrap();
+
+        let (_, files) = traverse_directory(&config(dir.path(), cache_dir.path())).unwrap();
+        assert_eq!(
+            token_counts(&files),
+            [("a.rs".to_string(), 12345), ("b.rs".to_string(), 12345)]
+        );
+
+        // A changed file misses the cache
+        fs::write(dir.path().join("a.rs"), "fn a() { todo!() }\n").unwrap();
+        let (_, files) = traverse_directory(&config(dir.path(), cache_dir.path())).unwrap();
+        let counts = token_counts(&files);
+        assert_ne!(counts[0].1, 12345);
+        assert_eq!(counts[1].1, 12345);
+
+        // Without the cache, every file is counted
+        let config = Code2PromptConfig::builder()
+            .path(dir.path().to_path_buf())
+            .build()
+            .unwrap();
+        let (_, files) = traverse_directory(&config).unwrap();
+        assert_eq!(token_counts(&files)[1], counted[1]);
+    }
+
+    #[test]
+    fn test_corrupted_cache_is_recounted() {
+        let dir = tempdir().unwrap();
+        let cache_dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.rs"), "fn a() {}\n").unwrap();
+        let cache_file = cache_dir.path().join(CACHE_FILE_NAME);
+
+        for corrupted in [
+            "{\"version\": 1, \"entr",
+            "[1, 2]",
+            "{\"version\": 99, \"entries\": {}}",
+        ] {
+            fs::write(&cache_file, corrupted).unwrap();
+            let (_, files) = traverse_directory(&config(dir.path(), cache_dir.path())).unwrap();
+            assert!(files[0]["token_count"].as_u64().unwrap() > 0);
+            // The cache is rewritten with the recounted file
+            let cache: Value =
+                serde_json::from_str(&fs::read_to_string(&cache_file).unwrap()).unwrap();
+            assert_eq!(cache["entries"].as_object().unwrap().len(), 1);
+        }
+
+        // A cache directory that can't be created doesn't fail the run
+        let not_a_dir = cache_dir.path().join("file");
+        fs::write(&not_a_dir, "").unwrap();
+        let (_, files) = traverse_directory(&config(dir.path(), &not_a_dir)).unwrap();
+        assert!(files[0]["token_count"].as_u64().unwrap() > 0);
+    }
+}
@@ -0,0 +1,103 @@
+use code2prompt_core::token_map::{build_token_map, summarize_directories, DirectorySummary};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn files() -> Vec<serde_json::Value> {
+        vec![
+            json!({"path": "src/lib.rs", "token_count": 10}),
+            json!({"path": "src/git/diff.rs", "token_count": 50}),
+            json!({"path": "src/git/log.rs", "token_count": 20}),
+            json!({"path": "README.md", "token_count": 20}),
+            json!({"path": "image.png", "token_count": null}),
+        ]
+    }
+
+    #[test]
+    fn test_build_token_map() {
+        let root = build_token_map(&files(), None);
+        assert_eq!(root.tokens, 100);
+        assert!(root.is_dir);
+
+        // Siblings are sorted by descending tokens
+        let names: Vec<&str> = root.children.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["src", "README.md"]);
+        let src = &root.children[0];
+        assert!(src.is_dir);
+        assert_eq!(src.tokens, 80);
+        assert_eq!(src.percentage, 80.0);
+        let names: Vec<&str> = src.children.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["git", "lib.rs"]);
+
+        let git = &src.children[0];
+        assert_eq!(git.path, "src/git");
+        assert_eq!(git.tokens, 70);
+        assert_eq!(git.children[0].path, "src/git/diff.rs");
+        assert!(!git.children[0].is_dir);
+        assert_eq!(git.children[0].percentage, 50.0);
+        assert!(git.children[0].children.is_empty());
+
+        // The file without a token count is left out
+        assert!(!root.children.iter().any(|c| c.name == "image.png"));
+    }
+
+    #[test]
+    fn test_build_token_map_depth() {
+        let root = build_token_map(&files(), Some(1));
+        assert_eq!(root.children.len(), 2);
+        let src = &root.children[0];
+        // The collapsed directory still counts the files below it
+        assert_eq!(src.tokens, 80);
+        assert!(src.is_dir);
+        assert!(src.children.is_empty());
+
+        let root = build_token_map(&files(), Some(2));
+        let git = &root.children[0].children[0];
+        assert_eq!(git.tokens, 70);
+        assert!(git.children.is_empty());
+
+        let root = build_token_map(&[], None);
+        assert_eq!(root.tokens, 0);
+        assert!(root.children.is_empty());
+    }
+
+    fn summary(path: &str, files: usize, size: u64, tokens: usize) -> DirectorySummary {
+        DirectorySummary {
+            path: path.to_string(),
+            files,
+            size,
+            tokens,
+        }
+    }
+
+    #[test]
+    fn test_summarize_directories() {
+        let files = vec![
+            json!({"path": "src/lib.rs", "size": 100, "token_count": 10}),
+            json!({"path": "src/git/diff.rs", "size": 500, "token_count": 50}),
+            json!({"path": "src/git/log.rs", "size": 200, "token_count": 20}),
+            json!({"path": "README.md", "size": 300, "token_count": 20}),
+            json!({"path": "image.png", "size": 1000, "token_count": null}),
+        ];
+
+        assert_eq!(
+            summarize_directories(&files, 1),
+            [summary(".", 2, 1300, 20), summary("src", 3, 800, 80)]
+        );
+        assert_eq!(
+            summarize_directories(&files, 2),
+            [
+                summary(".", 2, 1300, 20),
+                summary("src", 1, 100, 10),
+                summary("src/git", 2, 700, 70),
+            ]
+        );
+        assert_eq!(
+            summarize_directories(&files, 0),
+            [summary(".", 5, 2100, 100)]
+        );
+        assert!(summarize_directories(&[], 1).is_empty());
+    }
+}
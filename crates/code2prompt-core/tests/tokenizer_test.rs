@@ -0,0 +1,161 @@
+use code2prompt_core::tokenizer::{count_tokens, estimate_tokens, TokenCountMode, TokenizerType};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    const GOLDEN_TEXT: &str = "fn main() {\n    println!(\"Hello, 世界! 🦀\");\n}\n";
+
+    #[test]
+    fn test_count_tokens_golden() {
+        let expected = [
+            (TokenizerType::O200kBase, 16),
+            (TokenizerType::Cl100kBase, 19),
+            (TokenizerType::P50kBase, 24),
+            (TokenizerType::P50kEdit, 24),
+            (TokenizerType::R50kBase, 26),
+            (TokenizerType::Gpt2, 26),
+        ];
+        for (tokenizer_type, token_count) in expected {
+            assert_eq!(
+                count_tokens(GOLDEN_TEXT, &tokenizer_type),
+                token_count,
+                "{}",
+                tokenizer_type.encoding_name()
+            );
+        }
+    }
+
+    #[test]
+    fn test_tokenizer_type_from_encoding_name() {
+        assert_eq!(
+            TokenizerType::from_str("o200k_base").unwrap(),
+            TokenizerType::O200kBase
+        );
+        assert_eq!(
+            TokenizerType::from_str("o200k").unwrap(),
+            TokenizerType::O200kBase
+        );
+        assert_eq!(
+            TokenizerType::from_str("CL100K").unwrap(),
+            TokenizerType::Cl100kBase
+        );
+        assert_eq!(
+            TokenizerType::from_str("p50k_edit").unwrap(),
+            TokenizerType::P50kEdit
+        );
+        assert_eq!(
+            TokenizerType::from_str("r50k").unwrap(),
+            TokenizerType::R50kBase
+        );
+        assert_eq!(
+            TokenizerType::from_str("gpt2").unwrap(),
+            TokenizerType::Gpt2
+        );
+        assert_eq!(TokenizerType::O200kBase.encoding_name(), "o200k_base");
+    }
+
+    #[test]
+    fn test_tokenizer_type_from_model_name() {
+        for model in [
+            "gpt-4o",
+            "gpt-4o-mini",
+            "gpt-4.1",
+            "gpt-4.1-nano",
+            "o3",
+            "o3-mini",
+            "o1",
+        ] {
+            assert_eq!(
+                TokenizerType::from_str(model).unwrap(),
+                TokenizerType::O200kBase,
+                "{}",
+                model
+            );
+        }
+        for model in [
+            "gpt-4",
+            "gpt-4-turbo",
+            "gpt-3.5-turbo",
+            "text-embedding-ada-002",
+        ] {
+            assert_eq!(
+                TokenizerType::from_str(model).unwrap(),
+                TokenizerType::Cl100kBase,
+                "{}",
+                model
+            );
+        }
+        assert_eq!(
+            TokenizerType::from_str("text-davinci-003").unwrap(),
+            TokenizerType::P50kBase
+        );
+        assert_eq!(
+            TokenizerType::from_str("code-davinci-edit-001").unwrap(),
+            TokenizerType::P50kEdit
+        );
+        assert_eq!(
+            TokenizerType::from_str("davinci").unwrap(),
+            TokenizerType::R50kBase
+        );
+    }
+
+    #[test]
+    fn test_tokenizer_type_unknown_name() {
+        let error = TokenizerType::from_str("gpt-4oo").unwrap_err();
+        assert!(error.contains("Unknown encoding or model: gpt-4oo"));
+        assert!(error.contains("o200k_base, cl100k_base"));
+        assert!(error.contains("gpt-4o"));
+        assert!(TokenizerType::from_str("o30").is_err());
+    }
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens("", &TokenizerType::Cl100kBase), 0);
+        assert_eq!(estimate_tokens("12345678", &TokenizerType::Cl100kBase), 2);
+        assert_eq!(estimate_tokens("123456789", &TokenizerType::O200kBase), 3);
+        assert_eq!(estimate_tokens("1234", &TokenizerType::Gpt2), 2);
+
+        // Within a factor of two of the exact count on code
+        let code = GOLDEN_TEXT.repeat(50);
+        for tokenizer_type in [TokenizerType::Cl100kBase, TokenizerType::R50kBase] {
+            let exact = count_tokens(&code, &tokenizer_type) as f64;
+            let estimate = estimate_tokens(&code, &tokenizer_type) as f64;
+            assert!(
+                estimate > exact / 2.0 && estimate < exact * 2.0,
+                "{} vs {}",
+                estimate,
+                exact
+            );
+        }
+
+        assert_eq!(
+            TokenCountMode::from_str("Estimate").unwrap(),
+            TokenCountMode::Estimate
+        );
+        assert_eq!(
+            TokenCountMode::from_str("off").unwrap(),
+            TokenCountMode::Off
+        );
+        assert!(TokenCountMode::from_str("fast").is_err());
+    }
+
+    #[cfg(feature = "huggingface")]
+    #[test]
+    fn test_count_tokens_hugging_face() {
+        let path =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tokenizer.json");
+        let tokenizer_type = TokenizerType::HuggingFace(path.clone());
+        tokenizer_type.load().unwrap();
+        // The whitespace pre-tokenizer splits words and punctuation runs
+        assert_eq!(count_tokens("fn main() {\n}\n", &tokenizer_type), 5);
+        assert_eq!(count_tokens("unknown words + more", &tokenizer_type), 4);
+        assert_eq!(tokenizer_type.encoding_name(), "huggingface");
+        assert_eq!(tokenizer_type.to_string(), path.display().to_string());
+
+        let missing = TokenizerType::HuggingFace("missing/tokenizer.json".into());
+        let error = missing.load().unwrap_err().to_string();
+        assert!(error.contains("Failed to load the Hugging Face tokenizer missing/tokenizer.json"));
+    }
+}
@@ -1,4 +1,4 @@
-use code2prompt_core::util::strip_utf8_bom;
+use code2prompt_core::util::{decode_text, format_size, sniff_mime_type, strip_utf8_bom};
 
 #[cfg(test)]
 mod tests {
@@ -45,4 +45,58 @@ mod tests {
             "Input that is only a BOM should return an empty slice."
         );
     }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(12_600), "12.3 KB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MB");
+    }
+
+    #[test]
+    fn test_decode_text() {
+        let decoded = decode_text("plain ✓".as_bytes()).unwrap();
+        assert_eq!(decoded.text, "plain ✓");
+        assert_eq!(decoded.warning, None);
+
+        let decoded = decode_text(&[0xEF, 0xBB, 0xBF, b'h', b'i']).unwrap();
+        assert_eq!(decoded.text, "hi");
+        assert_eq!(decoded.warning, None);
+
+        let decoded = decode_text(&[0xFF, 0xFE, b'h', 0, b'i', 0]).unwrap();
+        assert_eq!(decoded.text, "hi");
+        assert_eq!(decoded.warning.as_deref(), Some("converted from UTF-16LE"));
+
+        let decoded = decode_text(&[0xFE, 0xFF, 0, b'h', 0, b'i', 0]).unwrap();
+        assert_eq!(decoded.text, "hi\u{FFFD}");
+
+        let decoded = decode_text(&[b'a', 0xFF, b'b']).unwrap();
+        assert_eq!(decoded.text, "a\u{FFFD}b");
+        assert_eq!(
+            decoded.warning.as_deref(),
+            Some("invalid UTF-8, 1 invalid sequence(s) replaced")
+        );
+
+        assert_eq!(decode_text(&[b'a', 0, b'b']), None);
+    }
+
+    #[test]
+    fn test_sniff_mime_type() {
+        assert_eq!(sniff_mime_type(b"\x89PNG\r\n\x1a\n\x00\x00"), "image/png");
+        assert_eq!(sniff_mime_type(b"\xff\xd8\xff\xe0\x00"), "image/jpeg");
+        assert_eq!(sniff_mime_type(b"GIF89a\x01\x00"), "image/gif");
+        assert_eq!(
+            sniff_mime_type(b"RIFF\x24\x00\x00\x00WEBPVP8 "),
+            "image/webp"
+        );
+        assert_eq!(
+            sniff_mime_type(b"RIFF\x24\x00\x00\x00WAVEfmt "),
+            "application/octet-stream"
+        );
+        assert_eq!(sniff_mime_type(b"wOF2\x00\x01"), "font/woff2");
+        assert_eq!(sniff_mime_type(b"\x00\x01\x00\x00\x00\x10"), "font/ttf");
+        assert_eq!(sniff_mime_type(b"PK\x03\x04\x14\x00"), "application/zip");
+        assert_eq!(sniff_mime_type(b"\x00\x01\x02"), "application/octet-stream");
+        assert_eq!(sniff_mime_type(b""), "application/octet-stream");
+    }
 }
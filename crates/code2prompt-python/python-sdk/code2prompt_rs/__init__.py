@@ -9,6 +9,6 @@ It provides a simple interface to the Rust-based code2prompt library, allowing y
 """
 
 # Import the Python wrapper class from the renamed file
-from .code2prompt import Code2Prompt
+from .code2prompt import Code2Prompt, builtin_templates
 
-__all__ = ['Code2Prompt']
\ No newline at end of file
+__all__ = ['Code2Prompt', 'builtin_templates']
\ No newline at end of file
@@ -2,6 +2,16 @@
 from . import code2prompt_rs as rust_sdk
 from pathlib import Path
 
+def builtin_templates():
+    """
+    List the built-in templates, usable with Code2Prompt.generate(template_builtin=...).
+
+    Returns:
+        List of (name, description, sections) tuples, sections being the optional
+        parts the template expects, like 'git_diff' or 'git_log'
+    """
+    return rust_sdk.builtin_templates()
+
 class RenderedPrompt:
     def __init__(self, prompt, token_count, directory, model_info):
         self.prompt = prompt
@@ -12,7 +22,8 @@ class RenderedPrompt:
 class Code2Prompt:
     def __init__(self, path, include_patterns=None, exclude_patterns=None, 
                  include_priority=False, line_numbers=False, absolute_paths=False,
-                 full_directory_tree=False, code_blocks=True, follow_symlinks=False, include_hidden=False):
+                 full_directory_tree=False, code_blocks=True, follow_symlinks=False, include_hidden=False,
+                 path_style=None):
         """
         Initialize a Code2Prompt configuration for generating prompts from code.
         
@@ -27,6 +38,8 @@ class Code2Prompt:
             code_blocks: Whether to wrap code in markdown code blocks
             follow_symlinks: Whether to follow symlinks
             include_hidden: Whether to include hidden files (default is False)
+            path_style: Optional style of the paths, 'relative', 'absolute' or
+                'strip:<prefix>', over absolute_paths
         """
         # Stocker la configuration
         self.path = Path(path)
@@ -39,6 +52,7 @@ class Code2Prompt:
         self.code_blocks = code_blocks
         self.follow_symlinks = follow_symlinks
         self.include_hidden = include_hidden
+        self.path_style = path_style
         
         # Initializer une session uniquement quand nécessaire
         self._session = None
@@ -63,16 +77,23 @@ class Code2Prompt:
         session = session.with_code_blocks(self.code_blocks)
         session = session.follow_symlinks(self.follow_symlinks)
         session = session.include_hidden(self.include_hidden)
+        if self.path_style:
+            session = session.with_path_style(self.path_style)
         
         return session
     
-    def generate(self, template=None, encoding=None) -> RenderedPrompt:
+    def generate(self, template=None, encoding=None, token_count_mode=None, variables=None,
+                 template_builtin=None) -> RenderedPrompt:
         """
         Generate a prompt from the code.
         
         Args:
             template: Optional template string to use
             encoding: Token encoding to use (e.g., 'cl100k', 'gpt2')
+            token_count_mode: 'exact' (default), 'estimate' for a fast estimate without
+                loading the tokenizer, or 'off'
+            variables: Optional dict of variables, available to the template as {{user.<key>}}
+            template_builtin: Optional name of a built-in template, see builtin_templates()
         
         Returns:
             String containing the generated prompt
@@ -82,9 +103,17 @@ class Code2Prompt:
         
         if encoding:
             session = session.with_token_encoding(encoding)
+
+        if token_count_mode:
+            session = session.with_token_count_mode(token_count_mode)
         
         if template:
             session = session.with_template(template)
+        elif template_builtin:
+            session = session.with_builtin_template(template_builtin)
+
+        if variables:
+            session = session.with_variables(variables)
             
         # Generate the prompt
         result = session.generate()
@@ -3,10 +3,12 @@ use std::collections::HashMap;
 use std::path::PathBuf;
 
 use code2prompt_core::configuration::Code2PromptConfigBuilder;
+use code2prompt_core::path::PathStyle;
 use code2prompt_core::session::Code2PromptSession;
 use code2prompt_core::sort::FileSortMethod;
 use code2prompt_core::template::OutputFormat;
-use code2prompt_core::tokenizer::{TokenFormat, TokenizerType};
+use code2prompt_core::template_library::{builtin_template, BUILTIN_TEMPLATES};
+use code2prompt_core::tokenizer::{TokenCountMode, TokenFormat, TokenizerType};
 
 #[pyclass]
 #[derive(Clone)]
@@ -40,12 +42,12 @@ impl PyCode2PromptSession {
         self.inner = Code2PromptSession::new(config);
 
         Python::with_gil(|py| {
-            Ok(Py::new(
+            Py::new(
                 py,
                 Self {
                     inner: self.inner.clone(),
                 },
-            )?)
+            )
         })
     }
 
@@ -55,12 +57,12 @@ impl PyCode2PromptSession {
         self.inner = Code2PromptSession::new(config);
 
         Python::with_gil(|py| {
-            Ok(Py::new(
+            Py::new(
                 py,
                 Self {
                     inner: self.inner.clone(),
                 },
-            )?)
+            )
         })
     }
 
@@ -70,12 +72,12 @@ impl PyCode2PromptSession {
         self.inner = Code2PromptSession::new(config);
 
         Python::with_gil(|py| {
-            Ok(Py::new(
+            Py::new(
                 py,
                 Self {
                     inner: self.inner.clone(),
                 },
-            )?)
+            )
         })
     }
 
@@ -85,27 +87,48 @@ impl PyCode2PromptSession {
         self.inner = Code2PromptSession::new(config);
 
         Python::with_gil(|py| {
-            Ok(Py::new(
+            Py::new(
                 py,
                 Self {
                     inner: self.inner.clone(),
                 },
-            )?)
+            )
         })
     }
 
     fn with_absolute_paths(&mut self, value: bool) -> PyResult<Py<Self>> {
         let mut config = self.inner.config.clone();
-        config.absolute_path = value;
+        config.path_style = if value {
+            PathStyle::Absolute
+        } else {
+            PathStyle::Relative
+        };
+        self.inner = Code2PromptSession::new(config);
+
+        Python::with_gil(|py| {
+            Py::new(
+                py,
+                Self {
+                    inner: self.inner.clone(),
+                },
+            )
+        })
+    }
+
+    fn with_path_style(&mut self, style: &str) -> PyResult<Py<Self>> {
+        let mut config = self.inner.config.clone();
+        config.path_style = style
+            .parse::<PathStyle>()
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
         self.inner = Code2PromptSession::new(config);
 
         Python::with_gil(|py| {
-            Ok(Py::new(
+            Py::new(
                 py,
                 Self {
                     inner: self.inner.clone(),
                 },
-            )?)
+            )
         })
     }
 
@@ -115,12 +138,12 @@ impl PyCode2PromptSession {
         self.inner = Code2PromptSession::new(config);
 
         Python::with_gil(|py| {
-            Ok(Py::new(
+            Py::new(
                 py,
                 Self {
                     inner: self.inner.clone(),
                 },
-            )?)
+            )
         })
     }
 
@@ -130,12 +153,12 @@ impl PyCode2PromptSession {
         self.inner = Code2PromptSession::new(config);
 
         Python::with_gil(|py| {
-            Ok(Py::new(
+            Py::new(
                 py,
                 Self {
                     inner: self.inner.clone(),
                 },
-            )?)
+            )
         })
     }
 
@@ -145,12 +168,12 @@ impl PyCode2PromptSession {
         self.inner = Code2PromptSession::new(config);
 
         Python::with_gil(|py| {
-            Ok(Py::new(
+            Py::new(
                 py,
                 Self {
                     inner: self.inner.clone(),
                 },
-            )?)
+            )
         })
     }
 
@@ -160,12 +183,12 @@ impl PyCode2PromptSession {
         self.inner = Code2PromptSession::new(config);
 
         Python::with_gil(|py| {
-            Ok(Py::new(
+            Py::new(
                 py,
                 Self {
                     inner: self.inner.clone(),
                 },
-            )?)
+            )
         })
     }
 
@@ -175,12 +198,57 @@ impl PyCode2PromptSession {
         self.inner = Code2PromptSession::new(config);
 
         Python::with_gil(|py| {
-            Ok(Py::new(
+            Py::new(
+                py,
+                Self {
+                    inner: self.inner.clone(),
+                },
+            )
+        })
+    }
+
+    fn file_git_info(&mut self, value: bool) -> PyResult<Py<Self>> {
+        let mut config = self.inner.config.clone();
+        config.file_git_info = value;
+        self.inner = Code2PromptSession::new(config);
+
+        Python::with_gil(|py| {
+            Py::new(
+                py,
+                Self {
+                    inner: self.inner.clone(),
+                },
+            )
+        })
+    }
+
+    fn tracked_only(&mut self, value: bool) -> PyResult<Py<Self>> {
+        let mut config = self.inner.config.clone();
+        config.tracked_only = value;
+        self.inner = Code2PromptSession::new(config);
+
+        Python::with_gil(|py| {
+            Py::new(
+                py,
+                Self {
+                    inner: self.inner.clone(),
+                },
+            )
+        })
+    }
+
+    fn include_submodules(&mut self, value: bool) -> PyResult<Py<Self>> {
+        let mut config = self.inner.config.clone();
+        config.include_submodules = value;
+        self.inner = Code2PromptSession::new(config);
+
+        Python::with_gil(|py| {
+            Py::new(
                 py,
                 Self {
                     inner: self.inner.clone(),
                 },
-            )?)
+            )
         })
     }
 
@@ -191,9 +259,13 @@ impl PyCode2PromptSession {
             "name_desc" => config.sort_method = Some(FileSortMethod::NameDesc),
             "date" | "date_asc" => config.sort_method = Some(FileSortMethod::DateAsc),
             "date_desc" => config.sort_method = Some(FileSortMethod::DateDesc),
+            "tokens" | "tokens_asc" => config.sort_method = Some(FileSortMethod::TokensAsc),
+            "tokens_desc" => config.sort_method = Some(FileSortMethod::TokensDesc),
+            "size" | "size_asc" => config.sort_method = Some(FileSortMethod::SizeAsc),
+            "size_desc" => config.sort_method = Some(FileSortMethod::SizeDesc),
             _ => {
                 return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
-                "Invalid sort method: {}. Valid values: name_asc, name_desc, date_asc, date_desc",
+                "Invalid sort method: {}. Valid values: name_asc, name_desc, date_asc, date_desc, tokens_asc, tokens_desc, size_asc, size_desc",
                 method
             )))
             }
@@ -201,12 +273,12 @@ impl PyCode2PromptSession {
         self.inner = Code2PromptSession::new(config);
 
         Python::with_gil(|py| {
-            Ok(Py::new(
+            Py::new(
                 py,
                 Self {
                     inner: self.inner.clone(),
                 },
-            )?)
+            )
         })
     }
 
@@ -217,6 +289,8 @@ impl PyCode2PromptSession {
             // Assuming from the error that there's a Plain variant - please replace if needed
             "xml" | "text" => config.output_format = OutputFormat::Xml,
             "json" => config.output_format = OutputFormat::Json,
+            "yaml" | "yml" => config.output_format = OutputFormat::Yaml,
+            "ndjson" | "jsonl" => config.output_format = OutputFormat::Ndjson,
             _ => {
                 return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
                     "Invalid output format: {}",
@@ -227,40 +301,46 @@ impl PyCode2PromptSession {
         self.inner = Code2PromptSession::new(config);
 
         Python::with_gil(|py| {
-            Ok(Py::new(
+            Py::new(
                 py,
                 Self {
                     inner: self.inner.clone(),
                 },
-            )?)
+            )
         })
     }
 
     fn with_token_encoding(&mut self, encoding: &str) -> PyResult<Py<Self>> {
         let mut config = self.inner.config.clone();
-        match encoding.to_lowercase().as_str() {
-            "gpt2" => config.encoding = TokenizerType::Gpt2,
-            "cl100k" => config.encoding = TokenizerType::Cl100kBase,
-            "o200k" => config.encoding = TokenizerType::O200kBase,
-            "p50k" => config.encoding = TokenizerType::P50kBase,
-            "p50k_edit" => config.encoding = TokenizerType::P50kEdit,
-            "r50k" => config.encoding = TokenizerType::R50kBase,
-            _ => {
-                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
-                    "Invalid token encoding: {}",
-                    encoding
-                )))
-            }
-        }
+        config.encoding = encoding
+            .parse::<TokenizerType>()
+            .map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)?;
+        self.inner = Code2PromptSession::new(config);
+
+        Python::with_gil(|py| {
+            Py::new(
+                py,
+                Self {
+                    inner: self.inner.clone(),
+                },
+            )
+        })
+    }
+
+    fn with_token_count_mode(&mut self, mode: &str) -> PyResult<Py<Self>> {
+        let mut config = self.inner.config.clone();
+        config.token_count_mode = mode
+            .parse::<TokenCountMode>()
+            .map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)?;
         self.inner = Code2PromptSession::new(config);
 
         Python::with_gil(|py| {
-            Ok(Py::new(
+            Py::new(
                 py,
                 Self {
                     inner: self.inner.clone(),
                 },
-            )?)
+            )
         })
     }
 
@@ -279,12 +359,12 @@ impl PyCode2PromptSession {
         self.inner = Code2PromptSession::new(config);
 
         Python::with_gil(|py| {
-            Ok(Py::new(
+            Py::new(
                 py,
                 Self {
                     inner: self.inner.clone(),
                 },
-            )?)
+            )
         })
     }
 
@@ -300,12 +380,35 @@ impl PyCode2PromptSession {
         self.inner = Code2PromptSession::new(config);
 
         Python::with_gil(|py| {
-            Ok(Py::new(
+            Py::new(
+                py,
+                Self {
+                    inner: self.inner.clone(),
+                },
+            )
+        })
+    }
+
+    #[pyo3(signature = (name))]
+    fn with_builtin_template(&mut self, name: &str) -> PyResult<Py<Self>> {
+        let builtin = builtin_template(name).ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "No built-in template named {}",
+                name
+            ))
+        })?;
+        let mut config = self.inner.config.clone();
+        config.template_str = builtin.content.to_string();
+        config.template_name = builtin.name.to_string();
+        self.inner = Code2PromptSession::new(config);
+
+        Python::with_gil(|py| {
+            Py::new(
                 py,
                 Self {
                     inner: self.inner.clone(),
                 },
-            )?)
+            )
         })
     }
 
@@ -316,15 +419,35 @@ impl PyCode2PromptSession {
         self.inner = Code2PromptSession::new(config);
 
         Python::with_gil(|py| {
-            Ok(Py::new(
+            Py::new(
+                py,
+                Self {
+                    inner: self.inner.clone(),
+                },
+            )
+        })
+    }
+
+    #[pyo3(signature = (variables))]
+    fn with_variables(&mut self, variables: HashMap<String, String>) -> PyResult<Py<Self>> {
+        let mut config = self.inner.config.clone();
+        config.user_variables.extend(variables);
+        self.inner = Code2PromptSession::new(config);
+
+        Python::with_gil(|py| {
+            Py::new(
                 py,
                 Self {
                     inner: self.inner.clone(),
                 },
-            )?)
+            )
         })
     }
 
+    fn variables(&self) -> HashMap<String, String> {
+        self.inner.config.user_variables.clone()
+    }
+
     fn generate(&mut self) -> PyResult<String> {
         match self.inner.generate_prompt() {
             Ok(rendered) => Ok(rendered.prompt),
@@ -366,9 +489,31 @@ impl PyCode2PromptSession {
     }
 }
 
+/// Lists the built-in templates as (name, description, sections) tuples, the sections being
+/// the optional ones the template expects, like "git_diff".
+#[pyfunction]
+fn builtin_templates() -> Vec<(String, String, Vec<String>)> {
+    BUILTIN_TEMPLATES
+        .iter()
+        .map(|builtin| {
+            let sections = builtin
+                .sections
+                .iter()
+                .map(|section| section.key().to_string())
+                .collect();
+            (
+                builtin.name.to_string(),
+                builtin.description.to_string(),
+                sections,
+            )
+        })
+        .collect()
+}
+
 // Module definition - Updated PyO3 syntax
 #[pymodule(name = "code2prompt_rs")]
 fn code2prompt_rs(_py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<PyCode2PromptSession>()?;
+    m.add_function(wrap_pyfunction!(builtin_templates, m)?)?;
     Ok(())
 }
@@ -1,6 +1,6 @@
 """Tests for prompt generation."""
 import pytest
-from code2prompt_rs import Code2Prompt
+from code2prompt_rs import Code2Prompt, builtin_templates
 
 def test_generate_basic(test_dir):
     """Test basic prompt generation."""
@@ -143,4 +143,26 @@ def test_multiple_encoding_options(test_dir):
     # (but for very small files they might be the same)
     if len(token_counts) > 1:
         unique_counts = set(token_counts.values())
-        print(f"Token counts: {token_counts}")
\ No newline at end of file
+        print(f"Token counts: {token_counts}")
+
+def test_generate_with_variables(test_dir):
+    """Test that variables are available to the template under user."""
+    prompt = Code2Prompt(path=test_dir)
+    result = prompt.generate(
+        template="Ticket {{user.ticket}}: {{user.note}}",
+        variables={"ticket": "ABC-12", "note": "a=b"},
+    )
+
+    assert result.prompt == "Ticket ABC-12: a=b"
+    session = prompt.session().with_variables({"ticket": "ABC-12"})
+    assert session.variables() == {"ticket": "ABC-12"}
+
+def test_generate_with_builtin_template(test_dir):
+    """Test that the built-in templates can be listed and used by name."""
+    templates = {name: sections for name, _, sections in builtin_templates()}
+    assert templates["pr-description"] == ["git_diff", "git_log"]
+    assert templates["explain-codebase"] == []
+
+    prompt = Code2Prompt(path=test_dir)
+    result = prompt.generate(template_builtin="explain-codebase")
+    assert "explain it to me" in result.prompt
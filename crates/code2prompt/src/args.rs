@@ -1,7 +1,21 @@
-use clap::Parser;
-use code2prompt_core::{template::OutputFormat, tokenizer::TokenFormat};
+use clap::{ArgGroup, Parser};
+use code2prompt_core::{
+    budget::BudgetStrategy,
+    engine::TemplateEngine,
+    git::{
+        DiffRenderStyle, WhitespaceMode, DEFAULT_CONTRIBUTORS_COMMIT_LIMIT,
+        DEFAULT_CONTRIBUTORS_COUNT,
+    },
+    output::Compression,
+    path::{BinaryPolicy, PathStyle},
+    split::ChunkSize,
+    template::{check_date_format, parse_locale, OutputFormat},
+    tokenizer::{TokenCountMode, TokenFormat, TokenizerType},
+};
 use std::path::PathBuf;
 
+use crate::clipboard::ClipboardBackend;
+
 // ~~~ CLI Arguments ~~~
 #[derive(Parser)]
 #[clap(
@@ -10,11 +24,21 @@ use std::path::PathBuf;
     author = env!("CARGO_PKG_AUTHORS")
 )]
 #[command(arg_required_else_help = true)]
+#[clap(group(ArgGroup::new("diff_stat_source").args(["git_diff_branch", "git_show"]).multiple(true)))]
+#[clap(group(ArgGroup::new("template_source").args(["template", "template_builtin"])))]
 pub struct Cli {
-    /// Path to the codebase directory
+    /// Path to the codebase directory, or the HTTPS/SSH URL of a git repository to clone
     #[arg()]
     pub path: PathBuf,
 
+    /// Branch to check out when cloning a remote repository
+    #[clap(long, value_name = "BRANCH")]
+    pub remote_branch: Option<String>,
+
+    /// Keep the clone of a remote repository instead of deleting it afterwards
+    #[clap(long)]
+    pub keep_clone: bool,
+
     /// Patterns to include
     #[clap(short = 'i', long = "include", value_delimiter = ',')]
     pub include: Vec<String>,
@@ -27,53 +51,465 @@ pub struct Cli {
     #[clap(long)]
     pub include_priority: bool,
 
-    /// Optional output file path
+    /// Optional output file path, with the {timestamp}, {dirname} and {branch} tokens replaced,
+    /// e.g. "prompts/{dirname}-{timestamp}.md". Its directory is created if missing
     #[clap(short = 'O', long = "output-file")]
     pub output_file: Option<String>,
 
-    /// Output format: markdown, json, or xml
+    /// Append the prompt to the output file, after a separator line, rather than overwrite it
+    #[clap(long, requires = "output_file", conflicts_with_all = ["split", "chunk_size"])]
+    pub output_append: bool,
+
+    /// Compress the written prompts: gzip, or zstd with the zstd feature. Inferred from a .gz
+    /// or .zst output file, and disables the clipboard
+    #[clap(long, value_name = "FORMAT")]
+    pub compress: Option<Compression>,
+
+    /// Also write a CSV manifest with a row per included file: its path, extension, language,
+    /// bytes, lines, tokens, whether it was truncated and, with --file-git-info, its last commit
+    #[clap(long, value_name = "PATH")]
+    pub manifest: Option<PathBuf>,
+
+    /// Add a row to the manifest per file skipped while reading the codebase or omitted to fit
+    /// the token budget, with its skip_reason
+    #[clap(long, requires = "manifest")]
+    pub manifest_include_skipped: bool,
+
+    /// Output format: markdown, json, xml, yaml, ndjson for a JSON record per line, or html for
+    /// a self-contained page with highlighted code (needs the html feature)
     #[clap(short = 'F', long = "output-format", default_value = "markdown")]
     pub output_format: OutputFormat,
 
-    /// Optional Path to a custom Handlebars template
+    /// Write the JSON output on a single line rather than indented
+    #[clap(long)]
+    pub json_compact: bool,
+
+    /// Optional Path to a custom Handlebars or Jinja template, or the name of a template of a
+    /// .code2prompt/templates directory of the repository or of a built-in template
     #[clap(short, long)]
     pub template: Option<PathBuf>,
 
+    /// Built-in template to use by name, even when a repository template has the same name
+    ///
+    /// Includes explain-codebase, pr-description, security-review and write-tests; list them
+    /// with --list-templates.
+    #[clap(long, value_name = "NAME", conflicts_with = "template")]
+    pub template_builtin: Option<String>,
+
+    /// Engine the template is written for: handlebars or jinja
+    ///
+    /// Defaults to jinja for the .j2 and .jinja template files and to handlebars otherwise.
+    /// Jinja templates need code2prompt built with the jinja feature.
+    #[clap(long, value_name = "ENGINE")]
+    pub template_engine: Option<TemplateEngine>,
+
+    /// Handlebars partial to register, as NAME=PATH, used with {{> NAME}} (repeatable)
+    ///
+    /// The .hbs files of a partials directory next to the template file are registered too,
+    /// named after their file. The default templates render each file with the file_block
+    /// partial, which a partial of the same name replaces.
+    #[clap(long, value_name = "NAME=PATH")]
+    pub template_partial: Vec<String>,
+
+    /// Directory the {{include "PATH"}} helper of the template can read files from (repeatable)
+    ///
+    /// The paths are relative to the directory of the template file, or to the current
+    /// directory for the built-in templates, which the helper can always read from.
+    #[clap(long, value_name = "DIR")]
+    pub include_root: Vec<PathBuf>,
+
+    /// Escape the <, >, & and quotes of the values rendered by the template as HTML entities,
+    /// for a prompt rendered into an HTML page. Code is rendered unchanged otherwise
+    #[clap(long)]
+    pub escape_html: bool,
+
+    /// Default strftime format of the {{format_date}} helper, e.g. "%d %B %Y" [default: %Y-%m-%d]
+    ///
+    /// The built-in templates render the dates of the commits with it.
+    #[clap(long, value_name = "FORMAT", value_parser = parse_date_format)]
+    pub date_format: Option<String>,
+
+    /// Locale of the thousands separators of the {{format_num}} helper, e.g. "de" [default: en]
+    #[clap(long, value_name = "NAME", value_parser = parse_locale_name)]
+    pub locale: Option<String>,
+
+    /// Handlebars template rendered once per file, as a path or inline, e.g. "{{path}}: {{lines}}"
+    ///
+    /// It replaces the file_block partial, so the default templates render each file with it;
+    /// its context is the file: path, language, code, lines, token_count, last_commit...
+    #[clap(long, value_name = "PATH|TEMPLATE")]
+    pub file_template: Option<String>,
+
+    /// The line above each file of the Markdown prompt instead of its path and tokens, with
+    /// the {path}, {language}, {tokens} and {index} tokens, e.g. "===== {path} ====="
+    #[clap(long, value_name = "FORMAT")]
+    pub file_header_format: Option<String>,
+
+    /// The line below each file of the Markdown prompt, with the tokens of
+    /// --file-header-format, e.g. "<<<END {path}>>>"
+    #[clap(long, value_name = "FORMAT")]
+    pub file_footer_format: Option<String>,
+
+    /// Template variable, as KEY=VALUE, available to the template as {{user.KEY}} (repeatable)
+    ///
+    /// The value is everything after the first `=`. The variables the template uses but
+    /// aren't defined are asked for interactively.
+    #[clap(short = 'D', long = "define", value_name = "KEY=VALUE")]
+    pub define: Vec<String>,
+
+    /// Never ask for values interactively: fail if the template uses undefined variables
+    #[clap(long)]
+    pub no_input: bool,
+
+    /// Check the template for syntax errors and for keys the prompt data doesn't have, then
+    /// exit without reading the codebase
+    ///
+    /// Exits with an error code if the template can't be compiled; unknown keys are warnings.
+    #[clap(long, requires = "template_source")]
+    pub validate_template: bool,
+
+    /// Print the data the template would be rendered with as JSON instead of the prompt, to
+    /// see the keys a template can use
+    ///
+    /// The file contents are cut to their first 200 characters, followed by a marker with the
+    /// number of characters elided; the other options apply as in a normal run.
+    #[clap(long)]
+    pub show_context: bool,
+
+    /// Like --show-context, with the whole file contents
+    #[clap(long)]
+    pub show_context_full: bool,
+
+    /// List the templates that can be used by name with --template, then exit
+    #[clap(long)]
+    pub list_templates: bool,
+
     /// List the full directory tree
     #[clap(long)]
     pub full_directory_tree: bool,
 
-    /// Optional tokenizer to use for token count
+    /// Tokenizer encoding, or model whose encoding is used, for the token count
     ///
-    /// Supported tokenizers: cl100k (default), p50k, p50k_edit, r50k, gpt2
-    #[clap(short = 'c', long)]
-    pub encoding: Option<String>,
+    /// Supported encodings: o200k_base, cl100k_base (default), p50k_base, p50k_edit, r50k_base,
+    /// gpt2. Supported models include gpt-4o, gpt-4.1, gpt-5, o1, o3, o4-mini, gpt-4 and
+    /// gpt-3.5-turbo
+    #[clap(
+        short = 'c',
+        long,
+        visible_alias = "model",
+        value_name = "ENCODING|MODEL",
+        default_value = "cl100k_base"
+    )]
+    pub encoding: TokenizerType,
+
+    /// Count the tokens with a Hugging Face tokenizer.json file (e.g. of a Llama, Mistral or Qwen model)
+    #[cfg(feature = "huggingface")]
+    #[clap(long, value_name = "PATH", conflicts_with = "encoding")]
+    pub hf_tokenizer: Option<PathBuf>,
 
     /// Display the token count of the generated prompt.
-    /// Accepts a format: "raw" (machine parsable) or "format" (human readable).
-    #[clap(long, value_name = "FORMAT", default_value = "format")]
-    pub tokens: TokenFormat,
+    /// Accepts a format: "raw" (machine parsable) or "format" (human readable), "estimate" for
+    /// a fast estimate from the length of the text without loading the tokenizer, or "off" to
+    /// not count the tokens.
+    #[clap(long, value_name = "FORMAT", default_value = "format", value_parser = parse_tokens)]
+    pub tokens: (TokenFormat, TokenCountMode),
+
+    /// Estimate the input cost of the prompt for these models (e.g. "gpt-4o,claude-3-5-sonnet")
+    #[clap(
+        long,
+        visible_alias = "price",
+        value_name = "MODELS",
+        value_delimiter = ','
+    )]
+    pub model_cost: Vec<String>,
+
+    /// TOML file of model prices replacing or extending the built-in pricing table
+    #[clap(long, value_name = "FILE")]
+    pub pricing_file: Option<PathBuf>,
+
+    /// Number of threads reading and tokenizing the files [default: one per CPU]
+    #[clap(short = 'j', long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    pub jobs: Option<u16>,
+
+    /// Fail on a file that can't be read or isn't valid UTF-8, instead of skipping or
+    /// converting it with a warning
+    #[clap(long)]
+    pub strict: bool,
+
+    /// Count the tokens of every file instead of reusing the counts cached by previous runs
+    #[clap(long)]
+    pub no_cache: bool,
+
+    /// Delete the token cache before counting
+    #[clap(long)]
+    pub clear_cache: bool,
+
+    /// Directory of the token cache, defaults to the code2prompt directory of the user cache directory
+    #[clap(long, value_name = "DIR")]
+    pub cache_dir: Option<PathBuf>,
+
+    /// List the biggest token count changes of the files since the previous run on the codebase
+    #[clap(long)]
+    pub diff_stats_prev: bool,
+
+    /// Replace the files over this many tokens with a stub showing their first and last lines
+    #[clap(long, value_name = "N")]
+    pub max_tokens_per_file: Option<usize>,
+
+    /// Replace the files over this many lines with a stub, without tokenizing them
+    #[clap(long, value_name = "N")]
+    pub max_lines_per_file: Option<usize>,
+
+    /// Omit or cut files so that the prompt fits in this many tokens
+    #[clap(long, value_name = "N")]
+    pub token_budget: Option<usize>,
+
+    /// How files are cut to fit --token-budget: "drop-largest-first", "drop-last",
+    /// "truncate-tail" (cut the end of each file in proportion) or "priority" (keep the files
+    /// matching --priority first and cut them last, then fill the remaining budget with the
+    /// other files in order)
+    #[clap(
+        long,
+        value_name = "STRATEGY",
+        default_value = "drop-largest-first",
+        requires = "token_budget"
+    )]
+    pub budget_strategy: BudgetStrategy,
+
+    /// Patterns of the files placed first in the prompt, and cut last by --token-budget
+    #[clap(long, value_name = "PATTERNS", value_delimiter = ',')]
+    pub priority: Vec<String>,
+
+    /// Context window, in tokens or as a model name of the pricing table (e.g. "gpt-4o"), to
+    /// warn about a prompt that doesn't fit in it
+    #[clap(long, value_name = "N|MODEL")]
+    pub context_window: Option<String>,
+
+    /// Split a prompt exceeding --context-window into numbered output files that each fit in
+    /// it, cutting between files
+    #[clap(long, requires_all = ["context_window", "output_file"])]
+    pub split: bool,
+
+    /// Write the prompt into numbered output files of at most this many tokens, e.g. 30000 or
+    /// 30k, or characters with a c suffix, e.g. 120000c, cutting between files. The source tree
+    /// and the git sections are only in the first one
+    #[clap(
+        long,
+        value_name = "SIZE",
+        requires = "output_file",
+        conflicts_with = "split"
+    )]
+    pub chunk_size: Option<ChunkSize>,
+
+    /// Repeat the source tree and the git sections in every chunk of --chunk-size
+    #[clap(long, requires = "chunk_size")]
+    pub chunk_repeat_context: bool,
+
+    /// Write one prompt per top-level directory into --output-dir, each with the tree and the
+    /// files of its directory, and the files at the root in _root
+    #[clap(long, requires = "output_dir", conflicts_with_all = ["split", "chunk_size"])]
+    pub split_by_dir: bool,
+
+    /// The directory of the prompts of --split-by-dir, created if missing
+    #[clap(long, value_name = "DIR", requires = "split_by_dir")]
+    pub output_dir: Option<PathBuf>,
 
     /// Include git diff
     #[clap(short, long)]
     pub diff: bool,
 
-    /// Generate git diff between two branches
-    #[clap(long, value_name = "BRANCHES", num_args = 2, value_delimiter = ',')]
+    /// Number of context lines shown around each change in git diffs
+    #[clap(long, value_name = "N", default_value_t = 3)]
+    pub diff_context: u32,
+
+    /// Include untracked files (respecting .gitignore) as additions in the git diff
+    #[clap(long)]
+    pub diff_untracked: bool,
+
+    /// Also include ignored files (e.g. target/, node_modules/) with --diff-untracked
+    #[clap(long, requires = "diff_untracked")]
+    pub diff_include_ignored: bool,
+
+    /// Whitespace changes ignored in git diffs: "none", "all", "change" or "eol".
+    /// The default "all" hides indentation-only changes; use "none" for Python or YAML projects
+    #[clap(long, value_name = "MODE", default_value = "all")]
+    pub diff_whitespace: WhitespaceMode,
+
+    /// How changed lines are rendered in git diffs: "patch" (whole lines) or "word" (inline changes)
+    #[clap(long, value_name = "STYLE", default_value = "patch")]
+    pub diff_style: DiffRenderStyle,
+
+    /// Leave binary files out of git diffs instead of summarizing them on one line
+    #[clap(long)]
+    pub diff_skip_binary: bool,
+
+    /// Minimum similarity percentage for a file to be shown as renamed or copied in git diffs
+    #[clap(long, value_name = "PERCENT", default_value_t = 50, value_parser = clap::value_parser!(u16).range(0..=100))]
+    pub diff_rename_threshold: u16,
+
+    /// Restrict git diffs to these pathspecs (prefix with '!' to exclude).
+    /// Defaults to the --include/--exclude patterns
+    #[clap(long, value_name = "PATHSPEC", value_delimiter = ',')]
+    pub diff_path: Vec<String>,
+
+    /// Generate git diff between two references (branches, tags, or commits)
+    #[clap(long, value_name = "REFS", num_args = 2, value_delimiter = ',')]
     pub git_diff_branch: Option<Vec<String>>,
 
+    /// Summarize the git diff between the --git-diff-branch references or of the --git-show commit
+    /// (files changed, insertions, deletions) instead of including the full patch
+    #[clap(long, requires = "diff_stat_source")]
+    pub git_diff_stat: bool,
+
+    /// Diff the --git-diff-branch references from their merge base (like `git diff A...B`)
+    #[clap(long, requires = "git_diff_branch")]
+    pub git_diff_merge_base: bool,
+
+    /// Generate the git diff and log between the two most recent tags (or the latest tag and HEAD)
+    #[clap(long, conflicts_with_all = ["git_diff_branch", "git_log_branch", "git_log_range"])]
+    pub git_diff_latest_tags: bool,
+
+    /// Retrieve the diff of a stash entry (0 is the most recent stash)
+    #[clap(long, value_name = "N")]
+    pub git_diff_stash: Option<usize>,
+
+    /// Diff the working tree, staged or not, against a reference (like `git diff <ref>`)
+    #[clap(long, value_name = "REF")]
+    pub git_diff_against: Option<String>,
+
+    /// Show the message and diff of a single commit (like `git show <rev>`)
+    #[clap(long, value_name = "REV")]
+    pub git_show: Option<String>,
+
+    /// Truncate each git diff to this many tokens, dropping the largest hunks first
+    #[clap(long, value_name = "N")]
+    pub diff_token_limit: Option<usize>,
+
     /// Retrieve git log between two branches
     #[clap(long, value_name = "BRANCHES", num_args = 2, value_delimiter = ',')]
     pub git_log_branch: Option<Vec<String>>,
 
-    /// Add line numbers to the source code
+    /// Retrieve git log for a revision range (e.g. "HEAD~10..HEAD", "main...feature" or "-n 20")
+    #[clap(
+        long,
+        value_name = "RANGE",
+        allow_hyphen_values = true,
+        conflicts_with = "git_log_branch"
+    )]
+    pub git_log_range: Option<String>,
+
+    /// Only include files changed since a git revision (e.g. "HEAD~5", "v1.2.0") or a number of days (e.g. "7d")
+    #[clap(long, value_name = "REV|Nd")]
+    pub modified_since: Option<String>,
+
+    /// Only include the files changed between the --git-diff-branch references, with their diff
+    /// stats. The log and changelog default to the same references
+    #[clap(long, requires = "git_diff_branch")]
+    pub changed_files_only: bool,
+
+    /// Generate a changelog grouped by conventional-commit type for a range (e.g. "v1.0.0..HEAD")
+    #[clap(long, value_name = "FROM..TO")]
+    pub git_changelog: Option<String>,
+
+    /// Include the full commit message body in the git log
+    #[clap(long)]
+    pub log_with_body: bool,
+
+    /// Include the commit author in the git log
+    #[clap(long)]
+    pub log_with_author: bool,
+
+    /// Include the commit date in the git log
+    #[clap(long)]
+    pub log_with_date: bool,
+
+    /// Format used for commit dates (strftime-like, e.g. "%Y-%m-%d")
+    #[clap(long, value_name = "FORMAT", default_value = "%Y-%m-%d %H:%M:%S %z")]
+    pub log_date_format: String,
+
+    /// Maximum number of (most recent) commits to include in the git log
+    #[clap(long, value_name = "N")]
+    pub log_max_count: Option<usize>,
+
+    /// List the files changed by each commit in the git log (A/M/D/R)
+    #[clap(long)]
+    pub log_files: bool,
+
+    /// Maximum number of changed files listed per commit with --log-files
+    #[clap(long, value_name = "N", default_value_t = 20, requires = "log_files")]
+    pub log_files_max: usize,
+
+    /// Only include commits whose author name or email contains this string
+    #[clap(long, value_name = "AUTHOR")]
+    pub log_author: Option<String>,
+
+    /// Only include commits more recent than a date (e.g. "2024-05-01" or "2 weeks ago")
+    #[clap(long, value_name = "DATE")]
+    pub log_since: Option<String>,
+
+    /// Only include commits older than a date (e.g. "2024-06-01" or "yesterday")
+    #[clap(long, value_name = "DATE")]
+    pub log_until: Option<String>,
+
+    /// Leave merge commits out of the git log
+    #[clap(long)]
+    pub log_no_merges: bool,
+
+    /// Follow only the first parent of merge commits, so the git log reads like the history
+    /// of the branch itself
+    #[clap(long)]
+    pub log_first_parent: bool,
+
+    /// Summarize the main contributors (commit counts and last activity, resolved through
+    /// .mailmap), over --git-log-range if set
+    #[clap(long)]
+    pub contributors: bool,
+
+    /// Maximum number of contributors listed with --contributors
+    #[clap(long, value_name = "N", default_value_t = DEFAULT_CONTRIBUTORS_COUNT, requires = "contributors")]
+    pub contributors_max: usize,
+
+    /// Maximum number of recent commits walked to find the contributors
+    #[clap(long, value_name = "N", default_value_t = DEFAULT_CONTRIBUTORS_COMMIT_LIMIT, requires = "contributors")]
+    pub contributors_commit_limit: usize,
+
+    /// Add line numbers to the source code, e.g. `  84 | `, kept by the stubs and truncations
     #[clap(short, long)]
     pub line_numbers: bool,
 
-    /// If true, paths in the output will be absolute instead of relative.
+    /// Annotate each source line with the commit, author and age of its last change
     #[clap(long)]
+    pub blame: bool,
+
+    /// Emit one blame summary line per hunk instead of annotating every line
+    #[clap(long, requires = "blame")]
+    pub blame_hunks: bool,
+
+    /// Skip blame annotations for files with more lines than this
+    #[clap(long, value_name = "N", default_value_t = 5000)]
+    pub blame_max_lines: usize,
+
+    /// Head each file with the commit, author, date and summary of its last change
+    #[clap(long)]
+    pub file_git_info: bool,
+
+    /// If true, paths in the output will be absolute instead of relative, like --path-style
+    /// absolute
+    #[clap(long, conflicts_with = "path_style")]
     pub absolute_paths: bool,
 
+    /// How the paths are written in the output: "relative" to the directory, "absolute", or
+    /// "strip:<prefix>" for the relative paths without a leading directory, e.g.
+    /// strip:packages/api. The separators are always "/"
+    #[clap(long, value_name = "STYLE")]
+    pub path_style: Option<PathStyle>,
+
+    /// What is written for the binary files: "skip" them, a "placeholder" line with the path,
+    /// the size and the MIME type, or "base64:<max-bytes>" for the content of the images and
+    /// fonts of at most that many bytes, a placeholder for the others. The tree lists them
+    #[clap(long, value_name = "POLICY", default_value = "skip")]
+    pub binary_policy: BinaryPolicy,
+
     /// Follow symlinks
     #[clap(short = 'L', long)]
     pub follow_symlinks: bool,
@@ -86,15 +522,52 @@ pub struct Cli {
     #[clap(long)]
     pub no_codeblock: bool,
 
+    /// Leave the source tree out of the prompt
+    #[clap(long)]
+    pub no_tree: bool,
+
+    /// Leave the contents of the files out of the prompt, which keeps the source tree
+    #[clap(long)]
+    pub no_contents: bool,
+
+    /// Only the source tree, without the contents of the files. The token count of the full
+    /// prompt is reported too, as hypothetical
+    #[clap(long, conflicts_with_all = ["no_tree", "no_contents"])]
+    pub tree_only: bool,
+
     /// Optional Disable copying to clipboard
     #[clap(long)]
     pub no_clipboard: bool,
 
-    /// Skip .gitignore rules
+    /// Clipboard to copy the prompt to: none, system, or osc52 for an escape sequence to the
+    /// terminal, which works over SSH. [default: osc52 without an X11 or Wayland display or in
+    /// an SSH session, system otherwise]
+    #[clap(long, value_name = "BACKEND", conflicts_with = "no_clipboard")]
+    pub clipboard: Option<ClipboardBackend>,
+
+    /// Skip the rules of the .gitignore files and of .git/info/exclude
     #[clap(long)]
     pub no_ignore: bool,
 
-    /// Sort order for files: one of "name_asc", "name_desc", "date_asc", or "date_desc"
+    /// Only include files tracked by git (listed in the index)
+    #[clap(long)]
+    pub tracked_only: bool,
+
+    /// Omit the content of files marked linguist-generated or export-ignore in .gitattributes (default)
+    #[clap(long, overrides_with = "no_respect_gitattributes")]
+    pub respect_gitattributes: bool,
+
+    /// Include files marked linguist-generated or export-ignore in .gitattributes
+    #[clap(long, overrides_with = "respect_gitattributes")]
+    pub no_respect_gitattributes: bool,
+
+    /// Traverse git submodules and include their changes in git diffs
+    #[clap(long)]
+    pub include_submodules: bool,
+
+    /// Sort order for files: one of "name_asc", "name_desc", "date_asc", "date_desc",
+    /// "tokens_asc", "tokens_desc", "size_asc" or "size_desc". Every order is stable: paths are
+    /// compared byte-wise, and break the ties of the other orders
     #[clap(long)]
     pub sort: Option<String>,
 
@@ -102,6 +575,19 @@ pub struct Cli {
     #[clap(short, long)]
     pub quiet: bool,
 
+    /// Start the prompt with the number of files, bytes and tokens of each directory
+    #[clap(long)]
+    pub dir_summary: bool,
+
+    /// Add a table of contents after the tree, linking to a heading above each file, to
+    /// navigate the Markdown prompt. The other output formats ignore it
+    #[clap(long)]
+    pub toc: bool,
+
+    /// Number of directory levels of the directory summary (default: 1, the top-level directories)
+    #[clap(long, value_name = "N")]
+    pub dir_summary_depth: Option<usize>,
+
     /// Display a visual token map of files (similar to disk usage tools)
     #[clap(long)]
     pub token_map: bool,
@@ -110,6 +596,10 @@ pub struct Cli {
     #[clap(long, value_name = "NUMBER")]
     pub token_map_lines: Option<usize>,
 
+    /// Number of directory levels in the token map; deeper directories are collapsed
+    #[clap(long, value_name = "N", requires = "token_map")]
+    pub token_map_depth: Option<usize>,
+
     /// Minimum percentage of tokens to display in token map (default: 0.1%)
     #[clap(long, value_name = "PERCENT")]
     pub token_map_min_percent: Option<f64>,
@@ -117,3 +607,33 @@ pub struct Cli {
     #[arg(long, hide = true)]
     pub clipboard_daemon: bool,
 }
+
+/// Checks the `--date-format` value, so that an invalid format fails before the codebase is read.
+fn parse_date_format(value: &str) -> Result<String, String> {
+    check_date_format(value)
+        .map(|_| value.to_string())
+        .map_err(|e| e.to_string())
+}
+
+/// Checks the `--locale` value.
+fn parse_locale_name(value: &str) -> Result<String, String> {
+    parse_locale(value)
+        .map(|_| value.to_string())
+        .map_err(|e| e.to_string())
+}
+
+/// Parses the `--tokens` value into the display format and the token count mode.
+fn parse_tokens(value: &str) -> Result<(TokenFormat, TokenCountMode), String> {
+    match value.parse::<TokenCountMode>() {
+        Ok(TokenCountMode::Exact) | Err(_) => value
+            .parse::<TokenFormat>()
+            .map(|format| (format, TokenCountMode::Exact))
+            .map_err(|_| {
+                format!(
+                    "Invalid token option: {}. Use 'raw', 'format', 'estimate' or 'off'.",
+                    value
+                )
+            }),
+        Ok(mode) => Ok((TokenFormat::Format, mode)),
+    }
+}
@@ -1,4 +1,102 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::io::{IsTerminal, Write};
+
+/// The clipboard the prompt is copied to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ClipboardBackend {
+    /// Don't copy the prompt
+    None,
+    /// The clipboard of the display, X11 or Wayland on Linux
+    System,
+    /// An OSC 52 escape sequence to the terminal, which copies the prompt on the machine of the
+    /// terminal, e.g. over SSH
+    Osc52,
+}
+
+impl ClipboardBackend {
+    /// Returns OSC 52 in an SSH session or, on Linux, without an X11 or Wayland display, and the
+    /// system clipboard otherwise.
+    pub fn detect() -> Self {
+        let is_set = |name: &str| std::env::var_os(name).is_some_and(|value| !value.is_empty());
+        let has_display =
+            !cfg!(target_os = "linux") || is_set("DISPLAY") || is_set("WAYLAND_DISPLAY");
+        if !has_display || (is_set("SSH_TTY") && !cfg!(target_os = "linux")) {
+            ClipboardBackend::Osc52
+        } else {
+            ClipboardBackend::System
+        }
+    }
+}
+
+/// The size of an OSC 52 payload above which some terminals cut or ignore the sequence.
+pub const OSC52_LIMIT: usize = 100_000;
+
+/// The size of the pieces of a sequence passed through GNU screen, which limits the length of
+/// its strings.
+const SCREEN_CHUNK_SIZE: usize = 76;
+
+/// The terminal multiplexer an OSC 52 sequence is wrapped for, so that it reaches the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Multiplexer {
+    None,
+    Tmux,
+    Screen,
+}
+
+impl Multiplexer {
+    fn detect() -> Self {
+        if std::env::var_os("TMUX").is_some() {
+            Multiplexer::Tmux
+        } else if std::env::var("TERM").is_ok_and(|term| term.starts_with("screen")) {
+            Multiplexer::Screen
+        } else {
+            Multiplexer::None
+        }
+    }
+}
+
+/// Returns the size of the base64 payload of the OSC 52 sequence of a text.
+pub fn osc52_payload_len(text: &str) -> usize {
+    text.len().div_ceil(3) * 4
+}
+
+/// Returns the OSC 52 sequence setting the clipboard to a text, wrapped for the multiplexer.
+fn osc52_sequence(text: &str, multiplexer: Multiplexer) -> String {
+    let sequence = format!("\x1b]52;c;{}\x07", STANDARD.encode(text));
+    match multiplexer {
+        Multiplexer::None => sequence,
+        Multiplexer::Tmux => format!("\x1bPtmux;\x1b{}\x1b\\", sequence),
+        // The sequence is ASCII, so the pieces are too
+        Multiplexer::Screen => sequence
+            .as_bytes()
+            .chunks(SCREEN_CHUNK_SIZE)
+            .map(|piece| format!("\x1bP{}\x1b\\", String::from_utf8_lossy(piece)))
+            .collect(),
+    }
+}
+
+/// Copies a text to the clipboard of the terminal with an OSC 52 escape sequence, written to
+/// the controlling terminal, or to stderr if it is a terminal.
+///
+/// The terminal doesn't answer, so a terminal without OSC 52 support ignores the sequence.
+pub fn copy_with_osc52(text: &str) -> Result<()> {
+    let sequence = osc52_sequence(text, Multiplexer::detect());
+    #[cfg(unix)]
+    if let Ok(mut tty) = std::fs::OpenOptions::new().write(true).open("/dev/tty") {
+        tty.write_all(sequence.as_bytes())
+            .context("Failed to write the OSC 52 sequence to the terminal")?;
+        return Ok(tty.flush()?);
+    }
+    if !std::io::stderr().is_terminal() {
+        bail!("No terminal to send the OSC 52 sequence to");
+    }
+    let mut stderr = std::io::stderr().lock();
+    stderr
+        .write_all(sequence.as_bytes())
+        .context("Failed to write the OSC 52 sequence to the terminal")?;
+    Ok(stderr.flush()?)
+}
 
 #[cfg(not(target_os = "linux"))]
 /// Copies the provided text to the system clipboard.
@@ -109,3 +207,41 @@ pub fn spawn_clipboard_daemon(content: &str) -> Result<()> {
     );
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_osc52_sequence() {
+        assert_eq!(
+            osc52_sequence("hello", Multiplexer::None),
+            "\x1b]52;c;aGVsbG8=\x07"
+        );
+        assert_eq!(
+            osc52_sequence("hello", Multiplexer::Tmux),
+            "\x1bPtmux;\x1b\x1b]52;c;aGVsbG8=\x07\x1b\\"
+        );
+        assert_eq!(osc52_payload_len("hello"), "aGVsbG8=".len());
+        assert_eq!(osc52_payload_len(""), 0);
+    }
+
+    #[test]
+    fn test_osc52_sequence_in_screen_chunks() {
+        let text = "x".repeat(200);
+        let sequence = osc52_sequence(&text, Multiplexer::Screen);
+        let pieces: Vec<&str> = sequence
+            .split("\x1b\\")
+            .filter(|piece| !piece.is_empty())
+            .collect();
+        assert!(pieces.len() > 1);
+        assert!(pieces
+            .iter()
+            .all(|piece| piece.starts_with("\x1bP") && piece.len() <= SCREEN_CHUNK_SIZE + 2));
+        let unwrapped: String = pieces
+            .iter()
+            .map(|piece| piece.trim_start_matches("\x1bP"))
+            .collect();
+        assert_eq!(unwrapped, osc52_sequence(&text, Multiplexer::None));
+    }
+}
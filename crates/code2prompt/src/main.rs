@@ -5,38 +5,165 @@ mod args;
 mod clipboard;
 mod token_map;
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use args::Cli;
-use clap::Parser;
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches};
+use clipboard::{copy_with_osc52, osc52_payload_len, ClipboardBackend, OSC52_LIMIT};
 use code2prompt_core::{
     configuration::Code2PromptConfig,
+    engine::{renderer, RenderOptions, TemplateEngine},
+    front_matter::{split_front_matter, FrontMatter},
+    git::{parse_git_date, BlameMode, DiffConfig, GitLogOptions},
+    manifest::manifest_csv,
+    output::{
+        append_to_file, output_path_tokens, part_file_path, resolve_output_path,
+        write_compressed_to_file, write_to_file, Compression,
+    },
+    path::PathStyle,
+    pricing::PricingTable,
+    project_config::{load_project_config, resolve_template, CONFIG_FILE, TEMPLATE_ENV_VAR},
+    remote::{clone_repository, is_remote_url, CloneOptions, ClonedRepository},
+    run_history::{compare_runs, RunHistory},
     session::Code2PromptSession,
     sort::FileSortMethod,
-    template::{extract_undefined_variables, write_to_file},
-    tokenizer::{TokenFormat, TokenizerType},
+    template::{
+        elide_file_contents, extract_undefined_variables, extract_user_variables,
+        file_format_tokens, read_partials_dir, validate_file_template, validate_template,
+        FormatOptions, IncludeOptions, UnknownKey, CONTEXT_PREVIEW_CHARS,
+    },
+    template_library::{
+        builtin_template, find_template, list_templates, TemplateSection, TemplateSource,
+        BUILTIN_TEMPLATES,
+    },
+    token_cache::{clear_cache, TokenCache},
+    tokenizer::{TokenCountMode, TokenFormat},
+    util::format_size,
 };
 use colored::*;
 use indicatif::{ProgressBar, ProgressStyle};
 use inquire::Text;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use num_format::{SystemLocale, ToFormattedString};
-use std::{path::PathBuf, str::FromStr};
+use std::{
+    collections::HashMap,
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Mutex, PoisonError},
+};
+
+/// Number of files listed in the largest files line of the stats.
+const TOP_FILES_BY_TOKENS: usize = 5;
+
+/// The clone of a remote repository, deleted once the run ends, even by [`exit`].
+static CLONED_REPOSITORY: Mutex<Option<ClonedRepository>> = Mutex::new(None);
 
 fn main() -> Result<()> {
+    let result = run();
+    remove_cloned_repository();
+    result
+}
+
+/// Deletes the clone of a remote repository, if any.
+fn remove_cloned_repository() {
+    drop(
+        CLONED_REPOSITORY
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take(),
+    );
+}
+
+/// Exits with the given code once the clone of a remote repository is deleted, which
+/// `std::process::exit` would leave behind since it skips the destructors.
+fn exit(code: i32) -> ! {
+    remove_cloned_repository();
+    std::process::exit(code)
+}
+
+fn run() -> Result<()> {
     env_logger::init();
     info! {"Args: {:?}", std::env::args().collect::<Vec<_>>()};
-    let args = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let mut args = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
 
     // ~~~ Arguments Validation ~~~
     // if no_clipboard is true, output_file must be specified.
-    if args.no_clipboard && args.output_file.is_none() {
-        eprintln!("Error: --output-file is required when --no-clipboard is used.");
-        std::process::exit(1);
+    let clipboard_disabled = args.no_clipboard || args.clipboard == Some(ClipboardBackend::None);
+    if clipboard_disabled && args.output_file.is_none() && args.output_dir.is_none() {
+        eprintln!(
+            "Error: --output-file is required when --no-clipboard or --clipboard none is used."
+        );
+        exit(1);
+    }
+
+    // Without token counts, the options cutting or mapping the tokens have nothing to work with
+    if args.tokens.1 == TokenCountMode::Off {
+        let token_options = [
+            ("--token-budget", args.token_budget.is_some()),
+            ("--max-tokens-per-file", args.max_tokens_per_file.is_some()),
+            ("--diff-token-limit", args.diff_token_limit.is_some()),
+            ("--token-map", args.token_map),
+            ("--context-window", args.context_window.is_some()),
+            (
+                "--chunk-size",
+                args.chunk_size.is_some_and(|size| size.is_tokens()),
+            ),
+        ];
+        if let Some((option, _)) = token_options.iter().find(|(_, used)| *used) {
+            eprintln!(
+                "Error: {} needs the tokens to be counted or estimated, not --tokens off.",
+                option
+            );
+            exit(1);
+        }
+    }
+
+    if args.split && args.output_file.as_deref() == Some("-") {
+        eprintln!("Error: --split writes numbered files and can't write to stdout.");
+        exit(1);
+    }
+    if args.chunk_size.is_some() && args.output_file.as_deref() == Some("-") {
+        eprintln!("Error: --chunk-size writes numbered files and can't write to stdout.");
+        exit(1);
+    }
+    // An unknown token of the output path fails before anything is generated
+    if let Some(Err(e)) = args.output_file.as_deref().map(output_path_tokens) {
+        eprintln!("Error: {}", e);
+        exit(1);
+    }
+    // And so does an unknown token of the file header and footer formats
+    let file_formats = [&args.file_header_format, &args.file_footer_format];
+    if let Some(Err(e)) = file_formats
+        .into_iter()
+        .flatten()
+        .map(|format| file_format_tokens(format))
+        .find(Result::is_err)
+    {
+        eprintln!("Error: {}", e);
+        exit(1);
+    }
+
+    // A .gz or .zst output file is compressed without --compress
+    let compression = args
+        .compress
+        .or_else(|| args.output_file.as_deref().and_then(Compression::from_path));
+    if args.compress.is_some() && args.output_file.is_none() && args.output_dir.is_none() {
+        eprintln!("Error: --compress needs --output-file or --output-dir.");
+        exit(1);
+    }
+    if let Some(Err(e)) = compression.map(|compression| compression.ensure_supported()) {
+        eprintln!("Error: {}", e);
+        exit(1);
+    }
+    if let Err(e) = args.output_format.ensure_supported() {
+        eprintln!("Error: {}", e);
+        exit(1);
     }
 
     // Disable clipboard when outputting to stdout (unless clipboard is explicitly enabled)
-    let no_clipboard = args.no_clipboard || 
-        args.output_file.as_ref().map_or(false, |f| f == "-");
+    let no_clipboard = clipboard_disabled || 
+        args.output_file.as_ref().is_some_and(|f| f == "-");
 
     // ~~~ Clipboard Daemon ~~~
     #[cfg(target_os = "linux")]
@@ -50,12 +177,217 @@ fn main() -> Result<()> {
         }
     }
 
+    // ~~~ Remote Repository ~~~
+    // The clone lives until the end of the run so the session can read it, unless it is kept
+    let remote_url = args.path.to_string_lossy().to_string();
+    let mut kept_clone = None;
+    let path = if is_remote_url(&remote_url) {
+        let clone_options = CloneOptions {
+            branch: args.remote_branch.clone(),
+            ..CloneOptions::default()
+        };
+        let cloned = clone_repository(&remote_url, &clone_options).unwrap_or_else(|e| {
+            error!("{}", e);
+            exit(1);
+        });
+        if args.keep_clone {
+            let kept_path = cloned.keep();
+            kept_clone = Some(kept_path.clone());
+            kept_path
+        } else {
+            let path = cloned.path().to_path_buf();
+            *CLONED_REPOSITORY
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner) = Some(cloned);
+            path
+        }
+    } else {
+        args.path.clone()
+    };
+
+    // ~~~ Output Path ~~~
+    let mut output_path_resolved = false;
+    if let Some(output_file) = &args.output_file {
+        let resolved = resolve_output_path(output_file, &path).unwrap_or_else(|e| {
+            eprintln!("Error: {:#}", e);
+            exit(1);
+        });
+        output_path_resolved = resolved != *output_file;
+        args.output_file = Some(resolved);
+    }
+
+    // ~~~ Template Listing ~~~
+    if args.list_templates {
+        print_templates(&path).unwrap_or_else(|e| {
+            error!("Failed to list the templates: {:#}", e);
+            exit(1);
+        });
+        return Ok(());
+    }
+
+    // ~~~ Configuration File ~~~
+    let project_config = load_project_config(&path).unwrap_or_else(|e| {
+        error!("{:#}", e);
+        exit(1);
+    });
+
+    // ~~~ Template ~~~
+    // Without --template, the environment or the configuration file can set the template
+    if args.template_builtin.is_none() {
+        let env_template = std::env::var(TEMPLATE_ENV_VAR).ok();
+        let config_template = project_config
+            .as_ref()
+            .map(|(file, config)| (file.as_path(), config));
+        let config_file = config_template.map(|(file, _)| file.display().to_string());
+        debug!(
+            "Template resolution, the first one set wins: --template {:?}, {} {:?}, {} {:?}, \
+             then the default template",
+            args.template,
+            TEMPLATE_ENV_VAR,
+            env_template,
+            config_file.as_deref().unwrap_or(CONFIG_FILE),
+            config_template.and_then(|(_, config)| config.template.as_deref()),
+        );
+        let choice = resolve_template(
+            args.template.as_deref(),
+            env_template.as_deref(),
+            config_template,
+        );
+        debug!(
+            "Using the template {:?} from {:?}",
+            choice.template, choice.origin
+        );
+        args.template = choice.template;
+    }
+    // The front-matter of the template sets defaults for the options below
+    let parsed_template = match &args.template_builtin {
+        Some(name) => parse_builtin_template(name),
+        None => parse_template(&args.template, &path, args.quiet),
+    };
+    let (template_source, template_name, template_file) = parsed_template.unwrap_or_else(|e| {
+        error!("Failed to parse template: {:#}", e);
+        exit(1);
+    });
+    let template_str = split_front_matter(&template_source)
+        .map(|(front_matter, body)| {
+            apply_front_matter(&mut args, &matches, front_matter);
+            body.to_string()
+        })
+        .unwrap_or_else(|e| {
+            error!("Failed to parse template: {:#}", e);
+            exit(1);
+        });
+    // The changes of the changed files are described by the commits between the same references
+    if args.changed_files_only {
+        let Some(refs) = args.git_diff_branch.clone() else {
+            error!(
+                "The template {} only includes the files changed between two references: \
+                 pass them with --git-diff-branch",
+                template_name
+            );
+            exit(1);
+        };
+        if args.git_log_branch.is_none() && args.git_log_range.is_none() {
+            args.git_log_branch = Some(refs.clone());
+        }
+        if args.git_changelog.is_none() {
+            args.git_changelog = Some(refs.join(".."));
+        }
+    }
+    if template_file.is_none() && !args.quiet {
+        let diff_passed = args.diff
+            || args.git_diff_branch.is_some()
+            || args.git_diff_against.is_some()
+            || args.git_diff_stash.is_some()
+            || args.git_show.is_some()
+            || args.git_diff_latest_tags;
+        let log_passed = args.git_log_branch.is_some()
+            || args.git_log_range.is_some()
+            || args.git_diff_latest_tags;
+        warn_missing_sections(&template_name, diff_passed, log_passed);
+    }
+    let template_partials = parse_partials(template_file.as_deref(), &args.template_partial)
+        .unwrap_or_else(|e| {
+            error!("Failed to load the template partials: {:#}", e);
+            exit(1);
+        });
+    let template_engine = args
+        .template_engine
+        .or_else(|| template_file.as_deref().and_then(TemplateEngine::from_path))
+        .unwrap_or_default();
+
+    let file_template = args.file_template.as_deref().map(|value| {
+        parse_file_template(value).unwrap_or_else(|e| {
+            error!("Failed to load the file template: {:#}", e);
+            exit(1);
+        })
+    });
+
+    // The templates are checked before the codebase is read
+    if let Some(file_template) = &file_template {
+        let validated_name = args.file_template.as_deref().unwrap_or_default();
+        let validated_name = match Path::new(validated_name).is_file() {
+            true => validated_name,
+            false => "--file-template",
+        };
+        // A file template is written for the engine of the template
+        if template_engine == TemplateEngine::Handlebars {
+            let unknown_keys =
+                validate_file_template(file_template, validated_name, &template_partials)
+                    .unwrap_or_else(|e| {
+                        error!("{:#}", e);
+                        exit(1);
+                    });
+            if !args.quiet {
+                warn_unknown_keys(validated_name, file_template, &unknown_keys, &args);
+            }
+        }
+    }
+    if !template_source.is_empty() {
+        let validated_name = template_file
+            .as_deref()
+            .map_or(template_name.clone(), |file| file.display().to_string());
+        let validation = match template_engine {
+            TemplateEngine::Handlebars => {
+                validate_template(&template_source, &validated_name, &template_partials)
+            }
+            // Only the syntax of a Jinja template is checked
+            TemplateEngine::Jinja => renderer(
+                template_engine,
+                &template_str,
+                &validated_name,
+                &template_partials,
+                &RenderOptions::default(),
+            )
+            .map(|_| Vec::new()),
+        };
+        let unknown_keys = validation.unwrap_or_else(|e| {
+            error!("{:#}", e);
+            exit(1);
+        });
+        if !args.quiet {
+            warn_unknown_keys(&validated_name, &template_str, &unknown_keys, &args);
+        }
+        if args.validate_template {
+            if !args.quiet {
+                println!(
+                    "{}{}{} {}",
+                    "[".bold().white(),
+                    "✓".bold().green(),
+                    "]".bold().white(),
+                    format!("The template {} is valid", validated_name).green()
+                );
+            }
+            return Ok(());
+        }
+    }
+
     // ~~~ Configuration ~~~
     let mut configuration = Code2PromptConfig::builder();
 
     // Configure Path
     configuration
-        .path(args.path.clone())
+        .path(path.clone())
         .include_priority(args.include_priority);
 
     // Configure Selection Patterns
@@ -67,9 +399,26 @@ fn main() -> Result<()> {
     let output_format = args.output_format.clone();
     configuration
         .line_numbers(args.line_numbers)
-        .absolute_path(args.absolute_paths)
+        .path_style(match args.path_style.clone() {
+            Some(path_style) => path_style,
+            None if args.absolute_paths => PathStyle::Absolute,
+            None => PathStyle::Relative,
+        })
+        .binary_policy(args.binary_policy)
         .full_directory_tree(args.full_directory_tree)
-        .output_format(output_format);
+        .output_format(output_format)
+        .json_compact(args.json_compact);
+
+    // Configure Blame
+    let blame = match (args.blame, args.blame_hunks) {
+        (_, true) => Some(BlameMode::Hunks),
+        (true, false) => Some(BlameMode::Lines),
+        (false, false) => None,
+    };
+    configuration
+        .blame(blame)
+        .blame_max_lines(Some(args.blame_max_lines))
+        .file_git_info(args.file_git_info);
 
     // Configure Sort Method
     let sort_method = args
@@ -79,50 +428,180 @@ fn main() -> Result<()> {
         .transpose()
         .unwrap_or_else(|err| {
             eprintln!("{}", err);
-            std::process::exit(1);
+            exit(1);
         })
         .unwrap_or(FileSortMethod::NameAsc);
 
     configuration.sort_method(sort_method);
 
     // Configure Tokenizer
-    let tokenizer_type = args
-        .encoding
-        .as_deref()
-        .unwrap_or("cl100k")
-        .parse::<TokenizerType>()
-        .unwrap_or_default();
-
+    #[cfg(feature = "huggingface")]
+    let encoding = match &args.hf_tokenizer {
+        Some(path) => code2prompt_core::tokenizer::TokenizerType::HuggingFace(path.clone()),
+        None => args.encoding,
+    };
+    #[cfg(not(feature = "huggingface"))]
+    let encoding = args.encoding;
+    if let Err(e) = encoding.load() {
+        error!("{:#}", e);
+        exit(1);
+    }
     configuration
-        .encoding(tokenizer_type)
-        .token_format(args.tokens);
+        .encoding(encoding)
+        .token_format(args.tokens.0)
+        .token_count_mode(args.tokens.1)
+        .cost_models(args.model_cost.clone())
+        .pricing_file(args.pricing_file.clone())
+        .jobs(args.jobs.map(usize::from))
+        .strict(args.strict)
+        .token_cache(!args.no_cache)
+        .cache_dir(args.cache_dir.clone())
+        .max_tokens_per_file(args.max_tokens_per_file)
+        .max_lines_per_file(args.max_lines_per_file)
+        .token_budget(args.token_budget)
+        .budget_strategy(args.budget_strategy)
+        .priority_patterns(args.priority);
+
+    // A cache that can't be cleared is only reported: the run never fails on the cache
+    if args.clear_cache {
+        if let Some(cache_dir) = args.cache_dir.clone().or_else(TokenCache::default_dir) {
+            match clear_cache(&cache_dir) {
+                Ok(()) => info!("Cleared the token cache in {}", cache_dir.display()),
+                Err(e) => warn!("{:#}", e),
+            }
+        }
+    }
+
+    // Check the cost models before traversing the codebase
+    if !args.model_cost.is_empty() {
+        let pricing = PricingTable::load(args.pricing_file.as_deref()).unwrap_or_else(|e| {
+            error!("{:#}", e);
+            exit(1);
+        });
+        for model in &args.model_cost {
+            if let Err(e) = pricing.price(model) {
+                error!("{}", e);
+                exit(1);
+            }
+        }
+    }
+
+    // Resolve the context window before traversing the codebase
+    let context_window = args.context_window.as_deref().map(|window| {
+        parse_context_window(window, args.pricing_file.as_deref()).unwrap_or_else(|e| {
+            error!("{:#}", e);
+            exit(1);
+        })
+    });
 
     // Configure Template
-    let (template_str, template_name) = parse_template(&args.template).unwrap_or_else(|e| {
-        error!("Failed to parse template: {}", e);
-        std::process::exit(1);
+    let user_variables = parse_user_variables(
+        &args.define,
+        std::iter::once(template_str.as_str())
+            .chain(file_template.as_deref())
+            .chain(template_partials.values().map(String::as_str)),
+        args.no_input,
+    )
+    .unwrap_or_else(|e| {
+        error!("{:#}", e);
+        exit(1);
     });
 
     configuration
         .template_str(template_str.clone())
-        .template_name(template_name);
+        .template_name(template_name)
+        .template_engine(template_engine)
+        .template_partials(template_partials)
+        .file_template(file_template)
+        .include_options(IncludeOptions {
+            base_dir: template_file
+                .as_deref()
+                .and_then(Path::parent)
+                .map(Path::to_path_buf),
+            roots: args.include_root.clone(),
+        })
+        .escape_html(args.escape_html)
+        .format_options(FormatOptions {
+            date_format: args.date_format.clone(),
+            locale: args.locale.clone(),
+        })
+        .user_variables(user_variables)
+        .languages(
+            project_config
+                .map(|(_, project_config)| project_config.languages)
+                .unwrap_or_default(),
+        );
 
     // Configure Git
     let diff_branches = parse_branch_argument(&args.git_diff_branch);
     let log_branches = parse_branch_argument(&args.git_log_branch);
 
+    let diff_config = DiffConfig {
+        context_lines: args.diff_context,
+        include_untracked: args.diff_untracked,
+        include_ignored: args.diff_include_ignored,
+        whitespace: args.diff_whitespace,
+        render_style: args.diff_style,
+        skip_binary: args.diff_skip_binary,
+        rename_threshold: args.diff_rename_threshold,
+        pathspecs: args.diff_path,
+        merge_base: args.git_diff_merge_base,
+        ..DiffConfig::default()
+    };
+
+    let log_options = GitLogOptions {
+        include_body: args.log_with_body,
+        include_author: args.log_with_author,
+        include_date: args.log_with_date,
+        date_format: args.log_date_format,
+        max_commits: args.log_max_count,
+        include_files: args.log_files,
+        max_files: Some(args.log_files_max),
+        author: args.log_author,
+        since: args.log_since.as_deref().map(parse_log_date),
+        until: args.log_until.as_deref().map(parse_log_date),
+        skip_merges: args.log_no_merges,
+        first_parent: args.log_first_parent,
+    };
+
     configuration
         .diff_enabled(args.diff)
+        .diff_config(diff_config)
         .diff_branches(diff_branches)
-        .log_branches(log_branches);
+        .diff_stat(args.git_diff_stat)
+        .diff_latest_tags(args.git_diff_latest_tags)
+        .diff_stash(args.git_diff_stash)
+        .diff_against(args.git_diff_against.clone())
+        .show_commit(args.git_show.clone())
+        .diff_token_limit(args.diff_token_limit)
+        .log_branches(log_branches)
+        .log_range(args.git_log_range)
+        .modified_since(args.modified_since)
+        .changed_files_only(args.changed_files_only)
+        .changelog_range(args.git_changelog.as_deref().map(parse_changelog_range))
+        .log_options(log_options)
+        .contributors_enabled(args.contributors)
+        .contributors_max(Some(args.contributors_max))
+        .contributors_commit_limit(Some(args.contributors_commit_limit));
 
     // Boolean arguments
     configuration
         .no_ignore(args.no_ignore)
+        .tracked_only(args.tracked_only)
+        .respect_gitattributes(!args.no_respect_gitattributes)
+        .include_submodules(args.include_submodules)
         .hidden(args.hidden)
         .no_codeblock(args.no_codeblock)
+        .no_tree(args.no_tree)
+        .no_contents(args.no_contents || args.tree_only)
         .follow_symlinks(args.follow_symlinks)
-        .token_map_enabled(args.token_map);
+        .token_map_enabled(args.token_map)
+        .token_map_depth(args.token_map_depth)
+        .dir_summary(args.dir_summary)
+        .toc(args.toc)
+        .file_header_format(args.file_header_format.clone())
+        .file_footer_format(args.file_footer_format.clone())
+        .dir_summary_depth(args.dir_summary_depth);
 
     // ~~~ Code2Prompt ~~~
     let mut session = Code2PromptSession::new(configuration.build()?);
@@ -138,9 +617,51 @@ fn main() -> Result<()> {
         if let Some(ref s) = spinner {
             s.finish_with_message("Failed!".red().to_string());
         }
-        error!("Failed to build directory tree: {}", e);
-        std::process::exit(1);
+        error!("Failed to build directory tree: {:#}", e);
+        exit(1);
     });
+    // Load Git log for a revision range if provided
+    if session.config.log_range.is_some() {
+        if let Some(ref s) = spinner {
+            s.set_message("Generating git log for the revision range...");
+        }
+        session.load_git_log_range().unwrap_or_else(|e| {
+            if let Some(ref s) = spinner {
+                s.finish_with_message("Failed!".red().to_string());
+            }
+            error!("Failed to generate git log: {}", e);
+            exit(1);
+        });
+    }
+
+    // Load conventional changelog if provided
+    if session.config.changelog_range.is_some() {
+        if let Some(ref s) = spinner {
+            s.set_message("Generating changelog...");
+        }
+        session.load_git_changelog().unwrap_or_else(|e| {
+            if let Some(ref s) = spinner {
+                s.finish_with_message("Failed!".red().to_string());
+            }
+            error!("Failed to generate changelog: {}", e);
+            exit(1);
+        });
+    }
+
+    // Load contributors if requested
+    if session.config.contributors_enabled {
+        if let Some(ref s) = spinner {
+            s.set_message("Summarizing contributors...");
+        }
+        session.load_contributors().unwrap_or_else(|e| {
+            if let Some(ref s) = spinner {
+                s.finish_with_message("Failed!".red().to_string());
+            }
+            error!("Failed to summarize contributors: {}", e);
+            exit(1);
+        });
+    }
+
     if let Some(ref s) = spinner {
         s.finish_with_message("Done!".green().to_string());
     }
@@ -156,7 +677,7 @@ fn main() -> Result<()> {
                 s.finish_with_message("Failed!".red().to_string());
             }
             error!("Failed to generate git diff: {}", e);
-            std::process::exit(1);
+            exit(1);
         });
     }
 
@@ -172,10 +693,66 @@ fn main() -> Result<()> {
                     s.finish_with_message("Failed!".red().to_string());
                 }
                 error!("Failed to generate git diff: {}", e);
-                std::process::exit(1);
+                exit(1);
             });
     }
 
+    // Load Git diff and log between the latest tags if requested
+    if session.config.diff_latest_tags {
+        if let Some(ref s) = spinner {
+            s.set_message("Generating git diff between the latest tags...");
+        }
+        session.load_git_diff_latest_tags().unwrap_or_else(|e| {
+            if let Some(ref s) = spinner {
+                s.finish_with_message("Failed!".red().to_string());
+            }
+            error!("Failed to generate git diff between the latest tags: {}", e);
+            exit(1);
+        });
+    }
+
+    // Load Git stash diff if provided
+    if session.config.diff_stash.is_some() {
+        if let Some(ref s) = spinner {
+            s.set_message("Generating git stash diff...");
+        }
+        session.load_git_diff_stash().unwrap_or_else(|e| {
+            if let Some(ref s) = spinner {
+                s.finish_with_message("Failed!".red().to_string());
+            }
+            error!("Failed to generate git stash diff: {}", e);
+            exit(1);
+        });
+    }
+
+    // Load Git diff against a reference if provided
+    if session.config.diff_against.is_some() {
+        if let Some(ref s) = spinner {
+            s.set_message("Generating git diff against reference...");
+        }
+        session.load_git_diff_against().unwrap_or_else(|e| {
+            if let Some(ref s) = spinner {
+                s.finish_with_message("Failed!".red().to_string());
+            }
+            error!("Failed to generate git diff against reference: {}", e);
+            exit(1);
+        });
+    }
+
+    // Load the shown commit if provided
+    if session.config.show_commit.is_some() {
+        if let Some(ref s) = spinner {
+            s.set_message("Generating git show...");
+        }
+        session.load_git_show().unwrap_or_else(|e| {
+            if let Some(ref s) = spinner {
+                s.finish_with_message("Failed!".red().to_string());
+            }
+            error!("Failed to generate git show: {}", e);
+            exit(1);
+        });
+    }
+
     // Load Git log between branches if provided
     if session.config.log_branches.is_some() {
         if let Some(ref s) = spinner {
@@ -186,7 +763,21 @@ fn main() -> Result<()> {
                 s.finish_with_message("Failed!".red().to_string());
             }
             error!("Failed to generate git log: {}", e);
-            std::process::exit(1);
+            exit(1);
+        });
+    }
+
+    // Fit the files in the token budget if provided
+    if session.config.token_budget.is_some() {
+        if let Some(ref s) = spinner {
+            s.set_message("Fitting the prompt in the token budget...");
+        }
+        session.enforce_token_budget().unwrap_or_else(|e| {
+            if let Some(ref s) = spinner {
+                s.finish_with_message("Failed!".red().to_string());
+            }
+            error!("Failed to fit the prompt in the token budget: {}", e);
+            exit(1);
         });
     }
 
@@ -198,49 +789,455 @@ fn main() -> Result<()> {
 
     // Data
     let mut data = session.build_template_data();
-    handle_undefined_variables(&mut data, &template_str)?;
+    if !args.no_input {
+        handle_undefined_variables(&mut data, &template_str)?;
+    }
     debug!(
         "JSON Data: {}",
         serde_json::to_string_pretty(&data).unwrap()
     );
+    if args.show_context || args.show_context_full {
+        if !args.show_context_full {
+            elide_file_contents(&mut data, CONTEXT_PREVIEW_CHARS);
+        }
+        println!("{}", serde_json::to_string_pretty(&data)?);
+        return Ok(());
+    }
 
     // Render
     let rendered = session.render_prompt(&data).unwrap_or_else(|e| {
         error!("Failed to render prompt: {}", e);
-        std::process::exit(1);
+        exit(1);
     });
 
     // ~~~ Token Count ~~~
     let token_count = rendered.token_count;
-    let formatted_token_count: String = match session.config.token_format {
-        TokenFormat::Raw => token_count.to_string(),
-        TokenFormat::Format => token_count.to_formatted_string(&SystemLocale::default().unwrap()),
+    let token_count_mode = session.config.token_count_mode;
+    let format_count = |count: usize| -> String {
+        match session.config.token_format {
+            TokenFormat::Raw => count.to_string(),
+            TokenFormat::Format => count.to_formatted_string(&SystemLocale::default().unwrap()),
+        }
     };
+    let format_tokens = |count: usize| -> String {
+        match token_count_mode {
+            TokenCountMode::Estimate => format!("~{}", format_count(count)),
+            _ => format_count(count),
+        }
+    };
+    let formatted_token_count = format_tokens(token_count);
     let model_info = rendered.model_info;
 
-    if !args.quiet {
+    // ~~~ Previous Run ~~~
+    // The comparison with the previous run on the codebase is silently skipped without one
+    let mut run_history = args
+        .cache_dir
+        .clone()
+        .or_else(TokenCache::default_dir)
+        .filter(|_| token_count_mode != TokenCountMode::Off)
+        .map(|cache_dir| RunHistory::open(&cache_dir));
+    let run_key = RunHistory::key(&session.config);
+    let run_summary = session.run_summary(token_count);
+    let token_delta = run_history
+        .as_ref()
+        .and_then(|history| history.previous(&run_key))
+        .map(|previous| compare_runs(previous, &run_summary, TOP_FILES_BY_TOKENS));
+    let format_change = |change: i64| -> String {
+        let sign = match change {
+            change if change > 0 => "+",
+            change if change < 0 => "-",
+            _ => "±",
+        };
+        format!("{}{}", sign, format_count(change.unsigned_abs() as usize))
+    };
+
+    if !args.quiet && token_count_mode != TokenCountMode::Off {
         println!(
-            "{}{}{} Token count: {}, Model info: {}",
+            "{}{}{} Token count: {}{}{}, Encoding: {}, Model info: {}",
             "[".bold().white(),
             "i".bold().blue(),
             "]".bold().white(),
             formatted_token_count,
+            if token_count_mode == TokenCountMode::Estimate {
+                " (estimate)"
+            } else {
+                ""
+            },
+            token_delta
+                .as_ref()
+                .map(|delta| format!(" ({} vs last run)", format_change(delta.total_change())))
+                .unwrap_or_default(),
+            session.config.encoding,
             model_info
         );
+
+        if let Some(full_token_count) = rendered.full_token_count {
+            let left_out = match (session.config.no_tree, session.config.no_contents) {
+                (true, true) => "tree and file contents",
+                (true, false) => "tree",
+                _ => "file contents",
+            };
+            println!(
+                "{}{}{} Full prompt with the skipped {} (hypothetical): {} tokens",
+                "[".bold().white(),
+                "i".bold().blue(),
+                "]".bold().white(),
+                left_out,
+                format_tokens(full_token_count)
+            );
+        }
+
+        if let Some(delta) = token_delta.as_ref().filter(|_| args.diff_stats_prev) {
+            let changes: Vec<String> = delta
+                .changes
+                .iter()
+                .map(|file| {
+                    let note = match (file.before, file.after) {
+                        (None, _) => " (added)",
+                        (_, None) => " (removed)",
+                        _ => "",
+                    };
+                    format!("{} {}{}", file.path, format_change(file.change()), note)
+                })
+                .collect();
+            println!(
+                "{}{}{} Token changes vs last run: {}",
+                "[".bold().white(),
+                "i".bold().blue(),
+                "]".bold().white(),
+                if changes.is_empty() {
+                    "none".to_string()
+                } else {
+                    changes.join(", ")
+                }
+            );
+        }
+
+        let sections = rendered.section_tokens;
+        let section_rows = [
+            ("Source tree", sections.tree),
+            ("File contents", sections.files),
+            ("Git diff", sections.diff),
+            ("Git log", sections.log),
+            ("Template", sections.template),
+        ];
+        let count_width = section_rows
+            .iter()
+            .map(|(_, tokens)| format_tokens(*tokens).len())
+            .max()
+            .unwrap_or(0);
+        println!(
+            "{}{}{} Section tokens:",
+            "[".bold().white(),
+            "i".bold().blue(),
+            "]".bold().white()
+        );
+        for (section, tokens) in section_rows {
+            println!(
+                "    {:<14} {:>width$}",
+                section,
+                format_tokens(tokens),
+                width = count_width
+            );
+        }
+
+        let breakdown = rendered.token_breakdown;
+        println!(
+            "{}{}{} Token breakdown: file contents: {}, template overhead: {}, total: {}",
+            "[".bold().white(),
+            "i".bold().blue(),
+            "]".bold().white(),
+            format_tokens(breakdown.file_tokens),
+            format_tokens(breakdown.overhead_tokens),
+            format_tokens(breakdown.total_tokens)
+        );
+    }
+
+    if !args.quiet {
+        let stats = session.file_stats();
+        println!(
+            "{}{}{} Stats: {} files, {} lines, {} words, {}",
+            "[".bold().white(),
+            "i".bold().blue(),
+            "]".bold().white(),
+            format_count(stats.files),
+            format_count(stats.lines),
+            format_count(stats.words),
+            format_size(stats.bytes)
+        );
+
+        let file_warnings = &session.data.file_warnings;
+        if !file_warnings.is_empty() {
+            let warned: Vec<String> = file_warnings
+                .iter()
+                .map(|warning| format!("{} ({})", warning.path, warning.message))
+                .collect();
+            eprintln!(
+                "{}{}{} {}",
+                "[".bold().white(),
+                "!".bold().yellow(),
+                "]".bold().white(),
+                format!(
+                    "{} file(s) skipped or converted: {}",
+                    file_warnings.len(),
+                    warned.join(", ")
+                )
+                .yellow()
+            );
+        }
+
+        if !rendered.cost_estimates.is_empty() {
+            let estimates: Vec<String> = rendered
+                .cost_estimates
+                .iter()
+                .map(|estimate| {
+                    format!(
+                        "{} ${:.4} ({} tokens, {})",
+                        estimate.model,
+                        estimate.cost_usd,
+                        format_tokens(estimate.token_count),
+                        estimate.encoding
+                    )
+                })
+                .collect();
+            println!(
+                "{}{}{} Estimated input cost: {}",
+                "[".bold().white(),
+                "i".bold().blue(),
+                "]".bold().white(),
+                estimates.join(", ")
+            );
+        }
+
+        if let Some(report) = &session.data.token_budget {
+            let omitted_tokens: usize = report.omitted.iter().map(|file| file.token_count).sum();
+            let priority_info = if session.config.priority_patterns.is_empty() {
+                String::new()
+            } else {
+                format!(
+                    ", priority files: {}, {} cut",
+                    report.priority_files,
+                    report.priority_cut()
+                )
+            };
+            println!(
+                "{}{}{} Token budget: {}, before: {}, after: {}, cut: {} file(s), {} tokens{}",
+                "[".bold().white(),
+                "i".bold().blue(),
+                "]".bold().white(),
+                format_tokens(report.budget),
+                format_tokens(report.tokens_before),
+                format_tokens(report.tokens_after),
+                report.omitted.len(),
+                format_tokens(omitted_tokens),
+                priority_info
+            );
+        }
+
+        let mut file_tokens: Vec<(String, usize)> = session
+            .file_token_counts()
+            .into_iter()
+            .filter_map(|(path, token_count)| Some((path, token_count?)))
+            .collect();
+        file_tokens.sort_by(|(a_path, a), (b_path, b)| b.cmp(a).then_with(|| a_path.cmp(b_path)));
+        if !file_tokens.is_empty() {
+            let largest: Vec<String> = file_tokens
+                .iter()
+                .take(TOP_FILES_BY_TOKENS)
+                .map(|(path, token_count)| format!("{} ({})", path, format_tokens(*token_count)))
+                .collect();
+            println!(
+                "{}{}{} Largest files: {}",
+                "[".bold().white(),
+                "i".bold().blue(),
+                "]".bold().white(),
+                largest.join(", ")
+            );
+        }
+
+        let stubbed: Vec<String> = session
+            .data
+            .files
+            .as_ref()
+            .and_then(|files| files.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|file| {
+                let stub = file.get("stub")?;
+                let path = file.get("path")?.as_str()?;
+                Some(match stub["token_count"].as_u64() {
+                    Some(token_count) => {
+                        format!("{} ({} tokens)", path, format_tokens(token_count as usize))
+                    }
+                    None => format!("{} ({} lines)", path, stub["lines"]),
+                })
+            })
+            .collect();
+        if !stubbed.is_empty() {
+            println!(
+                "{}{}{} Stubbed files over the per-file limit: {}",
+                "[".bold().white(),
+                "i".bold().blue(),
+                "]".bold().white(),
+                stubbed.join(", ")
+            );
+        }
+    }
+
+    if let Some(history) = &mut run_history {
+        history.record(run_key, run_summary);
+        if let Err(e) = history.save() {
+            debug!("{:#}", e);
+        }
+    }
+
+    // ~~~ Context Window ~~~
+    let mut parts = None;
+    if let Some((context_window, model)) = &context_window {
+        if token_count > *context_window {
+            let window_label = match model {
+                Some(model) => format!("{} tokens of {}", format_tokens(*context_window), model),
+                None => format!("{} tokens", format_tokens(*context_window)),
+            };
+            if !args.quiet {
+                eprintln!(
+                    "{}{}{} {}",
+                    "[".bold().white(),
+                    "!".bold().yellow(),
+                    "]".bold().white(),
+                    format!(
+                        "The prompt takes {} tokens, {} over the context window of {}",
+                        formatted_token_count,
+                        format_tokens(token_count - context_window),
+                        window_label
+                    )
+                    .yellow()
+                );
+            }
+
+            if args.split {
+                let prompt_parts = session.split_prompt(*context_window).unwrap_or_else(|e| {
+                    error!("Failed to split the prompt: {}", e);
+                    exit(1);
+                });
+                if !args.quiet {
+                    for part in prompt_parts.iter().filter(|part| part.oversized) {
+                        eprintln!(
+                            "{}{}{} {}",
+                            "[".bold().white(),
+                            "!".bold().yellow(),
+                            "]".bold().white(),
+                            format!(
+                                "{} alone takes {} tokens, over the context window; it is part {} of {}",
+                                part.files[0],
+                                format_tokens(part.token_count),
+                                part.index,
+                                part.count
+                            )
+                            .yellow()
+                        );
+                    }
+                    println!(
+                        "{}{}{} Split the prompt into {} parts: {}",
+                        "[".bold().white(),
+                        "i".bold().blue(),
+                        "]".bold().white(),
+                        prompt_parts.len(),
+                        prompt_parts
+                            .iter()
+                            .map(|part| format!(
+                                "{} files ({} tokens)",
+                                part.files.len(),
+                                format_tokens(part.token_count)
+                            ))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                }
+                parts = Some(prompt_parts);
+            }
+        }
+    }
+
+    // ~~~ Chunks ~~~
+    if let Some(chunk_size) = args.chunk_size {
+        let chunks = session
+            .chunk_prompt(chunk_size, args.chunk_repeat_context)
+            .unwrap_or_else(|e| {
+                error!("Failed to split the prompt into chunks: {}", e);
+                exit(1);
+            });
+        // A prompt that fits in a chunk is written as is
+        if chunks.len() > 1 {
+            if !args.quiet {
+                let output_path = args.output_file.as_deref().unwrap_or_default();
+                println!(
+                    "{}{}{} Split the prompt into {} chunks of at most {}:",
+                    "[".bold().white(),
+                    "i".bold().blue(),
+                    "]".bold().white(),
+                    chunks.len(),
+                    chunk_size
+                );
+                for chunk in &chunks {
+                    println!(
+                        "    {}: {} files, {} tokens{}",
+                        part_file_path(output_path, chunk.index),
+                        chunk.files.len(),
+                        format_tokens(chunk.token_count),
+                        if chunk.oversized {
+                            ", a file over the chunk size"
+                        } else {
+                            ""
+                        }
+                    );
+                }
+            }
+            parts = Some(chunks);
+        }
+    }
+
+    // ~~~ Directories ~~~
+    let mut directory_prompts = Vec::new();
+    if let Some(output_dir) = &args.output_dir {
+        let prompts = session.split_by_directory().unwrap_or_else(|e| {
+            error!("Failed to split the prompt by directory: {}", e);
+            exit(1);
+        });
+        if !args.quiet {
+            println!(
+                "{}{}{} Split the prompt into {} prompts, one per top-level directory:",
+                "[".bold().white(),
+                "i".bold().blue(),
+                "]".bold().white(),
+                prompts.len()
+            );
+        }
+        for prompt in prompts {
+            let mut file_name = format!("{}.{}", prompt.name, args.output_format.extension());
+            if let Some(compression) = compression {
+                file_name = format!("{}.{}", file_name, compression.extension());
+            }
+            let path = output_dir.join(file_name);
+            let path = path.to_string_lossy().into_owned();
+            if !args.quiet {
+                println!(
+                    "    {}: {} files, {} tokens",
+                    path,
+                    prompt.files.len(),
+                    format_tokens(prompt.token_count)
+                );
+            }
+            directory_prompts.push((path, prompt.prompt));
+        }
     }
 
     // ~~~ Token Map Display ~~~
     if args.token_map {
         use crate::token_map::{display_token_map, generate_token_map_with_limit};
 
-        if let Some(files) = session.data.files.as_ref().and_then(|f| f.as_array()) {
-            // Calculate total tokens from individual file counts
-            let total_from_files: usize = files
-                .iter()
-                .filter_map(|f| f.get("token_count"))
-                .filter_map(|tc| tc.as_u64())
-                .map(|tc| tc as usize)
-                .sum();
+        if session.data.files.is_some() {
+            let token_map = session.token_map();
 
             // Get max lines from command line or calculate from terminal height
             let max_lines = args.token_map_lines.unwrap_or_else(|| {
@@ -259,26 +1256,51 @@ fn main() -> Result<()> {
 
             // Use the sum of individual file tokens for the map with line limit
             let entries = generate_token_map_with_limit(
-                files,
-                total_from_files,
+                &token_map,
                 Some(max_lines),
                 args.token_map_min_percent,
             );
-            display_token_map(&entries, total_from_files);
+            display_token_map(&entries, token_map.tokens);
         }
     }
 
     // ~~~ Copy to Clipboard ~~~
-    if !no_clipboard {
-        #[cfg(target_os = "linux")]
-        {
-            use clipboard::spawn_clipboard_daemon;
-            spawn_clipboard_daemon(&rendered.prompt)?;
+    // A compressed prompt is only written to its file
+    if let Some(compression) = compression.filter(|_| !no_clipboard) {
+        if !args.quiet {
+            eprintln!(
+                "{}{}{} {}",
+                "[".bold().white(),
+                "!".bold().yellow(),
+                "]".bold().white(),
+                format!(
+                    "Not copied to clipboard: the output is compressed with {}",
+                    compression
+                )
+                .yellow()
+            );
         }
-        #[cfg(not(target_os = "linux"))]
-        {
-            use crate::clipboard::copy_text_to_clipboard;
-            match copy_text_to_clipboard(&rendered.prompt) {
+    }
+    // The parts of a split prompt are only written to their files
+    let clipboard_backend = args.clipboard.unwrap_or_else(ClipboardBackend::detect);
+    if !no_clipboard && compression.is_none() && parts.is_none() && !args.split_by_dir {
+        if clipboard_backend == ClipboardBackend::Osc52 {
+            // Terminals limit the sequences they accept, so a large prompt may not make it
+            let payload_len = osc52_payload_len(&rendered.prompt);
+            if payload_len > OSC52_LIMIT && !args.quiet {
+                eprintln!(
+                    "{}{}{} {}",
+                    "[".bold().white(),
+                    "!".bold().yellow(),
+                    "]".bold().white(),
+                    format!(
+                        "The OSC 52 sequence takes {} bytes, over the {} bytes that some terminals accept: the clipboard may be cut or left unchanged, use --output-file to keep the whole prompt",
+                        payload_len, OSC52_LIMIT
+                    )
+                    .yellow()
+                );
+            }
+            match copy_with_osc52(&rendered.prompt) {
                 Ok(_) => {
                     if !args.quiet {
                         println!(
@@ -286,7 +1308,7 @@ fn main() -> Result<()> {
                             "[".bold().white(),
                             "✓".bold().green(),
                             "]".bold().white(),
-                            "Copied to clipboard successfully.".green()
+                            "Copied to clipboard with an OSC 52 sequence to the terminal.".green()
                         );
                     }
                 }
@@ -304,12 +1326,130 @@ fn main() -> Result<()> {
                     println!("{}", &rendered.prompt);
                 }
             }
+        } else {
+            #[cfg(target_os = "linux")]
+            {
+                use clipboard::spawn_clipboard_daemon;
+                spawn_clipboard_daemon(&rendered.prompt)?;
+            }
+            #[cfg(not(target_os = "linux"))]
+            {
+                use crate::clipboard::copy_text_to_clipboard;
+                match copy_text_to_clipboard(&rendered.prompt) {
+                    Ok(_) => {
+                        if !args.quiet {
+                            println!(
+                                "{}{}{} {}",
+                                "[".bold().white(),
+                                "✓".bold().green(),
+                                "]".bold().white(),
+                                "Copied to clipboard successfully.".green()
+                            );
+                        }
+                    }
+                    Err(e) => {
+                        if !args.quiet {
+                            eprintln!(
+                                "{}{}{} {}",
+                                "[".bold().white(),
+                                "!".bold().red(),
+                                "]".bold().white(),
+                                format!("Failed to copy to clipboard: {}", e).red()
+                            );
+                        }
+                        // Always print the prompt if clipboard fails, regardless of quiet mode
+                        println!("{}", &rendered.prompt);
+                    }
+                }
+            }
         }
     }
 
     // ~~~ Output File ~~~
+    // The prompts are streamed through the encoder when they are compressed
+    let write_prompt = |path: &str, prompt: &str, append: bool| match compression {
+        Some(compression) => {
+            write_compressed_to_file(path, prompt, compression, append, args.quiet)
+        }
+        None if append => append_to_file(path, prompt, args.quiet),
+        None => write_to_file(path, prompt, args.quiet),
+    };
     if let Some(output_path) = &args.output_file {
-        write_to_file(output_path, &rendered.prompt, args.quiet)?;
+        if let Some(parent) = Path::new(output_path)
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create the output directory {}", parent.display())
+            })?;
+        }
+        match &parts {
+            Some(parts) => {
+                for part in parts {
+                    write_prompt(
+                        &part_file_path(output_path, part.index),
+                        &part.prompt,
+                        false,
+                    )?;
+                }
+            }
+            None => write_prompt(output_path, &rendered.prompt, args.output_append)?,
+        }
+        // Without the messages, scripts still get the resolved path, alone on its line
+        if output_path_resolved && args.quiet {
+            println!("{}", output_path);
+        }
+    }
+    if let Some(output_dir) = &args.output_dir {
+        std::fs::create_dir_all(output_dir).with_context(|| {
+            format!(
+                "Failed to create the output directory {}",
+                output_dir.display()
+            )
+        })?;
+        for (path, prompt) in &directory_prompts {
+            write_prompt(path, prompt, false)?;
+        }
+    }
+
+    // ~~~ Manifest ~~~
+    if let Some(manifest_path) = &args.manifest {
+        let csv = manifest_csv(&session.manifest_rows(args.manifest_include_skipped));
+        if let Some(parent) = manifest_path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent).with_context(|| {
+                format!(
+                    "Failed to create the manifest directory {}",
+                    parent.display()
+                )
+            })?;
+        }
+        std::fs::write(manifest_path, csv)
+            .with_context(|| format!("Failed to write the manifest {}", manifest_path.display()))?;
+        if !args.quiet {
+            println!(
+                "{}{}{} {}",
+                "[".bold().white(),
+                "✓".bold().green(),
+                "]".bold().white(),
+                format!("Manifest written to file: {}", manifest_path.display()).green()
+            );
+        }
+    }
+
+    // ~~~ Remote Repository Cleanup ~~~
+    if let Some(kept_path) = kept_clone {
+        if !args.quiet {
+            eprintln!(
+                "{}{}{} {}",
+                "[".bold().white(),
+                "✓".bold().green(),
+                "]".bold().white(),
+                format!("Clone kept at: {}", kept_path.display()).green()
+            );
+        }
     }
 
     Ok(())
@@ -356,25 +1496,388 @@ fn parse_branch_argument(branch_arg: &Option<Vec<String>>) -> Option<(String, St
     }
 }
 
-/// Loads a template from a file path or returns default values.
+/// Parses a `--log-since`/`--log-until` date, exiting with an error if it is invalid.
+///
+/// # Arguments
+///
+/// * `date` - The date given on the command line
+///
+/// # Returns
+///
+/// * `i64` - The corresponding Unix timestamp
+fn parse_log_date(date: &str) -> i64 {
+    parse_git_date(date).unwrap_or_else(|e| {
+        error!("{}", e);
+        exit(1);
+    })
+}
+
+/// Parses a `--git-changelog` range, exiting with an error if it is invalid.
+///
+/// # Arguments
+///
+/// * `range` - The range given on the command line, e.g. "v1.0.0..HEAD"
+///
+/// # Returns
+///
+/// * `(String, String)` - The references the changelog starts after and ends at
+fn parse_changelog_range(range: &str) -> (String, String) {
+    match range.split_once("..") {
+        Some((from, to)) if !from.is_empty() && !to.is_empty() && !to.starts_with('.') => {
+            (from.to_string(), to.to_string())
+        }
+        _ => {
+            error!("Invalid changelog range '{}': expected <from>..<to>", range);
+            exit(1);
+        }
+    }
+}
+
+/// Parses the context window given as a number of tokens or as a model name.
+///
+/// # Arguments
+///
+/// * `window` - A number of tokens, or the name of a model of the pricing table
+/// * `pricing_file` - The pricing file extending the built-in pricing table, if any
+///
+/// # Returns
+///
+/// * `Result<(usize, Option<String>)>` - The context window in tokens and the model it comes from
+fn parse_context_window(
+    window: &str,
+    pricing_file: Option<&std::path::Path>,
+) -> Result<(usize, Option<String>)> {
+    if let Ok(tokens) = window.parse::<usize>() {
+        if tokens == 0 {
+            anyhow::bail!("The context window must be at least 1 token");
+        }
+        return Ok((tokens, None));
+    }
+    let pricing = PricingTable::load(pricing_file)?;
+    Ok((pricing.context_window(window)?, Some(window.to_string())))
+}
+
+/// Loads a template from a file path or by name, or returns default values.
+///
+/// An existing file or a path with several components is read as a template file. Otherwise
+/// the argument is the name of a template of the `.code2prompt/templates` directories of the
+/// codebase or of a built-in template. A notice is printed when a repository template takes
+/// precedence over a built-in template of the same name.
+///
+/// # Arguments
+///
+/// * `template_arg` - An optional path to a template file, or template name
+/// * `path` - The path of the codebase, whose templates directories are looked up
+/// * `quiet` - Whether to skip the notice
+///
+/// # Returns
+///
+/// * `Result<(String, String, Option<PathBuf>)>` - A tuple containing (template_content,
+///   template_name, template_file) where template_name is "custom" for template files, the
+///   name of a named template, or "default" otherwise, and template_file is the file the
+///   template was read from, none for the built-in templates
+pub fn parse_template(
+    template_arg: &Option<PathBuf>,
+    path: &Path,
+    quiet: bool,
+) -> Result<(String, String, Option<PathBuf>)> {
+    let Some(template_path) = template_arg else {
+        return Ok(("".to_string(), "default".to_string(), None));
+    };
+    if template_path.is_file() || template_path.components().count() > 1 {
+        let template_str = std::fs::read_to_string(template_path)
+            .context("Failed to load custom template file")?;
+        return Ok((
+            template_str,
+            "custom".to_string(),
+            Some(template_path.clone()),
+        ));
+    }
+
+    let name = template_path.to_string_lossy();
+    let template = find_template(&name, path)?.ok_or_else(|| {
+        anyhow!(
+            "No template file or template named {}. List the templates with --list-templates",
+            name
+        )
+    })?;
+    let template_file = match &template.info.source {
+        TemplateSource::Repository(file) => Some(file.clone()),
+        TemplateSource::Builtin => None,
+    };
+    let notice_file = template_file
+        .as_ref()
+        .filter(|_| template.info.overrides_builtin && !quiet);
+    if let Some(file) = notice_file {
+        eprintln!(
+            "{}{}{} Using the repository template {} instead of the built-in template {}",
+            "[".bold().white(),
+            "i".bold().blue(),
+            "]".bold().white(),
+            file.display(),
+            template.info.name,
+        );
+    }
+    Ok((template.content, template.info.name, template_file))
+}
+
+/// Loads a built-in template by name, ignoring the repository templates.
+///
+/// # Arguments
+///
+/// * `name` - The name of the built-in template
+///
+/// # Returns
+///
+/// * `Result<(String, String, Option<PathBuf>)>` - A tuple containing (template_content,
+///   template_name, None), or an error listing the built-in templates if none has this name
+pub fn parse_builtin_template(name: &str) -> Result<(String, String, Option<PathBuf>)> {
+    let builtin = builtin_template(name).ok_or_else(|| {
+        let names: Vec<&str> = BUILTIN_TEMPLATES
+            .iter()
+            .map(|builtin| builtin.name)
+            .collect();
+        anyhow!(
+            "No built-in template named {}. Built-in templates: {}",
+            name,
+            names.join(", ")
+        )
+    })?;
+    Ok((builtin.content.to_string(), builtin.name.to_string(), None))
+}
+
+/// Applies the options of the front-matter of the template that weren't passed on the
+/// command line: the flags are turned on and the patterns and output format are set when
+/// none was passed.
+fn apply_front_matter(args: &mut Cli, matches: &ArgMatches, front_matter: FrontMatter) {
+    args.diff |= front_matter.diff;
+    args.line_numbers |= front_matter.line_numbers;
+    args.absolute_paths |= front_matter.absolute_paths;
+    args.full_directory_tree |= front_matter.full_directory_tree;
+    args.no_codeblock |= front_matter.no_codeblock;
+    args.hidden |= front_matter.hidden;
+    args.changed_files_only |= front_matter.changed_files_only;
+    if args.include.is_empty() {
+        args.include = front_matter.include;
+    }
+    if args.exclude.is_empty() {
+        args.exclude = front_matter.exclude;
+    }
+    if let Some(output_format) = front_matter.output_format {
+        if matches.value_source("output_format") != Some(ValueSource::CommandLine) {
+            args.output_format = output_format;
+        }
+    }
+}
+
+/// Warns about the keys a template references that the prompt data doesn't have, except the
+/// variables defined with -D and the ones that will be asked for.
+fn warn_unknown_keys(
+    template_name: &str,
+    template_str: &str,
+    unknown_keys: &[UnknownKey],
+    args: &Cli,
+) {
+    let prompted = if args.no_input {
+        Vec::new()
+    } else {
+        extract_undefined_variables(template_str)
+    };
+    for unknown in unknown_keys {
+        let defined = args
+            .define
+            .iter()
+            .any(|define| define.split('=').next() == Some(unknown.key.as_str()));
+        if defined || prompted.contains(&unknown.key) {
+            continue;
+        }
+        eprintln!(
+            "{}{}{} {}",
+            "[".bold().white(),
+            "!".bold().yellow(),
+            "]".bold().white(),
+            format!(
+                "The template {} references {} at line {}, column {}, which the prompt data \
+                 doesn't have",
+                template_name, unknown.key, unknown.line, unknown.column
+            )
+            .yellow()
+        );
+    }
+}
+
+/// Warns about the sections a built-in template expects that no option fills.
 ///
 /// # Arguments
 ///
-/// * `template_arg` - An optional path to a template file
+/// * `template_name` - The name of the template, which may not be a built-in one
+/// * `diff_passed` - Whether an option retrieving a git diff was passed
+/// * `log_passed` - Whether an option retrieving a git log was passed
+fn warn_missing_sections(template_name: &str, diff_passed: bool, log_passed: bool) {
+    let Some(builtin) = builtin_template(template_name) else {
+        return;
+    };
+    for section in builtin.sections {
+        let (passed, options) = match section {
+            TemplateSection::GitDiff => (
+                diff_passed,
+                "--diff, --git-diff-branch, --git-diff-against, --git-diff-stash or --git-show",
+            ),
+            TemplateSection::GitLog => (log_passed, "--git-log-branch or --git-log-range"),
+        };
+        if !passed {
+            eprintln!(
+                "{}{}{} {}",
+                "[".bold().white(),
+                "!".bold().yellow(),
+                "]".bold().white(),
+                format!(
+                    "The template {} expects a {}, pass {}",
+                    template_name,
+                    section.label(),
+                    options
+                )
+                .yellow()
+            );
+        }
+    }
+}
+
+/// Loads the template partials: the ones of the partials directory next to the template file,
+/// then the ones passed as `NAME=PATH`, which replace them.
+///
+/// # Arguments
+///
+/// * `template_file` - The file the template was read from, if any
+/// * `partial_args` - The partials passed as `NAME=PATH`
 ///
 /// # Returns
 ///
-/// * `Result<(String, String)>` - A tuple containing (template_content, template_name)
-///   where template_name is "custom" for user-provided templates or "default" otherwise
-pub fn parse_template(template_arg: &Option<PathBuf>) -> Result<(String, String)> {
-    match template_arg {
-        Some(path) => {
-            let template_str =
-                std::fs::read_to_string(path).context("Failed to load custom template file")?;
-            Ok((template_str, "custom".to_string()))
+/// * `Result<HashMap<String, String>>` - The partials by name, or an error if an argument is
+///   malformed or a partial can't be read
+pub fn parse_partials(
+    template_file: Option<&Path>,
+    partial_args: &[String],
+) -> Result<HashMap<String, String>> {
+    let mut partials = match template_file {
+        Some(file) => read_partials_dir(file)?,
+        None => HashMap::new(),
+    };
+    for arg in partial_args {
+        let (name, partial_path) = arg
+            .split_once('=')
+            .filter(|(name, partial_path)| !name.is_empty() && !partial_path.is_empty())
+            .ok_or_else(|| anyhow!("Invalid partial {}, expected NAME=PATH", arg))?;
+        let content = std::fs::read_to_string(partial_path).with_context(|| {
+            format!("Failed to read the partial {} from {}", name, partial_path)
+        })?;
+        partials.insert(name.to_string(), content);
+    }
+    Ok(partials)
+}
+
+/// Reads the file template of --file-template: the file at the path if there is one, the
+/// value itself if it's a template.
+fn parse_file_template(value: &str) -> Result<String> {
+    let path = Path::new(value);
+    if path.is_file() {
+        return std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the file template {}", path.display()));
+    }
+    if !value.contains("{{") {
+        bail!("{} is neither a file nor a Handlebars template", value);
+    }
+    Ok(value.to_string())
+}
+
+/// Prints the templates that can be used by name, with their description and source.
+fn print_templates(path: &Path) -> Result<()> {
+    let templates = list_templates(path)?;
+    let width = templates
+        .iter()
+        .map(|template| template.name.len())
+        .max()
+        .unwrap_or(0);
+    for template in templates {
+        let source = match &template.source {
+            TemplateSource::Repository(file) if template.overrides_builtin => {
+                format!("{}, overrides the built-in template", file.display())
+            }
+            TemplateSource::Repository(file) => file.display().to_string(),
+            TemplateSource::Builtin if !template.sections.is_empty() => {
+                let sections: Vec<&str> = template
+                    .sections
+                    .iter()
+                    .map(|section| section.label())
+                    .collect();
+                format!("built-in, expects a {}", sections.join(" and "))
+            }
+            TemplateSource::Builtin => "built-in".to_string(),
+        };
+        println!(
+            "{}  {} ({})",
+            format!("{:<width$}", template.name, width = width).bold(),
+            template.description,
+            source.dimmed()
+        );
+    }
+    Ok(())
+}
+
+/// Parses the user-defined variables passed as `KEY=VALUE`, and asks for the ones the template
+/// uses but that aren't defined.
+///
+/// # Arguments
+///
+/// * `define_args` - The variables passed as `KEY=VALUE`, the value being everything after
+///   the first `=`
+/// * `sources` - The template string and the content of its partials
+/// * `no_input` - Whether to fail instead of asking for the undefined variables
+///
+/// # Returns
+///
+/// * `Result<HashMap<String, String>>` - The variables by key, or an error if an argument is
+///   malformed or variables are undefined and can't be asked for
+pub fn parse_user_variables<'a>(
+    define_args: &[String],
+    sources: impl IntoIterator<Item = &'a str>,
+    no_input: bool,
+) -> Result<HashMap<String, String>> {
+    let mut variables = HashMap::new();
+    for arg in define_args {
+        let (key, value) = arg
+            .split_once('=')
+            .filter(|(key, _)| !key.is_empty())
+            .ok_or_else(|| anyhow!("Invalid variable {}, expected KEY=VALUE", arg))?;
+        variables.insert(key.to_string(), value.to_string());
+    }
+
+    let mut missing: Vec<String> = Vec::new();
+    for source in sources {
+        for key in extract_user_variables(source) {
+            if !variables.contains_key(&key) && !missing.contains(&key) {
+                missing.push(key);
+            }
         }
-        None => Ok(("".to_string(), "default".to_string())),
     }
+    if missing.is_empty() {
+        return Ok(variables);
+    }
+    if no_input || !std::io::stdin().is_terminal() {
+        let keys: Vec<String> = missing.iter().map(|key| format!("user.{}", key)).collect();
+        return Err(anyhow!(
+            "The template uses undefined variables: {}. Define them with -D KEY=VALUE",
+            keys.join(", ")
+        ));
+    }
+    for key in missing {
+        let value = Text::new(&format!("Enter value for 'user.{}': ", key))
+            .with_help_message("Fill user defined variable in template, or pass -D KEY=VALUE")
+            .prompt()
+            .context("Failed to read the value of a template variable")?;
+        variables.insert(key, value);
+    }
+    Ok(variables)
 }
 
 /// Handles user-defined variables in the template and adds them to the data.
@@ -1,13 +1,13 @@
+use code2prompt_core::token_map::TokenMapNode;
 use lscolors::{Indicator, LsColors};
+#[cfg(windows)]
 use log::error;
-use serde::Deserialize;
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, BinaryHeap, HashMap};
 use std::fs;
-use std::path::Path;
 use unicode_width::UnicodeWidthStr;
 
-#[derive(Debug, Clone, Copy, Deserialize)]
+#[derive(Debug, Clone, Copy)]
 pub struct EntryMetadata {
     pub is_dir: bool,
 }
@@ -20,13 +20,19 @@ struct TreeNode {
     metadata: Option<EntryMetadata>,
 }
 
-impl TreeNode {
-    fn with_path(path: String) -> Self {
+impl From<&TokenMapNode> for TreeNode {
+    fn from(node: &TokenMapNode) -> Self {
         TreeNode {
-            tokens: 0,
-            children: BTreeMap::new(),
-            path,
-            metadata: None,
+            tokens: node.tokens,
+            children: node
+                .children
+                .iter()
+                .map(|child| (child.name.clone(), TreeNode::from(child)))
+                .collect(),
+            path: node.path.clone(),
+            metadata: Some(EntryMetadata {
+                is_dir: node.is_dir,
+            }),
         }
     }
 }
@@ -56,39 +62,16 @@ impl PartialOrd for NodePriority {
 }
 
 pub fn generate_token_map_with_limit(
-    files: &[serde_json::Value],
-    total_tokens: usize,
+    token_map: &TokenMapNode,
     max_lines: Option<usize>,
     min_percent: Option<f64>,
 ) -> Vec<TokenMapEntry> {
     // Default values
     let max_lines = max_lines.unwrap_or(20);
     let min_percent = min_percent.unwrap_or(0.1);
-    // Build tree structure
-    let mut root = TreeNode::with_path(String::new());
-    root.tokens = total_tokens;
-
-    // Insert all files into the tree
-    for file in files {
-        if let (Some(path_str), Some(tokens), Some(metadata_json)) = (
-            file.get("path").and_then(|p| p.as_str()),
-            file.get("token_count").and_then(|t| t.as_u64()),
-            file.get("metadata"),
-        ) {
-            let tokens = tokens as usize;
-            if let Ok(metadata) = serde_json::from_value(metadata_json.clone()) {
-                let path = Path::new(path_str);
-
-                // Skip the root component if it exists
-                let components: Vec<_> = path
-                    .components()
-                    .filter_map(|c| c.as_os_str().to_str())
-                    .collect();
-
-                insert_path(&mut root, &components, tokens, String::new(), metadata);
-            }
-        }
-    }
+    // The token counts are aggregated up the tree by the core, from the count of each file
+    let root = TreeNode::from(token_map);
+    let total_tokens = token_map.tokens;
 
     // Use priority queue to select most significant entries
     let allowed_nodes = select_nodes_to_display(&root, total_tokens, max_lines, min_percent);
@@ -108,12 +91,14 @@ pub fn generate_token_map_with_limit(
     // Add summary for hidden files if needed
     let displayed_tokens: usize = entries
         .iter()
-        .map(|e| {
-            if !e.metadata.is_dir {
-                e.tokens
-            } else {
-                // For directories, only count their direct file children to avoid double counting
+        .enumerate()
+        .map(|(i, e)| {
+            let has_children = entries.get(i + 1).is_some_and(|next| next.depth > e.depth);
+            if has_children {
+                // Only count the entries without displayed children to avoid double counting
                 0
+            } else {
+                e.tokens
             }
         })
         .sum();
@@ -134,57 +119,15 @@ pub fn generate_token_map_with_limit(
     entries
 }
 
+/// Sums the tokens of the leaves: the files and the directories collapsed by the depth limit.
 fn calculate_file_tokens(node: &TreeNode) -> usize {
-    if node.metadata.map_or(false, |m| !m.is_dir) {
+    if node.children.is_empty() {
         node.tokens
     } else {
         node.children.values().map(calculate_file_tokens).sum()
     }
 }
 
-fn insert_path(
-    node: &mut TreeNode,
-    components: &[&str],
-    tokens: usize,
-    parent_path: String,
-    file_metadata: EntryMetadata,
-) {
-    if components.is_empty() {
-        return;
-    }
-
-    if components.len() == 1 {
-        // This is a file
-        let file_name = components[0].to_string();
-        let file_path = if parent_path.is_empty() {
-            file_name.clone()
-        } else {
-            format!("{}/{}", parent_path, file_name)
-        };
-        let child = node
-            .children
-            .entry(file_name)
-            .or_insert_with(|| TreeNode::with_path(file_path));
-        child.tokens = tokens;
-        child.metadata = Some(file_metadata);
-    } else {
-        // This is a directory
-        let dir_name = components[0].to_string();
-        let dir_path = if parent_path.is_empty() {
-            dir_name.clone()
-        } else {
-            format!("{}/{}", parent_path, dir_name)
-        };
-        let child = node
-            .children
-            .entry(dir_name)
-            .or_insert_with(|| TreeNode::with_path(dir_path.clone()));
-        child.tokens += tokens;
-        child.metadata = Some(EntryMetadata { is_dir: true });
-        insert_path(child, &components[1..], tokens, dir_path, file_metadata);
-    }
-}
-
 #[derive(Debug)]
 pub struct TokenMapEntry {
     pub path: String,
@@ -273,7 +216,7 @@ fn rebuild_filtered_tree(
     // Check if this node should be included
     if !path.is_empty() && allowed_nodes.contains_key(&path) {
         let percentage = (node.tokens as f64 / total_tokens as f64) * 100.0;
-        let name = path.split('/').last().unwrap_or(&path).to_string();
+        let name = path.split('/').next_back().unwrap_or(&path).to_string();
 
         let metadata = node.metadata.unwrap_or(EntryMetadata { is_dir: true });
 
@@ -296,7 +239,7 @@ fn rebuild_filtered_tree(
         .collect();
 
     // Sort by tokens descending
-    filtered_children.sort_by(|a, b| b.1.tokens.cmp(&a.1.tokens));
+    filtered_children.sort_by_key(|(_, child)| std::cmp::Reverse(child.tokens));
 
     let child_count = filtered_children.len();
     for (i, (name, child)) in filtered_children.into_iter().enumerate() {
@@ -400,11 +343,11 @@ pub fn display_token_map(entries: &[TokenMapEntry], total_tokens: usize) {
             if d < entry.depth - 1 {
                 // Check if we need a vertical line at this depth
                 let mut needs_line = false;
-                for j in (i + 1)..entries.len() {
-                    if entries[j].depth <= d {
+                for next in &entries[i + 1..] {
+                    if next.depth <= d {
                         break;
                     }
-                    if entries[j].depth == d + 1 {
+                    if next.depth == d + 1 {
                         needs_line = true;
                         break;
                     }
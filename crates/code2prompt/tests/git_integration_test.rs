@@ -9,7 +9,7 @@ use std::path::Path;
 use std::sync::Once;
 use tempfile::tempdir;
 
-use git2::Repository;
+use git2::{Repository, Signature};
 
 static INIT: Once = Once::new();
 
@@ -26,11 +26,13 @@ fn init_logger() {
 fn create_temp_file(dir: &Path, name: &str, content: &str) {
     let file_path = dir.join(name);
     let parent_dir = file_path.parent().unwrap();
-    fs::create_dir_all(parent_dir).expect(&format!("Failed to create directory: {:?}", parent_dir));
-    let mut file =
-        File::create(&file_path).expect(&format!("Failed to create temp file: {:?}", file_path));
+    fs::create_dir_all(parent_dir)
+        .unwrap_or_else(|_| panic!("Failed to create directory: {:?}", parent_dir));
+    let mut file = File::create(&file_path)
+        .unwrap_or_else(|_| panic!("Failed to create temp file: {:?}", file_path));
     //debug!("Writing to file: {:?}", file_path);
-    writeln!(file, "{}", content).expect(&format!("Failed to write to temp file: {:?}", file_path));
+    writeln!(file, "{}", content)
+        .unwrap_or_else(|_| panic!("Failed to write to temp file: {:?}", file_path));
 }
 
 fn create_test_hierarchy(base_path: &Path) {
@@ -65,9 +67,37 @@ fn create_test_hierarchy(base_path: &Path) {
     );
 }
 
+/// Stages every file of the working directory and commits it on HEAD.
+fn commit_all(repo: &Repository, message: &str) {
+    let mut index = repo.index().expect("Failed to get repository index");
+    index
+        .add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)
+        .expect("Failed to add files to index");
+    index.write().expect("Failed to write index");
+
+    let tree_id = index.write_tree().expect("Failed to write tree");
+    let tree = repo.find_tree(tree_id).expect("Failed to find tree");
+    let signature = Signature::now("Test", "test@example.com").expect("Failed to create signature");
+    let parents = match repo.head() {
+        Ok(head) => vec![head.peel_to_commit().expect("Failed to peel HEAD")],
+        Err(_) => vec![],
+    };
+    let parent_refs: Vec<&git2::Commit> = parents.iter().collect();
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &parent_refs,
+    )
+    .expect("Failed to commit");
+}
+
 fn read_output_file(dir: &Path, file_name: &str) -> String {
     let file_path = dir.join(file_name);
-    read_to_string(&file_path).expect(&format!("Failed to read output file: {:?}", file_path))
+    read_to_string(&file_path)
+        .unwrap_or_else(|_| panic!("Failed to read output file: {:?}", file_path))
 }
 
 mod tests {
@@ -93,7 +123,7 @@ mod tests {
         fn command(&self) -> Command {
             let mut cmd =
                 Command::cargo_bin("code2prompt").expect("Failed to find code2prompt binary");
-            cmd.arg(&self.dir.path().to_str().unwrap())
+            cmd.arg(self.dir.path().to_str().unwrap())
                 .arg("--output-file")
                 .arg(&self.output_file)
                 .arg("--no-clipboard");
@@ -132,4 +162,116 @@ mod tests {
         assert!(contains("ignored.txt").eval(&output2));
         assert!(contains("Ignored file").eval(&output2));
     }
+
+    #[test]
+    fn test_git_diff_stat() {
+        let env = TestEnv::new();
+        let repo = Repository::open(env.dir.path()).expect("Failed to open repository");
+        commit_all(&repo, "Initial commit");
+        create_temp_file(env.dir.path(), "test_dir/included.txt", "Changed file");
+        commit_all(&repo, "Change included file");
+
+        let mut cmd = env.command();
+        cmd.args([
+            "--git-diff-branch",
+            "HEAD~1",
+            "HEAD",
+            "--git-diff-stat",
+            "--exclude=output.txt",
+        ])
+        .assert()
+        .success();
+
+        let output = env.read_output();
+        debug!("Test --git-diff-stat output:\n{}", output);
+        assert!(contains("Git Diff Stat:").eval(&output));
+        assert!(contains("test_dir/included.txt | +1 -1").eval(&output));
+        assert!(contains("1 files changed, 1 insertions(+), 1 deletions(-)").eval(&output));
+    }
+
+    #[test]
+    fn test_blame() {
+        let env = TestEnv::new();
+        let repo = Repository::open(env.dir.path()).expect("Failed to open repository");
+        commit_all(&repo, "Initial commit");
+        create_temp_file(env.dir.path(), "test_dir/untracked.txt", "Untracked file");
+
+        let mut cmd = env.command();
+        cmd.args(["--blame", "--exclude=output.txt"])
+            .assert()
+            .success();
+
+        let output = env.read_output();
+        debug!("Test --blame output:\n{}", output);
+        assert!(contains("Test just now | Included file").eval(&output));
+        assert!(contains("\nUntracked file\n").eval(&output));
+    }
+
+    #[test]
+    fn test_ambiguous_reference_warning() {
+        let env = TestEnv::new();
+        let repo = Repository::open(env.dir.path()).expect("Failed to open repository");
+        commit_all(&repo, "Initial commit");
+        create_temp_file(env.dir.path(), "test_dir/included.txt", "Changed file");
+        commit_all(&repo, "Change included file");
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.branch("pu", &head, false).unwrap();
+        repo.tag_lightweight("pu", &head.parent(0).unwrap().into_object(), false)
+            .unwrap();
+
+        let mut cmd = env.command();
+        cmd.env("RUST_LOG", "warn")
+            .args(["--git-diff-branch", "pu", "HEAD", "--exclude=output.txt"])
+            .assert()
+            .success()
+            .stderr(contains(
+                "Reference pu is ambiguous, it matches 'refs/tags/pu' or 'refs/heads/pu'; using refs/tags/pu",
+            ));
+
+        let output = env.read_output();
+        assert!(contains("Changed file").eval(&output));
+    }
+
+    #[test]
+    fn test_template_builtin_pr() {
+        let env = TestEnv::new();
+        let repo = Repository::open(env.dir.path()).expect("Failed to open repository");
+        create_temp_file(env.dir.path(), "test_dir/stable.txt", "Stable file");
+        commit_all(&repo, "Initial commit");
+        create_temp_file(env.dir.path(), "test_dir/included.txt", "Changed file");
+        create_temp_file(env.dir.path(), "test_dir/added.txt", "Added file");
+        commit_all(&repo, "feat(dir): change the included file");
+
+        let mut cmd = env.command();
+        cmd.args([
+            "--template-builtin",
+            "pr",
+            "--git-diff-branch",
+            "HEAD~1",
+            "HEAD",
+            "--exclude=output.txt",
+        ])
+        .assert()
+        .success()
+        .stderr(contains("expects").not());
+
+        let output = env.read_output();
+        debug!("Test --template-builtin pr output:\n{}", output);
+        assert!(
+            contains("HEAD is 1 commit(s) ahead of and 0 commit(s) behind HEAD~1").eval(&output)
+        );
+        assert!(contains("feat:\n- dir: change the included file").eval(&output));
+        assert!(contains("- test_dir/added.txt | +1 -0").eval(&output));
+        assert!(contains("2 files changed, 2 insertions(+), 1 deletions(-)").eval(&output));
+        assert!(contains("Changed file").eval(&output));
+        assert!(contains("Added file").eval(&output));
+        assert!(contains("Stable file").not().eval(&output));
+
+        let mut cmd = env.command();
+        cmd.args(["--template-builtin", "pr"])
+            .env("RUST_LOG", "error")
+            .assert()
+            .failure()
+            .stderr(contains("pass them with --git-diff-branch"));
+    }
 }
@@ -24,11 +24,13 @@ fn init_logger() {
 fn create_temp_file(dir: &Path, name: &str, content: &str) {
     let file_path = dir.join(name);
     let parent_dir = file_path.parent().unwrap();
-    fs::create_dir_all(parent_dir).expect(&format!("Failed to create directory: {:?}", parent_dir));
-    let mut file =
-        File::create(&file_path).expect(&format!("Failed to create temp file: {:?}", file_path));
+    fs::create_dir_all(parent_dir)
+        .unwrap_or_else(|_| panic!("Failed to create directory: {:?}", parent_dir));
+    let mut file = File::create(&file_path)
+        .unwrap_or_else(|_| panic!("Failed to create temp file: {:?}", file_path));
     //debug!("Writing to file: {:?}", file_path);
-    writeln!(file, "{}", content).expect(&format!("Failed to write to temp file: {:?}", file_path));
+    writeln!(file, "{}", content)
+        .unwrap_or_else(|_| panic!("Failed to write to temp file: {:?}", file_path));
 }
 
 fn create_test_hierarchy(base_path: &Path) {
@@ -67,7 +69,8 @@ fn create_test_hierarchy(base_path: &Path) {
 
 fn read_output_file(dir: &Path, file_name: &str) -> String {
     let file_path = dir.join(file_name);
-    read_to_string(&file_path).expect(&format!("Failed to read output file: {:?}", file_path))
+    read_to_string(&file_path)
+        .unwrap_or_else(|_| panic!("Failed to read output file: {:?}", file_path))
 }
 
 mod tests {
@@ -91,7 +94,7 @@ mod tests {
         fn command(&self) -> Command {
             let mut cmd =
                 Command::cargo_bin("code2prompt").expect("Failed to find code2prompt binary");
-            cmd.arg(&self.dir.path().to_str().unwrap())
+            cmd.arg(self.dir.path().to_str().unwrap())
                 .arg("--output-file")
                 .arg(&self.output_file)
                 .arg("--no-clipboard");
@@ -299,4 +302,1343 @@ mod tests {
         assert!(contains("corge.txt").not().eval(&output));
         assert!(contains("grault.txt").not().eval(&output));
     }
+
+    #[test]
+    fn test_file_token_counts() {
+        let env = TestEnv::new();
+        let mut cmd = env.command();
+        cmd.arg("--include=lowercase/*.py")
+            .assert()
+            .success()
+            .stdout(contains("Largest files: lowercase/"));
+
+        let output = env.read_output();
+        debug!("Test file token counts output:\n{}", output);
+        assert!(contains("`lowercase/foo.py` (").eval(&output));
+        assert!(contains(" tokens):").eval(&output));
+
+        let mut cmd = env.command();
+        cmd.args(["--include=lowercase/*.py", "--output-format=json"])
+            .assert()
+            .success();
+        let output: serde_json::Value = serde_json::from_str(&env.read_output()).unwrap();
+        let counts = output["file_token_counts"].as_object().unwrap();
+        assert_eq!(counts.len(), 3);
+        assert!(counts
+            .values()
+            .all(|count| count.as_u64().is_some_and(|count| count > 0)));
+    }
+
+    #[test]
+    fn test_token_budget() {
+        let env = TestEnv::new();
+        let mut cmd = env.command();
+        cmd.args(["--include=lowercase/*.py", "--token-budget=1"])
+            .env("RUST_LOG", "warn")
+            .assert()
+            .success()
+            .stdout(contains("Token budget: 1, before: "))
+            .stdout(contains("cut: 3 file(s)"))
+            .stderr(contains("without any file, over the budget of 1"));
+
+        let output = env.read_output();
+        debug!("Test token budget output:\n{}", output);
+        assert!(contains("Omitted to fit the token budget of 1 tokens:").eval(&output));
+        assert!(contains("- `lowercase/foo.py` (").eval(&output));
+        assert!(contains("content foo.py").not().eval(&output));
+
+        let mut cmd = env.command();
+        cmd.args(["--budget-strategy=drop-last"]).assert().failure();
+    }
+
+    #[test]
+    fn test_encoding_from_model_name() {
+        let env = TestEnv::new();
+        let mut cmd = env.command();
+        cmd.args(["--model", "gpt-4o"])
+            .assert()
+            .success()
+            .stdout(contains("Encoding: o200k_base"));
+
+        let mut cmd = env.command();
+        cmd.args(["--encoding", "not-a-model"])
+            .assert()
+            .failure()
+            .stderr(contains("Supported encodings: o200k_base, cl100k_base"));
+    }
+
+    #[test]
+    fn test_max_tokens_per_file() {
+        let env = TestEnv::new();
+        let big: Vec<String> = (1..=500).map(|i| format!("value_{} = {}", i, i)).collect();
+        create_temp_file(env.dir.path(), "lowercase/big.py", &big.join("\n"));
+
+        let mut cmd = env.command();
+        cmd.args(["--include=lowercase/*.py", "--output-format=json"])
+            .assert()
+            .success();
+        let full: serde_json::Value = serde_json::from_str(&env.read_output()).unwrap();
+
+        let mut cmd = env.command();
+        cmd.args(["--include=lowercase/*.py", "--max-tokens-per-file=200"])
+            .assert()
+            .success()
+            .stdout(contains(
+                "Stubbed files over the per-file limit: lowercase/big.py (",
+            ));
+        let output = env.read_output();
+        debug!("Test max tokens per file output:\n{}", output);
+        assert!(contains("value_1 = 1\n").eval(&output));
+        assert!(contains("value_250 = 250").not().eval(&output));
+        assert!(contains("value_500 = 500").eval(&output));
+        assert!(contains("490 lines omitted: lowercase/big.py has").eval(&output));
+
+        let mut cmd = env.command();
+        cmd.args([
+            "--include=lowercase/*.py",
+            "--max-tokens-per-file=200",
+            "--output-format=json",
+        ])
+        .assert()
+        .success();
+        let stubbed: serde_json::Value = serde_json::from_str(&env.read_output()).unwrap();
+        let full_tokens = full["file_token_counts"]["lowercase/big.py"]
+            .as_u64()
+            .unwrap();
+        let stub_tokens = stubbed["file_token_counts"]["lowercase/big.py"]
+            .as_u64()
+            .unwrap();
+        assert!(full_tokens > 200);
+        assert!(stub_tokens < 200);
+        // The total reflects the stub, not the original file, give or take a token merged at
+        // the boundaries of the code block
+        let saved =
+            full["token_count"].as_u64().unwrap() - stubbed["token_count"].as_u64().unwrap();
+        assert!(
+            saved.abs_diff(full_tokens - stub_tokens) <= 2,
+            "{} tokens saved",
+            saved
+        );
+    }
+
+    #[test]
+    fn test_token_map_depth() {
+        let env = TestEnv::new();
+        let mut cmd = env.command();
+        cmd.args(["--token-map", "--token-map-depth=1"])
+            .env("NO_COLOR", "1")
+            .assert()
+            .success()
+            .stdout(contains("── lowercase"))
+            .stdout(contains("── uppercase"))
+            .stdout(contains("── foo.py").not());
+
+        let mut cmd = env.command();
+        cmd.args([
+            "--include=*.py",
+            "--token-map",
+            "--token-map-depth=1",
+            "--output-format=json",
+        ])
+        .assert()
+        .success();
+        let output: serde_json::Value = serde_json::from_str(&env.read_output()).unwrap();
+        let token_map = &output["token_map"];
+        let children = token_map["children"].as_array().unwrap();
+        assert_eq!(children.len(), 2);
+        assert!(children
+            .iter()
+            .all(|child| child["is_dir"] == true && child["children"] == serde_json::json!([])));
+        let sum: u64 = children
+            .iter()
+            .map(|child| child["tokens"].as_u64().unwrap())
+            .sum();
+        assert_eq!(token_map["tokens"].as_u64().unwrap(), sum);
+        // Siblings are sorted by descending tokens
+        assert!(children[0]["tokens"].as_u64() >= children[1]["tokens"].as_u64());
+
+        let mut cmd = env.command();
+        cmd.arg("--token-map-depth=1").assert().failure();
+    }
+
+    #[test]
+    fn test_model_cost() {
+        let env = TestEnv::new();
+        let mut cmd = env.command();
+        cmd.args(["--include=*.py", "--model-cost", "gpt-4o,claude-3-5-sonnet"])
+            .assert()
+            .success()
+            .stdout(contains("Estimated input cost: gpt-4o $"))
+            .stdout(contains("tokens, o200k_base), claude-3-5-sonnet $"))
+            .stdout(contains("tokens, cl100k_base)"));
+
+        let pricing_file = env.dir.path().join("pricing.toml");
+        std::fs::write(&pricing_file, "[my-model]\ninput_per_million = 1000000.0\n").unwrap();
+        let mut cmd = env.command();
+        cmd.args(["--include=*.py", "--output-format=json", "--price=my-model"])
+            .arg(format!("--pricing-file={}", pricing_file.display()))
+            .assert()
+            .success();
+        let json: serde_json::Value = serde_json::from_str(&env.read_output()).unwrap();
+        let estimate = &json["cost_estimates"][0];
+        assert_eq!(estimate["model"], "my-model");
+        // A dollar per token
+        assert_eq!(
+            estimate["cost_usd"].as_f64().unwrap(),
+            estimate["token_count"].as_f64().unwrap()
+        );
+
+        let mut cmd = env.command();
+        cmd.args(["--model-cost=gpt-9"])
+            .env("RUST_LOG", "error")
+            .assert()
+            .failure()
+            .stderr(contains("No price for the model gpt-9"));
+    }
+
+    #[test]
+    fn test_token_cache() {
+        let env = TestEnv::new();
+        let cache_dir = tempdir().unwrap();
+        let cache_arg = format!("--cache-dir={}", cache_dir.path().display());
+
+        let mut cmd = env.command();
+        cmd.args(["--include=lowercase/*.py", &cache_arg])
+            .env("RUST_LOG", "debug")
+            .assert()
+            .success()
+            .stderr(contains("Token cache: 0 hits, 3 misses"));
+
+        // The second run counts none of the files
+        let mut cmd = env.command();
+        cmd.args(["--include=lowercase/*.py", &cache_arg])
+            .env("RUST_LOG", "debug")
+            .assert()
+            .success()
+            .stderr(contains("Token cache: 3 hits, 0 misses"));
+
+        let mut cmd = env.command();
+        cmd.args(["--include=lowercase/*.py", &cache_arg, "--no-cache"])
+            .env("RUST_LOG", "debug")
+            .assert()
+            .success()
+            .stderr(contains("Token cache:").not());
+
+        let mut cmd = env.command();
+        cmd.args(["--include=lowercase/*.py", &cache_arg, "--clear-cache"])
+            .env("RUST_LOG", "debug")
+            .assert()
+            .success()
+            .stderr(contains("Token cache: 0 hits, 3 misses"));
+    }
+
+    #[test]
+    fn test_token_breakdown() {
+        let env = TestEnv::new();
+        let mut cmd = env.command();
+        cmd.args(["--include=*.py"])
+            .assert()
+            .success()
+            .stdout(contains("Token breakdown: file contents: "))
+            .stdout(contains(", template overhead: "));
+    }
+
+    #[test]
+    fn test_dir_summary() {
+        let env = TestEnv::new();
+        create_temp_file(env.dir.path(), "root.py", "content root.py");
+        let mut cmd = env.command();
+        cmd.args(["--include=*.py", "--dir-summary"])
+            .assert()
+            .success();
+        let output = env.read_output();
+        debug!("Test dir summary output:\n{}", output);
+        assert!(contains("Directory Summary:").eval(&output));
+        assert!(contains("- `.`: 1 files, ").eval(&output));
+        assert!(contains("- `lowercase`: 3 files, ").eval(&output));
+
+        let mut cmd = env.command();
+        cmd.args(["--include=*.py", "--output-format=json"])
+            .assert()
+            .success();
+        let json: serde_json::Value = serde_json::from_str(&env.read_output()).unwrap();
+        let directories = json["directories"].as_array().unwrap();
+        assert_eq!(directories[0]["path"], ".");
+        assert!(directories
+            .iter()
+            .any(|directory| directory["path"] == "uppercase"));
+        assert!(contains("<directory-summary>")
+            .not()
+            .eval(json["prompt"].as_str().unwrap()));
+    }
+
+    #[cfg(feature = "huggingface")]
+    #[test]
+    fn test_hf_tokenizer() {
+        let env = TestEnv::new();
+        let tokenizer = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../code2prompt-core/tests/fixtures/tokenizer.json");
+        let mut cmd = env.command();
+        cmd.args(["--include=*.py", "--output-format=json", "--no-cache"])
+            .arg("--hf-tokenizer")
+            .arg(&tokenizer)
+            .assert()
+            .success();
+        let json: serde_json::Value = serde_json::from_str(&env.read_output()).unwrap();
+        assert_eq!(json["encoding"], tokenizer.display().to_string());
+        // The words and punctuation runs of the code block: ```, py, content, foo, ., py, ```
+        assert_eq!(json["file_token_counts"]["lowercase/foo.py"], 7);
+
+        let mut cmd = env.command();
+        cmd.args(["--hf-tokenizer=missing/tokenizer.json"])
+            .env("RUST_LOG", "error")
+            .assert()
+            .failure()
+            .stderr(contains(
+                "Failed to load the Hugging Face tokenizer missing/tokenizer.json",
+            ));
+    }
+
+    #[test]
+    fn test_tokens_estimate_and_off() {
+        let env = TestEnv::new();
+        let mut cmd = env.command();
+        cmd.args(["--include=*.py", "--tokens=estimate"])
+            .assert()
+            .success()
+            .stdout(contains(" (estimate), Encoding: cl100k_base"))
+            .stdout(contains("Token count: ~"));
+        assert!(contains("`lowercase/foo.py` (~").eval(&env.read_output()));
+
+        let mut cmd = env.command();
+        cmd.args(["--include=*.py", "--tokens=off"])
+            .assert()
+            .success()
+            .stdout(contains("Token count:").not());
+        assert!(contains("tokens)").not().eval(&env.read_output()));
+
+        let mut cmd = env.command();
+        cmd.args(["--tokens=off", "--token-budget=100"])
+            .assert()
+            .failure()
+            .stderr(contains(
+                "--token-budget needs the tokens to be counted or estimated",
+            ));
+
+        let mut cmd = env.command();
+        cmd.args(["--tokens=exactly"])
+            .assert()
+            .failure()
+            .stderr(contains("Invalid token option"));
+    }
+
+    #[test]
+    fn test_context_window_split() {
+        let env = TestEnv::new();
+        create_temp_file(env.dir.path(), "lowercase/big.py", &"big ".repeat(2000));
+
+        let mut cmd = env.command();
+        cmd.args(["--include=*.py", "--context-window=gpt-4o"])
+            .assert()
+            .success()
+            .stderr(contains("over the context window").not());
+
+        std::fs::remove_file(&env.output_file).unwrap();
+        let mut cmd = env.command();
+        cmd.args(["--include=*.py", "--context-window=1000", "--split"])
+            .assert()
+            .success()
+            .stderr(contains("over the context window of 1000 tokens"))
+            .stderr(contains("big.py alone takes"))
+            .stdout(contains("Split the prompt into"));
+
+        assert!(!env.dir.path().join("output.txt").exists());
+        let mut part_files = Vec::new();
+        for index in 1.. {
+            let part_path = env.dir.path().join(format!("output.part{}.txt", index));
+            if !part_path.exists() {
+                break;
+            }
+            part_files.push(std::fs::read_to_string(part_path).unwrap());
+        }
+        assert!(part_files.len() >= 2);
+        let count = part_files.len();
+        for (index, part) in part_files.iter().enumerate() {
+            assert!(part.contains(&format!("Part {} of {}", index + 1, count)));
+            assert!(part.contains("Source Tree:"));
+        }
+        assert_eq!(
+            part_files
+                .iter()
+                .filter(|part| part.contains("big big big"))
+                .count(),
+            1
+        );
+        assert_eq!(
+            part_files
+                .iter()
+                .filter(|part| part.contains("content foo.py"))
+                .count(),
+            1
+        );
+
+        let mut cmd = env.command();
+        cmd.args(["--context-window=gpt-9"])
+            .env("RUST_LOG", "error")
+            .assert()
+            .failure()
+            .stderr(contains("No price for the model gpt-9"));
+    }
+
+    #[test]
+    fn test_file_stats() {
+        let env = TestEnv::new();
+        let mut cmd = env.command();
+        cmd.args(["--include=*.py", "--tokens=off"])
+            .assert()
+            .success()
+            .stdout(contains("Stats: 6 files, 6 lines, 12 words, 90 B"));
+
+        let mut cmd = env.command();
+        cmd.args(["--include=*.py", "--output-format=json", "--tokens=off"])
+            .assert()
+            .success();
+        let json: serde_json::Value = serde_json::from_str(&env.read_output()).unwrap();
+        assert_eq!(
+            json["stats"],
+            serde_json::json!({"files": 6, "lines": 6, "words": 12, "bytes": 90})
+        );
+    }
+
+    #[test]
+    fn test_encoding_issues() {
+        let env = TestEnv::new();
+        fs::write(env.dir.path().join("lowercase/latin1.py"), b"caf\xe9 = 1\n").unwrap();
+        fs::write(
+            env.dir.path().join("lowercase/blob.py"),
+            b"\x00\x01\x02\xff",
+        )
+        .unwrap();
+
+        let mut cmd = env.command();
+        cmd.args(["--include=*.py"])
+            .assert()
+            .success()
+            .stderr(contains("2 file(s) skipped or converted"))
+            .stderr(contains("lowercase/blob.py (binary content, skipped)"))
+            .stderr(contains(
+                "lowercase/latin1.py (invalid UTF-8, 1 invalid sequence(s) replaced)",
+            ));
+        let output = env.read_output();
+        assert!(contains("caf\u{FFFD} = 1").eval(&output));
+        assert!(contains("content foo.py").eval(&output));
+
+        let mut cmd = env.command();
+        cmd.args(["--include=*.py", "--strict"])
+            .env("RUST_LOG", "error")
+            .assert()
+            .failure()
+            .stderr(contains("Failed to decode lowercase/latin1.py"));
+    }
+
+    #[test]
+    fn test_priority_files_first() {
+        let env = TestEnv::new();
+        let mut cmd = env.command();
+        cmd.args([
+            "--include=*.py",
+            "--sort=name_asc",
+            "--priority=uppercase/BAZ.py",
+            "--token-budget=10000",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("priority files: 1, 0 cut"));
+
+        let output = env.read_output();
+        let priority_position = output.find("CONTENT BAZ.PY").unwrap();
+        let other_position = output.find("content bar.py").unwrap();
+        assert!(priority_position < other_position);
+    }
+
+    #[test]
+    fn test_token_delta_vs_last_run() {
+        let env = TestEnv::new();
+        let cache_dir = tempdir().unwrap();
+        let cache_arg = format!("--cache-dir={}", cache_dir.path().display());
+
+        let mut cmd = env.command();
+        cmd.args(["--include=*.py", &cache_arg])
+            .assert()
+            .success()
+            .stdout(contains("vs last run").not());
+
+        let mut cmd = env.command();
+        cmd.args(["--include=lowercase/*.py", &cache_arg, "--diff-stats-prev"])
+            .assert()
+            .success()
+            .stdout(contains(" vs last run), Encoding"))
+            .stdout(contains("Token count: ").and(contains("(-")))
+            .stdout(contains("Token changes vs last run: uppercase/"))
+            .stdout(contains("(removed)"));
+
+        // A corrupted history disables the comparison
+        std::fs::write(cache_dir.path().join("last_runs.json"), "garbage").unwrap();
+        let mut cmd = env.command();
+        cmd.args(["--include=*.py", &cache_arg, "--diff-stats-prev"])
+            .assert()
+            .success()
+            .stdout(contains("vs last run").not());
+    }
+
+    #[test]
+    fn test_section_tokens_table() {
+        let env = TestEnv::new();
+        let mut cmd = env.command();
+        cmd.args(["--include=*.py"])
+            .assert()
+            .success()
+            .stdout(contains("Section tokens:\n    Source tree"))
+            .stdout(contains("    Git diff      "))
+            .stdout(contains("    Template      "));
+
+        let mut cmd = env.command();
+        cmd.args(["--include=*.py", "--output-format=json"])
+            .assert()
+            .success();
+        let json: serde_json::Value = serde_json::from_str(&env.read_output()).unwrap();
+        let sections = &json["section_tokens"];
+        let sum: u64 = ["files", "tree", "diff", "log", "template"]
+            .iter()
+            .map(|section| sections[section].as_u64().unwrap())
+            .sum();
+        assert_eq!(sum, json["token_count"].as_u64().unwrap());
+    }
+
+    #[test]
+    fn test_named_templates() {
+        let env = TestEnv::new();
+        let templates_dir = env.dir.path().join(".code2prompt/templates");
+        fs::create_dir_all(&templates_dir).unwrap();
+        fs::write(
+            templates_dir.join("review.hbs"),
+            "{{!-- Review the changes --}}\nPlease review {{absolute_code_path}}",
+        )
+        .unwrap();
+        fs::write(templates_dir.join("fix-bugs.hbs"), "Our own bug template").unwrap();
+
+        let mut cmd = Command::cargo_bin("code2prompt").unwrap();
+        cmd.arg(env.dir.path())
+            .arg("--list-templates")
+            .assert()
+            .success()
+            .stdout(contains("Review the changes"))
+            .stdout(contains("overrides the built-in template"))
+            .stdout(contains("document-the-code"));
+
+        let mut cmd = env.command();
+        cmd.args(["--template", "review"]).assert().success();
+        assert!(env.read_output().starts_with("Please review"));
+
+        let mut cmd = env.command();
+        cmd.args(["--template", "fix-bugs"])
+            .assert()
+            .success()
+            .stderr(contains("instead of the built-in template fix-bugs"));
+        assert_eq!(env.read_output().trim(), "Our own bug template");
+
+        let mut cmd = env.command();
+        cmd.args(["--template", "missing"])
+            .env("RUST_LOG", "error")
+            .assert()
+            .failure()
+            .stderr(contains("No template file or template named missing"));
+    }
+
+    #[test]
+    fn test_template_partials() {
+        let env = TestEnv::new();
+        let templates_dir = tempdir().unwrap();
+        let partials_dir = templates_dir.path().join("partials");
+        fs::create_dir(&partials_dir).unwrap();
+        let template = templates_dir.path().join("list.hbs");
+        fs::write(&template, "{{#each files}}{{> file_block}}{{/each}}").unwrap();
+        fs::write(partials_dir.join("file_block.hbs"), "DIR {{path}}\n").unwrap();
+        let user_partial = templates_dir.path().join("mine.hbs");
+        fs::write(&user_partial, "MINE {{path}}\n").unwrap();
+
+        // The partials directory next to the template replaces the built-in partial
+        let mut cmd = env.command();
+        cmd.args(["--include=lowercase/foo.py", "-t"])
+            .arg(&template)
+            .assert()
+            .success();
+        assert_eq!(env.read_output().trim(), "DIR lowercase/foo.py");
+
+        // --template-partial replaces the partials directory, and the default template
+        let mut cmd = env.command();
+        cmd.args(["--include=lowercase/foo.py", "-t"])
+            .arg(&template)
+            .arg(format!(
+                "--template-partial=file_block={}",
+                user_partial.display()
+            ))
+            .assert()
+            .success();
+        assert_eq!(env.read_output().trim(), "MINE lowercase/foo.py");
+
+        let mut cmd = env.command();
+        cmd.arg("--include=lowercase/foo.py")
+            .arg(format!(
+                "--template-partial=file_block={}",
+                user_partial.display()
+            ))
+            .assert()
+            .success();
+        assert!(env.read_output().contains("MINE lowercase/foo.py"));
+
+        let mut cmd = env.command();
+        cmd.arg("--template-partial=file_block")
+            .env("RUST_LOG", "error")
+            .assert()
+            .failure()
+            .stderr(contains("expected NAME=PATH"));
+    }
+
+    #[test]
+    fn test_user_variables() {
+        let env = TestEnv::new();
+        let template = env.dir.path().join("ticket.hbs");
+        fs::write(&template, "Ticket {{user.ticket}}: {{user.instruction}}").unwrap();
+
+        let mut cmd = env.command();
+        cmd.arg("-t")
+            .arg(&template)
+            .args(["-D", "ticket=ABC-12", "-D", "instruction=set x=1 then y=2"])
+            .assert()
+            .success();
+        assert_eq!(env.read_output().trim(), "Ticket ABC-12: set x=1 then y=2");
+
+        let mut cmd = env.command();
+        cmd.arg("-t")
+            .arg(&template)
+            .args(["--no-input", "-D", "ticket=ABC-12"])
+            .env("RUST_LOG", "error")
+            .assert()
+            .failure()
+            .stderr(contains("undefined variables: user.instruction"));
+
+        let mut cmd = env.command();
+        cmd.args(["-D", "=value"])
+            .env("RUST_LOG", "error")
+            .assert()
+            .failure()
+            .stderr(contains("expected KEY=VALUE"));
+    }
+
+    #[test]
+    fn test_template_builtin() {
+        let env = TestEnv::new();
+        let templates_dir = env.dir.path().join(".code2prompt/templates");
+        fs::create_dir_all(&templates_dir).unwrap();
+        fs::write(templates_dir.join("pr-description.hbs"), "Our own template").unwrap();
+
+        // The built-in template is used even with a repository template of the same name
+        let mut cmd = env.command();
+        cmd.args(["--template-builtin", "pr-description"])
+            .assert()
+            .success()
+            .stderr(contains("The template pr-description expects a git diff"))
+            .stderr(contains("The template pr-description expects a git log"));
+        assert!(env.read_output().contains("description of a pull request"));
+
+        let mut cmd = env.command();
+        cmd.args(["--template-builtin", "explain-codebase"])
+            .assert()
+            .success()
+            .stderr(contains("expects").not());
+        assert!(env.read_output().contains("content foo.py"));
+
+        let mut cmd = env.command();
+        cmd.args(["--template-builtin", "missing"])
+            .env("RUST_LOG", "error")
+            .assert()
+            .failure()
+            .stderr(contains("Built-in templates: "));
+    }
+
+    #[test]
+    fn test_template_front_matter() {
+        let env = TestEnv::new();
+        let template_dir = tempdir().unwrap();
+        let template = template_dir.path().join("python.hbs");
+        fs::write(
+            &template,
+            "+++\ninclude = [\"*.py\"]\nline_numbers = true\n+++\n{{#each files}}{{path}}\n{{code}}\n{{/each}}",
+        )
+        .unwrap();
+
+        // The patterns passed on the command line take precedence
+        let mut cmd = env.command();
+        cmd.arg("-t")
+            .arg(&template)
+            .arg("--include=*.txt")
+            .assert()
+            .success();
+        let output = env.read_output();
+        assert!(output.contains("qux.txt"));
+        assert!(!output.contains("foo.py"));
+
+        let mut cmd = env.command();
+        cmd.arg("-t").arg(&template).assert().success();
+        let output = env.read_output();
+        assert!(!output.contains("+++"));
+        assert!(output.contains("foo.py"));
+        assert!(output.contains("1 | content foo.py"));
+        assert!(!output.contains("qux.txt"));
+
+        fs::write(&template, "+++\nline_numbers = yes\n+++\nBody").unwrap();
+        let mut cmd = env.command();
+        cmd.arg("-t")
+            .arg(&template)
+            .env("RUST_LOG", "error")
+            .assert()
+            .failure()
+            .stderr(contains("Invalid front-matter at line 2"))
+            .stderr(contains("line_numbers = yes"));
+    }
+
+    #[test]
+    fn test_show_context() {
+        let env = TestEnv::new();
+        fs::write(env.dir.path().join("lowercase/long.txt"), "x".repeat(500)).unwrap();
+        let mut cmd = env.command();
+        let assert = cmd
+            .args([
+                "--show-context",
+                "--include=*.txt",
+                "--exclude=output.txt",
+                "-q",
+            ])
+            .assert()
+            .success();
+        let context: serde_json::Value =
+            serde_json::from_slice(&assert.get_output().stdout).unwrap();
+        let files = context["files"].as_array().unwrap();
+        assert!(!files.is_empty());
+        assert!(files
+            .iter()
+            .all(|file| file["path"].as_str().unwrap().ends_with(".txt")));
+        let long = files
+            .iter()
+            .find(|file| file["path"] == "lowercase/long.txt")
+            .unwrap();
+        let code = long["code"].as_str().unwrap();
+        assert!(
+            code.ends_with("more characters elided from this preview ...]"),
+            "{}",
+            code
+        );
+        assert_eq!(long["truncated"], false);
+        assert!(context.get("flags").is_some());
+        // The prompt isn't rendered
+        assert!(!env.dir.path().join("output.txt").exists());
+
+        let mut cmd = env.command();
+        let assert = cmd
+            .args([
+                "--show-context-full",
+                "--include=*.txt",
+                "--exclude=output.txt",
+                "-q",
+            ])
+            .assert()
+            .success();
+        let context: serde_json::Value =
+            serde_json::from_slice(&assert.get_output().stdout).unwrap();
+        let long = context["files"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|file| file["path"] == "lowercase/long.txt")
+            .unwrap();
+        assert!(long["code"].as_str().unwrap().contains(&"x".repeat(500)));
+    }
+
+    #[test]
+    fn test_validate_template() {
+        let env = TestEnv::new();
+        let template_dir = tempdir().unwrap();
+        let template = template_dir.path().join("review.hbs");
+
+        fs::write(&template, "Files:\n{{#each file}}\n{{path}}\n").unwrap();
+        let mut cmd = env.command();
+        cmd.arg("-t")
+            .arg(&template)
+            .arg("--validate-template")
+            .env("RUST_LOG", "error")
+            .assert()
+            .failure()
+            .stderr(contains(
+                "at line 2, column 1: the block {{#each}} isn't closed",
+            ));
+
+        fs::write(
+            &template,
+            "{{#each file}}{{path}}{{/each}}\n{{source_tree}}",
+        )
+        .unwrap();
+        let mut cmd = env.command();
+        cmd.arg("-t")
+            .arg(&template)
+            .arg("--validate-template")
+            .assert()
+            .success()
+            .stdout(contains("is valid"))
+            .stderr(contains(
+                "references file at line 1, column 9, which the prompt data doesn't have",
+            ));
+        // The codebase isn't read
+        assert!(!env.dir.path().join("output.txt").exists());
+
+        let mut cmd = env.command();
+        cmd.arg("--validate-template").assert().failure();
+    }
+
+    #[cfg(feature = "jinja")]
+    #[test]
+    fn test_jinja_template() {
+        let env = TestEnv::new();
+        let template_dir = tempdir().unwrap();
+        let template = template_dir.path().join("files.j2");
+        fs::write(
+            &template,
+            "{% for file in files %}\n- {{ file.path }}\n{% endfor %}",
+        )
+        .unwrap();
+
+        let mut cmd = env.command();
+        cmd.args(["--include=*.py", "--exclude=**/uppercase/**"])
+            .arg("-t")
+            .arg(&template)
+            .assert()
+            .success();
+        let output = env.read_output();
+        assert!(contains("- lowercase/foo.py").eval(&output), "{}", output);
+        assert!(contains("{%").not().eval(&output));
+
+        fs::write(&template, "Files:\n{% for file in files %}").unwrap();
+        let mut cmd = env.command();
+        cmd.arg("-t")
+            .arg(&template)
+            .arg("--validate-template")
+            .env("RUST_LOG", "error")
+            .assert()
+            .failure()
+            .stderr(contains("at line 2"));
+
+        // The engine of the flag wins over the extension
+        let template = template_dir.path().join("files.hbs");
+        fs::write(&template, "{% if flags.has_tree %}tree{% endif %}").unwrap();
+        let mut cmd = env.command();
+        cmd.arg("-t")
+            .arg(&template)
+            .args(["--template-engine", "jinja", "--validate-template"])
+            .assert()
+            .success()
+            .stdout(contains("is valid"));
+    }
+
+    #[cfg(not(feature = "jinja"))]
+    #[test]
+    fn test_jinja_template_without_feature() {
+        let env = TestEnv::new();
+        let template_dir = tempdir().unwrap();
+        let template = template_dir.path().join("files.j2");
+        fs::write(
+            &template,
+            "{% for file in files %}{{ file.path }}{% endfor %}",
+        )
+        .unwrap();
+
+        let mut cmd = env.command();
+        cmd.arg("-t")
+            .arg(&template)
+            .env("RUST_LOG", "error")
+            .assert()
+            .failure()
+            .stderr(contains("built without the jinja feature"));
+    }
+
+    #[test]
+    fn test_path_style() {
+        let env = TestEnv::new();
+        let mut cmd = env.command();
+        cmd.args(["--include=*.py", "--path-style", "strip:lowercase"])
+            .assert()
+            .success();
+        let output = env.read_output();
+        assert!(contains("`foo.py`").eval(&output), "{}", output);
+        assert!(
+            contains("`lowercase/foo.py`").not().eval(&output),
+            "{}",
+            output
+        );
+
+        let mut cmd = env.command();
+        cmd.args(["--path-style", "strip:"])
+            .assert()
+            .failure()
+            .stderr(contains("Invalid path style"));
+        let mut cmd = env.command();
+        cmd.args(["--path-style", "relative", "--absolute-paths"])
+            .assert()
+            .failure()
+            .stderr(contains("cannot be used with"));
+    }
+
+    #[test]
+    fn test_chunk_size() {
+        let env = TestEnv::new();
+        create_temp_file(env.dir.path(), "lowercase/big.py", &"big\n".repeat(3000));
+
+        let mut cmd = env.command();
+        cmd.args(["--include=*.py", "--chunk-size=4kc"])
+            .assert()
+            .success()
+            .stdout(contains("Split the prompt into"))
+            .stdout(contains("output.part1.txt: "))
+            .stdout(contains("a file over the chunk size"));
+
+        assert!(!env.dir.path().join("output.txt").exists());
+        let mut part_files = Vec::new();
+        for index in 1.. {
+            let part_path = env.dir.path().join(format!("output.part{}.txt", index));
+            if !part_path.exists() {
+                break;
+            }
+            part_files.push(std::fs::read_to_string(part_path).unwrap());
+        }
+        assert!(part_files.len() >= 3);
+        for (index, part) in part_files.iter().enumerate() {
+            assert!(part.chars().count() <= 4000);
+            assert_eq!(part.contains("Source Tree:"), index == 0);
+        }
+        assert!(part_files[1].contains("see part 1 for the tree"));
+        assert!(part_files
+            .iter()
+            .any(|part| part.contains("... (continued in part")));
+
+        let mut cmd = Command::cargo_bin("code2prompt").expect("Failed to find code2prompt binary");
+        cmd.arg(env.dir.path().to_str().unwrap())
+            .args(["--chunk-size=4kc", "--output-file=-", "--no-clipboard"])
+            .assert()
+            .failure()
+            .stderr(contains("can't write to stdout"));
+        let mut cmd = env.command();
+        cmd.args(["--chunk-size=4x"])
+            .assert()
+            .failure()
+            .stderr(contains("Invalid chunk size"));
+    }
+
+    #[test]
+    fn test_split_by_dir() {
+        let env = TestEnv::new();
+        let output_dir = env.dir.path().join("out");
+        let mut cmd = env.command();
+        cmd.args([
+            "--include=*.py",
+            "--split-by-dir",
+            "--output-dir",
+            output_dir.to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout(contains(
+            "Split the prompt into 2 prompts, one per top-level directory",
+        ))
+        .stdout(contains("lowercase.md: 3 files"));
+
+        let lowercase = std::fs::read_to_string(output_dir.join("lowercase.md")).unwrap();
+        assert!(lowercase.contains("content foo.py"), "{}", lowercase);
+        assert!(!lowercase.contains("FOO.py"), "{}", lowercase);
+        let uppercase = std::fs::read_to_string(output_dir.join("uppercase.md")).unwrap();
+        assert!(uppercase.contains("CONTENT FOO.PY"), "{}", uppercase);
+        assert!(!output_dir.join("_root.md").exists());
+
+        let mut cmd = env.command();
+        cmd.args(["--split-by-dir"])
+            .assert()
+            .failure()
+            .stderr(contains("--output-dir <DIR>"));
+    }
+
+    #[test]
+    fn test_output_path_tokens_and_append() {
+        let env = TestEnv::new();
+        let dirname = env
+            .dir
+            .path()
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        let output_template = env.dir.path().join("prompts/{dirname}.md");
+        let output_path = env.dir.path().join(format!("prompts/{}.md", dirname));
+        let mut cmd = Command::cargo_bin("code2prompt").expect("Failed to find code2prompt binary");
+        cmd.arg(env.dir.path().to_str().unwrap())
+            .args([
+                "--include=*.py",
+                "--no-clipboard",
+                "--quiet",
+                "--output-file",
+            ])
+            .arg(&output_template)
+            .assert()
+            .success()
+            .stdout(format!("{}\n", output_path.display()));
+        let first = std::fs::read_to_string(&output_path).unwrap();
+
+        let mut cmd = Command::cargo_bin("code2prompt").expect("Failed to find code2prompt binary");
+        cmd.arg(env.dir.path().to_str().unwrap())
+            .args([
+                "--include=*.py",
+                "--no-clipboard",
+                "--output-append",
+                "--output-file",
+            ])
+            .arg(&output_template)
+            .assert()
+            .success()
+            .stdout(contains("Prompt appended to file"));
+        let appended = std::fs::read_to_string(&output_path).unwrap();
+        assert_eq!(appended.matches("content foo.py").count(), 2);
+        assert!(appended.starts_with(&first));
+        assert!(appended.contains("\n---\n"));
+
+        let mut cmd = Command::cargo_bin("code2prompt").expect("Failed to find code2prompt binary");
+        cmd.arg(env.dir.path().to_str().unwrap())
+            .args(["--no-clipboard", "--output-file", "{date}.md"])
+            .assert()
+            .failure()
+            .stderr(contains("Unknown token {date}"))
+            .stderr(contains("{timestamp}, {dirname}, {branch}"));
+    }
+
+    #[test]
+    fn test_fence_languages() {
+        let env = TestEnv::new();
+        create_temp_file(
+            env.dir.path(),
+            "scripts/deploy",
+            "#!/usr/bin/env python3\nprint('deploy')",
+        );
+        create_temp_file(env.dir.path(), "scripts/fast.pyx", "def fast(): pass");
+        create_temp_file(
+            env.dir.path(),
+            ".code2prompt.toml",
+            "[languages]\npyx = \"cython\"",
+        );
+        let mut cmd = env.command();
+        cmd.args(["--include=scripts/*"]).assert().success();
+        let output = env.read_output();
+        assert!(
+            contains("```python\n#!/usr/bin/env python3").eval(&output),
+            "{}",
+            output
+        );
+        assert!(
+            contains("```cython\ndef fast()").eval(&output),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn test_manifest() {
+        let env = TestEnv::new();
+        std::fs::write(env.dir.path().join("lowercase/data.bin"), [0u8, 1, 2, 0]).unwrap();
+        create_temp_file(env.dir.path(), "lowercase/a,b.py", "print('comma')");
+        let manifest_path = env.dir.path().join("reports/manifest.csv");
+        let mut cmd = env.command();
+        cmd.args(["--include=lowercase/*", "--manifest"])
+            .arg(&manifest_path)
+            .assert()
+            .success()
+            .stdout(contains("Manifest written to file"));
+        let manifest = std::fs::read_to_string(&manifest_path).unwrap();
+        let mut rows: Vec<&str> = manifest.split_terminator("\r\n").collect();
+        assert_eq!(
+            rows.remove(0),
+            "path,extension,language,bytes,lines,tokens,truncated,last_commit_sha,skip_reason"
+        );
+        rows.sort();
+        assert_eq!(rows.len(), 7, "{}", manifest);
+        assert!(
+            rows[0].starts_with("\"lowercase/a,b.py\",py,python,15,1,"),
+            "{}",
+            manifest
+        );
+        assert!(
+            rows[1].starts_with("lowercase/bar.py,py,python,15,1,"),
+            "{}",
+            manifest
+        );
+        assert!(
+            rows.iter().all(|row| row.ends_with(",false,,")),
+            "{}",
+            manifest
+        );
+        assert!(!manifest.contains("data.bin"));
+
+        let mut cmd = env.command();
+        cmd.args([
+            "--include=lowercase/*",
+            "--manifest-include-skipped",
+            "--manifest",
+        ])
+        .arg(&manifest_path)
+        .assert()
+        .success();
+        let manifest = std::fs::read_to_string(&manifest_path).unwrap();
+        assert!(
+            manifest.contains("lowercase/data.bin,bin,,,,,false,,\"binary content, skipped\"\r\n"),
+            "{}",
+            manifest
+        );
+
+        let mut cmd = env.command();
+        cmd.arg("--manifest-include-skipped")
+            .assert()
+            .failure()
+            .stderr(contains("--manifest <PATH>"));
+    }
+
+    #[test]
+    fn test_compressed_output_file() {
+        use std::io::Read;
+
+        let env = TestEnv::new();
+        let mut cmd = env.command();
+        cmd.args(["--include=lowercase/*"]).assert().success();
+        let uncompressed = env.read_output();
+
+        // The clipboard is left alone without --no-clipboard
+        let output_path = env.dir.path().join("prompt.md.gz");
+        let mut cmd = Command::cargo_bin("code2prompt").expect("Failed to find code2prompt binary");
+        cmd.arg(env.dir.path().to_str().unwrap())
+            .args(["--include=lowercase/*", "--output-file"])
+            .arg(&output_path)
+            .assert()
+            .success()
+            .stdout(contains("Prompt written to file (gzip)"))
+            .stderr(contains(
+                "Not copied to clipboard: the output is compressed with gzip",
+            ));
+        let mut decompressed = String::new();
+        flate2::read::GzDecoder::new(std::fs::File::open(&output_path).unwrap())
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, uncompressed);
+
+        let mut cmd = env.command();
+        cmd.args(["--include=lowercase/*", "--compress", "brotli"])
+            .assert()
+            .failure()
+            .stderr(contains("Allowed values: gzip, zstd"));
+        #[cfg(not(feature = "zstd"))]
+        {
+            let mut cmd = env.command();
+            cmd.args(["--include=lowercase/*", "--compress", "zstd"])
+                .assert()
+                .failure()
+                .stderr(contains("needs code2prompt built with the zstd feature"));
+        }
+    }
+
+    #[test]
+    fn test_clipboard_none() {
+        let env = TestEnv::new();
+        let mut cmd = Command::cargo_bin("code2prompt").expect("Failed to find code2prompt binary");
+        cmd.arg(env.dir.path().to_str().unwrap())
+            .args(["--clipboard", "none"])
+            .assert()
+            .failure()
+            .stderr(contains(
+                "--output-file is required when --no-clipboard or --clipboard none is used",
+            ));
+
+        let mut cmd = Command::cargo_bin("code2prompt").expect("Failed to find code2prompt binary");
+        cmd.arg(env.dir.path().to_str().unwrap())
+            .args([
+                "--include=lowercase/*",
+                "--clipboard",
+                "none",
+                "--output-file",
+            ])
+            .arg(env.dir.path().join("prompt.md"))
+            .assert()
+            .success()
+            .stdout(contains("Copied to clipboard").not());
+        assert!(std::fs::read_to_string(env.dir.path().join("prompt.md"))
+            .unwrap()
+            .contains("content foo.py"));
+
+        let mut cmd = env.command();
+        cmd.args(["--clipboard", "osc52"])
+            .assert()
+            .failure()
+            .stderr(contains("cannot be used with"));
+    }
+
+    #[test]
+    fn test_tree_only() {
+        let env = TestEnv::new();
+        let mut cmd = env.command();
+        cmd.args(["--include=lowercase/*", "--tree-only"])
+            .assert()
+            .success()
+            .stdout(contains(
+                "Full prompt with the skipped file contents (hypothetical):",
+            ));
+        let output = env.read_output();
+        assert!(contains("foo.py").eval(&output), "{}", output);
+        assert!(contains("content foo.py").not().eval(&output), "{}", output);
+
+        let mut cmd = env.command();
+        cmd.args(["--include=lowercase/*", "--no-tree"])
+            .assert()
+            .success()
+            .stdout(contains(
+                "Full prompt with the skipped tree (hypothetical):",
+            ));
+        let output = env.read_output();
+        assert!(contains("Source Tree:").not().eval(&output), "{}", output);
+        assert!(contains("content foo.py").eval(&output), "{}", output);
+
+        let mut cmd = env.command();
+        cmd.args(["--tree-only", "--no-tree"])
+            .assert()
+            .failure()
+            .stderr(contains("cannot be used with"));
+    }
+
+    #[test]
+    fn test_toc() {
+        let env = TestEnv::new();
+        let mut cmd = env.command();
+        cmd.args(["--include=*/foo.py", "--toc"]).assert().success();
+        let output = env.read_output();
+        assert!(contains("Table of Contents:").eval(&output), "{}", output);
+        assert!(
+            contains("- [`lowercase/foo.py`](#lowercasefoopy)").eval(&output),
+            "{}",
+            output
+        );
+        assert!(
+            contains("### `lowercase/foo.py`").eval(&output),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn test_html_output_format() {
+        let env = TestEnv::new();
+        let mut cmd = env.command();
+        #[cfg(not(feature = "html"))]
+        cmd.args(["--include=lowercase/*", "--output-format=html"])
+            .assert()
+            .failure()
+            .stderr(contains("needs code2prompt built with the html feature"));
+        #[cfg(feature = "html")]
+        {
+            cmd.args(["--include=lowercase/*", "--output-format=html"])
+                .assert()
+                .success();
+            let output = env.read_output();
+            assert!(output.starts_with("<!DOCTYPE html>"), "{}", output);
+            assert_eq!(output.matches("<section class=\"file\"").count(), 6);
+        }
+    }
+
+    #[test]
+    fn test_binary_policy() {
+        let env = TestEnv::new();
+        let logo = b"\x89PNG\r\n\x1a\n\x00\x00";
+        std::fs::write(env.dir.path().join("lowercase/logo.png"), logo).unwrap();
+
+        let mut cmd = env.command();
+        cmd.args(["--include=lowercase/*", "--output-format=json"])
+            .assert()
+            .success();
+        let json: serde_json::Value = serde_json::from_str(&env.read_output()).unwrap();
+        let files = json["files"].as_array().unwrap();
+        assert!(files.iter().all(|file| file["encoding"] == "text"));
+        assert!(!files
+            .iter()
+            .any(|file| file["path"] == "lowercase/logo.png"));
+        assert!(json["prompt"].as_str().unwrap().contains("logo.png"));
+
+        let mut cmd = env.command();
+        cmd.args([
+            "--include=lowercase/*",
+            "--output-format=json",
+            "--binary-policy=base64:1024",
+        ])
+        .assert()
+        .success();
+        let json: serde_json::Value = serde_json::from_str(&env.read_output()).unwrap();
+        let logo = json["files"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|file| file["path"] == "lowercase/logo.png")
+            .unwrap()
+            .clone();
+        assert_eq!(logo["encoding"], "base64");
+        assert_eq!(logo["content"], "data:image/png;base64,iVBORw0KGgoAAA==\n");
+
+        let mut cmd = env.command();
+        cmd.args(["--include=lowercase/*", "--binary-policy=base64"])
+            .assert()
+            .failure()
+            .stderr(contains(
+                "Allowed values: skip, placeholder, base64:<max-bytes>",
+            ));
+    }
+
+    #[test]
+    fn test_file_header_and_footer_formats() {
+        let env = TestEnv::new();
+        let mut cmd = env.command();
+        cmd.args([
+            "--include=lowercase/foo.py",
+            "--file-header-format=<<<FILE {path} {tokens}>>>",
+            "--file-footer-format=<<<END {index}>>>",
+        ])
+        .assert()
+        .success();
+        let output = env.read_output();
+        assert!(
+            contains("<<<FILE lowercase/foo.py ").eval(&output),
+            "{}",
+            output
+        );
+        assert!(contains("<<<END 1>>>").eval(&output), "{}", output);
+
+        let mut cmd = env.command();
+        cmd.args([
+            "--include=lowercase/foo.py",
+            "--file-header-format===== {file} ====",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("Unknown token {file}"))
+        .stderr(contains(
+            "Valid tokens: {path}, {language}, {tokens}, {index}",
+        ));
+    }
 }
@@ -25,10 +25,12 @@ fn init_logger() {
 fn create_temp_file(dir: &Path, name: &str, content: &str) -> std::path::PathBuf {
     let file_path = dir.join(name);
     let parent_dir = file_path.parent().unwrap();
-    fs::create_dir_all(parent_dir).expect(&format!("Failed to create directory: {:?}", parent_dir));
-    let mut file =
-        File::create(&file_path).expect(&format!("Failed to create temp file: {:?}", file_path));
-    writeln!(file, "{}", content).expect(&format!("Failed to write to temp file: {:?}", file_path));
+    fs::create_dir_all(parent_dir)
+        .unwrap_or_else(|_| panic!("Failed to create directory: {:?}", parent_dir));
+    let mut file = File::create(&file_path)
+        .unwrap_or_else(|_| panic!("Failed to create temp file: {:?}", file_path));
+    writeln!(file, "{}", content)
+        .unwrap_or_else(|_| panic!("Failed to write to temp file: {:?}", file_path));
     file_path
 }
 
@@ -56,7 +58,8 @@ fn create_test_codebase(base_path: &Path) {
 }
 
 fn read_output_file(file_path: &Path) -> String {
-    fs::read_to_string(file_path).expect(&format!("Failed to read output file: {:?}", file_path))
+    fs::read_to_string(file_path)
+        .unwrap_or_else(|_| panic!("Failed to read output file: {:?}", file_path))
 }
 
 mod template_tests {
@@ -81,9 +84,9 @@ mod template_tests {
         fn command(&self) -> Command {
             let mut cmd =
                 Command::cargo_bin("code2prompt").expect("Failed to find code2prompt binary");
-            cmd.arg(&self.dir.path().to_str().unwrap())
+            cmd.arg(self.dir.path().to_str().unwrap())
                 .arg("--output-file")
-                .arg(&self.output_file.to_str().unwrap())
+                .arg(self.output_file.to_str().unwrap())
                 .arg("--no-clipboard");
             cmd
         }
@@ -104,7 +107,7 @@ mod template_tests {
 
         // Check markdown-specific formatting
         assert!(contains("Source Tree:").eval(&output));
-        assert!(contains("```rs").eval(&output));
+        assert!(contains("```rust").eval(&output));
         assert!(contains("fn main()").eval(&output));
         assert!(contains("Hello, world!").eval(&output));
     }
@@ -120,8 +123,9 @@ mod template_tests {
 
         // Check XML-specific formatting
         assert!(contains("<directory>").eval(&output));
-        assert!(contains("</file>").eval(&output));
-        assert!(contains(".rs\"").eval(&output));
+        assert!(contains("<documents>\n<document index=\"1\"").eval(&output));
+        assert!(contains("</document_contents>\n</document>").eval(&output));
+        assert!(contains(".rs</source>").eval(&output));
         assert!(contains("fn main()").eval(&output));
         assert!(contains("Hello, world!").eval(&output));
     }
@@ -175,6 +179,115 @@ mod template_tests {
     //         assert!(contains("Hello, world!").eval(&output));
     //     }
 
+    #[test]
+    fn test_template_include() {
+        let env = TestEnv::new();
+        let template_dir = tempdir().unwrap();
+        let shared_dir = tempdir().unwrap();
+        create_temp_file(template_dir.path(), "style.md", "Be concise");
+        create_temp_file(shared_dir.path(), "rules.md", "Follow the rules");
+        let template = create_temp_file(
+            template_dir.path(),
+            "review.hbs",
+            &format!(
+                "{{{{include \"style.md\"}}}}\n{{{{include \"{}\"}}}}",
+                shared_dir.path().join("rules.md").display()
+            ),
+        );
+
+        let mut cmd = env.command();
+        cmd.arg("--template")
+            .arg(&template)
+            .env("RUST_LOG", "error")
+            .assert()
+            .failure()
+            .stderr(contains("is outside the template directory"));
+
+        let mut cmd = env.command();
+        cmd.arg("--template")
+            .arg(&template)
+            .arg("--include-root")
+            .arg(shared_dir.path())
+            .assert()
+            .success();
+        let output = env.read_output();
+        assert!(contains("Be concise\nFollow the rules").eval(&output));
+    }
+
+    #[test]
+    fn test_file_template() {
+        let env = TestEnv::new();
+        let mut cmd = env.command();
+        cmd.args(["--file-template", "FILE {{path}} ({{lines}} lines)"])
+            .assert()
+            .success();
+        let output = env.read_output();
+        assert!(contains("Source Tree:").eval(&output));
+        assert!(contains("FILE src/main.rs (3 lines)").eval(&output));
+        assert!(contains("Hello, world!").not().eval(&output));
+
+        let mut cmd = env.command();
+        cmd.args(["--file-template", "{{#each path}}"])
+            .env("RUST_LOG", "error")
+            .assert()
+            .failure()
+            .stderr(contains("Invalid template --file-template at line 1"));
+    }
+
+    #[test]
+    fn test_default_template_from_config_and_env() {
+        let env = TestEnv::new();
+        create_temp_file(env.dir.path(), "review.hbs", "CONFIG TEMPLATE");
+        create_temp_file(env.dir.path(), "env.hbs", "ENV TEMPLATE");
+        create_temp_file(env.dir.path(), "flag.hbs", "FLAG TEMPLATE");
+        create_temp_file(
+            env.dir.path(),
+            ".code2prompt.toml",
+            "template = \"review.hbs\"",
+        );
+        let env_template = env.dir.path().join("env.hbs");
+
+        // The configuration file only
+        let mut cmd = env.command();
+        cmd.env_remove("CODE2PROMPT_TEMPLATE")
+            .env("RUST_LOG", "debug")
+            .assert()
+            .success()
+            .stderr(contains("Template resolution, the first one set wins"));
+        assert_eq!(env.read_output(), "CONFIG TEMPLATE");
+
+        // The environment variable over the configuration file
+        let mut cmd = env.command();
+        cmd.env("CODE2PROMPT_TEMPLATE", &env_template)
+            .assert()
+            .success();
+        assert_eq!(env.read_output(), "ENV TEMPLATE");
+
+        // The flag over both, and the default template forced
+        let mut cmd = env.command();
+        cmd.env("CODE2PROMPT_TEMPLATE", &env_template)
+            .arg("--template")
+            .arg(env.dir.path().join("flag.hbs"))
+            .assert()
+            .success();
+        assert_eq!(env.read_output(), "FLAG TEMPLATE");
+
+        let mut cmd = env.command();
+        cmd.env("CODE2PROMPT_TEMPLATE", &env_template)
+            .args(["--template", "default"])
+            .assert()
+            .success();
+        assert!(contains("Source Tree:").eval(&env.read_output()));
+
+        // The environment variable only
+        std::fs::remove_file(env.dir.path().join(".code2prompt.toml")).unwrap();
+        let mut cmd = env.command();
+        cmd.env("CODE2PROMPT_TEMPLATE", &env_template)
+            .assert()
+            .success();
+        assert_eq!(env.read_output(), "ENV TEMPLATE");
+    }
+
     #[test]
     fn test_json_output_format() {
         let env = TestEnv::new();
@@ -192,6 +305,26 @@ mod template_tests {
         assert!(ends_with("}").eval(&output));
     }
 
+    #[test]
+    fn test_ndjson_output_format() {
+        let env = TestEnv::new();
+        let mut cmd = env.command();
+        cmd.args(["--output-format=ndjson", "--include=*.rs"])
+            .assert()
+            .success();
+
+        let output = env.read_output();
+        let mut types = Vec::new();
+        for line in output.lines() {
+            let record: serde_json::Value = serde_json::from_str(line).unwrap();
+            types.push(record["type"].as_str().unwrap().to_string());
+            if record["type"] == "file" {
+                assert!(record["path"].as_str().unwrap().ends_with(".rs"));
+            }
+        }
+        assert_eq!(types, ["meta", "file", "file", "file", "stats"]);
+    }
+
     //     #[test]
     //     fn test_template_with_empty_variables() {
     //         let env = TestEnv::new();